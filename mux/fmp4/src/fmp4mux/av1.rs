@@ -0,0 +1,1353 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Minimal AV1 sequence header and frame header parser.
+//!
+//! Only what is needed to find the position of the tile data inside frame and tile group OBUs
+//! is parsed, which is required for subsample encryption. Unlike the headers of other codecs the
+//! size of the AV1 frame header depends on the sequence header and on the state of the
+//! reference frames, so the parser has to keep track of both.
+
+use std::io::{self, Cursor};
+
+use anyhow::{bail, Context as _, Error};
+use bitstream_io::{BigEndian, BitRead, BitReader};
+
+const NUM_REF_FRAMES: usize = 8;
+const REFS_PER_FRAME: usize = 7;
+const PRIMARY_REF_NONE: u32 = 7;
+
+const KEY_FRAME: u32 = 0;
+const INTRA_ONLY_FRAME: u32 = 2;
+const SWITCH_FRAME: u32 = 3;
+
+const SELECT_SCREEN_CONTENT_TOOLS: u32 = 2;
+const SELECT_INTEGER_MV: u32 = 2;
+
+const SUPERRES_NUM: u32 = 8;
+const SUPERRES_DENOM_MIN: u32 = 9;
+
+const MAX_TILE_WIDTH: u32 = 4096;
+const MAX_TILE_AREA: u32 = 4096 * 2304;
+const MAX_TILE_ROWS: u32 = 64;
+const MAX_TILE_COLS: u32 = 64;
+
+const MAX_SEGMENTS: usize = 8;
+const SEG_LVL_MAX: usize = 8;
+/// Number of bits, signedness and maximum value of each segmentation feature.
+const SEGMENTATION_FEATURES: [(u32, bool, i32); SEG_LVL_MAX] = [
+    (8, true, 255),
+    (6, true, 63),
+    (6, true, 63),
+    (6, true, 63),
+    (6, true, 63),
+    (3, false, 7),
+    (0, false, 0),
+    (0, false, 0),
+];
+
+/// Helper trait for the descriptors of the AV1 specification.
+trait BitReadExt: BitRead {
+    /// `f(n)`
+    fn read_bits(&mut self, bits: u32) -> io::Result<u32> {
+        if bits == 0 {
+            Ok(0)
+        } else {
+            self.read_var::<u32>(bits)
+        }
+    }
+
+    /// `su(n)`
+    fn read_su(&mut self, bits: u32) -> io::Result<i32> {
+        let value = self.read_bits(bits)? as i32;
+        let sign_mask = 1 << (bits - 1);
+        if value & sign_mask != 0 {
+            Ok(value - 2 * sign_mask)
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// `ns(n)`
+    fn read_ns(&mut self, n: u32) -> io::Result<u32> {
+        let w = u32::BITS - n.leading_zeros();
+        let m = (1 << w) - n;
+        let v = self.read_bits(w - 1)?;
+        if v < m {
+            return Ok(v);
+        }
+        let extra_bit = self.read_bit()? as u32;
+        Ok((v << 1) - m + extra_bit)
+    }
+
+    /// `uvlc()`
+    fn read_uvlc(&mut self) -> io::Result<u32> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+        }
+        if leading_zeros >= 32 {
+            return Ok(u32::MAX);
+        }
+        Ok(self.read_bits(leading_zeros)? + ((1 << leading_zeros) - 1))
+    }
+
+    /// `decode_subexp(numSyms)`, only the number of bits read matters here.
+    fn skip_subexp(&mut self, num_syms: u32) -> io::Result<()> {
+        let mut i = 0;
+        let mut mk = 0;
+        let k = 3;
+        loop {
+            let b2 = if i > 0 { k + i - 1 } else { k };
+            let a = 1 << b2;
+            if num_syms <= mk + 3 * a {
+                self.read_ns(num_syms - mk)?;
+                return Ok(());
+            }
+            if self.read_bit()? {
+                i += 1;
+                mk += a;
+            } else {
+                self.read_bits(b2)?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<T: BitRead + ?Sized> BitReadExt for T {}
+
+/// The fields of the sequence header that are needed for parsing frame headers.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SequenceHeader {
+    reduced_still_picture_header: bool,
+    decoder_model_info_present: bool,
+    equal_picture_interval: bool,
+    buffer_removal_time_length: u32,
+    frame_presentation_time_length: u32,
+    /// `operating_point_idc` of all operating points with a decoder model.
+    decoder_model_operating_points: Vec<u32>,
+    frame_width_bits: u32,
+    frame_height_bits: u32,
+    max_frame_width: u32,
+    max_frame_height: u32,
+    frame_id_numbers_present: bool,
+    delta_frame_id_length: u32,
+    frame_id_length: u32,
+    use_128x128_superblock: bool,
+    enable_warped_motion: bool,
+    enable_order_hint: bool,
+    enable_ref_frame_mvs: bool,
+    seq_force_screen_content_tools: u32,
+    seq_force_integer_mv: u32,
+    order_hint_bits: u32,
+    enable_superres: bool,
+    enable_cdef: bool,
+    enable_restoration: bool,
+    mono_chrome: bool,
+    subsampling_x: bool,
+    subsampling_y: bool,
+    separate_uv_delta_q: bool,
+    film_grain_params_present: bool,
+}
+
+impl SequenceHeader {
+    /// Parses the payload of a sequence header OBU.
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, Error> {
+        let mut r = BitReader::endian(Cursor::new(data), BigEndian);
+        let r = &mut r;
+        let mut seq = SequenceHeader::default();
+
+        let seq_profile = r.read_bits(3).context("seq_profile")?;
+        let _still_picture = r.read_bit().context("still_picture")?;
+        seq.reduced_still_picture_header = r.read_bit().context("reduced_still_picture_header")?;
+
+        if seq.reduced_still_picture_header {
+            let _seq_level_idx = r.read_bits(5).context("seq_level_idx")?;
+        } else {
+            let timing_info_present = r.read_bit().context("timing_info_present_flag")?;
+            let mut buffer_delay_length = 0;
+            if timing_info_present {
+                let _num_units_in_display_tick = r.read_bits(32).context("timing_info")?;
+                let _time_scale = r.read_bits(32).context("timing_info")?;
+                seq.equal_picture_interval = r.read_bit().context("equal_picture_interval")?;
+                if seq.equal_picture_interval {
+                    let _num_ticks_per_picture_minus_1 =
+                        r.read_uvlc().context("num_ticks_per_picture_minus_1")?;
+                }
+
+                seq.decoder_model_info_present =
+                    r.read_bit().context("decoder_model_info_present_flag")?;
+                if seq.decoder_model_info_present {
+                    buffer_delay_length = r.read_bits(5).context("decoder_model_info")? + 1;
+                    let _num_units_in_decoding_tick =
+                        r.read_bits(32).context("decoder_model_info")?;
+                    seq.buffer_removal_time_length =
+                        r.read_bits(5).context("decoder_model_info")? + 1;
+                    seq.frame_presentation_time_length =
+                        r.read_bits(5).context("decoder_model_info")? + 1;
+                }
+            }
+
+            let initial_display_delay_present =
+                r.read_bit().context("initial_display_delay_present_flag")?;
+            let operating_points_cnt = r.read_bits(5).context("operating_points_cnt")? + 1;
+            for _ in 0..operating_points_cnt {
+                let operating_point_idc = r.read_bits(12).context("operating_point_idc")?;
+                let seq_level_idx = r.read_bits(5).context("seq_level_idx")?;
+                if seq_level_idx > 7 {
+                    let _seq_tier = r.read_bit().context("seq_tier")?;
+                }
+                if seq.decoder_model_info_present {
+                    let decoder_model_present =
+                        r.read_bit().context("decoder_model_present_for_this_op")?;
+                    if decoder_model_present {
+                        let _decoder_buffer_delay = r
+                            .read_bits(buffer_delay_length)
+                            .context("operating_parameters_info")?;
+                        let _encoder_buffer_delay = r
+                            .read_bits(buffer_delay_length)
+                            .context("operating_parameters_info")?;
+                        let _low_delay_mode = r.read_bit().context("operating_parameters_info")?;
+                        seq.decoder_model_operating_points.push(operating_point_idc);
+                    }
+                }
+                if initial_display_delay_present {
+                    let present = r
+                        .read_bit()
+                        .context("initial_display_delay_present_for_this_op")?;
+                    if present {
+                        let _initial_display_delay_minus_1 =
+                            r.read_bits(4).context("initial_display_delay_minus_1")?;
+                    }
+                }
+            }
+        }
+
+        seq.frame_width_bits = r.read_bits(4).context("frame_width_bits")? + 1;
+        seq.frame_height_bits = r.read_bits(4).context("frame_height_bits")? + 1;
+        seq.max_frame_width = r
+            .read_bits(seq.frame_width_bits)
+            .context("max_frame_width")?
+            + 1;
+        seq.max_frame_height = r
+            .read_bits(seq.frame_height_bits)
+            .context("max_frame_height")?
+            + 1;
+
+        if !seq.reduced_still_picture_header {
+            seq.frame_id_numbers_present = r.read_bit().context("frame_id_numbers_present_flag")?;
+        }
+        if seq.frame_id_numbers_present {
+            seq.delta_frame_id_length = r.read_bits(4).context("delta_frame_id_length")? + 2;
+            seq.frame_id_length = r.read_bits(3).context("additional_frame_id_length")?
+                + 1
+                + seq.delta_frame_id_length;
+        }
+
+        seq.use_128x128_superblock = r.read_bit().context("use_128x128_superblock")?;
+        let _enable_filter_intra = r.read_bit().context("enable_filter_intra")?;
+        let _enable_intra_edge_filter = r.read_bit().context("enable_intra_edge_filter")?;
+
+        if seq.reduced_still_picture_header {
+            seq.seq_force_screen_content_tools = SELECT_SCREEN_CONTENT_TOOLS;
+            seq.seq_force_integer_mv = SELECT_INTEGER_MV;
+        } else {
+            let _enable_interintra_compound = r.read_bit().context("enable_interintra_compound")?;
+            let _enable_masked_compound = r.read_bit().context("enable_masked_compound")?;
+            seq.enable_warped_motion = r.read_bit().context("enable_warped_motion")?;
+            let _enable_dual_filter = r.read_bit().context("enable_dual_filter")?;
+            seq.enable_order_hint = r.read_bit().context("enable_order_hint")?;
+            if seq.enable_order_hint {
+                let _enable_jnt_comp = r.read_bit().context("enable_jnt_comp")?;
+                seq.enable_ref_frame_mvs = r.read_bit().context("enable_ref_frame_mvs")?;
+            }
+
+            let seq_choose_screen_content_tools =
+                r.read_bit().context("seq_choose_screen_content_tools")?;
+            seq.seq_force_screen_content_tools = if seq_choose_screen_content_tools {
+                SELECT_SCREEN_CONTENT_TOOLS
+            } else {
+                r.read_bits(1).context("seq_force_screen_content_tools")?
+            };
+
+            seq.seq_force_integer_mv = if seq.seq_force_screen_content_tools > 0 {
+                let seq_choose_integer_mv = r.read_bit().context("seq_choose_integer_mv")?;
+                if seq_choose_integer_mv {
+                    SELECT_INTEGER_MV
+                } else {
+                    r.read_bits(1).context("seq_force_integer_mv")?
+                }
+            } else {
+                SELECT_INTEGER_MV
+            };
+
+            if seq.enable_order_hint {
+                seq.order_hint_bits = r.read_bits(3).context("order_hint_bits")? + 1;
+            }
+        }
+
+        seq.enable_superres = r.read_bit().context("enable_superres")?;
+        seq.enable_cdef = r.read_bit().context("enable_cdef")?;
+        seq.enable_restoration = r.read_bit().context("enable_restoration")?;
+
+        // color_config()
+        let high_bitdepth = r.read_bit().context("high_bitdepth")?;
+        let bit_depth = if seq_profile == 2 && high_bitdepth {
+            if r.read_bit().context("twelve_bit")? {
+                12
+            } else {
+                10
+            }
+        } else if high_bitdepth {
+            10
+        } else {
+            8
+        };
+        if seq_profile != 1 {
+            seq.mono_chrome = r.read_bit().context("mono_chrome")?;
+        }
+        let (color_primaries, transfer_characteristics, matrix_coefficients) =
+            if r.read_bit().context("color_description_present_flag")? {
+                (
+                    r.read_bits(8).context("color_primaries")?,
+                    r.read_bits(8).context("transfer_characteristics")?,
+                    r.read_bits(8).context("matrix_coefficients")?,
+                )
+            } else {
+                (2, 2, 2)
+            };
+        if seq.mono_chrome {
+            let _color_range = r.read_bit().context("color_range")?;
+            seq.subsampling_x = true;
+            seq.subsampling_y = true;
+        } else {
+            // sRGB, BT.709 primaries and identity matrix
+            if color_primaries == 1 && transfer_characteristics == 13 && matrix_coefficients == 0 {
+                seq.subsampling_x = false;
+                seq.subsampling_y = false;
+            } else {
+                let _color_range = r.read_bit().context("color_range")?;
+                match seq_profile {
+                    0 => {
+                        seq.subsampling_x = true;
+                        seq.subsampling_y = true;
+                    }
+                    1 => {
+                        seq.subsampling_x = false;
+                        seq.subsampling_y = false;
+                    }
+                    _ => {
+                        if bit_depth == 12 {
+                            seq.subsampling_x = r.read_bit().context("subsampling_x")?;
+                            if seq.subsampling_x {
+                                seq.subsampling_y = r.read_bit().context("subsampling_y")?;
+                            }
+                        } else {
+                            seq.subsampling_x = true;
+                        }
+                    }
+                }
+                if seq.subsampling_x && seq.subsampling_y {
+                    let _chroma_sample_position =
+                        r.read_bits(2).context("chroma_sample_position")?;
+                }
+            }
+            seq.separate_uv_delta_q = r.read_bit().context("separate_uv_delta_q")?;
+        }
+
+        seq.film_grain_params_present = r.read_bit().context("film_grain_params_present")?;
+
+        Ok(seq)
+    }
+
+    fn num_planes(&self) -> usize {
+        if self.mono_chrome {
+            1
+        } else {
+            3
+        }
+    }
+}
+
+/// Tile layout of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TileInfo {
+    pub(crate) tile_cols: u32,
+    pub(crate) tile_rows: u32,
+    tile_cols_log2: u32,
+    tile_rows_log2: u32,
+    tile_size_bytes: u32,
+}
+
+impl TileInfo {
+    /// Returns the byte ranges of the tile data of all tiles of a tile group OBU payload.
+    ///
+    /// The tile size fields in front of each tile are not part of the ranges.
+    pub(crate) fn tiles(&self, data: &[u8]) -> Result<Vec<std::ops::Range<usize>>, Error> {
+        let num_tiles = self.tile_cols * self.tile_rows;
+
+        let mut cursor = Cursor::new(data);
+        let (tg_start, tg_end) = {
+            let mut r = BitReader::endian(&mut cursor, BigEndian);
+            let tile_start_and_end_present =
+                num_tiles > 1 && r.read_bit().context("tile_start_and_end_present_flag")?;
+            let tg = if tile_start_and_end_present {
+                let tile_bits = self.tile_cols_log2 + self.tile_rows_log2;
+                (
+                    r.read_bits(tile_bits).context("tg_start")?,
+                    r.read_bits(tile_bits).context("tg_end")?,
+                )
+            } else {
+                (0, num_tiles - 1)
+            };
+            r.byte_align();
+            tg
+        };
+
+        if tg_end < tg_start || tg_end >= num_tiles {
+            bail!("Invalid tile group");
+        }
+
+        let mut pos = cursor.position() as usize;
+        let mut tiles = Vec::new();
+        for tile_num in tg_start..=tg_end {
+            let tile_size = if tile_num == tg_end {
+                data.len()
+                    .checked_sub(pos)
+                    .context("Truncated tile group")?
+            } else {
+                let size_bytes = self.tile_size_bytes as usize;
+                let tile_size_minus_1 = data
+                    .get(pos..pos + size_bytes)
+                    .context("Truncated tile size")?
+                    .iter()
+                    .rev()
+                    .fold(0usize, |size, b| (size << 8) | *b as usize);
+                pos += size_bytes;
+                tile_size_minus_1 + 1
+            };
+
+            let end = pos
+                .checked_add(tile_size)
+                .filter(|end| *end <= data.len())
+                .context("Truncated tile")?;
+            tiles.push(pos..end);
+            pos = end;
+        }
+
+        Ok(tiles)
+    }
+}
+
+/// Parsed frame header.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FrameHeader {
+    /// Size of the frame header including the trailing byte alignment.
+    pub(crate) size: usize,
+    /// Tile layout, `None` if an existing frame is shown.
+    pub(crate) tile_info: Option<TileInfo>,
+}
+
+/// State that is stored per reference frame slot.
+#[derive(Debug, Clone, Copy, Default)]
+struct RefFrame {
+    frame_type: u32,
+    order_hint: u32,
+    upscaled_width: u32,
+    frame_width: u32,
+    frame_height: u32,
+    render_width: u32,
+    render_height: u32,
+    /// Quantizer segmentation feature (`SEG_LVL_ALT_Q`) per segment.
+    seg_alt_q: [Option<i32>; MAX_SEGMENTS],
+}
+
+/// Frame size related values of the current frame.
+#[derive(Debug, Clone, Copy, Default)]
+struct FrameSize {
+    upscaled_width: u32,
+    frame_width: u32,
+    frame_height: u32,
+    render_width: u32,
+    render_height: u32,
+}
+
+impl FrameSize {
+    fn mi_cols(&self) -> u32 {
+        2 * ((self.frame_width + 7) >> 3)
+    }
+
+    fn mi_rows(&self) -> u32 {
+        2 * ((self.frame_height + 7) >> 3)
+    }
+}
+
+/// AV1 frame header parser that keeps track of the sequence header and the reference frames.
+#[derive(Debug, Default)]
+pub(crate) struct Parser {
+    sequence_header: Option<SequenceHeader>,
+    ref_frames: [RefFrame; NUM_REF_FRAMES],
+    /// Tile layout of the last frame header for subsequent tile group OBUs.
+    tile_info: Option<TileInfo>,
+}
+
+impl Parser {
+    /// Parses the payload of a sequence header OBU.
+    pub(crate) fn parse_sequence_header(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.sequence_header = Some(SequenceHeader::parse(data)?);
+        Ok(())
+    }
+
+    /// Parses the sequence header of `av1C` codec data, if it contains one.
+    pub(crate) fn parse_codec_data(&mut self, codec_data: &[u8]) -> Result<(), Error> {
+        const OBU_SEQUENCE_HEADER: u8 = 1;
+
+        // configOBUs start after the 4 byte header
+        let mut pos = 4;
+        while pos < codec_data.len() {
+            let header = codec_data[pos];
+            let obu_type = (header >> 3) & 0x0f;
+            let header_size = 1 + ((header & 0x04) != 0) as usize;
+            if header & 0x02 == 0 {
+                bail!("configOBUs without size field");
+            }
+
+            let (size, leb128_size) = super::cenc::read_leb128(
+                codec_data
+                    .get(pos + header_size..)
+                    .context("Truncated OBU header")?,
+            )?;
+            let start = pos + header_size + leb128_size;
+            let end = start
+                .checked_add(size)
+                .filter(|end| *end <= codec_data.len())
+                .context("Truncated OBU")?;
+
+            if obu_type == OBU_SEQUENCE_HEADER {
+                return self.parse_sequence_header(&codec_data[start..end]);
+            }
+
+            pos = end;
+        }
+
+        Ok(())
+    }
+
+    /// Tile layout of the last parsed frame header.
+    pub(crate) fn tile_info(&self) -> Option<TileInfo> {
+        self.tile_info
+    }
+
+    /// Parses the payload of a frame header OBU or the frame header at the start of a frame OBU.
+    pub(crate) fn parse_frame_header(
+        &mut self,
+        data: &[u8],
+        temporal_id: u8,
+        spatial_id: u8,
+    ) -> Result<FrameHeader, Error> {
+        let mut cursor = Cursor::new(data);
+        let tile_info = {
+            let mut r = BitReader::endian(&mut cursor, BigEndian);
+            let tile_info = self.uncompressed_header(&mut r, temporal_id, spatial_id)?;
+            r.byte_align();
+            tile_info
+        };
+        self.tile_info = tile_info;
+
+        Ok(FrameHeader {
+            size: cursor.position() as usize,
+            tile_info,
+        })
+    }
+
+    /// `uncompressed_header()`, returns the tile layout unless an existing frame is shown.
+    fn uncompressed_header<R: BitRead + ?Sized>(
+        &mut self,
+        r: &mut R,
+        temporal_id: u8,
+        spatial_id: u8,
+    ) -> Result<Option<TileInfo>, Error> {
+        let seq = self
+            .sequence_header
+            .as_ref()
+            .context("No sequence header before frame header")?;
+
+        let all_frames = (1u32 << NUM_REF_FRAMES) - 1;
+
+        let (frame_type, show_frame, showable_frame, error_resilient_mode) =
+            if seq.reduced_still_picture_header {
+                (KEY_FRAME, true, false, true)
+            } else {
+                let show_existing_frame = r.read_bit().context("show_existing_frame")?;
+                if show_existing_frame {
+                    let frame_to_show_map_idx =
+                        r.read_bits(3).context("frame_to_show_map_idx")? as usize;
+                    if seq.decoder_model_info_present && !seq.equal_picture_interval {
+                        r.read_bits(seq.frame_presentation_time_length)
+                            .context("frame_presentation_time")?;
+                    }
+                    if seq.frame_id_numbers_present {
+                        r.read_bits(seq.frame_id_length)
+                            .context("display_frame_id")?;
+                    }
+
+                    // Showing a key frame refreshes all reference frames with it
+                    let shown = self.ref_frames[frame_to_show_map_idx];
+                    if shown.frame_type == KEY_FRAME {
+                        self.ref_frames = [shown; NUM_REF_FRAMES];
+                    }
+
+                    return Ok(None);
+                }
+
+                let frame_type = r.read_bits(2).context("frame_type")?;
+                let show_frame = r.read_bit().context("show_frame")?;
+                if show_frame && seq.decoder_model_info_present && !seq.equal_picture_interval {
+                    r.read_bits(seq.frame_presentation_time_length)
+                        .context("frame_presentation_time")?;
+                }
+                let showable_frame = if show_frame {
+                    frame_type != KEY_FRAME
+                } else {
+                    r.read_bit().context("showable_frame")?
+                };
+                let error_resilient_mode =
+                    if frame_type == SWITCH_FRAME || (frame_type == KEY_FRAME && show_frame) {
+                        true
+                    } else {
+                        r.read_bit().context("error_resilient_mode")?
+                    };
+
+                (frame_type, show_frame, showable_frame, error_resilient_mode)
+            };
+        let frame_is_intra = frame_type == KEY_FRAME || frame_type == INTRA_ONLY_FRAME;
+
+        if frame_type == KEY_FRAME && show_frame {
+            for ref_frame in &mut self.ref_frames {
+                ref_frame.order_hint = 0;
+            }
+        }
+
+        let disable_cdf_update = r.read_bit().context("disable_cdf_update")?;
+        let allow_screen_content_tools =
+            if seq.seq_force_screen_content_tools == SELECT_SCREEN_CONTENT_TOOLS {
+                r.read_bit().context("allow_screen_content_tools")?
+            } else {
+                seq.seq_force_screen_content_tools != 0
+            };
+        let mut force_integer_mv = if allow_screen_content_tools {
+            if seq.seq_force_integer_mv == SELECT_INTEGER_MV {
+                r.read_bit().context("force_integer_mv")?
+            } else {
+                seq.seq_force_integer_mv != 0
+            }
+        } else {
+            false
+        };
+        if frame_is_intra {
+            force_integer_mv = true;
+        }
+
+        if seq.frame_id_numbers_present {
+            r.read_bits(seq.frame_id_length)
+                .context("current_frame_id")?;
+        }
+
+        let frame_size_override_flag = if frame_type == SWITCH_FRAME {
+            true
+        } else if seq.reduced_still_picture_header {
+            false
+        } else {
+            r.read_bit().context("frame_size_override_flag")?
+        };
+
+        let order_hint = r.read_bits(seq.order_hint_bits).context("order_hint")?;
+
+        let primary_ref_frame = if frame_is_intra || error_resilient_mode {
+            PRIMARY_REF_NONE
+        } else {
+            r.read_bits(3).context("primary_ref_frame")?
+        };
+
+        if seq.decoder_model_info_present {
+            let buffer_removal_time_present =
+                r.read_bit().context("buffer_removal_time_present_flag")?;
+            if buffer_removal_time_present {
+                for &op_pt_idc in &seq.decoder_model_operating_points {
+                    let in_temporal_layer = (op_pt_idc >> temporal_id) & 1 != 0;
+                    let in_spatial_layer = (op_pt_idc >> (spatial_id + 8)) & 1 != 0;
+                    if op_pt_idc == 0 || (in_temporal_layer && in_spatial_layer) {
+                        r.read_bits(seq.buffer_removal_time_length)
+                            .context("buffer_removal_time")?;
+                    }
+                }
+            }
+        }
+
+        let refresh_frame_flags =
+            if frame_type == SWITCH_FRAME || (frame_type == KEY_FRAME && show_frame) {
+                all_frames
+            } else {
+                r.read_bits(8).context("refresh_frame_flags")?
+            };
+
+        if (!frame_is_intra || refresh_frame_flags != all_frames)
+            && error_resilient_mode
+            && seq.enable_order_hint
+        {
+            for ref_frame in &mut self.ref_frames {
+                ref_frame.order_hint =
+                    r.read_bits(seq.order_hint_bits).context("ref_order_hint")?;
+            }
+        }
+
+        let mut allow_intrabc = false;
+        let mut allow_high_precision_mv = false;
+        let mut ref_frame_idx = [0usize; REFS_PER_FRAME];
+        let frame_size;
+        if frame_is_intra {
+            frame_size = read_frame_size(r, seq, frame_size_override_flag)?;
+            if allow_screen_content_tools && frame_size.upscaled_width == frame_size.frame_width {
+                allow_intrabc = r.read_bit().context("allow_intrabc")?;
+            }
+        } else {
+            let frame_refs_short_signaling =
+                seq.enable_order_hint && r.read_bit().context("frame_refs_short_signaling")?;
+            if frame_refs_short_signaling {
+                let last_frame_idx = r.read_bits(3).context("last_frame_idx")? as usize;
+                let gold_frame_idx = r.read_bits(3).context("gold_frame_idx")? as usize;
+                ref_frame_idx =
+                    self.set_frame_refs(seq, order_hint, last_frame_idx, gold_frame_idx);
+            }
+            for idx in &mut ref_frame_idx {
+                if !frame_refs_short_signaling {
+                    *idx = r.read_bits(3).context("ref_frame_idx")? as usize;
+                }
+                if seq.frame_id_numbers_present {
+                    r.read_bits(seq.delta_frame_id_length)
+                        .context("delta_frame_id_minus_1")?;
+                }
+            }
+
+            frame_size = if frame_size_override_flag && !error_resilient_mode {
+                self.frame_size_with_refs(r, seq, &ref_frame_idx)?
+            } else {
+                read_frame_size(r, seq, frame_size_override_flag)?
+            };
+
+            if !force_integer_mv {
+                allow_high_precision_mv = r.read_bit().context("allow_high_precision_mv")?;
+            }
+            // read_interpolation_filter()
+            if !r.read_bit().context("is_filter_switchable")? {
+                r.read_bits(2).context("interpolation_filter")?;
+            }
+            let _is_motion_mode_switchable = r.read_bit().context("is_motion_mode_switchable")?;
+            if !error_resilient_mode && seq.enable_ref_frame_mvs {
+                let _use_ref_frame_mvs = r.read_bit().context("use_ref_frame_mvs")?;
+            }
+        }
+
+        if !seq.reduced_still_picture_header && !disable_cdf_update {
+            let _disable_frame_end_update_cdf =
+                r.read_bit().context("disable_frame_end_update_cdf")?;
+        }
+
+        // load_previous(): segmentation parameters are inherited from the primary reference frame
+        let mut seg_alt_q = if primary_ref_frame == PRIMARY_REF_NONE {
+            [None; MAX_SEGMENTS]
+        } else {
+            self.ref_frames[ref_frame_idx[primary_ref_frame as usize]].seg_alt_q
+        };
+
+        let tile_info = tile_info(r, seq, &frame_size)?;
+
+        // quantization_params()
+        let base_q_idx = r.read_bits(8).context("base_q_idx")? as i32;
+        let mut deltas_zero = read_delta_q(r)? == 0;
+        if seq.num_planes() > 1 {
+            let diff_uv_delta = seq.separate_uv_delta_q && r.read_bit().context("diff_uv_delta")?;
+            deltas_zero &= read_delta_q(r)? == 0;
+            deltas_zero &= read_delta_q(r)? == 0;
+            if diff_uv_delta {
+                deltas_zero &= read_delta_q(r)? == 0;
+                deltas_zero &= read_delta_q(r)? == 0;
+            }
+        }
+        if r.read_bit().context("using_qmatrix")? {
+            r.read_bits(4).context("qm_y")?;
+            r.read_bits(4).context("qm_u")?;
+            if seq.separate_uv_delta_q {
+                r.read_bits(4).context("qm_v")?;
+            }
+        }
+
+        // segmentation_params()
+        let segmentation_enabled = r.read_bit().context("segmentation_enabled")?;
+        if segmentation_enabled {
+            let segmentation_update_data = if primary_ref_frame == PRIMARY_REF_NONE {
+                true
+            } else {
+                let segmentation_update_map = r.read_bit().context("segmentation_update_map")?;
+                if segmentation_update_map {
+                    r.read_bit().context("segmentation_temporal_update")?;
+                }
+                r.read_bit().context("segmentation_update_data")?
+            };
+            if segmentation_update_data {
+                for alt_q in &mut seg_alt_q {
+                    *alt_q = None;
+                    for (feature, &(bits, signed, limit)) in
+                        SEGMENTATION_FEATURES.iter().enumerate()
+                    {
+                        if !r.read_bit().context("feature_enabled")? {
+                            continue;
+                        }
+                        let value = if signed {
+                            r.read_su(1 + bits)
+                                .context("feature_value")?
+                                .clamp(-limit, limit)
+                        } else {
+                            (r.read_bits(bits).context("feature_value")? as i32).clamp(0, limit)
+                        };
+                        if feature == 0 {
+                            *alt_q = Some(value);
+                        }
+                    }
+                }
+            }
+        } else {
+            seg_alt_q = [None; MAX_SEGMENTS];
+        }
+
+        // delta_q_params() and delta_lf_params()
+        let delta_q_present = base_q_idx > 0 && r.read_bit().context("delta_q_present")?;
+        if delta_q_present {
+            r.read_bits(2).context("delta_q_res")?;
+            if !allow_intrabc && r.read_bit().context("delta_lf_present")? {
+                r.read_bits(2).context("delta_lf_res")?;
+                r.read_bit().context("delta_lf_multi")?;
+            }
+        }
+
+        let coded_lossless = deltas_zero
+            && seg_alt_q.iter().all(|alt_q| {
+                let qindex = match alt_q {
+                    Some(alt_q) => (base_q_idx + alt_q).clamp(0, 255),
+                    None => base_q_idx,
+                };
+                qindex == 0
+            });
+        let all_lossless = coded_lossless && frame_size.frame_width == frame_size.upscaled_width;
+
+        // loop_filter_params()
+        if !coded_lossless && !allow_intrabc {
+            let level_0 = r.read_bits(6).context("loop_filter_level")?;
+            let level_1 = r.read_bits(6).context("loop_filter_level")?;
+            if seq.num_planes() > 1 && (level_0 != 0 || level_1 != 0) {
+                r.read_bits(6).context("loop_filter_level")?;
+                r.read_bits(6).context("loop_filter_level")?;
+            }
+            r.read_bits(3).context("loop_filter_sharpness")?;
+            let loop_filter_delta_enabled = r.read_bit().context("loop_filter_delta_enabled")?;
+            if loop_filter_delta_enabled && r.read_bit().context("loop_filter_delta_update")? {
+                // 8 reference deltas followed by 2 mode deltas
+                for _ in 0..10 {
+                    if r.read_bit().context("update_delta")? {
+                        r.read_su(7).context("loop_filter_delta")?;
+                    }
+                }
+            }
+        }
+
+        // cdef_params()
+        if !coded_lossless && !allow_intrabc && seq.enable_cdef {
+            r.read_bits(2).context("cdef_damping_minus_3")?;
+            let cdef_bits = r.read_bits(2).context("cdef_bits")?;
+            for _ in 0..(1 << cdef_bits) {
+                r.read_bits(6).context("cdef_y_strength")?;
+                if seq.num_planes() > 1 {
+                    r.read_bits(6).context("cdef_uv_strength")?;
+                }
+            }
+        }
+
+        // lr_params()
+        if !all_lossless && !allow_intrabc && seq.enable_restoration {
+            let mut uses_lr = false;
+            let mut uses_chroma_lr = false;
+            for plane in 0..seq.num_planes() {
+                let lr_type = r.read_bits(2).context("lr_type")?;
+                if lr_type != 0 {
+                    uses_lr = true;
+                    uses_chroma_lr |= plane > 0;
+                }
+            }
+            if uses_lr {
+                let lr_unit_shift = r.read_bit().context("lr_unit_shift")?;
+                if !seq.use_128x128_superblock && lr_unit_shift {
+                    r.read_bit().context("lr_unit_extra_shift")?;
+                }
+                if seq.subsampling_x && seq.subsampling_y && uses_chroma_lr {
+                    r.read_bit().context("lr_uv_shift")?;
+                }
+            }
+        }
+
+        // read_tx_mode()
+        if !coded_lossless {
+            r.read_bit().context("tx_mode_select")?;
+        }
+
+        // frame_reference_mode()
+        let reference_select = !frame_is_intra && r.read_bit().context("reference_select")?;
+
+        // skip_mode_params()
+        if !frame_is_intra
+            && reference_select
+            && seq.enable_order_hint
+            && self.skip_mode_allowed(seq, order_hint, &ref_frame_idx)
+        {
+            r.read_bit().context("skip_mode_present")?;
+        }
+
+        if !frame_is_intra && !error_resilient_mode && seq.enable_warped_motion {
+            r.read_bit().context("allow_warped_motion")?;
+        }
+        r.read_bit().context("reduced_tx_set")?;
+
+        // global_motion_params()
+        if !frame_is_intra {
+            const IDENTITY: u32 = 0;
+            const TRANSLATION: u32 = 1;
+            const ROTZOOM: u32 = 2;
+            const AFFINE: u32 = 3;
+
+            for _ in 0..REFS_PER_FRAME {
+                let gm_type = if !r.read_bit().context("is_global")? {
+                    IDENTITY
+                } else if r.read_bit().context("is_rot_zoom")? {
+                    ROTZOOM
+                } else if r.read_bit().context("is_translation")? {
+                    TRANSLATION
+                } else {
+                    AFFINE
+                };
+
+                // Only the number of parameters and their ranges affect the size
+                let abs_alpha_bits = 12;
+                let abs_trans_bits = if gm_type == TRANSLATION {
+                    9 - !allow_high_precision_mv as u32
+                } else {
+                    12
+                };
+                let num_alpha_params = match gm_type {
+                    ROTZOOM => 2,
+                    AFFINE => 4,
+                    _ => 0,
+                };
+                for _ in 0..num_alpha_params {
+                    r.skip_subexp(2 * (1 << abs_alpha_bits) + 1)
+                        .context("global_motion_param")?;
+                }
+                if gm_type >= TRANSLATION {
+                    for _ in 0..2 {
+                        r.skip_subexp(2 * (1 << abs_trans_bits) + 1)
+                            .context("global_motion_param")?;
+                    }
+                }
+            }
+        }
+
+        // film_grain_params()
+        if seq.film_grain_params_present
+            && (show_frame || showable_frame)
+            && r.read_bit().context("apply_grain")?
+        {
+            r.read_bits(16).context("grain_seed")?;
+            let update_grain = frame_type != 1 || r.read_bit().context("update_grain")?;
+            if !update_grain {
+                r.read_bits(3).context("film_grain_params_ref_idx")?;
+            } else {
+                film_grain_params(r, seq)?;
+            }
+        }
+
+        let ref_frame = RefFrame {
+            frame_type,
+            order_hint,
+            upscaled_width: frame_size.upscaled_width,
+            frame_width: frame_size.frame_width,
+            frame_height: frame_size.frame_height,
+            render_width: frame_size.render_width,
+            render_height: frame_size.render_height,
+            seg_alt_q,
+        };
+        for (i, slot) in self.ref_frames.iter_mut().enumerate() {
+            if refresh_frame_flags & (1 << i) != 0 {
+                *slot = ref_frame;
+            }
+        }
+
+        Ok(Some(tile_info))
+    }
+
+    /// `frame_size_with_refs()`
+    fn frame_size_with_refs<R: BitRead + ?Sized>(
+        &self,
+        r: &mut R,
+        seq: &SequenceHeader,
+        ref_frame_idx: &[usize; REFS_PER_FRAME],
+    ) -> Result<FrameSize, Error> {
+        for &idx in ref_frame_idx {
+            if r.read_bit().context("found_ref")? {
+                let ref_frame = &self.ref_frames[idx];
+                let mut size = FrameSize {
+                    upscaled_width: ref_frame.upscaled_width,
+                    frame_width: ref_frame.upscaled_width,
+                    frame_height: ref_frame.frame_height,
+                    render_width: ref_frame.render_width,
+                    render_height: ref_frame.render_height,
+                };
+                superres_params(r, seq, &mut size)?;
+                return Ok(size);
+            }
+        }
+
+        read_frame_size(r, seq, true)
+    }
+
+    fn relative_dist(seq: &SequenceHeader, a: u32, b: u32) -> i32 {
+        if !seq.enable_order_hint {
+            return 0;
+        }
+        let diff = a as i32 - b as i32;
+        let m = 1 << (seq.order_hint_bits - 1);
+        (diff & (m - 1)) - (diff & m)
+    }
+
+    /// `set_frame_refs()`
+    fn set_frame_refs(
+        &self,
+        seq: &SequenceHeader,
+        order_hint: u32,
+        last_frame_idx: usize,
+        gold_frame_idx: usize,
+    ) -> [usize; REFS_PER_FRAME] {
+        // Indexed by reference frame minus LAST_FRAME
+        const LAST2: usize = 1;
+        const LAST3: usize = 2;
+        const BWDREF: usize = 4;
+        const ALTREF2: usize = 5;
+        const ALTREF: usize = 6;
+
+        let mut ref_frame_idx = [None; REFS_PER_FRAME];
+        ref_frame_idx[0] = Some(last_frame_idx);
+        ref_frame_idx[3] = Some(gold_frame_idx);
+
+        let mut used_frame = [false; NUM_REF_FRAMES];
+        used_frame[last_frame_idx] = true;
+        used_frame[gold_frame_idx] = true;
+
+        let cur_frame_hint = 1 << (seq.order_hint_bits - 1);
+        let shifted_order_hints = self.ref_frames.map(|ref_frame| {
+            cur_frame_hint + Self::relative_dist(seq, ref_frame.order_hint, order_hint)
+        });
+
+        let find = |used_frame: &[bool; NUM_REF_FRAMES], backward: bool, latest: bool| {
+            let mut found: Option<(usize, i32)> = None;
+            for (i, &hint) in shifted_order_hints.iter().enumerate() {
+                if used_frame[i] || (hint >= cur_frame_hint) != backward {
+                    continue;
+                }
+                let better = match found {
+                    None => true,
+                    Some((_, best)) if latest => hint >= best,
+                    Some((_, best)) => hint < best,
+                };
+                if better {
+                    found = Some((i, hint));
+                }
+            }
+            found.map(|(i, _)| i)
+        };
+
+        // find_latest_backward(), find_earliest_backward() twice
+        for (ref_frame, latest) in [(ALTREF, true), (BWDREF, false), (ALTREF2, false)] {
+            if let Some(idx) = find(&used_frame, true, latest) {
+                ref_frame_idx[ref_frame] = Some(idx);
+                used_frame[idx] = true;
+            }
+        }
+
+        for ref_frame in [LAST2, LAST3, BWDREF, ALTREF2, ALTREF] {
+            if ref_frame_idx[ref_frame].is_none() {
+                // find_latest_forward()
+                if let Some(idx) = find(&used_frame, false, true) {
+                    ref_frame_idx[ref_frame] = Some(idx);
+                    used_frame[idx] = true;
+                }
+            }
+        }
+
+        // Remaining references use the frame with the earliest order hint
+        let earliest = (0..NUM_REF_FRAMES)
+            .min_by_key(|&i| shifted_order_hints[i])
+            .unwrap();
+
+        ref_frame_idx.map(|idx| idx.unwrap_or(earliest))
+    }
+
+    /// Whether `skip_mode_present` is signalled.
+    fn skip_mode_allowed(
+        &self,
+        seq: &SequenceHeader,
+        order_hint: u32,
+        ref_frame_idx: &[usize; REFS_PER_FRAME],
+    ) -> bool {
+        let dist = |a, b| Self::relative_dist(seq, a, b);
+
+        let mut forward_hint = None;
+        let mut backward_hint = None;
+        for &idx in ref_frame_idx {
+            let ref_hint = self.ref_frames[idx].order_hint;
+            if dist(ref_hint, order_hint) < 0 {
+                if forward_hint.is_none_or(|forward_hint| dist(ref_hint, forward_hint) > 0) {
+                    forward_hint = Some(ref_hint);
+                }
+            } else if dist(ref_hint, order_hint) > 0
+                && backward_hint.is_none_or(|backward_hint| dist(ref_hint, backward_hint) < 0)
+            {
+                backward_hint = Some(ref_hint);
+            }
+        }
+
+        let Some(forward_hint) = forward_hint else {
+            return false;
+        };
+        if backward_hint.is_some() {
+            return true;
+        }
+
+        ref_frame_idx
+            .iter()
+            .any(|&idx| dist(self.ref_frames[idx].order_hint, forward_hint) < 0)
+    }
+}
+
+/// `frame_size()` followed by `render_size()`.
+fn read_frame_size<R: BitRead + ?Sized>(
+    r: &mut R,
+    seq: &SequenceHeader,
+    frame_size_override_flag: bool,
+) -> Result<FrameSize, Error> {
+    let mut size = FrameSize::default();
+    if frame_size_override_flag {
+        size.frame_width = r
+            .read_bits(seq.frame_width_bits)
+            .context("frame_width_minus_1")?
+            + 1;
+        size.frame_height = r
+            .read_bits(seq.frame_height_bits)
+            .context("frame_height_minus_1")?
+            + 1;
+    } else {
+        size.frame_width = seq.max_frame_width;
+        size.frame_height = seq.max_frame_height;
+    }
+    superres_params(r, seq, &mut size)?;
+
+    if r.read_bit().context("render_and_frame_size_different")? {
+        size.render_width = r.read_bits(16).context("render_width_minus_1")? + 1;
+        size.render_height = r.read_bits(16).context("render_height_minus_1")? + 1;
+    } else {
+        size.render_width = size.upscaled_width;
+        size.render_height = size.frame_height;
+    }
+
+    Ok(size)
+}
+
+/// `superres_params()` and `compute_image_size()`
+fn superres_params<R: BitRead + ?Sized>(
+    r: &mut R,
+    seq: &SequenceHeader,
+    size: &mut FrameSize,
+) -> Result<(), Error> {
+    let use_superres = seq.enable_superres && r.read_bit().context("use_superres")?;
+    let superres_denom = if use_superres {
+        r.read_bits(3).context("coded_denom")? + SUPERRES_DENOM_MIN
+    } else {
+        SUPERRES_NUM
+    };
+    size.upscaled_width = size.frame_width;
+    size.frame_width = (size.upscaled_width * SUPERRES_NUM + superres_denom / 2) / superres_denom;
+
+    Ok(())
+}
+
+/// `read_delta_q()`
+fn read_delta_q<R: BitRead + ?Sized>(r: &mut R) -> Result<i32, Error> {
+    if r.read_bit().context("delta_coded")? {
+        Ok(r.read_su(7).context("delta_q")?)
+    } else {
+        Ok(0)
+    }
+}
+
+fn tile_log2(blk_size: u32, target: u32) -> u32 {
+    let mut k = 0;
+    while (blk_size << k) < target {
+        k += 1;
+    }
+    k
+}
+
+/// `tile_info()`
+fn tile_info<R: BitRead + ?Sized>(
+    r: &mut R,
+    seq: &SequenceHeader,
+    size: &FrameSize,
+) -> Result<TileInfo, Error> {
+    let (sb_cols, sb_rows, sb_shift) = if seq.use_128x128_superblock {
+        ((size.mi_cols() + 31) >> 5, (size.mi_rows() + 31) >> 5, 5)
+    } else {
+        ((size.mi_cols() + 15) >> 4, (size.mi_rows() + 15) >> 4, 4)
+    };
+    let sb_size = sb_shift + 2;
+    let max_tile_width_sb = MAX_TILE_WIDTH >> sb_size;
+    let mut max_tile_area_sb = MAX_TILE_AREA >> (2 * sb_size);
+    let min_log2_tile_cols = tile_log2(max_tile_width_sb, sb_cols);
+    let max_log2_tile_cols = tile_log2(1, sb_cols.min(MAX_TILE_COLS));
+    let max_log2_tile_rows = tile_log2(1, sb_rows.min(MAX_TILE_ROWS));
+    let min_log2_tiles = min_log2_tile_cols.max(tile_log2(max_tile_area_sb, sb_rows * sb_cols));
+
+    let (tile_cols, tile_cols_log2, tile_rows, tile_rows_log2) =
+        if r.read_bit().context("uniform_tile_spacing_flag")? {
+            let mut cols_log2 = min_log2_tile_cols;
+            while cols_log2 < max_log2_tile_cols {
+                if !r.read_bit().context("increment_tile_cols_log2")? {
+                    break;
+                }
+                cols_log2 += 1;
+            }
+            let tile_width_sb = (sb_cols + (1 << cols_log2) - 1) >> cols_log2;
+            let tile_cols = sb_cols.div_ceil(tile_width_sb);
+
+            let mut rows_log2 = min_log2_tiles.saturating_sub(cols_log2);
+            while rows_log2 < max_log2_tile_rows {
+                if !r.read_bit().context("increment_tile_rows_log2")? {
+                    break;
+                }
+                rows_log2 += 1;
+            }
+            let tile_height_sb = (sb_rows + (1 << rows_log2) - 1) >> rows_log2;
+            let tile_rows = sb_rows.div_ceil(tile_height_sb);
+
+            (tile_cols, cols_log2, tile_rows, rows_log2)
+        } else {
+            let mut widest_tile_sb = 0;
+            let mut start_sb = 0;
+            let mut cols = 0;
+            while start_sb < sb_cols {
+                let max_width = (sb_cols - start_sb).min(max_tile_width_sb);
+                let size_sb = r.read_ns(max_width).context("width_in_sbs_minus_1")? + 1;
+                widest_tile_sb = widest_tile_sb.max(size_sb);
+                start_sb += size_sb;
+                cols += 1;
+            }
+            let tile_cols = cols;
+
+            if min_log2_tiles > 0 {
+                max_tile_area_sb = (sb_rows * sb_cols) >> (min_log2_tiles + 1);
+            } else {
+                max_tile_area_sb = sb_rows * sb_cols;
+            }
+            let max_tile_height_sb = (max_tile_area_sb / widest_tile_sb).max(1);
+
+            let mut start_sb = 0;
+            let mut rows = 0;
+            while start_sb < sb_rows {
+                let max_height = (sb_rows - start_sb).min(max_tile_height_sb);
+                start_sb += r.read_ns(max_height).context("height_in_sbs_minus_1")? + 1;
+                rows += 1;
+            }
+            let tile_rows = rows;
+
+            (
+                tile_cols,
+                tile_log2(1, tile_cols),
+                tile_rows,
+                tile_log2(1, tile_rows),
+            )
+        };
+
+    let mut tile_size_bytes = 4;
+    if tile_cols_log2 > 0 || tile_rows_log2 > 0 {
+        r.read_bits(tile_cols_log2 + tile_rows_log2)
+            .context("context_update_tile_id")?;
+        tile_size_bytes = r.read_bits(2).context("tile_size_bytes_minus_1")? + 1;
+    }
+
+    Ok(TileInfo {
+        tile_cols,
+        tile_rows,
+        tile_cols_log2,
+        tile_rows_log2,
+        tile_size_bytes,
+    })
+}
+
+/// Skips the film grain parameters after `update_grain`.
+fn film_grain_params<R: BitRead + ?Sized>(r: &mut R, seq: &SequenceHeader) -> Result<(), Error> {
+    let num_y_points = r.read_bits(4).context("num_y_points")?;
+    r.skip(16 * num_y_points).context("point_y")?;
+    let chroma_scaling_from_luma =
+        !seq.mono_chrome && r.read_bit().context("chroma_scaling_from_luma")?;
+    let (num_cb_points, num_cr_points) = if seq.mono_chrome
+        || chroma_scaling_from_luma
+        || (seq.subsampling_x && seq.subsampling_y && num_y_points == 0)
+    {
+        (0, 0)
+    } else {
+        let num_cb_points = r.read_bits(4).context("num_cb_points")?;
+        r.skip(16 * num_cb_points).context("point_cb")?;
+        let num_cr_points = r.read_bits(4).context("num_cr_points")?;
+        r.skip(16 * num_cr_points).context("point_cr")?;
+        (num_cb_points, num_cr_points)
+    };
+
+    r.read_bits(2).context("grain_scaling_minus_8")?;
+    let ar_coeff_lag = r.read_bits(2).context("ar_coeff_lag")?;
+    let num_pos_luma = 2 * ar_coeff_lag * (ar_coeff_lag + 1);
+    let num_pos_chroma = if num_y_points > 0 {
+        r.skip(8 * num_pos_luma).context("ar_coeffs_y")?;
+        num_pos_luma + 1
+    } else {
+        num_pos_luma
+    };
+    if chroma_scaling_from_luma || num_cb_points > 0 {
+        r.skip(8 * num_pos_chroma).context("ar_coeffs_cb")?;
+    }
+    if chroma_scaling_from_luma || num_cr_points > 0 {
+        r.skip(8 * num_pos_chroma).context("ar_coeffs_cr")?;
+    }
+    r.read_bits(2).context("ar_coeff_shift_minus_6")?;
+    r.read_bits(2).context("grain_scale_shift")?;
+    if num_cb_points > 0 {
+        r.skip(25).context("cb_mult")?;
+    }
+    if num_cr_points > 0 {
+        r.skip(25).context("cr_mult")?;
+    }
+    r.read_bit().context("overlap_flag")?;
+    r.read_bit().context("clip_to_restricted_range")?;
+
+    Ok(())
+}
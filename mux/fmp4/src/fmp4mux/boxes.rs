@@ -170,6 +170,12 @@ fn cmaf_brands_from_caps(caps: &gst::CapsRef, compatible_brands: &mut Vec<&'stat
         "audio/x-opus" => {
             compatible_brands.push(b"opus");
         }
+        "application/x-subtitle-vtt-fragmented" => {
+            compatible_brands.push(b"cwvt");
+        }
+        "application/ttml+xml" => {
+            compatible_brands.push(b"im1t");
+        }
         "video/x-av1" => {
             compatible_brands.push(b"av01");
             compatible_brands.push(b"cmf2");
@@ -324,14 +330,16 @@ fn cmaf_brands_from_caps(caps: &gst::CapsRef, compatible_brands: &mut Vec<&'stat
 
 fn brands_from_variant_and_caps<'a>(
     variant: super::Variant,
+    on_demand: bool,
     mut caps: impl Iterator<Item = &'a gst::Caps>,
 ) -> (&'static [u8; 4], Vec<&'static [u8; 4]>) {
     match variant {
         super::Variant::ISO | super::Variant::ONVIF => (b"iso6", vec![b"iso6"]),
-        super::Variant::DASH => {
-            // FIXME: `dsms` / `dash` brands, `msix`
-            (b"msdh", vec![b"dums", b"msdh", b"iso6"])
+        super::Variant::DASH if on_demand => {
+            // Indexed media segments of the on-demand profile, see ISO/IEC 23009-1 6.3.4.3
+            (b"dash", vec![b"iso6", b"dash", b"dsms", b"msix", b"msdh"])
         }
+        super::Variant::DASH => (b"msdh", vec![b"dums", b"msdh", b"dsms", b"dash", b"iso6"]),
         super::Variant::CMAF => {
             let mut compatible_brands = vec![b"iso6", b"cmfc"];
 
@@ -347,8 +355,11 @@ fn brands_from_variant_and_caps<'a>(
 pub(super) fn create_fmp4_header(cfg: super::HeaderConfiguration) -> Result<gst::Buffer, Error> {
     let mut v = vec![];
 
-    let (brand, compatible_brands) =
-        brands_from_variant_and_caps(cfg.variant, cfg.streams.iter().map(|s| &s.caps));
+    let (brand, compatible_brands) = brands_from_variant_and_caps(
+        cfg.variant,
+        cfg.on_demand,
+        cfg.streams.iter().map(|s| &s.caps),
+    );
 
     write_box(&mut v, b"ftyp", |v| {
         // major brand
@@ -443,6 +454,40 @@ fn write_moov(v: &mut Vec<u8>, cfg: &super::HeaderConfiguration) -> Result<(), E
     }
     write_box(v, b"mvex", |v| write_mvex(v, cfg))?;
 
+    let mut key_ids = Vec::new();
+    for encryption in cfg.streams.iter().filter_map(|s| s.encryption.as_ref()) {
+        if !key_ids.contains(&encryption.key_id) {
+            key_ids.push(encryption.key_id);
+        }
+    }
+    if !key_ids.is_empty() {
+        write_full_box(v, b"pssh", FULL_BOX_VERSION_1, FULL_BOX_FLAGS_NONE, |v| {
+            write_common_pssh(v, &key_ids)
+        })?;
+
+        for pssh in &cfg.pssh_boxes {
+            v.extend(pssh);
+        }
+    }
+
+    Ok(())
+}
+
+fn write_common_pssh(v: &mut Vec<u8>, key_ids: &[[u8; 16]]) -> Result<(), Error> {
+    // System ID
+    v.extend(super::cenc::COMMON_SYSTEM_ID);
+
+    // KID count
+    v.extend((key_ids.len() as u32).to_be_bytes());
+
+    // KIDs
+    for key_id in key_ids {
+        v.extend(key_id);
+    }
+
+    // Data size
+    v.extend(0u32.to_be_bytes());
+
     Ok(())
 }
 
@@ -633,7 +678,7 @@ fn write_trak(
     // TODO: write edts optionally for negative DTS instead of offsetting the DTS
     write_box(v, b"mdia", |v| write_mdia(v, cfg, stream, creation_time))?;
     if !stream.elst_infos.is_empty() && cfg.write_edts {
-        if let Err(e) = write_edts(v, cfg, stream) {
+        if let Err(e) = write_box(v, b"edts", |v| write_edts(v, cfg, stream)) {
             gst::warning!(CAT, "Failed to write edts: {e}");
         }
     }
@@ -805,6 +850,11 @@ fn write_hdlr(
             (b"soun", b"SoundHandler\0".as_slice())
         }
         "application/x-onvif-metadata" => (b"meta", b"MetadataHandler\0".as_slice()),
+        "application/x-subtitle-vtt-fragmented" => (b"text", b"TextHandler\0".as_slice()),
+        "application/ttml+xml" => (b"subt", b"SubtitleHandler\0".as_slice()),
+        "closedcaption/x-cea-608" | "closedcaption/x-cea-708" => {
+            (b"clcp", b"ClosedCaptionHandler\0".as_slice())
+        }
         _ => unreachable!(),
     };
 
@@ -839,11 +889,20 @@ fn write_minf(
                 write_smhd(v, cfg)
             })?
         }
-        "application/x-onvif-metadata" => {
+        "application/x-onvif-metadata"
+        | "application/x-subtitle-vtt-fragmented"
+        | "closedcaption/x-cea-608"
+        | "closedcaption/x-cea-708" => {
             write_full_box(v, b"nmhd", FULL_BOX_VERSION_0, FULL_BOX_FLAGS_NONE, |_v| {
                 Ok(())
             })?
         }
+        "application/ttml+xml" => {
+            // See ISO/IEC 14496-12 Section 12.6.2
+            write_full_box(v, b"sthd", FULL_BOX_VERSION_0, FULL_BOX_FLAGS_NONE, |_v| {
+                Ok(())
+            })?
+        }
         _ => unreachable!(),
     }
 
@@ -939,6 +998,8 @@ fn write_stsd(
     // Entry count
     v.extend(1u32.to_be_bytes());
 
+    let entry_pos = v.len();
+
     let s = stream.caps.structure(0).unwrap();
     match s.name().as_str() {
         "video/x-h264" | "video/x-h265" | "video/x-vp8" | "video/x-vp9" | "video/x-av1"
@@ -948,9 +1009,108 @@ fn write_stsd(
             write_audio_sample_entry(v, cfg, stream)?
         }
         "application/x-onvif-metadata" => write_xml_meta_data_sample_entry(v, cfg, stream)?,
+        "application/x-subtitle-vtt-fragmented" => write_wvtt_sample_entry(v, cfg, stream)?,
+        "application/ttml+xml" => write_stpp_sample_entry(v, cfg, stream)?,
+        "closedcaption/x-cea-608" | "closedcaption/x-cea-708" => {
+            write_caption_sample_entry(v, cfg, stream)?
+        }
         _ => unreachable!(),
     }
 
+    if let Some(encryption) = &stream.encryption {
+        // Replace the sample entry type and append the protection scheme information box with
+        // the original sample entry type
+        let original_fourcc = <[u8; 4]>::try_from(&v[entry_pos + 4..][..4]).unwrap();
+        let fourcc = if s.name().starts_with("audio/") {
+            b"enca"
+        } else {
+            b"encv"
+        };
+        v[entry_pos + 4..][..4].copy_from_slice(fourcc);
+
+        write_box(v, b"sinf", |v| write_sinf(v, original_fourcc, encryption))?;
+
+        let size = u32::try_from(v.len() - entry_pos).context("too big sample entry")?;
+        v[entry_pos..][..4].copy_from_slice(&size.to_be_bytes());
+    }
+
+    Ok(())
+}
+
+fn write_sinf(
+    v: &mut Vec<u8>,
+    original_fourcc: [u8; 4],
+    encryption: &super::cenc::TrackEncryption,
+) -> Result<(), Error> {
+    write_box(v, b"frma", |v| {
+        // Original format
+        v.extend(original_fourcc);
+
+        Ok(())
+    })?;
+
+    write_full_box(v, b"schm", FULL_BOX_VERSION_0, FULL_BOX_FLAGS_NONE, |v| {
+        // Scheme type
+        v.extend(match encryption.scheme {
+            super::EncryptionScheme::Cenc => b"cenc",
+            super::EncryptionScheme::Cbcs => b"cbcs",
+            super::EncryptionScheme::None => unreachable!(),
+        });
+
+        // Scheme version
+        v.extend(0x0001_0000u32.to_be_bytes());
+
+        Ok(())
+    })?;
+
+    write_box(v, b"schi", |v| {
+        let version = if encryption.scheme == super::EncryptionScheme::Cbcs {
+            FULL_BOX_VERSION_1
+        } else {
+            FULL_BOX_VERSION_0
+        };
+
+        write_full_box(v, b"tenc", version, FULL_BOX_FLAGS_NONE, |v| {
+            write_tenc(v, version, encryption)
+        })
+    })
+}
+
+fn write_tenc(
+    v: &mut Vec<u8>,
+    version: u8,
+    encryption: &super::cenc::TrackEncryption,
+) -> Result<(), Error> {
+    // Reserved
+    v.push(0);
+
+    if version == FULL_BOX_VERSION_0 {
+        // Reserved
+        v.push(0);
+    } else {
+        // Default crypt and skip byte block
+        v.push((encryption.crypt_byte_block << 4) | encryption.skip_byte_block);
+    }
+
+    // Default is protected
+    v.push(1);
+
+    // Default per-sample IV size
+    v.push(encryption.per_sample_iv_size);
+
+    // Default KID
+    v.extend(encryption.key_id);
+
+    if encryption.per_sample_iv_size == 0 {
+        let constant_iv = encryption.constant_iv.unwrap();
+
+        // Default constant IV size
+        v.push(constant_iv.len() as u8);
+
+        // Default constant IV
+        v.extend(constant_iv);
+    }
+
     Ok(())
 }
 
@@ -1312,21 +1472,7 @@ fn write_visual_sample_entry(
         }
 
         if stream.avg_bitrate.is_some() || stream.max_bitrate.is_some() {
-            write_box(v, b"btrt", |v| {
-                // Buffer size DB
-                // TODO
-                v.extend(0u32.to_be_bytes());
-
-                // Maximum bitrate
-                let max_bitrate = stream.max_bitrate.or(stream.avg_bitrate).unwrap();
-                v.extend(max_bitrate.to_be_bytes());
-
-                // Average bitrate
-                let avg_bitrate = stream.avg_bitrate.or(stream.max_bitrate).unwrap();
-                v.extend(avg_bitrate.to_be_bytes());
-
-                Ok(())
-            })?;
+            write_box(v, b"btrt", |v| write_btrt(v, stream))?;
         }
 
         Ok(())
@@ -1489,21 +1635,7 @@ fn write_audio_sample_entry(
         }
 
         if stream.avg_bitrate.is_some() || stream.max_bitrate.is_some() {
-            write_box(v, b"btrt", |v| {
-                // Buffer size DB
-                // TODO
-                v.extend(0u32.to_be_bytes());
-
-                // Maximum bitrate
-                let max_bitrate = stream.max_bitrate.or(stream.avg_bitrate).unwrap();
-                v.extend(max_bitrate.to_be_bytes());
-
-                // Average bitrate
-                let avg_bitrate = stream.avg_bitrate.or(stream.max_bitrate).unwrap();
-                v.extend(avg_bitrate.to_be_bytes());
-
-                Ok(())
-            })?;
+            write_box(v, b"btrt", |v| write_btrt(v, stream))?;
         }
 
         // TODO: chnl box for channel ordering? probably not needed for AAC
@@ -1514,6 +1646,21 @@ fn write_audio_sample_entry(
     Ok(())
 }
 
+fn write_btrt(v: &mut Vec<u8>, stream: &super::HeaderStream) -> Result<(), Error> {
+    // Buffer size DB
+    v.extend(stream.buffer_size_db.unwrap_or(0).to_be_bytes());
+
+    // Maximum bitrate
+    let max_bitrate = stream.max_bitrate.or(stream.avg_bitrate).unwrap();
+    v.extend(max_bitrate.to_be_bytes());
+
+    // Average bitrate
+    let avg_bitrate = stream.avg_bitrate.or(stream.max_bitrate).unwrap();
+    v.extend(avg_bitrate.to_be_bytes());
+
+    Ok(())
+}
+
 fn write_esds_aac(
     v: &mut Vec<u8>,
     _cfg: &super::HeaderConfiguration,
@@ -1709,6 +1856,65 @@ fn write_xml_meta_data_sample_entry(
     Ok(())
 }
 
+fn write_wvtt_sample_entry(
+    v: &mut Vec<u8>,
+    _cfg: &super::HeaderConfiguration,
+    _stream: &super::HeaderStream,
+) -> Result<(), Error> {
+    // See ISO/IEC 14496-30 Section 7.5
+    write_sample_entry_box(v, b"wvtt", move |v| {
+        write_box(v, b"vttC", |v| {
+            // WebVTT file header without any header blocks
+            v.extend(b"WEBVTT");
+
+            Ok(())
+        })
+    })?;
+
+    Ok(())
+}
+
+fn write_stpp_sample_entry(
+    v: &mut Vec<u8>,
+    _cfg: &super::HeaderConfiguration,
+    _stream: &super::HeaderStream,
+) -> Result<(), Error> {
+    // See ISO/IEC 14496-30 Section 6.5
+    write_sample_entry_box(v, b"stpp", move |v| {
+        // namespace
+        v.extend_from_slice(TTML_NAMESPACE.as_bytes());
+        v.push(0);
+
+        // schema_location, empty string list
+        v.push(0);
+
+        // auxiliary_mime_types, empty string list
+        v.push(0);
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+fn write_caption_sample_entry(
+    v: &mut Vec<u8>,
+    _cfg: &super::HeaderConfiguration,
+    stream: &super::HeaderStream,
+) -> Result<(), Error> {
+    let s = stream.caps.structure(0).unwrap();
+    let fourcc = match s.name().as_str() {
+        "closedcaption/x-cea-608" => b"c608",
+        "closedcaption/x-cea-708" => b"c708",
+        _ => unreachable!(),
+    };
+
+    // Caption sample entries have no fields besides the generic sample entry fields
+    write_sample_entry_box(v, fourcc, |_v| Ok(()))?;
+
+    Ok(())
+}
+
 fn write_stts(v: &mut Vec<u8>, _cfg: &super::HeaderConfiguration) -> Result<(), Error> {
     // Entry count
     v.extend(0u32.to_be_bytes());
@@ -1820,8 +2026,11 @@ pub(super) fn create_fmp4_fragment_header(
 
     // Don't write a `styp` if this is only a chunk unless it's the last.
     if !cfg.chunk || cfg.last_fragment {
-        let (brand, mut compatible_brands) =
-            brands_from_variant_and_caps(cfg.variant, cfg.streams.iter().map(|s| &s.caps));
+        let (brand, mut compatible_brands) = brands_from_variant_and_caps(
+            cfg.variant,
+            cfg.on_demand,
+            cfg.streams.iter().map(|s| &s.caps),
+        );
 
         if cfg.last_fragment {
             compatible_brands.push(b"lmsg");
@@ -1909,6 +2118,9 @@ fn write_moof(
     v: &mut Vec<u8>,
     cfg: &super::FragmentHeaderConfiguration,
 ) -> Result<Vec<usize>, Error> {
+    // Position of the moof box header for offsets relative to the moof
+    let moof_pos = v.len() - 8;
+
     write_full_box(v, b"mfhd", FULL_BOX_VERSION_0, FULL_BOX_FLAGS_NONE, |v| {
         write_mfhd(v, cfg)
     })?;
@@ -1921,7 +2133,7 @@ fn write_moof(
         }
 
         write_box(v, b"traf", |v| {
-            write_traf(v, cfg, &mut data_offset_offsets, idx, stream)
+            write_traf(v, cfg, moof_pos, &mut data_offset_offsets, idx, stream)
         })?;
     }
 
@@ -1977,6 +2189,8 @@ const DEFAULT_SAMPLE_SIZE_PRESENT: u32 = 0x10;
 const DEFAULT_SAMPLE_FLAGS_PRESENT: u32 = 0x20;
 const DEFAULT_BASE_IS_MOOF: u32 = 0x2_00_00;
 
+const SENC_USE_SUBSAMPLE_ENCRYPTION: u32 = 0x2;
+
 const DATA_OFFSET_PRESENT: u32 = 0x0_01;
 const FIRST_SAMPLE_FLAGS_PRESENT: u32 = 0x0_04;
 const SAMPLE_DURATION_PRESENT: u32 = 0x1_00;
@@ -2118,6 +2332,7 @@ fn analyze_buffers(
 fn write_traf(
     v: &mut Vec<u8>,
     cfg: &super::FragmentHeaderConfiguration,
+    moof_pos: usize,
     data_offset_offsets: &mut Vec<usize>,
     idx: usize,
     stream: &super::FragmentHeaderStream,
@@ -2208,11 +2423,101 @@ fn write_traf(
         tr_flags &= !FIRST_SAMPLE_FLAGS_PRESENT;
     }
 
-    // TODO: saio, saiz, sbgp, sgpd, subs?
+    if let Some(encryption) = &stream.encryption {
+        write_sample_encryption(v, moof_pos, encryption)?;
+    }
+
+    // TODO: sbgp, sgpd, subs?
 
     Ok(())
 }
 
+fn write_sample_encryption(
+    v: &mut Vec<u8>,
+    moof_pos: usize,
+    encryption: &super::cenc::FragmentEncryption,
+) -> Result<(), Error> {
+    let sizes = encryption
+        .samples
+        .iter()
+        .map(|sample| u8::try_from(sample.aux_info_size(encryption.use_subsamples)))
+        .collect::<Result<Vec<_>, _>>()
+        .context("too many subsamples")?;
+    let default_size = match sizes.first() {
+        Some(&size) if sizes.iter().all(|s| *s == size) => size,
+        _ => 0,
+    };
+
+    write_full_box(v, b"saiz", FULL_BOX_VERSION_0, FULL_BOX_FLAGS_NONE, |v| {
+        // Default sample info size
+        v.push(default_size);
+
+        // Sample count
+        v.extend((sizes.len() as u32).to_be_bytes());
+
+        // Sample info sizes
+        if default_size == 0 {
+            v.extend(&sizes);
+        }
+
+        Ok(())
+    })?;
+
+    let saio_offset_pos =
+        write_full_box(v, b"saio", FULL_BOX_VERSION_0, FULL_BOX_FLAGS_NONE, |v| {
+            // Entry count
+            v.extend(1u32.to_be_bytes());
+
+            // Offset, filled in once the senc box is written
+            let offset_pos = v.len();
+            v.extend(0u32.to_be_bytes());
+
+            Ok(offset_pos)
+        })?;
+
+    write_full_box(
+        v,
+        b"senc",
+        FULL_BOX_VERSION_0,
+        if encryption.use_subsamples {
+            SENC_USE_SUBSAMPLE_ENCRYPTION
+        } else {
+            FULL_BOX_FLAGS_NONE
+        },
+        |v| {
+            // Sample count
+            v.extend((encryption.samples.len() as u32).to_be_bytes());
+
+            // The sample auxiliary information starts right here, relative to the moof
+            let offset = u32::try_from(v.len() - moof_pos).context("too big moof")?;
+            v[saio_offset_pos..][..4].copy_from_slice(&offset.to_be_bytes());
+
+            for sample in &encryption.samples {
+                // Per-sample IV
+                v.extend(&sample.iv);
+
+                if encryption.use_subsamples {
+                    // Subsample count
+                    v.extend(
+                        u16::try_from(sample.subsamples.len())
+                            .context("too many subsamples")?
+                            .to_be_bytes(),
+                    );
+
+                    for (clear, protected) in &sample.subsamples {
+                        // Bytes of clear data
+                        v.extend(clear.to_be_bytes());
+                        // Bytes of protected data
+                        v.extend(protected.to_be_bytes());
+                    }
+                }
+            }
+
+            Ok(())
+        },
+    )
+}
+
 fn write_tfhd(
     v: &mut Vec<u8>,
     _cfg: &super::FragmentHeaderConfiguration,
@@ -2391,6 +2696,100 @@ pub(crate) fn create_mfra(
     Ok(gst::Buffer::from_mut_slice(v))
 }
 
+/// Creates `sidx` box for the first track that references all subsegments
+///
+/// `first_offset` is the distance between the end of the `sidx` box and the first subsegment.
+pub(crate) fn create_sidx(
+    timescale: u32,
+    first_offset: u64,
+    references: &[super::SidxReference],
+) -> Result<gst::Buffer, Error> {
+    let to_timescale = |time: gst::ClockTime| {
+        time.nseconds()
+            .mul_div_floor(timescale as u64, gst::ClockTime::SECOND.nseconds())
+            .context("time overflow")
+    };
+
+    let mut v = vec![];
+
+    write_full_box(
+        &mut v,
+        b"sidx",
+        FULL_BOX_VERSION_1,
+        FULL_BOX_FLAGS_NONE,
+        |v| {
+            // Reference ID
+            v.extend(1u32.to_be_bytes());
+
+            // Timescale
+            v.extend(timescale.to_be_bytes());
+
+            // Earliest presentation time
+            let earliest_presentation_time = references
+                .first()
+                .map(|reference| to_timescale(reference.start_time))
+                .transpose()?
+                .unwrap_or(0);
+            v.extend(earliest_presentation_time.to_be_bytes());
+
+            // First offset
+            v.extend(first_offset.to_be_bytes());
+
+            // Reserved
+            v.extend(0u16.to_be_bytes());
+
+            // Reference count
+            v.extend(
+                u16::try_from(references.len())
+                    .context("too many subsegments")?
+                    .to_be_bytes(),
+            );
+
+            for reference in references {
+                // Reference type (media) and referenced size
+                let size = u32::try_from(reference.size)
+                    .ok()
+                    .filter(|size| *size < 0x8000_0000)
+                    .context("too big subsegment")?;
+                v.extend(size.to_be_bytes());
+
+                // Subsegment duration
+                let duration = to_timescale(reference.end_time)?
+                    .checked_sub(to_timescale(reference.start_time)?)
+                    .and_then(|duration| u32::try_from(duration).ok())
+                    .context("invalid subsegment duration")?;
+                v.extend(duration.to_be_bytes());
+
+                // Starts with SAP, SAP type and SAP delta time
+                if reference.starts_with_sap {
+                    v.extend((0x8000_0000u32 | (1 << 28)).to_be_bytes());
+                } else {
+                    v.extend(0u32.to_be_bytes());
+                }
+            }
+
+            Ok(())
+        },
+    )?;
+
+    Ok(gst::Buffer::from_mut_slice(v))
+}
+
+/// Creates `free` box of the given size including the box header
+pub(crate) fn create_free(size: u64) -> Result<gst::Buffer, Error> {
+    let size = u32::try_from(size)
+        .ok()
+        .filter(|size| *size >= 8)
+        .context("invalid free box size")?;
+
+    let mut v = Vec::with_capacity(size as usize);
+    v.extend(size.to_be_bytes());
+    v.extend(b"free");
+    v.resize(size as usize, 0);
+
+    Ok(gst::Buffer::from_mut_slice(v))
+}
+
 /// Create FLAC `dfLa` box.
 pub(crate) fn write_dfla(caps: &gst::CapsRef) -> Result<Vec<u8>, Error> {
     let mut dfla = Vec::new();
@@ -2605,6 +3004,205 @@ pub(crate) fn create_dec3(buffer: &gst::BufferRef) -> Result<Vec<u8>, Error> {
     Ok(dec3)
 }
 
+const TTML_NAMESPACE: &str = "http://www.w3.org/ns/ttml";
+
+/// Creates a timed text sample as defined in ISO/IEC 14496-30 from a buffer.
+///
+/// Returns `None` if the buffer contains no cues, in which case an empty sample has to be used.
+pub(crate) fn create_text_sample(
+    caps: &gst::CapsRef,
+    buffer: &gst::BufferRef,
+) -> Result<Option<Vec<u8>>, Error> {
+    let map = buffer
+        .map_readable()
+        .context("Mapping text buffer readable")?;
+
+    match caps.structure(0).unwrap().name().as_str() {
+        "application/x-subtitle-vtt-fragmented" => {
+            let text = std::str::from_utf8(&map).context("WebVTT is not valid UTF-8")?;
+            create_wvtt_sample(text)
+        }
+        "application/ttml+xml" => {
+            if map.iter().all(u8::is_ascii_whitespace) {
+                Ok(None)
+            } else {
+                Ok(Some(map.to_vec()))
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Creates an empty timed text sample for filling the time between cues.
+pub(crate) fn create_empty_text_sample(caps: &gst::CapsRef) -> Vec<u8> {
+    match caps.structure(0).unwrap().name().as_str() {
+        "application/x-subtitle-vtt-fragmented" => {
+            // See ISO/IEC 14496-30 Section 7.4
+            let mut v = vec![];
+            write_box(&mut v, b"vtte", |_v| Ok(())).unwrap();
+            v
+        }
+        "application/ttml+xml" => {
+            format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?><tt xmlns=\"{TTML_NAMESPACE}\"/>")
+                .into_bytes()
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Converts a WebVTT fragment into a `wvtt` sample with one `vttc` box per cue.
+///
+/// The `WEBVTT` header and any `NOTE`, `STYLE` or `REGION` blocks are skipped.
+fn create_wvtt_sample(text: &str) -> Result<Option<Vec<u8>>, Error> {
+    let mut v = vec![];
+
+    let text = text.replace("\r\n", "\n");
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().filter(|line| !line.is_empty());
+
+        let Some(mut timing) = lines.next() else {
+            continue;
+        };
+        if ["WEBVTT", "NOTE", "STYLE", "REGION"]
+            .iter()
+            .any(|keyword| timing.starts_with(keyword))
+        {
+            continue;
+        }
+
+        // Cue identifier is optional and on the line before the timing
+        let mut identifier = None;
+        if !timing.contains("-->") {
+            identifier = Some(timing);
+            timing = lines
+                .next()
+                .filter(|line| line.contains("-->"))
+                .ok_or_else(|| anyhow!("WebVTT cue without timing"))?;
+        }
+
+        // Cue settings follow the end timestamp
+        let (_, end) = timing.split_once("-->").unwrap();
+        let settings = end
+            .trim()
+            .split_once(char::is_whitespace)
+            .map(|(_, settings)| settings.trim())
+            .filter(|settings| !settings.is_empty());
+
+        let payload = lines.collect::<Vec<_>>().join("\n");
+
+        write_box(&mut v, b"vttc", |v| {
+            if let Some(identifier) = identifier {
+                write_box(v, b"iden", |v| {
+                    v.extend(identifier.as_bytes());
+                    Ok(())
+                })?;
+            }
+
+            if let Some(settings) = settings {
+                write_box(v, b"sttg", |v| {
+                    v.extend(settings.as_bytes());
+                    Ok(())
+                })?;
+            }
+
+            write_box(v, b"payl", |v| {
+                v.extend(payload.as_bytes());
+                Ok(())
+            })
+        })?;
+    }
+
+    if v.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(v))
+    }
+}
+
+/// Creates a `c608` or `c708` sample from a CEA-608 or CEA-708 caption buffer.
+///
+/// CEA-608 byte pairs are stored in a `cdat` box for field 1 and a `cdt2` box for field 2,
+/// CEA-708 CDPs are stored as a whole in a `ccdp` box.
+pub(crate) fn create_caption_sample(
+    caps: &gst::CapsRef,
+    buffer: &gst::BufferRef,
+) -> Result<Vec<u8>, Error> {
+    let map = buffer
+        .map_readable()
+        .context("Mapping caption buffer readable")?;
+
+    let s = caps.structure(0).unwrap();
+    let mut v = vec![];
+    match s.name().as_str() {
+        "closedcaption/x-cea-608" => {
+            let (mut field1, field2) = split_cea608_fields(
+                s.get::<&str>("format")
+                    .context("CEA-608 caps without format")?,
+                &map,
+            )?;
+
+            // Write padding for samples without any caption data
+            if field1.is_empty() && field2.is_empty() {
+                field1.extend([0x80, 0x80]);
+            }
+
+            if !field1.is_empty() {
+                write_box(&mut v, b"cdat", |v| {
+                    v.extend(field1);
+                    Ok(())
+                })?;
+            }
+            if !field2.is_empty() {
+                write_box(&mut v, b"cdt2", |v| {
+                    v.extend(field2);
+                    Ok(())
+                })?;
+            }
+        }
+        "closedcaption/x-cea-708" => {
+            write_box(&mut v, b"ccdp", |v| {
+                v.extend_from_slice(&map);
+                Ok(())
+            })?;
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(v)
+}
+
+/// Splits CEA-608 caption data into the byte pairs of field 1 and field 2.
+fn split_cea608_fields(format: &str, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    match format {
+        "raw" => {
+            if data.len() % 2 != 0 {
+                bail!("Invalid raw CEA-608 data size {}", data.len());
+            }
+
+            Ok((data.to_vec(), vec![]))
+        }
+        "s334-1a" => {
+            if data.len() % 3 != 0 {
+                bail!("Invalid S334-1A CEA-608 data size {}", data.len());
+            }
+
+            let mut field1 = vec![];
+            let mut field2 = vec![];
+            for triplet in data.chunks_exact(3) {
+                // The highest bit of the first byte is set for field 1
+                if triplet[0] & 0x80 != 0 {
+                    field1.extend_from_slice(&triplet[1..]);
+                } else {
+                    field2.extend_from_slice(&triplet[1..]);
+                }
+            }
+
+            Ok((field1, field2))
+        }
+        _ => bail!("Unsupported CEA-608 format {format}"),
+    }
+}
+
 mod ac3 {
     use anyhow::{bail, Context, Error};
     use bitstream_io::{FromBitStream, ToBitStream};
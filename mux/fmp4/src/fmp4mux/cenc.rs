@@ -0,0 +1,606 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Common Encryption (ISO/IEC 23001-7) of samples with the `cenc` and `cbcs` schemes.
+//!
+//! Video samples are encrypted with subsample encryption: the NAL unit and slice headers and AV1
+//! OBU, frame and tile group headers stay in the clear and the protected part of each NAL unit /
+//! AV1 tile is a multiple of the AES block size. Audio samples are encrypted as a whole.
+
+use std::cmp;
+
+use aes::cipher::{BlockEncrypt, KeyInit};
+use anyhow::{bail, Context, Error};
+
+use super::av1;
+use super::EncryptionScheme;
+
+/// System ID of the W3C Common PSSH box format.
+pub(crate) const COMMON_SYSTEM_ID: [u8; 16] = [
+    0x10, 0x77, 0xef, 0xec, 0xc0, 0xb2, 0x4d, 0x02, 0xac, 0xe3, 0x3c, 0x1e, 0x52, 0xe2, 0xfb, 0x4b,
+];
+
+/// Encryption configuration shared by all tracks.
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub(crate) scheme: EncryptionScheme,
+    pub(crate) key_id: [u8; 16],
+    key: [u8; 16],
+    /// Initial IV if configured, otherwise a random IV is used for each track.
+    iv: Option<Vec<u8>>,
+}
+
+impl Config {
+    /// Parses the configuration from the hex-encoded property values.
+    ///
+    /// Returns `None` if encryption is disabled.
+    pub(crate) fn from_hex(
+        scheme: EncryptionScheme,
+        key_id: Option<&str>,
+        key: Option<&str>,
+        iv: Option<&str>,
+    ) -> Result<Option<Self>, Error> {
+        if scheme == EncryptionScheme::None {
+            return Ok(None);
+        }
+
+        let parse_16 = |name: &str, value: Option<&str>| -> Result<[u8; 16], Error> {
+            let value = value.with_context(|| format!("No {name} configured"))?;
+            let value = hex::decode(value).with_context(|| format!("Invalid {name}"))?;
+            <[u8; 16]>::try_from(value.as_slice())
+                .ok()
+                .with_context(|| format!("{name} must be 16 bytes"))
+        };
+
+        let key_id = parse_16("key ID", key_id)?;
+        let key = parse_16("key", key)?;
+
+        let iv = iv
+            .map(|iv| hex::decode(iv).context("Invalid IV"))
+            .transpose()?;
+        match (scheme, iv.as_ref().map(Vec::len)) {
+            (_, None)
+            | (EncryptionScheme::Cenc, Some(8 | 16))
+            | (EncryptionScheme::Cbcs, Some(16)) => {}
+            (EncryptionScheme::Cenc, Some(_)) => bail!("IV must be 8 or 16 bytes for cenc"),
+            (_, Some(_)) => bail!("IV must be 16 bytes for cbcs"),
+        }
+
+        Ok(Some(Config {
+            scheme,
+            key_id,
+            key,
+            iv,
+        }))
+    }
+}
+
+/// Default encryption parameters of a track for the `tenc` box.
+#[derive(Debug, Clone)]
+pub(crate) struct TrackEncryption {
+    pub(crate) scheme: EncryptionScheme,
+    pub(crate) key_id: [u8; 16],
+    /// Size of the per-sample IVs, 0 if a constant IV is used.
+    pub(crate) per_sample_iv_size: u8,
+    /// Constant IV for all samples if no per-sample IVs are used.
+    pub(crate) constant_iv: Option<[u8; 16]>,
+    /// Encryption pattern, only used for `cbcs`.
+    pub(crate) crypt_byte_block: u8,
+    pub(crate) skip_byte_block: u8,
+}
+
+/// Sample auxiliary information of a single sample for the `senc` box.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SampleEncryption {
+    /// Per-sample IV, empty if a constant IV is used.
+    pub(crate) iv: Vec<u8>,
+    /// Number of clear and protected bytes of each subsample.
+    pub(crate) subsamples: Vec<(u16, u32)>,
+}
+
+impl SampleEncryption {
+    /// Size of the sample auxiliary information for the `saiz` box.
+    pub(crate) fn aux_info_size(&self, use_subsamples: bool) -> usize {
+        self.iv.len()
+            + if use_subsamples {
+                2 + 6 * self.subsamples.len()
+            } else {
+                0
+            }
+    }
+}
+
+/// Sample auxiliary information of all samples of a track in a fragment.
+#[derive(Debug, Clone)]
+pub(crate) struct FragmentEncryption {
+    /// Whether subsample encryption is used for this track.
+    pub(crate) use_subsamples: bool,
+    /// One entry for every sample of the track in the fragment, in order.
+    pub(crate) samples: Vec<SampleEncryption>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SampleFormat {
+    /// Length-prefixed H.264 / H.265 NAL units.
+    Nal { length_size: usize, h265: bool },
+    /// AV1 OBUs in low overhead bitstream format.
+    Av1,
+    /// Whole sample is encrypted.
+    Full,
+}
+
+/// Encrypts the samples of a single track.
+pub(crate) struct Encryptor {
+    cipher: aes::Aes128,
+    track: TrackEncryption,
+    format: SampleFormat,
+    /// Frame header parser state for AV1.
+    av1: Option<av1::Parser>,
+    /// Counter block for the next sample with `cenc`.
+    next_counter: u128,
+}
+
+impl std::fmt::Debug for Encryptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encryptor")
+            .field("track", &self.track)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl Encryptor {
+    /// Creates an encryptor for the track with index `idx` and the given caps.
+    ///
+    /// Returns `None` if samples of this format are not encrypted.
+    pub(crate) fn new(
+        config: &Config,
+        idx: usize,
+        caps: &gst::CapsRef,
+    ) -> Result<Option<Self>, Error> {
+        let s = caps.structure(0).unwrap();
+
+        let format = match s.name().as_str() {
+            "video/x-h264" => SampleFormat::Nal {
+                // lengthSizeMinusOne is in the 5th byte of the avcC
+                length_size: nal_length_size(s, 4),
+                h265: false,
+            },
+            "video/x-h265" => SampleFormat::Nal {
+                // lengthSizeMinusOne is in the 22nd byte of the hvcC
+                length_size: nal_length_size(s, 21),
+                h265: true,
+            },
+            "video/x-av1" => SampleFormat::Av1,
+            name if name.starts_with("audio/") => SampleFormat::Full,
+            _ => return Ok(None),
+        };
+
+        let video = format != SampleFormat::Full;
+
+        let av1 = if format == SampleFormat::Av1 {
+            let mut parser = av1::Parser::default();
+            if let Ok(codec_data) = s.get::<&gst::BufferRef>("codec_data") {
+                let map = codec_data
+                    .map_readable()
+                    .context("codec_data not mappable")?;
+                parser
+                    .parse_codec_data(&map)
+                    .context("Failed to parse av1C")?;
+            }
+            Some(parser)
+        } else {
+            None
+        };
+
+        let (per_sample_iv_size, constant_iv, crypt_byte_block, skip_byte_block, next_counter) =
+            match config.scheme {
+                EncryptionScheme::Cenc => {
+                    let (iv_size, counter) = match config.iv.as_deref() {
+                        // Tracks share the key, so make sure their IVs never overlap as long
+                        // as there are less than 2^32 samples per track.
+                        Some(iv) if iv.len() == 8 => {
+                            let iv = u64::from_be_bytes(iv.try_into().unwrap());
+                            (8, (iv.wrapping_add((idx as u64) << 32) as u128) << 64)
+                        }
+                        Some(iv) => {
+                            let iv = u128::from_be_bytes(iv.try_into().unwrap());
+                            (16, iv.wrapping_add((idx as u128) << 96))
+                        }
+                        None => (8, (rand::random::<u64>() as u128) << 64),
+                    };
+                    (iv_size, None, 0, 0, counter)
+                }
+                EncryptionScheme::Cbcs => {
+                    let iv = match config.iv.as_deref() {
+                        Some(iv) => iv.try_into().unwrap(),
+                        None => rand::random::<[u8; 16]>(),
+                    };
+                    // 1:9 pattern for video as required by the specification, all blocks for audio.
+                    let (crypt, skip) = if video { (1, 9) } else { (0, 0) };
+                    (0, Some(iv), crypt, skip, 0)
+                }
+                EncryptionScheme::None => unreachable!(),
+            };
+
+        Ok(Some(Encryptor {
+            cipher: aes::Aes128::new(&config.key.into()),
+            track: TrackEncryption {
+                scheme: config.scheme,
+                key_id: config.key_id,
+                per_sample_iv_size,
+                constant_iv,
+                crypt_byte_block,
+                skip_byte_block,
+            },
+            format,
+            av1,
+            next_counter,
+        }))
+    }
+
+    pub(crate) fn track_encryption(&self) -> &TrackEncryption {
+        &self.track
+    }
+
+    /// Whether subsample encryption is used for this track.
+    pub(crate) fn use_subsamples(&self) -> bool {
+        self.format != SampleFormat::Full
+    }
+
+    /// Encrypts a single sample in place and returns its auxiliary information.
+    pub(crate) fn encrypt(&mut self, data: &mut [u8]) -> Result<SampleEncryption, Error> {
+        let subsamples = match self.format {
+            SampleFormat::Nal { length_size, h265 } => nal_subsamples(data, length_size, h265)?,
+            SampleFormat::Av1 => av1_subsamples(data, self.av1.as_mut().unwrap())?,
+            SampleFormat::Full => Vec::new(),
+        };
+
+        let ranges = if self.use_subsamples() {
+            let mut offset = 0;
+            subsamples
+                .iter()
+                .map(|&(clear, protected)| {
+                    let start = offset + clear as usize;
+                    offset = start + protected as usize;
+                    start..offset
+                })
+                .collect::<Vec<_>>()
+        } else {
+            vec![0..data.len()]
+        };
+
+        let iv = match self.track.scheme {
+            EncryptionScheme::Cenc => {
+                // The counter continues over all subsamples of a sample
+                let mut ctr = Ctr::new(&self.cipher, self.next_counter);
+                for range in ranges {
+                    ctr.apply(&mut data[range]);
+                }
+
+                let iv_size = self.track.per_sample_iv_size as usize;
+                let iv = self.next_counter.to_be_bytes()[..iv_size].to_vec();
+                self.next_counter = if iv_size == 8 {
+                    // 8 byte IVs are incremented by one per sample and the block counter in the
+                    // lower 64 bits starts at zero for each sample.
+                    ((self.next_counter >> 64) + 1) << 64
+                } else {
+                    // 16 byte IVs continue after the last block used by this sample.
+                    ctr.counter
+                };
+
+                iv
+            }
+            EncryptionScheme::Cbcs => {
+                // The CBC chain starts with the constant IV in each subsample
+                let iv = self.track.constant_iv.unwrap();
+                for range in ranges {
+                    cbc_pattern(
+                        &self.cipher,
+                        &iv,
+                        &mut data[range],
+                        self.track.crypt_byte_block,
+                        self.track.skip_byte_block,
+                    );
+                }
+
+                Vec::new()
+            }
+            EncryptionScheme::None => unreachable!(),
+        };
+
+        Ok(SampleEncryption { iv, subsamples })
+    }
+}
+
+fn nal_length_size(s: &gst::StructureRef, offset: usize) -> usize {
+    s.get::<&gst::BufferRef>("codec_data")
+        .ok()
+        .and_then(|codec_data| {
+            let map = codec_data.map_readable().ok()?;
+            map.get(offset).map(|b| (b & 0x03) as usize + 1)
+        })
+        .unwrap_or(4)
+}
+
+/// Collects clear and protected ranges into subsample entries.
+#[derive(Default)]
+struct SubsampleBuilder {
+    subsamples: Vec<(u16, u32)>,
+    clear: usize,
+}
+
+impl SubsampleBuilder {
+    fn clear(&mut self, size: usize) {
+        self.clear += size;
+    }
+
+    /// Adds a unit with a clear header and an encrypted payload.
+    ///
+    /// The payload is shortened to a multiple of the AES block size and the remainder is moved
+    /// into the clear header.
+    fn unit(&mut self, header_size: usize, payload_size: usize) -> Result<(), Error> {
+        let remainder = payload_size % 16;
+        self.clear += header_size + remainder;
+
+        let protected = payload_size - remainder;
+        if protected == 0 {
+            return Ok(());
+        }
+
+        self.flush_clear();
+        self.subsamples.push((
+            self.clear as u16,
+            u32::try_from(protected).context("too big subsample")?,
+        ));
+        self.clear = 0;
+
+        Ok(())
+    }
+
+    /// Splits clear ranges that don't fit into a single subsample entry.
+    fn flush_clear(&mut self) {
+        while self.clear > u16::MAX as usize {
+            self.subsamples.push((u16::MAX, 0));
+            self.clear -= u16::MAX as usize;
+        }
+    }
+
+    fn finish(mut self) -> Vec<(u16, u32)> {
+        if self.clear > 0 {
+            self.flush_clear();
+            self.subsamples.push((self.clear as u16, 0));
+        }
+
+        self.subsamples
+    }
+}
+
+/// Number of bytes after the NAL unit header of VCL NAL units that stay in the clear.
+///
+/// The slice header has to stay in the clear but is not parsed as that would require tracking
+/// all SPS and PPS, so a conservative prefix that covers the slice header of all but slices with
+/// very large prediction weight tables or reference picture list modifications is used instead.
+const SLICE_HEADER_CLEAR_SIZE: usize = 64;
+
+/// Creates the subsamples for length-prefixed H.264 / H.265 NAL units.
+///
+/// The NAL unit header and the slice header of VCL NAL units stay in the clear and all other NAL
+/// units are not encrypted.
+fn nal_subsamples(data: &[u8], length_size: usize, h265: bool) -> Result<Vec<(u16, u32)>, Error> {
+    let mut builder = SubsampleBuilder::default();
+    let header_size = if h265 { 2 } else { 1 };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        if data.len() - pos < length_size {
+            bail!("Truncated NAL unit length");
+        }
+        let len = data[pos..][..length_size]
+            .iter()
+            .fold(0usize, |len, b| (len << 8) | *b as usize);
+        let nal_start = pos + length_size;
+        let nal_end = nal_start
+            .checked_add(len)
+            .filter(|end| *end <= data.len())
+            .context("Truncated NAL unit")?;
+
+        let is_vcl = len > header_size
+            && if h265 {
+                (data[nal_start] >> 1) & 0x3f < 32
+            } else {
+                (1..=5).contains(&(data[nal_start] & 0x1f))
+            };
+
+        if is_vcl {
+            let clear_size = cmp::min(len, header_size + SLICE_HEADER_CLEAR_SIZE);
+            builder.unit(length_size + clear_size, len - clear_size)?;
+        } else {
+            builder.clear(nal_end - pos);
+        }
+
+        pos = nal_end;
+    }
+
+    Ok(builder.finish())
+}
+
+/// Creates the subsamples for AV1 OBUs.
+///
+/// Only the tile data of tile group and frame OBUs is encrypted, with a separate subsample for
+/// each tile. The OBU headers, the frame header of frame OBUs, the tile group header and the tile
+/// sizes stay in the clear.
+fn av1_subsamples(data: &[u8], parser: &mut av1::Parser) -> Result<Vec<(u16, u32)>, Error> {
+    const OBU_SEQUENCE_HEADER: u8 = 1;
+    const OBU_FRAME_HEADER: u8 = 3;
+    const OBU_TILE_GROUP: u8 = 4;
+    const OBU_FRAME: u8 = 6;
+
+    let mut builder = SubsampleBuilder::default();
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data[pos];
+        let obu_type = (header >> 3) & 0x0f;
+        let has_extension = header & 0x04 != 0;
+        let has_size = header & 0x02 != 0;
+
+        let mut header_size = 1 + has_extension as usize;
+        if pos + header_size > data.len() {
+            bail!("Truncated OBU header");
+        }
+        let (temporal_id, spatial_id) = if has_extension {
+            let extension = data[pos + 1];
+            (extension >> 5, (extension >> 3) & 0x03)
+        } else {
+            (0, 0)
+        };
+
+        let payload_size = if has_size {
+            let (size, leb128_size) = read_leb128(&data[pos + header_size..])?;
+            header_size += leb128_size;
+            size
+        } else {
+            data.len() - pos - header_size
+        };
+
+        let obu_end = (pos + header_size)
+            .checked_add(payload_size)
+            .filter(|end| *end <= data.len())
+            .context("Truncated OBU")?;
+        let payload = &data[pos + header_size..obu_end];
+
+        match obu_type {
+            OBU_SEQUENCE_HEADER => {
+                parser
+                    .parse_sequence_header(payload)
+                    .context("Failed to parse sequence header")?;
+                builder.clear(obu_end - pos);
+            }
+            OBU_FRAME_HEADER => {
+                parser
+                    .parse_frame_header(payload, temporal_id, spatial_id)
+                    .context("Failed to parse frame header")?;
+                builder.clear(obu_end - pos);
+            }
+            OBU_FRAME | OBU_TILE_GROUP => {
+                let (tile_group_offset, tile_info) = if obu_type == OBU_FRAME {
+                    let frame_header = parser
+                        .parse_frame_header(payload, temporal_id, spatial_id)
+                        .context("Failed to parse frame header")?;
+                    (
+                        frame_header.size,
+                        frame_header
+                            .tile_info
+                            .context("Frame OBU without tile info")?,
+                    )
+                } else {
+                    (
+                        0,
+                        parser
+                            .tile_info()
+                            .context("Tile group without frame header")?,
+                    )
+                };
+
+                let tile_group_start = pos + header_size + tile_group_offset;
+                let mut clear_start = pos;
+                for tile in tile_info.tiles(&payload[tile_group_offset..])? {
+                    builder.unit(tile_group_start + tile.start - clear_start, tile.len())?;
+                    clear_start = tile_group_start + tile.end;
+                }
+                builder.clear(obu_end - clear_start);
+            }
+            _ => builder.clear(obu_end - pos),
+        }
+
+        pos = obu_end;
+    }
+
+    Ok(builder.finish())
+}
+
+pub(super) fn read_leb128(data: &[u8]) -> Result<(usize, usize), Error> {
+    let mut value = 0usize;
+    for (i, byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7f) as usize) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+
+    bail!("Invalid leb128 value");
+}
+
+/// AES-CTR keystream that continues over multiple ranges.
+struct Ctr<'a> {
+    cipher: &'a aes::Aes128,
+    counter: u128,
+    keystream: [u8; 16],
+    pos: usize,
+}
+
+impl<'a> Ctr<'a> {
+    fn new(cipher: &'a aes::Aes128, counter: u128) -> Self {
+        Ctr {
+            cipher,
+            counter,
+            keystream: [0; 16],
+            pos: 16,
+        }
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        for byte in data {
+            if self.pos == 16 {
+                let mut block = aes::Block::from(self.counter.to_be_bytes());
+                self.cipher.encrypt_block(&mut block);
+                self.keystream.copy_from_slice(&block);
+                self.counter = self.counter.wrapping_add(1);
+                self.pos = 0;
+            }
+
+            *byte ^= self.keystream[self.pos];
+            self.pos += 1;
+        }
+    }
+}
+
+/// AES-CBC encryption with a `crypt:skip` block pattern.
+///
+/// A `0:0` pattern encrypts all blocks. A trailing partial block always stays in the clear.
+fn cbc_pattern(cipher: &aes::Aes128, iv: &[u8; 16], data: &mut [u8], crypt: u8, skip: u8) {
+    let (crypt, skip) = if crypt == 0 && skip == 0 {
+        (1, 0)
+    } else {
+        (crypt as usize, skip as usize)
+    };
+
+    let mut chain = *iv;
+    let mut blocks = data.chunks_exact_mut(16);
+    loop {
+        for _ in 0..crypt {
+            let Some(block) = blocks.next() else {
+                return;
+            };
+
+            for (b, c) in block.iter_mut().zip(chain.iter()) {
+                *b ^= c;
+            }
+            let block = aes::Block::from_mut_slice(block);
+            cipher.encrypt_block(block);
+            chain.copy_from_slice(block);
+        }
+
+        for _ in 0..skip {
+            if blocks.next().is_none() {
+                return;
+            }
+        }
+    }
+}
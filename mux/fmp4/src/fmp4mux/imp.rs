@@ -116,6 +116,13 @@ const DEFAULT_MANUAL_SPLIT: bool = false;
 const DEFAULT_OFFSET_TO_ZERO: bool = false;
 const DEFAULT_DECODE_TIME_OFFSET: gst::ClockTimeDiff = 0;
 const DEFAULT_START_FRAGMENT_SEQUENCE_NUMBER: u32 = 1;
+const DEFAULT_ON_DEMAND: bool = false;
+const DEFAULT_ENCRYPTION_SCHEME: super::EncryptionScheme = super::EncryptionScheme::None;
+
+/// Number of `sidx` entries to reserve space for in DASH on-demand mode if the duration is unknown.
+const DEFAULT_SIDX_RESERVE_ENTRIES: u64 = 1024;
+/// Additional space to reserve in DASH on-demand mode for the final header being bigger.
+const HEADER_RESERVE_SLACK: u64 = 256;
 
 #[derive(Debug, Clone)]
 struct Settings {
@@ -133,6 +140,12 @@ struct Settings {
     manual_split: bool,
     decode_time_offset: gst::ClockTimeDiff,
     start_fragment_sequence_number: u32,
+    on_demand: bool,
+    encryption_scheme: super::EncryptionScheme,
+    key_id: Option<String>,
+    key: Option<String>,
+    iv: Option<String>,
+    pssh_boxes: gst::Array,
 }
 
 impl Default for Settings {
@@ -152,6 +165,12 @@ impl Default for Settings {
             manual_split: DEFAULT_MANUAL_SPLIT,
             decode_time_offset: DEFAULT_DECODE_TIME_OFFSET,
             start_fragment_sequence_number: DEFAULT_START_FRAGMENT_SEQUENCE_NUMBER,
+            on_demand: DEFAULT_ON_DEMAND,
+            encryption_scheme: DEFAULT_ENCRYPTION_SCHEME,
+            key_id: None,
+            key: None,
+            iv: None,
+            pssh_boxes: gst::Array::default(),
         }
     }
 }
@@ -291,9 +310,21 @@ struct Stream {
     avg_bitrate: Option<u32>,
     max_bitrate: Option<u32>,
 
+    /// Number of bytes of all samples drained so far.
+    drained_bytes: u64,
+    /// Size of the biggest sample drained so far.
+    max_sample_size: u32,
+    /// Start and number of bytes drained since then for measuring the maximum bitrate.
+    bitrate_window: Option<(gst::ClockTime, u64)>,
+    /// Maximum bitrate measured over windows of at least one second.
+    measured_max_bitrate: Option<u32>,
+
     /// Edit list entries for this stream.
     elst_infos: Vec<super::ElstInfo>,
 
+    /// Encryptor for the samples if Common Encryption is enabled.
+    encryptor: Option<super::cenc::Encryptor>,
+
     /// Pending split-now event for this stream, if any.
     ///
     /// This will be processed on the next aggregate call once
@@ -304,7 +335,6 @@ struct Stream {
 impl Stream {
     fn get_elst_infos(&self) -> Result<Vec<super::ElstInfo>, anyhow::Error> {
         let mut elst_infos = self.elst_infos.clone();
-        let earliest_pts = self.earliest_pts.unwrap_or(gst::ClockTime::ZERO);
         let end_pts = self
             .end_pts
             .unwrap_or(gst::ClockTime::from_nseconds(u64::MAX - 1));
@@ -322,7 +352,11 @@ impl Stream {
                             .unwrap_or(gst::ClockTime::ZERO),
                     )
                 } else {
-                    Some(end_pts - earliest_pts)
+                    Some(
+                        (gst::Signed::Positive(end_pts) - elst_info.start.unwrap())
+                            .positive()
+                            .unwrap_or(gst::ClockTime::ZERO),
+                    )
                 }
             }
         }
@@ -370,6 +404,26 @@ impl Stream {
         }
     }
 
+    /// Average and maximum bitrate measured from all samples drained so far.
+    fn measured_bitrates(&self) -> Option<(u32, u32)> {
+        let duration = self.end_pts?.checked_sub(self.earliest_pts?)?;
+        if duration.is_zero() || self.drained_bytes == 0 {
+            return None;
+        }
+
+        let avg_bitrate = (self.drained_bytes * 8)
+            .mul_div_floor(gst::ClockTime::SECOND.nseconds(), duration.nseconds())
+            .and_then(|bitrate| u32::try_from(bitrate).ok())
+            .unwrap_or(u32::MAX);
+        let max_bitrate = self
+            .measured_max_bitrate
+            .map_or(avg_bitrate, |max_bitrate| {
+                cmp::max(max_bitrate, avg_bitrate)
+            });
+
+        Some((avg_bitrate, max_bitrate))
+    }
+
     fn caps_or_tag_change(&self) -> bool {
         self.next_caps.is_some() || self.tag_changed
     }
@@ -387,6 +441,22 @@ impl Stream {
         self.stream_orientation.unwrap_or(self.global_orientation)
     }
 
+    /// Whether this is a timed text stream that is muxed as `wvtt` or `stpp`.
+    fn is_text(&self) -> bool {
+        matches!(
+            self.caps.structure(0).unwrap().name().as_str(),
+            "application/x-subtitle-vtt-fragmented" | "application/ttml+xml"
+        )
+    }
+
+    /// Whether this is a closed caption stream that is muxed as `c608` or `c708`.
+    fn is_caption(&self) -> bool {
+        matches!(
+            self.caps.structure(0).unwrap().name().as_str(),
+            "closedcaption/x-cea-608" | "closedcaption/x-cea-708"
+        )
+    }
+
     fn parse_language_code(lang: &str) -> Option<[u8; 3]> {
         let lang = gst_tag::language_codes::language_code_iso_639_2t(lang)?;
         if lang.len() == 3 && lang.chars().all(|c| c.is_ascii_lowercase()) {
@@ -444,6 +514,13 @@ struct State {
 
     /// If headers (ftyp / moov box) were sent.
     sent_headers: bool,
+    /// Size of the headers that were sent last.
+    sent_header_size: u64,
+
+    /// Size of the space reserved after the headers for the `sidx` box in DASH on-demand mode.
+    sidx_reserve_size: u64,
+    /// Subsegments for the `sidx` box in DASH on-demand mode.
+    sidx_references: Vec<super::SidxReference>,
 
     /// split-at-running-time requests
     pending_split_at_running_time_requests: BTreeSet<gst::ClockTime>,
@@ -457,6 +534,7 @@ impl State {
 
         self.current_offset = 0;
         self.fragment_offsets.clear();
+        self.sidx_references.clear();
         self.pending_split_at_running_time_requests.clear();
         self.end_pts = None;
         self.fragment_start_pts = None;
@@ -569,6 +647,14 @@ impl FMP4Mux {
             return Err(gst::FlowError::Error);
         }
 
+        // Every timed text and caption sample is a sync sample, but e.g. WebVTT cues are usually
+        // flagged as delta units after the header.
+        if (stream.is_text() || stream.is_caption())
+            && buffer.flags().contains(gst::BufferFlags::DELTA_UNIT)
+        {
+            buffer.make_mut().unset_flags(gst::BufferFlags::DELTA_UNIT);
+        }
+
         if delta_frames.intra_only() && buffer.flags().contains(gst::BufferFlags::DELTA_UNIT) {
             gst::error!(CAT, obj = sinkpad, "Intra-only stream with delta units");
             return Err(gst::FlowError::Error);
@@ -650,17 +736,6 @@ impl FMP4Mux {
                 gst::ClockTime::ZERO
             });
 
-        if stream
-            .earliest_pts
-            .is_none_or(|earliest_pts| earliest_pts > pts)
-        {
-            stream.end_pts = Some(pts);
-        }
-
-        if stream.end_pts.opt_lt(end_pts).unwrap_or(true) {
-            stream.end_pts = Some(end_pts);
-        }
-
         let (dts, end_dts) = if !stream.delta_frames.requires_dts() {
             (None, None)
         } else {
@@ -928,6 +1003,17 @@ impl FMP4Mux {
 
         let buffer = stream.pre_queue.pop_front().unwrap();
 
+        if stream
+            .earliest_pts
+            .is_none_or(|earliest_pts| earliest_pts > buffer.pts)
+        {
+            stream.earliest_pts = Some(buffer.pts);
+        }
+
+        if stream.end_pts.opt_lt(buffer.end_pts).unwrap_or(true) {
+            stream.end_pts = Some(buffer.end_pts);
+        }
+
         if let Err(err) = self.add_elst_info(&buffer, stream) {
             gst::error!(CAT, "Failed to add elst info: {err:#}");
         }
@@ -983,7 +1069,10 @@ impl FMP4Mux {
             | "audio/x-ac3" | "audio/x-eac3" | "audio/x-adpcm" => {
                 ["channels", "rate", "layout", "bitrate", "codec_data"].as_slice()
             }
-            "application/x-onvif-metadata" => [].as_slice(),
+            "application/x-onvif-metadata"
+            | "application/x-subtitle-vtt-fragmented"
+            | "application/ttml+xml" => [].as_slice(),
+            "closedcaption/x-cea-608" | "closedcaption/x-cea-708" => ["format"].as_slice(),
             _ => unreachable!(),
         };
 
@@ -2395,6 +2484,151 @@ impl FMP4Mux {
         Ok(gops)
     }
 
+    /// Converts the buffers of a timed text stream into samples.
+    ///
+    /// Time that is not covered by any cue, including gap buffers, is filled with empty samples
+    /// as required by ISO/IEC 14496-30.
+    fn create_text_samples(
+        &self,
+        stream: &Stream,
+        gop_buffers: Vec<GopBuffer>,
+        end_pts: gst::ClockTime,
+    ) -> Result<Vec<GopBuffer>, gst::FlowError> {
+        let create_sample = |buffer: &gst::BufferRef, data: Vec<u8>| {
+            let mut sample = gst::Buffer::from_mut_slice(data);
+            let _ = buffer.copy_into(
+                sample.get_mut().unwrap(),
+                gst::BufferCopyFlags::TIMESTAMPS | gst::BufferCopyFlags::META,
+                ..,
+            );
+            sample
+        };
+
+        let mut samples = Vec::with_capacity(gop_buffers.len());
+        let mut gop_buffers = gop_buffers.into_iter().peekable();
+        while let Some(buffer) = gop_buffers.next() {
+            let next_pts = gop_buffers.peek().map_or(end_pts, |buffer| buffer.pts);
+
+            let sample = if buffer.buffer.flags().contains(gst::BufferFlags::GAP)
+                && buffer.buffer.size() == 0
+            {
+                None
+            } else {
+                boxes::create_text_sample(&stream.caps, &buffer.buffer).map_err(|err| {
+                    gst::error!(
+                        CAT,
+                        obj = stream.sinkpad,
+                        "Failed to create text sample: {err}"
+                    );
+                    gst::FlowError::Error
+                })?
+            };
+
+            let Some(sample) = sample else {
+                // Buffers without cues only fill the time until the next buffer
+                if next_pts <= buffer.pts {
+                    gst::trace!(
+                        CAT,
+                        obj = stream.sinkpad,
+                        "Dropping empty text buffer {buffer:?}"
+                    );
+                    continue;
+                }
+
+                let sample = boxes::create_empty_text_sample(&stream.caps);
+                samples.push(GopBuffer {
+                    buffer: create_sample(&buffer.buffer, sample),
+                    ..buffer
+                });
+                continue;
+            };
+
+            let cue_end_pts = buffer
+                .buffer
+                .duration()
+                .map(|duration| (buffer.pts + duration, buffer.pts_position + duration))
+                .filter(|(cue_end_pts, _)| *cue_end_pts < next_pts);
+
+            samples.push(GopBuffer {
+                buffer: create_sample(&buffer.buffer, sample),
+                ..buffer
+            });
+
+            // Fill the time after the cue until the next buffer with an empty sample
+            if let Some((pts, pts_position)) = cue_end_pts {
+                gst::trace!(
+                    CAT,
+                    obj = stream.sinkpad,
+                    "Filling {} after cue with an empty sample",
+                    next_pts - pts,
+                );
+
+                let mut buffer =
+                    gst::Buffer::from_mut_slice(boxes::create_empty_text_sample(&stream.caps));
+                {
+                    let buffer = buffer.get_mut().unwrap();
+                    buffer.set_pts(pts_position);
+                    buffer.set_duration(next_pts - pts);
+                }
+
+                samples.push(GopBuffer {
+                    buffer,
+                    pts,
+                    pts_position,
+                    dts: None,
+                    split_now: Vec::new(),
+                });
+            }
+        }
+
+        Ok(samples)
+    }
+
+    /// Converts the caption buffers of a stream into `c608` / `c708` samples.
+    ///
+    /// Gap buffers are kept as-is and handled like for any other stream.
+    fn create_caption_samples(
+        &self,
+        stream: &Stream,
+        gop_buffers: Vec<GopBuffer>,
+    ) -> Result<Vec<GopBuffer>, gst::FlowError> {
+        gop_buffers
+            .into_iter()
+            .map(|buffer| {
+                if buffer.buffer.flags().contains(gst::BufferFlags::GAP)
+                    && buffer.buffer.flags().contains(gst::BufferFlags::DROPPABLE)
+                    && buffer.buffer.size() == 0
+                {
+                    return Ok(buffer);
+                }
+
+                let sample =
+                    boxes::create_caption_sample(&stream.caps, &buffer.buffer).map_err(|err| {
+                        gst::error!(
+                            CAT,
+                            obj = stream.sinkpad,
+                            "Failed to create caption sample: {err}"
+                        );
+                        gst::FlowError::Error
+                    })?;
+
+                let mut sample = gst::Buffer::from_mut_slice(sample);
+                let _ = buffer.buffer.copy_into(
+                    sample.get_mut().unwrap(),
+                    gst::BufferCopyFlags::FLAGS
+                        | gst::BufferCopyFlags::TIMESTAMPS
+                        | gst::BufferCopyFlags::META,
+                    ..,
+                );
+
+                Ok(GopBuffer {
+                    buffer: sample,
+                    ..buffer
+                })
+            })
+            .collect()
+    }
+
     /// Flatten all GOPs, remove any gaps and calculate durations.
     #[allow(clippy::type_complexity)]
     fn flatten_gops(
@@ -2430,6 +2664,14 @@ impl FMP4Mux {
         let mut gop_buffers = Vec::with_capacity(gops.iter().map(|g| g.buffers.len()).sum());
         gop_buffers.extend(gops.into_iter().flat_map(|gop| gop.buffers.into_iter()));
 
+        let gop_buffers = if stream.is_text() {
+            self.create_text_samples(stream, gop_buffers, end_pts)?
+        } else if stream.is_caption() {
+            self.create_caption_samples(stream, gop_buffers)?
+        } else {
+            gop_buffers
+        };
+
         // Then calculate durations for all of the buffers and get rid of any GAP buffers in
         // the process.
         // Also calculate the earliest PTS / start DTS here, which needs to consider GAP
@@ -2786,6 +3028,7 @@ impl FMP4Mux {
                         start_ntp_time: None,
                         delta_frames: stream.delta_frames,
                         trak_timescale,
+                        encryption: None,
                     },
                     VecDeque::new(),
                 ));
@@ -2839,6 +3082,7 @@ impl FMP4Mux {
                             start_ntp_time: None,
                             delta_frames: stream.delta_frames,
                             trak_timescale,
+                            encryption: None,
                         },
                         VecDeque::new(),
                     ));
@@ -2887,6 +3131,7 @@ impl FMP4Mux {
                     start_ntp_time,
                     delta_frames: stream.delta_frames,
                     trak_timescale,
+                    encryption: None,
                 },
                 buffers,
             ));
@@ -3040,6 +3285,53 @@ impl FMP4Mux {
         upstream_events.push((stream.sinkpad.clone(), fku));
     }
 
+    /// Encrypt the buffers of all encrypted streams in place and collect the sample auxiliary
+    /// information for the fragment header.
+    fn encrypt_buffers(
+        &self,
+        state: &mut State,
+        buffers: &mut [Buffer],
+        streams: &mut [super::FragmentHeaderStream],
+    ) -> Result<(), gst::FlowError> {
+        for (idx, stream) in state.streams.iter_mut().enumerate() {
+            if let Some(ref encryptor) = stream.encryptor {
+                streams[idx].encryption = Some(super::cenc::FragmentEncryption {
+                    use_subsamples: encryptor.use_subsamples(),
+                    samples: Vec::new(),
+                });
+            }
+        }
+
+        for buffer in buffers {
+            let Some(ref mut encryptor) = state.streams[buffer.idx].encryptor else {
+                continue;
+            };
+
+            let mut map = buffer.buffer.make_mut().map_writable().map_err(|_| {
+                gst::error!(CAT, imp = self, "Failed to map buffer writable");
+                gst::FlowError::Error
+            })?;
+
+            let sample = encryptor.encrypt(&mut map).map_err(|err| {
+                gst::element_imp_error!(
+                    self,
+                    gst::StreamError::Failed,
+                    ["Failed to encrypt sample: {err}"]
+                );
+                gst::FlowError::Error
+            })?;
+
+            streams[buffer.idx]
+                .encryption
+                .as_mut()
+                .unwrap()
+                .samples
+                .push(sample);
+        }
+
+        Ok(())
+    }
+
     /// Fills upstream events as needed and returns the caps the first time draining can happen.
     ///
     /// If it returns `(_, None)` then there's currently nothing to drain anymore.
@@ -3092,7 +3384,7 @@ impl FMP4Mux {
         // Create header now if it was not created before and return the caps
         let mut caps = None;
         if state.stream_header.is_none() {
-            let (_, new_caps) = self.update_header(state, settings, false)?.unwrap();
+            let (_, new_caps) = self.update_header(state, settings, false, false)?.unwrap();
             caps = Some(new_caps);
         }
 
@@ -3100,6 +3392,8 @@ impl FMP4Mux {
         let (mut interleaved_buffers, mut streams) =
             self.interleave_buffers(settings, drained_streams)?;
 
+        self.encrypt_buffers(state, &mut interleaved_buffers, &mut streams)?;
+
         // Offset stream start time to start at 0 in ONVIF mode, or if 'offset-to-zero' is enabled,
         // instead of using the UTC time verbatim. This would be used for the tfdt box later.
         if self.obj().class().as_ref().variant == super::Variant::ONVIF || settings.offset_to_zero {
@@ -3162,6 +3456,19 @@ impl FMP4Mux {
         let mut fmp4_header = None;
         if !state.sent_headers {
             let mut buffer = state.stream_header.as_ref().unwrap().copy();
+            state.sent_header_size = buffer.size() as u64;
+
+            // In DASH on-demand mode reserve space after the header for the final header and the
+            // sidx box, which are written once all fragments are known.
+            if settings.on_demand {
+                state.sidx_reserve_size = self.sidx_reserve_size(state, settings);
+                let free = boxes::create_free(state.sidx_reserve_size).map_err(|err| {
+                    gst::error!(CAT, imp = self, "Failed to create free box: {}", err);
+                    gst::FlowError::Error
+                })?;
+                buffer.append(free);
+            }
+
             {
                 let buffer = buffer.get_mut().unwrap();
 
@@ -3180,8 +3487,6 @@ impl FMP4Mux {
             state.sent_headers = true;
         }
 
-        // TODO: Write sidx boxes before moof and rewrite once offsets are known
-
         let sequence_number = state.sequence_number;
         // If this is the last chunk of a fragment then increment the sequence number for the
         // start of the next fragment.
@@ -3196,6 +3501,7 @@ impl FMP4Mux {
                 streams: streams.as_slice(),
                 buffers: interleaved_buffers.as_slice(),
                 last_fragment: at_eos,
+                on_demand: settings.on_demand,
             })
             .map_err(|err| {
                 gst::error!(
@@ -3234,6 +3540,69 @@ impl FMP4Mux {
             + fmp4_header.as_ref().map(|h| h.size()).unwrap_or(0) as u64
             + moof_offset;
 
+        // Measure the bitrates of all streams for the final header.
+        for (idx, stream) in state.streams.iter_mut().enumerate() {
+            let mut bytes = 0;
+            for buffer in interleaved_buffers
+                .iter()
+                .filter(|buffer| buffer.idx == idx)
+            {
+                let size = buffer.buffer.size();
+                bytes += size as u64;
+                stream.max_sample_size = cmp::max(stream.max_sample_size, size as u32);
+            }
+            stream.drained_bytes += bytes;
+
+            let (window_start, window_bytes) =
+                stream.bitrate_window.unwrap_or((min_earliest_pts, 0));
+            let window_bytes = window_bytes + bytes;
+            let window_duration = chunk_end_pts.saturating_sub(window_start);
+            if window_duration >= gst::ClockTime::SECOND {
+                let bitrate = (window_bytes * 8)
+                    .mul_div_floor(
+                        gst::ClockTime::SECOND.nseconds(),
+                        window_duration.nseconds(),
+                    )
+                    .and_then(|bitrate| u32::try_from(bitrate).ok())
+                    .unwrap_or(u32::MAX);
+                stream.measured_max_bitrate =
+                    Some(cmp::max(stream.measured_max_bitrate.unwrap_or(0), bitrate));
+                stream.bitrate_window = Some((chunk_end_pts, 0));
+            } else {
+                stream.bitrate_window = Some((window_start, window_bytes));
+            }
+        }
+
+        // Remember the subsegments for the sidx box in DASH on-demand mode. Chunks are part of
+        // the subsegment of their fragment.
+        if settings.on_demand {
+            if let Some(start_time) = streams.first().and_then(|stream| stream.start_time) {
+                let size = fmp4_fragment_header.size() as u64
+                    + interleaved_buffers
+                        .iter()
+                        .map(|buffer| buffer.buffer.size() as u64)
+                        .sum::<u64>();
+                let end_time = start_time + chunk_end_pts.saturating_sub(min_earliest_pts);
+
+                match state.sidx_references.last_mut() {
+                    Some(reference) if !fragment_start => {
+                        reference.end_time = end_time;
+                        reference.size += size;
+                    }
+                    _ => {
+                        state.sidx_references.push(super::SidxReference {
+                            start_time,
+                            end_time,
+                            size,
+                            starts_with_sap: interleaved_buffers.first().is_some_and(|b| {
+                                !b.buffer.flags().contains(gst::BufferFlags::DELTA_UNIT)
+                            }),
+                        });
+                    }
+                }
+            }
+        }
+
         let buffers_len = interleaved_buffers.len();
         for (idx, buffer) in interleaved_buffers.iter_mut().enumerate() {
             // Fix up buffer flags, all other buffers are DELTA_UNIT
@@ -3304,9 +3673,6 @@ impl FMP4Mux {
         // Reset timeout delay now that we've output an actual fragment or chunk
         state.timeout_delay = gst::ClockTime::ZERO;
 
-        // TODO: Write edit list at EOS
-        // TODO: Rewrite bitrates at EOS
-
         Ok((caps, Some(buffer_list)))
     }
 
@@ -3404,7 +3770,7 @@ impl FMP4Mux {
     }
 
     /// Create all streams.
-    fn create_streams(&self, state: &mut State) -> Result<(), gst::FlowError> {
+    fn create_streams(&self, state: &mut State, settings: &Settings) -> Result<(), gst::FlowError> {
         for pad in self
             .obj()
             .sink_pads()
@@ -3614,6 +3980,8 @@ impl FMP4Mux {
                 "audio/x-alaw" | "audio/x-mulaw" => (),
                 "audio/x-adpcm" => (),
                 "application/x-onvif-metadata" => (),
+                "application/x-subtitle-vtt-fragmented" | "application/ttml+xml" => (),
+                "closedcaption/x-cea-608" | "closedcaption/x-cea-708" => (),
                 _ => unreachable!(),
             }
 
@@ -3641,7 +4009,12 @@ impl FMP4Mux {
                 stream_orientation,
                 avg_bitrate,
                 max_bitrate,
+                drained_bytes: 0,
+                max_sample_size: 0,
+                bitrate_window: None,
+                measured_max_bitrate: None,
                 elst_infos: Vec::new(),
+                encryptor: None,
                 pending_split_now: Vec::new(),
             });
         }
@@ -3651,7 +4024,8 @@ impl FMP4Mux {
             return Err(gst::FlowError::Error);
         }
 
-        // Sort video streams first and then audio streams and then metadata streams, and each group by pad name.
+        // Sort video streams first and then audio streams, then metadata streams and then text
+        // and caption streams, and each group by pad name.
         state.streams.sort_by(|a, b| {
             let order_of_caps = |caps: &gst::CapsRef| {
                 let s = caps.structure(0).unwrap();
@@ -3662,6 +4036,13 @@ impl FMP4Mux {
                     1
                 } else if s.name().starts_with("application/x-onvif-metadata") {
                     2
+                } else if s
+                    .name()
+                    .starts_with("application/x-subtitle-vtt-fragmented")
+                    || s.name().starts_with("application/ttml+xml")
+                    || s.name().starts_with("closedcaption/")
+                {
+                    3
                 } else {
                     unimplemented!();
                 }
@@ -3677,15 +4058,78 @@ impl FMP4Mux {
             st_a.cmp(&st_b)
         });
 
+        let encryption_config = super::cenc::Config::from_hex(
+            settings.encryption_scheme,
+            settings.key_id.as_deref(),
+            settings.key.as_deref(),
+            settings.iv.as_deref(),
+        )
+        .map_err(|err| {
+            gst::element_imp_error!(
+                self,
+                gst::LibraryError::Settings,
+                ["Invalid encryption settings: {err}"]
+            );
+            gst::FlowError::Error
+        })?;
+
+        if let Some(ref encryption_config) = encryption_config {
+            for pssh in settings.pssh_boxes.iter() {
+                let pssh = pssh.get::<gst::Buffer>().unwrap();
+                let map = pssh.map_readable().unwrap();
+                if map.len() < 8
+                    || &map[4..8] != b"pssh"
+                    || u32::from_be_bytes(map[0..4].try_into().unwrap()) as usize != map.len()
+                {
+                    gst::element_imp_error!(
+                        self,
+                        gst::LibraryError::Settings,
+                        ["Invalid pssh box configured"]
+                    );
+                    return Err(gst::FlowError::Error);
+                }
+            }
+
+            for (idx, stream) in state.streams.iter_mut().enumerate() {
+                stream.encryptor =
+                    super::cenc::Encryptor::new(encryption_config, idx, &stream.caps).map_err(
+                        |err| {
+                            gst::element_imp_error!(
+                                self,
+                                gst::LibraryError::Settings,
+                                ["Failed to configure encryption: {err}"]
+                            );
+                            gst::FlowError::Error
+                        },
+                    )?;
+
+                if stream.encryptor.is_none() {
+                    let s = stream.caps.structure(0).unwrap();
+                    if s.name().starts_with("video/") || s.name().starts_with("image/") {
+                        gst::warning!(
+                            CAT,
+                            obj = stream.sinkpad,
+                            "Encryption of {} is not supported, leaving stream unencrypted",
+                            s.name(),
+                        );
+                    }
+                }
+            }
+        }
+
         Ok(())
     }
 
     /// Generate an updated header at the end and the corresponding caps with the new streamheader.
+    ///
+    /// If `use_measurements` is set then the bitrates measured from the drained samples are used
+    /// instead of the ones from the tags.
     fn update_header(
         &self,
         state: &mut State,
         settings: &Settings,
         at_eos: bool,
+        use_measurements: bool,
     ) -> Result<Option<(gst::BufferList, gst::Caps)>, gst::FlowError> {
         let aggregator = self.obj();
         let class = aggregator.class();
@@ -3694,6 +4138,7 @@ impl FMP4Mux {
         if [super::HeaderUpdateMode::None, super::HeaderUpdateMode::Caps]
             .contains(&settings.header_update_mode)
             && at_eos
+            && !settings.on_demand
         {
             return Ok(None);
         }
@@ -3701,11 +4146,12 @@ impl FMP4Mux {
         assert!(!at_eos || state.streams.iter().all(|s| s.queued_gops.is_empty()));
 
         let duration = if at_eos
-            && [
-                super::HeaderUpdateMode::Update,
-                super::HeaderUpdateMode::Rewrite,
-            ]
-            .contains(&settings.header_update_mode)
+            && (settings.on_demand
+                || [
+                    super::HeaderUpdateMode::Update,
+                    super::HeaderUpdateMode::Rewrite,
+                ]
+                .contains(&settings.header_update_mode))
         {
             state
                 .end_pts
@@ -3716,11 +4162,47 @@ impl FMP4Mux {
             None
         };
 
+        // Edit list media times are in the same timeline as the tfdt, see `drain_one_chunk()`.
+        let shift_media_time = |start: gst::Signed<gst::ClockTime>| {
+            let start = if variant == super::Variant::ONVIF || settings.offset_to_zero {
+                start.checked_sub_unsigned(state.earliest_pts.unwrap_or(gst::ClockTime::ZERO))?
+            } else {
+                start
+            };
+
+            let decode_time_offset =
+                gst::ClockTime::from_nseconds(settings.decode_time_offset.unsigned_abs());
+            if settings.decode_time_offset >= 0 {
+                start.checked_add_unsigned(decode_time_offset)
+            } else {
+                start.checked_sub_unsigned(decode_time_offset)
+            }
+        };
+
         let streams = state
             .streams
             .iter()
             .map(|s| {
                 let trak_timescale = { s.sinkpad.imp().settings.lock().unwrap().trak_timescale };
+                let (avg_bitrate, max_bitrate, buffer_size_db) = match s.measured_bitrates() {
+                    Some((avg_bitrate, max_bitrate)) if use_measurements => (
+                        Some(avg_bitrate),
+                        Some(max_bitrate),
+                        Some(s.max_sample_size),
+                    ),
+                    _ => (s.avg_bitrate, s.max_bitrate, None),
+                };
+                let mut elst_infos = s.get_elst_infos().unwrap_or_else(|e| {
+                    gst::error!(CAT, "Could not prepare edit lists: {e:?}");
+
+                    Vec::new()
+                });
+                for elst_info in &mut elst_infos {
+                    if let Some(start) = elst_info.start {
+                        elst_info.start = Some(shift_media_time(start).unwrap_or(start));
+                    }
+                }
+
                 super::HeaderStream {
                     trak_timescale,
                     delta_frames: s.delta_frames,
@@ -3729,13 +4211,14 @@ impl FMP4Mux {
                     codec_specific_boxes: s.codec_specific_boxes.clone(),
                     language_code: s.language_code,
                     orientation: s.orientation(),
-                    max_bitrate: s.max_bitrate,
-                    avg_bitrate: s.avg_bitrate,
-                    elst_infos: s.get_elst_infos().unwrap_or_else(|e| {
-                        gst::error!(CAT, "Could not prepare edit lists: {e:?}");
-
-                        Vec::new()
-                    }),
+                    max_bitrate,
+                    avg_bitrate,
+                    buffer_size_db,
+                    elst_infos,
+                    encryption: s
+                        .encryptor
+                        .as_ref()
+                        .map(|encryptor| encryptor.track_encryption().clone()),
                 }
             })
             .collect::<Vec<_>>();
@@ -3754,6 +4237,13 @@ impl FMP4Mux {
             write_mehd: settings.write_mehd,
             duration: if at_eos { duration } else { None },
             write_edts,
+            on_demand: settings.on_demand,
+            pssh_boxes: settings
+                .pssh_boxes
+                .iter()
+                .map(|pssh| pssh.get::<gst::Buffer>().unwrap())
+                .map(|pssh| pssh.map_readable().unwrap().to_vec())
+                .collect(),
             start_utc_time: if variant == super::Variant::ONVIF {
                 state
                     .earliest_pts
@@ -3802,24 +4292,85 @@ impl FMP4Mux {
         // Do remaining EOS handling after the end of the stream was pushed.
         gst::debug!(CAT, imp = self, "Doing EOS handling");
 
+        if settings.on_demand {
+            self.finish_on_demand(settings);
+
+            // Need to output new headers if started again after EOS
+            self.state.lock().unwrap().sent_headers = false;
+            return;
+        }
+
         if settings.header_update_mode == super::HeaderUpdateMode::None {
             // Need to output new headers if started again after EOS
             self.state.lock().unwrap().sent_headers = false;
             return;
         }
 
-        let updated_header = self.update_header(&mut self.state.lock().unwrap(), settings, true);
+        let rewrite = settings.header_update_mode == super::HeaderUpdateMode::Rewrite && {
+            let mut q = gst::query::Seeking::new(gst::Format::Bytes);
+            self.obj().src_pad().peer_query(&mut q) && q.result().0
+        };
+
+        let updated_header = {
+            let mut state = self.state.lock().unwrap();
+            if rewrite {
+                let sent_header_size = state.sent_header_size;
+                let updated_header = self.update_header(&mut state, settings, true, true);
+
+                // When rewriting, the final header has to fit exactly into the space of the
+                // header that was sent at the beginning. If the measured bitrates don't fit then
+                // fall back to the bitrates from the tags.
+                let fits = match updated_header {
+                    Ok(Some((ref buffer_list, _))) => {
+                        let size = buffer_list.calculate_size() as u64;
+                        size == sent_header_size || size + 8 <= sent_header_size
+                    }
+                    _ => true,
+                };
+
+                if !fits {
+                    gst::debug!(
+                        CAT,
+                        imp = self,
+                        "Final header with measured bitrates doesn't fit, using tags",
+                    );
+                    self.update_header(&mut state, settings, true, false)
+                } else {
+                    updated_header
+                }
+            } else {
+                // The measured bitrates only replace the ones of the initial header, an updated
+                // header after the media keeps the bitrates from the tags
+                self.update_header(&mut state, settings, true, false)
+            }
+        };
         match updated_header {
-            Ok(Some((buffer_list, caps))) => {
+            Ok(Some((mut buffer_list, caps))) => {
                 match settings.header_update_mode {
                     super::HeaderUpdateMode::None | super::HeaderUpdateMode::Caps => unreachable!(),
                     super::HeaderUpdateMode::Rewrite => {
-                        let mut q = gst::query::Seeking::new(gst::Format::Bytes);
-                        if self.obj().src_pad().peer_query(&mut q) && q.result().0 {
+                        if rewrite {
                             let aggregator = self.obj();
 
                             aggregator.set_src_caps(&caps);
 
+                            // Fill the remaining space of the initial header
+                            let size = buffer_list.calculate_size() as u64;
+                            let sent_header_size = self.state.lock().unwrap().sent_header_size;
+                            if size < sent_header_size {
+                                match boxes::create_free(sent_header_size - size) {
+                                    Ok(free) => buffer_list.get_mut().unwrap().add(free),
+                                    Err(err) => {
+                                        gst::error!(
+                                            CAT,
+                                            imp = self,
+                                            "Failed to create free box: {}",
+                                            err
+                                        );
+                                    }
+                                }
+                            }
+
                             // Seek to the beginning with a default bytes segment
                             aggregator.update_segment(
                                 &gst::FormattedSegment::<gst::format::Bytes>::new(),
@@ -3870,6 +4421,146 @@ impl FMP4Mux {
         // Need to output new headers if started again after EOS
         self.state.lock().unwrap().sent_headers = false;
     }
+
+    /// Size of the space to reserve after the header in DASH on-demand mode for the final header
+    /// and the `sidx` box.
+    fn sidx_reserve_size(&self, state: &State, settings: &Settings) -> u64 {
+        let duration = state.streams[0]
+            .sinkpad
+            .peer_query_duration::<gst::ClockTime>();
+
+        let num_entries = match duration {
+            Some(duration) if !settings.fragment_duration.is_zero() => {
+                // Leave room for fragments that are shorter than the fragment duration, e.g.
+                // because of caps changes or split-now events.
+                2 * duration
+                    .nseconds()
+                    .div_ceil(settings.fragment_duration.nseconds())
+                    + 16
+            }
+            _ => DEFAULT_SIDX_RESERVE_ENTRIES,
+        };
+        let num_entries = cmp::min(num_entries, u16::MAX as u64);
+
+        gst::debug!(
+            CAT,
+            imp = self,
+            "Reserving space for {} sidx entries with duration {}",
+            num_entries,
+            duration.display(),
+        );
+
+        // Version 1 sidx box with 12 bytes per entry
+        HEADER_RESERVE_SLACK + 40 + 12 * num_entries
+    }
+
+    /// Rewrite the header with the final header and a `sidx` box indexing all fragments in DASH
+    /// on-demand mode.
+    fn finish_on_demand(&self, settings: &Settings) {
+        let mut q = gst::query::Seeking::new(gst::Format::Bytes);
+        if !self.obj().src_pad().peer_query(&mut q) || !q.result().0 {
+            gst::error!(
+                CAT,
+                imp = self,
+                "Can't write sidx box because downstream is not seekable"
+            );
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.sidx_references.is_empty() {
+            gst::debug!(CAT, imp = self, "No fragments written");
+            return;
+        }
+
+        let (mut buffer_list, caps) = match self.update_header(&mut state, settings, true, true) {
+            Ok(Some(res)) => res,
+            Ok(None) => unreachable!(),
+            Err(err) => {
+                gst::error!(
+                    CAT,
+                    imp = self,
+                    "Failed to generate updated header: {:?}",
+                    err
+                );
+                return;
+            }
+        };
+
+        // The final header, sidx box and a free box for the remaining space have to fit exactly
+        // into the initial header and the space reserved after it.
+        let available = state.sent_header_size + state.sidx_reserve_size;
+        let fits = |size: u64| size == available || size + 8 <= available;
+        let header_size = buffer_list.calculate_size() as u64;
+
+        let timescale = state.streams[0].timescale();
+        let sidx = match boxes::create_sidx(timescale, 0, &state.sidx_references) {
+            Ok(sidx) if fits(header_size + sidx.size() as u64) => {
+                // The free box between the sidx box and the first fragment is skipped via the
+                // first offset.
+                let first_offset = available - header_size - sidx.size() as u64;
+                boxes::create_sidx(timescale, first_offset, &state.sidx_references).ok()
+            }
+            Ok(_) => {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Not enough space reserved for sidx box with {} entries",
+                    state.sidx_references.len(),
+                );
+                None
+            }
+            Err(err) => {
+                gst::error!(CAT, imp = self, "Failed to create sidx box: {}", err);
+                None
+            }
+        };
+
+        if sidx.is_none() && !fits(header_size) {
+            gst::error!(
+                CAT,
+                imp = self,
+                "Not enough space reserved for final header"
+            );
+            return;
+        }
+
+        {
+            let buffer_list = buffer_list.get_mut().unwrap();
+            let mut size = header_size;
+            if let Some(sidx) = sidx {
+                size += sidx.size() as u64;
+                buffer_list.add(sidx);
+            }
+
+            if size < available {
+                match boxes::create_free(available - size) {
+                    Ok(free) => buffer_list.add(free),
+                    Err(err) => {
+                        gst::error!(CAT, imp = self, "Failed to create free box: {}", err);
+                        return;
+                    }
+                }
+            }
+        }
+        drop(state);
+
+        let aggregator = self.obj();
+
+        aggregator.set_src_caps(&caps);
+
+        // Seek to the beginning with a default bytes segment
+        aggregator.update_segment(&gst::FormattedSegment::<gst::format::Bytes>::new());
+
+        if let Err(err) = aggregator.finish_buffer_list(buffer_list) {
+            gst::error!(
+                CAT,
+                imp = self,
+                "Failed pushing final header and sidx buffers downstream: {:?}",
+                err,
+            );
+        }
+    }
 }
 
 #[glib::object_subclass]
@@ -4088,6 +4779,82 @@ impl ObjectImpl for FMP4Mux {
                     .default_value(DEFAULT_START_FRAGMENT_SEQUENCE_NUMBER)
                     .mutable_ready()
                     .build(),
+               /**
+                 * GstFMP4Mux:encryption-scheme:
+                 *
+                 * Common Encryption scheme to encrypt the samples with.
+                 *
+                 * H.264, H.265 and AV1 video is encrypted with subsample encryption, leaving the
+                 * NAL unit and OBU headers in the clear. Audio samples are encrypted as a whole.
+                 * Other streams are not encrypted.
+                 *
+                 * The `key-id` and `key` properties must be set if encryption is enabled.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecEnum::builder_with_default("encryption-scheme", DEFAULT_ENCRYPTION_SCHEME)
+                    .nick("Encryption Scheme")
+                    .blurb("Common Encryption scheme to use")
+                    .mutable_ready()
+                    .build(),
+               /**
+                 * GstFMP4Mux:key-id:
+                 *
+                 * 16 byte key ID as hex string.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecString::builder("key-id")
+                    .nick("Key ID")
+                    .blurb("16 byte key ID as hex string")
+                    .mutable_ready()
+                    .build(),
+               /**
+                 * GstFMP4Mux:key:
+                 *
+                 * 16 byte AES-128 key as hex string.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecString::builder("key")
+                    .nick("Key")
+                    .blurb("16 byte AES-128 key as hex string")
+                    .mutable_ready()
+                    .build(),
+               /**
+                 * GstFMP4Mux:iv:
+                 *
+                 * Initial IV as hex string. For `cenc` this can be 8 or 16 bytes and is
+                 * incremented for every sample, for `cbcs` this is the 16 byte constant IV.
+                 *
+                 * If not set, a random IV is used.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecString::builder("iv")
+                    .nick("IV")
+                    .blurb("Initial IV as hex string (default: random)")
+                    .mutable_ready()
+                    .build(),
+               /**
+                 * GstFMP4Mux:pssh-boxes:
+                 *
+                 * Complete `pssh` boxes of DRM systems to include in the header in addition to
+                 * the one for the W3C Common PSSH box format.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                gst::ParamSpecArray::builder("pssh-boxes")
+                    .nick("PSSH Boxes")
+                    .blurb("Additional pssh boxes to include in the header")
+                    .element_spec(
+                        &glib::ParamSpecBoxed::builder::<gst::Buffer>("pssh-box")
+                            .nick("PSSH Box")
+                            .blurb("Complete pssh box")
+                            .build(),
+                    )
+                    .mutable_ready()
+                    .build(),
             ]
         });
 
@@ -4178,6 +4945,26 @@ impl ObjectImpl for FMP4Mux {
                 settings.start_fragment_sequence_number =
                     value.get().expect("type checked upstream");
             }
+            "encryption-scheme" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.encryption_scheme = value.get().expect("type checked upstream");
+            }
+            "key-id" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.key_id = value.get().expect("type checked upstream");
+            }
+            "key" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.key = value.get().expect("type checked upstream");
+            }
+            "iv" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.iv = value.get().expect("type checked upstream");
+            }
+            "pssh-boxes" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.pssh_boxes = value.get::<gst::Array>().expect("type checked upstream");
+            }
             _ => unimplemented!(),
         }
     }
@@ -4243,6 +5030,26 @@ impl ObjectImpl for FMP4Mux {
                 let settings = self.settings.lock().unwrap();
                 settings.start_fragment_sequence_number.to_value()
             }
+            "encryption-scheme" => {
+                let settings = self.settings.lock().unwrap();
+                settings.encryption_scheme.to_value()
+            }
+            "key-id" => {
+                let settings = self.settings.lock().unwrap();
+                settings.key_id.to_value()
+            }
+            "key" => {
+                let settings = self.settings.lock().unwrap();
+                settings.key.to_value()
+            }
+            "iv" => {
+                let settings = self.settings.lock().unwrap();
+                settings.iv.to_value()
+            }
+            "pssh-boxes" => {
+                let settings = self.settings.lock().unwrap();
+                settings.pssh_boxes.to_value()
+            }
             _ => unimplemented!(),
         }
     }
@@ -4649,7 +5456,7 @@ impl AggregatorImpl for FMP4Mux {
 
             // Create streams
             if state.streams.is_empty() {
-                self.create_streams(&mut state)?;
+                self.create_streams(&mut state, &settings)?;
             }
 
             self.calculate_fragment_end_pts(&settings, &mut state);
@@ -4954,6 +5761,14 @@ impl ElementImpl for ISOFMP4Mux {
                         .field("channels", gst::IntRange::<i32>::new(1, u16::MAX as i32))
                         .field("rate", gst::IntRange::<i32>::new(1, i32::MAX))
                         .build(),
+                    gst::Structure::builder("application/x-subtitle-vtt-fragmented").build(),
+                    gst::Structure::builder("application/ttml+xml").build(),
+                    gst::Structure::builder("closedcaption/x-cea-608")
+                        .field("format", gst::List::new(["raw", "s334-1a"]))
+                        .build(),
+                    gst::Structure::builder("closedcaption/x-cea-708")
+                        .field("format", "cdp")
+                        .build(),
                 ]
                 .into_iter()
                 .collect::<gst::Caps>(),
@@ -5061,6 +5876,8 @@ impl ElementImpl for CMAFMux {
                         .field("channels", gst::IntRange::<i32>::new(1, u16::MAX as i32))
                         .field("rate", gst::IntRange::<i32>::new(1, i32::MAX))
                         .build(),
+                    gst::Structure::builder("application/x-subtitle-vtt-fragmented").build(),
+                    gst::Structure::builder("application/ttml+xml").build(),
                 ]
                 .into_iter()
                 .collect::<gst::Caps>(),
@@ -5091,7 +5908,64 @@ impl ObjectSubclass for DASHMP4Mux {
     type ParentType = super::FMP4Mux;
 }
 
-impl ObjectImpl for DASHMP4Mux {}
+impl ObjectImpl for DASHMP4Mux {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                /**
+                 * GstDASHMP4Mux:on-demand:
+                 *
+                 * Write a single file for the DASH on-demand profile.
+                 *
+                 * Space is reserved after the initial header and once all fragments are written,
+                 * the final header with accurate durations, edit lists and bitrates is written
+                 * together with a `sidx` box that indexes all fragments. This requires downstream
+                 * to be seekable, otherwise no `sidx` box is written.
+                 *
+                 * The `header-update-mode` property is ignored in this mode.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecBoolean::builder("on-demand")
+                    .nick("On Demand")
+                    .blurb("Write a single indexed file for the DASH on-demand profile")
+                    .default_value(DEFAULT_ON_DEMAND)
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+
+        &PROPERTIES
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let obj = self.obj();
+        let fmp4mux = obj.upcast_ref::<super::FMP4Mux>().imp();
+
+        match pspec.name() {
+            "on-demand" => {
+                let settings = fmp4mux.settings.lock().unwrap();
+                settings.on_demand.to_value()
+            }
+
+            _ => unimplemented!(),
+        }
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let obj = self.obj();
+        let fmp4mux = obj.upcast_ref::<super::FMP4Mux>().imp();
+
+        match pspec.name() {
+            "on-demand" => {
+                let mut settings = fmp4mux.settings.lock().unwrap();
+                settings.on_demand = value.get().expect("type checked upstream");
+            }
+
+            _ => unimplemented!(),
+        }
+    }
+}
 
 impl GstObjectImpl for DASHMP4Mux {}
 
@@ -5186,6 +6060,8 @@ impl ElementImpl for DASHMP4Mux {
                         .field("channels", gst::IntRange::<i32>::new(1, u16::MAX as i32))
                         .field("rate", gst::IntRange::<i32>::new(1, i32::MAX))
                         .build(),
+                    gst::Structure::builder("application/x-subtitle-vtt-fragmented").build(),
+                    gst::Structure::builder("application/ttml+xml").build(),
                 ]
                 .into_iter()
                 .collect::<gst::Caps>(),
@@ -11,7 +11,9 @@ use gst::glib;
 use gst::prelude::*;
 use gst::subclass::prelude::*;
 
+mod av1;
 mod boxes;
+mod cenc;
 mod imp;
 
 mod obu;
@@ -47,6 +49,7 @@ pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
         FMP4MuxPad::static_type().mark_as_plugin_api(gst::PluginAPIFlags::empty());
         HeaderUpdateMode::static_type().mark_as_plugin_api(gst::PluginAPIFlags::empty());
         WriteEdtsMode::static_type().mark_as_plugin_api(gst::PluginAPIFlags::empty());
+        EncryptionScheme::static_type().mark_as_plugin_api(gst::PluginAPIFlags::empty());
     }
     gst::Element::register(
         Some(plugin),
@@ -193,6 +196,12 @@ pub(crate) struct HeaderConfiguration {
 
     /// Whether to write edts box
     write_edts: bool,
+
+    /// Whether this is for the DASH on-demand profile.
+    on_demand: bool,
+
+    /// Additional complete `pssh` boxes to write if any stream is encrypted.
+    pssh_boxes: Vec<Vec<u8>>,
 }
 
 #[derive(Debug, Clone)]
@@ -223,9 +232,14 @@ pub(crate) struct HeaderStream {
     orientation: &'static TransformMatrix,
     avg_bitrate: Option<u32>,
     max_bitrate: Option<u32>,
+    /// Size of the decoding buffer in bytes for the `btrt` box
+    buffer_size_db: Option<u32>,
 
     /// Edit list clipping information
     elst_infos: Vec<ElstInfo>,
+
+    /// Common Encryption parameters if this stream is encrypted
+    encryption: Option<cenc::TrackEncryption>,
 }
 
 #[derive(Debug)]
@@ -243,6 +257,9 @@ pub(crate) struct FragmentHeaderConfiguration<'a> {
 
     /// If this is for the last fragment.
     last_fragment: bool,
+
+    /// Whether this is for the DASH on-demand profile.
+    on_demand: bool,
 }
 
 #[derive(Debug)]
@@ -267,6 +284,9 @@ pub(crate) struct FragmentHeaderStream {
     ///
     /// Only the first track is ever used.
     start_ntp_time: Option<gst::ClockTime>,
+
+    /// Sample auxiliary information if this stream is encrypted.
+    encryption: Option<cenc::FragmentEncryption>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -332,6 +352,19 @@ pub(crate) struct FragmentOffset {
     offset: u64,
 }
 
+/// Subsegment reference for the `sidx` box.
+#[derive(Debug)]
+pub(crate) struct SidxReference {
+    /// Start time of the subsegment in the media timeline.
+    start_time: gst::ClockTime,
+    /// End time of the subsegment in the media timeline.
+    end_time: gst::ClockTime,
+    /// Size of the subsegment in bytes, from the start of its `styp` to the end of its last `mdat`.
+    size: u64,
+    /// Whether the subsegment starts with a stream access point.
+    starts_with_sap: bool,
+}
+
 /**
  * GstFMP4MuxHeaderUpdateMode:
  *
@@ -379,6 +412,38 @@ pub(crate) enum HeaderUpdateMode {
     Caps,
 }
 
+/**
+ * GstFMP4MuxEncryptionScheme:
+ *
+ * Common Encryption scheme to use for encrypting the samples.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, glib::Enum, Default)]
+#[repr(i32)]
+#[enum_type(name = "GstFMP4MuxEncryptionScheme")]
+pub(crate) enum EncryptionScheme {
+    /**
+     * GstFMP4MuxEncryptionScheme:none:
+     *
+     * Don't encrypt samples.
+     */
+    #[default]
+    None,
+    /**
+     * GstFMP4MuxEncryptionScheme:cenc:
+     *
+     * AES-CTR full sample and subsample encryption with per-sample IVs.
+     */
+    Cenc,
+    /**
+     * GstFMP4MuxEncryptionScheme:cbcs:
+     *
+     * AES-CBC pattern encryption with a constant IV.
+     */
+    Cbcs,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, glib::Enum, Default)]
 #[enum_type(name = "GstFMP4MuxWriteEdtsMode")]
 pub(crate) enum WriteEdtsMode {
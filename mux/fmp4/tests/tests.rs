@@ -4081,3 +4081,830 @@ fn test_multi_stream_late_key_frame_sparse_on_frag_boundary() {
 fn test_multi_stream_late_key_frame_sparse_on_frag_boundary_gap() {
     test_late_key_frame_sparse(2_000, true, true)
 }
+
+#[test]
+fn test_wvtt_gap_filling() {
+    init();
+
+    let mut h = gst_check::Harness::new("cmafmux");
+    h.set_src_caps(gst::Caps::builder("application/x-subtitle-vtt-fragmented").build());
+    h.play();
+
+    let mut push = |data: &str, pts: gst::ClockTime, duration: Option<gst::ClockTime>| {
+        let mut buffer = gst::Buffer::from_slice(data.to_owned());
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(pts);
+            buffer.set_duration(duration);
+        }
+        assert_eq!(h.push(buffer), Ok(gst::FlowSuccess::Ok));
+    };
+
+    push("WEBVTT\n\n", gst::ClockTime::ZERO, None);
+    push(
+        "00:00:00.000 --> 00:00:01.000\nHello\n",
+        gst::ClockTime::ZERO,
+        Some(1.seconds()),
+    );
+    push(
+        "00:00:02.000 --> 00:00:02.500\nWorld\n",
+        2.seconds(),
+        Some(500.mseconds()),
+    );
+    push(
+        "00:00:03.000 --> 00:00:04.500\nGoodbye\n",
+        3.seconds(),
+        Some(1_500.mseconds()),
+    );
+
+    h.push_event(gst::event::Eos::new());
+
+    let header = h.pull().unwrap();
+    assert_eq!(
+        header.flags(),
+        gst::BufferFlags::HEADER | gst::BufferFlags::DISCONT
+    );
+    {
+        let map = header.map_readable().unwrap();
+        assert!(map.windows(4).any(|w| w == b"wvtt"));
+        assert!(map.windows(4).any(|w| w == b"vttC"));
+    }
+    check_fragment_header(&mut h);
+
+    // Each cue is followed by an empty cue that fills the gap until the next cue
+    let expected = [
+        (b"vttc", 0.mseconds(), 1_000.mseconds()),
+        (b"vtte", 1_000.mseconds(), 1_000.mseconds()),
+        (b"vttc", 2_000.mseconds(), 500.mseconds()),
+        (b"vtte", 2_500.mseconds(), 500.mseconds()),
+        (b"vttc", 3_000.mseconds(), 1_500.mseconds()),
+    ];
+    for (fourcc, pts, duration) in expected {
+        let buffer = h.pull().unwrap();
+        assert_eq!(buffer.pts(), Some(pts));
+        assert_eq!(buffer.duration(), Some(duration));
+        let map = buffer.map_readable().unwrap();
+        assert_eq!(&map[4..8], fourcc);
+    }
+
+    let ev = h.pull_event().unwrap();
+    assert_eq!(ev.type_(), gst::EventType::StreamStart);
+    let ev = h.pull_event().unwrap();
+    assert_eq!(ev.type_(), gst::EventType::Caps);
+    let ev = h.pull_event().unwrap();
+    assert_eq!(ev.type_(), gst::EventType::Segment);
+    let ev = h.pull_event().unwrap();
+    assert_eq!(ev.type_(), gst::EventType::Eos);
+}
+
+#[test]
+fn test_c608_samples() {
+    init();
+
+    let mut h = gst_check::Harness::with_padnames("isofmp4mux", Some("sink_0"), Some("src"));
+    h.set_src_caps(
+        gst::Caps::builder("closedcaption/x-cea-608")
+            .field("format", "s334-1a")
+            .field("framerate", gst::Fraction::new(25, 1))
+            .build(),
+    );
+    h.play();
+
+    let output_offset = (60 * 60 * 1000).seconds();
+
+    // One field 1 and one field 2 byte pair per frame, and then a frame with only padding
+    for i in 0..3u64 {
+        let data = if i < 2 {
+            vec![0x80, 0x94, 0x20, 0x00, 0x15, 0x2c]
+        } else {
+            vec![]
+        };
+        let mut buffer = gst::Buffer::from_mut_slice(data);
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts((i * 40).mseconds());
+            buffer.set_duration(40.mseconds());
+        }
+        assert_eq!(h.push(buffer), Ok(gst::FlowSuccess::Ok));
+    }
+
+    h.push_event(gst::event::Eos::new());
+
+    let header = h.pull().unwrap();
+    {
+        let map = header.map_readable().unwrap();
+        assert!(map.windows(4).any(|w| w == b"c608"));
+        assert!(map.windows(4).any(|w| w == b"clcp"));
+    }
+    check_fragment_header(&mut h);
+
+    for i in 0..3u64 {
+        let buffer = h.pull().unwrap();
+        assert_eq!(buffer.pts(), Some((i * 40).mseconds() + output_offset));
+        assert_eq!(buffer.duration(), Some(40.mseconds()));
+
+        let map = buffer.map_readable().unwrap();
+        if i < 2 {
+            assert_eq!(
+                map.as_slice(),
+                [
+                    0, 0, 0, 10, b'c', b'd', b'a', b't', 0x94, 0x20, 0, 0, 0, 10, b'c', b'd', b't',
+                    b'2', 0x15, 0x2c
+                ]
+            );
+        } else {
+            assert_eq!(
+                map.as_slice(),
+                [0, 0, 0, 10, b'c', b'd', b'a', b't', 0x80, 0x80]
+            );
+        }
+    }
+}
+
+/// Returns fourcc, offset and size of all top-level boxes.
+fn parse_top_level_boxes(data: &[u8]) -> Vec<([u8; 4], usize, usize)> {
+    let mut boxes = Vec::new();
+
+    let mut offset = 0;
+    while offset + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[offset..][..4].try_into().unwrap()) as usize;
+        let fourcc = <[u8; 4]>::try_from(&data[offset + 4..][..4]).unwrap();
+        assert!(size >= 8 && offset + size <= data.len());
+
+        boxes.push((fourcc, offset, size));
+        offset += size;
+    }
+    assert_eq!(offset, data.len());
+
+    boxes
+}
+
+#[test]
+fn test_dash_on_demand() {
+    init();
+
+    let location =
+        std::env::temp_dir().join(format!("fmp4mux-dash-on-demand-{}.mp4", std::process::id()));
+
+    let caps = gst::Caps::builder("video/x-h264")
+        .field("width", 1920i32)
+        .field("height", 1080i32)
+        .field("framerate", gst::Fraction::new(30, 1))
+        .field("stream-format", "avc")
+        .field("alignment", "au")
+        .field("codec_data", gst::Buffer::with_size(1).unwrap())
+        .build();
+
+    let pipeline = gst::Pipeline::new();
+    let appsrc = gst_app::AppSrc::builder()
+        .caps(&caps)
+        .format(gst::Format::Time)
+        .build();
+    let mux = gst::ElementFactory::make("dashmp4mux")
+        .property("on-demand", true)
+        .property("fragment-duration", 1.seconds())
+        .build()
+        .unwrap();
+    let sink = gst::ElementFactory::make("filesink")
+        .property("location", location.to_str().unwrap())
+        .build()
+        .unwrap();
+    pipeline
+        .add_many([appsrc.upcast_ref(), &mux, &sink])
+        .unwrap();
+    gst::Element::link_many([appsrc.upcast_ref(), &mux, &sink]).unwrap();
+
+    // 5s with a keyframe every second
+    for i in 0..150u64 {
+        let mut buffer = gst::Buffer::with_size(if i % 30 == 0 { 5000 } else { 1000 }).unwrap();
+        {
+            let buffer = buffer.get_mut().unwrap();
+            let pts = gst::ClockTime::SECOND.mul_div_floor(i, 30).unwrap();
+            let end_pts = gst::ClockTime::SECOND.mul_div_floor(i + 1, 30).unwrap();
+            buffer.set_pts(pts);
+            buffer.set_dts(pts);
+            buffer.set_duration(end_pts - pts);
+            if i % 30 != 0 {
+                buffer.set_flags(gst::BufferFlags::DELTA_UNIT);
+            }
+        }
+        appsrc.push_buffer(buffer).unwrap();
+    }
+    appsrc.end_of_stream().unwrap();
+
+    to_completion(&pipeline);
+
+    let data = std::fs::read(&location).unwrap();
+    std::fs::remove_file(&location).unwrap();
+
+    let boxes = parse_top_level_boxes(&data);
+    assert_eq!(&boxes[0].0, b"ftyp");
+    assert_eq!(&boxes[1].0, b"moov");
+    assert_eq!(&boxes[2].0, b"sidx");
+    assert_eq!(&boxes[3].0, b"free");
+
+    // Major brand and compatible brands of the on-demand profile
+    let ftyp = &data[boxes[0].1..][..boxes[0].2];
+    assert_eq!(&ftyp[8..12], b"dash");
+    let compatible_brands = ftyp[16..].chunks(4).collect::<Vec<_>>();
+    for brand in [b"dash", b"dsms", b"msix"] {
+        assert!(compatible_brands.contains(&&brand[..]));
+    }
+
+    // Final header contains the measured bitrates
+    let moov = &data[boxes[1].1..][..boxes[1].2];
+    assert!(moov.windows(4).any(|w| w == b"btrt"));
+
+    let fragments = boxes
+        .iter()
+        .filter(|(fourcc, _, _)| fourcc == b"styp")
+        .map(|(_, offset, _)| *offset)
+        .collect::<Vec<_>>();
+    assert_eq!(fragments.len(), 5);
+
+    let sidx = &data[boxes[2].1..][..boxes[2].2];
+    // Version 1
+    assert_eq!(sidx[8], 1);
+    // Reference ID
+    assert_eq!(u32::from_be_bytes(sidx[12..16].try_into().unwrap()), 1);
+    // Timescale
+    assert_eq!(u32::from_be_bytes(sidx[16..20].try_into().unwrap()), 3000);
+    // Earliest presentation time
+    assert_eq!(u64::from_be_bytes(sidx[20..28].try_into().unwrap()), 0);
+    // First offset points at the first fragment
+    let first_offset = u64::from_be_bytes(sidx[28..36].try_into().unwrap()) as usize;
+    assert_eq!(boxes[2].1 + boxes[2].2 + first_offset, fragments[0]);
+    // Reference count
+    assert_eq!(
+        u16::from_be_bytes(sidx[38..40].try_into().unwrap()) as usize,
+        fragments.len()
+    );
+
+    for (idx, reference) in sidx[40..].chunks(12).enumerate() {
+        let size = u32::from_be_bytes(reference[0..4].try_into().unwrap()) as usize;
+        let duration = u32::from_be_bytes(reference[4..8].try_into().unwrap());
+        let sap = u32::from_be_bytes(reference[8..12].try_into().unwrap());
+
+        let end = fragments.get(idx + 1).copied().unwrap_or(data.len());
+        assert_eq!(size, end - fragments[idx]);
+        assert_eq!(duration, 3000);
+        assert_eq!(sap, 0x9000_0000);
+    }
+}
+
+#[test]
+fn test_header_update_without_measured_bitrates() {
+    init();
+
+    let mut h = gst_check::Harness::with_padnames("isofmp4mux", Some("sink_0"), Some("src"));
+    {
+        let mux = h.element().unwrap();
+        mux.set_property("fragment-duration", 1.seconds());
+        mux.set_property_from_str("header-update-mode", "update");
+    }
+
+    let caps = gst::Caps::builder("video/x-h264")
+        .field("width", 1920i32)
+        .field("height", 1080i32)
+        .field("framerate", gst::Fraction::new(30, 1))
+        .field("stream-format", "avc")
+        .field("alignment", "au")
+        .field("codec_data", gst::Buffer::from_slice([1, 2, 3, 4]))
+        .build();
+    h.set_src_caps(caps);
+    h.play();
+
+    // 2s with a keyframe every second
+    for i in 0..60u64 {
+        let mut buffer = gst::Buffer::with_size(1000).unwrap();
+        {
+            let buffer = buffer.get_mut().unwrap();
+            let pts = gst::ClockTime::SECOND.mul_div_floor(i, 30).unwrap();
+            let end_pts = gst::ClockTime::SECOND.mul_div_floor(i + 1, 30).unwrap();
+            buffer.set_pts(pts);
+            buffer.set_dts(pts);
+            buffer.set_duration(end_pts - pts);
+            if i % 30 != 0 {
+                buffer.set_flags(gst::BufferFlags::DELTA_UNIT);
+            }
+        }
+        assert_eq!(h.push(buffer), Ok(gst::FlowSuccess::Ok));
+    }
+    h.push_event(gst::event::Eos::new());
+
+    let is_header =
+        |buffer: &gst::Buffer| buffer.map_readable().unwrap().get(4..8) == Some(b"ftyp".as_slice());
+    let mut headers = Vec::new();
+    while let Some(buffer) = h.pull_until_eos().unwrap() {
+        if is_header(&buffer) {
+            headers.push(buffer);
+        }
+    }
+    // The updated header is pushed after EOS
+    if headers.len() < 2 {
+        headers.push(h.pull().unwrap());
+    }
+    assert_eq!(headers.len(), 2);
+    assert!(is_header(&headers[1]));
+
+    // The updated header after the media only has the bitrates from the tags, i.e. none
+    let data = headers[1].map_readable().unwrap();
+    let boxes = parse_top_level_boxes(&data);
+    assert_eq!(&boxes[0].0, b"ftyp");
+    assert_eq!(&boxes[1].0, b"moov");
+    let moov = &data[boxes[1].1..][..boxes[1].2];
+    assert!(!moov.windows(4).any(|w| w == b"btrt"));
+}
+
+const TEST_KEY_ID: &str = "101112131415161718191a1b1c1d1e1f";
+const TEST_KEY: [u8; 16] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+];
+
+/// Returns the offsets and contents of all child boxes of type `fourcc`.
+fn find_boxes<'a>(data: &'a [u8], fourcc: &[u8; 4]) -> Vec<(usize, &'a [u8])> {
+    parse_top_level_boxes(data)
+        .into_iter()
+        .filter(|(f, _, _)| f == fourcc)
+        .map(|(_, offset, size)| (offset, &data[offset..][..size]))
+        .collect()
+}
+
+/// Decrypts a single sample in place.
+///
+/// `pattern` is the `cbcs` crypt:skip pattern, `None` for `cenc`.
+fn decrypt_sample(
+    pattern: Option<(usize, usize)>,
+    iv: &[u8],
+    subsamples: &[(u16, u32)],
+    data: &mut [u8],
+) {
+    use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+
+    let cipher = aes::Aes128::new(&TEST_KEY.into());
+
+    let ranges = if subsamples.is_empty() {
+        vec![0..data.len()]
+    } else {
+        let mut offset = 0;
+        let ranges = subsamples
+            .iter()
+            .map(|&(clear, protected)| {
+                let start = offset + clear as usize;
+                offset = start + protected as usize;
+                start..offset
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(offset, data.len());
+        ranges
+    };
+
+    match pattern {
+        None => {
+            let mut counter = if iv.len() == 8 {
+                (u64::from_be_bytes(iv.try_into().unwrap()) as u128) << 64
+            } else {
+                u128::from_be_bytes(iv.try_into().unwrap())
+            };
+
+            // The keystream continues over all subsamples
+            let mut keystream = Vec::new();
+            for range in ranges {
+                for byte in &mut data[range] {
+                    if keystream.is_empty() {
+                        let mut block = aes::Block::from(counter.to_be_bytes());
+                        cipher.encrypt_block(&mut block);
+                        keystream = block.to_vec();
+                        keystream.reverse();
+                        counter += 1;
+                    }
+                    *byte ^= keystream.pop().unwrap();
+                }
+            }
+        }
+        Some((crypt, skip)) => {
+            let (crypt, skip) = if crypt == 0 && skip == 0 {
+                (1, 0)
+            } else {
+                (crypt, skip)
+            };
+
+            for range in ranges {
+                let mut chain = <[u8; 16]>::try_from(iv).unwrap();
+                for (idx, block) in data[range].chunks_exact_mut(16).enumerate() {
+                    if idx % (crypt + skip) >= crypt {
+                        continue;
+                    }
+
+                    let ciphertext = <[u8; 16]>::try_from(&*block).unwrap();
+                    let block = aes::Block::from_mut_slice(block);
+                    cipher.decrypt_block(block);
+                    for (b, c) in block.iter_mut().zip(chain.iter()) {
+                        *b ^= c;
+                    }
+                    chain = ciphertext;
+                }
+            }
+        }
+    }
+}
+
+/// Encrypts the samples with the given scheme and caps, then decrypts the output again and
+/// compares it with the original samples.
+///
+/// Returns the subsamples of each sample.
+fn test_encryption(
+    scheme: &str,
+    iv: &str,
+    caps: gst::Caps,
+    samples: &[Vec<u8>],
+) -> Vec<Vec<(u16, u32)>> {
+    let mut h = gst_check::Harness::new("cmafmux");
+    {
+        let mux = h.element().unwrap();
+        mux.set_property("fragment-duration", 5.seconds());
+        mux.set_property_from_str("encryption-scheme", scheme);
+        mux.set_property("key-id", TEST_KEY_ID);
+        mux.set_property("key", hex::encode(TEST_KEY));
+        mux.set_property("iv", iv);
+    }
+
+    let original_format: &[u8; 4] = match caps.structure(0).unwrap().name().as_str() {
+        "video/x-h264" => b"avc1",
+        "video/x-av1" => b"av01",
+        _ => b"mp4a",
+    };
+    let video = caps.structure(0).unwrap().name().starts_with("video/");
+    h.set_src_caps(caps);
+    h.play();
+
+    for (i, sample) in samples.iter().enumerate() {
+        let mut buffer = gst::Buffer::from_slice(sample.clone());
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts((i as u64).seconds());
+            buffer.set_dts((i as u64).seconds());
+            buffer.set_duration(gst::ClockTime::SECOND);
+            if video && i != 0 && i != 5 {
+                buffer.set_flags(gst::BufferFlags::DELTA_UNIT);
+            }
+        }
+        assert_eq!(h.push(buffer), Ok(gst::FlowSuccess::Ok));
+    }
+    h.push_event(gst::event::Eos::new());
+
+    let mut data = Vec::new();
+    while let Some(buffer) = h.pull_until_eos().unwrap() {
+        data.extend_from_slice(&buffer.map_readable().unwrap());
+    }
+
+    let boxes = parse_top_level_boxes(&data);
+    assert_eq!(&boxes[0].0, b"ftyp");
+    assert_eq!(&boxes[1].0, b"moov");
+
+    // Protection scheme information in the sample entry
+    let moov = &data[boxes[1].1..][..boxes[1].2];
+    let find = |fourcc: &[u8; 4]| moov.windows(4).position(|w| w == fourcc).unwrap();
+    let entry_type = if video { b"encv" } else { b"enca" };
+    assert!(moov.windows(4).any(|w| w == entry_type));
+    let frma = find(b"frma");
+    assert_eq!(&moov[frma + 4..][..4], original_format);
+    let schm = find(b"schm");
+    assert_eq!(&moov[schm + 8..][..4], scheme.as_bytes());
+    assert_eq!(
+        u32::from_be_bytes(moov[schm + 12..][..4].try_into().unwrap()),
+        0x0001_0000
+    );
+
+    let tenc = find(b"tenc");
+    let version = moov[tenc + 4];
+    let pattern = moov[tenc + 9];
+    assert_eq!(moov[tenc + 10], 1);
+    let iv_size = moov[tenc + 11] as usize;
+    assert_eq!(hex::encode(&moov[tenc + 12..][..16]), TEST_KEY_ID);
+    let (pattern, constant_iv) = if scheme == "cbcs" {
+        assert_eq!(version, 1);
+        assert_eq!(iv_size, 0);
+        assert_eq!(moov[tenc + 28], 16);
+        let constant_iv = &moov[tenc + 29..][..16];
+        assert_eq!(hex::encode(constant_iv), iv);
+        (
+            Some(((pattern >> 4) as usize, (pattern & 0x0f) as usize)),
+            Some(constant_iv),
+        )
+    } else {
+        assert_eq!(version, 0);
+        assert_eq!(iv_size, iv.len() / 2);
+        (None, None)
+    };
+    if video {
+        assert_eq!(pattern, if scheme == "cbcs" { Some((1, 9)) } else { None });
+    }
+
+    // Common PSSH box with the key ID
+    let pssh = find(b"pssh");
+    assert_eq!(
+        hex::encode(&moov[pssh + 8..][..16]),
+        "1077efecc0b24d02ace33c1e52e2fb4b"
+    );
+    assert_eq!(hex::encode(&moov[pssh + 28..][..16]), TEST_KEY_ID);
+
+    let mut decrypted = Vec::new();
+    let mut all_subsamples = Vec::new();
+    for &(_, moof_offset, moof_size) in boxes.iter().filter(|(fourcc, _, _)| fourcc == b"moof") {
+        let moof = &data[moof_offset..][..moof_size];
+        let traf = find_boxes(&moof[8..], b"traf")[0].1;
+        let traf_data = &traf[8..];
+
+        let tfhd = find_boxes(traf_data, b"tfhd")[0].1;
+        let tf_flags = u32::from_be_bytes(tfhd[8..12].try_into().unwrap()) & 0xff_ffff;
+        let mut pos = 16;
+        for (flag, size) in [(0x1, 8), (0x2, 4), (0x8, 4)] {
+            if tf_flags & flag != 0 {
+                pos += size;
+            }
+        }
+        let default_size = (tf_flags & 0x10 != 0)
+            .then(|| u32::from_be_bytes(tfhd[pos..][..4].try_into().unwrap()) as usize);
+
+        let trun = find_boxes(traf_data, b"trun")[0].1;
+        let tr_flags = u32::from_be_bytes(trun[8..12].try_into().unwrap()) & 0xff_ffff;
+        let sample_count = u32::from_be_bytes(trun[12..16].try_into().unwrap()) as usize;
+        assert_ne!(tr_flags & 0x1, 0);
+        let data_offset = i32::from_be_bytes(trun[16..20].try_into().unwrap()) as usize;
+        let mut pos = if tr_flags & 0x4 != 0 { 24 } else { 20 };
+        let mut sample_sizes = Vec::new();
+        for _ in 0..sample_count {
+            if tr_flags & 0x100 != 0 {
+                pos += 4;
+            }
+            if tr_flags & 0x200 != 0 {
+                sample_sizes
+                    .push(u32::from_be_bytes(trun[pos..][..4].try_into().unwrap()) as usize);
+                pos += 4;
+            } else {
+                sample_sizes.push(default_size.unwrap());
+            }
+            if tr_flags & 0x400 != 0 {
+                pos += 4;
+            }
+            if tr_flags & 0x800 != 0 {
+                pos += 4;
+            }
+        }
+
+        let saiz = find_boxes(traf_data, b"saiz")[0].1;
+        assert_eq!(
+            u32::from_be_bytes(saiz[13..17].try_into().unwrap()) as usize,
+            sample_count
+        );
+        let saio = find_boxes(traf_data, b"saio")[0].1;
+        assert_eq!(u32::from_be_bytes(saio[12..16].try_into().unwrap()), 1);
+        let saio_offset = u32::from_be_bytes(saio[16..20].try_into().unwrap()) as usize;
+
+        let (senc_offset, senc) = find_boxes(traf_data, b"senc")[0];
+        let use_subsamples = senc[11] & 0x2 != 0;
+        assert_eq!(use_subsamples, video);
+        assert_eq!(
+            u32::from_be_bytes(senc[12..16].try_into().unwrap()) as usize,
+            sample_count
+        );
+        // The auxiliary information offset points right after the sample count
+        let traf_offset = moof_offset + 8 + find_boxes(&moof[8..], b"traf")[0].0;
+        assert_eq!(
+            moof_offset + saio_offset,
+            traf_offset + 8 + senc_offset + 16
+        );
+
+        let mut pos = 16;
+        let mut sample_pos = moof_offset + data_offset;
+        for size in sample_sizes {
+            let sample_iv = match constant_iv {
+                Some(constant_iv) => constant_iv,
+                None => &senc[pos..][..iv_size],
+            };
+            pos += iv_size;
+
+            let mut subsamples = Vec::new();
+            if use_subsamples {
+                let count = u16::from_be_bytes(senc[pos..][..2].try_into().unwrap());
+                pos += 2;
+                for _ in 0..count {
+                    subsamples.push((
+                        u16::from_be_bytes(senc[pos..][..2].try_into().unwrap()),
+                        u32::from_be_bytes(senc[pos + 2..][..4].try_into().unwrap()),
+                    ));
+                    pos += 6;
+                }
+            }
+
+            let mut sample = data[sample_pos..][..size].to_vec();
+            assert_ne!(sample, samples[decrypted.len()]);
+            decrypt_sample(pattern, sample_iv, &subsamples, &mut sample);
+            decrypted.push(sample);
+            all_subsamples.push(subsamples);
+            sample_pos += size;
+        }
+        assert_eq!(pos, senc.len());
+    }
+
+    assert_eq!(decrypted, samples);
+
+    all_subsamples
+}
+
+/// Creates H.264 samples with an SEI and a slice NAL unit each, and the expected subsamples.
+fn h264_test_samples() -> (gst::Caps, Vec<Vec<u8>>, Vec<Vec<(u16, u32)>>) {
+    let caps = gst::Caps::builder("video/x-h264")
+        .field("width", 1920i32)
+        .field("height", 1080i32)
+        .field("framerate", gst::Fraction::new(30, 1))
+        .field("stream-format", "avc")
+        .field("alignment", "au")
+        .field("codec_data", gst::Buffer::with_size(1).unwrap())
+        .build();
+
+    let mut samples = Vec::new();
+    let mut subsamples = Vec::new();
+    for i in 0..7 {
+        let mut sample = Vec::new();
+
+        // SEI
+        sample.extend(20u32.to_be_bytes());
+        sample.push(0x06);
+        sample.extend([0xab; 19]);
+
+        // IDR or non-IDR slice
+        let size = 500 + i * 7;
+        sample.extend((size as u32).to_be_bytes());
+        sample.push(if i == 0 || i == 5 { 0x65 } else { 0x41 });
+        sample.extend((1..size).map(|j| (i * 31 + j) as u8));
+
+        // The SEI, the NAL unit header and a slice header prefix stay in the clear and the
+        // protected part ends with the slice.
+        let slice_clear = 1 + 64;
+        let protected = (size - slice_clear) / 16 * 16;
+        subsamples.push(vec![((sample.len() - protected) as u16, protected as u32)]);
+        samples.push(sample);
+    }
+
+    (caps, samples, subsamples)
+}
+
+#[test]
+fn test_encryption_cenc_h264() {
+    init();
+
+    let (caps, samples, subsamples) = h264_test_samples();
+    let sample_subsamples = test_encryption("cenc", "0001020304050607", caps, &samples);
+    assert_eq!(sample_subsamples, subsamples);
+}
+
+#[test]
+fn test_encryption_cbcs_h264() {
+    init();
+
+    let (caps, samples, subsamples) = h264_test_samples();
+    let sample_subsamples =
+        test_encryption("cbcs", "202122232425262728292a2b2c2d2e2f", caps, &samples);
+    assert_eq!(sample_subsamples, subsamples);
+
+    // A typical slice header of up to 40 bytes after the SEI, the NAL unit length and the NAL
+    // unit header has to stay in the clear for cbcs.
+    for sample_subsamples in &sample_subsamples {
+        assert!(sample_subsamples[0].0 as usize >= 24 + 4 + 1 + 40);
+    }
+}
+
+/// Appends an OBU with size field.
+fn push_obu(sample: &mut Vec<u8>, obu_type: u8, payload: &[u8]) {
+    sample.push((obu_type << 3) | 0x02);
+    let mut size = payload.len();
+    loop {
+        let byte = (size & 0x7f) as u8;
+        size >>= 7;
+        if size == 0 {
+            sample.push(byte);
+            break;
+        }
+        sample.push(byte | 0x80);
+    }
+    sample.extend_from_slice(payload);
+}
+
+/// Creates AV1 temporal units with a frame OBU with two tiles each, and the expected subsamples.
+fn av1_test_samples() -> (gst::Caps, Vec<Vec<u8>>, Vec<Vec<(u16, u32)>>) {
+    // 640x480 main profile with order hints, CDEF and loop restoration enabled
+    let sequence_header = hex::decode("00000042627fef9fff3008").unwrap();
+    // Key frame and inter frame headers with two tile columns and 2 byte tile sizes. The inter
+    // frame header uses global motion parameters.
+    let key_frame_header = hex::decode("1000c2c801450a28e0401b3033028500").unwrap();
+    let inter_frame_header = hex::decode("300200400000730bc0051428a2d98198103ae5ca00").unwrap();
+
+    let mut codec_data = vec![0x81, 0x08, 0x0c, 0x00];
+    push_obu(&mut codec_data, 1, &sequence_header);
+
+    let caps = gst::Caps::builder("video/x-av1")
+        .field("width", 640i32)
+        .field("height", 480i32)
+        .field("framerate", gst::Fraction::new(30, 1))
+        .field("stream-format", "obu-stream")
+        .field("alignment", "tu")
+        .field("profile", "main")
+        .field("chroma-format", "4:2:0")
+        .field("bit-depth-luma", 8u32)
+        .field("bit-depth-chroma", 8u32)
+        .field("codec_data", gst::Buffer::from_mut_slice(codec_data))
+        .build();
+
+    let mut samples = Vec::new();
+    let mut subsamples = Vec::new();
+    for i in 0..7 {
+        let keyframe = i == 0 || i == 5;
+        let mut sample = Vec::new();
+
+        // Temporal delimiter and sequence header
+        push_obu(&mut sample, 2, &[]);
+        if keyframe {
+            push_obu(&mut sample, 1, &sequence_header);
+        }
+
+        let tile_0 = (0..100 + i).map(|j| (i * 31 + j) as u8).collect::<Vec<_>>();
+        let tile_1 = (0..200 + i * 3)
+            .map(|j| (i * 7 + j) as u8)
+            .collect::<Vec<_>>();
+
+        let mut frame = if keyframe {
+            key_frame_header.clone()
+        } else {
+            inter_frame_header.clone()
+        };
+        // Tile group header and size of the first tile
+        frame.push(0x00);
+        frame.extend(((tile_0.len() - 1) as u16).to_le_bytes());
+        let tile_0_offset = sample.len() + 3 + frame.len();
+        frame.extend(&tile_0);
+        frame.extend(&tile_1);
+        push_obu(&mut sample, 6, &frame);
+
+        // Everything but the tile data stays in the clear and the protected part of each tile
+        // ends with the tile.
+        subsamples.push(vec![
+            (
+                (tile_0_offset + tile_0.len() % 16) as u16,
+                (tile_0.len() / 16 * 16) as u32,
+            ),
+            ((tile_1.len() % 16) as u16, (tile_1.len() / 16 * 16) as u32),
+        ]);
+        samples.push(sample);
+    }
+
+    (caps, samples, subsamples)
+}
+
+#[test]
+fn test_encryption_cenc_av1() {
+    init();
+
+    let (caps, samples, subsamples) = av1_test_samples();
+    assert_eq!(
+        test_encryption("cenc", "0001020304050607", caps, &samples),
+        subsamples
+    );
+}
+
+#[test]
+fn test_encryption_cbcs_av1() {
+    init();
+
+    let (caps, samples, subsamples) = av1_test_samples();
+    assert_eq!(
+        test_encryption("cbcs", "202122232425262728292a2b2c2d2e2f", caps, &samples),
+        subsamples
+    );
+}
+
+#[test]
+fn test_encryption_cenc_aac() {
+    init();
+
+    let caps = gst::Caps::builder("audio/mpeg")
+        .field("mpegversion", 4i32)
+        .field("channels", 1i32)
+        .field("rate", 44100i32)
+        .field("stream-format", "raw")
+        .field("base-profile", "lc")
+        .field("profile", "lc")
+        .field("level", "2")
+        .field(
+            "codec_data",
+            gst::Buffer::from_slice([0x12, 0x08, 0x56, 0xe5, 0x00]),
+        )
+        .build();
+
+    let samples = (0..7)
+        .map(|i| (0..100 + i * 3).map(|j| (i * 17 + j) as u8).collect())
+        .collect::<Vec<Vec<u8>>>();
+
+    test_encryption("cenc", "0001020304050607", caps, &samples);
+}
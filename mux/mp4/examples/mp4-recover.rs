@@ -0,0 +1,32 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+// This makes an MP4 file that was written by `isomp4mux` in robust recording mode playable again
+// after the recording was interrupted. The file is modified in place.
+//
+// Usage: mp4-recover FILE [RECOVERY-FILE]
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Error};
+
+fn main() -> Result<(), Error> {
+    let mut args = std::env::args_os().skip(1);
+
+    let (Some(location), recovery_location, None) = (args.next(), args.next(), args.next()) else {
+        bail!("Usage: mp4-recover FILE [RECOVERY-FILE]");
+    };
+    let location = PathBuf::from(location);
+    let recovery_location = recovery_location.map(PathBuf::from);
+
+    gstmp4::recovery::recover_file(&location, recovery_location.as_deref())?;
+
+    println!("Recovered {}", location.display());
+
+    Ok(())
+}
@@ -0,0 +1,1514 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use anyhow::{anyhow, bail, Context, Error};
+
+/// Maximum size of a box header: 32 bit size, fourcc and 64 bit large size.
+pub(crate) const MAX_BOX_HEADER_SIZE: usize = 16;
+
+/// Big-endian reader over a byte slice.
+#[derive(Debug, Clone)]
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, pos: 0 }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub(crate) fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    pub(crate) fn skip(&mut self, n: usize) -> Result<(), Error> {
+        self.read_bytes(n).map(|_| ())
+    }
+
+    pub(crate) fn read_bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.remaining() < n {
+            bail!(
+                "Short read: need {n} bytes but only {} available",
+                self.remaining()
+            );
+        }
+
+        let data = &self.data[self.pos..][..n];
+        self.pos += n;
+        Ok(data)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_i16(&mut self) -> Result<i16, Error> {
+        Ok(i16::from_be_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_u24(&mut self) -> Result<u32, Error> {
+        let data = self.read_bytes(3)?;
+        Ok(u32::from_be_bytes([0, data[0], data[1], data[2]]))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_be_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_be_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_i64(&mut self) -> Result<i64, Error> {
+        Ok(i64::from_be_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn read_fourcc(&mut self) -> Result<[u8; 4], Error> {
+        Ok(self.read_bytes(4)?.try_into().unwrap())
+    }
+
+    /// Reads a NUL-terminated string. The terminator is optional at the end of the data.
+    pub(crate) fn read_cstring(&mut self) -> Result<String, Error> {
+        let rest = self.rest();
+        let len = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+        let s = std::str::from_utf8(&rest[..len]).context("Invalid UTF-8 string")?;
+        self.pos += (len + 1).min(rest.len());
+        Ok(String::from(s))
+    }
+
+    /// Reads the version and flags of a full box.
+    pub(crate) fn read_full_box_header(&mut self) -> Result<(u8, u32), Error> {
+        let version = self.read_u8()?;
+        let flags = self.read_u24()?;
+        Ok((version, flags))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BoxHeader {
+    pub(crate) fourcc: [u8; 4],
+    /// Size of the box header, including a large size field if any.
+    pub(crate) header_size: u64,
+    /// Size of the whole box, or `None` if the box extends until the end of the file.
+    pub(crate) size: Option<u64>,
+}
+
+/// Parses a box header from the start of `data`.
+///
+/// Returns `None` if not enough data is available yet.
+pub(crate) fn parse_box_header(data: &[u8]) -> Result<Option<BoxHeader>, Error> {
+    if data.len() < 8 {
+        return Ok(None);
+    }
+
+    let mut r = ByteReader::new(data);
+    let size = r.read_u32()?;
+    let fourcc = r.read_fourcc()?;
+
+    match size {
+        0 => Ok(Some(BoxHeader {
+            fourcc,
+            header_size: 8,
+            size: None,
+        })),
+        1 => {
+            if data.len() < 16 {
+                return Ok(None);
+            }
+            let size = r.read_u64()?;
+            if size < 16 {
+                bail!("Invalid large box size {size}");
+            }
+            Ok(Some(BoxHeader {
+                fourcc,
+                header_size: 16,
+                size: Some(size),
+            }))
+        }
+        2..=7 => bail!("Invalid box size {size}"),
+        _ => Ok(Some(BoxHeader {
+            fourcc,
+            header_size: 8,
+            size: Some(size as u64),
+        })),
+    }
+}
+
+/// Iterator over the child boxes in `data`, returning the fourcc and content of each box.
+pub(crate) struct BoxIter<'a> {
+    data: &'a [u8],
+}
+
+pub(crate) fn iter_boxes(data: &[u8]) -> BoxIter<'_> {
+    BoxIter { data }
+}
+
+impl<'a> Iterator for BoxIter<'a> {
+    type Item = Result<([u8; 4], &'a [u8]), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let header = match parse_box_header(self.data) {
+            Ok(Some(header)) => header,
+            Ok(None) => {
+                self.data = &[];
+                return Some(Err(anyhow!("Truncated box header")));
+            }
+            Err(err) => {
+                self.data = &[];
+                return Some(Err(err));
+            }
+        };
+
+        let size = header.size.unwrap_or(self.data.len() as u64);
+        if size > self.data.len() as u64 {
+            self.data = &[];
+            return Some(Err(anyhow!(
+                "Truncated '{}' box",
+                String::from_utf8_lossy(&header.fourcc)
+            )));
+        }
+
+        let content = &self.data[header.header_size as usize..size as usize];
+        self.data = &self.data[size as usize..];
+
+        Some(Ok((header.fourcc, content)))
+    }
+}
+
+/// Returns the content of the first child box with the given fourcc.
+pub(crate) fn find_box<'a>(data: &'a [u8], fourcc: &[u8; 4]) -> Result<Option<&'a [u8]>, Error> {
+    for b in iter_boxes(data) {
+        let (f, content) = b?;
+        if &f == fourcc {
+            return Ok(Some(content));
+        }
+    }
+
+    Ok(None)
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Movie {
+    /// Movie timescale from the `mvhd`.
+    pub(crate) timescale: u32,
+    /// Movie duration in movie timescale, if known.
+    pub(crate) duration: Option<u64>,
+    /// Whether the movie has a `mvex` and samples are in movie fragments.
+    pub(crate) fragmented: bool,
+    pub(crate) tracks: Vec<Track>,
+}
+
+impl Movie {
+    pub(crate) fn track_mut(&mut self, track_id: u32) -> Option<&mut Track> {
+        self.tracks.iter_mut().find(|t| t.track_id == track_id)
+    }
+}
+
+/// Per-track sample defaults from the `trex`.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct TrackDefaults {
+    pub(crate) sample_duration: u32,
+    pub(crate) sample_size: u32,
+    pub(crate) sample_flags: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Edit {
+    /// Duration of leading empty edits in movie timescale.
+    pub(crate) empty_duration: u64,
+    /// Media time in track timescale where the presentation starts.
+    pub(crate) media_time: i64,
+}
+
+#[derive(Debug)]
+pub(crate) struct Track {
+    pub(crate) track_id: u32,
+    pub(crate) handler_type: [u8; 4],
+    pub(crate) timescale: u32,
+    /// ISO 639-2/T language code.
+    pub(crate) language: Option<[u8; 3]>,
+    pub(crate) sample_entry: Option<SampleEntry>,
+    pub(crate) edit: Edit,
+    pub(crate) defaults: TrackDefaults,
+    /// Samples of the track. For fragmented files this only contains the samples of the
+    /// fragments parsed so far.
+    pub(crate) samples: Vec<Sample>,
+    /// Decode time of the next sample after the last parsed fragment.
+    pub(crate) next_decode_time: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Sample {
+    /// Absolute file offset of the sample data.
+    pub(crate) offset: u64,
+    pub(crate) size: u32,
+    /// Decode time in track timescale.
+    pub(crate) dts: u64,
+    /// Composition time offset in track timescale.
+    pub(crate) composition_time_offset: i64,
+    pub(crate) duration: u32,
+    pub(crate) sync_point: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SampleEntryKind {
+    Visual {
+        width: u16,
+        height: u16,
+    },
+    Audio {
+        channels: u16,
+        sample_size: u16,
+        sample_rate: u32,
+    },
+    XmlMetadata {
+        content_encoding: String,
+        namespace: String,
+        schema_location: String,
+    },
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SampleEntry {
+    pub(crate) fourcc: [u8; 4],
+    pub(crate) kind: SampleEntryKind,
+    /// Content of the sample entry after the common 8 byte header.
+    pub(crate) data: Vec<u8>,
+    /// Child boxes of the sample entry, e.g. codec configuration.
+    pub(crate) boxes: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl SampleEntry {
+    pub(crate) fn find_box(&self, fourcc: &[u8; 4]) -> Option<&[u8]> {
+        self.boxes
+            .iter()
+            .find(|(f, _)| f == fourcc)
+            .map(|(_, data)| data.as_slice())
+    }
+}
+
+pub(crate) fn parse_moov(data: &[u8]) -> Result<Movie, Error> {
+    let mut movie = Movie::default();
+
+    let mvhd = find_box(data, b"mvhd")?.context("No mvhd box")?;
+    let mut r = ByteReader::new(mvhd);
+    let (version, _flags) = r.read_full_box_header()?;
+    let duration = if version == 1 {
+        r.skip(16)?;
+        movie.timescale = r.read_u32()?;
+        r.read_u64()?
+    } else {
+        r.skip(8)?;
+        movie.timescale = r.read_u32()?;
+        let duration = r.read_u32()?;
+        if duration == u32::MAX {
+            u64::MAX
+        } else {
+            duration as u64
+        }
+    };
+    if movie.timescale == 0 {
+        bail!("Invalid movie timescale 0");
+    }
+    movie.duration = (duration != 0 && duration != u64::MAX).then_some(duration);
+
+    let mut trexs = Vec::new();
+    for b in iter_boxes(data) {
+        let (fourcc, content) = b?;
+        match &fourcc {
+            b"trak" => {
+                movie.tracks.push(parse_trak(content).context("trak")?);
+            }
+            b"mvex" => {
+                movie.fragmented = true;
+                for b in iter_boxes(content) {
+                    let (fourcc, content) = b?;
+                    match &fourcc {
+                        b"mehd" => {
+                            let mut r = ByteReader::new(content);
+                            let (version, _flags) = r.read_full_box_header()?;
+                            let duration = if version == 1 {
+                                r.read_u64()?
+                            } else {
+                                r.read_u32()? as u64
+                            };
+                            if duration != 0 {
+                                movie.duration = Some(duration);
+                            }
+                        }
+                        b"trex" => {
+                            let mut r = ByteReader::new(content);
+                            let _ = r.read_full_box_header()?;
+                            let track_id = r.read_u32()?;
+                            // default sample description index
+                            r.skip(4)?;
+                            let defaults = TrackDefaults {
+                                sample_duration: r.read_u32()?,
+                                sample_size: r.read_u32()?,
+                                sample_flags: r.read_u32()?,
+                            };
+                            trexs.push((track_id, defaults));
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    for (track_id, defaults) in trexs {
+        if let Some(track) = movie.track_mut(track_id) {
+            track.defaults = defaults;
+        }
+    }
+
+    Ok(movie)
+}
+
+fn parse_trak(data: &[u8]) -> Result<Track, Error> {
+    let tkhd = find_box(data, b"tkhd")?.context("No tkhd box")?;
+    let mut r = ByteReader::new(tkhd);
+    let (version, _flags) = r.read_full_box_header()?;
+    // creation and modification time
+    r.skip(if version == 1 { 16 } else { 8 })?;
+    let track_id = r.read_u32()?;
+
+    let mut edit = Edit::default();
+    if let Some(elst) = find_box(data, b"edts")?
+        .map(|edts| find_box(edts, b"elst"))
+        .transpose()?
+        .flatten()
+    {
+        edit = parse_elst(elst).context("elst")?;
+    }
+
+    let mdia = find_box(data, b"mdia")?.context("No mdia box")?;
+    let mdhd = find_box(mdia, b"mdhd")?.context("No mdhd box")?;
+    let mut r = ByteReader::new(mdhd);
+    let (version, _flags) = r.read_full_box_header()?;
+    // creation and modification time
+    r.skip(if version == 1 { 16 } else { 8 })?;
+    let timescale = r.read_u32()?;
+    // duration
+    r.skip(if version == 1 { 8 } else { 4 })?;
+    if timescale == 0 {
+        bail!("Invalid track timescale 0");
+    }
+    let language = r.read_u16()?;
+    let language = (language != 0 && language != 0x7fff).then(|| {
+        [
+            (((language >> 10) & 0x1f) as u8) + 0x60,
+            (((language >> 5) & 0x1f) as u8) + 0x60,
+            ((language & 0x1f) as u8) + 0x60,
+        ]
+    });
+
+    let hdlr = find_box(mdia, b"hdlr")?.context("No hdlr box")?;
+    let mut r = ByteReader::new(hdlr);
+    let _ = r.read_full_box_header()?;
+    r.skip(4)?;
+    let handler_type = r.read_fourcc()?;
+
+    let minf = find_box(mdia, b"minf")?.context("No minf box")?;
+    let stbl = find_box(minf, b"stbl")?.context("No stbl box")?;
+    let (sample_entry, samples) = parse_stbl(stbl).context("stbl")?;
+
+    Ok(Track {
+        track_id,
+        handler_type,
+        timescale,
+        language,
+        sample_entry,
+        edit,
+        defaults: TrackDefaults::default(),
+        next_decode_time: samples
+            .last()
+            .map(|s| s.dts + s.duration as u64)
+            .unwrap_or(0),
+        samples,
+    })
+}
+
+fn parse_elst(data: &[u8]) -> Result<Edit, Error> {
+    let mut r = ByteReader::new(data);
+    let (version, _flags) = r.read_full_box_header()?;
+    let entry_count = r.read_u32()?;
+
+    let mut edit = Edit::default();
+    for _ in 0..entry_count {
+        let (segment_duration, media_time) = if version == 1 {
+            (r.read_u64()?, r.read_i64()?)
+        } else {
+            (r.read_u32()? as u64, r.read_i32()? as i64)
+        };
+        // media rate
+        r.skip(4)?;
+
+        if media_time == -1 {
+            edit.empty_duration += segment_duration;
+        } else {
+            // Only the first non-empty edit is considered
+            edit.media_time = media_time;
+            break;
+        }
+    }
+
+    Ok(edit)
+}
+
+fn parse_sample_entry(fourcc: [u8; 4], data: &[u8]) -> Result<SampleEntry, Error> {
+    let mut r = ByteReader::new(data);
+    // reserved, data reference index
+    r.skip(8)?;
+
+    let kind = match &fourcc {
+        b"avc1" | b"avc3" | b"hvc1" | b"hev1" | b"vp08" | b"vp09" | b"av01" | b"jpeg" | b"mp4v"
+        | b"uncv" => {
+            // pre-defined, reserved, pre-defined
+            r.skip(2 + 2 + 12)?;
+            let width = r.read_u16()?;
+            let height = r.read_u16()?;
+            // resolution, reserved, frame count, compressor name, depth, pre-defined
+            r.skip(4 + 4 + 4 + 2 + 32 + 2 + 2)?;
+            SampleEntryKind::Visual { width, height }
+        }
+        b"mp4a" | b"Opus" | b"fLaC" | b"ac-3" | b"ec-3" | b"alaw" | b"ulaw" | b"ms\x00\x45"
+        | b"ipcm" | b"fpcm" | b"sowt" | b"twos" => {
+            let version = r.read_u16()?;
+            // revision level, vendor
+            r.skip(2 + 4)?;
+            let mut channels = r.read_u16()?;
+            let sample_size = r.read_u16()?;
+            // compression id, packet size
+            r.skip(4)?;
+            let mut sample_rate = r.read_u32()? >> 16;
+
+            // QuickTime sound sample description extensions
+            match version {
+                1 => {
+                    r.skip(16)?;
+                }
+                2 => {
+                    // size of struct
+                    r.skip(4)?;
+                    sample_rate = f64::from_bits(r.read_u64()?) as u32;
+                    channels = r.read_u32()? as u16;
+                    // reserved, bits per channel, format flags, bytes per frame, lpcm frames per packet
+                    r.skip(20)?;
+                }
+                _ => (),
+            }
+
+            SampleEntryKind::Audio {
+                channels,
+                sample_size,
+                sample_rate,
+            }
+        }
+        b"metx" => {
+            let content_encoding = r.read_cstring()?;
+            let namespace = r.read_cstring()?;
+            let schema_location = r.read_cstring()?;
+            SampleEntryKind::XmlMetadata {
+                content_encoding,
+                namespace,
+                schema_location,
+            }
+        }
+        _ => SampleEntryKind::Other,
+    };
+
+    let mut boxes = Vec::new();
+    if kind != SampleEntryKind::Other {
+        for b in iter_boxes(r.rest()) {
+            // Some files have trailing garbage after the child boxes, ignore that
+            let Ok((fourcc, content)) = b else {
+                break;
+            };
+            boxes.push((fourcc, content.to_vec()));
+        }
+    }
+
+    Ok(SampleEntry {
+        fourcc,
+        kind,
+        data: data.to_vec(),
+        boxes,
+    })
+}
+
+/// Sample sizes of a `stsz` or `stz2` box.
+enum SampleSizes {
+    /// Size and number of samples
+    Constant(u32, usize),
+    Table(Vec<u32>),
+}
+
+impl SampleSizes {
+    fn len(&self) -> usize {
+        match self {
+            SampleSizes::Constant(_, count) => *count,
+            SampleSizes::Table(sizes) => sizes.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.len()).map(move |idx| match self {
+            SampleSizes::Constant(size, _) => *size,
+            SampleSizes::Table(sizes) => sizes[idx],
+        })
+    }
+}
+
+fn parse_stbl(data: &[u8]) -> Result<(Option<SampleEntry>, Vec<Sample>), Error> {
+    let mut sample_entry = None;
+    let mut stts = None;
+    let mut ctts = None;
+    let mut stss = None;
+    let mut sizes = None;
+    let mut stsc = None;
+    let mut chunk_offsets = None;
+
+    for b in iter_boxes(data) {
+        let (fourcc, content) = b?;
+        match &fourcc {
+            b"stsd" => {
+                let mut r = ByteReader::new(content);
+                let _ = r.read_full_box_header()?;
+                let entry_count = r.read_u32()?;
+                if entry_count > 0 {
+                    let (fourcc, content) = iter_boxes(r.rest())
+                        .next()
+                        .ok_or_else(|| anyhow!("No sample entry"))??;
+                    sample_entry = Some(parse_sample_entry(fourcc, content).context("stsd")?);
+                }
+            }
+            b"stts" => stts = Some(content),
+            b"ctts" => ctts = Some(content),
+            b"stss" => stss = Some(content),
+            b"stsz" | b"stz2" => sizes = Some((fourcc, content)),
+            b"stsc" => stsc = Some(content),
+            b"stco" | b"co64" => chunk_offsets = Some((fourcc, content)),
+            _ => (),
+        }
+    }
+
+    let Some((sizes_fourcc, sizes)) = sizes else {
+        return Ok((sample_entry, Vec::new()));
+    };
+
+    // Sample sizes
+    let mut r = ByteReader::new(sizes);
+    let _ = r.read_full_box_header()?;
+    let sizes = if &sizes_fourcc == b"stsz" {
+        let sample_size = r.read_u32()?;
+        let sample_count = r.read_u32()?;
+        if sample_size != 0 {
+            SampleSizes::Constant(sample_size, sample_count as usize)
+        } else {
+            if r.remaining() / 4 < sample_count as usize {
+                bail!("Truncated stsz");
+            }
+            SampleSizes::Table(
+                (0..sample_count)
+                    .map(|_| r.read_u32())
+                    .collect::<Result<Vec<_>, _>>()?,
+            )
+        }
+    } else {
+        r.skip(3)?;
+        let field_size = r.read_u8()?;
+        let sample_count = r.read_u32()? as usize;
+        SampleSizes::Table(match field_size {
+            4 => {
+                let data = r.read_bytes(sample_count.div_ceil(2))?;
+                (0..sample_count)
+                    .map(|i| {
+                        let b = data[i / 2];
+                        if i % 2 == 0 {
+                            (b >> 4) as u32
+                        } else {
+                            (b & 0x0f) as u32
+                        }
+                    })
+                    .collect()
+            }
+            8 => r
+                .read_bytes(sample_count)?
+                .iter()
+                .map(|b| *b as u32)
+                .collect(),
+            16 => {
+                if r.remaining() / 2 < sample_count {
+                    bail!("Truncated stz2");
+                }
+                (0..sample_count)
+                    .map(|_| r.read_u16().map(|s| s as u32))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+            _ => bail!("Invalid stz2 field size {field_size}"),
+        })
+    };
+
+    if sizes.is_empty() {
+        return Ok((sample_entry, Vec::new()));
+    }
+
+    // Sample offsets
+    let (chunk_offsets_fourcc, chunk_offsets) = chunk_offsets.context("No stco/co64 box")?;
+    let mut r = ByteReader::new(chunk_offsets);
+    let _ = r.read_full_box_header()?;
+    let chunk_count = r.read_u32()? as usize;
+    let entry_size = if &chunk_offsets_fourcc == b"co64" {
+        8
+    } else {
+        4
+    };
+    if r.remaining() / entry_size < chunk_count {
+        bail!("Truncated chunk offsets");
+    }
+    let chunk_offsets = (0..chunk_count)
+        .map(|_| {
+            if entry_size == 8 {
+                r.read_u64()
+            } else {
+                r.read_u32().map(|o| o as u64)
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut r = ByteReader::new(stsc.context("No stsc box")?);
+    let _ = r.read_full_box_header()?;
+    let entry_count = r.read_u32()?;
+    let mut stsc_entries = Vec::with_capacity(entry_count.min(1024) as usize);
+    for _ in 0..entry_count {
+        let first_chunk = r.read_u32()?;
+        let samples_per_chunk = r.read_u32()?;
+        let _sample_description_index = r.read_u32()?;
+        if first_chunk == 0 {
+            bail!("Invalid stsc first chunk 0");
+        }
+        stsc_entries.push((first_chunk as usize - 1, samples_per_chunk));
+    }
+    let chunk_ranges = stsc_entries
+        .iter()
+        .enumerate()
+        .map(|(idx, (first_chunk, samples_per_chunk))| {
+            let last_chunk = stsc_entries
+                .get(idx + 1)
+                .map(|(first_chunk, _)| *first_chunk)
+                .unwrap_or(chunk_offsets.len())
+                .min(chunk_offsets.len());
+            (*first_chunk..last_chunk, *samples_per_chunk)
+        })
+        .collect::<Vec<_>>();
+
+    // The sample count is not limited by the size of a stsz box with a constant sample size, so
+    // check it against the chunks before allocating anything for the samples
+    let covered_samples = chunk_ranges
+        .iter()
+        .fold(0usize, |acc, (chunks, samples_per_chunk)| {
+            acc.saturating_add(chunks.len().saturating_mul(*samples_per_chunk as usize))
+        });
+    if covered_samples < sizes.len() {
+        bail!(
+            "Sample to chunk table covers {covered_samples} samples but there are {}",
+            sizes.len()
+        );
+    }
+    let mut samples = Vec::new();
+    samples
+        .try_reserve_exact(sizes.len())
+        .with_context(|| format!("Too many samples: {}", sizes.len()))?;
+
+    let mut sizes_iter = sizes.iter();
+    'chunks: for (chunks, samples_per_chunk) in chunk_ranges {
+        for chunk_offset in chunk_offsets.get(chunks).unwrap_or_default() {
+            let mut offset = *chunk_offset;
+            for _ in 0..samples_per_chunk {
+                let Some(size) = sizes_iter.next() else {
+                    break 'chunks;
+                };
+                samples.push(Sample {
+                    offset,
+                    size,
+                    dts: 0,
+                    composition_time_offset: 0,
+                    duration: 0,
+                    sync_point: true,
+                });
+                offset += size as u64;
+            }
+        }
+    }
+
+    if samples.len() != sizes.len() {
+        bail!(
+            "Sample to chunk table covers {} samples but there are {}",
+            samples.len(),
+            sizes.len()
+        );
+    }
+
+    // Decode times and durations
+    let mut r = ByteReader::new(stts.context("No stts box")?);
+    let _ = r.read_full_box_header()?;
+    let entry_count = r.read_u32()?;
+    let mut samples_iter = samples.iter_mut();
+    let mut dts = 0;
+    let mut delta = 0;
+    'stts: for _ in 0..entry_count {
+        let count = r.read_u32()?;
+        delta = r.read_u32()?;
+        for _ in 0..count {
+            let Some(sample) = samples_iter.next() else {
+                break 'stts;
+            };
+            sample.dts = dts;
+            sample.duration = delta;
+            dts += delta as u64;
+        }
+    }
+    // Samples not covered by the stts get the duration of the last entry
+    for sample in samples_iter {
+        sample.dts = dts;
+        sample.duration = delta;
+        dts += delta as u64;
+    }
+
+    // Composition time offsets
+    if let Some(ctts) = ctts {
+        let mut r = ByteReader::new(ctts);
+        let _ = r.read_full_box_header()?;
+        let entry_count = r.read_u32()?;
+        let mut samples_iter = samples.iter_mut();
+        'ctts: for _ in 0..entry_count {
+            let count = r.read_u32()?;
+            // Version 0 is supposed to be unsigned but in practice is often used
+            // for signed offsets too.
+            let offset = r.read_i32()?;
+            for _ in 0..count {
+                let Some(sample) = samples_iter.next() else {
+                    break 'ctts;
+                };
+                sample.composition_time_offset = offset as i64;
+            }
+        }
+    }
+
+    // Sync samples
+    if let Some(stss) = stss {
+        let mut r = ByteReader::new(stss);
+        let _ = r.read_full_box_header()?;
+        let entry_count = r.read_u32()?;
+
+        for sample in &mut samples {
+            sample.sync_point = false;
+        }
+
+        for _ in 0..entry_count {
+            let sample_number = r.read_u32()? as usize;
+            if let Some(sample) = sample_number
+                .checked_sub(1)
+                .and_then(|idx| samples.get_mut(idx))
+            {
+                sample.sync_point = true;
+            }
+        }
+    }
+
+    Ok((sample_entry, samples))
+}
+
+/// Returns the sequence number of the `mfhd`.
+pub(crate) fn parse_moof(data: &[u8], moof_offset: u64, movie: &mut Movie) -> Result<u32, Error> {
+    let mut sequence_number = 0;
+
+    for b in iter_boxes(data) {
+        let (fourcc, content) = b?;
+        match &fourcc {
+            b"mfhd" => {
+                let mut r = ByteReader::new(content);
+                let _ = r.read_full_box_header()?;
+                sequence_number = r.read_u32()?;
+            }
+            b"traf" => parse_traf(content, moof_offset, movie).context("traf")?,
+            _ => (),
+        }
+    }
+
+    Ok(sequence_number)
+}
+
+/// Sample flags as used in `trex`, `tfhd` and `trun`.
+const SAMPLE_FLAGS_IS_NON_SYNC: u32 = 0x0001_0000;
+
+fn parse_traf(data: &[u8], moof_offset: u64, movie: &mut Movie) -> Result<(), Error> {
+    let tfhd = find_box(data, b"tfhd")?.context("No tfhd box")?;
+    let mut r = ByteReader::new(tfhd);
+    let (_version, flags) = r.read_full_box_header()?;
+    let track_id = r.read_u32()?;
+
+    let Some(track) = movie.track_mut(track_id) else {
+        // Fragment for an unknown track, nothing to do here
+        return Ok(());
+    };
+
+    let base_data_offset = if flags & 0x01 != 0 {
+        Some(r.read_u64()?)
+    } else {
+        None
+    };
+    let mut defaults = track.defaults;
+    if flags & 0x02 != 0 {
+        // sample description index
+        r.skip(4)?;
+    }
+    if flags & 0x08 != 0 {
+        defaults.sample_duration = r.read_u32()?;
+    }
+    if flags & 0x10 != 0 {
+        defaults.sample_size = r.read_u32()?;
+    }
+    if flags & 0x20 != 0 {
+        defaults.sample_flags = r.read_u32()?;
+    }
+
+    if let Some(tfdt) = find_box(data, b"tfdt")? {
+        let mut r = ByteReader::new(tfdt);
+        let (version, _flags) = r.read_full_box_header()?;
+        track.next_decode_time = if version == 1 {
+            r.read_u64()?
+        } else {
+            r.read_u32()? as u64
+        };
+    }
+
+    // Without explicit base data offset the moof is used as base. This is correct for
+    // default-base-is-moof and for the first traf otherwise, which covers all files
+    // seen in practice. A trun without data offset continues after the previous one.
+    let base_offset = base_data_offset.unwrap_or(moof_offset);
+    let mut next_offset = base_offset;
+
+    for b in iter_boxes(data) {
+        let (fourcc, content) = b?;
+        if &fourcc != b"trun" {
+            continue;
+        }
+
+        let mut r = ByteReader::new(content);
+        let (_version, flags) = r.read_full_box_header()?;
+        let sample_count = r.read_u32()?;
+
+        let mut offset = if flags & 0x01 != 0 {
+            let data_offset = r.read_i32()?;
+            base_offset
+                .checked_add_signed(data_offset as i64)
+                .context("Invalid data offset")?
+        } else {
+            next_offset
+        };
+
+        let first_sample_flags = if flags & 0x04 != 0 {
+            Some(r.read_u32()?)
+        } else {
+            None
+        };
+
+        let entry_size = [0x100, 0x200, 0x400, 0x800]
+            .iter()
+            .filter(|f| flags & **f != 0)
+            .count()
+            * 4;
+        if entry_size > 0 && r.remaining() / entry_size < sample_count as usize {
+            bail!("Truncated trun");
+        }
+
+        track.samples.reserve(sample_count as usize);
+        for idx in 0..sample_count {
+            let duration = if flags & 0x100 != 0 {
+                r.read_u32()?
+            } else {
+                defaults.sample_duration
+            };
+            let size = if flags & 0x200 != 0 {
+                r.read_u32()?
+            } else {
+                defaults.sample_size
+            };
+            let sample_flags = if flags & 0x400 != 0 {
+                r.read_u32()?
+            } else if idx == 0 {
+                first_sample_flags.unwrap_or(defaults.sample_flags)
+            } else {
+                defaults.sample_flags
+            };
+            let composition_time_offset = if flags & 0x800 != 0 {
+                r.read_i32()? as i64
+            } else {
+                0
+            };
+
+            track.samples.push(Sample {
+                offset,
+                size,
+                dts: track.next_decode_time,
+                composition_time_offset,
+                duration,
+                sync_point: sample_flags & SAMPLE_FLAGS_IS_NON_SYNC == 0,
+            });
+
+            offset += size as u64;
+            track.next_decode_time += duration as u64;
+        }
+
+        next_offset = offset;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SidxReference {
+    /// Whether this references another `sidx` instead of media.
+    pub(crate) references_sidx: bool,
+    /// Absolute file offset of the referenced data.
+    pub(crate) offset: u64,
+    pub(crate) size: u32,
+    /// Earliest presentation time in the `sidx` timescale.
+    pub(crate) earliest_presentation_time: u64,
+    pub(crate) duration: u32,
+    pub(crate) starts_with_sap: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Sidx {
+    pub(crate) timescale: u32,
+    pub(crate) references: Vec<SidxReference>,
+}
+
+/// Parses a `sidx`. `end_offset` is the absolute file offset of the first byte after the box.
+pub(crate) fn parse_sidx(data: &[u8], end_offset: u64) -> Result<Sidx, Error> {
+    let mut r = ByteReader::new(data);
+    let (version, _flags) = r.read_full_box_header()?;
+    // reference ID
+    r.skip(4)?;
+    let timescale = r.read_u32()?;
+    if timescale == 0 {
+        bail!("Invalid sidx timescale 0");
+    }
+    let (mut earliest_presentation_time, first_offset) = if version == 1 {
+        (r.read_u64()?, r.read_u64()?)
+    } else {
+        (r.read_u32()? as u64, r.read_u32()? as u64)
+    };
+    // reserved
+    r.skip(2)?;
+    let reference_count = r.read_u16()?;
+
+    let mut offset = end_offset + first_offset;
+    let mut references = Vec::with_capacity(reference_count as usize);
+    for _ in 0..reference_count {
+        let v = r.read_u32()?;
+        let references_sidx = v & 0x8000_0000 != 0;
+        let size = v & 0x7fff_ffff;
+        let duration = r.read_u32()?;
+        let sap = r.read_u32()?;
+
+        references.push(SidxReference {
+            references_sidx,
+            offset,
+            size,
+            earliest_presentation_time,
+            duration,
+            starts_with_sap: sap & 0x8000_0000 != 0,
+        });
+
+        offset += size as u64;
+        earliest_presentation_time += duration as u64;
+    }
+
+    Ok(Sidx {
+        timescale,
+        references,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TfraEntry {
+    /// Presentation time in track timescale.
+    pub(crate) time: u64,
+    pub(crate) moof_offset: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Tfra {
+    pub(crate) track_id: u32,
+    pub(crate) entries: Vec<TfraEntry>,
+}
+
+/// Parses the size of the `mfra` from the `mfro`.
+pub(crate) fn parse_mfro(data: &[u8]) -> Result<u32, Error> {
+    let mut r = ByteReader::new(data);
+    let _ = r.read_full_box_header()?;
+    r.read_u32()
+}
+
+pub(crate) fn parse_mfra(data: &[u8]) -> Result<Vec<Tfra>, Error> {
+    let mut tfras = Vec::new();
+
+    for b in iter_boxes(data) {
+        let (fourcc, content) = b?;
+        if &fourcc != b"tfra" {
+            continue;
+        }
+
+        let mut r = ByteReader::new(content);
+        let (version, _flags) = r.read_full_box_header()?;
+        let track_id = r.read_u32()?;
+        let lengths = r.read_u32()?;
+        let length_size_of_traf_num = ((lengths >> 4) & 0x3) as usize + 1;
+        let length_size_of_trun_num = ((lengths >> 2) & 0x3) as usize + 1;
+        let length_size_of_sample_num = (lengths & 0x3) as usize + 1;
+        let number_of_entry = r.read_u32()?;
+
+        let mut entries = Vec::with_capacity(number_of_entry.min(65536) as usize);
+        for _ in 0..number_of_entry {
+            let (time, moof_offset) = if version == 1 {
+                (r.read_u64()?, r.read_u64()?)
+            } else {
+                (r.read_u32()? as u64, r.read_u32()? as u64)
+            };
+            r.skip(length_size_of_traf_num + length_size_of_trun_num + length_size_of_sample_num)?;
+
+            // Multiple entries per moof are possible, only keep the first one
+            if entries
+                .last()
+                .is_some_and(|e: &TfraEntry| e.moof_offset == moof_offset)
+            {
+                continue;
+            }
+            entries.push(TfraEntry { time, moof_offset });
+        }
+
+        tfras.push(Tfra { track_id, entries });
+    }
+
+    Ok(tfras)
+}
+
+/// Minimal MSB-first bit reader for codec configuration parsing.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0 }
+    }
+
+    fn read_bits(&mut self, n: u32) -> Result<u32, Error> {
+        assert!(n <= 32);
+
+        let mut v = 0u32;
+        for _ in 0..n {
+            let byte = *self
+                .data
+                .get(self.pos / 8)
+                .ok_or_else(|| anyhow!("Short read"))?;
+            let bit = (byte >> (7 - self.pos % 8)) & 1;
+            v = (v << 1) | bit as u32;
+            self.pos += 1;
+        }
+
+        Ok(v)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EsDescriptor {
+    pub(crate) object_type_indication: u8,
+    pub(crate) avg_bitrate: u32,
+    pub(crate) decoder_specific_info: Option<Vec<u8>>,
+}
+
+fn read_descriptor_header(r: &mut ByteReader) -> Result<(u8, usize), Error> {
+    let tag = r.read_u8()?;
+    let mut size = 0usize;
+    for _ in 0..4 {
+        let b = r.read_u8()?;
+        size = (size << 7) | (b & 0x7f) as usize;
+        if b & 0x80 == 0 {
+            break;
+        }
+    }
+
+    Ok((tag, size))
+}
+
+pub(crate) fn parse_esds(data: &[u8]) -> Result<EsDescriptor, Error> {
+    let mut r = ByteReader::new(data);
+    let _ = r.read_full_box_header()?;
+
+    let (tag, _size) = read_descriptor_header(&mut r)?;
+    if tag != 0x03 {
+        bail!("Expected ES descriptor but got tag {tag}");
+    }
+    // ES ID
+    r.skip(2)?;
+    let flags = r.read_u8()?;
+    if flags & 0x80 != 0 {
+        // depends on ES ID
+        r.skip(2)?;
+    }
+    if flags & 0x40 != 0 {
+        let url_len = r.read_u8()?;
+        r.skip(url_len as usize)?;
+    }
+    if flags & 0x20 != 0 {
+        // OCR ES ID
+        r.skip(2)?;
+    }
+
+    let (tag, size) = read_descriptor_header(&mut r)?;
+    if tag != 0x04 {
+        bail!("Expected decoder config descriptor but got tag {tag}");
+    }
+    let mut r = ByteReader::new(r.read_bytes(size.min(r.remaining()))?);
+    let object_type_indication = r.read_u8()?;
+    // stream type, upstream flag, buffer size, max bitrate
+    r.skip(4 + 4)?;
+    let avg_bitrate = r.read_u32()?;
+
+    let mut decoder_specific_info = None;
+    if r.remaining() > 0 {
+        let (tag, size) = read_descriptor_header(&mut r)?;
+        if tag == 0x05 {
+            decoder_specific_info = Some(r.read_bytes(size.min(r.remaining()))?.to_vec());
+        }
+    }
+
+    Ok(EsDescriptor {
+        object_type_indication,
+        avg_bitrate,
+        decoder_specific_info,
+    })
+}
+
+/// Returns the sample rate and number of channels from an AAC `AudioSpecificConfig`.
+pub(crate) fn parse_aac_audio_specific_config(data: &[u8]) -> Result<(u32, u32), Error> {
+    const SAMPLE_RATES: [u32; 13] = [
+        96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+    ];
+
+    let mut r = BitReader::new(data);
+    let object_type = r.read_bits(5)?;
+    if object_type == 31 {
+        r.read_bits(6)?;
+    }
+    let frequency_index = r.read_bits(4)?;
+    let rate = if frequency_index == 0xf {
+        r.read_bits(24)?
+    } else {
+        *SAMPLE_RATES
+            .get(frequency_index as usize)
+            .ok_or_else(|| anyhow!("Invalid sampling frequency index {frequency_index}"))?
+    };
+    let channels = match r.read_bits(4)? {
+        7 => 8,
+        c => c,
+    };
+
+    Ok((rate, channels))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct OpusSpecificBox {
+    pub(crate) output_channel_count: u8,
+    pub(crate) pre_skip: u16,
+    pub(crate) input_sample_rate: u32,
+    pub(crate) output_gain: i16,
+    pub(crate) channel_mapping_family: u8,
+    pub(crate) stream_count: u8,
+    pub(crate) coupled_count: u8,
+    pub(crate) channel_mapping: Vec<u8>,
+}
+
+pub(crate) fn parse_dops(data: &[u8]) -> Result<OpusSpecificBox, Error> {
+    let mut r = ByteReader::new(data);
+    let version = r.read_u8()?;
+    if version != 0 {
+        bail!("Unsupported dOps version {version}");
+    }
+    let output_channel_count = r.read_u8()?;
+    let pre_skip = r.read_u16()?;
+    let input_sample_rate = r.read_u32()?;
+    let output_gain = r.read_i16()?;
+    let channel_mapping_family = r.read_u8()?;
+
+    let (stream_count, coupled_count, channel_mapping) = if channel_mapping_family != 0 {
+        let stream_count = r.read_u8()?;
+        let coupled_count = r.read_u8()?;
+        let channel_mapping = r.read_bytes(output_channel_count as usize)?.to_vec();
+        (stream_count, coupled_count, channel_mapping)
+    } else {
+        (1, (output_channel_count > 1) as u8, Vec::new())
+    };
+
+    Ok(OpusSpecificBox {
+        output_channel_count,
+        pre_skip,
+        input_sample_rate,
+        output_gain,
+        channel_mapping_family,
+        stream_count,
+        coupled_count,
+        channel_mapping,
+    })
+}
+
+/// Returns the sample rate and number of channels from a FLAC `STREAMINFO` metadata block,
+/// including its block header.
+pub(crate) fn parse_flac_streaminfo(data: &[u8]) -> Result<(u32, u32), Error> {
+    if data.len() < 4 + 34 || data[0] & 0x7f != 0 {
+        bail!("Invalid STREAMINFO block");
+    }
+
+    let data = &data[4..];
+    let rate = ((data[10] as u32) << 12) | ((data[11] as u32) << 4) | ((data[12] as u32) >> 4);
+    let channels = ((data[12] as u32 >> 1) & 0x7) + 1;
+
+    Ok((rate, channels))
+}
+
+const AC3_SAMPLE_RATES: [u32; 3] = [48000, 44100, 32000];
+const AC3_ACMOD_CHANNELS: [u32; 8] = [2, 1, 2, 3, 3, 4, 4, 5];
+
+/// Returns the sample rate and number of channels from a `dac3`.
+pub(crate) fn parse_dac3(data: &[u8]) -> Result<(u32, u32), Error> {
+    let mut r = BitReader::new(data);
+    let fscod = r.read_bits(2)?;
+    // bsid, bsmod
+    r.read_bits(5 + 3)?;
+    let acmod = r.read_bits(3)?;
+    let lfeon = r.read_bits(1)?;
+
+    let rate = *AC3_SAMPLE_RATES
+        .get(fscod as usize)
+        .ok_or_else(|| anyhow!("Invalid fscod {fscod}"))?;
+
+    Ok((rate, AC3_ACMOD_CHANNELS[acmod as usize] + lfeon))
+}
+
+/// Returns the sample rate and number of channels of the first independent substream
+/// from a `dec3`.
+pub(crate) fn parse_dec3(data: &[u8]) -> Result<(u32, u32), Error> {
+    let mut r = BitReader::new(data);
+    // data rate, number of independent substreams
+    r.read_bits(13 + 3)?;
+    let fscod = r.read_bits(2)?;
+    // bsid, reserved, asvc, bsmod
+    r.read_bits(5 + 1 + 1 + 3)?;
+    let acmod = r.read_bits(3)?;
+    let lfeon = r.read_bits(1)?;
+
+    // fscod 3 signals reduced sample rates that are only in the bitstream
+    let rate = AC3_SAMPLE_RATES
+        .get(fscod as usize)
+        .copied()
+        .unwrap_or(24000);
+
+    Ok((rate, AC3_ACMOD_CHANNELS[acmod as usize] + lfeon))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_box(v: &mut Vec<u8>, fourcc: &[u8; 4], content: impl FnOnce(&mut Vec<u8>)) {
+        let pos = v.len();
+        v.extend([0u8; 4]);
+        v.extend(fourcc);
+        content(v);
+        let size = (v.len() - pos) as u32;
+        v[pos..][..4].copy_from_slice(&size.to_be_bytes());
+    }
+
+    #[test]
+    fn test_box_header() {
+        assert_eq!(parse_box_header(&[0, 0, 0, 8]).unwrap(), None);
+        assert_eq!(
+            parse_box_header(b"\x00\x00\x00\x10moov").unwrap(),
+            Some(BoxHeader {
+                fourcc: *b"moov",
+                header_size: 8,
+                size: Some(16),
+            })
+        );
+        assert_eq!(
+            parse_box_header(b"\x00\x00\x00\x00mdat").unwrap(),
+            Some(BoxHeader {
+                fourcc: *b"mdat",
+                header_size: 8,
+                size: None,
+            })
+        );
+        assert_eq!(parse_box_header(b"\x00\x00\x00\x01mdat").unwrap(), None);
+        assert_eq!(
+            parse_box_header(b"\x00\x00\x00\x01mdat\x00\x00\x00\x01\x00\x00\x00\x00").unwrap(),
+            Some(BoxHeader {
+                fourcc: *b"mdat",
+                header_size: 16,
+                size: Some(0x1_0000_0000),
+            })
+        );
+        assert!(parse_box_header(b"\x00\x00\x00\x04mdat").is_err());
+    }
+
+    #[test]
+    fn test_traf_default_base_is_moof() {
+        let mut movie = Movie {
+            timescale: 1000,
+            fragmented: true,
+            tracks: vec![Track {
+                track_id: 1,
+                handler_type: *b"vide",
+                timescale: 90000,
+                language: None,
+                sample_entry: None,
+                edit: Edit::default(),
+                defaults: TrackDefaults {
+                    sample_duration: 3000,
+                    sample_size: 0,
+                    sample_flags: SAMPLE_FLAGS_IS_NON_SYNC,
+                },
+                samples: Vec::new(),
+                next_decode_time: 0,
+            }],
+            ..Default::default()
+        };
+
+        let mut moof = Vec::new();
+        write_box(&mut moof, b"mfhd", |v| {
+            v.extend([0, 0, 0, 0]);
+            v.extend(5u32.to_be_bytes());
+        });
+        write_box(&mut moof, b"traf", |v| {
+            write_box(v, b"tfhd", |v| {
+                v.extend([0, 0x02, 0, 0]);
+                v.extend(1u32.to_be_bytes());
+            });
+            write_box(v, b"tfdt", |v| {
+                v.extend([1, 0, 0, 0]);
+                v.extend(90000u64.to_be_bytes());
+            });
+            write_box(v, b"trun", |v| {
+                // data offset, first sample flags, sample size, composition time offset
+                v.extend([0, 0, 0x0a, 0x05]);
+                v.extend(2u32.to_be_bytes());
+                v.extend(100i32.to_be_bytes());
+                v.extend(0u32.to_be_bytes());
+                v.extend(10u32.to_be_bytes());
+                v.extend(3000i32.to_be_bytes());
+                v.extend(20u32.to_be_bytes());
+                v.extend(0i32.to_be_bytes());
+            });
+        });
+
+        assert_eq!(parse_moof(&moof, 1000, &mut movie).unwrap(), 5);
+
+        let samples = &movie.tracks[0].samples;
+        assert_eq!(
+            samples,
+            &[
+                Sample {
+                    offset: 1100,
+                    size: 10,
+                    dts: 90000,
+                    composition_time_offset: 3000,
+                    duration: 3000,
+                    sync_point: true,
+                },
+                Sample {
+                    offset: 1110,
+                    size: 20,
+                    dts: 93000,
+                    composition_time_offset: 0,
+                    duration: 3000,
+                    sync_point: false,
+                },
+            ]
+        );
+        assert_eq!(movie.tracks[0].next_decode_time, 96000);
+    }
+
+    #[test]
+    fn test_sidx() {
+        let mut sidx = Vec::new();
+        sidx.extend([0, 0, 0, 0]);
+        sidx.extend(1u32.to_be_bytes());
+        sidx.extend(1000u32.to_be_bytes());
+        sidx.extend(500u32.to_be_bytes());
+        sidx.extend(16u32.to_be_bytes());
+        sidx.extend([0, 0]);
+        sidx.extend(2u16.to_be_bytes());
+        for (size, duration) in [(1000u32, 2000u32), (1500, 2500)] {
+            sidx.extend(size.to_be_bytes());
+            sidx.extend(duration.to_be_bytes());
+            sidx.extend(0x9000_0000u32.to_be_bytes());
+        }
+
+        let sidx = parse_sidx(&sidx, 100).unwrap();
+        assert_eq!(sidx.timescale, 1000);
+        assert_eq!(
+            sidx.references,
+            [
+                SidxReference {
+                    references_sidx: false,
+                    offset: 116,
+                    size: 1000,
+                    earliest_presentation_time: 500,
+                    duration: 2000,
+                    starts_with_sap: true,
+                },
+                SidxReference {
+                    references_sidx: false,
+                    offset: 1116,
+                    size: 1500,
+                    earliest_presentation_time: 2500,
+                    duration: 2500,
+                    starts_with_sap: true,
+                },
+            ]
+        );
+    }
+}
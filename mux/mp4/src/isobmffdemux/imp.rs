@@ -0,0 +1,2410 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::glib;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+
+use std::sync::{LazyLock, Mutex, MutexGuard};
+
+use anyhow::Context;
+
+use super::boxes;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "isobmffdemux",
+        gst::DebugColorFlags::empty(),
+        Some("ISO BMFF Demuxer"),
+    )
+});
+
+/// Top-level boxes other than `mdat` that are larger than this are considered invalid.
+const MAX_BOX_SIZE: u64 = 256 * 1024 * 1024;
+
+const ONVIF_METADATA_NAMESPACE: &str = "http://www.onvif.org/ver10/schema";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamType {
+    Video,
+    Audio,
+    Metadata,
+}
+
+impl StreamType {
+    fn template_name(self) -> &'static str {
+        match self {
+            StreamType::Video => "video_%u",
+            StreamType::Audio => "audio_%u",
+            StreamType::Metadata => "meta_%u",
+        }
+    }
+}
+
+struct Stream {
+    srcpad: gst::Pad,
+    stream_type: StreamType,
+    /// ID of the track this stream is outputting.
+    track_id: u32,
+    caps: gst::Caps,
+    /// Tags to send after the next segment event.
+    tags: Option<gst::TagList>,
+    /// Whether the pad was already added to the element.
+    exposed: bool,
+    /// Index of the next sample of the track to output.
+    sample_idx: usize,
+    need_caps: bool,
+    need_segment: bool,
+    discont: bool,
+    eos: bool,
+    /// Seek target for fragmented files that is applied once the samples are known.
+    seek_target: Option<gst::ClockTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PushState {
+    /// Waiting for the next top-level box header.
+    Header,
+    /// Waiting for the complete content of a top-level box.
+    Box(boxes::BoxHeader),
+    /// Skipping data until the given offset.
+    Skip { end: u64 },
+    /// Outputting samples from a `mdat` that ends at the given offset.
+    Mdat { end: Option<u64> },
+}
+
+/// Seek in push mode that is waiting for upstream to send the new byte segment.
+struct PendingSeek {
+    offset: u64,
+    seqnum: gst::Seqnum,
+    segment: gst::FormattedSegment<gst::ClockTime>,
+    /// Sample index per stream for progressive files, or `None` for fragmented files.
+    sample_indices: Option<Vec<usize>>,
+    target: gst::ClockTime,
+}
+
+struct State {
+    mode: gst::PadMode,
+    /// Offset of the next byte to handle.
+    offset: u64,
+    upstream_size: Option<u64>,
+    movie: Option<boxes::Movie>,
+    streams: Vec<Stream>,
+    /// Data ranges of all `mdat` seen so far.
+    mdats: Vec<(u64, Option<u64>)>,
+    sidx: Option<boxes::Sidx>,
+    tfras: Vec<boxes::Tfra>,
+    mfra_checked: bool,
+    first_moof_offset: Option<u64>,
+    /// End of the file was reached while looking for further boxes.
+    eof: bool,
+
+    adapter: gst_base::UniqueAdapter,
+    push_state: PushState,
+    /// Offset of a `mdat` before the `moov` to seek back to once the `moov` is parsed.
+    mdat_restart_offset: Option<u64>,
+    pending_seek: Option<PendingSeek>,
+
+    segment: gst::FormattedSegment<gst::ClockTime>,
+    /// Upstream provides a time segment, e.g. adaptive streaming demuxers.
+    upstream_time_segment: bool,
+    seqnum: gst::Seqnum,
+    group_id: gst::GroupId,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            mode: gst::PadMode::None,
+            offset: 0,
+            upstream_size: None,
+            movie: None,
+            streams: Vec::new(),
+            mdats: Vec::new(),
+            sidx: None,
+            tfras: Vec::new(),
+            mfra_checked: false,
+            first_moof_offset: None,
+            eof: false,
+            adapter: gst_base::UniqueAdapter::new(),
+            push_state: PushState::Header,
+            mdat_restart_offset: None,
+            pending_seek: None,
+            segment: gst::FormattedSegment::new(),
+            upstream_time_segment: false,
+            seqnum: gst::Seqnum::next(),
+            group_id: gst::GroupId::next(),
+        }
+    }
+}
+
+/// Converts `v` in `timescale` to nanoseconds.
+fn to_ns(v: i64, timescale: u32) -> i64 {
+    v.mul_div_floor(gst::ClockTime::SECOND.nseconds() as i64, timescale as i64)
+        .unwrap_or(if v < 0 { i64::MIN } else { i64::MAX })
+}
+
+/// Converts `ns` nanoseconds to `timescale`.
+fn from_ns(ns: u64, timescale: u32) -> u64 {
+    ns.mul_div_floor(timescale as u64, gst::ClockTime::SECOND.nseconds())
+        .unwrap_or(u64::MAX)
+}
+
+fn ns_to_clock_time(ns: i64) -> Option<gst::ClockTime> {
+    u64::try_from(ns).ok().map(gst::ClockTime::from_nseconds)
+}
+
+/// Returns the decode and presentation time in nanoseconds, and the duration of `sample`
+/// with the edit list applied.
+fn sample_times(
+    movie_timescale: u32,
+    track: &boxes::Track,
+    sample: &boxes::Sample,
+) -> (i64, i64, gst::ClockTime) {
+    let shift = to_ns(track.edit.empty_duration as i64, movie_timescale)
+        - to_ns(track.edit.media_time, track.timescale);
+    let dts = to_ns(sample.dts as i64, track.timescale).saturating_add(shift);
+    let pts = to_ns(
+        (sample.dts as i64).saturating_add(sample.composition_time_offset),
+        track.timescale,
+    )
+    .saturating_add(shift);
+    let duration =
+        gst::ClockTime::from_nseconds(to_ns(sample.duration as i64, track.timescale) as u64);
+
+    (dts, pts, duration)
+}
+
+/// Returns the index of the last sync sample at or before `target`, starting the search at
+/// `start`.
+fn find_sync_sample(
+    movie_timescale: u32,
+    track: &boxes::Track,
+    start: usize,
+    target: gst::ClockTime,
+) -> Option<usize> {
+    let samples = track.samples.get(start..)?;
+
+    // Decode times are increasing so find the first sample that is decoded after the target
+    // and then search backwards for a sync sample that is presented before it.
+    let end = samples.partition_point(|sample| {
+        sample_times(movie_timescale, track, sample).0 <= target.nseconds() as i64
+    });
+
+    samples[..end]
+        .iter()
+        .rposition(|sample| {
+            sample.sync_point
+                && sample_times(movie_timescale, track, sample).1 <= target.nseconds() as i64
+        })
+        .map(|idx| start + idx)
+}
+
+fn framerate(movie: &boxes::Movie, track: &boxes::Track) -> gst::Fraction {
+    // Progressive files know all samples, otherwise use the default sample duration
+    let (duration, count) = if !track.samples.is_empty() && !movie.fragmented {
+        let duration = track
+            .samples
+            .iter()
+            .map(|sample| sample.duration as u64)
+            .sum::<u64>();
+        (duration, track.samples.len() as u64)
+    } else {
+        (track.defaults.sample_duration as u64, 1)
+    };
+
+    let duration = to_ns(duration as i64, track.timescale) as u64 / count;
+    if duration == 0 {
+        return gst::Fraction::new(0, 1);
+    }
+
+    gst_video::guess_framerate(gst::ClockTime::from_nseconds(duration))
+        .unwrap_or_else(|| gst::Fraction::new(0, 1))
+}
+
+fn vp9_caps(
+    builder: gst::caps::Builder<gst::caps::NoFeature>,
+    vpcc: &[u8],
+) -> Result<gst::caps::Builder<gst::caps::NoFeature>, anyhow::Error> {
+    let mut r = boxes::ByteReader::new(vpcc);
+    let (version, _flags) = r.read_full_box_header()?;
+    if version != 1 {
+        anyhow::bail!("Unsupported vpcC version {version}");
+    }
+    let profile = r.read_u8()?;
+    let _level = r.read_u8()?;
+    let byte = r.read_u8()?;
+    let bit_depth = (byte >> 4) as u32;
+    let chroma_subsampling = (byte >> 1) & 0x7;
+    let full_range = byte & 0x1 != 0;
+    let primaries = r.read_u8()?;
+    let transfer = r.read_u8()?;
+    let matrix = r.read_u8()?;
+
+    let (chroma_format, chroma_site) = match chroma_subsampling {
+        0 => ("4:2:0", Some("v-cosited")),
+        1 => ("4:2:0", None),
+        2 => ("4:2:2", None),
+        3 => ("4:4:4", None),
+        _ => anyhow::bail!("Unsupported chroma subsampling {chroma_subsampling}"),
+    };
+
+    let colorimetry = gst_video::VideoColorimetry::new(
+        if full_range {
+            gst_video::VideoColorRange::Range0_255
+        } else {
+            gst_video::VideoColorRange::Range16_235
+        },
+        gst_video::VideoColorMatrix::from_iso(matrix as u32),
+        gst_video::VideoTransferFunction::from_iso(transfer as u32),
+        gst_video::VideoColorPrimaries::from_iso(primaries as u32),
+    );
+
+    Ok(builder
+        .field("profile", profile.to_string())
+        .field("chroma-format", chroma_format)
+        .field_if_some("chroma-site", chroma_site)
+        .field("bit-depth-luma", bit_depth)
+        .field("bit-depth-chroma", bit_depth)
+        .field("colorimetry", colorimetry.to_string()))
+}
+
+fn av1_caps(av1c: &[u8]) -> Result<gst::caps::Builder<gst::caps::NoFeature>, anyhow::Error> {
+    if av1c.len() < 4 || av1c[0] != 0x81 {
+        anyhow::bail!("Invalid av1C");
+    }
+
+    let profile = match av1c[1] >> 5 {
+        0 => "main",
+        1 => "high",
+        2 => "professional",
+        profile => anyhow::bail!("Unsupported AV1 profile {profile}"),
+    };
+    let high_bitdepth = av1c[2] & 0x40 != 0;
+    let twelve_bit = av1c[2] & 0x20 != 0;
+    let monochrome = av1c[2] & 0x10 != 0;
+    let chroma_subsampling_x = av1c[2] & 0x08 != 0;
+    let chroma_subsampling_y = av1c[2] & 0x04 != 0;
+
+    let bit_depth = match (high_bitdepth, twelve_bit) {
+        (false, _) => 8u32,
+        (true, false) => 10,
+        (true, true) => 12,
+    };
+    let chroma_format = match (monochrome, chroma_subsampling_x, chroma_subsampling_y) {
+        (true, _, _) => "4:0:0",
+        (false, true, true) => "4:2:0",
+        (false, true, false) => "4:2:2",
+        (false, false, false) => "4:4:4",
+        (false, false, true) => anyhow::bail!("Invalid AV1 chroma subsampling"),
+    };
+
+    Ok(gst::Caps::builder("video/x-av1")
+        .field("stream-format", "obu-stream")
+        .field("alignment", "tu")
+        .field("profile", profile)
+        .field("chroma-format", chroma_format)
+        .field("bit-depth-luma", bit_depth)
+        .field("bit-depth-chroma", bit_depth)
+        .field("codec_data", gst::Buffer::from_slice(av1c.to_vec())))
+}
+
+fn esds_caps(esds: &[u8], channels: u16, sample_rate: u32) -> Result<gst::Caps, anyhow::Error> {
+    let es = boxes::parse_esds(esds).context("esds")?;
+
+    let mut builder = match es.object_type_indication {
+        0x40 | 0x66 | 0x67 | 0x68 => {
+            let mut builder = gst::Caps::builder("audio/mpeg")
+                .field("mpegversion", 4i32)
+                .field("stream-format", "raw")
+                .field("framed", true);
+
+            let (rate, channels) = match es.decoder_specific_info {
+                Some(ref asc) => {
+                    let (rate, asc_channels) = boxes::parse_aac_audio_specific_config(asc)
+                        .context("AudioSpecificConfig")?;
+                    builder = builder.field("codec_data", gst::Buffer::from_slice(asc.clone()));
+                    (
+                        rate,
+                        if asc_channels == 0 {
+                            channels as u32
+                        } else {
+                            asc_channels
+                        },
+                    )
+                }
+                None => (sample_rate, channels as u32),
+            };
+
+            builder
+                .field("rate", rate as i32)
+                .field("channels", channels as i32)
+        }
+        0x69 | 0x6b => gst::Caps::builder("audio/mpeg")
+            .field("mpegversion", 1i32)
+            .field("layer", 3i32)
+            .field("parsed", true)
+            .field("rate", sample_rate as i32)
+            .field("channels", channels as i32),
+        object_type => anyhow::bail!("Unsupported object type indication {object_type:#x}"),
+    };
+
+    if es.avg_bitrate != 0 {
+        builder = builder.field("bitrate", es.avg_bitrate as i32);
+    }
+
+    Ok(builder.build())
+}
+
+fn opus_caps(dops: &[u8]) -> Result<gst::Caps, anyhow::Error> {
+    let dops = boxes::parse_dops(dops).context("dOps")?;
+
+    // Reconstruct the Ogg Opus headers from the dOps
+    let mut head = Vec::with_capacity(19 + 2 + dops.channel_mapping.len());
+    head.extend(b"OpusHead");
+    head.push(1);
+    head.push(dops.output_channel_count);
+    head.extend(dops.pre_skip.to_le_bytes());
+    head.extend(dops.input_sample_rate.to_le_bytes());
+    head.extend(dops.output_gain.to_le_bytes());
+    head.push(dops.channel_mapping_family);
+    if dops.channel_mapping_family != 0 {
+        head.push(dops.stream_count);
+        head.push(dops.coupled_count);
+        head.extend(&dops.channel_mapping);
+    }
+
+    let vendor = b"isobmffdemux";
+    let mut tags = Vec::with_capacity(8 + 4 + vendor.len() + 4);
+    tags.extend(b"OpusTags");
+    tags.extend((vendor.len() as u32).to_le_bytes());
+    tags.extend(vendor);
+    tags.extend(0u32.to_le_bytes());
+
+    let mut head = gst::Buffer::from_mut_slice(head);
+    head.get_mut().unwrap().set_flags(gst::BufferFlags::HEADER);
+    let mut tags = gst::Buffer::from_mut_slice(tags);
+    tags.get_mut().unwrap().set_flags(gst::BufferFlags::HEADER);
+
+    let mut builder = gst::Caps::builder("audio/x-opus")
+        .field("rate", dops.input_sample_rate as i32)
+        .field("channels", dops.output_channel_count as i32)
+        .field("channel-mapping-family", dops.channel_mapping_family as i32);
+    if dops.channel_mapping_family != 0 {
+        builder = builder
+            .field("stream-count", dops.stream_count as i32)
+            .field("coupled-count", dops.coupled_count as i32)
+            .field(
+                "channel-mapping",
+                gst::Array::new(dops.channel_mapping.iter().map(|c| *c as i32)),
+            );
+    }
+
+    Ok(builder
+        .field("streamheader", gst::Array::new([head, tags]))
+        .build())
+}
+
+fn flac_caps(dfla: &[u8]) -> Result<gst::Caps, anyhow::Error> {
+    let mut r = boxes::ByteReader::new(dfla);
+    let _ = r.read_full_box_header()?;
+
+    // Split into the individual metadata blocks
+    let mut blocks = Vec::new();
+    while r.remaining() > 0 {
+        let header = r.read_bytes(4)?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let data = r.read_bytes(len)?;
+        let mut block = Vec::with_capacity(4 + len);
+        block.extend(header);
+        block.extend(data);
+        blocks.push(block);
+    }
+
+    let Some((streaminfo, remainder)) = blocks.split_first() else {
+        anyhow::bail!("No FLAC metadata blocks");
+    };
+    let (rate, channels) = boxes::parse_flac_streaminfo(streaminfo).context("STREAMINFO")?;
+
+    // First header in the Ogg FLAC mapping format, followed by the other metadata blocks
+    let mut first = Vec::with_capacity(13 + streaminfo.len());
+    first.extend(b"\x7FFLAC\x01\x00");
+    first.extend((remainder.len() as u16).to_be_bytes());
+    first.extend(b"fLaC");
+    first.extend(streaminfo);
+
+    let streamheader = std::iter::once(first)
+        .chain(remainder.iter().cloned())
+        .map(|header| {
+            let mut buffer = gst::Buffer::from_mut_slice(header);
+            buffer
+                .get_mut()
+                .unwrap()
+                .set_flags(gst::BufferFlags::HEADER);
+            buffer
+        });
+
+    Ok(gst::Caps::builder("audio/x-flac")
+        .field("framed", true)
+        .field("rate", rate as i32)
+        .field("channels", channels as i32)
+        .field("streamheader", gst::Array::new(streamheader))
+        .build())
+}
+
+/// Creates the caps for the track, or `None` if the track is not supported.
+fn track_caps(
+    movie: &boxes::Movie,
+    track: &boxes::Track,
+) -> Result<Option<(StreamType, gst::Caps)>, anyhow::Error> {
+    let Some(entry) = track.sample_entry.as_ref() else {
+        return Ok(None);
+    };
+
+    match entry.kind {
+        boxes::SampleEntryKind::Visual { width, height } => {
+            let mut builder = match &entry.fourcc {
+                b"avc1" | b"avc3" => {
+                    let avcc = entry.find_box(b"avcC").context("No avcC box")?;
+                    gst::Caps::builder("video/x-h264")
+                        .field(
+                            "stream-format",
+                            if &entry.fourcc == b"avc1" {
+                                "avc"
+                            } else {
+                                "avc3"
+                            },
+                        )
+                        .field("alignment", "au")
+                        .field("codec_data", gst::Buffer::from_slice(avcc.to_vec()))
+                }
+                b"hvc1" | b"hev1" => {
+                    let hvcc = entry.find_box(b"hvcC").context("No hvcC box")?;
+                    gst::Caps::builder("video/x-h265")
+                        .field(
+                            "stream-format",
+                            if &entry.fourcc == b"hvc1" {
+                                "hvc1"
+                            } else {
+                                "hev1"
+                            },
+                        )
+                        .field("alignment", "au")
+                        .field("codec_data", gst::Buffer::from_slice(hvcc.to_vec()))
+                }
+                b"vp08" => gst::Caps::builder("video/x-vp8"),
+                b"vp09" => {
+                    let builder = gst::Caps::builder("video/x-vp9");
+                    match entry.find_box(b"vpcC") {
+                        Some(vpcc) => vp9_caps(builder, vpcc).context("vpcC")?,
+                        None => builder,
+                    }
+                }
+                b"av01" => {
+                    let av1c = entry.find_box(b"av1C").context("No av1C box")?;
+                    av1_caps(av1c).context("av1C")?
+                }
+                b"jpeg" => gst::Caps::builder("image/jpeg").field("parsed", true),
+                _ => return Ok(None),
+            };
+
+            builder = builder
+                .field("width", width as i32)
+                .field("height", height as i32)
+                .field("framerate", framerate(movie, track));
+
+            if let Some(pasp) = entry.find_box(b"pasp") {
+                let mut r = boxes::ByteReader::new(pasp);
+                let h_spacing = r.read_u32()?;
+                let v_spacing = r.read_u32()?;
+                if h_spacing != 0 && v_spacing != 0 {
+                    builder = builder.field(
+                        "pixel-aspect-ratio",
+                        gst::Fraction::new(h_spacing as i32, v_spacing as i32),
+                    );
+                }
+            }
+
+            Ok(Some((StreamType::Video, builder.build())))
+        }
+        boxes::SampleEntryKind::Audio {
+            channels,
+            sample_size,
+            sample_rate,
+        } => {
+            let caps = match &entry.fourcc {
+                b"mp4a" => {
+                    let esds = entry.find_box(b"esds").context("No esds box")?;
+                    esds_caps(esds, channels, sample_rate)?
+                }
+                b"Opus" => {
+                    let dops = entry.find_box(b"dOps").context("No dOps box")?;
+                    opus_caps(dops)?
+                }
+                b"fLaC" => {
+                    let dfla = entry.find_box(b"dfLa").context("No dfLa box")?;
+                    flac_caps(dfla)?
+                }
+                b"ac-3" => {
+                    let dac3 = entry.find_box(b"dac3").context("No dac3 box")?;
+                    let (rate, channels) = boxes::parse_dac3(dac3).context("dac3")?;
+                    gst::Caps::builder("audio/x-ac3")
+                        .field("framed", true)
+                        .field("alignment", "frame")
+                        .field("rate", rate as i32)
+                        .field("channels", channels as i32)
+                        .build()
+                }
+                b"ec-3" => {
+                    let dec3 = entry.find_box(b"dec3").context("No dec3 box")?;
+                    let (rate, channels) = boxes::parse_dec3(dec3).context("dec3")?;
+                    gst::Caps::builder("audio/x-eac3")
+                        .field("framed", true)
+                        .field("alignment", "iec61937")
+                        .field("rate", rate as i32)
+                        .field("channels", channels as i32)
+                        .build()
+                }
+                b"alaw" | b"ulaw" => gst::Caps::builder(if &entry.fourcc == b"alaw" {
+                    "audio/x-alaw"
+                } else {
+                    "audio/x-mulaw"
+                })
+                .field("rate", sample_rate as i32)
+                .field("channels", channels as i32)
+                .build(),
+                b"ms\x00\x45" => gst::Caps::builder("audio/x-adpcm")
+                    .field("layout", "g726")
+                    .field("rate", 8000i32)
+                    .field("channels", 1i32)
+                    .field("bitrate", sample_size as i32 * 8000)
+                    .build(),
+                _ => return Ok(None),
+            };
+
+            Ok(Some((StreamType::Audio, caps)))
+        }
+        boxes::SampleEntryKind::XmlMetadata { ref namespace, .. } => {
+            if namespace
+                .split(' ')
+                .any(|namespace| namespace == ONVIF_METADATA_NAMESPACE)
+            {
+                Ok(Some((
+                    StreamType::Metadata,
+                    gst::Caps::builder("application/x-onvif-metadata")
+                        .field("parsed", true)
+                        .build(),
+                )))
+            } else {
+                Ok(None)
+            }
+        }
+        boxes::SampleEntryKind::Other => Ok(None),
+    }
+}
+
+pub struct ISOBMFFDemux {
+    sinkpad: gst::Pad,
+    state: Mutex<State>,
+    flow_combiner: Mutex<gst_base::UniqueFlowCombiner>,
+}
+
+impl ISOBMFFDemux {
+    fn sink_activate(&self, pad: &gst::Pad) -> Result<(), gst::LoggableError> {
+        let mode = {
+            let mut query = gst::query::Scheduling::new();
+
+            if !pad.peer_query(&mut query) {
+                gst::debug!(CAT, obj = pad, "Scheduling query failed on peer");
+                gst::PadMode::Push
+            } else if query
+                .has_scheduling_mode_with_flags(gst::PadMode::Pull, gst::SchedulingFlags::SEEKABLE)
+            {
+                gst::debug!(CAT, obj = pad, "Activating in Pull mode");
+                gst::PadMode::Pull
+            } else {
+                gst::debug!(CAT, obj = pad, "Activating in Push mode");
+                gst::PadMode::Push
+            }
+        };
+
+        pad.activate_mode(mode, true)?;
+        Ok(())
+    }
+
+    fn sink_activatemode(
+        &self,
+        _pad: &gst::Pad,
+        mode: gst::PadMode,
+        active: bool,
+    ) -> Result<(), gst::LoggableError> {
+        if active {
+            {
+                let mut state = self.state.lock().unwrap();
+                *state = State::default();
+                state.mode = mode;
+            }
+
+            if mode == gst::PadMode::Pull {
+                self.start_task()?;
+            }
+        } else {
+            if mode == gst::PadMode::Pull {
+                let _ = self.sinkpad.stop_task();
+            }
+
+            self.stop();
+        }
+
+        Ok(())
+    }
+
+    fn start_task(&self) -> Result<(), gst::LoggableError> {
+        let self_ = self.ref_counted();
+        let res = self.sinkpad.start_task(move || {
+            self_.loop_fn();
+        });
+        if res.is_err() {
+            return Err(gst::loggable_error!(CAT, "Failed to start pad task"));
+        }
+        Ok(())
+    }
+
+    fn stop(&self) {
+        let streams = std::mem::take(&mut self.state.lock().unwrap().streams);
+        *self.state.lock().unwrap() = State::default();
+
+        let mut flow_combiner = self.flow_combiner.lock().unwrap();
+        for stream in streams {
+            if stream.exposed {
+                let _ = self.obj().remove_pad(&stream.srcpad);
+            }
+            flow_combiner.remove_pad(&stream.srcpad);
+        }
+        flow_combiner.reset();
+    }
+
+    fn loop_fn(&self) {
+        let Err(flow) = self.pull_iterate() else {
+            return;
+        };
+
+        match flow {
+            gst::FlowError::Flushing => {
+                gst::debug!(CAT, imp = self, "Pausing after flow {:?}", flow);
+            }
+            gst::FlowError::Eos => {
+                self.push_eos();
+
+                gst::debug!(CAT, imp = self, "Pausing after flow {:?}", flow);
+            }
+            gst::FlowError::Error => {
+                // Error message was already posted
+                self.push_eos();
+
+                gst::error!(CAT, imp = self, "Pausing after flow {:?}", flow);
+            }
+            _ => {
+                self.push_eos();
+
+                gst::error!(CAT, imp = self, "Pausing after flow {:?}", flow);
+
+                gst::element_imp_error!(
+                    self,
+                    gst::StreamError::Failed,
+                    ["Streaming stopped, reason: {:?}", flow]
+                );
+            }
+        }
+
+        let _ = self.sinkpad.pause_task();
+    }
+
+    fn push_eos(&self) {
+        let state = self.state.lock().unwrap();
+        let seqnum = state.seqnum;
+        let pads = state
+            .streams
+            .iter()
+            .filter(|stream| stream.exposed)
+            .map(|stream| stream.srcpad.clone())
+            .collect::<Vec<_>>();
+        drop(state);
+
+        for pad in pads {
+            pad.push_event(gst::event::Eos::builder().seqnum(seqnum).build());
+        }
+    }
+
+    /// Pulls `size` bytes at `offset` from upstream, failing with EOS on short reads.
+    fn pull_exact(
+        &self,
+        offset: u64,
+        size: u64,
+    ) -> Result<gst::MappedBuffer<gst::buffer::Readable>, gst::FlowError> {
+        let size = u32::try_from(size).map_err(|_| gst::FlowError::Error)?;
+        let buffer = self.sinkpad.pull_range(offset, size)?;
+        if buffer.size() < size as usize {
+            return Err(gst::FlowError::Eos);
+        }
+
+        buffer
+            .into_mapped_buffer_readable()
+            .map_err(|_| gst::FlowError::Error)
+    }
+
+    fn pull_iterate(&self) -> Result<(), gst::FlowError> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.upstream_size.is_none() {
+            state.upstream_size = self
+                .sinkpad
+                .peer_query_duration::<gst::format::Bytes>()
+                .map(|size| *size);
+        }
+
+        if state.movie.is_none() || self.need_fragment(&state) {
+            return self.pull_next_box(state);
+        }
+
+        let Some(stream_idx) = self.next_stream_by_time(&mut state) else {
+            return Err(gst::FlowError::Eos);
+        };
+
+        let stream = &state.streams[stream_idx];
+        let track = state
+            .movie
+            .as_ref()
+            .unwrap()
+            .tracks
+            .iter()
+            .find(|track| track.track_id == stream.track_id)
+            .unwrap();
+        let sample = track.samples[stream.sample_idx];
+
+        let buffer = match self.pull_exact(sample.offset, sample.size as u64) {
+            Ok(buffer) => buffer.into_buffer(),
+            Err(gst::FlowError::Eos) => {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Sample at offset {} is truncated",
+                    sample.offset
+                );
+                return Err(gst::FlowError::Eos);
+            }
+            Err(err) => return Err(err),
+        };
+
+        state.streams[stream_idx].sample_idx += 1;
+
+        self.push_sample(state, stream_idx, sample, buffer)
+            .map(|_| ())
+    }
+
+    /// Checks if a stream in a fragmented file has run out of samples and more fragments have
+    /// to be parsed.
+    fn need_fragment(&self, state: &State) -> bool {
+        let movie = state.movie.as_ref().unwrap();
+        if !movie.fragmented || state.eof {
+            return false;
+        }
+
+        state.streams.iter().any(|stream| {
+            let track = movie
+                .tracks
+                .iter()
+                .find(|track| track.track_id == stream.track_id)
+                .unwrap();
+            !stream.eos && stream.sample_idx >= track.samples.len()
+        })
+    }
+
+    /// Selects the stream with the earliest next sample. Streams that are after the segment
+    /// stop or have no samples left are marked as EOS.
+    fn next_stream_by_time(&self, state: &mut State) -> Option<usize> {
+        let State {
+            ref movie,
+            ref mut streams,
+            ref segment,
+            ..
+        } = *state;
+        let movie = movie.as_ref().unwrap();
+
+        let mut earliest = None;
+        for (idx, stream) in streams.iter_mut().enumerate() {
+            if stream.eos {
+                continue;
+            }
+
+            let track = movie
+                .tracks
+                .iter()
+                .find(|track| track.track_id == stream.track_id)
+                .unwrap();
+            let Some(sample) = track.samples.get(stream.sample_idx) else {
+                gst::debug!(CAT, obj = stream.srcpad, "Reached end of track");
+                stream.eos = true;
+                continue;
+            };
+
+            let (dts, pts, _duration) = sample_times(movie.timescale, track, sample);
+            if let (Some(stop), Some(pts)) = (segment.stop(), ns_to_clock_time(pts)) {
+                if pts >= stop {
+                    gst::debug!(CAT, obj = stream.srcpad, "Reached segment stop");
+                    stream.eos = true;
+                    continue;
+                }
+            }
+
+            if earliest.is_none_or(|(_, earliest_dts)| dts < earliest_dts) {
+                earliest = Some((idx, dts));
+            }
+        }
+
+        earliest.map(|(idx, _)| idx)
+    }
+
+    fn pull_next_box(&self, mut state: MutexGuard<State>) -> Result<(), gst::FlowError> {
+        let offset = state.offset;
+
+        let header = if state.upstream_size.is_some_and(|size| offset >= size) {
+            None
+        } else {
+            let buffer = match self
+                .sinkpad
+                .pull_range(offset, boxes::MAX_BOX_HEADER_SIZE as u32)
+            {
+                Ok(buffer) => buffer,
+                Err(gst::FlowError::Eos) => gst::Buffer::new(),
+                Err(err) => return Err(err),
+            };
+            let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
+
+            match boxes::parse_box_header(&map) {
+                Ok(header) => header,
+                Err(err) => {
+                    gst::warning!(
+                        CAT,
+                        imp = self,
+                        "Invalid box header at offset {offset}: {err}"
+                    );
+                    None
+                }
+            }
+        };
+
+        let Some(header) = header else {
+            if state.movie.is_none() {
+                drop(state);
+                gst::element_imp_error!(self, gst::StreamError::Demux, ["No moov box found"]);
+                return Err(gst::FlowError::Error);
+            }
+
+            gst::debug!(CAT, imp = self, "Reached end of file at offset {offset}");
+            state.eof = true;
+            return Ok(());
+        };
+
+        let size = header
+            .size
+            .or_else(|| state.upstream_size.map(|size| size - offset));
+
+        gst::trace!(
+            CAT,
+            imp = self,
+            "Found box '{}' at offset {offset} with size {size:?}",
+            String::from_utf8_lossy(&header.fourcc),
+        );
+
+        match &header.fourcc {
+            b"moov" | b"moof" | b"sidx" => {
+                let size = match size {
+                    Some(size) if size <= MAX_BOX_SIZE => size,
+                    _ => {
+                        drop(state);
+                        gst::element_imp_error!(
+                            self,
+                            gst::StreamError::Demux,
+                            [
+                                "Invalid '{}' box size {size:?}",
+                                String::from_utf8_lossy(&header.fourcc)
+                            ]
+                        );
+                        return Err(gst::FlowError::Error);
+                    }
+                };
+
+                let data = match self.pull_exact(offset, size) {
+                    Ok(data) => data,
+                    Err(gst::FlowError::Eos) if state.movie.is_some() => {
+                        gst::warning!(CAT, imp = self, "Truncated box at offset {offset}");
+                        state.eof = true;
+                        return Ok(());
+                    }
+                    Err(err) => return Err(err),
+                };
+                state.offset = offset + size;
+
+                let new_streams = match self.handle_box(
+                    &mut state,
+                    &header,
+                    offset,
+                    &data[header.header_size as usize..],
+                ) {
+                    Ok(new_streams) => new_streams,
+                    Err(err) => {
+                        drop(state);
+                        self.post_error_message(err);
+                        return Err(gst::FlowError::Error);
+                    }
+                };
+
+                if &header.fourcc == b"moov" && !state.mfra_checked {
+                    state.mfra_checked = true;
+                    if state.movie.as_ref().unwrap().fragmented {
+                        self.pull_mfra(&mut state);
+                    }
+                }
+
+                if new_streams {
+                    drop(state);
+                    self.expose_streams();
+                }
+            }
+            b"mdat" => {
+                let data_start = offset + header.header_size;
+                let end = size.map(|size| offset + size);
+                if !state.mdats.iter().any(|(start, _)| *start == data_start) {
+                    state.mdats.push((data_start, end));
+                }
+
+                match end {
+                    Some(end) => state.offset = end,
+                    None => state.eof = true,
+                }
+            }
+            _ => match size {
+                Some(size) => state.offset = offset + size,
+                None => state.eof = true,
+            },
+        }
+
+        if state.eof && state.movie.is_none() {
+            drop(state);
+            gst::element_imp_error!(self, gst::StreamError::Demux, ["No moov box found"]);
+            return Err(gst::FlowError::Error);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the `mfra` at the end of the file, if any.
+    fn pull_mfra(&self, state: &mut State) {
+        let Some(size) = state.upstream_size.filter(|size| *size >= 16) else {
+            return;
+        };
+
+        let Ok(mfro) = self.pull_exact(size - 16, 16) else {
+            return;
+        };
+        let mfra_size = match boxes::parse_box_header(&mfro) {
+            Ok(Some(header)) if &header.fourcc == b"mfro" => match boxes::parse_mfro(&mfro[8..]) {
+                Ok(mfra_size) => mfra_size as u64,
+                Err(_) => return,
+            },
+            _ => return,
+        };
+        if mfra_size < 16 || mfra_size > size || mfra_size > MAX_BOX_SIZE {
+            return;
+        }
+
+        let Ok(mfra) = self.pull_exact(size - mfra_size, mfra_size) else {
+            return;
+        };
+        match boxes::parse_box_header(&mfra) {
+            Ok(Some(header)) if &header.fourcc == b"mfra" => {
+                match boxes::parse_mfra(&mfra[header.header_size as usize..]) {
+                    Ok(tfras) => {
+                        gst::debug!(CAT, imp = self, "Found mfra with {} tracks", tfras.len());
+                        state.tfras = tfras;
+                    }
+                    Err(err) => {
+                        gst::warning!(CAT, imp = self, "Failed to parse mfra: {err:?}");
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Handles the content of a complete top-level box. Returns `true` if new streams were
+    /// created that have to be exposed.
+    fn handle_box(
+        &self,
+        state: &mut State,
+        header: &boxes::BoxHeader,
+        offset: u64,
+        content: &[u8],
+    ) -> Result<bool, gst::ErrorMessage> {
+        match &header.fourcc {
+            b"moov" => {
+                let movie = boxes::parse_moov(content).map_err(|err| {
+                    gst::error_msg!(gst::StreamError::Demux, ["Failed to parse moov: {err:?}"])
+                })?;
+
+                gst::debug!(
+                    CAT,
+                    imp = self,
+                    "Parsed moov with {} tracks, fragmented {}",
+                    movie.tracks.len(),
+                    movie.fragmented
+                );
+
+                if state.movie.is_some() {
+                    self.update_movie(state, movie);
+                    Ok(false)
+                } else {
+                    state.movie = Some(movie);
+                    self.create_streams(state)?;
+                    Ok(true)
+                }
+            }
+            b"moof" => {
+                let Some(movie) = state.movie.as_mut() else {
+                    return Err(gst::error_msg!(
+                        gst::StreamError::Demux,
+                        ["moof before moov"]
+                    ));
+                };
+
+                // Forget about all samples that were already output
+                for stream in &mut state.streams {
+                    if let Some(track) = movie.track_mut(stream.track_id) {
+                        track
+                            .samples
+                            .drain(..stream.sample_idx.min(track.samples.len()));
+                    }
+                    stream.sample_idx = 0;
+                }
+
+                let sequence_number = boxes::parse_moof(content, offset, movie).map_err(|err| {
+                    gst::error_msg!(gst::StreamError::Demux, ["Failed to parse moof: {err:?}"])
+                })?;
+                gst::trace!(
+                    CAT,
+                    imp = self,
+                    "Parsed moof with sequence number {sequence_number} at offset {offset}"
+                );
+
+                if state.first_moof_offset.is_none() {
+                    state.first_moof_offset = Some(offset);
+                }
+
+                self.apply_fragment_seek_targets(state);
+
+                Ok(false)
+            }
+            b"sidx" => {
+                if state.sidx.is_none() {
+                    let end_offset = offset + header.header_size + content.len() as u64;
+                    match boxes::parse_sidx(content, end_offset) {
+                        Ok(sidx) => {
+                            gst::debug!(
+                                CAT,
+                                imp = self,
+                                "Found sidx with {} references",
+                                sidx.references.len()
+                            );
+                            state.sidx = Some(sidx);
+                        }
+                        Err(err) => {
+                            gst::warning!(CAT, imp = self, "Failed to parse sidx: {err:?}");
+                        }
+                    }
+                }
+
+                Ok(false)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn create_streams(&self, state: &mut State) -> Result<(), gst::ErrorMessage> {
+        let movie = state.movie.as_ref().unwrap();
+        let obj = self.obj();
+        let mut counts = [0u32; 3];
+
+        for track in &movie.tracks {
+            let (stream_type, caps) = match track_caps(movie, track) {
+                Ok(Some(res)) => res,
+                Ok(None) => {
+                    gst::warning!(
+                        CAT,
+                        imp = self,
+                        "Ignoring unsupported track {} of type '{}' with sample entry {:?}",
+                        track.track_id,
+                        String::from_utf8_lossy(&track.handler_type),
+                        track
+                            .sample_entry
+                            .as_ref()
+                            .map(|entry| String::from_utf8_lossy(&entry.fourcc).into_owned()),
+                    );
+                    continue;
+                }
+                Err(err) => {
+                    gst::warning!(
+                        CAT,
+                        imp = self,
+                        "Ignoring track {} with invalid sample entry: {err:?}",
+                        track.track_id,
+                    );
+                    continue;
+                }
+            };
+
+            gst::debug!(
+                CAT,
+                imp = self,
+                "Creating stream for track {} with caps {caps:?}",
+                track.track_id,
+            );
+
+            let count = &mut counts[stream_type as usize];
+            let name = stream_type
+                .template_name()
+                .replace("%u", &count.to_string());
+            *count += 1;
+
+            let templ = obj
+                .element_class()
+                .pad_template(stream_type.template_name())
+                .unwrap();
+            let srcpad = gst::Pad::builder_from_template(&templ)
+                .name(name.as_str())
+                .event_function(|pad, parent, event| {
+                    ISOBMFFDemux::catch_panic_pad_function(
+                        parent,
+                        || false,
+                        |demux| demux.src_event(pad, event),
+                    )
+                })
+                .query_function(|pad, parent, query| {
+                    ISOBMFFDemux::catch_panic_pad_function(
+                        parent,
+                        || false,
+                        |demux| demux.src_query(pad, query),
+                    )
+                })
+                .build();
+
+            let tags = track.language.and_then(|language| {
+                let language = std::str::from_utf8(&language)
+                    .ok()
+                    .filter(|language| *language != "und")?;
+                let mut tags = gst::TagList::new();
+                {
+                    let tags = tags.get_mut().unwrap();
+                    tags.add::<gst::tags::LanguageCode>(&language, gst::TagMergeMode::Replace);
+                    tags.set_scope(gst::TagScope::Stream);
+                }
+                Some(tags)
+            });
+
+            state.streams.push(Stream {
+                srcpad,
+                stream_type,
+                track_id: track.track_id,
+                caps,
+                tags,
+                exposed: false,
+                sample_idx: 0,
+                need_caps: false,
+                need_segment: true,
+                discont: true,
+                eos: false,
+                seek_target: None,
+            });
+        }
+
+        if state.streams.is_empty() {
+            return Err(gst::error_msg!(
+                gst::StreamError::Demux,
+                ["No supported streams found"]
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Updates the movie from a new `moov`, e.g. after a representation switch of an adaptive
+    /// streaming demuxer.
+    fn update_movie(&self, state: &mut State, movie: boxes::Movie) {
+        for stream in &mut state.streams {
+            let Some(track) = movie
+                .tracks
+                .iter()
+                .find(|track| track.track_id == stream.track_id)
+            else {
+                gst::warning!(
+                    CAT,
+                    obj = stream.srcpad,
+                    "Track {} disappeared",
+                    stream.track_id
+                );
+                continue;
+            };
+
+            match track_caps(&movie, track) {
+                Ok(Some((stream_type, caps))) if stream_type == stream.stream_type => {
+                    if caps != stream.caps {
+                        gst::debug!(CAT, obj = stream.srcpad, "Caps changed to {caps:?}");
+                        stream.caps = caps;
+                        stream.need_caps = true;
+                    }
+                }
+                _ => {
+                    gst::warning!(
+                        CAT,
+                        obj = stream.srcpad,
+                        "Track {} changed to an incompatible format",
+                        stream.track_id
+                    );
+                }
+            }
+            stream.sample_idx = 0;
+        }
+
+        state.movie = Some(movie);
+    }
+
+    fn expose_streams(&self) {
+        let mut state = self.state.lock().unwrap();
+        let group_id = state.group_id;
+        let streams = state
+            .streams
+            .iter_mut()
+            .filter(|stream| !stream.exposed)
+            .map(|stream| {
+                stream.exposed = true;
+                (stream.srcpad.clone(), stream.track_id, stream.caps.clone())
+            })
+            .collect::<Vec<_>>();
+        drop(state);
+
+        let obj = self.obj();
+        for (srcpad, track_id, caps) in streams {
+            srcpad.set_active(true).unwrap();
+
+            let stream_id = srcpad.create_stream_id(&*obj, Some(&track_id.to_string()));
+            srcpad.push_event(
+                gst::event::StreamStart::builder(&stream_id)
+                    .group_id(group_id)
+                    .build(),
+            );
+            srcpad.push_event(gst::event::Caps::new(&caps));
+
+            self.flow_combiner.lock().unwrap().add_pad(&srcpad);
+            obj.add_pad(&srcpad).unwrap();
+        }
+
+        obj.no_more_pads();
+    }
+
+    /// Outputs `buffer` for the next `sample` of the stream, sending any pending events first.
+    fn push_sample(
+        &self,
+        mut state: MutexGuard<State>,
+        stream_idx: usize,
+        sample: boxes::Sample,
+        mut buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let movie = state.movie.as_ref().unwrap();
+        let stream = &state.streams[stream_idx];
+        let track = movie
+            .tracks
+            .iter()
+            .find(|track| track.track_id == stream.track_id)
+            .unwrap();
+        let (dts, pts, duration) = sample_times(movie.timescale, track, &sample);
+        let (dts, pts) = (ns_to_clock_time(dts), ns_to_clock_time(pts));
+
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_offset(sample.offset);
+            buffer.set_dts(dts);
+            buffer.set_pts(pts);
+            buffer.set_duration(duration);
+            if !sample.sync_point {
+                buffer.set_flags(gst::BufferFlags::DELTA_UNIT);
+            }
+            if stream.discont {
+                buffer.set_flags(gst::BufferFlags::DISCONT);
+            }
+        }
+
+        if let Some(position) = pts.or(dts) {
+            if !state.upstream_time_segment
+                && state
+                    .segment
+                    .position()
+                    .is_none_or(|current| position > current)
+            {
+                state.segment.set_position(position);
+            }
+        }
+
+        let seqnum = state.seqnum;
+        let segment = state.segment.clone();
+        let stream = &mut state.streams[stream_idx];
+        stream.discont = false;
+
+        let mut events = Vec::new();
+        if stream.need_caps {
+            stream.need_caps = false;
+            events.push(gst::event::Caps::new(&stream.caps));
+        }
+        if stream.need_segment {
+            stream.need_segment = false;
+            events.push(
+                gst::event::Segment::builder(&segment)
+                    .seqnum(seqnum)
+                    .build(),
+            );
+        }
+        if let Some(tags) = stream.tags.take() {
+            events.push(gst::event::Tag::new(tags));
+        }
+
+        let srcpad = stream.srcpad.clone();
+        drop(state);
+
+        for event in events {
+            srcpad.push_event(event);
+        }
+
+        gst::trace!(CAT, obj = srcpad, "Pushing buffer {buffer:?}");
+        let res = srcpad.push(buffer);
+
+        self.flow_combiner
+            .lock()
+            .unwrap()
+            .update_pad_flow(&srcpad, res)
+    }
+
+    /// Applies pending seek targets of fragmented files once the samples around them are known.
+    fn apply_fragment_seek_targets(&self, state: &mut State) {
+        let movie = state.movie.as_ref().unwrap();
+
+        for stream in &mut state.streams {
+            let Some(target) = stream.seek_target else {
+                continue;
+            };
+            let track = movie
+                .tracks
+                .iter()
+                .find(|track| track.track_id == stream.track_id)
+                .unwrap();
+            let Some(last) = track.samples.last() else {
+                continue;
+            };
+
+            if let Some(idx) = find_sync_sample(movie.timescale, track, stream.sample_idx, target) {
+                stream.sample_idx = idx;
+            }
+
+            // If the target is not inside this fragment skip the whole fragment
+            let (dts, _pts, duration) = sample_times(movie.timescale, track, last);
+            if dts.saturating_add(duration.nseconds() as i64) <= target.nseconds() as i64 {
+                stream.sample_idx = track.samples.len();
+            } else {
+                gst::debug!(
+                    CAT,
+                    obj = stream.srcpad,
+                    "Starting at sample {} for seek target {target}",
+                    stream.sample_idx
+                );
+                stream.seek_target = None;
+            }
+        }
+    }
+
+    /// Finds the position in the file to seek to for `target`.
+    ///
+    /// For progressive files this returns the offset of the earliest sample required and
+    /// the sample index per stream. For fragmented files this returns the offset of the
+    /// fragment to continue with, and the time of that fragment if known.
+    fn find_seek_position(
+        &self,
+        state: &State,
+        target: gst::ClockTime,
+    ) -> Option<(u64, Option<Vec<usize>>, Option<gst::ClockTime>)> {
+        let movie = state.movie.as_ref()?;
+
+        if !movie.fragmented {
+            let mut offset = None;
+            let mut indices = Vec::with_capacity(state.streams.len());
+            for stream in &state.streams {
+                let track = movie
+                    .tracks
+                    .iter()
+                    .find(|track| track.track_id == stream.track_id)
+                    .unwrap();
+                let idx = find_sync_sample(movie.timescale, track, 0, target).unwrap_or(0);
+                if let Some(sample) = track.samples.get(idx) {
+                    offset =
+                        Some(offset.map_or(sample.offset, |offset: u64| offset.min(sample.offset)));
+                }
+                indices.push(idx);
+            }
+
+            return Some((offset?, Some(indices), None));
+        }
+
+        // Prefer the mfra of the first video track, then the sidx, then the first fragment
+        let reference_stream = state
+            .streams
+            .iter()
+            .find(|stream| stream.stream_type == StreamType::Video)
+            .or_else(|| state.streams.first())?;
+        let track = movie
+            .tracks
+            .iter()
+            .find(|track| track.track_id == reference_stream.track_id)
+            .unwrap();
+
+        let tfra_entry = state
+            .tfras
+            .iter()
+            .find(|tfra| tfra.track_id == reference_stream.track_id)
+            .and_then(|tfra| {
+                tfra.entries.iter().rev().find(|entry| {
+                    let time = (entry.time as i64).saturating_sub(track.edit.media_time);
+                    to_ns(time, track.timescale) <= target.nseconds() as i64
+                })
+            });
+        if let Some(entry) = tfra_entry {
+            let time = (entry.time as i64).saturating_sub(track.edit.media_time);
+            return Some((
+                entry.moof_offset,
+                None,
+                ns_to_clock_time(to_ns(time, track.timescale)),
+            ));
+        }
+
+        if let Some(ref sidx) = state.sidx {
+            let target_time = from_ns(target.nseconds(), sidx.timescale);
+            if let Some(reference) = sidx
+                .references
+                .iter()
+                .filter(|reference| !reference.references_sidx && reference.starts_with_sap)
+                .take_while(|reference| reference.earliest_presentation_time <= target_time)
+                .last()
+            {
+                return Some((
+                    reference.offset,
+                    None,
+                    Some(gst::ClockTime::from_nseconds(to_ns(
+                        reference.earliest_presentation_time as i64,
+                        sidx.timescale,
+                    ) as u64)),
+                ));
+            }
+        }
+
+        state.first_moof_offset.map(|offset| (offset, None, None))
+    }
+
+    /// Resets the streams for continuing at the seek position.
+    fn apply_seek_position(
+        &self,
+        state: &mut State,
+        sample_indices: Option<&[usize]>,
+        target: gst::ClockTime,
+    ) {
+        let State {
+            ref mut movie,
+            ref mut streams,
+            ..
+        } = *state;
+        let movie = movie.as_mut().unwrap();
+
+        for (idx, stream) in streams.iter_mut().enumerate() {
+            stream.eos = false;
+            stream.discont = true;
+            stream.need_segment = true;
+
+            match sample_indices {
+                Some(indices) => {
+                    stream.sample_idx = indices[idx];
+                    stream.seek_target = None;
+                }
+                None => {
+                    if let Some(track) = movie.track_mut(stream.track_id) {
+                        track.samples.clear();
+                    }
+                    stream.sample_idx = 0;
+                    stream.seek_target = Some(target);
+                }
+            }
+        }
+    }
+
+    fn perform_seek(&self, event: &gst::event::Seek, upstream_event: gst::Event) -> bool {
+        let (rate, flags, start_type, start, stop_type, stop) = event.get();
+
+        let start: Option<gst::ClockTime> = match start.try_into() {
+            Ok(start) => start,
+            Err(_) => {
+                gst::debug!(CAT, imp = self, "Only TIME seeks are supported");
+                return false;
+            }
+        };
+        let stop: Option<gst::ClockTime> = match stop.try_into() {
+            Ok(stop) => stop,
+            Err(_) => {
+                gst::debug!(CAT, imp = self, "Only TIME seeks are supported");
+                return false;
+            }
+        };
+
+        if rate <= 0.0 {
+            gst::debug!(CAT, imp = self, "Reverse playback is not supported");
+            return false;
+        }
+
+        if start_type == gst::SeekType::End || stop_type == gst::SeekType::End {
+            gst::debug!(CAT, imp = self, "Relative seeks are not supported");
+            return false;
+        }
+
+        let mode = self.state.lock().unwrap().mode;
+        if mode == gst::PadMode::Pull {
+            self.perform_pull_seek(event, rate, flags, start_type, start, stop_type, stop)
+        } else {
+            self.perform_push_seek(
+                event,
+                upstream_event,
+                rate,
+                flags,
+                start_type,
+                start,
+                stop_type,
+                stop,
+            )
+        }
+    }
+
+    /// Configures the segment for the seek and returns the target position.
+    #[allow(clippy::too_many_arguments)]
+    fn configure_seek_segment(
+        &self,
+        state: &State,
+        rate: f64,
+        flags: gst::SeekFlags,
+        start_type: gst::SeekType,
+        start: Option<gst::ClockTime>,
+        stop_type: gst::SeekType,
+        stop: Option<gst::ClockTime>,
+    ) -> (gst::FormattedSegment<gst::ClockTime>, gst::ClockTime) {
+        let mut segment = state.segment.clone();
+        segment.do_seek(rate, flags, start_type, start, stop_type, stop);
+
+        let target = segment.start().unwrap_or(gst::ClockTime::ZERO);
+        (segment, target)
+    }
+
+    /// Snaps the segment to the keyframe before the target for key unit seeks.
+    fn snap_segment(
+        segment: &mut gst::FormattedSegment<gst::ClockTime>,
+        flags: gst::SeekFlags,
+        keyframe: Option<gst::ClockTime>,
+    ) {
+        if !flags.contains(gst::SeekFlags::KEY_UNIT) || flags.contains(gst::SeekFlags::ACCURATE) {
+            return;
+        }
+
+        if let Some(keyframe) = keyframe {
+            segment.set_start(keyframe);
+            segment.set_time(keyframe);
+            segment.set_position(keyframe);
+        }
+    }
+
+    /// Returns the presentation time of the sample of the first video stream, if any.
+    fn reference_keyframe(
+        &self,
+        state: &State,
+        sample_indices: &[usize],
+    ) -> Option<gst::ClockTime> {
+        let movie = state.movie.as_ref()?;
+        let (idx, stream) = state
+            .streams
+            .iter()
+            .enumerate()
+            .find(|(_, stream)| stream.stream_type == StreamType::Video)?;
+        let track = movie
+            .tracks
+            .iter()
+            .find(|track| track.track_id == stream.track_id)?;
+        let sample = track.samples.get(sample_indices[idx])?;
+
+        ns_to_clock_time(sample_times(movie.timescale, track, sample).1)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn perform_pull_seek(
+        &self,
+        event: &gst::event::Seek,
+        rate: f64,
+        flags: gst::SeekFlags,
+        start_type: gst::SeekType,
+        start: Option<gst::ClockTime>,
+        stop_type: gst::SeekType,
+        stop: Option<gst::ClockTime>,
+    ) -> bool {
+        let seqnum = event.seqnum();
+        let flush = flags.contains(gst::SeekFlags::FLUSH);
+
+        if flush {
+            let event = gst::event::FlushStart::builder().seqnum(seqnum).build();
+            gst::debug!(CAT, imp = self, "Pushing event {event:?}");
+            self.sinkpad.push_event(event.clone());
+            for pad in self.obj().src_pads() {
+                pad.push_event(event.clone());
+            }
+        }
+
+        let _ = self.sinkpad.pause_task();
+        let _stream_lock = self.sinkpad.stream_lock();
+
+        let mut state = self.state.lock().unwrap();
+        if state.movie.is_none() {
+            gst::debug!(CAT, imp = self, "Can't seek before the moov is known");
+            drop(state);
+            if flush {
+                self.flush_stop(seqnum);
+            }
+            return self.start_task().is_ok();
+        }
+
+        let (mut segment, target) =
+            self.configure_seek_segment(&state, rate, flags, start_type, start, stop_type, stop);
+
+        match self.find_seek_position(&state, target) {
+            Some((offset, Some(indices), _)) => {
+                gst::debug!(CAT, imp = self, "Seeking to {target} at offset {offset}");
+                Self::snap_segment(
+                    &mut segment,
+                    flags,
+                    self.reference_keyframe(&state, &indices),
+                );
+                self.apply_seek_position(&mut state, Some(&indices), target);
+            }
+            Some((offset, None, fragment_time)) => {
+                gst::debug!(
+                    CAT,
+                    imp = self,
+                    "Seeking to {target} in fragment at offset {offset}"
+                );
+                Self::snap_segment(&mut segment, flags, fragment_time);
+                state.offset = offset;
+                state.eof = false;
+                self.apply_seek_position(&mut state, None, target);
+            }
+            None => {
+                gst::debug!(CAT, imp = self, "No seek position for {target}");
+                self.apply_seek_position(&mut state, None, target);
+            }
+        }
+
+        state.segment = segment;
+        state.seqnum = seqnum;
+        drop(state);
+
+        if flush {
+            self.flush_stop(seqnum);
+        }
+
+        self.start_task().is_ok()
+    }
+
+    fn flush_stop(&self, seqnum: gst::Seqnum) {
+        let event = gst::event::FlushStop::builder(true).seqnum(seqnum).build();
+        gst::debug!(CAT, imp = self, "Pushing event {event:?}");
+        self.sinkpad.push_event(event.clone());
+        for pad in self.obj().src_pads() {
+            pad.push_event(event.clone());
+        }
+        self.flow_combiner.lock().unwrap().reset();
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn perform_push_seek(
+        &self,
+        event: &gst::event::Seek,
+        upstream_event: gst::Event,
+        rate: f64,
+        flags: gst::SeekFlags,
+        start_type: gst::SeekType,
+        start: Option<gst::ClockTime>,
+        stop_type: gst::SeekType,
+        stop: Option<gst::ClockTime>,
+    ) -> bool {
+        // Let upstream handle the seek if it can, e.g. adaptive streaming demuxers
+        if self.sinkpad.push_event(upstream_event) {
+            return true;
+        }
+
+        let seqnum = event.seqnum();
+        let mut state = self.state.lock().unwrap();
+        if state.movie.is_none() || state.upstream_time_segment {
+            return false;
+        }
+
+        let (mut segment, target) =
+            self.configure_seek_segment(&state, rate, flags, start_type, start, stop_type, stop);
+        let Some((offset, sample_indices, fragment_time)) = self.find_seek_position(&state, target)
+        else {
+            gst::debug!(CAT, imp = self, "No seek position for {target}");
+            return false;
+        };
+
+        let keyframe = match sample_indices {
+            Some(ref indices) => self.reference_keyframe(&state, indices),
+            None => fragment_time,
+        };
+        Self::snap_segment(&mut segment, flags, keyframe);
+
+        gst::debug!(CAT, imp = self, "Seeking to {target} at offset {offset}");
+        state.pending_seek = Some(PendingSeek {
+            offset,
+            seqnum,
+            segment,
+            sample_indices,
+            target,
+        });
+        drop(state);
+
+        let event = gst::event::Seek::builder(
+            1.0,
+            flags | gst::SeekFlags::ACCURATE,
+            gst::SeekType::Set,
+            Some(gst::format::Bytes::from_u64(offset)),
+            gst::SeekType::None,
+            gst::format::Bytes::NONE,
+        )
+        .seqnum(seqnum)
+        .build();
+
+        if !self.sinkpad.push_event(event) {
+            gst::debug!(
+                CAT,
+                imp = self,
+                "Upstream failed to seek to offset {offset}"
+            );
+            self.state.lock().unwrap().pending_seek = None;
+            return false;
+        }
+
+        true
+    }
+
+    /// Seeks upstream to `offset` for skipping over data in push mode.
+    fn seek_upstream(&self, offset: u64) -> bool {
+        let event = gst::event::Seek::new(
+            1.0,
+            gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE,
+            gst::SeekType::Set,
+            Some(gst::format::Bytes::from_u64(offset)),
+            gst::SeekType::None,
+            gst::format::Bytes::NONE,
+        );
+
+        gst::debug!(CAT, imp = self, "Seeking upstream to offset {offset}");
+        self.sinkpad.push_event(event)
+    }
+
+    fn sink_chain(
+        &self,
+        pad: &gst::Pad,
+        buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        gst::log!(CAT, obj = pad, "Handling buffer {:?}", buffer);
+
+        let mut state = self.state.lock().unwrap();
+        state.adapter.push(buffer);
+
+        loop {
+            let available = state.adapter.available();
+
+            match state.push_state {
+                PushState::Header => {
+                    let header = {
+                        let Ok(data) = state.adapter.map(available.min(boxes::MAX_BOX_HEADER_SIZE))
+                        else {
+                            return Ok(gst::FlowSuccess::Ok);
+                        };
+                        boxes::parse_box_header(&data)
+                    };
+
+                    let header = match header {
+                        Ok(Some(header)) => header,
+                        Ok(None) => return Ok(gst::FlowSuccess::Ok),
+                        Err(err) => {
+                            drop(state);
+                            gst::element_imp_error!(
+                                self,
+                                gst::StreamError::Demux,
+                                ["Invalid box header: {err}"]
+                            );
+                            return Err(gst::FlowError::Error);
+                        }
+                    };
+
+                    let offset = state.offset;
+                    gst::trace!(
+                        CAT,
+                        imp = self,
+                        "Found box '{}' at offset {offset} with size {:?}",
+                        String::from_utf8_lossy(&header.fourcc),
+                        header.size,
+                    );
+
+                    let end = match header.size.map(|size| offset.checked_add(size)) {
+                        Some(None) => {
+                            drop(state);
+                            gst::element_imp_error!(
+                                self,
+                                gst::StreamError::Demux,
+                                [
+                                    "Box '{}' at offset {offset} with size {:?} overflows",
+                                    String::from_utf8_lossy(&header.fourcc),
+                                    header.size,
+                                ]
+                            );
+                            return Err(gst::FlowError::Error);
+                        }
+                        end => end.flatten(),
+                    };
+
+                    if &header.fourcc == b"mdat" {
+                        let data_start = offset + header.header_size;
+                        if !state.mdats.iter().any(|(start, _)| *start == data_start) {
+                            state.mdats.push((data_start, end));
+                        }
+
+                        if state.movie.is_none() {
+                            // The moov is after the mdat, so skip over the mdat and come
+                            // back later
+                            let Some(end) = end else {
+                                drop(state);
+                                gst::element_imp_error!(
+                                    self,
+                                    gst::StreamError::Demux,
+                                    ["No moov before mdat of unknown size"]
+                                );
+                                return Err(gst::FlowError::Error);
+                            };
+
+                            state.mdat_restart_offset = Some(offset);
+                            state.push_state = PushState::Skip { end };
+                            drop(state);
+
+                            if !self.seek_upstream(end) {
+                                gst::element_imp_error!(
+                                    self,
+                                    gst::StreamError::Demux,
+                                    ["No moov before mdat and upstream is not seekable"]
+                                );
+                                return Err(gst::FlowError::Error);
+                            }
+
+                            return Ok(gst::FlowSuccess::Ok);
+                        }
+
+                        state.adapter.flush(header.header_size as usize);
+                        state.offset += header.header_size;
+                        state.push_state = PushState::Mdat { end };
+                    } else {
+                        match header.size {
+                            Some(size) if size <= MAX_BOX_SIZE => {
+                                state.push_state = PushState::Box(header);
+                            }
+                            _ if !matches!(&header.fourcc, b"moov" | b"moof" | b"sidx") => {
+                                state.push_state = PushState::Skip {
+                                    end: end.unwrap_or(u64::MAX),
+                                };
+                            }
+                            size => {
+                                drop(state);
+                                gst::element_imp_error!(
+                                    self,
+                                    gst::StreamError::Demux,
+                                    [
+                                        "Invalid '{}' box size {size:?}",
+                                        String::from_utf8_lossy(&header.fourcc)
+                                    ]
+                                );
+                                return Err(gst::FlowError::Error);
+                            }
+                        }
+                    }
+                }
+                PushState::Box(header) => {
+                    let size = header.size.unwrap();
+                    if (available as u64) < size {
+                        return Ok(gst::FlowSuccess::Ok);
+                    }
+
+                    let data = state
+                        .adapter
+                        .take_buffer(size as usize)
+                        .unwrap()
+                        .into_mapped_buffer_readable()
+                        .map_err(|_| gst::FlowError::Error)?;
+                    let offset = state.offset;
+                    state.offset += size;
+                    state.push_state = PushState::Header;
+
+                    let new_streams = match self.handle_box(
+                        &mut state,
+                        &header,
+                        offset,
+                        &data[header.header_size as usize..],
+                    ) {
+                        Ok(new_streams) => new_streams,
+                        Err(err) => {
+                            drop(state);
+                            self.post_error_message(err);
+                            return Err(gst::FlowError::Error);
+                        }
+                    };
+
+                    if new_streams {
+                        drop(state);
+                        self.expose_streams();
+                        state = self.state.lock().unwrap();
+                    }
+
+                    if &header.fourcc == b"moov" {
+                        if let Some(restart_offset) = state.mdat_restart_offset.take() {
+                            state.push_state = PushState::Skip { end: u64::MAX };
+                            drop(state);
+
+                            if !self.seek_upstream(restart_offset) {
+                                gst::element_imp_error!(
+                                    self,
+                                    gst::StreamError::Demux,
+                                    ["Failed to seek back to the mdat"]
+                                );
+                                return Err(gst::FlowError::Error);
+                            }
+
+                            return Ok(gst::FlowSuccess::Ok);
+                        }
+                    }
+                }
+                PushState::Skip { end } => {
+                    let Some(remaining) = end.checked_sub(state.offset) else {
+                        let offset = state.offset;
+                        drop(state);
+                        gst::element_imp_error!(
+                            self,
+                            gst::StreamError::Demux,
+                            ["Skip end {end} before current offset {offset}"]
+                        );
+                        return Err(gst::FlowError::Error);
+                    };
+                    let skip = remaining.min(available as u64);
+                    state.adapter.flush(skip as usize);
+                    state.offset += skip;
+
+                    if state.offset < end {
+                        return Ok(gst::FlowSuccess::Ok);
+                    }
+                    state.push_state = PushState::Header;
+                }
+                PushState::Mdat { end } => {
+                    let next = self.next_sample_by_offset(&mut state, end);
+                    let Some((stream_idx, sample)) = next else {
+                        // No more samples in this mdat
+                        match end {
+                            Some(end) => state.push_state = PushState::Skip { end },
+                            None => {
+                                state.adapter.flush(available);
+                                state.offset += available as u64;
+                                return Ok(gst::FlowSuccess::Ok);
+                            }
+                        }
+                        continue;
+                    };
+
+                    if sample.offset > state.offset {
+                        let skip = (sample.offset - state.offset).min(available as u64);
+                        state.adapter.flush(skip as usize);
+                        state.offset += skip;
+                        if sample.offset > state.offset {
+                            return Ok(gst::FlowSuccess::Ok);
+                        }
+                        continue;
+                    }
+
+                    if available < sample.size as usize {
+                        return Ok(gst::FlowSuccess::Ok);
+                    }
+
+                    let buffer = state.adapter.take_buffer(sample.size as usize).unwrap();
+                    state.offset += sample.size as u64;
+                    state.streams[stream_idx].sample_idx += 1;
+
+                    self.push_sample(state, stream_idx, sample, buffer)?;
+                    state = self.state.lock().unwrap();
+                }
+            }
+        }
+    }
+
+    /// Selects the stream with the next sample in the current `mdat` by offset. Samples
+    /// before the current offset are skipped.
+    fn next_sample_by_offset(
+        &self,
+        state: &mut State,
+        end: Option<u64>,
+    ) -> Option<(usize, boxes::Sample)> {
+        let State {
+            ref movie,
+            ref mut streams,
+            offset,
+            ..
+        } = *state;
+        let movie = movie.as_ref()?;
+
+        let mut next = None;
+        for (idx, stream) in streams.iter_mut().enumerate() {
+            let track = movie
+                .tracks
+                .iter()
+                .find(|track| track.track_id == stream.track_id)
+                .unwrap();
+
+            while let Some(sample) = track.samples.get(stream.sample_idx) {
+                if sample.offset >= offset {
+                    break;
+                }
+                gst::trace!(
+                    CAT,
+                    obj = stream.srcpad,
+                    "Skipping sample {} at offset {}",
+                    stream.sample_idx,
+                    sample.offset
+                );
+                stream.sample_idx += 1;
+            }
+
+            let Some(sample) = track.samples.get(stream.sample_idx) else {
+                continue;
+            };
+            if end.is_some_and(|end| sample.offset + sample.size as u64 > end) {
+                continue;
+            }
+
+            if next.is_none_or(|(_, next): (usize, boxes::Sample)| sample.offset < next.offset) {
+                next = Some((idx, *sample));
+            }
+        }
+
+        next
+    }
+
+    fn sink_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        use gst::EventView;
+
+        gst::log!(CAT, obj = pad, "Handling event {:?}", event);
+
+        match event.view() {
+            EventView::Caps(_) => {
+                // We send our own caps on the source pads
+                true
+            }
+            EventView::StreamStart(_) => {
+                // We send our own stream-start on the source pads
+                true
+            }
+            EventView::Segment(ev) => {
+                let segment = ev.segment();
+                let mut state = self.state.lock().unwrap();
+
+                if let Some(segment) = segment.downcast_ref::<gst::ClockTime>() {
+                    gst::debug!(CAT, obj = pad, "Using upstream time segment {segment:?}");
+                    state.segment = segment.clone();
+                    state.upstream_time_segment = true;
+                    state.seqnum = event.seqnum();
+                    for stream in &mut state.streams {
+                        stream.need_segment = true;
+                    }
+                } else if let Some(segment) = segment.downcast_ref::<gst::format::Bytes>() {
+                    let offset = segment.start().map(|start| *start).unwrap_or(0);
+                    gst::debug!(CAT, obj = pad, "Continuing at offset {offset}");
+
+                    state.adapter.clear();
+                    state.offset = offset;
+
+                    if let Some(pending_seek) = state
+                        .pending_seek
+                        .take_if(|pending_seek| pending_seek.offset == offset)
+                    {
+                        state.segment = pending_seek.segment;
+                        state.seqnum = pending_seek.seqnum;
+                        self.apply_seek_position(
+                            &mut state,
+                            pending_seek.sample_indices.as_deref(),
+                            pending_seek.target,
+                        );
+                    }
+
+                    let mdat = state
+                        .mdats
+                        .iter()
+                        .find(|(start, end)| *start <= offset && end.is_none_or(|end| offset < end))
+                        .copied();
+                    state.push_state = match mdat {
+                        Some((_, end)) if state.movie.is_some() => PushState::Mdat { end },
+                        _ => PushState::Header,
+                    };
+                }
+
+                true
+            }
+            EventView::FlushStop(_) => {
+                let mut state = self.state.lock().unwrap();
+                state.adapter.clear();
+                for stream in &mut state.streams {
+                    stream.need_segment = true;
+                    stream.discont = true;
+                    stream.eos = false;
+                }
+                drop(state);
+                self.flow_combiner.lock().unwrap().reset();
+
+                gst::Pad::event_default(pad, Some(&*self.obj()), event)
+            }
+            EventView::Eos(_) => {
+                if self.state.lock().unwrap().movie.is_none() {
+                    gst::element_imp_error!(self, gst::StreamError::Demux, ["No moov box found"]);
+                } else {
+                    self.push_eos();
+                }
+                true
+            }
+            _ => gst::Pad::event_default(pad, Some(&*self.obj()), event),
+        }
+    }
+
+    fn src_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        use gst::EventView;
+
+        gst::log!(CAT, obj = pad, "Handling event {:?}", event);
+
+        match event.view() {
+            EventView::Seek(ev) => self.perform_seek(ev, event.clone()),
+            _ => gst::Pad::event_default(pad, Some(&*self.obj()), event),
+        }
+    }
+
+    fn src_query(&self, pad: &gst::Pad, query: &mut gst::QueryRef) -> bool {
+        use gst::QueryViewMut;
+
+        gst::log!(CAT, obj = pad, "Handling query {:?}", query);
+
+        match query.view_mut() {
+            QueryViewMut::Duration(q) if q.format() == gst::Format::Time => {
+                let state = self.state.lock().unwrap();
+                let duration = state
+                    .movie
+                    .as_ref()
+                    .and_then(|movie| {
+                        movie
+                            .duration
+                            .map(|duration| to_ns(duration as i64, movie.timescale))
+                    })
+                    .and_then(ns_to_clock_time);
+                drop(state);
+
+                if duration.is_some() {
+                    q.set(duration);
+                    true
+                } else {
+                    self.sinkpad.peer_query(q.query_mut())
+                }
+            }
+            QueryViewMut::Position(q) if q.format() == gst::Format::Time => {
+                let state = self.state.lock().unwrap();
+                q.set(state.segment.position());
+                true
+            }
+            QueryViewMut::Seeking(q) if q.format() == gst::Format::Time => {
+                let state = self.state.lock().unwrap();
+                let duration = state
+                    .movie
+                    .as_ref()
+                    .and_then(|movie| {
+                        movie
+                            .duration
+                            .map(|duration| to_ns(duration as i64, movie.timescale))
+                    })
+                    .and_then(ns_to_clock_time);
+                let mode = state.mode;
+                let upstream_time_segment = state.upstream_time_segment;
+                drop(state);
+
+                let seekable = match mode {
+                    gst::PadMode::Pull => true,
+                    _ if upstream_time_segment => {
+                        return self.sinkpad.peer_query(q.query_mut());
+                    }
+                    _ => {
+                        let mut peer_query = gst::query::Seeking::new(gst::Format::Bytes);
+                        self.sinkpad.peer_query(&mut peer_query) && peer_query.result().0
+                    }
+                };
+
+                q.set(seekable, gst::ClockTime::ZERO, duration);
+                true
+            }
+            _ => gst::Pad::query_default(pad, Some(&*self.obj()), query),
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for ISOBMFFDemux {
+    const NAME: &'static str = "GstISOBMFFDemux";
+    type Type = super::ISOBMFFDemux;
+    type ParentType = gst::Element;
+
+    fn with_class(klass: &Self::Class) -> Self {
+        let templ = klass.pad_template("sink").unwrap();
+        let sinkpad = gst::Pad::builder_from_template(&templ)
+            .activate_function(|pad, parent| {
+                ISOBMFFDemux::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::loggable_error!(CAT, "Panic activating sink pad")),
+                    |demux| demux.sink_activate(pad),
+                )
+            })
+            .activatemode_function(|pad, parent, mode, active| {
+                ISOBMFFDemux::catch_panic_pad_function(
+                    parent,
+                    || {
+                        Err(gst::loggable_error!(
+                            CAT,
+                            "Panic activating sink pad with mode"
+                        ))
+                    },
+                    |demux| demux.sink_activatemode(pad, mode, active),
+                )
+            })
+            .chain_function(|pad, parent, buffer| {
+                ISOBMFFDemux::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::FlowError::Error),
+                    |demux| demux.sink_chain(pad, buffer),
+                )
+            })
+            .event_function(|pad, parent, event| {
+                ISOBMFFDemux::catch_panic_pad_function(
+                    parent,
+                    || false,
+                    |demux| demux.sink_event(pad, event),
+                )
+            })
+            .build();
+
+        Self {
+            sinkpad,
+            state: Mutex::new(State::default()),
+            flow_combiner: Mutex::new(gst_base::UniqueFlowCombiner::new()),
+        }
+    }
+}
+
+impl ObjectImpl for ISOBMFFDemux {
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        self.obj().add_pad(&self.sinkpad).unwrap();
+    }
+}
+
+impl GstObjectImpl for ISOBMFFDemux {}
+
+impl ElementImpl for ISOBMFFDemux {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "ISO BMFF Demuxer",
+                "Codec/Demuxer",
+                "Demuxes progressive and fragmented ISO BMFF (MP4) files",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let sink_pad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &[
+                    gst::Structure::new_empty("video/quicktime"),
+                    gst::Structure::new_empty("audio/x-m4a"),
+                    gst::Structure::new_empty("application/x-3gp"),
+                ]
+                .into_iter()
+                .collect::<gst::Caps>(),
+            )
+            .unwrap();
+
+            let mut templates = vec![sink_pad_template];
+            for stream_type in [StreamType::Video, StreamType::Audio, StreamType::Metadata] {
+                templates.push(
+                    gst::PadTemplate::new(
+                        stream_type.template_name(),
+                        gst::PadDirection::Src,
+                        gst::PadPresence::Sometimes,
+                        &gst::Caps::new_any(),
+                    )
+                    .unwrap(),
+                );
+            }
+
+            templates
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        gst::trace!(CAT, imp = self, "Changing state {:?}", transition);
+
+        let res = self.parent_change_state(transition)?;
+
+        if transition == gst::StateChange::PausedToReady {
+            self.stop();
+        }
+
+        Ok(res)
+    }
+}
@@ -14,10 +14,15 @@
  */
 use gst::glib;
 
+mod isobmffdemux;
 mod mp4mux;
+pub mod recovery;
 
 fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
-    mp4mux::register(plugin)
+    mp4mux::register(plugin)?;
+    isobmffdemux::register(plugin)?;
+
+    Ok(())
 }
 
 gst::plugin_define!(
@@ -16,6 +16,7 @@ use gst_base::subclass::prelude::*;
 use num_integer::Integer;
 use std::collections::HashSet;
 use std::collections::VecDeque;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Mutex;
 
@@ -79,6 +80,22 @@ fn running_time_to_utc_time(
         .and_then(|res| res.positive())
 }
 
+/// Atomically replaces the recovery file at `location` with `data`.
+fn write_recovery_file(location: &Path, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let dir = location
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(data)?;
+    file.as_file().sync_data()?;
+    file.persist(location)?;
+
+    Ok(())
+}
+
 pub(crate) static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
         "mp4mux",
@@ -127,6 +144,12 @@ impl CustomTag<'_> for PrecisionClockTimeUncertaintyNanosecondsTag {
 
 const DEFAULT_INTERLEAVE_BYTES: Option<u64> = None;
 const DEFAULT_INTERLEAVE_TIME: Option<gst::ClockTime> = Some(gst::ClockTime::from_mseconds(500));
+const DEFAULT_FASTSTART: bool = false;
+const DEFAULT_MOOV_RESERVE_SIZE: u64 = 0;
+const DEFAULT_RECOVERY_INTERVAL: Option<gst::ClockTime> = None;
+
+/// Size of the buffers the `mdat` content is pushed downstream with from the faststart file.
+const FASTSTART_BUFFER_SIZE: usize = 1024 * 1024;
 
 #[derive(Debug, Clone)]
 struct Settings {
@@ -135,6 +158,10 @@ struct Settings {
     movie_timescale: u32,
     extra_brands: Vec<[u8; 4]>,
     with_precision_timestamps: bool,
+    faststart: bool,
+    moov_reserve_size: u64,
+    recovery_interval: Option<gst::ClockTime>,
+    recovery_location: Option<String>,
 }
 
 impl Default for Settings {
@@ -145,6 +172,10 @@ impl Default for Settings {
             movie_timescale: 0,
             extra_brands: Vec::new(),
             with_precision_timestamps: false,
+            faststart: DEFAULT_FASTSTART,
+            moov_reserve_size: DEFAULT_MOOV_RESERVE_SIZE,
+            recovery_interval: DEFAULT_RECOVERY_INTERVAL,
+            recovery_location: None,
         }
     }
 }
@@ -226,6 +257,9 @@ struct Stream {
 
     extra_header_data: Option<Vec<u8>>,
 
+    /// Codec-specific boxes to be included in the sample entry
+    codec_specific_boxes: Vec<u8>,
+
     /// Language code from tags
     language_code: Option<[u8; 3]>,
 
@@ -306,6 +340,35 @@ impl Stream {
         Ok(elst_infos)
     }
 
+    /// Creates the header description of this stream without any chunks or auxiliary
+    /// information, or `None` if the stream has no samples.
+    fn header_stream(&self, min_earliest_pts: gst::ClockTime) -> Option<super::Stream> {
+        let (earliest_pts, end_pts) = Option::zip(self.earliest_pts, self.end_pts)?;
+
+        Some(super::Stream {
+            caps: self.caps.clone(),
+            delta_frames: self.delta_frames,
+            timescale: self.timescale(),
+            earliest_pts,
+            end_pts,
+            elst_infos: self.get_elst_infos(min_earliest_pts).unwrap_or_else(|e| {
+                gst::error!(CAT, "Could not prepare edit lists: {e:?}");
+
+                Vec::new()
+            }),
+            image_sequence: self.image_sequence_mode(),
+            extra_header_data: self.extra_header_data.clone(),
+            codec_specific_boxes: self.codec_specific_boxes.clone(),
+            language_code: self.language_code,
+            orientation: self.orientation(),
+            max_bitrate: self.max_bitrate,
+            avg_bitrate: self.avg_bitrate,
+            chunks: Vec::new(),
+            tai_clock_info: self.tai_clock_info,
+            auxiliary_info: Vec::new(),
+        })
+    }
+
     fn timescale(&self) -> u32 {
         let trak_timescale = { self.sinkpad.imp().settings.lock().unwrap().trak_timescale };
 
@@ -362,6 +425,19 @@ impl Stream {
         self.stream_orientation.unwrap_or(self.global_orientation)
     }
 
+    /// Whether this is a timed text stream that is muxed as `tx3g`.
+    fn is_text(&self) -> bool {
+        self.caps.structure(0).unwrap().name() == "text/x-raw"
+    }
+
+    /// Whether this is a closed caption stream that is muxed as `c608` or `c708`.
+    fn is_caption(&self) -> bool {
+        matches!(
+            self.caps.structure(0).unwrap().name().as_str(),
+            "closedcaption/x-cea-608" | "closedcaption/x-cea-708"
+        )
+    }
+
     fn parse_language_code(lang: &str) -> Option<[u8; 3]> {
         let lang = gst_tag::language_codes::language_code_iso_639_2t(lang)?;
         if lang.len() == 3 && lang.chars().all(|c| c.is_ascii_lowercase()) {
@@ -393,6 +469,24 @@ struct State {
     /// Size of the `mdat` as written so far.
     mdat_size: u64,
 
+    /// In faststart mode, temporary file the `mdat` content is written to until the `moov` box
+    /// is written at EOS.
+    faststart_file: Option<std::io::BufWriter<std::fs::File>>,
+
+    /// In faststart or robust recording mode, offset and size of the space reserved for the
+    /// `moov` box.
+    moov_reserve: Option<(u64, u64)>,
+
+    /// End running time of the samples written to the `mdat` so far.
+    mdat_end_time: Option<gst::ClockTime>,
+
+    /// In robust recording mode, end running time of the samples covered by the last recovery
+    /// `moov` box.
+    recovery_time: Option<gst::ClockTime>,
+
+    /// Whether a recovery `moov` box was written into the reserved space.
+    recovery_moov_reserved: bool,
+
     #[cfg(feature = "v1_28")]
     /// The last TAI timestamp value, in nanoseconds after epoch
     last_tai_timestamp: u64,
@@ -1135,6 +1229,188 @@ impl MP4Mux {
         }
     }
 
+    /// Pushes the `mdat` content collected in the faststart file downstream.
+    fn push_faststart_file(
+        &self,
+        faststart_file: std::io::BufWriter<std::fs::File>,
+    ) -> Result<(), gst::FlowError> {
+        use std::io::{Read, Seek};
+
+        let mut file = faststart_file
+            .into_inner()
+            .map_err(|err| err.into_error())
+            .and_then(|mut file| file.rewind().map(|_| file))
+            .map_err(|err| {
+                gst::element_imp_error!(
+                    self,
+                    gst::ResourceError::Write,
+                    ["Failed to finish faststart file: {err}"]
+                );
+                gst::FlowError::Error
+            })?;
+
+        loop {
+            let mut data = vec![0u8; FASTSTART_BUFFER_SIZE];
+            let len = file.read(&mut data).map_err(|err| {
+                gst::element_imp_error!(
+                    self,
+                    gst::ResourceError::Read,
+                    ["Failed to read faststart file: {err}"]
+                );
+                gst::FlowError::Error
+            })?;
+            if len == 0 {
+                break;
+            }
+            data.truncate(len);
+
+            self.obj()
+                .finish_buffer(gst::Buffer::from_mut_slice(data))
+                .inspect_err(|err| {
+                    gst::error!(CAT, imp = self, "Failed pushing mdat content: {err:?}");
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates a header for all samples written out so far, or `None` if there are none yet.
+    fn create_recovery_header(&self, settings: &Settings, state: &State) -> Option<super::Header> {
+        let min_earliest_pts = state.streams.iter().filter_map(|s| s.earliest_pts).min()?;
+
+        let mut streams = Vec::with_capacity(state.streams.len());
+        for stream in &state.streams {
+            let Some(mut header_stream) = stream.header_stream(min_earliest_pts) else {
+                continue;
+            };
+
+            // Auxiliary information is only written after the end of a chunk, so if there is
+            // some pending then the current chunk can't be referenced yet.
+            let num_chunks = if stream.pending_aux_info_data.is_empty() {
+                stream.chunks.len()
+            } else {
+                stream.chunks.len().saturating_sub(1)
+            };
+            if stream.chunks[..num_chunks]
+                .iter()
+                .all(|chunk| chunk.samples.is_empty())
+            {
+                continue;
+            }
+
+            header_stream.chunks = stream.chunks[..num_chunks].to_vec();
+            header_stream.auxiliary_info = stream.aux_info.clone();
+            streams.push(header_stream);
+        }
+
+        if streams.is_empty() {
+            return None;
+        }
+
+        Some(super::Header {
+            variant: self.obj().class().as_ref().variant,
+            movie_timescale: settings.movie_timescale,
+            streams,
+        })
+    }
+
+    /// Writes a recovery `moov` box for all samples written out so far into the reserved space
+    /// and/or the recovery file if the recovery interval has passed since the last one.
+    fn write_recovery_data(&self, settings: &Settings) -> Result<(), gst::FlowError> {
+        let Some(recovery_interval) = settings.recovery_interval else {
+            return Ok(());
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if state.faststart_file.is_some() {
+            return Ok(());
+        }
+
+        let Some(mdat_end_time) = state.mdat_end_time else {
+            return Ok(());
+        };
+        if state
+            .recovery_time
+            .is_some_and(|recovery_time| mdat_end_time < recovery_time + recovery_interval)
+        {
+            return Ok(());
+        }
+
+        let Some(header) = self.create_recovery_header(settings, &state) else {
+            return Ok(());
+        };
+        let moov = boxes::create_moov(&header).map_err(|err| {
+            gst::error!(CAT, imp = self, "Failed to create recovery moov box: {err}");
+            gst::FlowError::Error
+        })?;
+        let moov_size = moov.size() as u64;
+
+        gst::debug!(
+            CAT,
+            imp = self,
+            "Writing recovery moov box of {moov_size} bytes for samples until {mdat_end_time}"
+        );
+        state.recovery_time = Some(mdat_end_time);
+
+        if let Some(ref location) = settings.recovery_location {
+            let map = moov.map_readable().map_err(|_| {
+                gst::error!(CAT, imp = self, "Failed to map moov box");
+                gst::FlowError::Error
+            })?;
+            if let Err(err) = write_recovery_file(Path::new(location), &map) {
+                gst::element_imp_warning!(
+                    self,
+                    gst::ResourceError::Write,
+                    ["Failed to write recovery file {location}: {err}"]
+                );
+            }
+        }
+
+        let Some((offset, reserve_size)) = state.moov_reserve else {
+            return Ok(());
+        };
+        if moov_size != reserve_size && moov_size + 8 > reserve_size {
+            gst::warning!(
+                CAT,
+                imp = self,
+                "Reserved space of {reserve_size} bytes too small for recovery moov box of {moov_size} bytes"
+            );
+            return Ok(());
+        }
+        state.recovery_moov_reserved = true;
+        let current_offset = state.current_offset;
+        drop(state);
+
+        let mut buffers = gst::BufferList::new();
+        buffers.get_mut().unwrap().add(moov);
+        if moov_size < reserve_size {
+            let free = boxes::create_free(reserve_size - moov_size).map_err(|err| {
+                gst::error!(CAT, imp = self, "Failed to create free box: {err}");
+                gst::FlowError::Error
+            })?;
+            for buffer in free {
+                buffers.get_mut().unwrap().add(buffer);
+            }
+        }
+
+        let mut segment = gst::FormattedSegment::<gst::format::Bytes>::new();
+        segment.set_start(gst::format::Bytes::from_u64(offset));
+        self.obj().update_segment(&segment);
+        self.obj().finish_buffer_list(buffers).inspect_err(|err| {
+            gst::error!(
+                CAT,
+                imp = self,
+                "Failed pushing recovery moov box into reserved space downstream: {err:?}"
+            );
+        })?;
+
+        // Continue writing the mdat content where it was left off
+        segment.set_start(gst::format::Bytes::from_u64(current_offset));
+        self.obj().update_segment(&segment);
+
+        Ok(())
+    }
+
     fn flush_aux_info(
         &self,
         buffers: &mut gst::BufferListRef,
@@ -1194,11 +1470,13 @@ impl MP4Mux {
         // Now we can start handling buffers
         while let Some(idx) = self.find_earliest_stream(settings, state, buffers)? {
             let stream = &mut state.streams[idx];
-            let buffer = stream.pending_buffer.take().unwrap();
+            let mut buffer = stream.pending_buffer.take().unwrap();
 
+            // Gaps in timed text streams are written out as empty samples below.
             if buffer.buffer.flags().contains(gst::BufferFlags::GAP)
                 && buffer.buffer.flags().contains(gst::BufferFlags::DROPPABLE)
                 && buffer.buffer.size() == 0
+                && !stream.is_text()
             {
                 gst::trace!(CAT, obj = stream.sinkpad, "Skipping gap buffer {buffer:?}");
 
@@ -1249,8 +1527,64 @@ impl MP4Mux {
                 state.current_offset
             );
 
+            if stream.is_text() {
+                // A cue only lasts for its own duration. The time until the next cue is filled
+                // with an empty sample, which is queued as gap buffer and written out next.
+                if let Some(cue_duration) = buffer.buffer.duration().filter(|cue_duration| {
+                    !buffer.buffer.flags().contains(gst::BufferFlags::GAP)
+                        && *cue_duration < buffer.duration.unwrap()
+                }) {
+                    let remaining_duration = buffer.duration.unwrap() - cue_duration;
+
+                    let mut gap_buffer = gst::Buffer::new();
+                    {
+                        let gap_buffer = gap_buffer.get_mut().unwrap();
+                        gap_buffer.set_pts(buffer.buffer.pts().opt_add(cue_duration));
+                        gap_buffer.set_duration(remaining_duration);
+                        gap_buffer.set_flags(gst::BufferFlags::GAP | gst::BufferFlags::DROPPABLE);
+                    }
+
+                    gst::trace!(
+                        CAT,
+                        obj = stream.sinkpad,
+                        "Filling {remaining_duration} after cue with an empty sample"
+                    );
+
+                    assert!(stream.pending_buffer.is_none());
+                    stream.pending_buffer = Some(PendingBuffer {
+                        buffer: gap_buffer,
+                        timestamp: gst::Signed::Positive(buffer.pts + cue_duration),
+                        pts: buffer.pts + cue_duration,
+                        composition_time_offset: None,
+                        duration: Some(remaining_duration),
+                    });
+
+                    buffer.duration = Some(cue_duration);
+                }
+
+                buffer.buffer = boxes::create_tx3g_sample(&buffer.buffer).map_err(|err| {
+                    gst::error!(
+                        CAT,
+                        obj = stream.sinkpad,
+                        "Failed to create tx3g sample: {err}"
+                    );
+                    gst::FlowError::Error
+                })?;
+            } else if stream.is_caption() {
+                buffer.buffer = boxes::create_caption_sample(&stream.caps, &buffer.buffer)
+                    .map_err(|err| {
+                        gst::error!(
+                            CAT,
+                            obj = stream.sinkpad,
+                            "Failed to create caption sample: {err}"
+                        );
+                        gst::FlowError::Error
+                    })?;
+            }
+
             let duration = buffer.duration.unwrap();
             let composition_time_offset = buffer.composition_time_offset;
+            let end_time = buffer.pts + duration;
 
             if let Err(err) = self.add_elst_info(&buffer, stream) {
                 gst::error!(CAT, "Failed to add elst info: {:#}", err);
@@ -1373,6 +1707,12 @@ impl MP4Mux {
 
             state.current_offset += buffer.size() as u64;
             state.mdat_size += buffer.size() as u64;
+            if state
+                .mdat_end_time
+                .is_none_or(|mdat_end_time| mdat_end_time < end_time)
+            {
+                state.mdat_end_time = Some(end_time);
+            }
             buffers.add(buffer);
         }
 
@@ -1559,6 +1899,7 @@ impl MP4Mux {
 
             let mut delta_frames = super::DeltaFrames::IntraOnly;
             let mut discard_header_buffers = false;
+            let mut codec_specific_boxes = Vec::new();
             match s.name().as_str() {
                 "video/x-h264" | "video/x-h265" => {
                     if !s.has_field_with_type("codec_data", gst::Buffer::static_type()) {
@@ -1615,9 +1956,51 @@ impl MP4Mux {
                         return Err(gst::FlowError::NotNegotiated);
                     };
                 }
+                "audio/x-ac3" | "audio/x-eac3" => {
+                    let Some(first_buffer) = pad.peek_buffer() else {
+                        gst::error!(
+                            CAT,
+                            obj = pad,
+                            "Need first buffer for AC-3 / EAC-3 when creating header"
+                        );
+                        return Err(gst::FlowError::NotNegotiated);
+                    };
+
+                    match s.name().as_str() {
+                        "audio/x-ac3" => {
+                            codec_specific_boxes = match boxes::create_dac3(&first_buffer) {
+                                Ok(boxes) => boxes,
+                                Err(err) => {
+                                    gst::error!(
+                                        CAT,
+                                        obj = pad,
+                                        "Failed to create AC-3 codec specific box: {err}"
+                                    );
+                                    return Err(gst::FlowError::NotNegotiated);
+                                }
+                            };
+                        }
+                        "audio/x-eac3" => {
+                            codec_specific_boxes = match boxes::create_dec3(&first_buffer) {
+                                Ok(boxes) => boxes,
+                                Err(err) => {
+                                    gst::error!(
+                                        CAT,
+                                        obj = pad,
+                                        "Failed to create EAC-3 codec specific box: {err}"
+                                    );
+                                    return Err(gst::FlowError::NotNegotiated);
+                                }
+                            };
+                        }
+                        _ => unreachable!(),
+                    }
+                }
                 "audio/x-alaw" | "audio/x-mulaw" => (),
                 "audio/x-adpcm" => (),
                 "application/x-onvif-metadata" => (),
+                "text/x-raw" => (),
+                "closedcaption/x-cea-608" | "closedcaption/x-cea-708" => (),
                 _ => unreachable!(),
             }
 
@@ -1637,6 +2020,7 @@ impl MP4Mux {
                 end_pts: None,
                 running_time_utc_time_mapping: None,
                 extra_header_data: None,
+                codec_specific_boxes,
                 language_code,
                 global_orientation,
                 stream_orientation,
@@ -1653,7 +2037,8 @@ impl MP4Mux {
             return Err(gst::FlowError::Error);
         }
 
-        // Sort video streams first and then audio streams and then metadata streams, and each group by pad name.
+        // Sort video streams first and then audio streams, then metadata streams and then text
+        // and caption streams, and each group by pad name.
         state.streams.sort_by(|a, b| {
             let order_of_caps = |caps: &gst::CapsRef| {
                 let s = caps.structure(0).unwrap();
@@ -1664,6 +2049,8 @@ impl MP4Mux {
                     1
                 } else if s.name().starts_with("application/x-onvif-metadata") {
                     2
+                } else if s.name().starts_with("text/") || s.name().starts_with("closedcaption/") {
+                    3
                 } else {
                     unimplemented!();
                 }
@@ -1727,6 +2114,89 @@ impl ObjectImpl for MP4Mux {
                     .default_value(false)
                     .mutable_ready()
                     .build(),
+                /**
+                 * GstRsMP4Mux:faststart:
+                 *
+                 * Place the `moov` box before the `mdat` box so the file can be played back
+                 * while it is being downloaded.
+                 *
+                 * If #GstRsMP4Mux:moov-reserve-size is set and downstream is seekable, the `moov`
+                 * box is written into space reserved after the `ftyp` box at EOS. If the reserved
+                 * space is too small, the `moov` box is written at the end of the file instead
+                 * and the reserved space is left as a `free` box.
+                 *
+                 * Otherwise the `mdat` content is written to a temporary file and pushed
+                 * downstream after the `moov` box at EOS. This does not require downstream to
+                 * be seekable.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecBoolean::builder("faststart")
+                    .nick("Fast Start")
+                    .blurb("Place the moov box before the mdat box")
+                    .default_value(DEFAULT_FASTSTART)
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRsMP4Mux:moov-reserve-size:
+                 *
+                 * Number of bytes to reserve for the `moov` box after the `ftyp` box in
+                 * faststart or robust recording mode. 0 means that no space is reserved, in
+                 * which case a temporary file is used in faststart mode.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecUInt64::builder("moov-reserve-size")
+                    .nick("Moov Reserve Size")
+                    .blurb("Bytes to reserve for the moov box in faststart or robust recording mode (0 = none)")
+                    .maximum(u32::MAX as u64)
+                    .default_value(DEFAULT_MOOV_RESERVE_SIZE)
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRsMP4Mux:recovery-interval:
+                 *
+                 * Enables robust recording mode. Whenever this much media time was written, a
+                 * `moov` box for all samples written so far is written into the space reserved
+                 * via #GstRsMP4Mux:moov-reserve-size and/or into the file configured via
+                 * #GstRsMP4Mux:recovery-location.
+                 *
+                 * If the reserved space is big enough then the file stays playable as-is if the
+                 * recording is interrupted. Otherwise the recovery file can be used together with
+                 * the incomplete file to make it playable again, e.g. with the `mp4-recover`
+                 * example.
+                 *
+                 * Recovery data is not written if this is set to 0.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecUInt64::builder("recovery-interval")
+                    .nick("Recovery Interval")
+                    .blurb("Interval in nanoseconds at which recovery data is written (0 = disabled)")
+                    .default_value(
+                        DEFAULT_RECOVERY_INTERVAL
+                            .map(gst::ClockTime::nseconds)
+                            .unwrap_or(0),
+                    )
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRsMP4Mux:recovery-location:
+                 *
+                 * Location of the file the recovery `moov` box is written to in robust recording
+                 * mode. The file is removed again once the output is finished.
+                 *
+                 * Samples that are still buffered downstream when the recording is interrupted
+                 * are lost, so downstream should write out data without delay, e.g.
+                 * `filesink buffer-mode=unbuffered`.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecString::builder("recovery-location")
+                    .nick("Recovery Location")
+                    .blurb("Location of the recovery file in robust recording mode")
+                    .mutable_ready()
+                    .build(),
             ]
         });
 
@@ -1787,6 +2257,29 @@ impl ObjectImpl for MP4Mux {
                 settings.with_precision_timestamps = value.get().expect("type checked upstream");
             }
 
+            "faststart" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.faststart = value.get().expect("type checked upstream");
+            }
+
+            "moov-reserve-size" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.moov_reserve_size = value.get().expect("type checked upstream");
+            }
+
+            "recovery-interval" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.recovery_interval = match value.get().expect("type checked upstream") {
+                    0 | u64::MAX => None,
+                    v => Some(gst::ClockTime::from_nseconds(v)),
+                };
+            }
+
+            "recovery-location" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.recovery_location = value.get().expect("type checked upstream");
+            }
+
             _ => unimplemented!(),
         }
     }
@@ -1825,6 +2318,30 @@ impl ObjectImpl for MP4Mux {
                 settings.with_precision_timestamps.to_value()
             }
 
+            "faststart" => {
+                let settings = self.settings.lock().unwrap();
+                settings.faststart.to_value()
+            }
+
+            "moov-reserve-size" => {
+                let settings = self.settings.lock().unwrap();
+                settings.moov_reserve_size.to_value()
+            }
+
+            "recovery-interval" => {
+                let settings = self.settings.lock().unwrap();
+                settings
+                    .recovery_interval
+                    .map(gst::ClockTime::nseconds)
+                    .unwrap_or(0)
+                    .to_value()
+            }
+
+            "recovery-location" => {
+                let settings = self.settings.lock().unwrap();
+                settings.recovery_location.to_value()
+            }
+
             _ => unimplemented!(),
         }
     }
@@ -2124,18 +2641,57 @@ impl AggregatorImpl for MP4Mux {
             drop(state);
 
             let mut q = gst::query::Seeking::new(gst::Format::Bytes);
-            if self.obj().src_pad().peer_query(&mut q) {
-                if !q.result().0 {
-                    gst::element_imp_error!(
+            let seekable = if self.obj().src_pad().peer_query(&mut q) {
+                q.result().0
+            } else {
+                // Can't query downstream, have to assume downstream is seekable
+                gst::warning!(CAT, imp = self, "Can't query downstream for seekability");
+                true
+            };
+
+            // In faststart mode the moov box is written into the reserved space if downstream is
+            // seekable, otherwise the mdat is collected in a temporary file and nothing has to be
+            // rewritten later.
+            let faststart_file =
+                if settings.faststart && (settings.moov_reserve_size == 0 || !seekable) {
+                    match tempfile::tempfile() {
+                        Ok(file) => Some(file),
+                        Err(err) => {
+                            gst::element_imp_error!(
+                                self,
+                                gst::ResourceError::OpenWrite,
+                                ["Failed to create faststart file: {err}"]
+                            );
+                            return Err(gst::FlowError::Error);
+                        }
+                    }
+                } else {
+                    None
+                };
+
+            if !seekable && faststart_file.is_none() {
+                gst::element_imp_error!(
+                    self,
+                    gst::StreamError::Mux,
+                    ["Downstream is not seekable"]
+                );
+                return Err(gst::FlowError::Error);
+            }
+
+            if settings.recovery_interval.is_some() {
+                if faststart_file.is_some() {
+                    gst::element_imp_warning!(
                         self,
                         gst::StreamError::Mux,
-                        ["Downstream is not seekable"]
+                        ["Can't write recovery data in faststart mode without reserved space"]
+                    );
+                } else if settings.moov_reserve_size == 0 && settings.recovery_location.is_none() {
+                    gst::element_imp_warning!(
+                        self,
+                        gst::StreamError::Mux,
+                        ["No reserved space and no recovery location for recovery data configured"]
                     );
-                    return Err(gst::FlowError::Error);
                 }
-            } else {
-                // Can't query downstream, have to assume downstream is seekable
-                gst::warning!(CAT, imp = self, "Can't query downstream for seekability");
             }
 
             state = self.state.lock().unwrap();
@@ -2189,7 +2745,8 @@ impl AggregatorImpl for MP4Mux {
                     match caps_structure.name().as_str() {
                         "video/x-h264" | "video/x-h265" | "video/x-vp8" | "video/x-vp9"
                         | "image/jpeg" | "video/x-raw" | "audio/mpeg" | "audio/x-opus"
-                        | "audio/x-flac" | "audio/x-alaw" | "audio/x-mulaw" | "audio/x-adpcm" => {
+                        | "audio/x-flac" | "audio/x-alaw" | "audio/x-mulaw" | "audio/x-adpcm"
+                        | "audio/x-ac3" | "audio/x-eac3" => {
                             compatible_brands.insert(*b"mp41");
                             compatible_brands.insert(*b"mp42");
                             compatible_brands.insert(*b"isom");
@@ -2218,6 +2775,29 @@ impl AggregatorImpl for MP4Mux {
             state.current_offset += ftyp.size() as u64;
             buffers.get_mut().unwrap().add(ftyp);
 
+            if faststart_file.is_none()
+                && settings.moov_reserve_size > 0
+                && (settings.faststart || settings.recovery_interval.is_some())
+            {
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Reserving {} bytes for moov box at offset {}",
+                    settings.moov_reserve_size,
+                    state.current_offset
+                );
+                let reserve_size = std::cmp::max(settings.moov_reserve_size, 8);
+                let free = boxes::create_free(reserve_size).map_err(|err| {
+                    gst::error!(CAT, imp = self, "Failed to create free box: {err}");
+                    gst::FlowError::Error
+                })?;
+                state.moov_reserve = Some((state.current_offset, reserve_size));
+                state.current_offset += reserve_size;
+                for buffer in free {
+                    buffers.get_mut().unwrap().add(buffer);
+                }
+            }
+
             gst::info!(
                 CAT,
                 imp = self,
@@ -2231,9 +2811,17 @@ impl AggregatorImpl for MP4Mux {
             })?;
             state.current_offset += mdat.size() as u64;
             state.mdat_size = 0;
-            buffers.get_mut().unwrap().add(mdat);
+            if let Some(faststart_file) = faststart_file {
+                // The mdat box header is written with the final size directly before the mdat
+                // content at EOS.
+                state.mdat_offset = None;
+                state.faststart_file = Some(std::io::BufWriter::new(faststart_file));
+            } else {
+                buffers.get_mut().unwrap().add(mdat);
+            }
         }
 
+        let num_header_buffers = buffers.len();
         let res = match self.drain_buffers(&settings, &mut state, buffers.get_mut().unwrap()) {
             Ok(_) => Ok(gst::FlowSuccess::Ok),
             Err(err @ gst::FlowError::Eos) | Err(err @ gst_base::AGGREGATOR_FLOW_NEED_DATA) => {
@@ -2242,6 +2830,32 @@ impl AggregatorImpl for MP4Mux {
             Err(err) => return Err(err),
         };
 
+        // In faststart mode with a temporary file all mdat content goes there instead of
+        // downstream for now.
+        if let Some(ref mut faststart_file) = state.faststart_file {
+            use std::io::Write;
+
+            let buffers = buffers.get_mut().unwrap();
+            for buffer in buffers.iter().skip(num_header_buffers) {
+                let map = buffer.map_readable().map_err(|_| {
+                    gst::error!(CAT, imp = self, "Failed to map buffer");
+                    gst::FlowError::Error
+                })?;
+                if let Err(err) = faststart_file.write_all(&map) {
+                    gst::element_imp_error!(
+                        self,
+                        gst::ResourceError::Write,
+                        ["Failed to write to faststart file: {err}"]
+                    );
+                    return Err(gst::FlowError::Error);
+                }
+            }
+            buffers.remove(num_header_buffers..);
+        }
+
+        let mut faststart_file = None;
+        let mut reserved_moov = None;
+
         if res == Err(gst::FlowError::Eos) {
             // Create moov box now and append it to the buffers
 
@@ -2261,46 +2875,96 @@ impl AggregatorImpl for MP4Mux {
                 .unwrap();
             let mut streams = Vec::with_capacity(state.streams.len());
             for stream in state.streams.drain(..) {
-                let (earliest_pts, end_pts) = match Option::zip(stream.earliest_pts, stream.end_pts)
-                {
-                    Some(res) => res,
-                    None => continue, // empty stream
+                let Some(mut header_stream) = stream.header_stream(min_earliest_pts) else {
+                    continue; // empty stream
                 };
 
-                streams.push(super::Stream {
-                    caps: stream.caps.clone(),
-                    delta_frames: stream.delta_frames,
-                    timescale: stream.timescale(),
-                    earliest_pts,
-                    end_pts,
-                    elst_infos: stream.get_elst_infos(min_earliest_pts).unwrap_or_else(|e| {
-                        gst::error!(CAT, "Could not prepare edit lists: {e:?}");
-
-                        Vec::new()
-                    }),
-                    image_sequence: stream.image_sequence_mode(),
-                    extra_header_data: stream.extra_header_data.clone(),
-                    language_code: stream.language_code,
-                    orientation: stream.orientation(),
-                    max_bitrate: stream.max_bitrate,
-                    avg_bitrate: stream.avg_bitrate,
-                    chunks: stream.chunks,
-                    tai_clock_info: stream.tai_clock_info,
-                    auxiliary_info: stream.aux_info,
-                });
+                header_stream.chunks = stream.chunks;
+                header_stream.auxiliary_info = stream.aux_info;
+                streams.push(header_stream);
             }
 
-            let moov = boxes::create_moov(super::Header {
+            let mut header = super::Header {
                 variant: self.obj().class().as_ref().variant,
                 movie_timescale: settings.movie_timescale,
                 streams,
-            })
-            .map_err(|err| {
-                gst::error!(CAT, imp = self, "Failed to create moov box: {err}");
-                gst::FlowError::Error
-            })?;
-            state.current_offset += moov.size() as u64;
-            buffers.get_mut().unwrap().add(moov);
+            };
+
+            let create_moov = |header: &super::Header| {
+                boxes::create_moov(header).map_err(|err| {
+                    gst::error!(CAT, imp = self, "Failed to create moov box: {err}");
+                    gst::FlowError::Error
+                })
+            };
+
+            if let Some(file) = state.faststart_file.take() {
+                // The moov box is placed between the ftyp box and the mdat box, so all offsets
+                // have to be shifted by its size. Switching to 64 bit offsets can increase the
+                // size of the moov box, so repeat until the size does not change anymore.
+                let mut moov_size = 0;
+                let moov = loop {
+                    let moov = create_moov(&header)?;
+                    if moov.size() as u64 == moov_size {
+                        break moov;
+                    }
+                    header.shift_offsets(moov.size() as u64 - moov_size);
+                    moov_size = moov.size() as u64;
+                };
+
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Writing moov box of {moov_size} bytes before mdat with size {}",
+                    state.mdat_size
+                );
+
+                let mdat = boxes::create_mdat_header(Some(state.mdat_size)).map_err(|err| {
+                    gst::error!(CAT, imp = self, "Failed to create mdat box header: {err}");
+                    gst::FlowError::Error
+                })?;
+
+                state.current_offset += moov_size;
+                buffers.get_mut().unwrap().add(moov);
+                buffers.get_mut().unwrap().add(mdat);
+                faststart_file = Some(file);
+            } else {
+                let moov = create_moov(&header)?;
+                let moov_size = moov.size() as u64;
+
+                match state.moov_reserve {
+                    // Either the moov box fills the reserved space completely or the remaining
+                    // space is big enough for a free box.
+                    Some((offset, reserve_size))
+                        if moov_size == reserve_size || moov_size + 8 <= reserve_size =>
+                    {
+                        gst::info!(
+                            CAT,
+                            imp = self,
+                            "Writing moov box of {moov_size} bytes into reserved space of {reserve_size} bytes"
+                        );
+                        reserved_moov = Some((offset, reserve_size, Some(moov)));
+                    }
+                    reserve => {
+                        if let Some((offset, reserve_size)) = reserve {
+                            gst::element_imp_warning!(
+                                self,
+                                gst::StreamError::Mux,
+                                [
+                                    "Reserved space of {reserve_size} bytes too small for moov box of {moov_size} bytes, writing it at the end"
+                                ]
+                            );
+
+                            // A previous recovery moov box has to be overwritten again
+                            if state.recovery_moov_reserved {
+                                reserved_moov = Some((offset, reserve_size, None));
+                            }
+                        }
+
+                        state.current_offset += moov_size;
+                        buffers.get_mut().unwrap().add(moov);
+                    }
+                }
+            }
         }
 
         drop(state);
@@ -2316,8 +2980,53 @@ impl AggregatorImpl for MP4Mux {
             }
         }
 
+        if let Some(faststart_file) = faststart_file {
+            self.push_faststart_file(faststart_file)?;
+        }
+
+        if res != Err(gst::FlowError::Eos) {
+            self.write_recovery_data(&settings)?;
+        }
+
+        if let Some((offset, reserve_size, moov)) = reserved_moov {
+            let mut buffers = gst::BufferList::new();
+            let mut remaining = reserve_size;
+            if let Some(moov) = moov {
+                gst::info!(CAT, imp = self, "Writing moov box at offset {offset} now");
+                remaining -= moov.size() as u64;
+                buffers.get_mut().unwrap().add(moov);
+            } else {
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Clearing recovery moov box at offset {offset} now"
+                );
+            }
+            if remaining > 0 {
+                let free = boxes::create_free(remaining).map_err(|err| {
+                    gst::error!(CAT, imp = self, "Failed to create free box: {err}");
+                    gst::FlowError::Error
+                })?;
+                for buffer in free {
+                    buffers.get_mut().unwrap().add(buffer);
+                }
+            }
+
+            let mut segment = gst::FormattedSegment::<gst::format::Bytes>::new();
+            segment.set_start(gst::format::Bytes::from_u64(offset));
+            self.obj().update_segment(&segment);
+            if let Err(err) = self.obj().finish_buffer_list(buffers) {
+                gst::error!(
+                    CAT,
+                    imp = self,
+                    "Failed pushing moov box into reserved space downstream: {err:?}",
+                );
+            }
+        }
+
         if res == Err(gst::FlowError::Eos) {
             let mut state = self.state.lock().unwrap();
+            let have_recovery_data = state.recovery_time.is_some();
 
             if let Some(mdat_offset) = state.mdat_offset {
                 gst::info!(
@@ -2344,6 +3053,14 @@ impl AggregatorImpl for MP4Mux {
                     );
                 }
             }
+
+            // The file is complete now so the recovery file is not needed anymore
+            if let Some(ref location) = settings.recovery_location {
+                if have_recovery_data {
+                    gst::debug!(CAT, imp = self, "Removing recovery file {location}");
+                    let _ = std::fs::remove_file(location);
+                }
+            }
         }
 
         res
@@ -2542,6 +3259,27 @@ impl ElementImpl for ISOMP4Mux {
                         .field("channels", gst::IntRange::<i32>::new(1, 8))
                         .field("rate", gst::IntRange::<i32>::new(1, 10 * u16::MAX as i32))
                         .build(),
+                    gst::Structure::builder("audio/x-ac3")
+                        .field("framed", true)
+                        .field("alignment", "frame")
+                        .field("channels", gst::IntRange::<i32>::new(1, u16::MAX as i32))
+                        .field("rate", gst::IntRange::<i32>::new(1, i32::MAX))
+                        .build(),
+                    gst::Structure::builder("audio/x-eac3")
+                        .field("framed", true)
+                        .field("alignment", "iec61937")
+                        .field("channels", gst::IntRange::<i32>::new(1, u16::MAX as i32))
+                        .field("rate", gst::IntRange::<i32>::new(1, i32::MAX))
+                        .build(),
+                    gst::Structure::builder("text/x-raw")
+                        .field("format", "utf8")
+                        .build(),
+                    gst::Structure::builder("closedcaption/x-cea-608")
+                        .field("format", gst::List::new(["raw", "s334-1a"]))
+                        .build(),
+                    gst::Structure::builder("closedcaption/x-cea-708")
+                        .field("format", "cdp")
+                        .build(),
                 ]
                 .into_iter()
                 .collect::<gst::Caps>(),
@@ -174,7 +174,7 @@ impl DeltaFrames {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct Sample {
     /// Sync point
     sync_point: bool,
@@ -191,7 +191,7 @@ pub(crate) struct Sample {
     size: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct Chunk {
     /// Chunk start offset
     offset: u64,
@@ -280,6 +280,9 @@ pub(crate) struct Stream {
     // More data to be included in the fragmented stream header
     extra_header_data: Option<Vec<u8>>,
 
+    // Codec-specific boxes to be included in the sample entry
+    codec_specific_boxes: Vec<u8>,
+
     // Language code from tags
     language_code: Option<[u8; 3]>,
 
@@ -312,6 +315,23 @@ pub(crate) struct Header {
     streams: Vec<Stream>,
 }
 
+impl Header {
+    /// Shifts all absolute file offsets of the samples and auxiliary information by `delta`.
+    fn shift_offsets(&mut self, delta: u64) {
+        for stream in &mut self.streams {
+            for chunk in &mut stream.chunks {
+                chunk.offset += delta;
+            }
+
+            for auxiliary_info in &mut stream.auxiliary_info {
+                for entry in &mut auxiliary_info.entries {
+                    entry.entry_offset += delta;
+                }
+            }
+        }
+    }
+}
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum Variant {
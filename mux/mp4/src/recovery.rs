@@ -0,0 +1,163 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Recovery of MP4 files that were written by `isomp4mux` / `onvifmp4mux` in robust recording
+//! mode but were never finished, e.g. because the process was killed.
+//!
+//! Such files end with an `mdat` box that has no size set yet. The `moov` box describing the
+//! samples written until the last recovery point is either stored in the space reserved after
+//! the `ftyp` box or in a separate recovery file.
+//!
+//! All sample offsets in the recovery `moov` box are absolute file offsets, so recovering the
+//! file only requires fixing up the `mdat` box header and appending the `moov` box if it was
+//! stored in a separate recovery file.
+
+use anyhow::{bail, Context, Error};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+#[derive(Debug)]
+struct BoxHeader {
+    fourcc: [u8; 4],
+    /// Offset of the box from the beginning of the file.
+    offset: u64,
+    /// Size of the box header.
+    header_size: u64,
+    /// Size of the box including its header, `None` if it extends until the end of the file.
+    size: Option<u64>,
+}
+
+/// Reads the headers of all top-level boxes of the file.
+fn read_top_level_boxes(file: &mut File, file_size: u64) -> Result<Vec<BoxHeader>, Error> {
+    let mut boxes = Vec::new();
+
+    let mut offset = 0;
+    while file_size - offset >= 8 {
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+        let fourcc = <[u8; 4]>::try_from(&header[4..8]).unwrap();
+
+        let (header_size, size) = match u32::from_be_bytes(header[0..4].try_into().unwrap()) {
+            0 => (8, None),
+            1 => {
+                let mut size = [0u8; 8];
+                file.read_exact(&mut size).context("truncated box header")?;
+                (16, Some(u64::from_be_bytes(size)))
+            }
+            size => (8, Some(size as u64)),
+        };
+
+        if size.is_some_and(|size| size < header_size) {
+            bail!(
+                "Invalid size for box {} at offset {offset}",
+                String::from_utf8_lossy(&fourcc)
+            );
+        }
+
+        boxes.push(BoxHeader {
+            fourcc,
+            offset,
+            header_size,
+            size,
+        });
+
+        match size {
+            Some(size) if offset + size <= file_size => offset += size,
+            _ => break,
+        }
+    }
+
+    Ok(boxes)
+}
+
+/// Recovers the unfinished MP4 file at `location` in place.
+///
+/// If `recovery_location` is given then the `moov` box stored in that recovery file is used,
+/// otherwise the recovery `moov` box stored in the reserved space of the file itself.
+///
+/// Any data after the last recovery point stays in the file but is not referenced anymore.
+pub fn recover_file(location: &Path, recovery_location: Option<&Path>) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(location)
+        .with_context(|| format!("Failed to open {}", location.display()))?;
+    let file_size = file.metadata()?.len();
+
+    let boxes = read_top_level_boxes(&mut file, file_size)?;
+    if boxes.first().is_none_or(|b| &b.fourcc != b"ftyp") {
+        bail!("Not an MP4 file");
+    }
+
+    let Some(mdat_idx) = boxes.iter().position(|b| &b.fourcc == b"mdat") else {
+        bail!("File has no mdat box");
+    };
+    let mdat = &boxes[mdat_idx];
+    if mdat_idx != boxes.len() - 1 || mdat.size.is_some() {
+        bail!("File is not an unfinished recording");
+    }
+
+    let reserved_moov = boxes[..mdat_idx].iter().find(|b| &b.fourcc == b"moov");
+
+    let moov = if let Some(recovery_location) = recovery_location {
+        let moov = std::fs::read(recovery_location)
+            .with_context(|| format!("Failed to read {}", recovery_location.display()))?;
+        if moov.len() < 8
+            || &moov[4..8] != b"moov"
+            || u32::from_be_bytes(moov[0..4].try_into().unwrap()) as usize != moov.len()
+        {
+            bail!("Recovery file does not contain a moov box");
+        }
+
+        // The recovery file is at least as recent as the moov box in the reserved space
+        if let Some(reserved_moov) = reserved_moov {
+            file.seek(SeekFrom::Start(reserved_moov.offset + 4))?;
+            file.write_all(b"free")?;
+        }
+
+        Some(moov)
+    } else if reserved_moov.is_some() {
+        None
+    } else {
+        bail!("File contains no recovery data");
+    };
+
+    // Fix up the mdat box header to cover all data until the end of the file. If the size does
+    // not fit into 32 bits, the 8 byte free box directly before the mdat box written by the
+    // muxer is used for a 64 bit header instead.
+    let mdat_data_size = file_size - mdat.offset - mdat.header_size;
+    if let Ok(size) = u32::try_from(mdat_data_size + 8) {
+        file.seek(SeekFrom::Start(mdat.offset))?;
+        file.write_all(&size.to_be_bytes())?;
+    } else {
+        let free = mdat_idx
+            .checked_sub(1)
+            .map(|idx| &boxes[idx])
+            .filter(|b| &b.fourcc == b"free" && b.size == Some(8));
+        let Some(free) = free else {
+            bail!("No space for 64 bit mdat box header");
+        };
+
+        file.seek(SeekFrom::Start(free.offset))?;
+        file.write_all(&1u32.to_be_bytes())?;
+        file.write_all(b"mdat")?;
+        file.write_all(&(mdat_data_size + 16).to_be_bytes())?;
+    }
+
+    if let Some(moov) = moov {
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&moov)?;
+    }
+
+    file.sync_all()?;
+
+    Ok(())
+}
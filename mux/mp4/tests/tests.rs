@@ -7,9 +7,9 @@
 // SPDX-License-Identifier: MPL-2.0
 //
 
-use std::{fs::File, path::Path};
 #[cfg(feature = "v1_28")]
-use std::{io::Seek as _, sync::LazyLock};
+use std::sync::LazyLock;
+use std::{fs::File, io::Seek as _, path::Path};
 
 #[cfg(feature = "v1_28")]
 use gst::{ClockTime, ReferenceTimestampMeta};
@@ -24,6 +24,7 @@ fn init() {
     INIT.call_once(|| {
         gst::init().unwrap();
         gstmp4::plugin_register_static().unwrap();
+        gstfmp4::plugin_register_static().unwrap();
     });
 }
 
@@ -210,6 +211,54 @@ fn test_roundtrip_av1_aac() {
     })
 }
 
+#[test]
+fn test_roundtrip_vp9_ac3() {
+    init();
+    test_short_basic_with("vp9enc ! vp9parse", "avenc_ac3 ! ac3parse", |location| {
+        let data = std::fs::read(location).unwrap();
+        assert!(data.windows(4).any(|w| w == b"ac-3"));
+        assert!(data.windows(4).any(|w| w == b"dac3"));
+
+        let Ok(pipeline) = gst::parse::launch(
+            "filesrc name=src ! qtdemux name=demux \
+             demux.audio_0 ! queue ! avdec_ac3 ! fakesink \
+             demux.video_0 ! queue ! vp9dec ! fakesink",
+        ) else {
+            panic!("could not build decoding pipeline")
+        };
+        let pipeline = Pipeline(pipeline.downcast::<gst::Pipeline>().unwrap());
+        pipeline
+            .by_name("src")
+            .unwrap()
+            .set_property("location", location.display().to_string());
+        pipeline.into_completion();
+    })
+}
+
+#[test]
+fn test_roundtrip_vp9_eac3() {
+    init();
+    test_short_basic_with("vp9enc ! vp9parse", "avenc_eac3 ! ac3parse", |location| {
+        let data = std::fs::read(location).unwrap();
+        assert!(data.windows(4).any(|w| w == b"ec-3"));
+        assert!(data.windows(4).any(|w| w == b"dec3"));
+
+        let Ok(pipeline) = gst::parse::launch(
+            "filesrc name=src ! qtdemux name=demux \
+             demux.audio_0 ! queue ! avdec_eac3 ! fakesink \
+             demux.video_0 ! queue ! vp9dec ! fakesink",
+        ) else {
+            panic!("could not build decoding pipeline")
+        };
+        let pipeline = Pipeline(pipeline.downcast::<gst::Pipeline>().unwrap());
+        pipeline
+            .by_name("src")
+            .unwrap()
+            .set_property("location", location.display().to_string());
+        pipeline.into_completion();
+    })
+}
+
 fn test_uncompressed_with(format: &str, width: u32, height: u32, cb: impl FnOnce(&Path)) {
     let Ok(pipeline) = gst::parse::launch(&format!(
         "videotestsrc num-buffers=34 ! video/x-raw,format={format},width={width},height={height} ! mux. \
@@ -1369,3 +1418,717 @@ fn test_taic_x264_no_sync() {
     init();
     test_taic_encode_cannot_sync("x264enc");
 }
+
+#[derive(Debug, Default)]
+struct DemuxedStreams {
+    video_buffers: usize,
+    audio_buffers: usize,
+    first_video_pts: Option<gst::ClockTime>,
+}
+
+fn demux_with(
+    location: &Path,
+    demux_pipeline: &str,
+    seek: Option<gst::ClockTime>,
+) -> DemuxedStreams {
+    use std::sync::{Arc, Mutex};
+
+    let pipeline = gst::parse::launch(&format!(
+        "{demux_pipeline} \
+         demux.video_0 ! queue ! fakesink name=vsink \
+         demux.audio_0 ! queue ! fakesink name=asink"
+    ))
+    .expect("could not build demuxing pipeline");
+    let pipeline = Pipeline(pipeline.downcast::<gst::Pipeline>().unwrap());
+    pipeline
+        .by_name("src")
+        .unwrap()
+        .set_property("location", location.display().to_string());
+
+    let streams = Arc::new(Mutex::new(DemuxedStreams::default()));
+    for (name, is_video) in [("vsink", true), ("asink", false)] {
+        let streams = streams.clone();
+        let sinkpad = pipeline.by_name(name).unwrap().static_pad("sink").unwrap();
+        sinkpad.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+            let Some(buffer) = info.buffer() else {
+                return gst::PadProbeReturn::Ok;
+            };
+
+            let mut streams = streams.lock().unwrap();
+            if is_video {
+                if streams.video_buffers == 0 {
+                    streams.first_video_pts = buffer.pts();
+                }
+                streams.video_buffers += 1;
+            } else {
+                streams.audio_buffers += 1;
+            }
+
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    if let Some(position) = seek {
+        pipeline.set_state(gst::State::Paused).unwrap();
+        let (res, _, _) = pipeline.state(gst::ClockTime::NONE);
+        res.expect("Unable to preroll the pipeline");
+
+        *streams.lock().unwrap() = DemuxedStreams::default();
+        pipeline
+            .seek_simple(gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT, position)
+            .expect("Seek failed");
+        let (res, _, _) = pipeline.state(gst::ClockTime::NONE);
+        res.expect("Unable to preroll the pipeline after seeking");
+    }
+
+    pipeline.into_completion();
+
+    Arc::into_inner(streams).unwrap().into_inner().unwrap()
+}
+
+#[test]
+fn test_isobmffdemux_x264_aac() {
+    init();
+    test_basic_with("x264enc", "fdkaacenc", |location| {
+        let streams = demux_with(location, "filesrc name=src ! isobmffdemux name=demux", None);
+        assert_eq!(streams.video_buffers, 99);
+        assert!(streams.audio_buffers > 0);
+        assert_eq!(streams.first_video_pts, Some(gst::ClockTime::ZERO));
+    })
+}
+
+#[test]
+fn test_isobmffdemux_push_mode() {
+    init();
+    test_basic_with("x264enc", "fdkaacenc", |location| {
+        let streams = demux_with(
+            location,
+            "filesrc name=src ! queue ! isobmffdemux name=demux",
+            None,
+        );
+        assert_eq!(streams.video_buffers, 99);
+        assert!(streams.audio_buffers > 0);
+    })
+}
+
+#[test]
+fn test_isobmffdemux_vp9_flac() {
+    init();
+    test_short_basic_with("vp9enc ! vp9parse", "flacenc ! flacparse", |location| {
+        let Ok(pipeline) = gst::parse::launch(
+            "filesrc name=src ! isobmffdemux name=demux \
+             demux.audio_0 ! queue ! flacdec ! fakesink \
+             demux.video_0 ! queue ! vp9dec ! fakesink",
+        ) else {
+            panic!("could not build decoding pipeline")
+        };
+        let pipeline = Pipeline(pipeline.downcast::<gst::Pipeline>().unwrap());
+        pipeline
+            .by_name("src")
+            .unwrap()
+            .set_property("location", location.display().to_string());
+        pipeline.into_completion();
+    })
+}
+
+#[test]
+fn test_isobmffdemux_seek() {
+    init();
+    test_basic_with("x264enc key-int-max=30", "fdkaacenc", |location| {
+        let streams = demux_with(
+            location,
+            "filesrc name=src ! isobmffdemux name=demux",
+            Some(gst::ClockTime::from_mseconds(1_500)),
+        );
+
+        // Key unit seek snaps to the previous keyframe
+        let first_video_pts = streams.first_video_pts.unwrap();
+        assert!(first_video_pts <= gst::ClockTime::from_mseconds(1_500));
+        assert!(first_video_pts >= gst::ClockTime::from_seconds(1));
+        assert!(streams.video_buffers < 99);
+        assert!(streams.audio_buffers > 0);
+    })
+}
+
+fn test_fragmented_with(mux: &str, cb: impl FnOnce(&Path)) {
+    let Ok(pipeline) = gst::parse::launch(&format!(
+        "videotestsrc num-buffers=99 ! x264enc key-int-max=30 ! mux. \
+         audiotestsrc num-buffers=140 ! fdkaacenc ! mux. \
+         {mux} name=mux fragment-duration=1000000000 ! filesink name=sink"
+    )) else {
+        println!("could not build encoding pipeline");
+        return;
+    };
+    run_pipeline(pipeline, cb);
+}
+
+#[test]
+fn test_isobmffdemux_fragmented() {
+    init();
+    test_fragmented_with("isofmp4mux", |location| {
+        let boxes = top_level_boxes(location)
+            .into_iter()
+            .map(|(fourcc, ..)| fourcc)
+            .collect::<Vec<_>>();
+        assert!(boxes.contains(b"moof"));
+
+        let streams = demux_with(location, "filesrc name=src ! isobmffdemux name=demux", None);
+        assert_eq!(streams.video_buffers, 99);
+        assert!(streams.audio_buffers > 0);
+    })
+}
+
+#[test]
+fn test_isobmffdemux_fragmented_push_mode() {
+    init();
+    test_fragmented_with("isofmp4mux", |location| {
+        let streams = demux_with(
+            location,
+            "filesrc name=src ! queue ! isobmffdemux name=demux",
+            None,
+        );
+        assert_eq!(streams.video_buffers, 99);
+        assert!(streams.audio_buffers > 0);
+    })
+}
+
+fn check_fragmented_seek(location: &Path, demux_pipeline: &str) {
+    let streams = demux_with(
+        location,
+        demux_pipeline,
+        Some(gst::ClockTime::from_mseconds(1_500)),
+    );
+
+    // Fragments start at keyframes every second, so the seek snaps to the second fragment
+    let first_video_pts = streams.first_video_pts.unwrap();
+    assert!(first_video_pts <= gst::ClockTime::from_mseconds(1_500));
+    assert!(first_video_pts >= gst::ClockTime::from_seconds(1));
+    assert!(streams.video_buffers < 99);
+    assert!(streams.audio_buffers > 0);
+}
+
+#[test]
+fn test_isobmffdemux_fragmented_seek_mfra() {
+    init();
+    test_fragmented_with("isofmp4mux write-mfra=true", |location| {
+        let boxes = top_level_boxes(location)
+            .into_iter()
+            .map(|(fourcc, ..)| fourcc)
+            .collect::<Vec<_>>();
+        assert_eq!(boxes.last(), Some(b"mfra"));
+        assert!(!boxes.contains(b"sidx"));
+
+        // The mfra at the end of the file is only read in pull mode
+        check_fragmented_seek(location, "filesrc name=src ! isobmffdemux name=demux");
+    })
+}
+
+#[test]
+fn test_isobmffdemux_fragmented_seek_sidx() {
+    init();
+    test_fragmented_with("dashmp4mux on-demand=true", |location| {
+        let boxes = top_level_boxes(location)
+            .into_iter()
+            .map(|(fourcc, ..)| fourcc)
+            .collect::<Vec<_>>();
+        assert!(boxes.contains(b"sidx"));
+        assert!(!boxes.contains(b"mfra"));
+
+        check_fragmented_seek(location, "filesrc name=src ! isobmffdemux name=demux");
+        check_fragmented_seek(
+            location,
+            "filesrc name=src ! queue ! isobmffdemux name=demux",
+        );
+    })
+}
+
+#[test]
+fn test_roundtrip_tx3g() {
+    use std::sync::{Arc, Mutex};
+
+    init();
+
+    let Ok(pipeline) = gst::parse::launch(
+        "appsrc name=src format=time caps=text/x-raw,format=utf8 ! \
+         isomp4mux ! filesink name=sink",
+    ) else {
+        println!("could not build encoding pipeline");
+        return;
+    };
+
+    // Cues with gaps in between, which have to be filled with empty samples
+    let cues = [
+        ("Hello", 0, 1_000),
+        ("World", 2_000, 500),
+        ("Goodbye", 3_000, 1_500),
+    ];
+    let appsrc = pipeline
+        .downcast_ref::<gst::Bin>()
+        .unwrap()
+        .by_name("src")
+        .unwrap();
+    for (text, pts, duration) in cues {
+        let mut buffer = gst::Buffer::from_slice(text);
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(gst::ClockTime::from_mseconds(pts));
+            buffer.set_duration(gst::ClockTime::from_mseconds(duration));
+        }
+        let _ = appsrc.emit_by_name::<gst::FlowReturn>("push-buffer", &[&buffer]);
+    }
+    let _ = appsrc.emit_by_name::<gst::FlowReturn>("end-of-stream", &[]);
+
+    run_pipeline(pipeline, |location| {
+        let pipeline =
+            gst::parse::launch("filesrc name=src ! qtdemux name=demux ! fakesink name=sink")
+                .expect("could not build demuxing pipeline");
+        let pipeline = Pipeline(pipeline.downcast::<gst::Pipeline>().unwrap());
+        pipeline
+            .by_name("src")
+            .unwrap()
+            .set_property("location", location.display().to_string());
+
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let sinkpad = pipeline
+            .by_name("sink")
+            .unwrap()
+            .static_pad("sink")
+            .unwrap();
+        {
+            let samples = samples.clone();
+            sinkpad.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+                if let Some(buffer) = info.buffer() {
+                    let map = buffer.map_readable().unwrap();
+                    samples.lock().unwrap().push((
+                        String::from_utf8_lossy(&map).into_owned(),
+                        buffer.pts(),
+                        buffer.duration(),
+                    ));
+                }
+                gst::PadProbeReturn::Ok
+            });
+        }
+
+        pipeline.into_completion();
+
+        let samples = samples.lock().unwrap();
+        let text_samples = samples
+            .iter()
+            .filter(|(text, _, _)| !text.is_empty())
+            .collect::<Vec<_>>();
+        assert_eq!(text_samples.len(), cues.len());
+        for ((text, pts, duration), (expected_text, expected_pts, expected_duration)) in
+            text_samples.into_iter().zip(cues)
+        {
+            assert!(text.contains(expected_text));
+            assert_eq!(*pts, Some(gst::ClockTime::from_mseconds(expected_pts)));
+            assert_eq!(
+                *duration,
+                Some(gst::ClockTime::from_mseconds(expected_duration))
+            );
+        }
+    });
+}
+
+#[test]
+fn test_roundtrip_c608() {
+    use std::sync::{Arc, Mutex};
+
+    init();
+
+    let Ok(pipeline) = gst::parse::launch(
+        "appsrc name=src format=time \
+           caps=closedcaption/x-cea-608,format=raw,framerate=30/1 ! \
+         isomp4mux ! filesink name=sink",
+    ) else {
+        println!("could not build encoding pipeline");
+        return;
+    };
+
+    let appsrc = pipeline
+        .downcast_ref::<gst::Bin>()
+        .unwrap()
+        .by_name("src")
+        .unwrap();
+    let frame_duration = gst::ClockTime::from_nseconds(1_000_000_000 / 30);
+    for i in 0..30u64 {
+        let mut buffer = gst::Buffer::from_slice([0x94, i as u8 | 0x80]);
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(frame_duration * i);
+            buffer.set_duration(frame_duration);
+        }
+        let _ = appsrc.emit_by_name::<gst::FlowReturn>("push-buffer", &[&buffer]);
+    }
+    let _ = appsrc.emit_by_name::<gst::FlowReturn>("end-of-stream", &[]);
+
+    run_pipeline(pipeline, |location| {
+        let pipeline = gst::parse::launch("filesrc name=src ! qtdemux ! fakesink name=sink")
+            .expect("could not build demuxing pipeline");
+        let pipeline = Pipeline(pipeline.downcast::<gst::Pipeline>().unwrap());
+        pipeline
+            .by_name("src")
+            .unwrap()
+            .set_property("location", location.display().to_string());
+
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let sinkpad = pipeline
+            .by_name("sink")
+            .unwrap()
+            .static_pad("sink")
+            .unwrap();
+        {
+            let samples = samples.clone();
+            sinkpad.add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+                if let Some(buffer) = info.buffer() {
+                    let map = buffer.map_readable().unwrap();
+                    samples.lock().unwrap().push(map.to_vec());
+                }
+                gst::PadProbeReturn::Ok
+            });
+        }
+
+        pipeline.into_completion();
+
+        // qtdemux outputs the field 1 byte pairs as S334-1A triplets
+        let samples = samples.lock().unwrap();
+        assert_eq!(samples.len(), 30);
+        for (i, sample) in samples.iter().enumerate() {
+            assert_eq!(sample.as_slice(), [0x80, 0x94, i as u8 | 0x80]);
+        }
+    });
+}
+
+fn test_faststart_with(mux_properties: &str, cb: impl FnOnce(&Path)) {
+    let Ok(pipeline) = gst::parse::launch(&format!(
+        "videotestsrc num-buffers=99 ! x264enc ! mux. \
+         audiotestsrc num-buffers=140 ! fdkaacenc ! mux. \
+         isomp4mux name=mux faststart=true {mux_properties} ! filesink name=sink"
+    )) else {
+        println!("could not build encoding pipeline");
+        return;
+    };
+    run_pipeline(pipeline, cb);
+}
+
+/// Returns the type, offset and size of all top-level boxes.
+fn top_level_boxes(location: &Path) -> Vec<([u8; 4], u64, u64)> {
+    let data = std::fs::read(location).unwrap();
+
+    let mut boxes = vec![];
+    let mut offset = 0;
+    while offset < data.len() {
+        let header = &data[offset..];
+        let fourcc = <[u8; 4]>::try_from(&header[4..8]).unwrap();
+        let size = match u32::from_be_bytes(header[0..4].try_into().unwrap()) {
+            0 => (data.len() - offset) as u64,
+            1 => u64::from_be_bytes(header[8..16].try_into().unwrap()),
+            size => size as u64,
+        };
+        boxes.push((fourcc, offset as u64, size));
+        offset += size as usize;
+    }
+
+    boxes
+}
+
+/// Checks that all chunk offsets of all tracks point into the `mdat` box.
+fn check_chunk_offsets_in_mdat(location: &Path) {
+    let boxes = top_level_boxes(location);
+    let (_, moov_offset, _) = boxes.iter().find(|(fourcc, ..)| fourcc == b"moov").unwrap();
+    let (_, mdat_offset, mdat_size) = boxes.iter().find(|(fourcc, ..)| fourcc == b"mdat").unwrap();
+
+    let mut input = File::open(location).unwrap();
+    input.seek(std::io::SeekFrom::Start(*moov_offset)).unwrap();
+    let header = mp4_atom::Header::read_from(&mut input).unwrap();
+    let moov = mp4_atom::Moov::read_atom(&header, &mut input).unwrap();
+
+    assert_eq!(moov.trak.len(), 2);
+    for trak in &moov.trak {
+        let stbl = &trak.mdia.minf.stbl;
+        let offsets = match (&stbl.stco, &stbl.co64) {
+            (Some(stco), None) => stco.entries.iter().map(|o| *o as u64).collect::<Vec<_>>(),
+            (None, Some(co64)) => co64.entries.clone(),
+            _ => unreachable!(),
+        };
+        assert!(!offsets.is_empty());
+        for offset in offsets {
+            assert!(offset > *mdat_offset && offset < mdat_offset + mdat_size);
+        }
+    }
+}
+
+fn decode_x264_aac(location: &Path) {
+    let Ok(pipeline) = gst::parse::launch(
+        "filesrc name=src ! qtdemux name=demux \
+         demux.audio_0 ! queue ! aacparse ! fdkaacdec ! fakesink \
+         demux.video_0 ! queue ! h264parse ! avdec_h264 ! fakesink",
+    ) else {
+        panic!("could not build decoding pipeline")
+    };
+    let pipeline = Pipeline(pipeline.downcast::<gst::Pipeline>().unwrap());
+    pipeline
+        .by_name("src")
+        .unwrap()
+        .set_property("location", location.display().to_string());
+    pipeline.into_completion();
+}
+
+#[test]
+fn test_faststart_file() {
+    init();
+    test_faststart_with("", |location| {
+        let boxes = top_level_boxes(location)
+            .into_iter()
+            .map(|(fourcc, ..)| fourcc)
+            .collect::<Vec<_>>();
+        assert_eq!(boxes, [*b"ftyp", *b"moov", *b"free", *b"mdat"]);
+
+        check_chunk_offsets_in_mdat(location);
+        decode_x264_aac(location);
+    })
+}
+
+#[test]
+fn test_faststart_reserved_moov() {
+    init();
+    test_faststart_with("moov-reserve-size=65536", |location| {
+        let boxes = top_level_boxes(location);
+        let fourccs = boxes.iter().map(|(fourcc, ..)| *fourcc).collect::<Vec<_>>();
+        assert_eq!(fourccs, [*b"ftyp", *b"moov", *b"free", *b"free", *b"mdat"]);
+
+        // moov box and the following free box fill exactly the reserved space
+        assert_eq!(boxes[1].2 + boxes[2].2, 65536);
+
+        check_chunk_offsets_in_mdat(location);
+        decode_x264_aac(location);
+    })
+}
+
+#[test]
+fn test_faststart_reserved_moov_large() {
+    init();
+    // The free box is bigger than the buffers it is written in
+    test_faststart_with("moov-reserve-size=3000000", |location| {
+        let boxes = top_level_boxes(location);
+        let fourccs = boxes.iter().map(|(fourcc, ..)| *fourcc).collect::<Vec<_>>();
+        assert_eq!(fourccs, [*b"ftyp", *b"moov", *b"free", *b"free", *b"mdat"]);
+        assert_eq!(boxes[1].2 + boxes[2].2, 3000000);
+
+        check_chunk_offsets_in_mdat(location);
+        decode_x264_aac(location);
+    })
+}
+
+#[test]
+fn test_faststart_reserved_moov_too_small() {
+    init();
+    test_faststart_with("moov-reserve-size=64", |location| {
+        let boxes = top_level_boxes(location);
+        let fourccs = boxes.iter().map(|(fourcc, ..)| *fourcc).collect::<Vec<_>>();
+        // The reserved space stays a free box and the moov box is written at the end
+        assert_eq!(fourccs, [*b"ftyp", *b"free", *b"free", *b"mdat", *b"moov"]);
+        assert_eq!(boxes[1].2, 64);
+
+        check_chunk_offsets_in_mdat(location);
+        decode_x264_aac(location);
+    })
+}
+
+/// Runs a robust recording pipeline until all buffers reached the muxer but never finishes the
+/// file, as if the recording was interrupted.
+fn test_interrupted_recording_with(
+    mux_properties: &str,
+    with_recovery_file: bool,
+    cb: impl FnOnce(&Path, &Path),
+) {
+    let Ok(pipeline) = gst::parse::launch(&format!(
+        "videotestsrc num-buffers=99 ! x264enc ! mux. \
+         audiotestsrc num-buffers=140 ! fdkaacenc ! mux. \
+         isomp4mux name=mux recovery-interval=1000000000 {mux_properties} ! filesink name=sink"
+    )) else {
+        println!("could not build encoding pipeline");
+        return;
+    };
+    let pipeline = Pipeline(pipeline.downcast::<gst::Pipeline>().unwrap());
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let location = dir.path().join("test.mp4");
+    let recovery_location = dir.path().join("test.mp4.recovery");
+
+    let sink = pipeline.by_name("sink").unwrap();
+    sink.set_property("location", location.to_str().expect("Non-UTF8 filename"));
+
+    let mux = pipeline.by_name("mux").unwrap();
+    if with_recovery_file {
+        mux.set_property(
+            "recovery-location",
+            recovery_location.to_str().expect("Non-UTF8 filename"),
+        );
+    }
+
+    // Drop EOS before it reaches the muxer so that the file is never finished
+    for pad in mux.sink_pads() {
+        pad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, |pad, info| {
+            let Some(gst::PadProbeData::Event(ref event)) = info.data else {
+                return gst::PadProbeReturn::Ok;
+            };
+            if event.type_() != gst::EventType::Eos {
+                return gst::PadProbeReturn::Ok;
+            }
+
+            let element = pad.parent_element().unwrap();
+            let _ = element.post_message(
+                gst::message::Application::builder(gst::Structure::new_empty("eos-dropped"))
+                    .src(pad)
+                    .build(),
+            );
+            gst::PadProbeReturn::Drop
+        });
+    }
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .expect("Unable to set the pipeline to the `Playing` state");
+
+    let mut num_eos_dropped = 0;
+    for msg in pipeline.bus().unwrap().iter_timed(gst::ClockTime::NONE) {
+        use gst::MessageView;
+
+        match msg.view() {
+            MessageView::Application(..) => {
+                num_eos_dropped += 1;
+                if num_eos_dropped == mux.sink_pads().len() {
+                    break;
+                }
+            }
+            MessageView::Eos(..) => unreachable!(),
+            MessageView::Error(err) => {
+                panic!(
+                    "Error from {:?}: {} ({:?})",
+                    err.src().map(|s| s.path_string()),
+                    err.error(),
+                    err.debug()
+                );
+            }
+            _ => (),
+        }
+    }
+
+    pipeline
+        .set_state(gst::State::Null)
+        .expect("Unable to set the pipeline to the `Null` state");
+
+    cb(&location, &recovery_location)
+}
+
+#[test]
+fn test_recovery_reserved_moov() {
+    init();
+    test_interrupted_recording_with(
+        "moov-reserve-size=65536",
+        false,
+        |location, recovery_location| {
+            let fourccs = top_level_boxes(location)
+                .into_iter()
+                .map(|(fourcc, ..)| fourcc)
+                .collect::<Vec<_>>();
+            assert_eq!(fourccs, [*b"ftyp", *b"moov", *b"free", *b"free", *b"mdat"]);
+            assert!(!recovery_location.exists());
+
+            gstmp4::recovery::recover_file(location, None).unwrap();
+
+            let fourccs = top_level_boxes(location)
+                .into_iter()
+                .map(|(fourcc, ..)| fourcc)
+                .collect::<Vec<_>>();
+            assert_eq!(fourccs, [*b"ftyp", *b"moov", *b"free", *b"free", *b"mdat"]);
+
+            check_chunk_offsets_in_mdat(location);
+            decode_x264_aac(location);
+        },
+    )
+}
+
+#[test]
+fn test_recovery_file() {
+    init();
+    test_interrupted_recording_with("", true, |location, recovery_location| {
+        let fourccs = top_level_boxes(location)
+            .into_iter()
+            .map(|(fourcc, ..)| fourcc)
+            .collect::<Vec<_>>();
+        assert_eq!(fourccs, [*b"ftyp", *b"free", *b"mdat"]);
+        assert!(recovery_location.exists());
+
+        // Without the recovery file there is nothing to recover from
+        assert!(gstmp4::recovery::recover_file(location, None).is_err());
+
+        gstmp4::recovery::recover_file(location, Some(recovery_location)).unwrap();
+
+        let fourccs = top_level_boxes(location)
+            .into_iter()
+            .map(|(fourcc, ..)| fourcc)
+            .collect::<Vec<_>>();
+        assert_eq!(fourccs, [*b"ftyp", *b"free", *b"mdat", *b"moov"]);
+
+        check_chunk_offsets_in_mdat(location);
+        decode_x264_aac(location);
+
+        // Recovering a second time fails as the file is finished now
+        assert!(gstmp4::recovery::recover_file(location, Some(recovery_location)).is_err());
+    })
+}
+
+#[test]
+fn test_recovery_interval_property() {
+    init();
+
+    let mux = gst::ElementFactory::make("isomp4mux").build().unwrap();
+    assert_eq!(mux.property::<u64>("recovery-interval"), 0);
+
+    mux.set_property("recovery-interval", 1_000_000_000u64);
+    assert_eq!(mux.property::<u64>("recovery-interval"), 1_000_000_000);
+
+    mux.set_property("recovery-interval", 0u64);
+    assert_eq!(mux.property::<u64>("recovery-interval"), 0);
+}
+
+#[test]
+fn test_recovery_finished() {
+    init();
+
+    let Ok(pipeline) = gst::parse::launch(
+        "videotestsrc num-buffers=99 ! x264enc ! mux. \
+         audiotestsrc num-buffers=140 ! fdkaacenc ! mux. \
+         isomp4mux name=mux recovery-interval=1000000000 moov-reserve-size=65536 ! filesink name=sink",
+    ) else {
+        println!("could not build encoding pipeline");
+        return;
+    };
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let recovery_location = dir.path().join("test.mp4.recovery");
+    pipeline
+        .downcast_ref::<gst::Bin>()
+        .unwrap()
+        .by_name("mux")
+        .unwrap()
+        .set_property(
+            "recovery-location",
+            recovery_location.to_str().expect("Non-UTF8 filename"),
+        );
+
+    run_pipeline(pipeline, |location| {
+        // The final moov box replaces the recovery moov box in the reserved space
+        let fourccs = top_level_boxes(location)
+            .into_iter()
+            .map(|(fourcc, ..)| fourcc)
+            .collect::<Vec<_>>();
+        assert_eq!(fourccs, [*b"ftyp", *b"moov", *b"free", *b"free", *b"mdat"]);
+        assert!(!recovery_location.exists());
+
+        check_chunk_offsets_in_mdat(location);
+        decode_x264_aac(location);
+    });
+}
@@ -0,0 +1,1725 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use super::template;
+use super::DashSinkMpdType;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use gio::prelude::*;
+use gst::glib;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::Write;
+use std::path;
+use std::sync::{LazyLock, Mutex};
+
+const DEFAULT_MPD_LOCATION: &str = "manifest.mpd";
+const DEFAULT_INIT_LOCATION: &str = "init_$RepresentationID$.mp4";
+const DEFAULT_LOCATION: &str = "segment_$RepresentationID$_$Number%05d$.m4s";
+const DEFAULT_TARGET_DURATION: u32 = 10;
+const DEFAULT_CHUNK_DURATION: Option<gst::ClockTime> = gst::ClockTime::NONE;
+const DEFAULT_MPD_TYPE: DashSinkMpdType = DashSinkMpdType::Dynamic;
+const DEFAULT_MAX_SEGMENTS: u32 = 5;
+const DEFAULT_MAX_FILES: u32 = 10;
+const DEFAULT_MIN_BUFFER_TIME: gst::ClockTime = gst::ClockTime::from_seconds(2);
+const DEFAULT_UTC_TIMING_SCHEME: &str = UTC_TIMING_DIRECT_SCHEME;
+const DEFAULT_SYNC: bool = true;
+const DEFAULT_LATENCY: gst::ClockTime =
+    gst::ClockTime::from_mseconds((DEFAULT_TARGET_DURATION * 500) as u64);
+const DEFAULT_ADAPTATION_SET_ID: i32 = -1;
+const DEFAULT_BANDWIDTH: u32 = 0;
+
+const SIGNAL_GET_MPD_STREAM: &str = "get-mpd-stream";
+const SIGNAL_GET_INIT_STREAM: &str = "get-init-stream";
+const SIGNAL_GET_FRAGMENT_STREAM: &str = "get-fragment-stream";
+const SIGNAL_DELETE_FRAGMENT: &str = "delete-fragment";
+
+const UTC_TIMING_DIRECT_SCHEME: &str = "urn:mpeg:dash:utc:direct:2014";
+const AUDIO_CHANNEL_CONFIGURATION_SCHEME: &str =
+    "urn:mpeg:dash:23003:3:audio_channel_configuration:2011";
+const LIVE_PROFILE: &str = "urn:mpeg:dash:profile:isoff-live:2011";
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new("dashsink", gst::DebugColorFlags::empty(), Some("DASH sink"))
+});
+
+#[derive(Clone)]
+struct DashSinkPadSettings {
+    representation_id: Option<String>,
+    adaptation_set_id: i32,
+    bandwidth: u32,
+}
+
+impl Default for DashSinkPadSettings {
+    fn default() -> Self {
+        Self {
+            representation_id: None,
+            adaptation_set_id: DEFAULT_ADAPTATION_SET_ID,
+            bandwidth: DEFAULT_BANDWIDTH,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct DashSinkPad {
+    settings: Mutex<DashSinkPadSettings>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for DashSinkPad {
+    const NAME: &'static str = "GstRsDashSinkPad";
+    type Type = super::DashSinkPad;
+    type ParentType = gst::GhostPad;
+}
+
+impl ObjectImpl for DashSinkPad {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                /**
+                 * GstRsDashSinkPad:representation-id:
+                 *
+                 * Identifier of the representation in the MPD. Defaults to the pad name.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecString::builder("representation-id")
+                    .nick("Representation ID")
+                    .blurb("Identifier of the representation in the MPD (NULL = pad name)")
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRsDashSinkPad:adaptation-set-id:
+                 *
+                 * Identifier of the adaptation set this representation is part of. All
+                 * representations with the same identifier are placed into the same adaptation
+                 * set. By default representations are grouped by their content type.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecInt::builder("adaptation-set-id")
+                    .nick("Adaptation Set ID")
+                    .blurb("Identifier of the adaptation set (-1 = group by content type)")
+                    .minimum(-1)
+                    .default_value(DEFAULT_ADAPTATION_SET_ID)
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRsDashSinkPad:bandwidth:
+                 *
+                 * Bandwidth of the representation in bits per second. If 0 then the maximum
+                 * bitrate of the segments written so far is used.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecUInt::builder("bandwidth")
+                    .nick("Bandwidth")
+                    .blurb("Bandwidth of the representation in bits per second (0 = measured)")
+                    .default_value(DEFAULT_BANDWIDTH)
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "representation-id" => {
+                settings.representation_id = value.get().expect("type checked upstream");
+            }
+            "adaptation-set-id" => {
+                settings.adaptation_set_id = value.get().expect("type checked upstream");
+            }
+            "bandwidth" => {
+                settings.bandwidth = value.get().expect("type checked upstream");
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "representation-id" => settings.representation_id.to_value(),
+            "adaptation-set-id" => settings.adaptation_set_id.to_value(),
+            "bandwidth" => settings.bandwidth.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+impl GstObjectImpl for DashSinkPad {}
+
+impl PadImpl for DashSinkPad {}
+
+impl ProxyPadImpl for DashSinkPad {}
+
+impl GhostPadImpl for DashSinkPad {}
+
+impl DashSinkPad {
+    fn representation_id(&self) -> String {
+        self.settings
+            .lock()
+            .unwrap()
+            .representation_id
+            .clone()
+            .unwrap_or_else(|| self.obj().name().to_string())
+    }
+}
+
+#[derive(Clone)]
+struct Settings {
+    mpd_location: String,
+    mpd_root: Option<String>,
+    init_location: String,
+    location: String,
+    target_duration: u32,
+    chunk_duration: Option<gst::ClockTime>,
+    mpd_type: DashSinkMpdType,
+    max_segments: u32,
+    max_files: u32,
+    min_buffer_time: gst::ClockTime,
+    utc_timing_scheme: Option<String>,
+    utc_timing_value: Option<String>,
+    sync: bool,
+    latency: gst::ClockTime,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            mpd_location: String::from(DEFAULT_MPD_LOCATION),
+            mpd_root: None,
+            init_location: String::from(DEFAULT_INIT_LOCATION),
+            location: String::from(DEFAULT_LOCATION),
+            target_duration: DEFAULT_TARGET_DURATION,
+            chunk_duration: DEFAULT_CHUNK_DURATION,
+            mpd_type: DEFAULT_MPD_TYPE,
+            max_segments: DEFAULT_MAX_SEGMENTS,
+            max_files: DEFAULT_MAX_FILES,
+            min_buffer_time: DEFAULT_MIN_BUFFER_TIME,
+            utc_timing_scheme: Some(String::from(DEFAULT_UTC_TIMING_SCHEME)),
+            utc_timing_value: None,
+            sync: DEFAULT_SYNC,
+            latency: DEFAULT_LATENCY,
+        }
+    }
+}
+
+/// A segment that is listed in the `SegmentTimeline`.
+struct Segment {
+    number: u64,
+    /// Start time in the track timescale.
+    time: u64,
+    /// Duration in the track timescale.
+    duration: u64,
+}
+
+// gio::OutputStream is not thread-safe but the stream of the segment that is currently written
+// is only ever accessed with the state lock held.
+struct SegmentStream(gio::OutputStreamWrite<gio::OutputStream>);
+
+unsafe impl Send for SegmentStream {}
+
+/// The segment that is currently written. With chunking enabled this stays open until the
+/// next fragment starts.
+struct PendingSegment {
+    stream: SegmentStream,
+    location: String,
+    number: u64,
+    time: u64,
+    running_time: Option<gst::ClockTime>,
+    duration: gst::ClockTime,
+    size: u64,
+}
+
+struct Stream {
+    pad: super::DashSinkPad,
+    muxer: gst::Element,
+    appsink: gst_app::AppSink,
+
+    /// Timescale of the track from the `mdhd` box of the initialization segment.
+    timescale: Option<u32>,
+    caps: Option<gst::Caps>,
+    next_number: u64,
+    /// Expected start time of the next segment in the track timescale.
+    next_time: Option<u64>,
+    segments: VecDeque<Segment>,
+    segment_locations: VecDeque<String>,
+    pending_segment: Option<PendingSegment>,
+    max_bitrate: u64,
+    eos: bool,
+}
+
+impl Stream {
+    fn new(pad: super::DashSinkPad, muxer: gst::Element, appsink: gst_app::AppSink) -> Self {
+        Self {
+            pad,
+            muxer,
+            appsink,
+            timescale: None,
+            caps: None,
+            next_number: 1,
+            next_time: None,
+            segments: VecDeque::new(),
+            segment_locations: VecDeque::new(),
+            pending_segment: None,
+            max_bitrate: 0,
+            eos: false,
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Stream::new(self.pad.clone(), self.muxer.clone(), self.appsink.clone());
+    }
+}
+
+#[derive(Default)]
+struct State {
+    streams: Vec<Stream>,
+    audio_pad_serial: u32,
+    video_pad_serial: u32,
+    /// Media time of the first segment, this is mapped to the start of the period.
+    presentation_start: Option<gst::ClockTime>,
+    /// Wall clock time corresponding to the presentation start.
+    availability_start_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+pub struct DashSink {
+    settings: Mutex<Settings>,
+    state: Mutex<State>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for DashSink {
+    const NAME: &'static str = "GstRsDashSink";
+    type Type = super::DashSink;
+    type ParentType = gst::Bin;
+    type Interfaces = (gst::ChildProxy,);
+}
+
+impl ObjectImpl for DashSink {
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let obj = self.obj();
+        obj.set_suppressed_flags(gst::ElementFlags::SINK | gst::ElementFlags::SOURCE);
+        obj.set_element_flags(gst::ElementFlags::SINK);
+    }
+
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecString::builder("mpd-location")
+                    .nick("MPD Location")
+                    .blurb("Location of the MPD to write")
+                    .default_value(Some(DEFAULT_MPD_LOCATION))
+                    .build(),
+                glib::ParamSpecString::builder("mpd-root")
+                    .nick("MPD Root")
+                    .blurb("Base path for the initialization and media segments in the MPD")
+                    .build(),
+                glib::ParamSpecString::builder("init-location")
+                    .nick("Init Location")
+                    .blurb("Location template of the initialization segment files, can contain $RepresentationID$ and $Bandwidth$")
+                    .default_value(Some(DEFAULT_INIT_LOCATION))
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecString::builder("location")
+                    .nick("Location")
+                    .blurb("Location template of the media segment files, can contain $RepresentationID$, $Number$, $Time$ and $Bandwidth$")
+                    .default_value(Some(DEFAULT_LOCATION))
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("target-duration")
+                    .nick("Target duration")
+                    .blurb("The target duration in seconds of a segment")
+                    .minimum(1)
+                    .default_value(DEFAULT_TARGET_DURATION)
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRsDashSink:chunk-duration:
+                 *
+                 * Duration of the chunks of each segment. Chunks are written to the segment
+                 * stream as soon as they are available and the MPD signals the earlier
+                 * availability of the segments via `availabilityTimeOffset`.
+                 *
+                 * Note that the default handler of `get-fragment-stream` only makes the
+                 * segment file visible once it is complete.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecUInt64::builder("chunk-duration")
+                    .nick("Chunk Duration")
+                    .blurb("Duration of the chunks of each segment (default = no chunks)")
+                    .default_value(u64::MAX)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecEnum::builder_with_default("mpd-type", DEFAULT_MPD_TYPE)
+                    .nick("MPD Type")
+                    .blurb("Type of the MPD to write")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("max-segments")
+                    .nick("Max segments")
+                    .blurb("Maximum number of segments per representation in a dynamic MPD (0 = unlimited)")
+                    .default_value(DEFAULT_MAX_SEGMENTS)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("max-files")
+                    .nick("Max files")
+                    .blurb("Maximum number of segment files per representation to keep on disk for a dynamic MPD (0 = unlimited)")
+                    .default_value(DEFAULT_MAX_FILES)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt64::builder("min-buffer-time")
+                    .nick("Min Buffer Time")
+                    .blurb("Minimum buffer time signalled in the MPD (in nanoseconds)")
+                    .maximum(i64::MAX as u64)
+                    .default_value(DEFAULT_MIN_BUFFER_TIME.nseconds())
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRsDashSink:utc-timing-scheme:
+                 *
+                 * Scheme of the `UTCTiming` element of a dynamic MPD. No `UTCTiming` element is
+                 * written if this is NULL.
+                 *
+                 * For the `urn:mpeg:dash:utc:direct:2014` scheme the current time is used as
+                 * value unless `utc-timing-value` is set.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecString::builder("utc-timing-scheme")
+                    .nick("UTC Timing Scheme")
+                    .blurb("Scheme of the UTCTiming element of a dynamic MPD")
+                    .default_value(Some(DEFAULT_UTC_TIMING_SCHEME))
+                    .build(),
+                glib::ParamSpecString::builder("utc-timing-value")
+                    .nick("UTC Timing Value")
+                    .blurb("Value of the UTCTiming element of a dynamic MPD, e.g. the URL of a time server")
+                    .build(),
+                glib::ParamSpecBoolean::builder("sync")
+                    .nick("Sync")
+                    .blurb("Sync on the clock")
+                    .default_value(DEFAULT_SYNC)
+                    .build(),
+                glib::ParamSpecUInt64::builder("latency")
+                    .nick("Latency")
+                    .blurb(
+                        "Additional latency to allow upstream to take longer to \
+                         produce buffers for the current position (in nanoseconds)",
+                    )
+                    .maximum(i64::MAX as u64)
+                    .default_value(DEFAULT_LATENCY.nseconds())
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "mpd-location" => {
+                settings.mpd_location = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream")
+                    .unwrap_or_else(|| DEFAULT_MPD_LOCATION.into());
+            }
+            "mpd-root" => {
+                settings.mpd_root = value.get().expect("type checked upstream");
+            }
+            "init-location" => {
+                settings.init_location = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream")
+                    .unwrap_or_else(|| DEFAULT_INIT_LOCATION.into());
+            }
+            "location" => {
+                settings.location = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream")
+                    .unwrap_or_else(|| DEFAULT_LOCATION.into());
+            }
+            "target-duration" => {
+                settings.target_duration = value.get().expect("type checked upstream");
+                let state = self.state.lock().unwrap();
+                for stream in &state.streams {
+                    stream.muxer.set_property(
+                        "fragment-duration",
+                        gst::ClockTime::from_seconds(settings.target_duration as u64),
+                    );
+                }
+            }
+            "chunk-duration" => {
+                settings.chunk_duration = value.get().expect("type checked upstream");
+                let state = self.state.lock().unwrap();
+                for stream in &state.streams {
+                    stream
+                        .muxer
+                        .set_property("chunk-duration", settings.chunk_duration);
+                }
+            }
+            "mpd-type" => {
+                settings.mpd_type = value.get().expect("type checked upstream");
+            }
+            "max-segments" => {
+                settings.max_segments = value.get().expect("type checked upstream");
+            }
+            "max-files" => {
+                settings.max_files = value.get().expect("type checked upstream");
+            }
+            "min-buffer-time" => {
+                settings.min_buffer_time = value.get().expect("type checked upstream");
+            }
+            "utc-timing-scheme" => {
+                settings.utc_timing_scheme = value.get().expect("type checked upstream");
+            }
+            "utc-timing-value" => {
+                settings.utc_timing_value = value.get().expect("type checked upstream");
+            }
+            "sync" => {
+                settings.sync = value.get().expect("type checked upstream");
+                let state = self.state.lock().unwrap();
+                for stream in &state.streams {
+                    stream.appsink.set_property("sync", settings.sync);
+                }
+            }
+            "latency" => {
+                settings.latency = value.get().expect("type checked upstream");
+                let state = self.state.lock().unwrap();
+                for stream in &state.streams {
+                    stream.muxer.set_property("latency", settings.latency);
+                }
+            }
+            _ => unimplemented!(),
+        };
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "mpd-location" => settings.mpd_location.to_value(),
+            "mpd-root" => settings.mpd_root.to_value(),
+            "init-location" => settings.init_location.to_value(),
+            "location" => settings.location.to_value(),
+            "target-duration" => settings.target_duration.to_value(),
+            "chunk-duration" => settings.chunk_duration.to_value(),
+            "mpd-type" => settings.mpd_type.to_value(),
+            "max-segments" => settings.max_segments.to_value(),
+            "max-files" => settings.max_files.to_value(),
+            "min-buffer-time" => settings.min_buffer_time.to_value(),
+            "utc-timing-scheme" => settings.utc_timing_scheme.to_value(),
+            "utc-timing-value" => settings.utc_timing_value.to_value(),
+            "sync" => settings.sync.to_value(),
+            "latency" => settings.latency.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn signals() -> &'static [glib::subclass::Signal] {
+        static SIGNALS: LazyLock<Vec<glib::subclass::Signal>> = LazyLock::new(|| {
+            vec![
+                glib::subclass::Signal::builder(SIGNAL_GET_MPD_STREAM)
+                    .param_types([String::static_type()])
+                    .return_type::<Option<gio::OutputStream>>()
+                    .class_handler(|args| {
+                        let elem = args[0].get::<super::DashSink>().expect("signal arg");
+                        let mpd_location = args[1].get::<String>().expect("signal arg");
+                        let imp = elem.imp();
+
+                        Some(imp.new_file_stream(&mpd_location).ok().to_value())
+                    })
+                    .accumulator(|_hint, _acc, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+                glib::subclass::Signal::builder(SIGNAL_GET_INIT_STREAM)
+                    .param_types([String::static_type()])
+                    .return_type::<Option<gio::OutputStream>>()
+                    .class_handler(|args| {
+                        let elem = args[0].get::<super::DashSink>().expect("signal arg");
+                        let init_location = args[1].get::<String>().expect("signal arg");
+                        let imp = elem.imp();
+
+                        Some(imp.new_file_stream(&init_location).ok().to_value())
+                    })
+                    .accumulator(|_hint, _acc, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+                glib::subclass::Signal::builder(SIGNAL_GET_FRAGMENT_STREAM)
+                    .param_types([String::static_type()])
+                    .return_type::<Option<gio::OutputStream>>()
+                    .class_handler(|args| {
+                        let elem = args[0].get::<super::DashSink>().expect("signal arg");
+                        let fragment_location = args[1].get::<String>().expect("signal arg");
+                        let imp = elem.imp();
+
+                        Some(imp.new_file_stream(&fragment_location).ok().to_value())
+                    })
+                    .accumulator(|_hint, _acc, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+                glib::subclass::Signal::builder(SIGNAL_DELETE_FRAGMENT)
+                    .param_types([String::static_type()])
+                    .return_type::<bool>()
+                    .class_handler(|args| {
+                        let elem = args[0].get::<super::DashSink>().expect("signal arg");
+                        let fragment_location = args[1].get::<String>().expect("signal arg");
+                        let imp = elem.imp();
+
+                        imp.delete_fragment(&fragment_location);
+                        Some(true.to_value())
+                    })
+                    .accumulator(|_hint, _acc, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+            ]
+        });
+
+        SIGNALS.as_ref()
+    }
+}
+
+impl GstObjectImpl for DashSink {}
+
+impl ElementImpl for DashSink {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "DASH Sink",
+                "Sink/Muxer",
+                "Dynamic Adaptive Streaming over HTTP sink",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let caps_any = gst::Caps::new_any();
+
+            let audio_pad_template = gst::PadTemplate::with_gtype(
+                "audio_%u",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Request,
+                &caps_any,
+                super::DashSinkPad::static_type(),
+            )
+            .unwrap();
+
+            let video_pad_template = gst::PadTemplate::with_gtype(
+                "video_%u",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Request,
+                &caps_any,
+                super::DashSinkPad::static_type(),
+            )
+            .unwrap();
+
+            vec![audio_pad_template, video_pad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::ReadyToPaused {
+            let settings = self.settings.lock().unwrap().clone();
+            let mut state = self.state.lock().unwrap();
+
+            if let Err(err) = Self::validate(&settings, &state) {
+                gst::element_imp_error!(self, gst::ResourceError::Settings, ["{err}"]);
+                return Err(gst::StateChangeError);
+            }
+
+            state.presentation_start = None;
+            state.availability_start_time = None;
+            for stream in &mut state.streams {
+                stream.reset();
+            }
+        }
+
+        self.parent_change_state(transition)
+    }
+
+    fn request_new_pad(
+        &self,
+        templ: &gst::PadTemplate,
+        name: Option<&str>,
+        _caps: Option<&gst::Caps>,
+    ) -> Option<gst::Pad> {
+        let mut state = self.state.lock().unwrap();
+
+        let pad_name = match (templ.name_template(), name) {
+            (_, Some(name)) => name.to_string(),
+            ("audio_%u", None) => {
+                let name = format!("audio_{}", state.audio_pad_serial);
+                state.audio_pad_serial += 1;
+                name
+            }
+            ("video_%u", None) => {
+                let name = format!("video_{}", state.video_pad_serial);
+                state.video_pad_serial += 1;
+                name
+            }
+            (other_name, None) => {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "requested_new_pad: name \"{}\" is not one of audio, video",
+                    other_name
+                );
+                return None;
+            }
+        };
+
+        if state
+            .streams
+            .iter()
+            .any(|stream| stream.pad.name() == pad_name)
+        {
+            gst::warning!(CAT, imp = self, "Pad {pad_name} already exists");
+            return None;
+        }
+
+        let settings = self.settings.lock().unwrap().clone();
+
+        let muxer = match gst::ElementFactory::make("dashmp4mux")
+            .name(format!("muxer_{pad_name}"))
+            .property(
+                "fragment-duration",
+                gst::ClockTime::from_seconds(settings.target_duration as u64),
+            )
+            .property("chunk-duration", settings.chunk_duration)
+            .property("latency", settings.latency)
+            .build()
+        {
+            Ok(muxer) => muxer,
+            Err(err) => {
+                gst::error!(CAT, imp = self, "Failed to create dashmp4mux: {err}");
+                return None;
+            }
+        };
+
+        let appsink = gst_app::AppSink::builder()
+            .buffer_list(true)
+            .sync(settings.sync)
+            .name(format!("sink_{pad_name}"))
+            .build();
+
+        let obj = self.obj();
+        obj.add_many([&muxer, appsink.upcast_ref()]).unwrap();
+        muxer.link(&appsink).unwrap();
+
+        let self_weak = self.downgrade();
+        let sample_pad_name = pad_name.clone();
+        let eos_pad_name = pad_name.clone();
+        appsink.set_callbacks(
+            gst_app::AppSinkCallbacks::builder()
+                .new_sample(move |sink| {
+                    let Some(imp) = self_weak.upgrade() else {
+                        return Err(gst::FlowError::Eos);
+                    };
+
+                    let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    imp.on_new_sample(&sample_pad_name, sample)
+                })
+                .eos({
+                    let self_weak = self.downgrade();
+                    move |_sink| {
+                        let Some(imp) = self_weak.upgrade() else {
+                            return;
+                        };
+
+                        imp.on_eos(&eos_pad_name);
+                    }
+                })
+                .build(),
+        );
+
+        let sink_pad = gst::PadBuilder::<super::DashSinkPad>::from_template(templ)
+            .name(pad_name.as_str())
+            .build();
+        sink_pad
+            .set_target(Some(&muxer.static_pad("sink").unwrap()))
+            .unwrap();
+
+        state.streams.push(Stream::new(
+            sink_pad.clone(),
+            muxer.clone(),
+            appsink.clone(),
+        ));
+        drop(state);
+
+        muxer.sync_state_with_parent().unwrap();
+        appsink.sync_state_with_parent().unwrap();
+
+        obj.add_pad(&sink_pad).expect("Failed to add pad");
+        obj.child_added(sink_pad.upcast_ref::<gst::Object>(), &sink_pad.name());
+
+        Some(sink_pad.upcast())
+    }
+
+    fn release_pad(&self, pad: &gst::Pad) {
+        let mut state = self.state.lock().unwrap();
+        let Some(idx) = state
+            .streams
+            .iter()
+            .position(|stream| stream.pad.upcast_ref::<gst::Pad>() == pad)
+        else {
+            return;
+        };
+        let stream = state.streams.remove(idx);
+        drop(state);
+
+        let obj = self.obj();
+
+        let _ = pad.set_active(false);
+        let _ = stream.muxer.set_state(gst::State::Null);
+        let _ = stream.appsink.set_state(gst::State::Null);
+        if let Err(err) = obj.remove_many([&stream.muxer, stream.appsink.upcast_ref()]) {
+            gst::error!(
+                CAT,
+                imp = self,
+                "Failed to remove elements for pad {}: {err}",
+                pad.name()
+            );
+        }
+
+        obj.remove_pad(pad).expect("Failed to remove pad");
+        obj.child_removed(pad.upcast_ref::<gst::Object>(), &pad.name());
+    }
+}
+
+impl BinImpl for DashSink {}
+
+impl ChildProxyImpl for DashSink {
+    fn children_count(&self) -> u32 {
+        let object = self.obj();
+        object.num_pads() as u32
+    }
+
+    fn child_by_name(&self, name: &str) -> Option<glib::Object> {
+        let object = self.obj();
+        object
+            .pads()
+            .into_iter()
+            .find(|p| p.name() == name)
+            .map(|p| p.upcast())
+    }
+
+    fn child_by_index(&self, index: u32) -> Option<glib::Object> {
+        let object = self.obj();
+        object
+            .pads()
+            .into_iter()
+            .nth(index as usize)
+            .map(|p| p.upcast())
+    }
+}
+
+impl DashSink {
+    fn validate(settings: &Settings, state: &State) -> Result<(), String> {
+        let init_identifiers = template::identifiers(&settings.init_location)?;
+        let identifiers = template::identifiers(&settings.location)?;
+
+        if init_identifiers
+            .iter()
+            .any(|id| *id == "Number" || *id == "Time")
+        {
+            return Err(String::from(
+                "Initialization segment template can't contain $Number$ or $Time$",
+            ));
+        }
+
+        if !identifiers
+            .iter()
+            .any(|id| *id == "Number" || *id == "Time")
+        {
+            return Err(String::from(
+                "Media segment template must contain $Number$ or $Time$",
+            ));
+        }
+
+        if state.streams.len() > 1
+            && (!init_identifiers.contains(&"RepresentationID")
+                || !identifiers.contains(&"RepresentationID"))
+        {
+            return Err(String::from(
+                "Segment templates must contain $RepresentationID$ for multiple representations",
+            ));
+        }
+
+        if (init_identifiers.contains(&"Bandwidth") || identifiers.contains(&"Bandwidth"))
+            && state
+                .streams
+                .iter()
+                .any(|stream| stream.pad.imp().settings.lock().unwrap().bandwidth == 0)
+        {
+            return Err(String::from(
+                "Segment templates can only contain $Bandwidth$ if all pads have a bandwidth set",
+            ));
+        }
+
+        let mut representation_ids = state
+            .streams
+            .iter()
+            .map(|stream| stream.pad.imp().representation_id())
+            .collect::<Vec<_>>();
+        representation_ids.sort();
+        if let Some(id) = representation_ids.windows(2).find(|ids| ids[0] == ids[1]) {
+            return Err(format!("Duplicated representation id '{}'", id[0]));
+        }
+
+        Ok(())
+    }
+
+    fn template_values<'a>(stream: &Stream, representation_id: &'a str) -> template::Values<'a> {
+        let bandwidth = stream.pad.imp().settings.lock().unwrap().bandwidth;
+
+        template::Values {
+            representation_id,
+            bandwidth: (bandwidth > 0).then_some(bandwidth as u64),
+            ..Default::default()
+        }
+    }
+
+    fn on_new_sample(
+        &self,
+        pad_name: &str,
+        sample: gst::Sample,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let settings = self.settings.lock().unwrap().clone();
+        let mut state = self.state.lock().unwrap();
+        let Some(idx) = state
+            .streams
+            .iter()
+            .position(|stream| stream.pad.name() == pad_name)
+        else {
+            return Err(gst::FlowError::Flushing);
+        };
+
+        let mut buffer_list = sample.buffer_list_owned().unwrap();
+        let mut first = buffer_list.get(0).unwrap();
+
+        if first
+            .flags()
+            .contains(gst::BufferFlags::DISCONT | gst::BufferFlags::HEADER)
+        {
+            self.write_init_segment(&settings, &mut state.streams[idx], first)?;
+
+            buffer_list.make_mut().remove(0..1);
+            if buffer_list.is_empty() {
+                return Ok(gst::FlowSuccess::Ok);
+            }
+
+            first = buffer_list.get(0).unwrap();
+        }
+
+        // The first chunk of a fragment starts with a fragment header without DELTA_UNIT flag,
+        // all following chunks start with a chunk header that has the DELTA_UNIT flag set.
+        if !first.flags().contains(gst::BufferFlags::DELTA_UNIT) {
+            self.finish_segment(&settings, &mut state, idx)?;
+
+            let segment = sample
+                .segment()
+                .unwrap()
+                .downcast_ref::<gst::ClockTime>()
+                .unwrap();
+            let running_time = segment.to_running_time(first.pts().unwrap());
+
+            self.start_segment(&settings, &mut state.streams[idx], first, running_time)?;
+        } else {
+            let Some(pending_segment) = state.streams[idx].pending_segment.as_mut() else {
+                gst::element_imp_error!(
+                    self,
+                    gst::StreamError::Format,
+                    ["Received chunk without fragment"]
+                );
+                return Err(gst::FlowError::Error);
+            };
+
+            pending_segment.duration += first.duration().unwrap_or(gst::ClockTime::ZERO);
+        }
+
+        let pending_segment = state.streams[idx].pending_segment.as_mut().unwrap();
+        for buffer in &*buffer_list {
+            let map = buffer.map_readable().unwrap();
+
+            pending_segment.stream.0.write_all(&map).map_err(|_| {
+                gst::error!(CAT, imp = self, "Couldn't write segment to output stream",);
+                gst::FlowError::Error
+            })?;
+            pending_segment.size += map.size() as u64;
+        }
+
+        pending_segment.stream.0.flush().map_err(|_| {
+            gst::error!(CAT, imp = self, "Couldn't flush output stream",);
+            gst::FlowError::Error
+        })?;
+
+        if settings.chunk_duration.is_none() {
+            self.finish_segment(&settings, &mut state, idx)?;
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn on_eos(&self, pad_name: &str) {
+        let settings = self.settings.lock().unwrap().clone();
+        let mut state = self.state.lock().unwrap();
+        let Some(idx) = state
+            .streams
+            .iter()
+            .position(|stream| stream.pad.name() == pad_name)
+        else {
+            return;
+        };
+
+        gst::debug!(CAT, imp = self, "Got EOS for pad {pad_name}");
+
+        if self.finish_segment(&settings, &mut state, idx).is_err() {
+            return;
+        }
+        state.streams[idx].eos = true;
+
+        if state.streams.iter().all(|stream| stream.eos) {
+            gst::info!(CAT, imp = self, "All streams are EOS, writing final MPD");
+            let _ = self.write_mpd(&settings, &state, true);
+        }
+    }
+
+    fn write_init_segment(
+        &self,
+        settings: &Settings,
+        stream: &mut Stream,
+        buffer: &gst::BufferRef,
+    ) -> Result<(), gst::FlowError> {
+        let map = buffer.map_readable().unwrap();
+
+        let Some(timescale) = parse_timescale(&map) else {
+            gst::element_imp_error!(
+                self,
+                gst::StreamError::Format,
+                ["Initialization segment without track timescale"]
+            );
+            return Err(gst::FlowError::Error);
+        };
+
+        let representation_id = stream.pad.imp().representation_id();
+        let location = template::expand(
+            &settings.init_location,
+            &Self::template_values(stream, &representation_id),
+        )
+        .map_err(|err| {
+            gst::element_imp_error!(self, gst::ResourceError::Settings, ["{err}"]);
+            gst::FlowError::Error
+        })?;
+
+        gst::debug!(
+            CAT,
+            imp = self,
+            "Writing initialization segment for {representation_id} to {location}"
+        );
+
+        let mut init_stream = self
+            .obj()
+            .emit_by_name::<Option<gio::OutputStream>>(SIGNAL_GET_INIT_STREAM, &[&location])
+            .ok_or_else(|| {
+                gst::error!(CAT, imp = self, "Error while getting init stream");
+                gst::FlowError::Error
+            })?
+            .into_write();
+
+        init_stream.write_all(&map).map_err(|_| {
+            gst::error!(
+                CAT,
+                imp = self,
+                "Couldn't write init segment to output stream",
+            );
+            gst::FlowError::Error
+        })?;
+
+        init_stream
+            .into_output_stream()
+            .close(gio::Cancellable::NONE)
+            .map_err(|err| {
+                gst::error!(CAT, imp = self, "Couldn't close output stream: {err}");
+                gst::FlowError::Error
+            })?;
+
+        stream.timescale = Some(timescale);
+        stream.caps = stream.pad.current_caps();
+
+        Ok(())
+    }
+
+    fn start_segment(
+        &self,
+        settings: &Settings,
+        stream: &mut Stream,
+        header: &gst::BufferRef,
+        running_time: Option<gst::ClockTime>,
+    ) -> Result<(), gst::FlowError> {
+        let Some(timescale) = stream.timescale else {
+            gst::element_imp_error!(
+                self,
+                gst::StreamError::Format,
+                ["Received fragment before initialization segment"]
+            );
+            return Err(gst::FlowError::Error);
+        };
+
+        // Use the base media decode time of the fragment as start time so that the timeline
+        // matches the media, and snap to the end of the previous segment to avoid gaps caused
+        // by rounding.
+        let time = {
+            let map = header.map_readable().unwrap();
+            parse_base_media_decode_time(&map)
+        }
+        .or_else(|| {
+            running_time.and_then(|running_time| {
+                running_time
+                    .nseconds()
+                    .mul_div_floor(timescale as u64, gst::ClockTime::SECOND.nseconds())
+            })
+        })
+        .unwrap_or(0);
+
+        let time = match stream.next_time {
+            Some(next_time) if next_time.abs_diff(time) <= u64::max(1, timescale as u64 / 1000) => {
+                next_time
+            }
+            _ => time,
+        };
+
+        let representation_id = stream.pad.imp().representation_id();
+        let location = template::expand(
+            &settings.location,
+            &template::Values {
+                number: Some(stream.next_number),
+                time: Some(time),
+                ..Self::template_values(stream, &representation_id)
+            },
+        )
+        .map_err(|err| {
+            gst::element_imp_error!(self, gst::ResourceError::Settings, ["{err}"]);
+            gst::FlowError::Error
+        })?;
+
+        gst::debug!(
+            CAT,
+            imp = self,
+            "Starting segment {} at {time} for {representation_id} at {location}",
+            stream.next_number
+        );
+
+        let segment_stream = self
+            .obj()
+            .emit_by_name::<Option<gio::OutputStream>>(SIGNAL_GET_FRAGMENT_STREAM, &[&location])
+            .ok_or_else(|| {
+                gst::error!(CAT, imp = self, "Error while getting fragment stream");
+                gst::FlowError::Error
+            })?
+            .into_write();
+
+        stream.pending_segment = Some(PendingSegment {
+            stream: SegmentStream(segment_stream),
+            location,
+            number: stream.next_number,
+            time,
+            running_time,
+            duration: header.duration().unwrap_or(gst::ClockTime::ZERO),
+            size: 0,
+        });
+        stream.next_number += 1;
+
+        Ok(())
+    }
+
+    fn finish_segment(
+        &self,
+        settings: &Settings,
+        state: &mut State,
+        idx: usize,
+    ) -> Result<(), gst::FlowError> {
+        let stream = &mut state.streams[idx];
+        let Some(pending_segment) = stream.pending_segment.take() else {
+            return Ok(());
+        };
+
+        pending_segment
+            .stream
+            .0
+            .into_output_stream()
+            .close(gio::Cancellable::NONE)
+            .map_err(|err| {
+                gst::error!(CAT, imp = self, "Couldn't close output stream: {err}");
+                gst::FlowError::Error
+            })?;
+
+        let timescale = stream.timescale.unwrap();
+        let duration = pending_segment
+            .duration
+            .nseconds()
+            .mul_div_round(timescale as u64, gst::ClockTime::SECOND.nseconds())
+            .unwrap();
+
+        if duration > 0 {
+            let bitrate = (pending_segment.size * 8)
+                .mul_div_ceil(timescale as u64, duration)
+                .unwrap_or(u64::MAX);
+            stream.max_bitrate = u64::max(stream.max_bitrate, bitrate);
+        }
+
+        let representation_id = stream.pad.imp().representation_id();
+        gst::debug!(
+            CAT,
+            imp = self,
+            "Finished segment {} for {representation_id} with duration {}",
+            pending_segment.number,
+            pending_segment.duration,
+        );
+
+        stream.next_time = Some(pending_segment.time + duration);
+        stream.segments.push_back(Segment {
+            number: pending_segment.number,
+            time: pending_segment.time,
+            duration,
+        });
+        stream
+            .segment_locations
+            .push_back(pending_segment.location.clone());
+
+        if settings.mpd_type == DashSinkMpdType::Dynamic {
+            if settings.max_segments > 0 {
+                while stream.segments.len() > settings.max_segments as usize {
+                    stream.segments.pop_front();
+                }
+            }
+
+            if settings.max_files > 0 {
+                while stream.segment_locations.len() > settings.max_files as usize {
+                    let old_segment_location = stream.segment_locations.pop_front().unwrap();
+                    if !self
+                        .obj()
+                        .emit_by_name::<bool>(SIGNAL_DELETE_FRAGMENT, &[&old_segment_location])
+                    {
+                        gst::error!(CAT, imp = self, "Could not delete fragment");
+                    }
+                }
+            }
+        }
+
+        if state.presentation_start.is_none() {
+            let presentation_start = pending_segment
+                .time
+                .mul_div_floor(gst::ClockTime::SECOND.nseconds(), timescale as u64)
+                .unwrap()
+                .nseconds();
+            gst::debug!(
+                CAT,
+                imp = self,
+                "Presentation starts at media time {presentation_start}"
+            );
+
+            state.presentation_start = Some(presentation_start);
+            state.availability_start_time = Some(self.utc_time(pending_segment.running_time));
+        }
+
+        let mut s = gst::Structure::builder("dash-segment-added")
+            .field("location", &pending_segment.location)
+            .field("representation-id", &representation_id)
+            .field("duration", pending_segment.duration);
+        if let Some(running_time) = pending_segment.running_time {
+            s = s.field("running-time", running_time);
+        }
+        self.post_message(
+            gst::message::Element::builder(s.build())
+                .src(&*self.obj())
+                .build(),
+        );
+
+        // Only start writing the MPD once all representations have at least one segment
+        if settings.mpd_type == DashSinkMpdType::Dynamic
+            && state
+                .streams
+                .iter()
+                .all(|stream| stream.eos || !stream.segments.is_empty())
+        {
+            self.write_mpd(settings, state, false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Converts a running time to the corresponding wall clock time.
+    fn utc_time(&self, running_time: Option<gst::ClockTime>) -> DateTime<Utc> {
+        let now_utc = Utc::now();
+
+        let obj = self.obj();
+        let (Some(running_time), Some(clock), Some(base_time)) =
+            (running_time, obj.clock(), obj.base_time())
+        else {
+            return now_utc;
+        };
+
+        let now_gst = clock.time();
+        let diff = now_gst.nseconds() as i64 - (running_time + base_time).nseconds() as i64;
+
+        now_utc
+            .checked_sub_signed(Duration::nanoseconds(diff))
+            .unwrap_or(now_utc)
+    }
+
+    fn segment_uri(location: &str, mpd_root: Option<&str>) -> String {
+        let file_name = path::Path::new(&location)
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap();
+
+        if let Some(mpd_root) = mpd_root {
+            format!("{mpd_root}/{file_name}")
+        } else {
+            file_name.to_string()
+        }
+    }
+
+    fn build_mpd(&self, settings: &Settings, state: &State, is_final: bool) -> dash_mpd::MPD {
+        let now = Utc::now();
+        let presentation_start = state.presentation_start.unwrap_or(gst::ClockTime::ZERO);
+        let dynamic = settings.mpd_type == DashSinkMpdType::Dynamic;
+
+        // Representations grouped by adaptation set id, the content type of the first
+        // representation determines the content type of the adaptation set.
+        let mut adaptation_sets = BTreeMap::<u32, (&str, Vec<dash_mpd::Representation>)>::new();
+        let mut automatic_ids = BTreeMap::<&str, u32>::new();
+        let explicit_ids = state
+            .streams
+            .iter()
+            .filter_map(|stream| {
+                u32::try_from(stream.pad.imp().settings.lock().unwrap().adaptation_set_id).ok()
+            })
+            .collect::<Vec<_>>();
+
+        let mut end_time = presentation_start;
+        let mut time_shift_buffer_depth = gst::ClockTime::ZERO;
+
+        for stream in &state.streams {
+            let (Some(timescale), Some(caps), Some(first_segment)) = (
+                stream.timescale,
+                stream.caps.as_ref(),
+                stream.segments.front(),
+            ) else {
+                continue;
+            };
+            let s = caps.structure(0).unwrap();
+            let pad_settings = stream.pad.imp().settings.lock().unwrap().clone();
+            let representation_id = stream.pad.imp().representation_id();
+
+            let content_type = if s.name().starts_with("video/") {
+                "video"
+            } else if s.name().starts_with("audio/") {
+                "audio"
+            } else {
+                "text"
+            };
+
+            let adaptation_set_id = match u32::try_from(pad_settings.adaptation_set_id) {
+                Ok(id) => id,
+                Err(_) => {
+                    let next_id = (0..)
+                        .find(|id| {
+                            !explicit_ids.contains(id) && !automatic_ids.values().any(|v| v == id)
+                        })
+                        .unwrap();
+                    *automatic_ids.entry(content_type).or_insert(next_id)
+                }
+            };
+
+            let to_clock_time = |time: u64| {
+                time.mul_div_floor(gst::ClockTime::SECOND.nseconds(), timescale as u64)
+                    .unwrap()
+                    .nseconds()
+            };
+            let stream_end = to_clock_time(stream.next_time.unwrap_or(first_segment.time));
+            end_time = end_time.max(stream_end);
+            time_shift_buffer_depth = time_shift_buffer_depth
+                .max(stream_end.saturating_sub(to_clock_time(first_segment.time)));
+
+            let mut timeline: Vec<dash_mpd::S> = Vec::new();
+            let mut segment_end = None;
+            for segment in &stream.segments {
+                if segment_end == Some(segment.time) {
+                    match timeline.last_mut() {
+                        Some(last) if last.d == segment.duration => {
+                            last.r = Some(last.r.unwrap_or(0) + 1);
+                        }
+                        _ => timeline.push(dash_mpd::S {
+                            d: segment.duration,
+                            ..Default::default()
+                        }),
+                    }
+                } else {
+                    timeline.push(dash_mpd::S {
+                        t: Some(segment.time),
+                        d: segment.duration,
+                        ..Default::default()
+                    });
+                }
+                segment_end = Some(segment.time + segment.duration);
+            }
+
+            let (availability_time_offset, availability_time_complete) =
+                match settings.chunk_duration {
+                    Some(chunk_duration) if dynamic && !is_final => {
+                        let segment_duration =
+                            gst::ClockTime::from_seconds(settings.target_duration as u64);
+                        (
+                            Some(
+                                segment_duration
+                                    .saturating_sub(chunk_duration)
+                                    .seconds_f64(),
+                            ),
+                            Some(false),
+                        )
+                    }
+                    _ => (None, None),
+                };
+
+            let segment_template = dash_mpd::SegmentTemplate {
+                timescale: Some(timescale as u64),
+                initialization: Some(Self::segment_uri(
+                    &settings.init_location,
+                    settings.mpd_root.as_deref(),
+                )),
+                media: Some(Self::segment_uri(
+                    &settings.location,
+                    settings.mpd_root.as_deref(),
+                )),
+                startNumber: Some(first_segment.number),
+                presentationTimeOffset: presentation_start
+                    .nseconds()
+                    .mul_div_floor(timescale as u64, gst::ClockTime::SECOND.nseconds()),
+                availabilityTimeOffset: availability_time_offset,
+                availabilityTimeComplete: availability_time_complete,
+                SegmentTimeline: Some(dash_mpd::SegmentTimeline { segments: timeline }),
+                ..Default::default()
+            };
+
+            let bandwidth = if pad_settings.bandwidth > 0 {
+                pad_settings.bandwidth as u64
+            } else {
+                stream.max_bitrate
+            };
+
+            let mut representation = dash_mpd::Representation {
+                id: Some(representation_id),
+                bandwidth: Some(bandwidth),
+                codecs: gst_pbutils::codec_utils_caps_get_mime_codec(caps)
+                    .ok()
+                    .map(String::from),
+                SegmentTemplate: Some(segment_template),
+                ..Default::default()
+            };
+
+            if content_type == "video" {
+                representation.width = s.get::<i32>("width").ok().map(|width| width as u64);
+                representation.height = s.get::<i32>("height").ok().map(|height| height as u64);
+                representation.frameRate = s
+                    .get::<gst::Fraction>("framerate")
+                    .ok()
+                    .filter(|framerate| framerate.numer() > 0)
+                    .map(|framerate| {
+                        if framerate.denom() == 1 {
+                            framerate.numer().to_string()
+                        } else {
+                            format!("{}/{}", framerate.numer(), framerate.denom())
+                        }
+                    });
+            } else if content_type == "audio" {
+                representation.audioSamplingRate =
+                    s.get::<i32>("rate").ok().map(|rate| rate.to_string());
+                if let Ok(channels) = s.get::<i32>("channels") {
+                    representation.AudioChannelConfiguration =
+                        vec![dash_mpd::AudioChannelConfiguration {
+                            schemeIdUri: String::from(AUDIO_CHANNEL_CONFIGURATION_SCHEME),
+                            value: Some(channels.to_string()),
+                            ..Default::default()
+                        }];
+                }
+            }
+
+            adaptation_sets
+                .entry(adaptation_set_id)
+                .or_insert_with(|| (content_type, Vec::new()))
+                .1
+                .push(representation);
+        }
+
+        let adaptations = adaptation_sets
+            .into_iter()
+            .map(
+                |(id, (content_type, representations))| dash_mpd::AdaptationSet {
+                    id: Some(id.to_string()),
+                    contentType: Some(content_type.to_string()),
+                    mimeType: Some(match content_type {
+                        "video" => String::from("video/mp4"),
+                        "audio" => String::from("audio/mp4"),
+                        _ => String::from("application/mp4"),
+                    }),
+                    segmentAlignment: Some(true),
+                    startWithSAP: Some(1),
+                    representations,
+                    ..Default::default()
+                },
+            )
+            .collect::<Vec<_>>();
+
+        let period = dash_mpd::Period {
+            id: Some(String::from("0")),
+            start: Some(std::time::Duration::ZERO),
+            adaptations,
+            ..Default::default()
+        };
+
+        let mut mpd = dash_mpd::MPD {
+            mpdtype: Some(String::from(if dynamic { "dynamic" } else { "static" })),
+            xmlns: Some("urn:mpeg:dash:schema:mpd:2011".to_string()),
+            schemaLocation: Some("urn:mpeg:dash:schema:mpd:2011 DASH-MPD.xsd".to_string()),
+            profiles: Some(LIVE_PROFILE.to_string()),
+            minBufferTime: Some(std::time::Duration::from_nanos(
+                settings.min_buffer_time.nseconds(),
+            )),
+            periods: vec![period],
+            ..Default::default()
+        };
+
+        let duration = std::time::Duration::from_nanos((end_time - presentation_start).nseconds());
+        if is_final {
+            mpd.mediaPresentationDuration = Some(duration);
+        }
+
+        if dynamic {
+            mpd.availabilityStartTime = state.availability_start_time;
+            mpd.publishTime = Some(now);
+
+            if !is_final {
+                mpd.minimumUpdatePeriod = Some(std::time::Duration::from_secs(
+                    settings.target_duration as u64,
+                ));
+
+                if settings.max_segments > 0 {
+                    mpd.timeShiftBufferDepth = Some(std::time::Duration::from_nanos(
+                        time_shift_buffer_depth.nseconds(),
+                    ));
+                }
+            }
+
+            if let Some(scheme) = settings.utc_timing_scheme.as_ref() {
+                let value = settings.utc_timing_value.clone().or_else(|| {
+                    (scheme == UTC_TIMING_DIRECT_SCHEME)
+                        .then(|| now.to_rfc3339_opts(SecondsFormat::Millis, true))
+                });
+
+                mpd.UTCTiming = vec![dash_mpd::UTCTiming {
+                    schemeIdUri: scheme.clone(),
+                    value,
+                    ..Default::default()
+                }];
+            }
+        }
+
+        mpd
+    }
+
+    fn write_mpd(
+        &self,
+        settings: &Settings,
+        state: &State,
+        is_final: bool,
+    ) -> Result<(), gst::FlowError> {
+        use serde::ser::Serialize;
+
+        if state.presentation_start.is_none() {
+            gst::warning!(CAT, imp = self, "No segments, not writing MPD");
+            return Ok(());
+        }
+
+        gst::info!(CAT, imp = self, "Writing MPD, final {is_final}");
+
+        let mpd = self.build_mpd(settings, state, is_final);
+
+        let mut xml = String::new();
+        let mut ser = quick_xml::se::Serializer::new(&mut xml);
+        ser.indent(' ', 4);
+        mpd.serialize(ser).map_err(|err| {
+            gst::element_imp_error!(
+                self,
+                gst::ResourceError::Write,
+                ["Could not serialize MPD: {err}"]
+            );
+            gst::FlowError::Error
+        })?;
+
+        let mut mpd_stream = self
+            .obj()
+            .emit_by_name::<Option<gio::OutputStream>>(
+                SIGNAL_GET_MPD_STREAM,
+                &[&settings.mpd_location],
+            )
+            .ok_or_else(|| {
+                gst::error!(CAT, imp = self, "Could not get stream to write MPD");
+                gst::FlowError::Error
+            })?
+            .into_write();
+
+        write!(
+            mpd_stream,
+            r###"<?xml version="1.0" encoding="UTF-8"?>
+{xml}
+"###
+        )
+        .map_err(|err| {
+            gst::error!(CAT, imp = self, "Could not write MPD: {err}");
+            gst::FlowError::Error
+        })?;
+
+        mpd_stream
+            .into_output_stream()
+            .close(gio::Cancellable::NONE)
+            .map_err(|err| {
+                gst::error!(CAT, imp = self, "Could not close MPD stream: {err}");
+                gst::FlowError::Error
+            })?;
+
+        gst::debug!(CAT, imp = self, "Wrote new MPD");
+
+        Ok(())
+    }
+
+    fn new_file_stream<P>(&self, location: &P) -> Result<gio::OutputStream, String>
+    where
+        P: AsRef<path::Path>,
+    {
+        let file = gio::File::for_path(location);
+        // Open the file for writing, creating it if it doesn't exist
+        // Use replace() to write the content in atomic mode
+        // (writes to a temporary file and then atomically rename over the destination when the stream is closed)
+        let output_stream = file
+            .replace(
+                None,
+                false,
+                gio::FileCreateFlags::empty(),
+                None::<&gio::Cancellable>,
+            )
+            .map_err(move |err| {
+                let error_msg = gst::error_msg!(
+                    gst::ResourceError::OpenWrite,
+                    [
+                        "Could not open file {} for writing: {}",
+                        location.as_ref().to_str().unwrap(),
+                        err.to_string(),
+                    ]
+                );
+                self.post_error_message(error_msg);
+                err.to_string()
+            })?;
+        Ok(output_stream.upcast())
+    }
+
+    fn delete_fragment<P>(&self, location: &P)
+    where
+        P: AsRef<path::Path>,
+    {
+        let _ = fs::remove_file(location).map_err(|err| {
+            gst::warning!(
+                CAT,
+                imp = self,
+                "Could not delete segment file: {}",
+                err.to_string()
+            );
+        });
+    }
+}
+
+/// Returns the payload of the first box of type `fourcc` in `data`.
+///
+/// The last box might be truncated, e.g. the `mdat` box at the end of a fragment header.
+fn find_box<'a>(mut data: &'a [u8], fourcc: &[u8; 4]) -> Option<&'a [u8]> {
+    while data.len() >= 8 {
+        let (header_size, size) = match u32::from_be_bytes(data[0..4].try_into().unwrap()) {
+            0 => (8, data.len() as u64),
+            1 => (16, u64::from_be_bytes(data.get(8..16)?.try_into().unwrap())),
+            size => (8, size as u64),
+        };
+
+        if size < header_size {
+            return None;
+        }
+
+        let end = usize::try_from(size).unwrap_or(usize::MAX).min(data.len());
+        if &data[4..8] == fourcc {
+            return data.get(header_size as usize..end);
+        }
+
+        data = &data[end..];
+    }
+
+    None
+}
+
+fn find_box_path<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    path.iter()
+        .try_fold(data, |data, fourcc| find_box(data, fourcc))
+}
+
+/// Parses the track timescale from an initialization segment.
+fn parse_timescale(data: &[u8]) -> Option<u32> {
+    let mdhd = find_box_path(data, &[b"moov", b"trak", b"mdia", b"mdhd"])?;
+    let offset = match mdhd.first()? {
+        0 => 12,
+        1 => 20,
+        _ => return None,
+    };
+
+    let timescale = u32::from_be_bytes(mdhd.get(offset..offset + 4)?.try_into().unwrap());
+    (timescale > 0).then_some(timescale)
+}
+
+/// Parses the base media decode time of the first track from a fragment header.
+fn parse_base_media_decode_time(data: &[u8]) -> Option<u64> {
+    let tfdt = find_box_path(data, &[b"moof", b"traf", b"tfdt"])?;
+    match tfdt.first()? {
+        0 => Some(u32::from_be_bytes(tfdt.get(4..8)?.try_into().unwrap()) as u64),
+        1 => Some(u64::from_be_bytes(tfdt.get(4..12)?.try_into().unwrap())),
+        _ => None,
+    }
+}
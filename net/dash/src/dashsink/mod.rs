@@ -0,0 +1,81 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * element-dashsink:
+ * @short-description: MPEG-DASH sink writing fragmented MP4 segments and an MPD
+ *
+ * `dashsink` muxes each of its request sink pads with a separate `dashmp4mux` into an
+ * initialization segment and a sequence of media segments, and writes an MPD with a
+ * `SegmentTemplate` and `SegmentTimeline` for each representation.
+ *
+ * Each sink pad becomes one representation. By default all video pads are grouped into one
+ * adaptation set and all audio pads into another one, which can be overridden with the
+ * `adaptation-set-id` pad property.
+ *
+ * In `dynamic` mode the MPD is updated after every segment and old segments are removed
+ * according to the `max-segments` and `max-files` properties. In `static` mode the MPD is only
+ * written once at the end of the stream.
+ *
+ * ## Example launch line
+ *
+ * ```shell
+ * gst-launch-1.0 videotestsrc is-live=true ! x264enc key-int-max=60 ! h264parse ! dashsink.video_0 \
+ *     audiotestsrc is-live=true ! avenc_aac ! dashsink.audio_0 \
+ *     dashsink name=dashsink target-duration=4
+ * ```
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::glib;
+use gst::prelude::*;
+
+mod imp;
+mod template;
+
+#[derive(Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstRsDashSinkMpdType")]
+#[non_exhaustive]
+pub enum DashSinkMpdType {
+    #[enum_value(
+        name = "Static: The MPD is written once at the end of the stream and lists all segments",
+        nick = "static"
+    )]
+    Static = 0,
+
+    #[default]
+    #[enum_value(
+        name = "Dynamic: The MPD is updated after every segment for live playback",
+        nick = "dynamic"
+    )]
+    Dynamic = 1,
+}
+
+glib::wrapper! {
+    pub struct DashSink(ObjectSubclass<imp::DashSink>) @extends gst::Bin, gst::Element, gst::Object, @implements gst::ChildProxy;
+}
+
+glib::wrapper! {
+    pub(crate) struct DashSinkPad(ObjectSubclass<imp::DashSinkPad>) @extends gst::GhostPad, gst::ProxyPad, gst::Pad, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    #[cfg(feature = "doc")]
+    {
+        DashSinkPad::static_type().mark_as_plugin_api(gst::PluginAPIFlags::empty());
+        DashSinkMpdType::static_type().mark_as_plugin_api(gst::PluginAPIFlags::empty());
+    }
+
+    gst::Element::register(
+        Some(plugin),
+        "dashsink",
+        gst::Rank::NONE,
+        DashSink::static_type(),
+    )
+}
@@ -0,0 +1,174 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Expansion of the `SegmentTemplate` identifiers as defined in ISO/IEC 23009-1 5.3.9.4.4.
+
+use std::fmt::Write;
+
+/// Values to substitute for the identifiers of a template.
+#[derive(Debug, Default)]
+pub struct Values<'a> {
+    pub representation_id: &'a str,
+    pub number: Option<u64>,
+    pub time: Option<u64>,
+    pub bandwidth: Option<u64>,
+}
+
+/// Returns the identifiers used in `template`, or an error if the template is malformed.
+pub fn identifiers(template: &str) -> Result<Vec<&str>, String> {
+    let mut identifiers = Vec::new();
+
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        let Some(len) = rest[start + 1..].find('$') else {
+            return Err(format!("Unterminated identifier in template '{template}'"));
+        };
+
+        let identifier = &rest[start + 1..start + 1 + len];
+        if !identifier.is_empty() {
+            let (name, format) = identifier
+                .split_once('%')
+                .map(|(name, format)| (name, Some(format)))
+                .unwrap_or((identifier, None));
+
+            match name {
+                "RepresentationID" if format.is_none() => (),
+                "Number" | "Time" | "Bandwidth" => {
+                    if let Some(format) = format {
+                        parse_width(format).ok_or_else(|| {
+                            format!("Invalid format '{format}' in template '{template}'")
+                        })?;
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "Invalid identifier '{identifier}' in template '{template}'"
+                    ));
+                }
+            }
+
+            identifiers.push(name);
+        }
+
+        rest = &rest[start + len + 2..];
+    }
+
+    Ok(identifiers)
+}
+
+/// Parses a `%0[width]d` format tag and returns the width.
+fn parse_width(format: &str) -> Option<usize> {
+    let width = format.strip_prefix('0')?.strip_suffix('d')?;
+    if width.is_empty() {
+        return Some(1);
+    }
+
+    width.parse::<usize>().ok()
+}
+
+/// Substitutes all identifiers in `template` with `values`.
+pub fn expand(template: &str, values: &Values) -> Result<String, String> {
+    let mut res = String::with_capacity(template.len());
+
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        res.push_str(&rest[..start]);
+
+        let Some(len) = rest[start + 1..].find('$') else {
+            return Err(format!("Unterminated identifier in template '{template}'"));
+        };
+
+        let identifier = &rest[start + 1..start + 1 + len];
+        let (name, format) = identifier
+            .split_once('%')
+            .map(|(name, format)| (name, Some(format)))
+            .unwrap_or((identifier, None));
+
+        let value = match name {
+            "" => {
+                res.push('$');
+                None
+            }
+            "RepresentationID" if format.is_none() => {
+                res.push_str(values.representation_id);
+                None
+            }
+            "Number" => Some(values.number),
+            "Time" => Some(values.time),
+            "Bandwidth" => Some(values.bandwidth),
+            _ => {
+                return Err(format!(
+                    "Invalid identifier '{identifier}' in template '{template}'"
+                ))
+            }
+        };
+
+        if let Some(value) = value {
+            let Some(value) = value else {
+                return Err(format!(
+                    "No value for identifier '{name}' in template '{template}'"
+                ));
+            };
+
+            let width = match format {
+                Some(format) => parse_width(format)
+                    .ok_or_else(|| format!("Invalid format '{format}' in template '{template}'"))?,
+                None => 1,
+            };
+
+            write!(&mut res, "{value:0width$}").unwrap();
+        }
+
+        rest = &rest[start + len + 2..];
+    }
+    res.push_str(rest);
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let values = Values {
+            representation_id: "video_0",
+            number: Some(42),
+            time: Some(900000),
+            bandwidth: None,
+        };
+
+        assert_eq!(
+            expand("segment_$RepresentationID$_$Number%05d$.m4s", &values).unwrap(),
+            "segment_video_0_00042.m4s"
+        );
+        assert_eq!(
+            expand("$RepresentationID$/$Time$.m4s", &values).unwrap(),
+            "video_0/900000.m4s"
+        );
+        assert_eq!(
+            expand("init$$$RepresentationID$.mp4", &values).unwrap(),
+            "init$video_0.mp4"
+        );
+        assert!(expand("segment_$Bandwidth$.m4s", &values).is_err());
+        assert!(expand("segment_$Number.m4s", &values).is_err());
+        assert!(expand("segment_$Foo$.m4s", &values).is_err());
+    }
+
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(
+            identifiers("segment_$RepresentationID$_$Number%05d$.m4s").unwrap(),
+            vec!["RepresentationID", "Number"]
+        );
+        assert_eq!(identifiers("init$$.mp4").unwrap(), Vec::<&str>::new());
+        assert!(identifiers("segment_$Number%5d$.m4s").is_err());
+        assert!(identifiers("segment_$RepresentationID%05d$.m4s").is_err());
+    }
+}
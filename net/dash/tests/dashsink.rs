@@ -0,0 +1,354 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gio::prelude::*;
+use gst::prelude::*;
+use gstrsdash::dashsink::DashSinkMpdType;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+fn init() {
+    use std::sync::Once;
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        gst::init().unwrap();
+        gstfmp4::plugin_register_static().expect("Need dashmp4mux for dashsink test");
+        gstrsdash::plugin_register_static().expect("dashsink test");
+    });
+}
+
+struct MemoryFile {
+    handler: Arc<Mutex<String>>,
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let value = std::str::from_utf8(buf).unwrap();
+        let mut string = self.handler.lock().unwrap();
+        string.push_str(value);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Output {
+    /// Content of the MPD every time it was written.
+    mpds: Vec<Arc<Mutex<String>>>,
+    init_locations: Vec<String>,
+    fragment_locations: Vec<String>,
+    deleted_locations: Vec<String>,
+    /// Representation id and location of every `dash-segment-added` message.
+    segments_added: Vec<(String, String)>,
+}
+
+impl Output {
+    fn last_mpd(&self) -> dash_mpd::MPD {
+        let mpd = self.mpds.last().expect("No MPD written");
+        dash_mpd::parse(&mpd.lock().unwrap()).expect("Invalid MPD")
+    }
+}
+
+fn run_pipeline(pipeline: &gst::Pipeline, dashsink: &gst::Element) -> Output {
+    let output = Arc::new(Mutex::new(Output::default()));
+
+    dashsink.connect("get-mpd-stream", false, {
+        let output = output.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            assert_eq!(location, "manifest.mpd");
+
+            let content = Arc::new(Mutex::new(String::new()));
+            output.lock().unwrap().mpds.push(content.clone());
+
+            let stream = gio::WriteOutputStream::new(MemoryFile { handler: content });
+            Some(stream.to_value())
+        }
+    });
+
+    dashsink.connect("get-init-stream", false, {
+        let output = output.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            output.lock().unwrap().init_locations.push(location);
+
+            let stream = gio::MemoryOutputStream::new_resizable();
+            Some(stream.to_value())
+        }
+    });
+
+    dashsink.connect("get-fragment-stream", false, {
+        let output = output.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            output.lock().unwrap().fragment_locations.push(location);
+
+            let stream = gio::MemoryOutputStream::new_resizable();
+            Some(stream.to_value())
+        }
+    });
+
+    dashsink.connect("delete-fragment", false, {
+        let output = output.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            output.lock().unwrap().deleted_locations.push(location);
+
+            Some(true.to_value())
+        }
+    });
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+
+    let mut eos = false;
+    let bus = pipeline.bus().unwrap();
+    while let Some(msg) = bus.timed_pop(gst::ClockTime::NONE) {
+        use gst::MessageView;
+        match msg.view() {
+            MessageView::Eos(..) => {
+                eos = true;
+                break;
+            }
+            MessageView::Element(msg) => {
+                if let Some(structure) = msg.structure() {
+                    if structure.has_name("dash-segment-added") {
+                        let representation_id =
+                            structure.get::<String>("representation-id").unwrap();
+                        let location = structure.get::<String>("location").unwrap();
+                        output
+                            .lock()
+                            .unwrap()
+                            .segments_added
+                            .push((representation_id, location));
+                    }
+                }
+            }
+            MessageView::Error(err) => panic!("{err}"),
+            _ => (),
+        }
+    }
+
+    pipeline.set_state(gst::State::Null).unwrap();
+    assert!(eos);
+
+    let mut output = output.lock().unwrap();
+    std::mem::take(&mut *output)
+}
+
+fn timeline_segments(timeline: &dash_mpd::SegmentTimeline) -> u64 {
+    timeline
+        .segments
+        .iter()
+        .map(|s| 1 + s.r.unwrap_or(0) as u64)
+        .sum()
+}
+
+#[test]
+fn test_dashsink_static_multiple_representations() {
+    init();
+
+    let pipeline = gst::parse::launch(
+        "videotestsrc num-buffers=150 ! video/x-raw,width=320,height=240,framerate=30/1 ! tee name=t \
+         t. ! queue ! x264enc key-int-max=30 bitrate=1000 speed-preset=ultrafast ! h264parse ! dashsink.video_0 \
+         t. ! queue ! x264enc key-int-max=30 bitrate=300 speed-preset=ultrafast ! h264parse ! dashsink.video_1 \
+         dashsink name=dashsink target-duration=1 mpd-type=static sync=false",
+    )
+    .unwrap()
+    .downcast::<gst::Pipeline>()
+    .unwrap();
+
+    let dashsink = pipeline.by_name("dashsink").unwrap();
+    assert_eq!(
+        dashsink.property::<DashSinkMpdType>("mpd-type"),
+        DashSinkMpdType::Static
+    );
+    dashsink
+        .static_pad("video_1")
+        .unwrap()
+        .set_property("representation-id", "low");
+
+    let output = run_pipeline(&pipeline, &dashsink);
+
+    // The MPD is only written once at the end
+    assert_eq!(output.mpds.len(), 1);
+
+    let mut init_locations = output.init_locations.clone();
+    init_locations.sort();
+    assert_eq!(init_locations, vec!["init_low.mp4", "init_video_0.mp4"]);
+
+    assert_eq!(output.fragment_locations.len(), 10);
+    assert_eq!(output.segments_added.len(), 10);
+    assert!(output
+        .fragment_locations
+        .contains(&String::from("segment_low_00005.m4s")));
+    assert!(output.deleted_locations.is_empty());
+
+    let mpd = output.last_mpd();
+    assert_eq!(mpd.mpdtype.as_deref(), Some("static"));
+    let duration = mpd.mediaPresentationDuration.unwrap();
+    assert!(
+        duration >= std::time::Duration::from_millis(4900)
+            && duration <= std::time::Duration::from_millis(5100),
+        "{duration:?}"
+    );
+
+    assert_eq!(mpd.periods.len(), 1);
+    let adaptations = &mpd.periods[0].adaptations;
+    assert_eq!(adaptations.len(), 1);
+    assert_eq!(adaptations[0].contentType.as_deref(), Some("video"));
+    assert_eq!(adaptations[0].mimeType.as_deref(), Some("video/mp4"));
+
+    let representations = &adaptations[0].representations;
+    assert_eq!(representations.len(), 2);
+    assert_eq!(representations[0].id.as_deref(), Some("video_0"));
+    assert_eq!(representations[1].id.as_deref(), Some("low"));
+
+    for representation in representations {
+        assert_eq!(representation.width, Some(320));
+        assert_eq!(representation.height, Some(240));
+        assert_eq!(representation.frameRate.as_deref(), Some("30"));
+        assert!(representation
+            .codecs
+            .as_deref()
+            .unwrap()
+            .starts_with("avc1."));
+        assert!(representation.bandwidth.unwrap() > 0);
+
+        let template = representation.SegmentTemplate.as_ref().unwrap();
+        assert_eq!(
+            template.initialization.as_deref(),
+            Some("init_$RepresentationID$.mp4")
+        );
+        assert_eq!(
+            template.media.as_deref(),
+            Some("segment_$RepresentationID$_$Number%05d$.m4s")
+        );
+        assert_eq!(template.startNumber, Some(1));
+
+        let timeline = template.SegmentTimeline.as_ref().unwrap();
+        assert_eq!(timeline_segments(timeline), 5);
+        // All segments are contiguous
+        assert!(timeline.segments[0].t.is_some());
+        assert!(timeline.segments[1..].iter().all(|s| s.t.is_none()));
+    }
+
+    assert!(representations[0].bandwidth > representations[1].bandwidth);
+}
+
+#[test]
+fn test_dashsink_dynamic() {
+    init();
+
+    let pipeline = gst::parse::launch(
+        "videotestsrc num-buffers=240 ! video/x-raw,width=320,height=240,framerate=30/1 ! \
+         x264enc key-int-max=30 speed-preset=ultrafast ! h264parse ! dashsink.video_0 \
+         dashsink name=dashsink target-duration=1 max-segments=3 max-files=4 sync=false",
+    )
+    .unwrap()
+    .downcast::<gst::Pipeline>()
+    .unwrap();
+
+    let dashsink = pipeline.by_name("dashsink").unwrap();
+    let output = run_pipeline(&pipeline, &dashsink);
+
+    // One MPD after every segment and the final MPD
+    assert_eq!(output.fragment_locations.len(), 8);
+    assert_eq!(output.mpds.len(), 9);
+    assert_eq!(
+        output.deleted_locations,
+        vec![
+            "segment_video_0_00001.m4s",
+            "segment_video_0_00002.m4s",
+            "segment_video_0_00003.m4s",
+            "segment_video_0_00004.m4s",
+        ]
+    );
+
+    let first_mpd = dash_mpd::parse(&output.mpds[0].lock().unwrap()).unwrap();
+    assert_eq!(first_mpd.mpdtype.as_deref(), Some("dynamic"));
+    assert!(first_mpd.availabilityStartTime.is_some());
+    assert!(first_mpd.publishTime.is_some());
+    assert!(first_mpd.minimumUpdatePeriod.is_some());
+    assert!(first_mpd.timeShiftBufferDepth.is_some());
+    assert!(first_mpd.mediaPresentationDuration.is_none());
+    assert_eq!(first_mpd.UTCTiming.len(), 1);
+    assert_eq!(
+        first_mpd.UTCTiming[0].schemeIdUri,
+        "urn:mpeg:dash:utc:direct:2014"
+    );
+
+    let mpd = output.last_mpd();
+    assert_eq!(mpd.mpdtype.as_deref(), Some("dynamic"));
+    assert_eq!(mpd.availabilityStartTime, first_mpd.availabilityStartTime);
+    assert!(mpd.minimumUpdatePeriod.is_none());
+    assert!(mpd.mediaPresentationDuration.is_some());
+
+    let representation = &mpd.periods[0].adaptations[0].representations[0];
+    let template = representation.SegmentTemplate.as_ref().unwrap();
+    assert_eq!(template.startNumber, Some(6));
+    assert_eq!(
+        timeline_segments(template.SegmentTimeline.as_ref().unwrap()),
+        3
+    );
+}
+
+#[test]
+fn test_dashsink_audio_video() {
+    init();
+
+    if gst::ElementFactory::find("avenc_aac").is_none() {
+        eprintln!("Skipping test, avenc_aac is not available");
+        return;
+    }
+
+    let pipeline = gst::parse::launch(
+        "videotestsrc num-buffers=90 ! video/x-raw,width=320,height=240,framerate=30/1 ! \
+         x264enc key-int-max=30 speed-preset=ultrafast ! h264parse ! dashsink.video_0 \
+         audiotestsrc num-buffers=140 samplesperbuffer=1024 ! audio/x-raw,rate=48000,channels=2 ! \
+         avenc_aac ! aacparse ! dashsink.audio_0 \
+         dashsink name=dashsink target-duration=1 mpd-type=static sync=false",
+    )
+    .unwrap()
+    .downcast::<gst::Pipeline>()
+    .unwrap();
+
+    let dashsink = pipeline.by_name("dashsink").unwrap();
+    let output = run_pipeline(&pipeline, &dashsink);
+
+    let mpd = output.last_mpd();
+    let adaptations = &mpd.periods[0].adaptations;
+    assert_eq!(adaptations.len(), 2);
+
+    let video = adaptations
+        .iter()
+        .find(|a| a.contentType.as_deref() == Some("video"))
+        .unwrap();
+    let audio = adaptations
+        .iter()
+        .find(|a| a.contentType.as_deref() == Some("audio"))
+        .unwrap();
+    assert_ne!(video.id, audio.id);
+
+    assert_eq!(audio.mimeType.as_deref(), Some("audio/mp4"));
+    let representation = &audio.representations[0];
+    assert_eq!(representation.id.as_deref(), Some("audio_0"));
+    assert_eq!(representation.codecs.as_deref(), Some("mp4a.40.2"));
+    assert_eq!(representation.audioSamplingRate.as_deref(), Some("48000"));
+    assert_eq!(
+        representation.AudioChannelConfiguration[0].value.as_deref(),
+        Some("2")
+    );
+    assert_eq!(
+        representation.SegmentTemplate.as_ref().unwrap().timescale,
+        Some(48000)
+    );
+}
@@ -0,0 +1,463 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::glib;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use m3u8_rs::{AlternativeMediaType, Playlist};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tokio::runtime;
+use url::Url;
+
+use super::stream::{select_variant, Stream, Variant};
+
+const DEFAULT_CONNECTION_SPEED: u32 = 0;
+const DEFAULT_BITRATE_LIMIT: f32 = 0.8;
+const DEFAULT_TIMEOUT: u32 = 15;
+
+pub(super) static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "hlsdemux3",
+        gst::DebugColorFlags::empty(),
+        Some("HLS client"),
+    )
+});
+
+pub(super) static RUNTIME: LazyLock<runtime::Runtime> = LazyLock::new(|| {
+    runtime::Builder::new_multi_thread()
+        .enable_all()
+        .worker_threads(1)
+        .build()
+        .unwrap()
+});
+
+#[derive(Debug, Clone)]
+pub(super) struct Settings {
+    /// Connection speed in kbit/s, 0 = measure
+    pub(super) connection_speed: u32,
+    pub(super) bitrate_limit: f32,
+    timeout: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            connection_speed: DEFAULT_CONNECTION_SPEED,
+            bitrate_limit: DEFAULT_BITRATE_LIMIT,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+struct StreamHandle {
+    appsrc: gst_app::AppSrc,
+    pad: gst::GhostPad,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Playlist data received on the sink pad so far
+    playlist_data: Vec<u8>,
+    streams: Vec<StreamHandle>,
+}
+
+pub struct HlsDemux3 {
+    sinkpad: gst::Pad,
+    pub(super) settings: Mutex<Settings>,
+    state: Mutex<State>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for HlsDemux3 {
+    const NAME: &'static str = "GstHlsDemux3";
+    type Type = super::HlsDemux3;
+    type ParentType = gst::Bin;
+
+    fn with_class(klass: &Self::Class) -> Self {
+        let templ = klass.pad_template("sink").unwrap();
+        let sinkpad = gst::Pad::builder_from_template(&templ)
+            .chain_function(|pad, parent, buffer| {
+                HlsDemux3::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::FlowError::Error),
+                    |imp| imp.sink_chain(pad, buffer),
+                )
+            })
+            .event_function(|pad, parent, event| {
+                HlsDemux3::catch_panic_pad_function(
+                    parent,
+                    || false,
+                    |imp| imp.sink_event(pad, event),
+                )
+            })
+            .build();
+
+        Self {
+            sinkpad,
+            settings: Mutex::default(),
+            state: Mutex::default(),
+        }
+    }
+}
+
+impl ObjectImpl for HlsDemux3 {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecUInt::builder("connection-speed")
+                    .nick("Connection Speed")
+                    .blurb("Network connection speed to use for selecting the variant stream in kbit/s (0 = measure)")
+                    .default_value(DEFAULT_CONNECTION_SPEED)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecFloat::builder("bitrate-limit")
+                    .nick("Bitrate Limit")
+                    .blurb("Limit of the available bitrate to use when switching to alternates")
+                    .minimum(0.0)
+                    .maximum(1.0)
+                    .default_value(DEFAULT_BITRATE_LIMIT)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecUInt::builder("timeout")
+                    .nick("Timeout")
+                    .blurb("Timeout in seconds for each HTTP request (0 = no timeout)")
+                    .maximum(3600)
+                    .default_value(DEFAULT_TIMEOUT)
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "connection-speed" => {
+                settings.connection_speed = value.get().expect("type checked upstream");
+            }
+            "bitrate-limit" => {
+                settings.bitrate_limit = value.get().expect("type checked upstream");
+            }
+            "timeout" => {
+                settings.timeout = value.get().expect("type checked upstream");
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "connection-speed" => settings.connection_speed.to_value(),
+            "bitrate-limit" => settings.bitrate_limit.to_value(),
+            "timeout" => settings.timeout.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        self.obj().add_pad(&self.sinkpad).unwrap();
+    }
+}
+
+impl GstObjectImpl for HlsDemux3 {}
+
+impl ElementImpl for HlsDemux3 {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "HTTP Live Streaming Demuxer",
+                "Codec/Demuxer/Adaptive",
+                "HTTP Live Streaming client downloading and outputting the segments of a playlist",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let sink_pad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &gst::Caps::new_empty_simple("application/x-hls"),
+            )
+            .unwrap();
+
+            let src_pad_template = gst::PadTemplate::new(
+                "src_%u",
+                gst::PadDirection::Src,
+                gst::PadPresence::Sometimes,
+                &gst::Caps::new_any(),
+            )
+            .unwrap();
+
+            vec![sink_pad_template, src_pad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        let ret = self.parent_change_state(transition)?;
+
+        if transition == gst::StateChange::PausedToReady {
+            self.stop();
+        }
+
+        Ok(ret)
+    }
+}
+
+impl BinImpl for HlsDemux3 {}
+
+impl HlsDemux3 {
+    fn sink_chain(
+        &self,
+        _pad: &gst::Pad,
+        buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let map = buffer.map_readable().map_err(|_| {
+            gst::element_imp_error!(self, gst::CoreError::Failed, ["Failed to map buffer"]);
+            gst::FlowError::Error
+        })?;
+
+        let mut state = self.state.lock().unwrap();
+        if !state.streams.is_empty() {
+            gst::warning!(CAT, imp = self, "Ignoring data after the playlist");
+            return Ok(gst::FlowSuccess::Ok);
+        }
+        state.playlist_data.extend_from_slice(&map);
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn sink_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        use gst::EventView;
+
+        gst::log!(CAT, obj = pad, "Handling event {event:?}");
+
+        match event.view() {
+            EventView::Eos(_) => {
+                if let Err(err) = self.start() {
+                    self.post_error_message(err);
+                }
+                true
+            }
+            EventView::FlushStop(_) => {
+                self.state.lock().unwrap().playlist_data.clear();
+                true
+            }
+            // Nothing from upstream is forwarded, everything downstream is produced from the
+            // playlist once it is complete
+            _ => true,
+        }
+    }
+
+    fn playlist_uri(&self) -> Result<Url, gst::ErrorMessage> {
+        let mut query = gst::query::Uri::new();
+        if !self.sinkpad.peer_query(&mut query) {
+            return Err(gst::error_msg!(
+                gst::StreamError::Demux,
+                ["Failed to query the playlist URI from upstream"]
+            ));
+        }
+
+        let uri = query
+            .redirection()
+            .0
+            .or_else(|| query.uri())
+            .ok_or_else(|| {
+                gst::error_msg!(
+                    gst::StreamError::Demux,
+                    ["Upstream provided no playlist URI"]
+                )
+            })?;
+
+        Url::parse(&uri).map_err(|err| {
+            gst::error_msg!(
+                gst::StreamError::Demux,
+                ["Invalid playlist URI '{}': {}", uri, err]
+            )
+        })
+    }
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        let data = std::mem::take(&mut self.state.lock().unwrap().playlist_data);
+        let uri = self.playlist_uri()?;
+
+        gst::debug!(CAT, imp = self, "Starting with playlist {uri}");
+
+        let playlist = m3u8_rs::parse_playlist_res(&data).map_err(|_| {
+            gst::error_msg!(
+                gst::StreamError::Demux,
+                ["Failed to parse playlist {}", uri]
+            )
+        })?;
+
+        let settings = self.settings.lock().unwrap().clone();
+
+        let mut client = reqwest::Client::builder().user_agent("GStreamer hlsdemux3");
+        if settings.timeout > 0 {
+            client = client.timeout(Duration::from_secs(settings.timeout as u64));
+        }
+        let client = client.build().map_err(|err| {
+            gst::error_msg!(
+                gst::ResourceError::Failed,
+                ["Failed to create HTTP client: {}", err]
+            )
+        })?;
+
+        let join = |uri: &Url, relative: &str| {
+            uri.join(relative).map_err(|err| {
+                gst::error_msg!(
+                    gst::StreamError::Demux,
+                    ["Invalid URI '{}': {}", relative, err]
+                )
+            })
+        };
+
+        let mut streams = Vec::new();
+        match playlist {
+            Playlist::MediaPlaylist(playlist) => {
+                let variants = vec![Variant { uri, bandwidth: 0 }];
+                streams.push((variants, 0, Some(playlist)));
+            }
+            Playlist::MasterPlaylist(playlist) => {
+                let variants = playlist
+                    .variants
+                    .iter()
+                    .filter(|variant| !variant.is_i_frame)
+                    .map(|variant| {
+                        Ok(Variant {
+                            uri: join(&uri, &variant.uri)?,
+                            bandwidth: variant.bandwidth,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, gst::ErrorMessage>>()?;
+
+                if variants.is_empty() {
+                    return Err(gst::error_msg!(
+                        gst::StreamError::Demux,
+                        ["No variant streams in playlist {}", uri]
+                    ));
+                }
+
+                // The first variant is the default unless the connection speed is configured
+                let current_variant = if settings.connection_speed > 0 {
+                    select_variant(
+                        &variants,
+                        (settings.connection_speed as f64 * 1000.0 * settings.bitrate_limit as f64)
+                            as u64,
+                    )
+                } else {
+                    0
+                };
+
+                // Alternate audio renditions are only exposed for the group of the initial
+                // variant, which is assumed to be the same for all variants
+                let audio_group = playlist
+                    .variants
+                    .iter()
+                    .filter(|variant| !variant.is_i_frame)
+                    .nth(current_variant)
+                    .and_then(|variant| variant.audio.as_ref());
+                let audio = audio_group.and_then(|group| {
+                    let mut renditions = playlist.alternatives.iter().filter(|media| {
+                        media.media_type == AlternativeMediaType::Audio
+                            && &media.group_id == group
+                            && media.uri.is_some()
+                    });
+                    let first = renditions.clone().next();
+                    renditions.find(|media| media.default).or(first)
+                });
+
+                streams.push((variants, current_variant, None));
+
+                if let Some(audio) = audio {
+                    gst::debug!(CAT, imp = self, "Selected audio rendition '{}'", audio.name);
+                    let variants = vec![Variant {
+                        uri: join(&uri, audio.uri.as_ref().unwrap())?,
+                        bandwidth: 0,
+                    }];
+                    streams.push((variants, 0, None));
+                }
+            }
+        }
+
+        let obj = self.obj();
+        let templ = obj.pad_template("src_%u").unwrap();
+        let mut state = self.state.lock().unwrap();
+        for (variants, current_variant, playlist) in streams {
+            let idx = state.streams.len();
+
+            let appsrc = gst_app::AppSrc::builder()
+                .name(format!("appsrc_{idx}"))
+                .format(gst::Format::Bytes)
+                .block(true)
+                .build();
+            obj.add(&appsrc).unwrap();
+            let _ = appsrc.sync_state_with_parent();
+
+            let pad = gst::GhostPad::builder_from_template_with_target(
+                &templ,
+                &appsrc.static_pad("src").unwrap(),
+            )
+            .unwrap()
+            .name(format!("src_{idx}"))
+            .build();
+            pad.set_active(true).unwrap();
+            obj.add_pad(&pad).unwrap();
+
+            let stream = Stream::new(
+                obj.downgrade(),
+                appsrc.clone(),
+                client.clone(),
+                variants,
+                current_variant,
+                playlist,
+            );
+            let task = RUNTIME.spawn(stream.run());
+
+            state.streams.push(StreamHandle { appsrc, pad, task });
+        }
+        drop(state);
+
+        obj.no_more_pads();
+
+        Ok(())
+    }
+
+    fn stop(&self) {
+        let streams = {
+            let mut state = self.state.lock().unwrap();
+            state.playlist_data.clear();
+            std::mem::take(&mut state.streams)
+        };
+
+        let obj = self.obj();
+        for stream in streams {
+            stream.task.abort();
+
+            let _ = stream.appsrc.set_state(gst::State::Null);
+            let _ = obj.remove(&stream.appsrc);
+            let _ = obj.remove_pad(&stream.pad);
+        }
+    }
+}
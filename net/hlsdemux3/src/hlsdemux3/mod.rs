@@ -0,0 +1,54 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * element-hlsdemux3:
+ * @short-description: HTTP Live Streaming client
+ *
+ * `hlsdemux3` reads a multivariant or media playlist from its sink pad, and then downloads the
+ * media playlists and segments itself over HTTP. The segments are output unmodified on one
+ * source pad per stream, i.e. as MPEG-TS or as fragmented MP4 including the initialization
+ * section signalled via `EXT-X-MAP`.
+ *
+ * For multivariant playlists the variant stream is selected based on the measured download
+ * throughput, or based on the `connection-speed` property if it is set. The default audio
+ * rendition of the selected variant's audio group is exposed as a separate stream.
+ *
+ * Live playlists are reloaded according to their target duration until `EXT-X-ENDLIST` is
+ * found.
+ *
+ * Encrypted segments and seeking are not supported.
+ *
+ * ## Example launch line
+ *
+ * ```shell
+ * gst-launch-1.0 reqwesthttpsrc location=http://127.0.0.1:8080/multivariant.m3u8 ! hlsdemux3 name=demux \
+ *     demux. ! queue ! decodebin3 ! videoconvert ! autovideosink \
+ *     demux. ! queue ! decodebin3 ! audioconvert ! autoaudiosink
+ * ```
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::glib;
+use gst::prelude::*;
+
+mod imp;
+mod stream;
+
+glib::wrapper! {
+    pub struct HlsDemux3(ObjectSubclass<imp::HlsDemux3>) @extends gst::Bin, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "hlsdemux3",
+        gst::Rank::NONE,
+        HlsDemux3::static_type(),
+    )
+}
@@ -0,0 +1,548 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::glib;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use m3u8_rs::{MediaPlaylist, MediaSegment};
+use std::time::{Duration, Instant};
+use url::Url;
+
+use super::imp::CAT;
+
+/// A variant stream of a multivariant playlist, or the only media playlist of a stream.
+#[derive(Debug, Clone)]
+pub(super) struct Variant {
+    pub(super) uri: Url,
+    /// Bandwidth in bits per second
+    pub(super) bandwidth: u64,
+}
+
+/// Byte range of a segment or initialization section as `(offset, length)`.
+type ByteRange = (u64, u64);
+
+/// Downloads the segments of one stream and pushes them into its `appsrc`.
+pub(super) struct Stream {
+    element: glib::WeakRef<super::HlsDemux3>,
+    appsrc: gst_app::AppSrc,
+    client: reqwest::Client,
+    variants: Vec<Variant>,
+    current_variant: usize,
+
+    /// Current media playlist, `None` if it has to be (re)loaded
+    playlist: Option<MediaPlaylist>,
+    /// Effective URI of the current media playlist after redirects
+    playlist_uri: Url,
+    last_reload: Instant,
+    reload_interval: Duration,
+    /// Media sequence number of the next segment to download
+    next_media_sequence: Option<u64>,
+    /// Initialization section that was pushed last
+    current_map: Option<(Url, Option<ByteRange>)>,
+    discont: bool,
+    need_caps: bool,
+    /// Estimated throughput in bits per second
+    bitrate: Option<f64>,
+}
+
+impl Stream {
+    pub(super) fn new(
+        element: glib::WeakRef<super::HlsDemux3>,
+        appsrc: gst_app::AppSrc,
+        client: reqwest::Client,
+        variants: Vec<Variant>,
+        current_variant: usize,
+        playlist: Option<MediaPlaylist>,
+    ) -> Self {
+        let playlist_uri = variants[current_variant].uri.clone();
+        let reload_interval = playlist
+            .as_ref()
+            .map(|playlist| Duration::from_secs(playlist.target_duration.max(1)))
+            .unwrap_or_default();
+
+        Stream {
+            element,
+            appsrc,
+            client,
+            variants,
+            current_variant,
+            playlist,
+            playlist_uri,
+            last_reload: Instant::now(),
+            reload_interval,
+            next_media_sequence: None,
+            current_map: None,
+            discont: true,
+            need_caps: true,
+            bitrate: None,
+        }
+    }
+
+    pub(super) async fn run(mut self) {
+        match self.stream_loop().await {
+            Ok(()) => {
+                gst::debug!(CAT, obj = self.appsrc, "All segments downloaded");
+                let _ = self.appsrc.end_of_stream();
+            }
+            Err(None) => {
+                gst::debug!(CAT, obj = self.appsrc, "Stopped");
+            }
+            Err(Some(err)) => {
+                gst::error!(CAT, obj = self.appsrc, "Failed: {err:?}");
+                if let Some(element) = self.element.upgrade() {
+                    element.post_error_message(err);
+                }
+            }
+        }
+    }
+
+    async fn stream_loop(&mut self) -> Result<(), Option<gst::ErrorMessage>> {
+        loop {
+            if self.playlist.is_none() {
+                self.reload_playlist().await?;
+            }
+            let playlist = self.playlist.as_ref().unwrap();
+
+            let media_sequence = *self
+                .next_media_sequence
+                .get_or_insert_with(|| start_media_sequence(playlist));
+
+            if media_sequence < playlist.media_sequence {
+                gst::warning!(
+                    CAT,
+                    obj = self.appsrc,
+                    "Segment {media_sequence} not in the playlist anymore, continuing with {}",
+                    playlist.media_sequence
+                );
+                self.next_media_sequence = Some(playlist.media_sequence);
+                self.discont = true;
+                continue;
+            }
+
+            let idx = (media_sequence - playlist.media_sequence) as usize;
+            if idx >= playlist.segments.len() {
+                if playlist.end_list {
+                    return Ok(());
+                }
+
+                // Wait for the next segment of the live playlist
+                let deadline = self.last_reload + self.reload_interval;
+                tokio::time::sleep_until(deadline.into()).await;
+                self.reload_playlist().await?;
+                continue;
+            }
+
+            self.download_segment(idx).await?;
+            self.next_media_sequence = Some(media_sequence + 1);
+
+            self.update_variant();
+        }
+    }
+
+    async fn reload_playlist(&mut self) -> Result<(), Option<gst::ErrorMessage>> {
+        let uri = self.variants[self.current_variant].uri.clone();
+        gst::debug!(CAT, obj = self.appsrc, "Loading media playlist {uri}");
+
+        let (playlist_uri, data, _) = self.fetch(&uri, None).await?;
+        let map = data.map_readable().unwrap();
+        let playlist = m3u8_rs::parse_media_playlist_res(&map).map_err(|_| {
+            gst::error_msg!(
+                gst::StreamError::Demux,
+                ["Failed to parse media playlist {}", uri]
+            )
+        })?;
+
+        if playlist.segments.iter().any(|segment| {
+            segment
+                .key
+                .as_ref()
+                .is_some_and(|key| key.method != m3u8_rs::KeyMethod::None)
+        }) {
+            return Err(Some(gst::error_msg!(
+                gst::StreamError::DecryptNokey,
+                ["Encrypted segments are not supported"]
+            )));
+        }
+
+        // Reload after one target duration if the playlist changed, otherwise after half of it
+        let target_duration = Duration::from_secs(playlist.target_duration.max(1));
+        let end =
+            |playlist: &MediaPlaylist| playlist.media_sequence + playlist.segments.len() as u64;
+        self.reload_interval = if self
+            .playlist
+            .as_ref()
+            .is_some_and(|old| end(old) == end(&playlist))
+        {
+            target_duration / 2
+        } else {
+            target_duration
+        };
+        self.last_reload = Instant::now();
+        self.playlist_uri = playlist_uri;
+        self.playlist = Some(playlist);
+
+        Ok(())
+    }
+
+    async fn download_segment(&mut self, idx: usize) -> Result<(), Option<gst::ErrorMessage>> {
+        let playlist = self.playlist.as_ref().unwrap();
+        let segment = &playlist.segments[idx];
+
+        let uri = self.join(&segment.uri)?;
+        let byte_range = resolve_byte_range(&playlist.segments, idx);
+        let discontinuity = segment.discontinuity;
+
+        // The initialization section applies to all following segments until the next one
+        let map = playlist.segments[..=idx]
+            .iter()
+            .rev()
+            .find_map(|segment| segment.map.as_ref())
+            .map(|map| {
+                let byte_range = map
+                    .byte_range
+                    .as_ref()
+                    .map(|range| (range.offset.unwrap_or(0), range.length));
+                self.join(&map.uri).map(|uri| (uri, byte_range))
+            })
+            .transpose()?;
+
+        if discontinuity {
+            self.discont = true;
+        }
+
+        if map.is_some() && map != self.current_map {
+            let (map_uri, map_byte_range) = map.as_ref().unwrap();
+            gst::debug!(
+                CAT,
+                obj = self.appsrc,
+                "Downloading initialization section {map_uri}"
+            );
+
+            let (_, data, _) = self.fetch(map_uri, *map_byte_range).await?;
+            self.push(data, true)?;
+            self.current_map = map;
+        }
+
+        gst::debug!(CAT, obj = self.appsrc, "Downloading segment {uri}");
+
+        let (_, data, elapsed) = self.fetch(&uri, byte_range).await?;
+
+        let measured = (data.size() * 8) as f64 / elapsed.as_secs_f64().max(0.001);
+        self.bitrate = Some(match self.bitrate {
+            Some(bitrate) => 0.8 * bitrate + 0.2 * measured,
+            None => measured,
+        });
+        gst::trace!(
+            CAT,
+            obj = self.appsrc,
+            "Measured {measured:.0} bits/s, estimated {:.0} bits/s",
+            self.bitrate.unwrap()
+        );
+
+        self.push(data, false)
+    }
+
+    /// Switches to another variant if the available bandwidth changed accordingly.
+    fn update_variant(&mut self) {
+        if self.variants.len() < 2 {
+            return;
+        }
+
+        let Some(element) = self.element.upgrade() else {
+            return;
+        };
+        let (connection_speed, bitrate_limit) = {
+            let settings = element.imp().settings.lock().unwrap();
+            (settings.connection_speed, settings.bitrate_limit)
+        };
+
+        let available = if connection_speed > 0 {
+            connection_speed as f64 * 1000.0
+        } else if let Some(bitrate) = self.bitrate {
+            bitrate
+        } else {
+            return;
+        };
+
+        let variant = select_variant(&self.variants, (available * bitrate_limit as f64) as u64);
+        if variant == self.current_variant {
+            return;
+        }
+
+        let Variant { ref uri, bandwidth } = self.variants[variant];
+        gst::info!(
+            CAT,
+            obj = self.appsrc,
+            "Switching to variant {uri} with bandwidth {bandwidth}"
+        );
+
+        element.post_message(
+            gst::message::Element::builder(
+                gst::Structure::builder("hls-variant-switched")
+                    .field("uri", uri.as_str())
+                    .field("bandwidth", bandwidth)
+                    .build(),
+            )
+            .src(&element)
+            .build(),
+        );
+
+        // Media sequence numbers are aligned between variants so downloading continues
+        // with the next segment from the playlist of the new variant
+        self.current_variant = variant;
+        self.playlist = None;
+        self.current_map = None;
+        self.discont = true;
+        self.need_caps = true;
+    }
+
+    fn join(&self, relative: &str) -> Result<Url, gst::ErrorMessage> {
+        self.playlist_uri.join(relative).map_err(|err| {
+            gst::error_msg!(
+                gst::StreamError::Demux,
+                ["Invalid URI '{}': {}", relative, err]
+            )
+        })
+    }
+
+    /// Downloads `uri` and returns the effective URI after redirects, the data and the time it
+    /// took.
+    async fn fetch(
+        &self,
+        uri: &Url,
+        byte_range: Option<ByteRange>,
+    ) -> Result<(Url, gst::Buffer, Duration), gst::ErrorMessage> {
+        let start = Instant::now();
+
+        let mut request = self.client.get(uri.clone());
+        if let Some((offset, length)) = byte_range {
+            let range = range_header(offset, length).ok_or_else(|| {
+                gst::error_msg!(
+                    gst::StreamError::Demux,
+                    ["Invalid byte range {}@{} for {}", length, offset, uri]
+                )
+            })?;
+            request = request.header(reqwest::header::RANGE, range);
+        }
+
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| {
+                gst::error_msg!(
+                    gst::ResourceError::Read,
+                    ["Failed to download {}: {}", uri, err]
+                )
+            })?;
+        let effective_uri = response.url().clone();
+
+        let data = response.bytes().await.map_err(|err| {
+            gst::error_msg!(
+                gst::ResourceError::Read,
+                ["Failed to download {}: {}", uri, err]
+            )
+        })?;
+
+        Ok((
+            effective_uri,
+            gst::Buffer::from_slice(data),
+            start.elapsed(),
+        ))
+    }
+
+    fn push(
+        &mut self,
+        mut buffer: gst::Buffer,
+        header: bool,
+    ) -> Result<(), Option<gst::ErrorMessage>> {
+        if self.need_caps {
+            let map = buffer.map_readable().unwrap();
+            let (caps, _) =
+                gst_base::type_find_helper_for_data(Some(&self.appsrc), &map).map_err(|_| {
+                    gst::error_msg!(
+                        gst::StreamError::TypeNotFound,
+                        ["Could not determine type of stream"]
+                    )
+                })?;
+            drop(map);
+
+            gst::debug!(CAT, obj = self.appsrc, "Detected caps {caps:?}");
+            self.appsrc.set_caps(Some(&caps));
+            self.need_caps = false;
+        }
+
+        {
+            let buffer = buffer.get_mut().unwrap();
+            if self.discont {
+                buffer.set_flags(gst::BufferFlags::DISCONT);
+            }
+            if header {
+                buffer.set_flags(gst::BufferFlags::HEADER);
+            }
+        }
+        self.discont = false;
+
+        // Blocks until there is enough space in the queue of the appsrc
+        match tokio::task::block_in_place(|| self.appsrc.push_buffer(buffer)) {
+            Ok(_) => Ok(()),
+            Err(gst::FlowError::Flushing) | Err(gst::FlowError::Eos) => Err(None),
+            Err(err) => Err(Some(gst::error_msg!(
+                gst::StreamError::Failed,
+                ["Failed to push buffer: {:?}", err]
+            ))),
+        }
+    }
+}
+
+/// Selects the variant with the highest bandwidth below `bitrate`, or the one with the lowest
+/// bandwidth if there is none.
+pub(super) fn select_variant(variants: &[Variant], bitrate: u64) -> usize {
+    variants
+        .iter()
+        .enumerate()
+        .filter(|(_, variant)| variant.bandwidth <= bitrate)
+        .max_by_key(|(_, variant)| variant.bandwidth)
+        .or_else(|| {
+            variants
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, variant)| variant.bandwidth)
+        })
+        .map(|(idx, _)| idx)
+        .unwrap_or(0)
+}
+
+/// Returns the media sequence number of the segment to start with.
+///
+/// Live playlists are started at least three target durations from the end.
+fn start_media_sequence(playlist: &MediaPlaylist) -> u64 {
+    if playlist.end_list {
+        return playlist.media_sequence;
+    }
+
+    let limit = 3.0 * playlist.target_duration as f32;
+    let mut duration = 0.0;
+    let mut idx = playlist.segments.len();
+    while idx > 0 && duration < limit {
+        idx -= 1;
+        duration += playlist.segments[idx].duration;
+    }
+
+    playlist.media_sequence + idx as u64
+}
+
+/// Resolves the byte range of the segment at `idx`. Without an explicit offset the range starts
+/// right after the range of the previous segment of the same resource.
+fn resolve_byte_range(segments: &[MediaSegment], idx: usize) -> Option<ByteRange> {
+    let length = segments[idx].byte_range.as_ref()?.length;
+
+    let mut offset = 0;
+    let mut i = idx;
+    loop {
+        if let Some(start) = segments[i]
+            .byte_range
+            .as_ref()
+            .and_then(|range| range.offset)
+        {
+            offset += start;
+            break;
+        }
+
+        if i == 0 || segments[i - 1].uri != segments[idx].uri {
+            break;
+        }
+        i -= 1;
+
+        let Some(ref range) = segments[i].byte_range else {
+            break;
+        };
+        offset += range.length;
+    }
+
+    Some((offset, length))
+}
+
+/// Creates the value of the HTTP `Range` header for a byte range. Returns `None` for empty
+/// ranges or ranges that go beyond the maximum offset.
+fn range_header(offset: u64, length: u64) -> Option<String> {
+    let end = offset.checked_add(length.checked_sub(1)?)?;
+
+    Some(format!("bytes={offset}-{end}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(uri: &str, duration: f32, byte_range: Option<(u64, Option<u64>)>) -> MediaSegment {
+        MediaSegment {
+            uri: uri.into(),
+            duration,
+            byte_range: byte_range.map(|(length, offset)| m3u8_rs::ByteRange { length, offset }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_select_variant() {
+        let variants = [500_000, 2_000_000, 1_000_000]
+            .into_iter()
+            .map(|bandwidth| Variant {
+                uri: Url::parse("http://127.0.0.1/playlist.m3u8").unwrap(),
+                bandwidth,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(select_variant(&variants, 100_000), 0);
+        assert_eq!(select_variant(&variants, 1_500_000), 2);
+        assert_eq!(select_variant(&variants, 10_000_000), 1);
+    }
+
+    #[test]
+    fn test_start_media_sequence() {
+        let mut playlist = MediaPlaylist {
+            target_duration: 2,
+            media_sequence: 10,
+            segments: (0..6)
+                .map(|idx| segment(&format!("segment{idx}.ts"), 2.0, None))
+                .collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(start_media_sequence(&playlist), 13);
+
+        playlist.end_list = true;
+        assert_eq!(start_media_sequence(&playlist), 10);
+    }
+
+    #[test]
+    fn test_resolve_byte_range() {
+        let segments = [
+            segment("main.mp4", 2.0, Some((100, Some(50)))),
+            segment("main.mp4", 2.0, Some((200, None))),
+            segment("main.mp4", 2.0, Some((300, None))),
+            segment("other.mp4", 2.0, Some((400, None))),
+            segment("other.mp4", 2.0, None),
+        ];
+
+        assert_eq!(resolve_byte_range(&segments, 0), Some((50, 100)));
+        assert_eq!(resolve_byte_range(&segments, 1), Some((150, 200)));
+        assert_eq!(resolve_byte_range(&segments, 2), Some((350, 300)));
+        assert_eq!(resolve_byte_range(&segments, 3), Some((0, 400)));
+        assert_eq!(resolve_byte_range(&segments, 4), None);
+    }
+
+    #[test]
+    fn test_range_header() {
+        assert_eq!(range_header(50, 100).as_deref(), Some("bytes=50-149"));
+        assert_eq!(range_header(0, 1).as_deref(), Some("bytes=0-0"));
+        assert_eq!(range_header(50, 0), None);
+        assert_eq!(range_header(u64::MAX, 2), None);
+    }
+}
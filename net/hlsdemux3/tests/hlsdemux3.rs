@@ -0,0 +1,421 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::prelude::*;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+fn init() {
+    use std::sync::Once;
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        // clear this environment because it affects the default settings
+        std::env::remove_var("http_proxy");
+        gst::init().unwrap();
+        gsthlsdemux3::plugin_register_static().expect("hlsdemux3 test");
+        gsthlssink3::plugin_register_static().expect("hlsdemux3 test");
+        gstreqwest::plugin_register_static().expect("hlsdemux3 test");
+        gstfmp4::plugin_register_static().expect("hlsdemux3 test");
+    });
+}
+
+/// Runs `pipeline` until EOS. Returns `false` if any of the elements is not available.
+fn run_pipeline(pipeline: &str) -> bool {
+    let pipeline = match gst::parse::launch(pipeline) {
+        Ok(pipeline) => pipeline,
+        Err(err) => {
+            eprintln!("Skipping test: {err}");
+            return false;
+        }
+    };
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+    let bus = pipeline.bus().unwrap();
+    let msg = bus
+        .timed_pop_filtered(
+            gst::ClockTime::from_seconds(60),
+            &[gst::MessageType::Eos, gst::MessageType::Error],
+        )
+        .expect("Timed out");
+    pipeline.set_state(gst::State::Null).unwrap();
+
+    if let gst::MessageView::Error(err) = msg.view() {
+        panic!("Error generating content: {err:?}");
+    }
+
+    true
+}
+
+/// Writes three one-second segments with the given muxer sink into `dir`.
+fn generate_content(dir: &Path, bitrate: u32, sink: &str) -> bool {
+    std::fs::create_dir_all(dir).unwrap();
+
+    run_pipeline(&format!(
+        "videotestsrc num-buffers=90 ! video/x-raw,width=320,height=240,framerate=30/1 ! \
+         x264enc bitrate={bitrate} key-int-max=30 ! h264parse ! \
+         {sink} target-duration=1 playlist-type=vod max-files=0 \
+         playlist-location={}",
+        dir.join("playlist.m3u8").display(),
+    ))
+}
+
+fn generate_ts(dir: &Path) -> bool {
+    generate_content(
+        dir,
+        500,
+        &format!("hlssink3 location={}", dir.join("segment%05d.ts").display()),
+    )
+}
+
+fn generate_cmaf(dir: &Path, bitrate: u32) -> bool {
+    generate_content(
+        dir,
+        bitrate,
+        &format!(
+            "hlscmafsink location={} init-location={}",
+            dir.join("segment%05d.m4s").display(),
+            dir.join("init%05d.mp4").display(),
+        ),
+    )
+}
+
+/// Minimal HTTP server serving the files of a directory.
+///
+/// In live mode media playlists are served with two segments initially and one more segment
+/// for every request, and without `EXT-X-ENDLIST` until all segments are included.
+struct Server {
+    addr: SocketAddr,
+}
+
+impl Server {
+    fn new(root: PathBuf, live: bool) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let playlist_requests = Arc::new(AtomicUsize::new(0));
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    break;
+                };
+                let root = root.clone();
+                let playlist_requests = playlist_requests.clone();
+                std::thread::spawn(move || {
+                    handle_request(stream, &root, live.then_some(&*playlist_requests))
+                });
+            }
+        });
+
+        Server { addr }
+    }
+
+    fn uri(&self, path: &str) -> String {
+        format!("http://{}/{path}", self.addr)
+    }
+}
+
+fn handle_request(mut stream: TcpStream, root: &Path, playlist_requests: Option<&AtomicUsize>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap()
+        .trim_start_matches('/')
+        .to_string();
+
+    let mut range = None;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("range") {
+                let (start, end) = value
+                    .trim()
+                    .strip_prefix("bytes=")
+                    .unwrap()
+                    .split_once('-')
+                    .unwrap();
+                range = Some((
+                    start.parse::<usize>().unwrap(),
+                    end.parse::<usize>().unwrap(),
+                ));
+            }
+        }
+    }
+
+    let Ok(mut data) = std::fs::read(root.join(&path)) else {
+        write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+        return;
+    };
+
+    if let Some(playlist_requests) = playlist_requests.filter(|_| path.ends_with(".m3u8")) {
+        if let Ok(m3u8_rs::Playlist::MediaPlaylist(mut playlist)) =
+            m3u8_rs::parse_playlist_res(&data)
+        {
+            let n_segments = 2 + playlist_requests.fetch_add(1, Ordering::SeqCst);
+            if n_segments < playlist.segments.len() {
+                playlist.segments.truncate(n_segments);
+                playlist.end_list = false;
+            }
+            playlist.playlist_type = None;
+
+            data.clear();
+            playlist.write_to(&mut data).unwrap();
+        }
+    }
+
+    let status = if let Some((start, end)) = range {
+        let len = data.len();
+        data = data[start..=end].to_vec();
+        format!("206 Partial Content\r\nContent-Range: bytes {start}-{end}/{len}")
+    } else {
+        String::from("200 OK")
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        data.len()
+    )
+    .unwrap();
+    stream.write_all(&data).unwrap();
+}
+
+#[derive(Default)]
+struct Output {
+    caps: Vec<gst::Caps>,
+    buffers: Vec<gst::Buffer>,
+}
+
+/// Plays `uri` through `hlsdemux3` and returns the output of each source pad and all element
+/// messages of the demuxer.
+fn run_demux(uri: &str, connection_speed: u32) -> (Vec<Output>, Vec<gst::Structure>) {
+    let pipeline = gst::Pipeline::default();
+    let src = gst::ElementFactory::make("reqwesthttpsrc")
+        .property("location", uri)
+        .build()
+        .unwrap();
+    let demux = gst::ElementFactory::make("hlsdemux3")
+        .property("connection-speed", connection_speed)
+        .build()
+        .unwrap();
+    pipeline.add_many([&src, &demux]).unwrap();
+    src.link(&demux).unwrap();
+
+    let outputs = Arc::new(Mutex::new(Vec::new()));
+    demux.connect_pad_added({
+        let outputs = outputs.clone();
+        move |demux, pad| {
+            let idx = {
+                let mut outputs = outputs.lock().unwrap();
+                outputs.push(Output::default());
+                outputs.len() - 1
+            };
+
+            let outputs = outputs.clone();
+            let appsink = gst_app::AppSink::builder()
+                .sync(false)
+                .callbacks(
+                    gst_app::AppSinkCallbacks::builder()
+                        .new_sample(move |appsink| {
+                            let sample = appsink.pull_sample().unwrap();
+                            let mut outputs = outputs.lock().unwrap();
+                            let output = &mut outputs[idx];
+                            let caps = sample.caps_owned().unwrap();
+                            if output.caps.last() != Some(&caps) {
+                                output.caps.push(caps);
+                            }
+                            output.buffers.push(sample.buffer_owned().unwrap());
+
+                            Ok(gst::FlowSuccess::Ok)
+                        })
+                        .build(),
+                )
+                .build();
+
+            let pipeline = demux.parent().unwrap().downcast::<gst::Bin>().unwrap();
+            pipeline.add(&appsink).unwrap();
+            appsink.sync_state_with_parent().unwrap();
+            pad.link(&appsink.static_pad("sink").unwrap()).unwrap();
+        }
+    });
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+
+    let mut messages = Vec::new();
+    let bus = pipeline.bus().unwrap();
+    loop {
+        let msg = bus
+            .timed_pop_filtered(
+                gst::ClockTime::from_seconds(60),
+                &[
+                    gst::MessageType::Eos,
+                    gst::MessageType::Error,
+                    gst::MessageType::Element,
+                ],
+            )
+            .expect("Timed out");
+
+        match msg.view() {
+            gst::MessageView::Eos(..) => break,
+            gst::MessageView::Error(err) => panic!("Error: {err:?}"),
+            gst::MessageView::Element(msg) => {
+                if msg.src() == Some(demux.upcast_ref::<gst::Object>()) {
+                    messages.push(msg.structure().unwrap().to_owned());
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pipeline.set_state(gst::State::Null).unwrap();
+
+    let outputs = std::mem::take(&mut *outputs.lock().unwrap());
+    (outputs, messages)
+}
+
+/// Concatenates all segments listed in the media playlist at `path`.
+fn segments_data(path: &Path) -> Vec<u8> {
+    let data = std::fs::read(path).unwrap();
+    let playlist = m3u8_rs::parse_media_playlist_res(&data).unwrap();
+
+    playlist
+        .segments
+        .iter()
+        .flat_map(|segment| std::fs::read(path.parent().unwrap().join(&segment.uri)).unwrap())
+        .collect()
+}
+
+fn buffers_data(buffers: &[gst::Buffer]) -> Vec<u8> {
+    buffers
+        .iter()
+        .flat_map(|buffer| buffer.map_readable().unwrap().to_vec())
+        .collect()
+}
+
+#[test]
+fn test_hlsdemux3_mpegts() {
+    init();
+
+    let dir = tempfile::tempdir().unwrap();
+    if !generate_ts(dir.path()) {
+        return;
+    }
+
+    let server = Server::new(dir.path().to_owned(), false);
+    let (outputs, _) = run_demux(&server.uri("playlist.m3u8"), 0);
+
+    assert_eq!(outputs.len(), 1);
+    let output = &outputs[0];
+    assert_eq!(output.caps.len(), 1);
+    assert_eq!(output.caps[0].structure(0).unwrap().name(), "video/mpegts");
+    assert!(output.buffers[0]
+        .flags()
+        .contains(gst::BufferFlags::DISCONT));
+    assert_eq!(
+        buffers_data(&output.buffers),
+        segments_data(&dir.path().join("playlist.m3u8"))
+    );
+}
+
+#[test]
+fn test_hlsdemux3_live() {
+    init();
+
+    let dir = tempfile::tempdir().unwrap();
+    if !generate_ts(dir.path()) {
+        return;
+    }
+
+    let server = Server::new(dir.path().to_owned(), true);
+    let (outputs, _) = run_demux(&server.uri("playlist.m3u8"), 0);
+
+    // All segments are downloaded while the playlist grows, until EXT-X-ENDLIST appears
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(
+        buffers_data(&outputs[0].buffers),
+        segments_data(&dir.path().join("playlist.m3u8"))
+    );
+}
+
+#[test]
+fn test_hlsdemux3_cmaf_variant_switch() {
+    init();
+
+    let dir = tempfile::tempdir().unwrap();
+    if !generate_cmaf(&dir.path().join("low"), 300)
+        || !generate_cmaf(&dir.path().join("high"), 1000)
+    {
+        return;
+    }
+
+    std::fs::write(
+        dir.path().join("multivariant.m3u8"),
+        "#EXTM3U\n\
+         #EXT-X-STREAM-INF:BANDWIDTH=400000,CODECS=\"avc1.64000d\"\n\
+         low/playlist.m3u8\n\
+         #EXT-X-STREAM-INF:BANDWIDTH=1200000,CODECS=\"avc1.64000d\"\n\
+         high/playlist.m3u8\n",
+    )
+    .unwrap();
+
+    let server = Server::new(dir.path().to_owned(), false);
+
+    // A low connection speed keeps the lowest variant
+    let (outputs, messages) = run_demux(&server.uri("multivariant.m3u8"), 100);
+    assert_eq!(outputs.len(), 1);
+    assert!(messages.is_empty());
+    let output = &outputs[0];
+    assert_eq!(
+        output.caps[0].structure(0).unwrap().name(),
+        "video/quicktime"
+    );
+    assert!(output.buffers[0]
+        .flags()
+        .contains(gst::BufferFlags::HEADER | gst::BufferFlags::DISCONT));
+    assert_eq!(
+        buffers_data(&output.buffers[1..]),
+        segments_data(&dir.path().join("low/playlist.m3u8"))
+    );
+
+    // Without a configured connection speed the measured throughput on localhost selects the
+    // highest variant after the first segment
+    let (outputs, messages) = run_demux(&server.uri("multivariant.m3u8"), 0);
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].name(), "hls-variant-switched");
+    assert_eq!(messages[0].get::<u64>("bandwidth").unwrap(), 1_200_000);
+    assert_eq!(
+        messages[0].get::<&str>("uri").unwrap(),
+        server.uri("high/playlist.m3u8")
+    );
+
+    // Both initialization sections are output in front of the segments of their variant
+    let headers = outputs[0]
+        .buffers
+        .iter()
+        .filter(|buffer| buffer.flags().contains(gst::BufferFlags::HEADER))
+        .count();
+    assert_eq!(headers, 2);
+}
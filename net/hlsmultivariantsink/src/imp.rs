@@ -12,10 +12,11 @@
  * renditions and variant streams. Builds on top of and requires `hlscmafsink`
  * and `hlssink3`.
  *
- * TODO:
- *
- * - Support for closed captions
- * - Support for WebVTT subtitles
+ * Subtitles are requested as `subtitle_%u` pads and are always alternate
+ * renditions. They are written as WebVTT segments by `hlswebvttsink` for
+ * MPEG-TS, or as `wvtt` CMAF segments by `hlscmafsink`. Closed captions
+ * are carried in-band in the video and are only declared in the
+ * multivariant playlist with the `closed-captions` property.
  *
  * NOT SUPPORTED:
  *
@@ -24,15 +25,16 @@
  */
 
 use crate::{
-    HlsMultivariantSinkAlternativeMediaType, HlsMultivariantSinkMuxerType,
-    HlsMultivariantSinkPlaylistType,
+    HlsMultivariantSinkAlternativeMediaType, HlsMultivariantSinkEncryptionMethod,
+    HlsMultivariantSinkMuxerType, HlsMultivariantSinkPlaylistType,
 };
 use gio::prelude::*;
 use gst::glib;
 use gst::prelude::*;
 use gst::subclass::prelude::*;
 use m3u8_rs::{
-    AlternativeMedia, AlternativeMediaType, MasterPlaylist, MediaPlaylistType, VariantStream,
+    AlternativeMedia, AlternativeMediaType, ClosedCaptionGroupId, InstreamId, MasterPlaylist,
+    MediaPlaylistType, VariantStream,
 };
 use std::collections::HashMap;
 use std::convert::From;
@@ -58,13 +60,22 @@ const DEFAULT_TARGET_DURATION: u32 = 15;
 const DEFAULT_INIT_LOCATION: &str = "init%05d.mp4";
 const DEFAULT_CMAF_LOCATION: &str = "segment%05d.m4s";
 const DEFAULT_TS_LOCATION: &str = "segment%05d.ts";
+const DEFAULT_VTT_LOCATION: &str = "segment%05d.vtt";
 const DEFAULT_MULTIVARIANT_PLAYLIST_LOCATION: &str = "multivariant.m3u8";
+const DEFAULT_ENCRYPTION_METHOD: HlsMultivariantSinkEncryptionMethod =
+    HlsMultivariantSinkEncryptionMethod::None;
+const DEFAULT_KEY_LOCATION: &str = "key%05d.key";
+const DEFAULT_KEY_ROTATION_PERIOD: u32 = 0;
 
 const SIGNAL_DELETE_FRAGMENT: &str = "delete-fragment";
 const SIGNAL_GET_FRAGMENT_STREAM: &str = "get-fragment-stream";
 const SIGNAL_GET_INIT_STREAM: &str = "get-init-stream";
 const SIGNAL_GET_MULTIVARIANT_PLAYLIST_STREAM: &str = "get-multivariant-playlist-stream";
 const SIGNAL_GET_PLAYLIST_STREAM: &str = "get-playlist-stream";
+const SIGNAL_GET_KEY: &str = "get-key";
+const SIGNAL_GET_KEY_STREAM: &str = "get-key-stream";
+const SIGNAL_DELETE_KEY: &str = "delete-key";
+const SIGNAL_ROTATE_KEY: &str = "rotate-key";
 
 static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
@@ -79,6 +90,10 @@ impl From<HlsMultivariantSinkAlternativeMediaType> for AlternativeMediaType {
         match media_type {
             HlsMultivariantSinkAlternativeMediaType::Audio => AlternativeMediaType::Audio,
             HlsMultivariantSinkAlternativeMediaType::Video => AlternativeMediaType::Video,
+            HlsMultivariantSinkAlternativeMediaType::Subtitles => AlternativeMediaType::Subtitles,
+            HlsMultivariantSinkAlternativeMediaType::ClosedCaptions => {
+                AlternativeMediaType::ClosedCaptions
+            }
         }
     }
 }
@@ -88,8 +103,10 @@ impl From<AlternativeMediaType> for HlsMultivariantSinkAlternativeMediaType {
         match value {
             AlternativeMediaType::Audio => HlsMultivariantSinkAlternativeMediaType::Audio,
             AlternativeMediaType::Video => HlsMultivariantSinkAlternativeMediaType::Video,
-            AlternativeMediaType::ClosedCaptions => unimplemented!(),
-            AlternativeMediaType::Subtitles => unimplemented!(),
+            AlternativeMediaType::ClosedCaptions => {
+                HlsMultivariantSinkAlternativeMediaType::ClosedCaptions
+            }
+            AlternativeMediaType::Subtitles => HlsMultivariantSinkAlternativeMediaType::Subtitles,
             AlternativeMediaType::Other(_) => unimplemented!(),
         }
     }
@@ -104,7 +121,11 @@ impl FromStr for HlsMultivariantSinkAlternativeMediaType {
             "VIDEO" => Ok(HlsMultivariantSinkAlternativeMediaType::Video),
             "audio" => Ok(HlsMultivariantSinkAlternativeMediaType::Audio),
             "video" => Ok(HlsMultivariantSinkAlternativeMediaType::Video),
-            _ => unimplemented!(),
+            "SUBTITLES" | "subtitles" => Ok(HlsMultivariantSinkAlternativeMediaType::Subtitles),
+            "CLOSED-CAPTIONS" | "closed-captions" => {
+                Ok(HlsMultivariantSinkAlternativeMediaType::ClosedCaptions)
+            }
+            _ => Err(format!("Unknown media type {s}")),
         }
     }
 }
@@ -117,6 +138,22 @@ impl Display for HlsMultivariantSinkAlternativeMediaType {
             match self {
                 HlsMultivariantSinkAlternativeMediaType::Audio => "AUDIO",
                 HlsMultivariantSinkAlternativeMediaType::Video => "VIDEO",
+                HlsMultivariantSinkAlternativeMediaType::Subtitles => "SUBTITLES",
+                HlsMultivariantSinkAlternativeMediaType::ClosedCaptions => "CLOSED-CAPTIONS",
+            }
+        )
+    }
+}
+
+impl Display for HlsMultivariantSinkEncryptionMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                HlsMultivariantSinkEncryptionMethod::None => "none",
+                HlsMultivariantSinkEncryptionMethod::Aes128 => "aes-128",
+                HlsMultivariantSinkEncryptionMethod::SampleAes => "sample-aes",
             }
         )
     }
@@ -232,6 +269,73 @@ impl From<AlternateRendition> for gst::Structure {
     }
 }
 
+/*
+ * Closed captions are carried in-band in the video of the variant streams,
+ * so there is no pad and no media playlist for them. They are only declared
+ * with an EXT-X-MEDIA tag of TYPE CLOSED-CAPTIONS in the multivariant
+ * playlist.
+ */
+#[derive(Clone, Debug, Default)]
+struct ClosedCaptionRendition {
+    group_id: String,
+    /* CC1 to CC4 for CEA-608, SERVICE1 to SERVICE63 for CEA-708 */
+    instream_id: String,
+    language: Option<String>,
+    name: String,
+    default: bool,
+    autoselect: bool,
+}
+
+impl TryFrom<&gst::StructureRef> for ClosedCaptionRendition {
+    type Error = String;
+
+    fn try_from(s: &gst::StructureRef) -> Result<Self, String> {
+        Ok(ClosedCaptionRendition {
+            group_id: s
+                .get("group_id")
+                .map_err(|_| "group_id missing in closed captions")?,
+            instream_id: s
+                .get("instream_id")
+                .map_err(|_| "instream_id missing in closed captions")?,
+            language: s.get("language").unwrap_or(None),
+            name: s
+                .get("name")
+                .map_err(|_| "name missing in closed captions")?,
+            default: s.get("default").unwrap_or(DEFAULT_IS_DEFAULT),
+            autoselect: s.get("autoselect").unwrap_or(DEFAULT_AUTO_SELECT),
+        })
+    }
+}
+
+impl From<&ClosedCaptionRendition> for gst::Structure {
+    fn from(obj: &ClosedCaptionRendition) -> Self {
+        gst::Structure::builder("closed-captions")
+            .field("group_id", &obj.group_id)
+            .field("instream_id", &obj.instream_id)
+            .field("language", &obj.language)
+            .field("name", &obj.name)
+            .field("default", obj.default)
+            .field("autoselect", obj.autoselect)
+            .build()
+    }
+}
+
+impl From<&ClosedCaptionRendition> for AlternativeMedia {
+    fn from(rendition: &ClosedCaptionRendition) -> Self {
+        Self {
+            media_type: AlternativeMediaType::ClosedCaptions,
+            uri: None,
+            group_id: rendition.group_id.clone(),
+            language: rendition.language.clone(),
+            name: rendition.name.clone(),
+            default: rendition.default,
+            autoselect: rendition.autoselect,
+            instream_id: InstreamId::from_str(&rendition.instream_id).ok(),
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq)]
 struct Variant {
     /* No effect when using hlscmafsink which is the default */
@@ -245,6 +349,9 @@ struct Variant {
     codecs: Option<String>,
     audio: Option<String>,
     video: Option<String>,
+    subtitles: Option<String>,
+    /* NONE if the variant stream has no closed captions */
+    closed_captions: Option<String>,
 }
 
 impl From<gst::Structure> for Variant {
@@ -259,6 +366,8 @@ impl From<gst::Structure> for Variant {
                 .expect("bandwidth missing in variant stream") as u64,
             audio: s.get("audio").unwrap_or(None),
             video: s.get("video").unwrap_or(None),
+            subtitles: s.get("subtitles").unwrap_or(None),
+            closed_captions: s.get("closed-captions").unwrap_or(None),
             codecs: s.get("codecs").unwrap_or(None),
         }
     }
@@ -273,6 +382,8 @@ impl From<Variant> for gst::Structure {
             .field("codecs", obj.codecs)
             .field("audio", obj.audio)
             .field("video", obj.video)
+            .field("subtitles", obj.subtitles)
+            .field("closed-captions", obj.closed_captions)
             .build()
     }
 }
@@ -286,6 +397,14 @@ impl From<&Variant> for VariantStream {
             codecs: variant.codecs.clone(),
             audio: variant.audio.clone(),
             video: variant.video.clone(),
+            subtitles: variant.subtitles.clone(),
+            closed_captions: variant.closed_captions.as_deref().map(|group_id| {
+                if group_id == "NONE" {
+                    ClosedCaptionGroupId::None
+                } else {
+                    ClosedCaptionGroupId::GroupId(group_id.to_string())
+                }
+            }),
             ..Default::default()
         }
     }
@@ -300,6 +419,13 @@ impl From<VariantStream> for Variant {
             codecs: value.codecs,
             audio: value.audio,
             video: value.video,
+            subtitles: value.subtitles,
+            closed_captions: value.closed_captions.map(|group_id| match group_id {
+                ClosedCaptionGroupId::None => String::from("NONE"),
+                ClosedCaptionGroupId::GroupId(group_id) | ClosedCaptionGroupId::Other(group_id) => {
+                    group_id
+                }
+            }),
         }
     }
 }
@@ -418,26 +544,36 @@ fn get_existing_hlssink_for_variant(
     uri: String,
     muxer_type: HlsMultivariantSinkMuxerType,
 ) -> (bool, String, gst::Element) {
-    let sink_name = hlssink_name(uri, muxer_type);
+    let sink_name = hlssink_name(uri, muxer_type, false);
 
     match muxer_type {
         HlsMultivariantSinkMuxerType::Cmaf => {
-            let hlssink = hlssink_element(muxer_type, sink_name.clone());
+            let hlssink = hlssink_element(muxer_type, sink_name.clone(), false);
             (false, sink_name, hlssink)
         }
         HlsMultivariantSinkMuxerType::MpegTs => {
             if let Some(hlssink) = elem.obj().by_name(&sink_name) {
                 (true, sink_name, hlssink)
             } else {
-                let hlssink = hlssink_element(muxer_type, sink_name.clone());
+                let hlssink = hlssink_element(muxer_type, sink_name.clone(), false);
                 (false, sink_name, hlssink)
             }
         }
     }
 }
 
-fn hlssink_element(muxer_type: HlsMultivariantSinkMuxerType, sink_name: String) -> gst::Element {
+fn hlssink_element(
+    muxer_type: HlsMultivariantSinkMuxerType,
+    sink_name: String,
+    is_subtitle: bool,
+) -> gst::Element {
     match muxer_type {
+        HlsMultivariantSinkMuxerType::MpegTs if is_subtitle => {
+            gst::ElementFactory::make("hlswebvttsink")
+                .name(sink_name)
+                .build()
+                .expect("hlswebvttsink must be available")
+        }
         HlsMultivariantSinkMuxerType::Cmaf => gst::ElementFactory::make("hlscmafsink")
             .name(sink_name)
             .build()
@@ -449,8 +585,15 @@ fn hlssink_element(muxer_type: HlsMultivariantSinkMuxerType, sink_name: String)
     }
 }
 
-fn hlssink_name(uri: String, muxer_type: HlsMultivariantSinkMuxerType) -> String {
+fn hlssink_name(
+    uri: String,
+    muxer_type: HlsMultivariantSinkMuxerType,
+    is_subtitle: bool,
+) -> String {
     match muxer_type {
+        HlsMultivariantSinkMuxerType::MpegTs if is_subtitle => {
+            format!("hlswebvttsink-{uri}").to_string()
+        }
         HlsMultivariantSinkMuxerType::Cmaf => format!("hlscmafsink-{uri}").to_string(),
         HlsMultivariantSinkMuxerType::MpegTs => format!("hlssink3-{uri}").to_string(),
     }
@@ -460,8 +603,12 @@ fn hlssink_pad(
     hlssink: &gst::Element,
     muxer_type: HlsMultivariantSinkMuxerType,
     is_video: bool,
+    is_subtitle: bool,
 ) -> gst::Pad {
     match muxer_type {
+        HlsMultivariantSinkMuxerType::MpegTs if is_subtitle => hlssink
+            .static_pad("sink")
+            .expect("hlswebvttsink always has a sink pad"),
         HlsMultivariantSinkMuxerType::Cmaf => hlssink
             .static_pad("sink")
             .expect("hlscmafsink always has a sink pad"),
@@ -526,6 +673,7 @@ fn hlssink_setup_paths_relative(
     pad: &HlsMultivariantSinkPad,
     hlssink: &gst::Element,
     muxer_type: HlsMultivariantSinkMuxerType,
+    is_subtitle: bool,
     multivariant_playlist_location: String,
     uri: String,
 ) -> Result<(), gst::ErrorMessage> {
@@ -577,6 +725,12 @@ fn hlssink_setup_paths_relative(
                 format!("{segment_playlist_root}/{DEFAULT_CMAF_LOCATION}"),
             );
         }
+        HlsMultivariantSinkMuxerType::MpegTs if is_subtitle => {
+            hlssink.set_property(
+                "location",
+                format!("{segment_playlist_root}/{DEFAULT_VTT_LOCATION}"),
+            );
+        }
         HlsMultivariantSinkMuxerType::MpegTs => {
             hlssink.set_property(
                 "location",
@@ -592,6 +746,7 @@ fn hlssink_setup_paths(
     pad: &HlsMultivariantSinkPad,
     hlssink: &gst::Element,
     muxer_type: HlsMultivariantSinkMuxerType,
+    is_subtitle: bool,
     multivariant_playlist_location: String,
     uri: String,
 ) -> Result<(), gst::ErrorMessage> {
@@ -632,6 +787,7 @@ fn hlssink_setup_paths(
             pad,
             hlssink,
             muxer_type,
+            is_subtitle,
             multivariant_playlist_location,
             uri,
         )
@@ -647,7 +803,7 @@ fn hlssink_setup_paths(
  */
 fn is_alternate_rendition(s: &gst::Structure) -> bool {
     match s.get::<&str>("media_type") {
-        Ok(s) => s == "AUDIO" || s == "VIDEO" || s == "audio" || s == "video",
+        Ok(s) => HlsMultivariantSinkAlternativeMediaType::from_str(s).is_ok(),
         Err(_) => false,
     }
 }
@@ -756,27 +912,52 @@ impl ObjectImpl for HlsMultivariantSinkPad {
                 );
 
                 let parent = self.parent();
+
+                let obj = self.obj();
+                let pad_name = obj.name();
+
+                let is_video = pad_name.contains("video");
+                let is_subtitle = pad_name.starts_with("subtitle");
+
+                /*
+                 * Subtitle pads can only be subtitle renditions, and closed
+                 * captions have no pad as they are carried in the video.
+                 */
+                if is_subtitle
+                    != (rendition.media_type == HlsMultivariantSinkAlternativeMediaType::Subtitles)
+                    || rendition.media_type
+                        == HlsMultivariantSinkAlternativeMediaType::ClosedCaptions
+                {
+                    gst::element_error!(
+                        parent,
+                        gst::ResourceError::Settings,
+                        [
+                            "Alternate rendition of type {} not supported on pad {}",
+                            rendition.media_type,
+                            pad_name
+                        ]
+                    );
+                    return;
+                }
+
                 let elem = parent.imp();
                 let elem_settings = elem.settings.lock().unwrap();
                 let muxer_type = elem_settings.muxer_type;
 
                 let mut state = elem.state.lock().unwrap();
 
-                let obj = self.obj();
-                let pad_name = obj.name();
-
-                let is_video = pad_name.contains("video");
-                let sink_name = hlssink_name(rendition.uri.clone(), muxer_type);
+                let sink_name = hlssink_name(rendition.uri.clone(), muxer_type, is_subtitle);
 
-                let hlssink = hlssink_element(muxer_type, sink_name.clone());
-                let peer_pad = hlssink_pad(&hlssink, muxer_type, is_video);
+                let hlssink = hlssink_element(muxer_type, sink_name.clone(), is_subtitle);
+                let peer_pad = hlssink_pad(&hlssink, muxer_type, is_video, is_subtitle);
 
-                elem.setup_hlssink(&hlssink, &elem_settings);
+                elem.setup_hlssink(&hlssink, &elem_settings, &rendition.uri, is_subtitle);
 
                 if let Err(e) = hlssink_setup_paths(
                     self,
                     &hlssink,
                     muxer_type,
+                    is_subtitle,
                     elem_settings.multivariant_playlist_location.clone(),
                     rendition.uri.clone(),
                 ) {
@@ -819,15 +1000,28 @@ impl ObjectImpl for HlsMultivariantSinkPad {
                 gst::info!(CAT, imp = self, "Setting variant: {variant:?}");
 
                 let parent = self.parent();
+
+                let obj = self.obj();
+                let pad_name = obj.name();
+
+                if pad_name.starts_with("subtitle") {
+                    gst::element_error!(
+                        parent,
+                        gst::ResourceError::Settings,
+                        [
+                            "Subtitles can only be alternate renditions, not on pad {}",
+                            pad_name
+                        ]
+                    );
+                    return;
+                }
+
                 let elem = parent.imp();
                 let elem_settings = elem.settings.lock().unwrap();
                 let muxer_type = elem_settings.muxer_type;
 
                 let mut state = elem.state.lock().unwrap();
 
-                let obj = self.obj();
-                let pad_name = obj.name();
-
                 let is_video = pad_name.contains("video");
 
                 /*
@@ -839,15 +1033,16 @@ impl ObjectImpl for HlsMultivariantSinkPad {
                     variant.uri.clone(),
                     elem_settings.muxer_type,
                 );
-                let peer_pad = hlssink_pad(&hlssink, muxer_type, is_video);
+                let peer_pad = hlssink_pad(&hlssink, muxer_type, is_video, false);
 
                 if !muxed {
-                    elem.setup_hlssink(&hlssink, &elem_settings);
+                    elem.setup_hlssink(&hlssink, &elem_settings, &variant.uri, false);
 
                     if let Err(e) = hlssink_setup_paths(
                         self,
                         &hlssink,
                         muxer_type,
+                        false,
                         elem_settings.multivariant_playlist_location.clone(),
                         variant.uri.clone(),
                     ) {
@@ -963,16 +1158,29 @@ impl HlsMultivariantSinkPad {
     }
 }
 
+/* Key shared by all underlying hlscmafsink/hlssink3 */
+struct SharedKey {
+    key: glib::Bytes,
+    location: String,
+    /* Number of underlying sinks whose playlists still refer to the key */
+    users: usize,
+}
+
 #[derive(Default)]
 struct State {
     audio_pad_serial: u32,
     video_pad_serial: u32,
+    subtitle_pad_serial: u32,
     pads: HashMap<String, String>,
     alternatives: Vec<AlternativeMedia>,
     variants: Vec<Variant>,
     old_variants: Vec<Variant>,
     codecs: HashMap<String, Vec<String>>,
     wrote_manifest: bool,
+    /* Keys shared by all underlying hlscmafsink/hlssink3, by key index */
+    keys: HashMap<u32, SharedKey>,
+    /* Underlying hlssink3 whose segment boundaries are used for the WebVTT segments */
+    split_source: Option<String>,
 }
 
 #[derive(Debug)]
@@ -985,6 +1193,11 @@ struct Settings {
     max_num_segment_files: usize,
     send_keyframe_requests: bool,
     target_duration: u32,
+    encryption_method: HlsMultivariantSinkEncryptionMethod,
+    key_location: String,
+    key_uri: Option<String>,
+    key_rotation_period: u32,
+    closed_captions: Vec<ClosedCaptionRendition>,
 }
 
 impl Default for Settings {
@@ -997,6 +1210,11 @@ impl Default for Settings {
             send_keyframe_requests: DEFAULT_SEND_KEYFRAME_REQUESTS,
             target_duration: DEFAULT_TARGET_DURATION,
             muxer_type: DEFAULT_MUXER_TYPE,
+            encryption_method: DEFAULT_ENCRYPTION_METHOD,
+            key_location: DEFAULT_KEY_LOCATION.to_string(),
+            key_uri: None,
+            key_rotation_period: DEFAULT_KEY_ROTATION_PERIOD,
+            closed_captions: Vec::new(),
         }
     }
 }
@@ -1055,6 +1273,46 @@ impl ObjectImpl for HlsMultivariantSink {
                     .blurb("The target duration in seconds of a segment/file. (0 - disabled, useful for management of segment duration by the streaming server)")
                     .default_value(DEFAULT_TARGET_DURATION)
                     .build(),
+                glib::ParamSpecEnum::builder_with_default("encryption-method", DEFAULT_ENCRYPTION_METHOD)
+                    .nick("Encryption Method")
+                    .blurb("Method to encrypt the segments of all renditions and variants with, using the same keys for all of them")
+                    .build(),
+                glib::ParamSpecString::builder("key-location")
+                    .nick("Key Location")
+                    .blurb("Location of the key files to write, relative to the multivariant playlist, with the key index as format parameter")
+                    .default_value(Some(DEFAULT_KEY_LOCATION))
+                    .build(),
+                glib::ParamSpecString::builder("key-uri")
+                    .nick("Key URI")
+                    .blurb("URI of the keys in the media playlists, with the key index as format parameter (NULL = relative path to key-location)")
+                    .build(),
+                glib::ParamSpecUInt::builder("key-rotation-period")
+                    .nick("Key Rotation Period")
+                    .blurb("Number of segments after which a new key is used (0 = no rotation)")
+                    .default_value(DEFAULT_KEY_ROTATION_PERIOD)
+                    .build(),
+                /**
+                 * GstHlsMultivariantSink:closed-captions:
+                 *
+                 * Closed caption renditions carried in-band in the video of the
+                 * variant streams, as an array of structures with the fields
+                 * `group_id`, `name`, `instream_id` (e.g. `CC1`), and optionally
+                 * `language`, `default` and `autoselect`. Variant streams refer
+                 * to them with the `closed-captions` field.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                gst::ParamSpecArray::builder("closed-captions")
+                    .nick("Closed Captions")
+                    .blurb("Closed caption renditions carried in-band in the video of the variant streams")
+                    .element_spec(
+                        &glib::ParamSpecBoxed::builder::<gst::Structure>("closed-caption")
+                            .nick("Closed Caption")
+                            .blurb("Closed caption rendition")
+                            .build(),
+                    )
+                    .mutable_ready()
+                    .build(),
             ]
         });
 
@@ -1098,6 +1356,45 @@ impl ObjectImpl for HlsMultivariantSink {
             "target-duration" => {
                 settings.target_duration = value.get().expect("type checked upstream");
             }
+            "encryption-method" => {
+                settings.encryption_method = value
+                    .get::<HlsMultivariantSinkEncryptionMethod>()
+                    .expect("type checked upstream");
+            }
+            "key-location" => {
+                settings.key_location = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream")
+                    .unwrap_or_else(|| DEFAULT_KEY_LOCATION.to_string());
+            }
+            "key-uri" => {
+                settings.key_uri = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream");
+            }
+            "key-rotation-period" => {
+                settings.key_rotation_period = value.get().expect("type checked upstream");
+            }
+            "closed-captions" => {
+                let closed_captions = value.get::<gst::Array>().expect("type checked upstream");
+                settings.closed_captions = closed_captions
+                    .iter()
+                    .filter_map(|v| {
+                        let s = v
+                            .get::<&gst::StructureRef>()
+                            .expect("type checked upstream");
+                        ClosedCaptionRendition::try_from(s)
+                            .inspect_err(|err| {
+                                gst::element_imp_error!(
+                                    self,
+                                    gst::ResourceError::Settings,
+                                    ["Invalid closed captions {s}: {err}"]
+                                );
+                            })
+                            .ok()
+                    })
+                    .collect();
+            }
 
             _ => unimplemented!(),
         }
@@ -1120,6 +1417,14 @@ impl ObjectImpl for HlsMultivariantSink {
                 .to_value(),
             "send-keyframe-requests" => settings.send_keyframe_requests.to_value(),
             "target-duration" => settings.target_duration.to_value(),
+            "encryption-method" => settings.encryption_method.to_value(),
+            "key-location" => settings.key_location.to_value(),
+            "key-uri" => settings.key_uri.to_value(),
+            "key-rotation-period" => settings.key_rotation_period.to_value(),
+            "closed-captions" => {
+                gst::Array::new(settings.closed_captions.iter().map(gst::Structure::from))
+                    .to_value()
+            }
             _ => unimplemented!(),
         }
     }
@@ -1219,6 +1524,75 @@ impl ObjectImpl for HlsMultivariantSink {
                         std::ops::ControlFlow::Break(value.clone())
                     })
                     .build(),
+                /*
+                 * Requested once per key index, the key is then used by all
+                 * underlying hlscmafsink/hlssink3.
+                 */
+                glib::subclass::Signal::builder(SIGNAL_GET_KEY)
+                    .param_types([u32::static_type()])
+                    .return_type::<Option<glib::Bytes>>()
+                    .class_handler(|_args| {
+                        let key = glib::Bytes::from_owned(rand::random::<[u8; 16]>());
+                        Some(Some(key).to_value())
+                    })
+                    .accumulator(|_hint, _ret, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+                glib::subclass::Signal::builder(SIGNAL_GET_KEY_STREAM)
+                    .param_types([String::static_type()])
+                    .return_type::<Option<gio::OutputStream>>()
+                    .class_handler(|args| {
+                        let key_location = args[1].get::<String>().expect("signal arg");
+                        let elem = args[0]
+                            .get::<super::HlsMultivariantSink>()
+                            .expect("signal arg");
+                        let imp = elem.imp();
+
+                        Some(imp.new_file_stream(&key_location).ok().to_value())
+                    })
+                    .accumulator(|_hint, _ret, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+                /*
+                 * Emitted once none of the playlists of the underlying
+                 * hlscmafsink/hlssink3 refers to the key anymore.
+                 */
+                glib::subclass::Signal::builder(SIGNAL_DELETE_KEY)
+                    .param_types([String::static_type()])
+                    .return_type::<bool>()
+                    .class_handler(|args| {
+                        let key_location = args[1].get::<String>().expect("signal arg");
+                        let elem = args[0]
+                            .get::<super::HlsMultivariantSink>()
+                            .expect("signal arg");
+                        let imp = elem.imp();
+
+                        imp.delete_fragment(&key_location);
+                        Some(true.to_value())
+                    })
+                    .accumulator(|_hint, _ret, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+                glib::subclass::Signal::builder(SIGNAL_ROTATE_KEY)
+                    .action()
+                    .class_handler(|args| {
+                        let elem = args[0]
+                            .get::<super::HlsMultivariantSink>()
+                            .expect("signal arg");
+
+                        for hlssink in elem.children() {
+                            hlssink.emit_by_name::<()>(SIGNAL_ROTATE_KEY, &[]);
+                        }
+
+                        None
+                    })
+                    .build(),
             ]
         });
 
@@ -1242,8 +1616,8 @@ impl ElementImpl for HlsMultivariantSink {
                 "Validating alternate rendition and variants"
             );
 
-            if !self.validate_alternate_rendition_and_variants(&state.alternatives, &state.variants)
-            {
+            let alternatives = self.alternatives(&state);
+            if !self.validate_alternate_rendition_and_variants(&alternatives, &state.variants) {
                 gst::element_error!(
                     self.obj(),
                     gst::ResourceError::Settings,
@@ -1293,7 +1667,20 @@ impl ElementImpl for HlsMultivariantSink {
             )
             .unwrap();
 
-            vec![audio_pad_template, video_pad_template]
+            let subtitle_pad_template = gst::PadTemplate::with_gtype(
+                "subtitle_%u",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Request,
+                &caps_any,
+                super::HlsMultivariantSinkPad::static_type(),
+            )
+            .unwrap();
+
+            vec![
+                audio_pad_template,
+                video_pad_template,
+                subtitle_pad_template,
+            ]
         });
 
         PAD_TEMPLATES.as_ref()
@@ -1373,11 +1760,41 @@ impl ElementImpl for HlsMultivariantSink {
 
                 Some(sink_pad.upcast())
             }
+            "subtitle_%u" => {
+                let mut state = self.state.lock().unwrap();
+
+                let subtitle_pad_name = format!("subtitle_{}", state.subtitle_pad_serial);
+                let sink_pad =
+                    gst::PadBuilder::<super::HlsMultivariantSinkPad>::from_template(templ)
+                        .name(subtitle_pad_name.clone())
+                        .event_function(|pad, parent, event| {
+                            HlsMultivariantSink::catch_panic_pad_function(
+                                parent,
+                                || false,
+                                |this| this.sink_event(pad, event),
+                            )
+                        })
+                        .flags(gst::PadFlags::FIXED_CAPS)
+                        .build();
+
+                state.subtitle_pad_serial += 1;
+
+                self.obj()
+                    .add_pad(&sink_pad)
+                    .expect("Failed to add subtitle pad");
+
+                drop(state);
+
+                self.obj()
+                    .child_added(sink_pad.upcast_ref::<gst::Object>(), &sink_pad.name());
+
+                Some(sink_pad.upcast())
+            }
             other_name => {
                 gst::warning!(
                     CAT,
                     imp = self,
-                    "requested_new_pad: name \"{}\" is not one of audio, video",
+                    "requested_new_pad: name \"{}\" is not one of audio, video, subtitle",
                     other_name
                 );
                 None
@@ -1429,6 +1846,14 @@ impl BinImpl for HlsMultivariantSink {
                 );
                 self.parent_handle_message(message)
             }
+            MessageView::Element(e) => {
+                if let (Some(s), Some(src)) = (e.structure(), e.src()) {
+                    if s.name() == "hls-segment-added" {
+                        self.split_subtitles(src, s);
+                    }
+                }
+                self.parent_handle_message(message)
+            }
             _ => self.parent_handle_message(message),
         }
     }
@@ -1460,6 +1885,61 @@ impl ChildProxyImpl for HlsMultivariantSink {
 }
 
 impl HlsMultivariantSink {
+    /*
+     * Splits the WebVTT segments of all subtitle renditions at the segment
+     * boundaries of one of the other renditions, so that they line up even
+     * if the segments of the other renditions are not exactly of the target
+     * duration.
+     */
+    fn split_subtitles(&self, src: &gst::Object, s: &gst::StructureRef) {
+        if src.name().starts_with("hlswebvttsink-") {
+            return;
+        }
+
+        let (Ok(running_time), Ok(duration)) = (
+            s.get::<gst::ClockTime>("running-time"),
+            s.get::<gst::ClockTime>("duration"),
+        ) else {
+            return;
+        };
+
+        let subtitle_sinks = {
+            let mut state = self.state.lock().unwrap();
+            if *state
+                .split_source
+                .get_or_insert_with(|| src.name().to_string())
+                != src.name()
+            {
+                return;
+            }
+
+            state
+                .pads
+                .values()
+                .filter(|sink_name| sink_name.starts_with("hlswebvttsink-"))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        for sink_name in subtitle_sinks {
+            let Some(sink) = self.obj().by_name(&sink_name) else {
+                continue;
+            };
+
+            gst::trace!(
+                CAT,
+                imp = self,
+                "Splitting {sink_name} at {running_time} and {}",
+                running_time + duration
+            );
+
+            // Already requested boundaries are ignored by hlswebvttsink
+            for split in [running_time, running_time + duration] {
+                sink.emit_by_name::<()>("split-at-running-time", &[&split.nseconds()]);
+            }
+        }
+    }
+
     fn build_codec_str_and_write_multivariant_playlist(&self, codec_str: String, group_id: String) {
         let mut state = self.state.lock().unwrap();
 
@@ -1687,6 +2167,14 @@ impl HlsMultivariantSink {
 
         gst::log!(CAT, obj = pad, "Handling event {event:?}");
 
+        /*
+         * Subtitle renditions have their own group and don't
+         * contribute to the codec string of the variant streams.
+         */
+        if pad.name().starts_with("subtitle") {
+            return gst::Pad::event_default(pad, Some(&*self.obj()), event);
+        }
+
         if let gst::EventView::Caps(ev) = event.view() {
             let caps = ev.caps();
             let codec_str = match gst_pbutils::codec_utils_caps_get_mime_codec(caps) {
@@ -1745,8 +2233,14 @@ impl HlsMultivariantSink {
         gst::Pad::event_default(pad, Some(&*self.obj()), event)
     }
 
-    fn setup_hlssink(&self, hlssink: &gst::Element, settings: &Settings) {
-        /* Propagate some settings to the underlying hlscmafsink/hlssink3 */
+    fn setup_hlssink(
+        &self,
+        hlssink: &gst::Element,
+        settings: &Settings,
+        uri: &str,
+        is_subtitle: bool,
+    ) {
+        /* Propagate some settings to the underlying hlscmafsink/hlssink3/hlswebvttsink */
         hlssink.set_property("max-files", settings.max_num_segment_files as u32);
         hlssink.set_property("playlist-length", settings.playlist_length);
         hlssink.set_property_from_str(
@@ -1756,10 +2250,25 @@ impl HlsMultivariantSink {
                 .unwrap_or(DEFAULT_PLAYLIST_TYPE)
                 .to_string(),
         );
-        if settings.muxer_type == HlsMultivariantSinkMuxerType::MpegTs {
+        if settings.muxer_type == HlsMultivariantSinkMuxerType::MpegTs && !is_subtitle {
             hlssink.set_property("send-keyframe-requests", settings.send_keyframe_requests);
         }
         hlssink.set_property("target-duration", settings.target_duration);
+        /*
+         * WebVTT segments are split at the segment boundaries of the first
+         * other rendition, see `split_subtitles()`.
+         */
+        if settings.muxer_type == HlsMultivariantSinkMuxerType::MpegTs && is_subtitle {
+            hlssink.set_property("manual-split", true);
+        }
+
+        /* SAMPLE-AES is not defined for subtitles */
+        if settings.encryption_method == HlsMultivariantSinkEncryptionMethod::Aes128
+            || (settings.encryption_method == HlsMultivariantSinkEncryptionMethod::SampleAes
+                && !is_subtitle)
+        {
+            self.setup_hlssink_encryption(hlssink, settings, uri);
+        }
 
         let mut signals = vec![
             SIGNAL_DELETE_FRAGMENT,
@@ -1798,6 +2307,142 @@ impl HlsMultivariantSink {
         }
     }
 
+    fn setup_hlssink_encryption(&self, hlssink: &gst::Element, settings: &Settings, uri: &str) {
+        /*
+         * All underlying hlscmafsink/hlssink3 write the same key files next
+         * to the multivariant playlist, and the media playlists refer to
+         * them relative to their own location. A key file is only deleted
+         * once none of the media playlists refers to it anymore.
+         */
+        let key_uri = settings.key_uri.clone().unwrap_or_else(|| {
+            let depth = uri.matches('/').count();
+            format!("{}{}", "../".repeat(depth), settings.key_location)
+        });
+
+        hlssink.set_property_from_str("encryption-method", &settings.encryption_method.to_string());
+        hlssink.set_property("key-rotation-period", settings.key_rotation_period);
+        hlssink.set_property("key-location", Self::key_location_template(settings));
+        hlssink.set_property("key-uri", key_uri);
+
+        hlssink.connect(SIGNAL_GET_KEY, false, {
+            let self_weak = self.downgrade();
+            move |args| -> Option<glib::Value> {
+                let self_ = self_weak.upgrade()?;
+                let index = args[1].get::<u32>().unwrap();
+
+                Some(self_.key(index).to_value())
+            }
+        });
+
+        hlssink.connect(SIGNAL_GET_KEY_STREAM, false, {
+            let self_weak = self.downgrade();
+            move |args| -> Option<glib::Value> {
+                let self_ = self_weak.upgrade()?;
+                let location = args[1].get::<&str>().unwrap();
+
+                Some(
+                    self_
+                        .obj()
+                        .emit_by_name::<Option<gio::OutputStream>>(
+                            SIGNAL_GET_KEY_STREAM,
+                            &[&location],
+                        )
+                        .to_value(),
+                )
+            }
+        });
+
+        hlssink.connect(SIGNAL_DELETE_KEY, false, {
+            let self_weak = self.downgrade();
+            move |args| -> Option<glib::Value> {
+                let self_ = self_weak.upgrade()?;
+                let location = args[1].get::<&str>().unwrap();
+
+                Some(self_.release_key(location).to_value())
+            }
+        });
+    }
+
+    /* Location of the key files with the key index as format parameter */
+    fn key_location_template(settings: &Settings) -> String {
+        match path::Path::new(&settings.multivariant_playlist_location).parent() {
+            Some(root) => root
+                .join(&settings.key_location)
+                .to_str()
+                .unwrap()
+                .to_string(),
+            None => settings.key_location.clone(),
+        }
+    }
+
+    /*
+     * Returns the key with the given index, requesting it the first time it
+     * is needed by any of the underlying hlscmafsink/hlssink3.
+     */
+    fn key(&self, index: u32) -> Option<glib::Bytes> {
+        if let Some(key) = self.state.lock().unwrap().keys.get_mut(&index) {
+            key.users += 1;
+            return Some(key.key.clone());
+        }
+
+        let location = {
+            let settings = self.settings.lock().unwrap();
+            match sprintf::sprintf!(&Self::key_location_template(&settings), index) {
+                Ok(location) => location,
+                Err(err) => {
+                    gst::error!(CAT, imp = self, "Couldn't build key location, err: {err:?}");
+                    return None;
+                }
+            }
+        };
+
+        /* Not holding the state lock as the handler might call back into us */
+        let key = self
+            .obj()
+            .emit_by_name::<Option<glib::Bytes>>(SIGNAL_GET_KEY, &[&index])?;
+
+        let mut state = self.state.lock().unwrap();
+        let key = state.keys.entry(index).or_insert(SharedKey {
+            key,
+            location,
+            users: 0,
+        });
+        key.users += 1;
+
+        Some(key.key.clone())
+    }
+
+    /*
+     * Called when the playlist of one of the underlying hlscmafsink/hlssink3
+     * does not refer to the key at `location` anymore. The key file is
+     * deleted once this is the case for all of them.
+     */
+    fn release_key(&self, location: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(index) = state
+            .keys
+            .iter()
+            .find_map(|(index, key)| (key.location == location).then_some(*index))
+        else {
+            gst::warning!(CAT, imp = self, "Unknown key {location}");
+            return false;
+        };
+
+        let key = state.keys.get_mut(&index).unwrap();
+        key.users = key.users.saturating_sub(1);
+        if key.users > 0 {
+            return true;
+        }
+
+        state.keys.remove(&index);
+        drop(state);
+
+        gst::debug!(CAT, imp = self, "Deleting key {index} at {location}");
+
+        self.obj()
+            .emit_by_name::<bool>(SIGNAL_DELETE_KEY, &[&location])
+    }
+
     fn validate_alternate_rendition_and_variants(
         &self,
         alternatives: &[AlternativeMedia],
@@ -1816,21 +2461,25 @@ impl HlsMultivariantSink {
             .iter()
             .filter_map(|variant| variant.video.clone())
             .collect::<Vec<_>>();
+        let variants_subtitles_group_ids = variants
+            .iter()
+            .filter_map(|variant| variant.subtitles.clone())
+            .collect::<Vec<_>>();
+        let variants_closed_captions_group_ids = variants
+            .iter()
+            .filter_map(|variant| variant.closed_captions.clone())
+            .collect::<Vec<_>>();
 
         for alternate in alternatives.iter() {
             let groupid = &alternate.group_id;
 
-            let res = if alternate.media_type == AlternativeMediaType::Audio {
-                variants_audio_group_ids
-                    .clone()
-                    .into_iter()
-                    .find(|x| *x == *groupid)
-            } else {
-                variants_video_group_ids
-                    .clone()
-                    .into_iter()
-                    .find(|x| *x == *groupid)
+            let group_ids = match alternate.media_type {
+                AlternativeMediaType::Audio => &variants_audio_group_ids,
+                AlternativeMediaType::Subtitles => &variants_subtitles_group_ids,
+                AlternativeMediaType::ClosedCaptions => &variants_closed_captions_group_ids,
+                _ => &variants_video_group_ids,
             };
+            let res = group_ids.iter().find(|x| *x == groupid);
 
             if res.is_none() {
                 gst::error!(
@@ -1842,6 +2491,56 @@ impl HlsMultivariantSink {
             }
         }
 
+        /*
+         * Unlike audio and video, SUBTITLES and CLOSED-CAPTIONS of a variant
+         * stream always refer to a group of renditions, except for NONE.
+         */
+        for variant in variants.iter() {
+            let groups = [
+                (
+                    AlternativeMediaType::Subtitles,
+                    variant.subtitles.as_deref(),
+                ),
+                (
+                    AlternativeMediaType::ClosedCaptions,
+                    variant.closed_captions.as_deref().filter(|g| *g != "NONE"),
+                ),
+            ];
+
+            for (media_type, group_id) in groups {
+                let Some(group_id) = group_id else {
+                    continue;
+                };
+
+                if !alternatives
+                    .iter()
+                    .any(|alt| alt.media_type == media_type && alt.group_id == group_id)
+                {
+                    gst::error!(
+                        CAT,
+                        imp = self,
+                        "No {media_type:?} rendition for GROUP-ID {group_id} of variant stream {}",
+                        variant.uri
+                    );
+                    return false;
+                }
+            }
+        }
+
+        for alternate in alternatives.iter() {
+            if alternate.media_type == AlternativeMediaType::ClosedCaptions
+                && alternate.instream_id.is_none()
+            {
+                gst::error!(
+                    CAT,
+                    imp = self,
+                    "Invalid INSTREAM-ID for closed captions {}",
+                    alternate.name
+                );
+                return false;
+            }
+        }
+
         // NAME in alternate renditions must be unique
         let mut names = alternatives
             .iter()
@@ -1861,9 +2560,24 @@ impl HlsMultivariantSink {
         true
     }
 
+    /*
+     * Returns the alternate renditions of all pads together with the
+     * closed captions, which have no pad.
+     */
+    fn alternatives(&self, state: &State) -> Vec<AlternativeMedia> {
+        let settings = self.settings.lock().unwrap();
+
+        state
+            .alternatives
+            .iter()
+            .cloned()
+            .chain(settings.closed_captions.iter().map(AlternativeMedia::from))
+            .collect()
+    }
+
     fn write_multivariant_playlist(&self, state: &mut State) {
         let variant_streams = state.variants.iter().map(VariantStream::from).collect();
-        let alternatives = state.alternatives.clone();
+        let alternatives = self.alternatives(state);
         state.wrote_manifest = true;
 
         let settings = self.settings.lock().unwrap();
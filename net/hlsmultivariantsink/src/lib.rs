@@ -7,6 +7,8 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
+#![allow(unused_doc_comments)]
+
 /**
  * plugin-hlsmultivariantsink:
  *
@@ -43,6 +45,12 @@ pub enum HlsMultivariantSinkAlternativeMediaType {
 
     #[enum_value(name = "VIDEO", nick = "video")]
     Video = 1,
+
+    #[enum_value(name = "SUBTITLES", nick = "subtitles")]
+    Subtitles = 2,
+
+    #[enum_value(name = "CLOSED-CAPTIONS", nick = "closed-captions")]
+    ClosedCaptions = 3,
 }
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
@@ -69,6 +77,28 @@ pub enum HlsMultivariantSinkPlaylistType {
     Vod = 2,
 }
 
+#[derive(Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstHlsMultivariantSinkEncryptionMethod")]
+#[non_exhaustive]
+pub enum HlsMultivariantSinkEncryptionMethod {
+    #[default]
+    #[enum_value(name = "None: Segments are not encrypted", nick = "none")]
+    None = 0,
+
+    #[enum_value(
+        name = "AES-128: Whole segments are encrypted with AES-128-CBC (mpegts only)",
+        nick = "aes-128"
+    )]
+    Aes128 = 1,
+
+    #[enum_value(
+        name = "SAMPLE-AES: Media samples are encrypted with the cbcs scheme (cmaf only)",
+        nick = "sample-aes"
+    )]
+    SampleAes = 2,
+}
+
 glib::wrapper! {
     pub struct HlsMultivariantSink(ObjectSubclass<imp::HlsMultivariantSink>) @extends gst::Bin, gst::Element, gst::Object, @implements gst::ChildProxy;
 
@@ -88,6 +118,8 @@ pub fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
             .mark_as_plugin_api(gst::PluginAPIFlags::empty());
         HlsMultivariantSinkAlternativeMediaType::static_type()
             .mark_as_plugin_api(gst::PluginAPIFlags::empty());
+        HlsMultivariantSinkEncryptionMethod::static_type()
+            .mark_as_plugin_api(gst::PluginAPIFlags::empty());
     }
 
     gst::Element::register(
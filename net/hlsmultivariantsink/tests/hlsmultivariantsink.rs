@@ -1822,3 +1822,289 @@ low/video.m3u8
 
     Ok(())
 }
+
+#[ignore]
+#[test]
+#[serial]
+fn hlsmultivariantsink_subtitle_rendition_and_closed_captions_with_mpegts() -> Result<(), ()> {
+    init();
+
+    let pipeline = gst::Pipeline::with_name("hlsmultivariantsink_pipeline");
+
+    let closed_captions = gst::Array::new([gst::Structure::builder("closed-captions")
+        .field("group_id", "cc")
+        .field("instream_id", "CC1")
+        .field("language", "en")
+        .field("name", "English CC")
+        .field("default", true)
+        .field("autoselect", true)
+        .build()]);
+
+    let hlsmultivariantsink = gst::ElementFactory::make("hlsmultivariantsink")
+        .name("test_hlsmultivariantsink")
+        .property(
+            "multivariant-playlist-location",
+            "/tmp/hlssink/multivariant.m3u8",
+        )
+        .property("target-duration", 2u32)
+        .property("playlist-length", 2u32)
+        .property("max-files", 2u32)
+        .property("closed-captions", &closed_captions)
+        .build()
+        .expect("Must be able to instantiate hlsmultivariantsink");
+
+    hlsmultivariantsink.set_property("muxer-type", HlsMultivariantSinkMuxerType::MpegTs);
+
+    pipeline.add(&hlsmultivariantsink).unwrap();
+
+    let (hls_events_sender, hls_events_receiver) = mpsc::sync_channel(100);
+    let multivariant_playlist_content = Arc::new(Mutex::new(String::from("")));
+    let playlist_content = Arc::new(Mutex::new(String::from("")));
+
+    setup_signals(
+        &hlsmultivariantsink,
+        hls_events_sender.clone(),
+        multivariant_playlist_content.clone(),
+        playlist_content.clone(),
+        HlsMultivariantSinkMuxerType::MpegTs,
+    );
+
+    let appsrc = gst_app::AppSrc::builder()
+        .caps(
+            &gst::Caps::builder("text/x-raw")
+                .field("format", "utf8")
+                .build(),
+        )
+        .format(gst::Format::Time)
+        .build();
+    let subtitle_pad = hlsmultivariantsink
+        .request_pad_simple("subtitle_%u")
+        .unwrap();
+    subtitle_pad.set_property(
+        "playlist-location",
+        "/tmp/hlssink/subs/subtitles.m3u8".to_string(),
+    );
+    subtitle_pad.set_property(
+        "segment-location",
+        "/tmp/hlssink/subs/segment%05d.vtt".to_string(),
+    );
+    let r = gst::Structure::builder("subtitle-rendition")
+        .field("media_type", "SUBTITLES")
+        .field("uri", "subs/subtitles.m3u8")
+        .field("group_id", "subs")
+        .field("language", "en")
+        .field("name", "English")
+        .field("default", true)
+        .field("autoselect", true)
+        .build();
+    subtitle_pad.set_property("alternate-rendition", r);
+    pipeline.add(&appsrc).unwrap();
+    appsrc
+        .static_pad("src")
+        .unwrap()
+        .link(&subtitle_pad)
+        .unwrap();
+
+    let video_bin1 = video_bin(1920, 1080, 30, 2500, false, false).unwrap();
+    let video_bin1_pad = video_bin1.static_pad("src").unwrap();
+    let video1_pad = hlsmultivariantsink.request_pad_simple("video_%u").unwrap();
+    video1_pad.set_property(
+        "playlist-location",
+        "/tmp/hlssink/hi/video.m3u8".to_string(),
+    );
+    video1_pad.set_property(
+        "segment-location",
+        format!("/tmp/hlssink/hi/{DEFAULT_TS_LOCATION}"),
+    );
+    let v = gst::Structure::builder("video1-variant")
+        .field("uri", "hi/video.m3u8")
+        .field("subtitles", "subs")
+        .field("closed-captions", "cc")
+        .field("bandwidth", 2500)
+        .build();
+    video1_pad.set_property("variant", v);
+    pipeline.add(&video_bin1).unwrap();
+    video_bin1_pad.link(&video1_pad).unwrap();
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+
+    for (start, text) in [(0, "Hello"), (1500, "World")] {
+        let mut buffer = gst::Buffer::from_slice(text);
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(gst::ClockTime::from_mseconds(start));
+            buffer.set_duration(gst::ClockTime::from_mseconds(1000));
+        }
+        appsrc.push_buffer(buffer).unwrap();
+    }
+    appsrc.end_of_stream().unwrap();
+
+    let mut eos = false;
+    let bus = pipeline.bus().unwrap();
+    while let Some(msg) = bus.timed_pop(gst::ClockTime::NONE) {
+        use gst::MessageView;
+        match msg.view() {
+            MessageView::Eos(..) => {
+                eos = true;
+                break;
+            }
+            MessageView::Error(e) => gst::error!(CAT, "hlsmultivariantsink error: {}", e),
+            _ => (),
+        }
+    }
+
+    pipeline.debug_to_dot_file_with_ts(
+        gst::DebugGraphDetails::all(),
+        "subtitle_rendition_and_closed_captions_with_mpegts",
+    );
+
+    pipeline.set_state(gst::State::Null).unwrap();
+    assert!(eos);
+
+    let mut actual_events = Vec::new();
+    while let Ok(event) = hls_events_receiver.recv_timeout(Duration::from_secs(30)) {
+        actual_events.push(event);
+    }
+    {
+        use self::HlsSinkEvent::*;
+        assert!(actual_events.contains(&GetPlaylistStream(
+            "/tmp/hlssink/subs/subtitles.m3u8".to_string()
+        )));
+        assert!(actual_events.contains(&GetFragmentStream(
+            "/tmp/hlssink/subs/segment00000.vtt".to_string()
+        )));
+    }
+
+    let contents = multivariant_playlist_content.lock().unwrap();
+    assert!(contents.contains(
+        r#"#EXT-X-MEDIA:TYPE=SUBTITLES,URI="subs/subtitles.m3u8",GROUP-ID="subs",LANGUAGE="en",NAME="English",DEFAULT=YES,AUTOSELECT=YES"#
+    ));
+    assert!(contents.contains(
+        r#"#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID="cc",LANGUAGE="en",NAME="English CC",DEFAULT=YES,AUTOSELECT=YES,INSTREAM-ID="CC1""#
+    ));
+    assert!(contents.contains(r#"SUBTITLES="subs",CLOSED-CAPTIONS="cc""#));
+
+    Ok(())
+}
+
+#[test]
+#[serial]
+fn hlsmultivariantsink_shared_key_deletion_with_mpegts() -> Result<(), ()> {
+    init();
+
+    let pipeline = gst::Pipeline::with_name("hlsmultivariantsink_pipeline");
+
+    let hlsmultivariantsink = gst::ElementFactory::make("hlsmultivariantsink")
+        .name("test_hlsmultivariantsink")
+        .property(
+            "multivariant-playlist-location",
+            "/tmp/hlssink/multivariant.m3u8",
+        )
+        .property("target-duration", 2u32)
+        .property("playlist-length", 1u32)
+        .property("max-files", 1u32)
+        .property_from_str("encryption-method", "aes-128")
+        .property("key-rotation-period", 1u32)
+        .build()
+        .expect("Must be able to instantiate hlsmultivariantsink");
+
+    hlsmultivariantsink.set_property("muxer-type", HlsMultivariantSinkMuxerType::MpegTs);
+
+    pipeline.add(&hlsmultivariantsink).unwrap();
+
+    let key_streams = Arc::new(Mutex::new(Vec::new()));
+    let deleted_keys = Arc::new(Mutex::new(Vec::new()));
+
+    for signal in [
+        "get-multivariant-playlist-stream",
+        "get-playlist-stream",
+        "get-fragment-stream",
+    ] {
+        hlsmultivariantsink.connect(signal, false, |_args| {
+            let stream = gio::MemoryOutputStream::new_resizable();
+            Some(stream.to_value())
+        });
+    }
+
+    hlsmultivariantsink.connect("delete-fragment", false, |_args| Some(true.to_value()));
+
+    hlsmultivariantsink.connect("get-key-stream", false, {
+        let key_streams = key_streams.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            key_streams.lock().unwrap().push(location);
+
+            let stream = gio::MemoryOutputStream::new_resizable();
+            Some(stream.to_value())
+        }
+    });
+
+    hlsmultivariantsink.connect("delete-key", false, {
+        let deleted_keys = deleted_keys.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            deleted_keys.lock().unwrap().push(location);
+            Some(true.to_value())
+        }
+    });
+
+    for (name, width, height, bitrate) in [("hi", 1920, 1080, 2500), ("mid", 1280, 720, 1500)] {
+        let video_bin = video_bin(width, height, 30, bitrate, false, false).unwrap();
+        let video_bin_pad = video_bin.static_pad("src").unwrap();
+        let video_pad = hlsmultivariantsink.request_pad_simple("video_%u").unwrap();
+        video_pad.set_property(
+            "playlist-location",
+            format!("/tmp/hlssink/{name}/video.m3u8"),
+        );
+        video_pad.set_property(
+            "segment-location",
+            format!("/tmp/hlssink/{name}/{DEFAULT_TS_LOCATION}"),
+        );
+        let v = gst::Structure::builder(format!("{name}-variant"))
+            .field("uri", format!("{name}/video.m3u8"))
+            .field("bandwidth", bitrate as i32)
+            .field("codecs", "avc1")
+            .build();
+        video_pad.set_property("variant", v);
+        pipeline.add(&video_bin).unwrap();
+        video_bin_pad.link(&video_pad).unwrap();
+    }
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+
+    let mut eos = false;
+    let bus = pipeline.bus().unwrap();
+    while let Some(msg) = bus.timed_pop(gst::ClockTime::NONE) {
+        use gst::MessageView;
+        match msg.view() {
+            MessageView::Eos(..) => {
+                eos = true;
+                break;
+            }
+            MessageView::Error(e) => gst::error!(CAT, "hlsmultivariantsink error: {}", e),
+            _ => (),
+        }
+    }
+
+    pipeline.set_state(gst::State::Null).unwrap();
+    assert!(eos);
+
+    // Both variants write the shared keys next to the multivariant playlist
+    let mut key_streams = key_streams.lock().unwrap().clone();
+    key_streams.sort();
+    assert_eq!(
+        key_streams,
+        vec![
+            "/tmp/hlssink/key00000.key",
+            "/tmp/hlssink/key00000.key",
+            "/tmp/hlssink/key00001.key",
+            "/tmp/hlssink/key00001.key",
+        ]
+    );
+
+    // The first key is deleted once, after neither media playlist refers to it anymore
+    let deleted_keys = deleted_keys.lock().unwrap();
+    assert_eq!(*deleted_keys, vec!["/tmp/hlssink/key00000.key"]);
+
+    Ok(())
+}
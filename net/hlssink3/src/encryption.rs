@@ -0,0 +1,195 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Whole segment encryption for `EXT-X-KEY:METHOD=AES-128`.
+
+use aes::cipher::{BlockEncrypt, KeyInit};
+use gio::prelude::*;
+use gio::subclass::prelude::*;
+use gst::glib;
+use std::sync::Mutex;
+
+/// AES-128-CBC encryption with PKCS#7 padding as required by RFC 8216 section 5.2.
+pub struct Aes128CbcEncryptor {
+    cipher: aes::Aes128,
+    /// Previous ciphertext block, or the IV for the first block.
+    chain: [u8; 16],
+    /// Data that does not fill a complete block yet.
+    pending: Vec<u8>,
+}
+
+impl Aes128CbcEncryptor {
+    pub fn new(key: &[u8; 16], iv: &[u8; 16]) -> Self {
+        Self {
+            cipher: aes::Aes128::new(&(*key).into()),
+            chain: *iv,
+            pending: Vec::with_capacity(16),
+        }
+    }
+
+    /// Returns the IV for a segment without an explicit `IV` attribute, which is its media
+    /// sequence number as a big-endian 128 bit integer.
+    pub fn media_sequence_iv(media_sequence: u64) -> [u8; 16] {
+        (media_sequence as u128).to_be_bytes()
+    }
+
+    /// Encrypts all complete blocks of the pending data and `data`.
+    pub fn update(&mut self, data: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(data);
+
+        let len = self.pending.len() / 16 * 16;
+        let mut output = self.pending.drain(..len).collect::<Vec<_>>();
+        self.encrypt_blocks(&mut output);
+
+        output
+    }
+
+    /// Pads and encrypts the remaining data.
+    pub fn finish(mut self) -> Vec<u8> {
+        let padding = 16 - self.pending.len() % 16;
+        let mut output = std::mem::take(&mut self.pending);
+        output.resize(output.len() + padding, padding as u8);
+        self.encrypt_blocks(&mut output);
+
+        output
+    }
+
+    fn encrypt_blocks(&mut self, data: &mut [u8]) {
+        for block in data.chunks_exact_mut(16) {
+            for (b, c) in block.iter_mut().zip(self.chain.iter()) {
+                *b ^= c;
+            }
+            let block = aes::Block::from_mut_slice(block);
+            self.cipher.encrypt_block(block);
+            self.chain.copy_from_slice(block);
+        }
+    }
+}
+
+mod imp {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct AesOutputStream {
+        pub(super) stream: Mutex<Option<(gio::OutputStream, Aes128CbcEncryptor)>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for AesOutputStream {
+        const NAME: &'static str = "GstHlsBaseSinkAesOutputStream";
+        type Type = super::AesOutputStream;
+        type ParentType = gio::OutputStream;
+    }
+
+    impl ObjectImpl for AesOutputStream {}
+
+    impl OutputStreamImpl for AesOutputStream {
+        fn write(
+            &self,
+            buffer: &[u8],
+            cancellable: Option<&gio::Cancellable>,
+        ) -> Result<usize, glib::Error> {
+            let mut stream = self.stream.lock().unwrap();
+            let Some((ref stream, ref mut encryptor)) = *stream else {
+                return Err(glib::Error::new(gio::IOErrorEnum::Closed, "Stream closed"));
+            };
+
+            let data = encryptor.update(buffer);
+            stream.write_all(&data, cancellable)?;
+
+            Ok(buffer.len())
+        }
+
+        fn close(&self, cancellable: Option<&gio::Cancellable>) -> Result<(), glib::Error> {
+            let Some((stream, encryptor)) = self.stream.lock().unwrap().take() else {
+                return Ok(());
+            };
+
+            stream.write_all(&encryptor.finish(), cancellable)?;
+            stream.close(cancellable)
+        }
+
+        fn flush(&self, cancellable: Option<&gio::Cancellable>) -> Result<(), glib::Error> {
+            let stream = self.stream.lock().unwrap();
+            if let Some((ref stream, _)) = *stream {
+                stream.flush(cancellable)?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+// Encrypts everything written to it with AES-128-CBC and writes the result to another stream.
+// The final padded block is written when the stream is closed.
+glib::wrapper! {
+    pub struct AesOutputStream(ObjectSubclass<imp::AesOutputStream>) @extends gio::OutputStream;
+}
+
+unsafe impl Send for AesOutputStream {}
+unsafe impl Sync for AesOutputStream {}
+
+impl AesOutputStream {
+    pub fn new(stream: gio::OutputStream, key: &[u8; 16], iv: &[u8; 16]) -> Self {
+        let obj = glib::Object::new::<Self>();
+        *obj.imp().stream.lock().unwrap() = Some((stream, Aes128CbcEncryptor::new(key, iv)));
+        obj
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 16] = [
+        0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f,
+        0x3c,
+    ];
+    const IV: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+
+    #[test]
+    fn test_cbc_encryption() {
+        // NIST SP 800-38A F.2.1, first two blocks
+        let plaintext = [
+            0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93,
+            0x17, 0x2a, 0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac,
+            0x45, 0xaf, 0x8e, 0x51,
+        ];
+        let ciphertext = [
+            0x76, 0x49, 0xab, 0xac, 0x81, 0x19, 0xb2, 0x46, 0xce, 0xe9, 0x8e, 0x9b, 0x12, 0xe9,
+            0x19, 0x7d, 0x50, 0x86, 0xcb, 0x9b, 0x50, 0x72, 0x19, 0xee, 0x95, 0xdb, 0x11, 0x3a,
+            0x91, 0x76, 0x78, 0xb2,
+        ];
+
+        // Data is only output once a block is complete, independent of how it is split up
+        let mut encryptor = Aes128CbcEncryptor::new(&KEY, &IV);
+        let mut output = encryptor.update(&plaintext[..10]);
+        assert!(output.is_empty());
+        output.extend(encryptor.update(&plaintext[10..]));
+        assert_eq!(output, ciphertext);
+
+        // A complete block of padding is added for block aligned data
+        let padding = encryptor.finish();
+        assert_eq!(padding.len(), 16);
+    }
+
+    #[test]
+    fn test_padding() {
+        let mut encryptor = Aes128CbcEncryptor::new(&KEY, &IV);
+        assert!(encryptor.update(&[0u8; 5]).is_empty());
+        assert_eq!(encryptor.finish().len(), 16);
+
+        assert_eq!(
+            Aes128CbcEncryptor::media_sequence_iv(0x0102),
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x02]
+        );
+    }
+}
@@ -7,14 +7,17 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::playlist::Playlist;
+use crate::encryption::{Aes128CbcEncryptor, AesOutputStream};
+use crate::playlist::{DateRange, PartialSegment, Playlist, RenditionReport};
+use crate::scte35::{self, Scte35Event, SpliceCommand};
 use chrono::{DateTime, Duration, Utc};
 use gio::prelude::*;
 use gio::subclass::prelude::OutputStreamImpl;
 use gst::glib;
 use gst::prelude::*;
 use gst::subclass::prelude::*;
-use m3u8_rs::MediaSegment;
+use m3u8_rs::{ExtTag, MediaSegment};
+use std::collections::{HashMap, VecDeque};
 use std::fs;
 use std::io::Write;
 use std::path;
@@ -28,10 +31,19 @@ const DEFAULT_CLOCK_TRACKING_FOR_PDT: bool = true;
 const DEFAULT_ENDLIST: bool = true;
 const DEFAULT_PROGRAM_DATE_TIME_REFERENCE: HlsProgramDateTimeReference =
     HlsProgramDateTimeReference::Pipeline;
+const DEFAULT_ENCRYPTION_METHOD: HlsEncryptionMethod = HlsEncryptionMethod::None;
+const DEFAULT_KEY_LOCATION: &str = "key%05d.key";
+const DEFAULT_KEY_ROTATION_PERIOD: u32 = 0;
+const DEFAULT_RESUME: bool = false;
 
 const SIGNAL_GET_PLAYLIST_STREAM: &str = "get-playlist-stream";
+const SIGNAL_GET_PLAYLIST_INPUT_STREAM: &str = "get-playlist-input-stream";
 const SIGNAL_GET_FRAGMENT_STREAM: &str = "get-fragment-stream";
 const SIGNAL_DELETE_FRAGMENT: &str = "delete-fragment";
+const SIGNAL_GET_KEY: &str = "get-key";
+const SIGNAL_GET_KEY_STREAM: &str = "get-key-stream";
+const SIGNAL_DELETE_KEY: &str = "delete-key";
+const SIGNAL_ROTATE_KEY: &str = "rotate-key";
 
 static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
@@ -57,6 +69,25 @@ pub enum HlsProgramDateTimeReference {
     BufferReferenceTimestamp = 2,
 }
 
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstHlsEncryptionMethod")]
+#[non_exhaustive]
+pub enum HlsEncryptionMethod {
+    #[enum_value(name = "None: Segments are not encrypted", nick = "none")]
+    None = 0,
+    #[enum_value(
+        name = "AES-128: Whole segments are encrypted with AES-128-CBC (hlssink3 only)",
+        nick = "aes-128"
+    )]
+    Aes128 = 1,
+    #[enum_value(
+        name = "SAMPLE-AES: Media samples are encrypted with the cbcs scheme (hlscmafsink only)",
+        nick = "sample-aes"
+    )]
+    SampleAes = 2,
+}
+
 // We need to keep an OutputStream around for writing to the same file
 // to support the single media file use case. OutputStream not being
 // thread safe, use this wrapper to keep an OutputStream around in State.
@@ -136,6 +167,11 @@ struct Settings {
     program_date_time_reference: HlsProgramDateTimeReference,
     enable_endlist: bool,
     single_media_file: Option<String>,
+    encryption_method: HlsEncryptionMethod,
+    key_location: Option<String>,
+    key_uri: Option<String>,
+    key_rotation_period: u32,
+    resume: bool,
 }
 
 impl Default for Settings {
@@ -149,32 +185,81 @@ impl Default for Settings {
             program_date_time_reference: DEFAULT_PROGRAM_DATE_TIME_REFERENCE,
             enable_endlist: DEFAULT_ENDLIST,
             single_media_file: None,
+            encryption_method: DEFAULT_ENCRYPTION_METHOD,
+            key_location: Some(String::from(DEFAULT_KEY_LOCATION)),
+            key_uri: None,
+            key_rotation_period: DEFAULT_KEY_ROTATION_PERIOD,
+            resume: DEFAULT_RESUME,
         }
     }
 }
 
+/// Key that is used for encrypting new segments.
+struct SegmentKey {
+    index: u32,
+    key: [u8; 16],
+    /// Constant IV of SAMPLE-AES keys, which is configured on the muxer together with the key.
+    iv: Option<[u8; 16]>,
+    location: Option<String>,
+    tag: m3u8_rs::Key,
+    /// Number of segments encrypted with this key so far.
+    segments: u32,
+    /// Location of the last segment encrypted with this key.
+    last_segment_location: Option<String>,
+}
+
+/// Splice point that is not signalled in the playlist yet.
+struct PendingSplice {
+    running_time: gst::ClockTime,
+    event_id: u32,
+    command: SpliceCommand,
+    /// SCTE-35 `splice_info_section`, or `None` for the automatic return at the end of a break.
+    section: Option<Vec<u8>>,
+}
+
 pub struct PlaylistContext {
     pdt_base_utc: Option<DateTime<Utc>>,
     pdt_base_running_time: Option<gst::ClockTime>,
     playlist: Playlist,
     old_segment_locations: Vec<String>,
+    /// Locations of the partial segment files, by the location of their parent segment.
+    part_locations: HashMap<String, Vec<String>>,
     segment_template: String,
     playlist_location: String,
     max_num_segment_files: usize,
     playlist_length: u32,
     single_media_file: bool,
+    /// Media sequence number of the next segment that is opened.
+    next_media_sequence: u64,
+    key: Option<SegmentKey>,
+    rotate_key: bool,
+    /// Keys of the segments that were opened but not added to the playlist yet.
+    pending_segment_keys: VecDeque<m3u8_rs::Key>,
+    last_segment_key: Option<m3u8_rs::Key>,
+    /// Locations of the key files that are not needed anymore once the segment is deleted,
+    /// by the location of the last segment that was encrypted with them.
+    old_key_locations: HashMap<String, String>,
+    /// Splice points that are signalled with the first segment starting at or after them,
+    /// sorted by running time.
+    pending_splices: Vec<PendingSplice>,
+    /// Start dates of the breaks that did not end yet, by splice event id.
+    break_start_dates: HashMap<u32, DateTime<Utc>>,
 }
 
 #[derive(Default)]
 pub struct State {
     context: Option<PlaylistContext>,
     stream: Option<super::HlsBaseSinkGioOutputStream>,
+    /// SAMPLE-AES key that is kept when starting a new playlist, as the muxer can't change it.
+    sample_aes_key: Option<SegmentKey>,
 }
 
 #[derive(Default)]
 pub struct HlsBaseSink {
     settings: Mutex<Settings>,
     state: Mutex<State>,
+    /// Element that starts new segments via its `split-at-running-time` signal.
+    splitter: Mutex<Option<gst::Element>>,
 }
 
 #[glib::object_subclass]
@@ -241,6 +326,34 @@ impl ObjectImpl for HlsBaseSink {
                     .nick("Single media file")
                     .blurb("Location of the single media file to write (media playlist will use byte-range addressing)")
                     .build(),
+                glib::ParamSpecEnum::builder_with_default("encryption-method", DEFAULT_ENCRYPTION_METHOD)
+                    .nick("Encryption Method")
+                    .blurb("Method to encrypt the segments with, signalled via EXT-X-KEY")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecString::builder("key-location")
+                    .nick("Key Location")
+                    .blurb("Location of the key files to write (NULL = don't write key files)")
+                    .default_value(Some(DEFAULT_KEY_LOCATION))
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecString::builder("key-uri")
+                    .nick("Key URI")
+                    .blurb("URI of the keys in the playlist, with the key index as format parameter (NULL = derived from key-location)")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("key-rotation-period")
+                    .nick("Key Rotation Period")
+                    .blurb("Number of segments after which a new key is used (0 = no rotation)")
+                    .default_value(DEFAULT_KEY_ROTATION_PERIOD)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecBoolean::builder("resume")
+                    .nick("Resume")
+                    .blurb("Continue an existing playlist at playlist-location after a discontinuity instead of replacing it")
+                    .default_value(DEFAULT_RESUME)
+                    .mutable_ready()
+                    .build(),
             ]
         });
 
@@ -291,6 +404,25 @@ impl ObjectImpl for HlsBaseSink {
                     .get::<Option<String>>()
                     .expect("type checked upstream");
             }
+            "encryption-method" => {
+                settings.encryption_method = value.get().expect("type checked upstream");
+            }
+            "key-location" => {
+                settings.key_location = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream");
+            }
+            "key-uri" => {
+                settings.key_uri = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream");
+            }
+            "key-rotation-period" => {
+                settings.key_rotation_period = value.get().expect("type checked upstream");
+            }
+            "resume" => {
+                settings.resume = value.get().expect("type checked upstream");
+            }
             _ => unimplemented!(),
         };
     }
@@ -312,6 +444,11 @@ impl ObjectImpl for HlsBaseSink {
             "program-date-time-reference" => settings.program_date_time_reference.to_value(),
             "enable-endlist" => settings.enable_endlist.to_value(),
             "single-media-file" => settings.single_media_file.to_value(),
+            "encryption-method" => settings.encryption_method.to_value(),
+            "key-location" => settings.key_location.to_value(),
+            "key-uri" => settings.key_uri.to_value(),
+            "key-rotation-period" => settings.key_rotation_period.to_value(),
+            "resume" => settings.resume.to_value(),
             _ => unimplemented!(),
         }
     }
@@ -334,6 +471,21 @@ impl ObjectImpl for HlsBaseSink {
                         std::ops::ControlFlow::Break(value.clone())
                     })
                     .build(),
+                glib::subclass::Signal::builder(SIGNAL_GET_PLAYLIST_INPUT_STREAM)
+                    .param_types([String::static_type()])
+                    .return_type::<Option<gio::InputStream>>()
+                    .class_handler(|args| {
+                        let elem = args[0].get::<super::HlsBaseSink>().expect("signal arg");
+                        let playlist_location = args[1].get::<String>().expect("signal arg");
+                        let imp = elem.imp();
+
+                        Some(imp.read_file_stream(&playlist_location).to_value())
+                    })
+                    .accumulator(|_hint, _acc, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
                 glib::subclass::Signal::builder(SIGNAL_GET_FRAGMENT_STREAM)
                     .param_types([String::static_type()])
                     .return_type::<Option<gio::OutputStream>>()
@@ -365,6 +517,73 @@ impl ObjectImpl for HlsBaseSink {
                         std::ops::ControlFlow::Break(value.clone())
                     })
                     .build(),
+                glib::subclass::Signal::builder(SIGNAL_GET_KEY)
+                    .param_types([u32::static_type()])
+                    .return_type::<Option<glib::Bytes>>()
+                    .class_handler(|_args| {
+                        let key = glib::Bytes::from_owned(rand::random::<[u8; 16]>());
+                        Some(Some(key).to_value())
+                    })
+                    .accumulator(|_hint, _acc, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+                glib::subclass::Signal::builder(SIGNAL_GET_KEY_STREAM)
+                    .param_types([String::static_type()])
+                    .return_type::<Option<gio::OutputStream>>()
+                    .class_handler(|args| {
+                        let elem = args[0].get::<super::HlsBaseSink>().expect("signal arg");
+                        let key_location = args[1].get::<String>().expect("signal arg");
+                        let imp = elem.imp();
+
+                        Some(imp.new_file_stream(&key_location).ok().to_value())
+                    })
+                    .accumulator(|_hint, _acc, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+                glib::subclass::Signal::builder(SIGNAL_DELETE_KEY)
+                    .param_types([String::static_type()])
+                    .return_type::<bool>()
+                    .class_handler(|args| {
+                        let elem = args[0].get::<super::HlsBaseSink>().expect("signal arg");
+                        let key_location = args[1].get::<String>().expect("signal arg");
+                        let imp = elem.imp();
+
+                        imp.delete_fragment(&key_location);
+                        Some(true.to_value())
+                    })
+                    .accumulator(|_hint, _acc, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+                glib::subclass::Signal::builder(SIGNAL_ROTATE_KEY)
+                    .action()
+                    .class_handler(|args| {
+                        let elem = args[0].get::<super::HlsBaseSink>().expect("signal arg");
+                        let imp = elem.imp();
+
+                        if imp.encryption_method() == HlsEncryptionMethod::SampleAes {
+                            gst::warning!(
+                                CAT,
+                                imp = imp,
+                                "Key rotation is not supported for SAMPLE-AES"
+                            );
+                            return None;
+                        }
+
+                        let mut state = imp.state.lock().unwrap();
+                        if let Some(context) = state.context.as_mut() {
+                            gst::debug!(CAT, imp = imp, "Rotating key with the next segment");
+                            context.rotate_key = true;
+                        }
+
+                        None
+                    })
+                    .build(),
             ]
         });
 
@@ -374,6 +593,36 @@ impl ObjectImpl for HlsBaseSink {
 
 impl GstObjectImpl for HlsBaseSink {}
 
+/// Pad template for the request pad that receives SCTE-35 sections.
+pub fn scte35_pad_template() -> gst::PadTemplate {
+    let caps = gst::Caps::builder("application/x-scte35").build();
+    gst::PadTemplate::new(
+        "scte35",
+        gst::PadDirection::Sink,
+        gst::PadPresence::Request,
+        &caps,
+    )
+    .unwrap()
+}
+
+/// Extracts the index from the file name of a segment URI that was created from a
+/// `printf`-style segment location template, e.g. `segment00042.ts` for `segment%05d.ts`.
+fn template_index(template: &str, uri: &str) -> Option<u32> {
+    let template = template.rsplit('/').next().unwrap();
+    let file_name = uri.rsplit('/').next().unwrap();
+
+    let (prefix, conversion) = template.split_once('%')?;
+    let suffix = conversion
+        .trim_start_matches(|c: char| c.is_ascii_digit() || "-+ #".contains(c))
+        .strip_prefix(|c| matches!(c, 'd' | 'i' | 'u'))?;
+
+    file_name
+        .strip_prefix(prefix)?
+        .strip_suffix(suffix)?
+        .parse()
+        .ok()
+}
+
 impl ElementImpl for HlsBaseSink {
     fn change_state(
         &self,
@@ -394,6 +643,7 @@ impl ElementImpl for HlsBaseSink {
             }
             gst::StateChange::PausedToReady => {
                 self.close_playlist();
+                self.state.lock().unwrap().sample_aes_key = None;
             }
             _ => (),
         }
@@ -407,20 +657,159 @@ impl BinImpl for HlsBaseSink {}
 impl HlsBaseSinkImpl for HlsBaseSink {}
 
 impl HlsBaseSink {
-    pub fn open_playlist(&self, playlist: Playlist, segment_template: String) {
+    /// Opens a new playlist, or continues the existing one if `resume` is enabled.
+    ///
+    /// Returns the index of the next segment for the segment template.
+    pub fn open_playlist(&self, mut playlist: Playlist, segment_template: String) -> u32 {
+        // Read the existing playlist before taking the locks, as the handler of the signal
+        // might call back into the element
+        let existing = {
+            let settings = self.settings.lock().unwrap();
+            if settings.resume && settings.single_media_file.is_some() {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Can't resume playlist with a single media file"
+                );
+            }
+
+            (settings.resume && settings.single_media_file.is_none())
+                .then(|| settings.playlist_location.clone())
+        }
+        .and_then(|playlist_location| self.read_playlist(&playlist_location));
+
         let mut state = self.state.lock().unwrap();
         let settings = self.settings.lock().unwrap();
+
+        let (next_index, old_segment_locations) = existing
+            .and_then(|content| {
+                self.resume_playlist(&mut playlist, &settings, &segment_template, &content)
+            })
+            .unwrap_or_default();
+        let next_media_sequence = playlist.next_media_sequence();
+
         state.context = Some(PlaylistContext {
             pdt_base_utc: None,
             pdt_base_running_time: None,
             playlist,
-            old_segment_locations: Vec::new(),
+            old_segment_locations,
+            part_locations: HashMap::new(),
             segment_template,
             playlist_location: settings.playlist_location.clone(),
             max_num_segment_files: settings.max_num_segment_files,
             playlist_length: settings.playlist_length,
             single_media_file: settings.single_media_file.is_some(),
+            next_media_sequence,
+            key: state.sample_aes_key.take(),
+            rotate_key: false,
+            pending_segment_keys: VecDeque::new(),
+            last_segment_key: None,
+            old_key_locations: HashMap::new(),
+            pending_splices: Vec::new(),
+            break_start_dates: HashMap::new(),
         });
+
+        next_index
+    }
+
+    /// Reads the existing playlist at `playlist_location` via the `get-playlist-input-stream`
+    /// signal, if there is one.
+    fn read_playlist(&self, playlist_location: &str) -> Option<Vec<u8>> {
+        let Some(stream) = self.obj().emit_by_name::<Option<gio::InputStream>>(
+            SIGNAL_GET_PLAYLIST_INPUT_STREAM,
+            &[&playlist_location],
+        ) else {
+            gst::info!(
+                CAT,
+                imp = self,
+                "No playlist at {playlist_location} to resume"
+            );
+            return None;
+        };
+
+        let mut content = Vec::new();
+        let mut reader = stream.into_read();
+        if let Err(err) = std::io::Read::read_to_end(&mut reader, &mut content) {
+            gst::warning!(
+                CAT,
+                imp = self,
+                "Could not read playlist {playlist_location}: {err}"
+            );
+            return None;
+        }
+        let _ = reader.into_input_stream().close(None::<&gio::Cancellable>);
+
+        Some(content)
+    }
+
+    /// Continues the existing playlist with the given content.
+    ///
+    /// Returns the index of the next segment and the locations of the segment files that are
+    /// left from before, which are deleted again once there are more than `max-files`.
+    fn resume_playlist(
+        &self,
+        playlist: &mut Playlist,
+        settings: &Settings,
+        segment_template: &str,
+        content: &[u8],
+    ) -> Option<(u32, Vec<String>)> {
+        let existing = match m3u8_rs::parse_media_playlist_res(content) {
+            Ok(existing) => existing,
+            Err(err) => {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Could not parse playlist {}: {err:?}",
+                    settings.playlist_location
+                );
+                return None;
+            }
+        };
+
+        let next_media_sequence = existing.media_sequence + existing.segments.len() as u64;
+        let last_index = existing
+            .segments
+            .iter()
+            .map(|segment| template_index(segment_template, &segment.uri))
+            .collect::<Option<Vec<_>>>()
+            .map(|indices| indices.into_iter().max());
+
+        let (next_index, old_segment_locations) = match last_index {
+            Some(Some(last_index)) => {
+                // Segment files up to max-files before the next one are still around
+                let first_index = if settings.max_num_segment_files > 0 {
+                    (last_index + 1).saturating_sub(settings.max_num_segment_files as u32)
+                } else {
+                    last_index + 1
+                };
+                let old_segment_locations = (first_index..=last_index)
+                    .filter_map(|index| sprintf::sprintf!(segment_template, index).ok())
+                    .collect();
+
+                (last_index + 1, old_segment_locations)
+            }
+            Some(None) => (next_media_sequence as u32, Vec::new()),
+            None => {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Segment URIs don't match the segment location, old segments are not deleted"
+                );
+                (next_media_sequence as u32, Vec::new())
+            }
+        };
+
+        gst::info!(
+            CAT,
+            imp = self,
+            "Resuming playlist {} with {} segments at media sequence {next_media_sequence}, next segment index {next_index}",
+            settings.playlist_location,
+            existing.segments.len()
+        );
+
+        playlist.resume(existing);
+
+        Some((next_index, old_segment_locations))
     }
 
     pub fn close_playlist(&self) {
@@ -432,6 +821,10 @@ impl HlsBaseSink {
                     .stop(self.settings.lock().unwrap().enable_endlist);
                 let _ = self.write_playlist(&mut context);
             }
+
+            if self.encryption_method() == HlsEncryptionMethod::SampleAes {
+                state.sample_aes_key = context.key.take();
+            }
         }
     }
 
@@ -454,6 +847,25 @@ impl HlsBaseSink {
     }
 
     pub fn get_fragment_stream(&self, fragment_id: u32) -> Option<(gio::OutputStream, String)> {
+        // Request a new key if the next segment needs one before taking the state lock, as the
+        // handlers of the key signals might call back into the element
+        let key_index = {
+            let state = self.state.lock().unwrap();
+            let settings = self.settings.lock().unwrap();
+            state
+                .context
+                .as_ref()
+                .filter(|_| settings.single_media_file.is_none())
+                .and_then(|context| Self::next_key_index(context, &settings))
+        };
+        let new_key = match key_index.map(|index| self.new_key(index)).transpose() {
+            Ok(new_key) => new_key,
+            Err(err) => {
+                self.post_error_message(err);
+                return None;
+            }
+        };
+
         let mut state = self.state.lock().unwrap();
         let context = match state.context.as_mut() {
             Some(context) => context,
@@ -481,6 +893,20 @@ impl HlsBaseSink {
 
             gst::trace!(CAT, imp = self, "Segment location formatted: {}", location);
 
+            let media_sequence = context.next_media_sequence;
+            context.next_media_sequence += 1;
+
+            let stream =
+                match self.next_segment_key(context, &settings, new_key, &location, media_sequence)
+                {
+                    Ok(Some((key, Some(iv)))) => AesOutputStream::new(stream, &key, &iv).upcast(),
+                    Ok(_) => stream,
+                    Err(err) => {
+                        self.post_error_message(err);
+                        return None;
+                    }
+                };
+
             Some((stream, location))
         } else {
             let location = settings.single_media_file.as_ref().unwrap().clone();
@@ -504,6 +930,16 @@ impl HlsBaseSink {
         }
     }
 
+    pub fn get_part_stream(&self, location: &str) -> Option<gio::OutputStream> {
+        let stream = self
+            .obj()
+            .emit_by_name::<Option<gio::OutputStream>>(SIGNAL_GET_FRAGMENT_STREAM, &[&location])?;
+
+        gst::trace!(CAT, imp = self, "Part location formatted: {}", location);
+
+        Some(stream)
+    }
+
     pub fn get_segment_uri(&self, location: &str, prefix: Option<&str>) -> String {
         let settings = self.settings.lock().unwrap();
         let file_name = path::Path::new(&location)
@@ -597,8 +1033,20 @@ impl HlsBaseSink {
             }
         }
 
+        if let Some(running_time) = running_time {
+            self.add_splice_tags(context, running_time, &mut segment);
+        }
+
         let (init_segment_br, segment_br) = self.byte_ranges(context, &segment);
 
+        // EXT-X-KEY applies to all following segments so it's only needed when the key changes
+        if let Some(key) = context.pending_segment_keys.pop_front() {
+            if context.last_segment_key.as_ref() != Some(&key) {
+                segment.key = Some(key.clone());
+            }
+            context.last_segment_key = Some(key);
+        }
+
         context.playlist.add_segment(segment);
 
         if context.playlist.is_type_undefined() {
@@ -627,6 +1075,65 @@ impl HlsBaseSink {
         })
     }
 
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_part(
+        &self,
+        segment_location: &str,
+        location: &str,
+        running_time: Option<gst::ClockTime>,
+        duration: gst::ClockTime,
+        part: PartialSegment,
+        map: Option<m3u8_rs::Map>,
+        preload_hint: Option<String>,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let mut state = self.state.lock().unwrap();
+        let context = match state.context.as_mut() {
+            Some(context) => context,
+            None => {
+                gst::error!(CAT, imp = self, "Playlist is not configured",);
+
+                return Err(gst::FlowError::Error);
+            }
+        };
+
+        context.playlist.add_part(part, map, preload_hint);
+
+        if context.playlist.is_type_undefined() {
+            context
+                .part_locations
+                .entry(segment_location.to_string())
+                .or_default()
+                .push(location.to_string());
+        }
+
+        let (media_sequence, part_index) = context.playlist.last_part().unwrap();
+
+        self.write_playlist_file(context)?;
+
+        let mut s = gst::Structure::builder("hls-part-added")
+            .field("location", location)
+            .field("media-sequence", media_sequence)
+            .field("part-index", part_index)
+            .field("duration", duration);
+        if let Some(running_time) = running_time {
+            s = s.field("running-time", running_time);
+        }
+        self.post_message(
+            gst::message::Element::builder(s.build())
+                .src(&*self.obj())
+                .build(),
+        );
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    pub fn set_rendition_report(&self, report: RenditionReport) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(context) = state.context.as_mut() {
+            context.playlist.set_rendition_report(report);
+        }
+    }
+
     fn write_playlist(
         &self,
         context: &mut PlaylistContext,
@@ -642,6 +1149,53 @@ impl HlsBaseSink {
             .playlist
             .update_playlist_state(context.playlist_length as usize);
 
+        self.write_playlist_file(context)?;
+
+        let delete_fragment = context.playlist.is_type_undefined()
+            && context.max_num_segment_files > 0
+            && !context.single_media_file;
+        if delete_fragment {
+            // Cleanup old segments from filesystem
+            while context.old_segment_locations.len() > context.max_num_segment_files {
+                let old_segment_location = context.old_segment_locations.remove(0);
+                if !self
+                    .obj()
+                    .emit_by_name::<bool>(SIGNAL_DELETE_FRAGMENT, &[&old_segment_location])
+                {
+                    gst::error!(CAT, imp = self, "Could not delete fragment");
+                }
+
+                for old_part_location in context
+                    .part_locations
+                    .remove(&old_segment_location)
+                    .unwrap_or_default()
+                {
+                    if !self
+                        .obj()
+                        .emit_by_name::<bool>(SIGNAL_DELETE_FRAGMENT, &[&old_part_location])
+                    {
+                        gst::error!(CAT, imp = self, "Could not delete partial segment");
+                    }
+                }
+
+                if let Some(old_key_location) =
+                    context.old_key_locations.remove(&old_segment_location)
+                {
+                    if !self
+                        .obj()
+                        .emit_by_name::<bool>(SIGNAL_DELETE_KEY, &[&old_key_location])
+                    {
+                        gst::error!(CAT, imp = self, "Could not delete key");
+                    }
+                }
+            }
+        }
+
+        gst::debug!(CAT, imp = self, "Wrote new playlist file!");
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn write_playlist_file(&self, context: &PlaylistContext) -> Result<(), gst::FlowError> {
         // Acquires the playlist file handle so we can update it with new content. By default, this
         // is expected to be the same file every time.
         let mut playlist_stream = self
@@ -682,24 +1236,7 @@ impl HlsBaseSink {
             gst::FlowError::Error
         })?;
 
-        let delete_fragment = context.playlist.is_type_undefined()
-            && context.max_num_segment_files > 0
-            && !context.single_media_file;
-        if delete_fragment {
-            // Cleanup old segments from filesystem
-            while context.old_segment_locations.len() > context.max_num_segment_files {
-                let old_segment_location = context.old_segment_locations.remove(0);
-                if !self
-                    .obj()
-                    .emit_by_name::<bool>(SIGNAL_DELETE_FRAGMENT, &[&old_segment_location])
-                {
-                    gst::error!(CAT, imp = self, "Could not delete fragment");
-                }
-            }
-        }
-
-        gst::debug!(CAT, imp = self, "Wrote new playlist file!");
-        Ok(gst::FlowSuccess::Ok)
+        Ok(())
     }
 
     pub fn new_file_stream<P>(&self, location: &P) -> Result<gio::OutputStream, String>
@@ -732,6 +1269,18 @@ impl HlsBaseSink {
         Ok(output_stream.upcast())
     }
 
+    /// Opens the file at `location` for reading, if it exists.
+    fn read_file_stream(&self, location: &str) -> Option<gio::InputStream> {
+        match gio::File::for_path(location).read(None::<&gio::Cancellable>) {
+            Ok(stream) => Some(stream.upcast()),
+            Err(err) if err.matches(gio::IOErrorEnum::NotFound) => None,
+            Err(err) => {
+                gst::warning!(CAT, imp = self, "Could not open file {location}: {err}");
+                None
+            }
+        }
+    }
+
     fn delete_fragment<P>(&self, location: &P)
     where
         P: AsRef<path::Path>,
@@ -746,6 +1295,508 @@ impl HlsBaseSink {
         });
     }
 
+    pub fn encryption_method(&self) -> HlsEncryptionMethod {
+        let settings = self.settings.lock().unwrap();
+        settings.encryption_method
+    }
+
+    pub fn key_rotation_period(&self) -> u32 {
+        let settings = self.settings.lock().unwrap();
+        settings.key_rotation_period
+    }
+
+    /// Returns the key and IV for the first segment, creating them if necessary.
+    ///
+    /// This is used for SAMPLE-AES where the key and IV have to be configured on the muxer
+    /// before the first segment is started.
+    pub fn initial_key(&self) -> Result<([u8; 16], [u8; 16]), gst::ErrorMessage> {
+        let not_configured =
+            || gst::error_msg!(gst::LibraryError::Failed, ["Playlist is not configured"]);
+
+        let has_key = {
+            let state = self.state.lock().unwrap();
+            let context = state.context.as_ref().ok_or_else(not_configured)?;
+            context.key.is_some()
+        };
+        let new_key = if has_key {
+            None
+        } else {
+            Some(self.new_key(0)?)
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let context = state.context.as_mut().ok_or_else(not_configured)?;
+        if let Some(new_key) = new_key {
+            context.key.get_or_insert(new_key);
+        }
+
+        context
+            .key
+            .as_ref()
+            .and_then(|key| Some((key.key, key.iv?)))
+            .ok_or_else(|| gst::error_msg!(gst::LibraryError::Failed, ["No key available"]))
+    }
+
+    /// Returns the index of the key that has to be requested before the next segment can be
+    /// encrypted, if any.
+    ///
+    /// A new key is used every `key-rotation-period` segments and after the `rotate-key`
+    /// signal. SAMPLE-AES keys are configured on the muxer once and are never rotated, which is
+    /// why `hlscmafsink` refuses to start with a key rotation period.
+    fn next_key_index(context: &PlaylistContext, settings: &Settings) -> Option<u32> {
+        if settings.encryption_method == HlsEncryptionMethod::None {
+            return None;
+        }
+
+        match context.key {
+            None => Some(0),
+            Some(ref key) => (settings.encryption_method == HlsEncryptionMethod::Aes128
+                && (context.rotate_key
+                    || (settings.key_rotation_period > 0
+                        && key.segments >= settings.key_rotation_period)))
+                .then_some(key.index + 1),
+        }
+    }
+
+    /// Returns the key to encrypt the next segment with, if encryption is enabled. `new_key`
+    /// is the key requested for the index returned by `next_key_index()`.
+    ///
+    /// For AES-128 the IV is derived from the media sequence number of the segment. It is
+    /// signalled explicitly as the media sequence number of the final playlist can't be relied
+    /// on by clients.
+    fn next_segment_key(
+        &self,
+        context: &mut PlaylistContext,
+        settings: &Settings,
+        new_key: Option<SegmentKey>,
+        location: &str,
+        media_sequence: u64,
+    ) -> Result<Option<([u8; 16], Option<[u8; 16]>)>, gst::ErrorMessage> {
+        if settings.encryption_method == HlsEncryptionMethod::None {
+            return Ok(None);
+        }
+
+        if let Some(new_key) = new_key {
+            if let Some(old_key) = context.key.replace(new_key) {
+                if let Some((old_location, last_segment_location)) =
+                    Option::zip(old_key.location, old_key.last_segment_location)
+                {
+                    context
+                        .old_key_locations
+                        .insert(last_segment_location, old_location);
+                }
+            }
+            context.rotate_key = false;
+        }
+
+        let key = context
+            .key
+            .as_mut()
+            .ok_or_else(|| gst::error_msg!(gst::LibraryError::Failed, ["No key available"]))?;
+        key.segments += 1;
+        key.last_segment_location = Some(location.to_string());
+
+        let mut tag = key.tag.clone();
+        let iv = (settings.encryption_method == HlsEncryptionMethod::Aes128).then(|| {
+            let iv = Aes128CbcEncryptor::media_sequence_iv(media_sequence);
+            tag.iv = Some(format!("0x{:032X}", u128::from_be_bytes(iv)));
+            iv
+        });
+        context.pending_segment_keys.push_back(tag);
+
+        Ok(Some((key.key, iv)))
+    }
+
+    /// Requests the key with the given index via the `get-key` signal and writes the key file
+    /// via the `get-key-stream` signal.
+    ///
+    /// Must be called without the state lock held.
+    fn new_key(&self, index: u32) -> Result<SegmentKey, gst::ErrorMessage> {
+        let (key_location, key_uri, playlist_root, encryption_method) = {
+            let settings = self.settings.lock().unwrap();
+            (
+                settings.key_location.clone(),
+                settings.key_uri.clone(),
+                settings.playlist_root.clone(),
+                settings.encryption_method,
+            )
+        };
+
+        let key = self
+            .obj()
+            .emit_by_name::<Option<glib::Bytes>>(SIGNAL_GET_KEY, &[&index])
+            .and_then(|key| <[u8; 16]>::try_from(&*key).ok())
+            .ok_or_else(|| {
+                gst::error_msg!(
+                    gst::ResourceError::Settings,
+                    ["No valid 16 byte key provided for key {}", index]
+                )
+            })?;
+
+        let format = |template: &str| {
+            sprintf::sprintf!(template, index).map_err(|err| {
+                gst::error_msg!(
+                    gst::ResourceError::Settings,
+                    ["Invalid key location or URI '{}': {:?}", template, err]
+                )
+            })
+        };
+
+        let location = key_location.as_deref().map(&format).transpose()?;
+
+        if let Some(ref location) = location {
+            gst::debug!(CAT, imp = self, "Writing key {index} to {location}");
+
+            let stream = self
+                .obj()
+                .emit_by_name::<Option<gio::OutputStream>>(SIGNAL_GET_KEY_STREAM, &[location])
+                .ok_or_else(|| {
+                    gst::error_msg!(
+                        gst::ResourceError::OpenWrite,
+                        ["Could not get stream for key {}", location]
+                    )
+                })?;
+            stream
+                .write_all(&key, None::<&gio::Cancellable>)
+                .and_then(|_| stream.close(None::<&gio::Cancellable>))
+                .map_err(|err| {
+                    gst::error_msg!(
+                        gst::ResourceError::Write,
+                        ["Could not write key {}: {}", location, err]
+                    )
+                })?;
+        }
+
+        let uri = match (&key_uri, &location) {
+            (Some(key_uri), _) => format(key_uri)?,
+            (None, Some(location)) => {
+                let file_name = path::Path::new(location)
+                    .file_name()
+                    .unwrap()
+                    .to_str()
+                    .unwrap();
+                match playlist_root {
+                    Some(ref playlist_root) => format!("{playlist_root}/{file_name}"),
+                    None => file_name.to_string(),
+                }
+            }
+            (None, None) => {
+                return Err(gst::error_msg!(
+                    gst::ResourceError::Settings,
+                    ["Either key-location or key-uri must be set for encryption"]
+                ));
+            }
+        };
+
+        // AES-128 IVs are derived from the media sequence number of each segment, while
+        // SAMPLE-AES uses the same IV for all samples
+        let (method, iv) = match encryption_method {
+            HlsEncryptionMethod::SampleAes => (
+                m3u8_rs::KeyMethod::SampleAES,
+                Some(rand::random::<[u8; 16]>()),
+            ),
+            _ => (m3u8_rs::KeyMethod::AES128, None),
+        };
+
+        Ok(SegmentKey {
+            index,
+            key,
+            iv,
+            location,
+            tag: m3u8_rs::Key {
+                method,
+                uri: Some(uri),
+                iv: iv.map(|iv| format!("0x{:032X}", u128::from_be_bytes(iv))),
+                keyformat: None,
+                keyformatversions: None,
+            },
+            segments: 0,
+            last_segment_location: None,
+        })
+    }
+
+    /// Sets the element that starts new segments at the running times of splice points.
+    ///
+    /// The element has to request keyframes for the split times, like `splitmuxsink` with
+    /// `send-keyframe-requests` or `cmafmux` do.
+    pub fn set_splitter(&self, splitter: &gst::Element) {
+        *self.splitter.lock().unwrap() = Some(splitter.clone());
+    }
+
+    /// Creates the `scte35` request pad, if it does not exist yet.
+    pub fn request_scte35_pad(&self, templ: &gst::PadTemplate) -> Option<gst::Pad> {
+        if self.obj().static_pad("scte35").is_some() {
+            gst::debug!(
+                CAT,
+                imp = self,
+                "requested_new_pad: scte35 pad is already set"
+            );
+            return None;
+        }
+
+        let sink_pad = gst::Pad::builder_from_template(templ)
+            .chain_function(|pad, parent, buffer| {
+                HlsBaseSink::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::FlowError::Error),
+                    |imp| imp.scte35_chain(pad, buffer),
+                )
+            })
+            .event_function(|pad, parent, event| {
+                HlsBaseSink::catch_panic_pad_function(
+                    parent,
+                    || false,
+                    |imp| imp.sink_event(pad, event),
+                )
+            })
+            .build();
+        self.obj().add_pad(&sink_pad).unwrap();
+        sink_pad.set_active(true).unwrap();
+
+        Some(sink_pad)
+    }
+
+    /// Handles events on the sink pads. SCTE-35 sections can also be signalled by custom
+    /// downstream events on any sink pad, everything else is handled by the default handler.
+    pub fn sink_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        if let gst::EventView::CustomDownstream(ev) = event.view() {
+            if let Some(scte35) = Scte35Event::try_parse(ev) {
+                match scte35 {
+                    Ok(scte35) => match scte35.section.map_readable() {
+                        Ok(section) => self.on_splice(scte35.running_time, &section),
+                        Err(_) => gst::warning!(CAT, obj = pad, "Can't map SCTE-35 section"),
+                    },
+                    Err(err) => gst::warning!(CAT, obj = pad, "{err}"),
+                }
+
+                return true;
+            }
+        }
+
+        gst::Pad::event_default(pad, Some(&*self.obj()), event)
+    }
+
+    fn scte35_chain(
+        &self,
+        pad: &gst::Pad,
+        buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        // The buffer timestamp is the splice point
+        let running_time = buffer.pts().and_then(|pts| {
+            let segment = pad.sticky_event::<gst::event::Segment>(0)?;
+            segment
+                .segment()
+                .downcast_ref::<gst::ClockTime>()?
+                .to_running_time(pts)
+        });
+        let Some(running_time) = running_time else {
+            gst::warning!(
+                CAT,
+                obj = pad,
+                "Dropping SCTE-35 section without running time"
+            );
+            return Ok(gst::FlowSuccess::Ok);
+        };
+
+        let section = buffer.map_readable().map_err(|_| {
+            gst::error!(CAT, obj = pad, "Failed to map buffer");
+            gst::FlowError::Error
+        })?;
+        self.on_splice(running_time, &section);
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn on_splice(&self, running_time: gst::ClockTime, section: &[u8]) {
+        let split_times = self.add_splice(running_time, section);
+        if split_times.is_empty() {
+            return;
+        }
+
+        let Some(splitter) = self.splitter.lock().unwrap().clone() else {
+            gst::warning!(
+                CAT,
+                imp = self,
+                "Can't start new segments for splice points"
+            );
+            return;
+        };
+        for split_time in split_times {
+            gst::debug!(CAT, imp = self, "Starting new segment at {split_time}");
+            splitter.emit_by_name::<()>("split-at-running-time", &[&split_time]);
+        }
+    }
+
+    /// Schedules the splice point signalled by a SCTE-35 `splice_info_section` at the given
+    /// running time.
+    ///
+    /// Returns the running times at which new segments have to be started.
+    fn add_splice(&self, running_time: gst::ClockTime, section: &[u8]) -> Vec<gst::ClockTime> {
+        let splice = match scte35::parse_splice_info_section(section) {
+            Ok(Some(splice)) => splice,
+            Ok(None) => {
+                gst::debug!(
+                    CAT,
+                    imp = self,
+                    "Ignoring SCTE-35 section without splice point"
+                );
+                return vec![];
+            }
+            Err(err) => {
+                gst::warning!(CAT, imp = self, "Invalid SCTE-35 section: {err}");
+                return vec![];
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let Some(context) = state.context.as_mut() else {
+            gst::error!(CAT, imp = self, "Playlist is not configured");
+            return vec![];
+        };
+
+        let event_id = splice.event_id;
+        gst::debug!(
+            CAT,
+            imp = self,
+            "Splice {:?} of event {event_id} at {running_time}",
+            splice.command
+        );
+
+        let mut splices = vec![PendingSplice {
+            running_time,
+            event_id,
+            command: splice.command,
+            section: Some(splice.section),
+        }];
+        match splice.command {
+            SpliceCommand::Cancel => {
+                context
+                    .pending_splices
+                    .retain(|pending| pending.event_id != event_id);
+                return vec![];
+            }
+            SpliceCommand::Out {
+                duration: Some(duration),
+                auto_return: true,
+            } => {
+                splices.push(PendingSplice {
+                    running_time: running_time + duration,
+                    event_id,
+                    command: SpliceCommand::In,
+                    section: None,
+                });
+            }
+            _ => (),
+        }
+
+        let split_times = splices.iter().map(|splice| splice.running_time).collect();
+        context.pending_splices.extend(splices);
+        context
+            .pending_splices
+            .sort_by_key(|splice| splice.running_time);
+
+        split_times
+    }
+
+    /// Adds `EXT-X-DATERANGE` and `EXT-X-CUE-OUT` / `EXT-X-CUE-IN` tags for all pending splice
+    /// points up to the start of the segment.
+    fn add_splice_tags(
+        &self,
+        context: &mut PlaylistContext,
+        running_time: gst::ClockTime,
+        segment: &mut MediaSegment,
+    ) {
+        let count = context
+            .pending_splices
+            .iter()
+            .take_while(|splice| splice.running_time <= running_time)
+            .count();
+        if count == 0 {
+            return;
+        }
+
+        let splices = context.pending_splices.drain(..count).collect::<Vec<_>>();
+        let to_utc = |running_time: gst::ClockTime| {
+            let offset =
+                running_time.nseconds() as i64 - context.pdt_base_running_time?.nseconds() as i64;
+            context
+                .pdt_base_utc?
+                .checked_add_signed(Duration::nanoseconds(offset))
+        };
+
+        for splice in splices {
+            let Some(date) = to_utc(splice.running_time) else {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Can't signal splice of event {} without date",
+                    splice.event_id
+                );
+                continue;
+            };
+
+            gst::debug!(
+                CAT,
+                imp = self,
+                "Signalling splice {:?} of event {} at {date}",
+                splice.command,
+                splice.event_id
+            );
+
+            let (date_range, cue) = match splice.command {
+                SpliceCommand::Out { duration, .. } => {
+                    context.break_start_dates.insert(splice.event_id, date);
+
+                    let duration = duration.map(|duration| duration.mseconds() as f64 / 1_000.0);
+                    let date_range = DateRange {
+                        id: splice.event_id.to_string(),
+                        start_date: date,
+                        end_date: None,
+                        planned_duration: duration,
+                        scte35_out: splice.section,
+                        scte35_in: None,
+                    };
+                    let cue = ExtTag {
+                        tag: String::from("X-CUE-OUT"),
+                        rest: duration.map(|duration| format!("DURATION={duration:.3}")),
+                    };
+
+                    (date_range, cue)
+                }
+                SpliceCommand::In => {
+                    // The date range of the break is completed with the same ID and start date
+                    let start_date = context.break_start_dates.remove(&splice.event_id);
+                    let date_range = DateRange {
+                        id: splice.event_id.to_string(),
+                        start_date: start_date.unwrap_or(date),
+                        end_date: start_date.map(|_| date),
+                        planned_duration: None,
+                        scte35_out: None,
+                        scte35_in: splice.section,
+                    };
+                    let cue = ExtTag {
+                        tag: String::from("X-CUE-IN"),
+                        rest: None,
+                    };
+
+                    (date_range, cue)
+                }
+                SpliceCommand::Cancel => unreachable!(),
+            };
+
+            segment.unknown_tags.push(ExtTag {
+                tag: String::from("X-DATERANGE"),
+                rest: Some(date_range.to_string()),
+            });
+            segment.unknown_tags.push(cue);
+        }
+
+        // Playlists with date ranges must contain a program date time
+        if segment.program_date_time.is_none() {
+            segment.program_date_time = to_utc(running_time).map(Into::into);
+        }
+    }
+
     pub fn is_single_media_file(&self) -> bool {
         let settings = self.settings.lock().unwrap();
         settings.single_media_file.is_some()
@@ -6,10 +6,10 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::hlsbasesink::HlsBaseSinkImpl;
+use crate::hlsbasesink::{scte35_pad_template, HlsBaseSinkImpl, HlsEncryptionMethod};
 use crate::hlssink3::HlsSink3PlaylistType;
-use crate::playlist::Playlist;
-use crate::HlsBaseSink;
+use crate::playlist::{PartialSegment, Playlist, RenditionReport};
+use crate::{HlsBaseSink, HlsBaseSinkGioOutputStream};
 use chrono::{DateTime, Utc};
 use gio::prelude::*;
 use gst::glib;
@@ -22,13 +22,16 @@ use std::sync::Mutex;
 
 const DEFAULT_INIT_LOCATION: &str = "init%05d.mp4";
 const DEFAULT_CMAF_LOCATION: &str = "segment%05d.m4s";
+const DEFAULT_PART_LOCATION: &str = "segment%05d.%d.m4s";
 const DEFAULT_TARGET_DURATION: u32 = 15;
 const DEFAULT_PLAYLIST_TYPE: HlsSink3PlaylistType = HlsSink3PlaylistType::Unspecified;
 const DEFAULT_SYNC: bool = true;
+const DEFAULT_CAN_BLOCK_RELOAD: bool = false;
 const DEFAULT_LATENCY: gst::ClockTime =
     gst::ClockTime::from_mseconds((DEFAULT_TARGET_DURATION * 500) as u64);
 const SIGNAL_GET_INIT_STREAM: &str = "get-init-stream";
 const SIGNAL_NEW_PLAYLIST: &str = "new-playlist";
+const SIGNAL_UPDATE_RENDITION_REPORT: &str = "update-rendition-report";
 
 static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
@@ -52,6 +55,9 @@ struct HlsCmafSinkSettings {
     sync: bool,
     latency: gst::ClockTime,
     playlist_root_init: Option<String>,
+    chunk_duration: Option<gst::ClockTime>,
+    part_location: String,
+    can_block_reload: bool,
 
     cmafmux: gst::Element,
     appsink: gst_app::AppSink,
@@ -82,12 +88,27 @@ impl Default for HlsCmafSinkSettings {
             sync: DEFAULT_SYNC,
             latency: DEFAULT_LATENCY,
             playlist_root_init: None,
+            chunk_duration: None,
+            part_location: String::from(DEFAULT_PART_LOCATION),
+            can_block_reload: DEFAULT_CAN_BLOCK_RELOAD,
             cmafmux,
             appsink,
         }
     }
 }
 
+/// Segment that is currently being written chunk by chunk in low-latency mode.
+struct PendingSegment {
+    stream: HlsBaseSinkGioOutputStream,
+    location: String,
+    idx: u32,
+    running_time: Option<gst::ClockTime>,
+    duration: gst::ClockTime,
+    part_idx: u32,
+    // Initialization section if it changed before this segment
+    map: Option<m3u8_rs::Map>,
+}
+
 #[derive(Default)]
 struct HlsCmafSinkState {
     init_idx: u32,
@@ -95,6 +116,18 @@ struct HlsCmafSinkState {
     init_segment: Option<m3u8_rs::Map>,
     new_header: bool,
     offset: u64,
+    pending_segment: Option<PendingSegment>,
+}
+
+impl HlsCmafSinkState {
+    /// Returns the initialization section for the next segment if it changed since the last one.
+    fn take_new_map(&mut self) -> Option<m3u8_rs::Map> {
+        if std::mem::take(&mut self.new_header) {
+            self.init_segment.clone()
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Default)]
@@ -153,6 +186,49 @@ impl ObjectImpl for HlsCmafSink {
                     .nick("Playlist Root Init")
                     .blurb("Base path for the init fragment in the playlist file.")
                     .build(),
+                /**
+                 * GstHlsCmafSink:chunk-duration:
+                 *
+                 * Duration of the CMAF chunks of each segment. If set, a low-latency HLS
+                 * playlist is written with every chunk as a partial segment.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecUInt64::builder("chunk-duration")
+                    .nick("Chunk Duration")
+                    .blurb("Duration of the chunks of each segment, written as partial segments of a low-latency playlist (default = no chunks)")
+                    .default_value(u64::MAX)
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstHlsCmafSink:part-location:
+                 *
+                 * Location of the partial segment files to write. The first format argument is
+                 * the index of the parent segment, the second one the index of the partial
+                 * segment inside it.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecString::builder("part-location")
+                    .nick("Part Location")
+                    .blurb("Location of the partial segment files to write")
+                    .default_value(Some(DEFAULT_PART_LOCATION))
+                    .build(),
+                /**
+                 * GstHlsCmafSink:can-block-reload:
+                 *
+                 * Announce `CAN-BLOCK-RELOAD=YES` in the `EXT-X-SERVER-CONTROL` tag of a
+                 * low-latency playlist. The sink only writes the playlist, so this must only be
+                 * enabled if the HTTP server that serves it supports blocking playlist reloads.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecBoolean::builder("can-block-reload")
+                    .nick("Can Block Reload")
+                    .blurb("Announce that the server serving the playlist supports blocking playlist reloads")
+                    .default_value(DEFAULT_CAN_BLOCK_RELOAD)
+                    .mutable_ready()
+                    .build(),
             ]
         });
 
@@ -200,6 +276,21 @@ impl ObjectImpl for HlsCmafSink {
                     .get::<Option<String>>()
                     .expect("type checked upstream");
             }
+            "chunk-duration" => {
+                settings.chunk_duration = value.get().expect("type checked upstream");
+                settings
+                    .cmafmux
+                    .set_property("chunk-duration", settings.chunk_duration);
+            }
+            "part-location" => {
+                settings.part_location = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream")
+                    .unwrap_or_else(|| DEFAULT_PART_LOCATION.into());
+            }
+            "can-block-reload" => {
+                settings.can_block_reload = value.get().expect("type checked upstream");
+            }
             _ => unimplemented!(),
         };
     }
@@ -217,6 +308,9 @@ impl ObjectImpl for HlsCmafSink {
             "sync" => settings.sync.to_value(),
             "latency" => settings.latency.to_value(),
             "playlist-root-init" => settings.playlist_root_init.to_value(),
+            "chunk-duration" => settings.chunk_duration.to_value(),
+            "part-location" => settings.part_location.to_value(),
+            "can-block-reload" => settings.can_block_reload.to_value(),
             _ => unimplemented!(),
         }
     }
@@ -254,20 +348,35 @@ impl ObjectImpl for HlsCmafSink {
                             imp = imp,
                             "Closing current playlist and starting a new one"
                         );
+                        let _ = imp.finish_pending_segment();
                         base_imp!(imp).close_playlist();
 
-                        let (target_duration, playlist_type, segment_template, cmafmux) = {
+                        let (
+                            target_duration,
+                            playlist_type,
+                            chunk_duration,
+                            can_block_reload,
+                            segment_template,
+                            cmafmux,
+                        ) = {
                             let settings = imp.settings.lock().unwrap();
                             (
                                 settings.target_duration,
                                 settings.playlist_type.clone(),
+                                settings.chunk_duration,
+                                settings.can_block_reload,
                                 settings.location.clone(),
                                 settings.cmafmux.clone(),
                             )
                         };
 
-                        let playlist = imp.start(target_duration, playlist_type);
-                        base_imp!(imp).open_playlist(playlist, segment_template);
+                        let playlist = imp.start(
+                            target_duration,
+                            playlist_type,
+                            chunk_duration,
+                            can_block_reload,
+                        );
+                        imp.open_playlist(playlist, segment_template);
 
                         // This forces cmafmux to send the init headers again.
                         cmafmux.emit_by_name::<()>("send-headers", &[]);
@@ -275,6 +384,41 @@ impl ObjectImpl for HlsCmafSink {
                         None
                     })
                     .build(),
+                /**
+                 * GstHlsCmafSink::update-rendition-report:
+                 * @uri: URI of the media playlist of the other rendition
+                 * @last_msn: media sequence number of the last segment of the other rendition
+                 * @last_part: index of the last partial segment of the other rendition, or -1
+                 *
+                 * Adds or updates the `EXT-X-RENDITION-REPORT` of another rendition in the
+                 * low-latency playlist. The values are usually taken from the
+                 * `hls-part-added` messages of the sink of the other rendition.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::subclass::Signal::builder(SIGNAL_UPDATE_RENDITION_REPORT)
+                    .param_types([
+                        String::static_type(),
+                        u64::static_type(),
+                        i32::static_type(),
+                    ])
+                    .action()
+                    .class_handler(|args| {
+                        let elem = args[0].get::<super::HlsCmafSink>().expect("signal arg");
+                        let uri = args[1].get::<String>().expect("signal arg");
+                        let last_msn = args[2].get::<u64>().expect("signal arg");
+                        let last_part = args[3].get::<i32>().expect("signal arg");
+                        let imp = elem.imp();
+
+                        base_imp!(imp).set_rendition_report(RenditionReport {
+                            uri,
+                            last_msn,
+                            last_part: u32::try_from(last_part).ok(),
+                        });
+
+                        None
+                    })
+                    .build(),
             ]
         });
 
@@ -287,12 +431,23 @@ impl ObjectImpl for HlsCmafSink {
         let obj = self.obj();
         let settings = self.settings.lock().unwrap();
 
+        base_imp!(self).set_splitter(&settings.cmafmux);
+
         obj.add_many([&settings.cmafmux, settings.appsink.upcast_ref()])
             .unwrap();
         settings.cmafmux.link(&settings.appsink).unwrap();
 
         let sinkpad = settings.cmafmux.static_pad("sink").unwrap();
-        let gpad = gst::GhostPad::with_target(&sinkpad).unwrap();
+        let gpad = gst::GhostPad::builder_from_template(&obj.pad_template("sink").unwrap())
+            .event_function(|pad, parent, event| {
+                HlsCmafSink::catch_panic_pad_function(
+                    parent,
+                    || false,
+                    |imp| base_imp!(imp).sink_event(pad.upcast_ref(), event),
+                )
+            })
+            .build();
+        gpad.set_target(Some(&sinkpad)).unwrap();
 
         obj.add_pad(&gpad).unwrap();
 
@@ -307,6 +462,16 @@ impl ObjectImpl for HlsCmafSink {
                     let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
                     imp.on_new_sample(sample)
                 })
+                .eos({
+                    let self_weak = self.downgrade();
+                    move |_sink| {
+                        let Some(imp) = self_weak.upgrade() else {
+                            return;
+                        };
+
+                        let _ = imp.finish_pending_segment();
+                    }
+                })
                 .build(),
         );
     }
@@ -353,13 +518,14 @@ impl ElementImpl for HlsCmafSink {
                         .field("channels", gst::IntRange::new(1, u16::MAX as i32))
                         .field("rate", gst::IntRange::new(1, i32::MAX))
                         .build(),
+                    gst::Structure::builder("application/x-subtitle-vtt-fragmented").build(),
                 ]
                 .into_iter()
                 .collect::<gst::Caps>(),
             )
             .unwrap();
 
-            vec![pad_template]
+            vec![pad_template, scte35_pad_template()]
         });
 
         PAD_TEMPLATES.as_ref()
@@ -370,21 +536,84 @@ impl ElementImpl for HlsCmafSink {
         transition: gst::StateChange,
     ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
         if transition == gst::StateChange::ReadyToPaused {
-            let (target_duration, playlist_type, segment_template) = {
+            let (
+                target_duration,
+                playlist_type,
+                chunk_duration,
+                can_block_reload,
+                segment_template,
+            ) = {
                 let settings = self.settings.lock().unwrap();
                 (
                     settings.target_duration,
                     settings.playlist_type.clone(),
+                    settings.chunk_duration,
+                    settings.can_block_reload,
                     settings.location.clone(),
                 )
             };
 
-            let playlist = self.start(target_duration, playlist_type);
-            base_imp!(self).open_playlist(playlist, segment_template);
+            if chunk_duration.is_some() && base_imp!(self).is_single_media_file() {
+                gst::element_imp_error!(
+                    self,
+                    gst::LibraryError::Settings,
+                    ["Chunks are not supported together with a single media file"]
+                );
+                return Err(gst::StateChangeError);
+            }
+
+            let encryption_method = base_imp!(self).encryption_method();
+            match encryption_method {
+                HlsEncryptionMethod::None => (),
+                HlsEncryptionMethod::SampleAes if !base_imp!(self).is_single_media_file() => (),
+                HlsEncryptionMethod::SampleAes => {
+                    gst::element_imp_error!(
+                        self,
+                        gst::LibraryError::Settings,
+                        ["Encryption is not supported together with a single media file"]
+                    );
+                    return Err(gst::StateChangeError);
+                }
+                _ => {
+                    gst::element_imp_error!(
+                        self,
+                        gst::LibraryError::Settings,
+                        ["Only SAMPLE-AES encryption is supported for CMAF segments"]
+                    );
+                    return Err(gst::StateChangeError);
+                }
+            }
+
+            let playlist = self.start(
+                target_duration,
+                playlist_type,
+                chunk_duration,
+                can_block_reload,
+            );
+            self.open_playlist(playlist, segment_template);
+
+            if let Err(err) = self.configure_encryption(encryption_method) {
+                self.post_error_message(err);
+                return Err(gst::StateChangeError);
+            }
         }
 
         self.parent_change_state(transition)
     }
+
+    fn request_new_pad(
+        &self,
+        templ: &gst::PadTemplate,
+        _name: Option<&str>,
+        _caps: Option<&gst::Caps>,
+    ) -> Option<gst::Pad> {
+        base_imp!(self).request_scte35_pad(templ)
+    }
+
+    fn release_pad(&self, pad: &gst::Pad) {
+        pad.set_active(false).unwrap();
+        self.obj().remove_pad(pad).unwrap();
+    }
 }
 
 impl BinImpl for HlsCmafSink {}
@@ -392,7 +621,13 @@ impl BinImpl for HlsCmafSink {}
 impl HlsBaseSinkImpl for HlsCmafSink {}
 
 impl HlsCmafSink {
-    fn start(&self, target_duration: u32, playlist_type: Option<MediaPlaylistType>) -> Playlist {
+    fn start(
+        &self,
+        target_duration: u32,
+        playlist_type: Option<MediaPlaylistType>,
+        chunk_duration: Option<gst::ClockTime>,
+        can_block_reload: bool,
+    ) -> Playlist {
         gst::info!(CAT, imp = self, "Starting");
 
         let mut state = self.state.lock().unwrap();
@@ -412,7 +647,60 @@ impl HlsCmafSink {
             ..Default::default()
         };
 
-        Playlist::new(playlist, turn_vod, true)
+        let mut playlist = Playlist::new(playlist, turn_vod, true);
+        if let Some(chunk_duration) = chunk_duration {
+            playlist.enable_low_latency(
+                chunk_duration.nseconds() as f64 / 1_000_000_000f64,
+                can_block_reload,
+            );
+        }
+
+        playlist
+    }
+
+    /// Opens the playlist and continues the segment numbering if it was resumed.
+    fn open_playlist(&self, playlist: Playlist, segment_template: String) {
+        let next_index = base_imp!(self).open_playlist(playlist, segment_template);
+
+        // A new init segment is only written before a new segment, so numbering them from the
+        // next segment index doesn't overwrite init segments referenced by a resumed playlist
+        let mut state = self.state.lock().unwrap();
+        state.segment_idx = next_index;
+        state.init_idx = next_index;
+    }
+
+    /// Configures the muxer for SAMPLE-AES, which corresponds to `cbcs` Common Encryption
+    /// for fragmented MP4.
+    ///
+    /// The key and IV can't be changed on the muxer once it started, so key rotation is not
+    /// supported.
+    fn configure_encryption(
+        &self,
+        encryption_method: HlsEncryptionMethod,
+    ) -> Result<(), gst::ErrorMessage> {
+        let cmafmux = self.settings.lock().unwrap().cmafmux.clone();
+
+        if encryption_method != HlsEncryptionMethod::SampleAes {
+            cmafmux.set_property_from_str("encryption-scheme", "none");
+            return Ok(());
+        }
+
+        if base_imp!(self).key_rotation_period() > 0 {
+            return Err(gst::error_msg!(
+                gst::LibraryError::Settings,
+                ["Key rotation is not supported for SAMPLE-AES"]
+            ));
+        }
+
+        let (key, iv) = base_imp!(self).initial_key()?;
+        let to_hex = |data: &[u8]| data.iter().map(|b| format!("{b:02x}")).collect::<String>();
+
+        cmafmux.set_property_from_str("encryption-scheme", "cbcs");
+        cmafmux.set_property("key", to_hex(&key));
+        cmafmux.set_property("key-id", to_hex(&rand::random::<[u8; 16]>()));
+        cmafmux.set_property("iv", to_hex(&iv));
+
+        Ok(())
     }
 
     fn on_init_segment(
@@ -498,16 +786,9 @@ impl HlsCmafSink {
         location: String,
         timestamp: Option<DateTime<Utc>>,
         byte_range: Option<m3u8_rs::ByteRange>,
+        map: Option<m3u8_rs::Map>,
     ) -> Result<gst::FlowSuccess, gst::FlowError> {
         let uri = base_imp!(self).get_segment_uri(&location, None);
-        let mut state = self.state.lock().unwrap();
-
-        let map = if state.new_header {
-            state.new_header = false;
-            state.init_segment.clone()
-        } else {
-            None
-        };
 
         base_imp!(self).add_segment(
             &location,
@@ -532,6 +813,9 @@ impl HlsCmafSink {
             .flags()
             .contains(gst::BufferFlags::DISCONT | gst::BufferFlags::HEADER)
         {
+            // The segment that is currently written still uses the previous init segment
+            self.finish_pending_segment()?;
+
             let mut stream = self.on_init_segment(first.size() as u64).map_err(|err| {
                 gst::error!(
                     CAT,
@@ -574,6 +858,16 @@ impl HlsCmafSink {
         let running_time = segment.to_running_time(first.pts().unwrap());
         let duration = first.duration().unwrap();
 
+        if self.settings.lock().unwrap().chunk_duration.is_some() {
+            // Chunk headers are marked as delta units, fragment headers are not
+            if !first.flags().contains(gst::BufferFlags::DELTA_UNIT) {
+                self.finish_pending_segment()?;
+                self.start_pending_segment(running_time)?;
+            }
+
+            return self.on_new_chunk(&buffer_list, running_time, duration);
+        }
+
         let (mut stream, location) = self.on_new_fragment().map_err(|err| {
             gst::error!(
                 CAT,
@@ -597,10 +891,10 @@ impl HlsCmafSink {
             gst::FlowError::Error
         })?;
 
+        let mut state = self.state.lock().unwrap();
         let byte_range = if base_imp!(self).is_single_media_file() {
             let length = buffer_list.calculate_size() as u64;
 
-            let mut state = self.state.lock().unwrap();
             let offset = Some(state.offset);
             state.offset += length;
 
@@ -608,7 +902,168 @@ impl HlsCmafSink {
         } else {
             None
         };
+        let map = state.take_new_map();
+        drop(state);
+
+        self.add_segment(duration, running_time, location, None, byte_range, map)
+    }
+
+    fn start_pending_segment(
+        &self,
+        running_time: Option<gst::ClockTime>,
+    ) -> Result<(), gst::FlowError> {
+        let mut state = self.state.lock().unwrap();
+        let idx = state.segment_idx;
+        let (stream, location) = base_imp!(self).get_fragment_stream(idx).ok_or_else(|| {
+            gst::error!(CAT, imp = self, "Couldn't get output stream for segment");
+            gst::FlowError::Error
+        })?;
+
+        state.segment_idx += 1;
+        let map = state.take_new_map();
+        state.pending_segment = Some(PendingSegment {
+            stream: HlsBaseSinkGioOutputStream::new(stream),
+            location,
+            idx,
+            running_time,
+            duration: gst::ClockTime::ZERO,
+            part_idx: 0,
+            map,
+        });
+
+        Ok(())
+    }
+
+    fn on_new_chunk(
+        &self,
+        buffer_list: &gst::BufferListRef,
+        running_time: Option<gst::ClockTime>,
+        duration: gst::ClockTime,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let (target_duration, part_location) = {
+            let settings = self.settings.lock().unwrap();
+            (
+                gst::ClockTime::from_seconds(settings.target_duration as u64),
+                settings.part_location.clone(),
+            )
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let next_segment_idx = state.segment_idx;
+        let Some(pending_segment) = state.pending_segment.as_mut() else {
+            gst::error!(CAT, imp = self, "Got chunk without segment");
+            return Err(gst::FlowError::Error);
+        };
+        let init_segment = if pending_segment.part_idx == 0 {
+            pending_segment.map.clone()
+        } else {
+            None
+        };
+
+        let location = sprintf::sprintf!(
+            &part_location,
+            pending_segment.idx,
+            pending_segment.part_idx
+        )
+        .map_err(|err| {
+            gst::error!(CAT, imp = self, "Couldn't build file name, err: {:?}", err);
+            gst::FlowError::Error
+        })?;
+        let part_stream = base_imp!(self).get_part_stream(&location).ok_or_else(|| {
+            gst::error!(CAT, imp = self, "Couldn't get output stream for part");
+            gst::FlowError::Error
+        })?;
+        let mut part_write = part_stream.clone().into_write();
+
+        let mut segment_stream = pending_segment
+            .stream
+            .upcast_ref::<gio::OutputStream>()
+            .clone()
+            .into_write();
 
-        self.add_segment(duration, running_time, location, None, byte_range)
+        for buffer in buffer_list {
+            let map = buffer.map_readable().unwrap();
+
+            part_write.write_all(&map).map_err(|_| {
+                gst::error!(CAT, imp = self, "Couldn't write part to output stream",);
+                gst::FlowError::Error
+            })?;
+            segment_stream.write_all(&map).map_err(|_| {
+                gst::error!(CAT, imp = self, "Couldn't write segment to output stream",);
+                gst::FlowError::Error
+            })?;
+        }
+
+        part_write.flush().map_err(|_| {
+            gst::error!(CAT, imp = self, "Couldn't flush output stream",);
+            gst::FlowError::Error
+        })?;
+        drop(part_write);
+        // The part is complete, unlike the segment it is part of
+        part_stream.close(gio::Cancellable::NONE).map_err(|_| {
+            gst::error!(CAT, imp = self, "Couldn't close part output stream",);
+            gst::FlowError::Error
+        })?;
+
+        let independent = pending_segment.part_idx == 0;
+        pending_segment.duration += duration;
+        pending_segment.part_idx += 1;
+
+        // The next chunk most likely starts a new segment if the target duration would be
+        // exceeded by it
+        let next_location = if pending_segment.duration + duration > target_duration {
+            sprintf::sprintf!(&part_location, next_segment_idx, 0u32)
+        } else {
+            sprintf::sprintf!(
+                &part_location,
+                pending_segment.idx,
+                pending_segment.part_idx
+            )
+        }
+        .ok();
+
+        let segment_location = pending_segment.location.clone();
+        drop(state);
+
+        let uri = base_imp!(self).get_segment_uri(&location, None);
+        let preload_hint =
+            next_location.map(|location| base_imp!(self).get_segment_uri(&location, None));
+
+        base_imp!(self).add_part(
+            &segment_location,
+            &location,
+            running_time,
+            duration,
+            PartialSegment {
+                uri,
+                duration: duration.nseconds() as f64 / 1_000_000_000f64,
+                independent,
+            },
+            init_segment,
+            preload_hint,
+        )
+    }
+
+    fn finish_pending_segment(&self) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let Some(pending_segment) = self.state.lock().unwrap().pending_segment.take() else {
+            return Ok(gst::FlowSuccess::Ok);
+        };
+
+        pending_segment
+            .stream
+            .close(gio::Cancellable::NONE)
+            .map_err(|_| {
+                gst::error!(CAT, imp = self, "Couldn't close segment output stream",);
+                gst::FlowError::Error
+            })?;
+
+        self.add_segment(
+            pending_segment.duration,
+            pending_segment.running_time,
+            pending_segment.location,
+            None,
+            None,
+            pending_segment.map,
+        )
     }
 }
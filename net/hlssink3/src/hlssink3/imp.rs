@@ -7,7 +7,7 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::hlsbasesink::HlsBaseSinkImpl;
+use crate::hlsbasesink::{scte35_pad_template, HlsBaseSinkImpl, HlsEncryptionMethod};
 use crate::hlssink3::HlsSink3PlaylistType;
 use crate::playlist::Playlist;
 use crate::HlsBaseSink;
@@ -278,6 +278,8 @@ impl ObjectImpl for HlsSink3 {
         let obj = self.obj();
         let settings = self.settings.lock().unwrap();
 
+        base_imp!(self).set_splitter(&settings.splitmuxsink);
+
         obj.add(&settings.splitmuxsink).unwrap();
         settings
             .splitmuxsink
@@ -369,7 +371,11 @@ impl ElementImpl for HlsSink3 {
             )
             .unwrap();
 
-            vec![video_pad_template, audio_pad_template]
+            vec![
+                video_pad_template,
+                audio_pad_template,
+                scte35_pad_template(),
+            ]
         });
 
         PAD_TEMPLATES.as_ref()
@@ -380,6 +386,27 @@ impl ElementImpl for HlsSink3 {
         transition: gst::StateChange,
     ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
         if transition == gst::StateChange::ReadyToPaused {
+            match base_imp!(self).encryption_method() {
+                HlsEncryptionMethod::None => (),
+                HlsEncryptionMethod::Aes128 if !base_imp!(self).is_single_media_file() => (),
+                HlsEncryptionMethod::Aes128 => {
+                    gst::element_imp_error!(
+                        self,
+                        gst::LibraryError::Settings,
+                        ["Encryption is not supported together with a single media file"]
+                    );
+                    return Err(gst::StateChangeError);
+                }
+                _ => {
+                    gst::element_imp_error!(
+                        self,
+                        gst::LibraryError::Settings,
+                        ["Only AES-128 encryption is supported for MPEG-TS segments"]
+                    );
+                    return Err(gst::StateChangeError);
+                }
+            }
+
             let (target_duration, playlist_type, i_frames_only, segment_template) = {
                 let settings = self.settings.lock().unwrap();
                 (
@@ -391,7 +418,14 @@ impl ElementImpl for HlsSink3 {
             };
 
             let playlist = self.start(target_duration, playlist_type, i_frames_only);
-            base_imp!(self).open_playlist(playlist, segment_template);
+            let next_index = base_imp!(self).open_playlist(playlist, segment_template);
+
+            // Continue the segment numbering of a resumed playlist
+            self.settings
+                .lock()
+                .unwrap()
+                .splitmuxsink
+                .set_property("start-index", next_index as i32);
         }
 
         self.parent_change_state(transition)
@@ -425,7 +459,7 @@ impl ElementImpl for HlsSink3 {
                 }
 
                 let peer_pad = settings.splitmuxsink.request_pad_simple("audio_0").unwrap();
-                let sink_pad = gst::GhostPad::from_template_with_target(templ, &peer_pad).unwrap();
+                let sink_pad = self.new_sink_pad(templ, &peer_pad);
                 self.obj().add_pad(&sink_pad).unwrap();
                 sink_pad.set_active(true).unwrap();
                 settings.audio_sink = true;
@@ -443,18 +477,19 @@ impl ElementImpl for HlsSink3 {
                 }
                 let peer_pad = settings.splitmuxsink.request_pad_simple("video").unwrap();
 
-                let sink_pad = gst::GhostPad::from_template_with_target(templ, &peer_pad).unwrap();
+                let sink_pad = self.new_sink_pad(templ, &peer_pad);
                 self.obj().add_pad(&sink_pad).unwrap();
                 sink_pad.set_active(true).unwrap();
                 settings.video_sink = true;
 
                 Some(sink_pad.upcast())
             }
+            "scte35" => base_imp!(self).request_scte35_pad(templ),
             other_name => {
                 gst::debug!(
                     CAT,
                     imp = self,
-                    "requested_new_pad: name \"{}\" is not audio or video",
+                    "requested_new_pad: name \"{}\" is not audio, video or scte35",
                     other_name
                 );
                 None
@@ -465,6 +500,12 @@ impl ElementImpl for HlsSink3 {
     fn release_pad(&self, pad: &gst::Pad) {
         let mut settings = self.settings.lock().unwrap();
 
+        if pad.name() == "scte35" {
+            pad.set_active(false).unwrap();
+            self.obj().remove_pad(pad).unwrap();
+            return;
+        }
+
         if !settings.audio_sink && !settings.video_sink {
             return;
         }
@@ -527,6 +568,21 @@ impl BinImpl for HlsSink3 {
 impl HlsBaseSinkImpl for HlsSink3 {}
 
 impl HlsSink3 {
+    fn new_sink_pad(&self, templ: &gst::PadTemplate, peer_pad: &gst::Pad) -> gst::GhostPad {
+        let sink_pad = gst::GhostPad::builder_from_template(templ)
+            .event_function(|pad, parent, event| {
+                HlsSink3::catch_panic_pad_function(
+                    parent,
+                    || false,
+                    |imp| base_imp!(imp).sink_event(pad.upcast_ref(), event),
+                )
+            })
+            .build();
+        sink_pad.set_target(Some(peer_pad)).unwrap();
+
+        sink_pad
+    }
+
     fn start(
         &self,
         target_duration: u32,
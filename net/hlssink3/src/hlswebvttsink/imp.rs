@@ -0,0 +1,734 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::hlsbasesink::{HlsBaseSinkImpl, HlsEncryptionMethod};
+use crate::hlssink3::HlsSink3PlaylistType;
+use crate::playlist::Playlist;
+use crate::HlsBaseSink;
+use gio::prelude::*;
+use gst::glib;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use m3u8_rs::{MediaPlaylist, MediaPlaylistType, MediaSegment};
+use std::collections::VecDeque;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+
+const DEFAULT_VTT_LOCATION: &str = "segment%05d.vtt";
+const DEFAULT_TARGET_DURATION: u32 = 15;
+const DEFAULT_PLAYLIST_TYPE: HlsSink3PlaylistType = HlsSink3PlaylistType::Unspecified;
+// mpegtsmux starts its timestamps one hour after running time zero
+const DEFAULT_TIMESTAMP_OFFSET: gst::ClockTime = gst::ClockTime::from_seconds(3600);
+const DEFAULT_MANUAL_SPLIT: bool = false;
+
+const SIGNAL_SPLIT_AT_RUNNING_TIME: &str = "split-at-running-time";
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "hlswebvttsink",
+        gst::DebugColorFlags::empty(),
+        Some("HLS WebVTT sink"),
+    )
+});
+
+macro_rules! base_imp {
+    ($i:expr) => {
+        $i.obj().upcast_ref::<HlsBaseSink>().imp()
+    };
+}
+
+struct HlsWebVttSinkSettings {
+    location: String,
+    target_duration: u32,
+    playlist_type: Option<MediaPlaylistType>,
+    timestamp_offset: gst::ClockTime,
+    manual_split: bool,
+
+    appsink: gst_app::AppSink,
+}
+
+impl Default for HlsWebVttSinkSettings {
+    fn default() -> Self {
+        let appsink = gst_app::AppSink::builder().sync(false).name("sink").build();
+
+        Self {
+            location: String::from(DEFAULT_VTT_LOCATION),
+            target_duration: DEFAULT_TARGET_DURATION,
+            playlist_type: None,
+            timestamp_offset: DEFAULT_TIMESTAMP_OFFSET,
+            manual_split: DEFAULT_MANUAL_SPLIT,
+            appsink,
+        }
+    }
+}
+
+/// A cue with its timing in running time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cue {
+    start: gst::ClockTime,
+    end: gst::ClockTime,
+    settings: Option<String>,
+    text: String,
+}
+
+#[derive(Default)]
+struct HlsWebVttSinkState {
+    segment_idx: u32,
+    target_duration: gst::ClockTime,
+    manual_split: bool,
+    /// Requested segment boundaries after the start of the current segment, in running time.
+    splits: VecDeque<gst::ClockTime>,
+    /// Running time at which the current segment starts.
+    segment_start: Option<gst::ClockTime>,
+    /// Running time up to which the current segment is known to be complete.
+    position: Option<gst::ClockTime>,
+    /// Cues overlapping the current segment.
+    cues: Vec<Cue>,
+}
+
+impl HlsWebVttSinkState {
+    /// Running time at which the current segment ends, if known yet.
+    fn segment_end(&self) -> Option<gst::ClockTime> {
+        if self.manual_split {
+            self.splits.front().copied()
+        } else {
+            self.segment_start
+                .map(|segment_start| segment_start + self.target_duration)
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct HlsWebVttSink {
+    settings: Mutex<HlsWebVttSinkSettings>,
+    state: Mutex<HlsWebVttSinkState>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for HlsWebVttSink {
+    const NAME: &'static str = "GstHlsWebVttSink";
+    type Type = super::HlsWebVttSink;
+    type ParentType = HlsBaseSink;
+}
+
+impl ObjectImpl for HlsWebVttSink {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecString::builder("location")
+                    .nick("File Location")
+                    .blurb("Location of the file to write")
+                    .default_value(Some(DEFAULT_VTT_LOCATION))
+                    .build(),
+                glib::ParamSpecUInt::builder("target-duration")
+                    .nick("Target duration")
+                    .blurb("The target duration in seconds of a segment/file. Unless manual-split is enabled, segments start at multiples of the target duration in running time.")
+                    .default_value(DEFAULT_TARGET_DURATION)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecEnum::builder_with_default("playlist-type", DEFAULT_PLAYLIST_TYPE)
+                    .nick("Playlist Type")
+                    .blurb("The type of the playlist to use. When VOD type is set, the playlist will be live until the pipeline ends execution.")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt64::builder("timestamp-offset")
+                    .nick("Timestamp Offset")
+                    .blurb("Offset of the MPEG-TS timestamps of the other renditions from the running time, used for X-TIMESTAMP-MAP (in nanoseconds)")
+                    .maximum(i64::MAX as u64)
+                    .default_value(DEFAULT_TIMESTAMP_OFFSET.nseconds())
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecBoolean::builder("manual-split")
+                    .nick("Manual Split")
+                    .blurb("Only start new segments at the running times requested via the split-at-running-time signal, e.g. the segment boundaries of the other renditions")
+                    .default_value(DEFAULT_MANUAL_SPLIT)
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn signals() -> &'static [glib::subclass::Signal] {
+        static SIGNALS: LazyLock<Vec<glib::subclass::Signal>> = LazyLock::new(|| {
+            vec![
+                glib::subclass::Signal::builder(SIGNAL_SPLIT_AT_RUNNING_TIME)
+                    .param_types([u64::static_type()])
+                    .action()
+                    .class_handler(|args| {
+                        let elem = args[0].get::<super::HlsWebVttSink>().expect("signal arg");
+                        let running_time = args[1].get::<u64>().expect("signal arg");
+                        let imp = elem.imp();
+
+                        imp.split_at_running_time(gst::ClockTime::from_nseconds(running_time));
+
+                        None
+                    })
+                    .build(),
+            ]
+        });
+
+        SIGNALS.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "location" => {
+                settings.location = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream")
+                    .unwrap_or_else(|| DEFAULT_VTT_LOCATION.into());
+            }
+            "target-duration" => {
+                settings.target_duration = value.get().expect("type checked upstream");
+            }
+            "playlist-type" => {
+                settings.playlist_type = value
+                    .get::<HlsSink3PlaylistType>()
+                    .expect("type checked upstream")
+                    .into();
+            }
+            "timestamp-offset" => {
+                settings.timestamp_offset =
+                    gst::ClockTime::from_nseconds(value.get().expect("type checked upstream"));
+            }
+            "manual-split" => {
+                settings.manual_split = value.get().expect("type checked upstream");
+            }
+            _ => unimplemented!(),
+        };
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "location" => settings.location.to_value(),
+            "target-duration" => settings.target_duration.to_value(),
+            "playlist-type" => {
+                let playlist_type: HlsSink3PlaylistType = settings.playlist_type.as_ref().into();
+                playlist_type.to_value()
+            }
+            "timestamp-offset" => settings.timestamp_offset.nseconds().to_value(),
+            "manual-split" => settings.manual_split.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let obj = self.obj();
+        let settings = self.settings.lock().unwrap();
+
+        obj.add(&settings.appsink).unwrap();
+
+        let sinkpad = settings.appsink.static_pad("sink").unwrap();
+        let gpad =
+            gst::GhostPad::from_template_with_target(&obj.pad_template("sink").unwrap(), &sinkpad)
+                .unwrap();
+
+        obj.add_pad(&gpad).unwrap();
+
+        sinkpad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, {
+            let self_weak = self.downgrade();
+            move |pad, info| {
+                let Some(imp) = self_weak.upgrade() else {
+                    return gst::PadProbeReturn::Ok;
+                };
+
+                if let Some(gst::PadProbeData::Event(ref event)) = info.data {
+                    imp.on_event(pad, event);
+                }
+
+                gst::PadProbeReturn::Ok
+            }
+        });
+
+        let self_weak = self.downgrade();
+        settings.appsink.set_callbacks(
+            gst_app::AppSinkCallbacks::builder()
+                .new_sample(move |sink| {
+                    let Some(imp) = self_weak.upgrade() else {
+                        return Err(gst::FlowError::Eos);
+                    };
+
+                    let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    imp.on_new_sample(sample)
+                })
+                .eos({
+                    let self_weak = self.downgrade();
+                    move |_sink| {
+                        let Some(imp) = self_weak.upgrade() else {
+                            return;
+                        };
+
+                        let _ = imp.finish_segment();
+                    }
+                })
+                .build(),
+        );
+    }
+}
+
+impl GstObjectImpl for HlsWebVttSink {}
+
+impl ElementImpl for HlsWebVttSink {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "HTTP Live Streaming WebVTT Sink",
+                "Sink/Muxer/Subtitle",
+                "HTTP Live Streaming sink for WebVTT subtitles",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let pad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &[
+                    gst::Structure::builder("application/x-subtitle-vtt").build(),
+                    gst::Structure::builder("text/x-raw")
+                        .field("format", "utf8")
+                        .build(),
+                ]
+                .into_iter()
+                .collect::<gst::Caps>(),
+            )
+            .unwrap();
+
+            vec![pad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::ReadyToPaused {
+            if base_imp!(self).is_single_media_file() {
+                gst::element_imp_error!(
+                    self,
+                    gst::LibraryError::Settings,
+                    ["A single media file is not supported for WebVTT segments"]
+                );
+                return Err(gst::StateChangeError);
+            }
+
+            if self.settings.lock().unwrap().target_duration == 0 {
+                gst::element_imp_error!(
+                    self,
+                    gst::LibraryError::Settings,
+                    ["A target duration is required for WebVTT segments"]
+                );
+                return Err(gst::StateChangeError);
+            }
+
+            match base_imp!(self).encryption_method() {
+                HlsEncryptionMethod::None | HlsEncryptionMethod::Aes128 => (),
+                _ => {
+                    gst::element_imp_error!(
+                        self,
+                        gst::LibraryError::Settings,
+                        ["Only AES-128 encryption is supported for WebVTT segments"]
+                    );
+                    return Err(gst::StateChangeError);
+                }
+            }
+
+            let (target_duration, playlist_type, manual_split, segment_template) = {
+                let settings = self.settings.lock().unwrap();
+                (
+                    settings.target_duration,
+                    settings.playlist_type.clone(),
+                    settings.manual_split,
+                    settings.location.clone(),
+                )
+            };
+
+            let playlist = self.start(target_duration, playlist_type, manual_split);
+            let next_index = base_imp!(self).open_playlist(playlist, segment_template);
+
+            self.state.lock().unwrap().segment_idx = next_index;
+        }
+
+        self.parent_change_state(transition)
+    }
+}
+
+impl BinImpl for HlsWebVttSink {}
+
+impl HlsBaseSinkImpl for HlsWebVttSink {}
+
+impl HlsWebVttSink {
+    fn start(
+        &self,
+        target_duration: u32,
+        playlist_type: Option<MediaPlaylistType>,
+        manual_split: bool,
+    ) -> Playlist {
+        gst::info!(CAT, imp = self, "Starting");
+
+        let mut state = self.state.lock().unwrap();
+        *state = HlsWebVttSinkState {
+            target_duration: gst::ClockTime::from_seconds(target_duration as u64),
+            manual_split,
+            ..Default::default()
+        };
+
+        let (turn_vod, playlist_type) = if playlist_type == Some(MediaPlaylistType::Vod) {
+            (true, Some(MediaPlaylistType::Event))
+        } else {
+            (false, playlist_type)
+        };
+
+        let playlist = MediaPlaylist {
+            version: Some(3),
+            target_duration: target_duration as u64,
+            playlist_type,
+            ..Default::default()
+        };
+
+        Playlist::new(playlist, turn_vod, false)
+    }
+
+    fn on_new_sample(&self, sample: gst::Sample) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let buffer = sample.buffer().ok_or(gst::FlowError::Error)?;
+
+        let running_time = buffer.pts().and_then(|pts| {
+            sample
+                .segment()?
+                .downcast_ref::<gst::ClockTime>()?
+                .to_running_time(pts)
+        });
+        let Some(running_time) = running_time else {
+            gst::warning!(CAT, imp = self, "Dropping cue without running time");
+            return Ok(gst::FlowSuccess::Ok);
+        };
+
+        // Segments are complete up to this cue even if it is not written, e.g. for the header
+        self.advance(running_time)?;
+
+        if buffer.flags().contains(gst::BufferFlags::HEADER) {
+            return Ok(gst::FlowSuccess::Ok);
+        }
+
+        let map = buffer.map_readable().map_err(|_| {
+            gst::error!(CAT, imp = self, "Failed to map buffer");
+            gst::FlowError::Error
+        })?;
+        let Ok(data) = std::str::from_utf8(&map) else {
+            gst::warning!(CAT, imp = self, "Dropping cue that is not valid UTF-8");
+            return Ok(gst::FlowSuccess::Ok);
+        };
+
+        let is_webvtt = sample
+            .caps()
+            .and_then(|caps| caps.structure(0))
+            .is_some_and(|s| s.name() == "application/x-subtitle-vtt");
+        let (settings, text) = if is_webvtt {
+            match parse_cue(data) {
+                Some(cue) => cue,
+                // Header or comment blocks
+                None => return Ok(gst::FlowSuccess::Ok),
+            }
+        } else {
+            (None, data.trim_end().to_string())
+        };
+
+        if text.is_empty() {
+            return Ok(gst::FlowSuccess::Ok);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let end = match buffer.duration() {
+            Some(duration) => running_time + duration,
+            // Without duration the cue is shown until the end of the segment
+            None => state
+                .segment_end()
+                .unwrap_or(running_time + state.target_duration),
+        };
+
+        gst::trace!(CAT, imp = self, "Cue from {running_time} to {end}: {text}");
+
+        state.cues.push(Cue {
+            start: running_time,
+            end,
+            settings,
+            text,
+        });
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn on_event(&self, pad: &gst::Pad, event: &gst::Event) {
+        let gst::EventView::Gap(gap) = event.view() else {
+            return;
+        };
+
+        let (timestamp, duration) = gap.get();
+        let running_time = pad
+            .sticky_event::<gst::event::Segment>(0)
+            .and_then(|segment| {
+                segment
+                    .segment()
+                    .downcast_ref::<gst::ClockTime>()?
+                    .to_running_time(timestamp + duration.unwrap_or(gst::ClockTime::ZERO))
+            });
+
+        // Segments are complete up to the end of the gap
+        if let Some(running_time) = running_time {
+            let _ = self.advance(running_time);
+        }
+    }
+
+    /// Requests a segment boundary at `running_time` in manual split mode.
+    fn split_at_running_time(&self, running_time: gst::ClockTime) {
+        let mut state = self.state.lock().unwrap();
+        if !state.manual_split {
+            gst::warning!(
+                CAT,
+                imp = self,
+                "Ignoring split request without manual-split"
+            );
+            return;
+        }
+
+        // Boundaries before the start of the current segment are already passed
+        let last = state.splits.back().copied().or(state.segment_start);
+        if last.is_some_and(|last| running_time <= last) {
+            return;
+        }
+
+        gst::debug!(CAT, imp = self, "Splitting at running time {running_time}");
+        state.splits.push_back(running_time);
+    }
+
+    /// Writes all segments that end at or before `running_time`.
+    fn advance(&self, running_time: gst::ClockTime) -> Result<gst::FlowSuccess, gst::FlowError> {
+        loop {
+            let mut state = self.state.lock().unwrap();
+            state.position = state.position.max(Some(running_time));
+
+            if state.segment_start.is_none() {
+                state.segment_start = if state.manual_split {
+                    // Start with the first segment of the other renditions
+                    state.splits.pop_front()
+                } else {
+                    // Align segments to multiples of the target duration like the other renditions
+                    let target_duration = state.target_duration.nseconds();
+                    Some(gst::ClockTime::from_nseconds(
+                        running_time.nseconds() / target_duration * target_duration,
+                    ))
+                };
+            }
+
+            let (Some(segment_start), Some(segment_end)) =
+                (state.segment_start, state.segment_end())
+            else {
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            if running_time < segment_end {
+                return Ok(gst::FlowSuccess::Ok);
+            }
+
+            let idx = state.segment_idx;
+            state.segment_idx += 1;
+            state.segment_start = Some(segment_end);
+            if state.manual_split {
+                state.splits.pop_front();
+            }
+
+            // Cues overlapping the next segment are repeated there
+            let cues = state.cues.clone();
+            state.cues.retain(|cue| cue.end > segment_end);
+            drop(state);
+
+            self.write_segment(idx, segment_start, segment_end - segment_start, &cues)?;
+        }
+    }
+
+    /// Writes the last, possibly shorter, segment.
+    fn finish_segment(&self) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let mut state = self.state.lock().unwrap();
+        let Some(segment_start) = state.segment_start else {
+            return Ok(gst::FlowSuccess::Ok);
+        };
+
+        let segment_end = state
+            .cues
+            .iter()
+            .map(|cue| cue.end)
+            .chain(state.position)
+            .max()
+            .unwrap_or(segment_start)
+            .min(
+                state
+                    .segment_end()
+                    .unwrap_or(segment_start + state.target_duration),
+            );
+        state.segment_start = None;
+        state.splits.clear();
+        if segment_end <= segment_start {
+            return Ok(gst::FlowSuccess::Ok);
+        }
+
+        let idx = state.segment_idx;
+        state.segment_idx += 1;
+        let cues = std::mem::take(&mut state.cues);
+        drop(state);
+
+        self.write_segment(idx, segment_start, segment_end - segment_start, &cues)
+    }
+
+    fn write_segment(
+        &self,
+        idx: u32,
+        running_time: gst::ClockTime,
+        duration: gst::ClockTime,
+        cues: &[Cue],
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let (stream, location) = base_imp!(self).get_fragment_stream(idx).ok_or_else(|| {
+            gst::error!(CAT, imp = self, "Couldn't get output stream for segment");
+            gst::FlowError::Error
+        })?;
+
+        let timestamp_offset = self.settings.lock().unwrap().timestamp_offset;
+        let content = webvtt_segment(cues, timestamp_offset);
+
+        stream
+            .write_all(content.as_bytes(), gio::Cancellable::NONE)
+            .map_err(|_| {
+                gst::error!(CAT, imp = self, "Couldn't write segment to output stream");
+                gst::FlowError::Error
+            })?;
+        stream.close(gio::Cancellable::NONE).map_err(|_| {
+            gst::error!(CAT, imp = self, "Couldn't close segment output stream");
+            gst::FlowError::Error
+        })?;
+
+        let uri = base_imp!(self).get_segment_uri(&location, None);
+        base_imp!(self).add_segment(
+            &location,
+            Some(running_time),
+            duration,
+            None,
+            MediaSegment {
+                uri,
+                duration: duration.mseconds() as f32 / 1_000f32,
+                ..Default::default()
+            },
+        )
+    }
+}
+
+/// Splits a WebVTT cue block into its settings and its payload.
+///
+/// Returns `None` for blocks without cue timings, like the header.
+fn parse_cue(block: &str) -> Option<(Option<String>, String)> {
+    let mut lines = block.lines();
+
+    // The timings might be preceded by a cue identifier
+    let timings = lines.by_ref().find(|line| line.contains("-->"))?;
+    let (_, end) = timings.split_once("-->")?;
+    let settings = end
+        .trim()
+        .split_once(char::is_whitespace)
+        .map(|(_, settings)| settings.trim().to_string())
+        .filter(|settings| !settings.is_empty());
+
+    let text = lines.collect::<Vec<_>>().join("\n").trim_end().to_string();
+
+    Some((settings, text))
+}
+
+fn vtt_timestamp(time: gst::ClockTime) -> String {
+    let ms = time.mseconds();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1_000 % 60,
+        ms % 1_000
+    )
+}
+
+/// Creates a WebVTT segment with the cue timings in running time.
+///
+/// `X-TIMESTAMP-MAP` maps running time zero to the 33 bit MPEG-TS timestamp of the other
+/// renditions.
+fn webvtt_segment(cues: &[Cue], timestamp_offset: gst::ClockTime) -> String {
+    let mpegts =
+        (timestamp_offset.nseconds() as u128 * 90_000 / 1_000_000_000) as u64 & ((1 << 33) - 1);
+
+    let mut content = format!("WEBVTT\nX-TIMESTAMP-MAP=MPEGTS:{mpegts},LOCAL:00:00:00.000\n");
+    for cue in cues {
+        content.push_str(&format!(
+            "\n{} --> {}",
+            vtt_timestamp(cue.start),
+            vtt_timestamp(cue.end)
+        ));
+        if let Some(ref settings) = cue.settings {
+            content.push_str(&format!(" {settings}"));
+        }
+        content.push_str(&format!("\n{}\n", cue.text));
+    }
+
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cue() {
+        assert_eq!(parse_cue("WEBVTT\n\n"), None);
+        assert_eq!(
+            parse_cue("00:00:01.000 --> 00:00:02.500\nHello\nWorld\n"),
+            Some((None, String::from("Hello\nWorld")))
+        );
+        assert_eq!(
+            parse_cue("1\n00:00:01.000 --> 00:00:02.500 line:0 align:start\nHello\n\n"),
+            Some((
+                Some(String::from("line:0 align:start")),
+                String::from("Hello")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_webvtt_segment() {
+        let cues = [Cue {
+            start: gst::ClockTime::from_mseconds(3_723_456),
+            end: gst::ClockTime::from_mseconds(3_725_000),
+            settings: Some(String::from("align:start")),
+            text: String::from("Hello"),
+        }];
+
+        assert_eq!(
+            webvtt_segment(&cues, DEFAULT_TIMESTAMP_OFFSET),
+            "WEBVTT\nX-TIMESTAMP-MAP=MPEGTS:324000000,LOCAL:00:00:00.000\n\n\
+             01:02:03.456 --> 01:02:05.000 align:start\nHello\n"
+        );
+        assert_eq!(
+            webvtt_segment(&[], gst::ClockTime::ZERO),
+            "WEBVTT\nX-TIMESTAMP-MAP=MPEGTS:0,LOCAL:00:00:00.000\n"
+        );
+    }
+}
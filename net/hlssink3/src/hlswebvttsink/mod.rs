@@ -0,0 +1,44 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+#![allow(clippy::non_send_fields_in_send_ty, unused_doc_comments)]
+
+use crate::HlsBaseSink;
+/**
+ * element-hlswebvttsink:
+ *
+ * HLS sink for WebVTT subtitle renditions.
+ *
+ * Cues are collected into WebVTT segments that start at multiples of the target duration in
+ * running time, so that the segments line up with the segments of the video renditions.
+ * With `manual-split`, segments instead start at the running times requested via the
+ * `split-at-running-time` action signal, e.g. the segment boundaries of the main rendition.
+ * Each segment has an `X-TIMESTAMP-MAP` header that maps the running time of the cues to the
+ * MPEG-TS timestamps of the other renditions. Cues spanning a segment boundary are repeated in
+ * every segment they overlap, and empty segments are written for gaps.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::glib;
+use gst::prelude::*;
+
+mod imp;
+
+glib::wrapper! {
+    pub struct HlsWebVttSink(ObjectSubclass<imp::HlsWebVttSink>) @extends HlsBaseSink, gst::Bin, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "hlswebvttsink",
+        gst::Rank::NONE,
+        HlsWebVttSink::static_type(),
+    )?;
+
+    Ok(())
+}
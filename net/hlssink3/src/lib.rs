@@ -14,10 +14,13 @@
  */
 use gst::glib;
 
+mod encryption;
 mod hlsbasesink;
 pub mod hlscmafsink;
 pub mod hlssink3;
+pub mod hlswebvttsink;
 mod playlist;
+mod scte35;
 
 glib::wrapper! {
     pub struct HlsBaseSinkGioOutputStream(ObjectSubclass<hlsbasesink::HlsBaseSinkGioOutputStream>) @extends gio::OutputStream;
@@ -37,10 +40,13 @@ pub fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
         HlsBaseSink::static_type().mark_as_plugin_api(gst::PluginAPIFlags::empty());
         hlsbasesink::HlsProgramDateTimeReference::static_type()
             .mark_as_plugin_api(gst::PluginAPIFlags::empty());
+        hlsbasesink::HlsEncryptionMethod::static_type()
+            .mark_as_plugin_api(gst::PluginAPIFlags::empty());
     }
 
     hlssink3::register(plugin)?;
     hlscmafsink::register(plugin)?;
+    hlswebvttsink::register(plugin)?;
 
     Ok(())
 }
@@ -6,9 +6,117 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use m3u8_rs::{MediaPlaylist, MediaPlaylistType, MediaSegment};
+use chrono::{DateTime, SecondsFormat, Utc};
+use m3u8_rs::{ExtTag, MediaPlaylist, MediaPlaylistType, MediaSegment};
+use std::collections::VecDeque;
+use std::fmt;
 use std::io::Write;
 
+/// Tags that only apply to the live edge of a low-latency playlist and are dropped when
+/// resuming an existing playlist.
+const LOW_LATENCY_TAGS: &[&str] = &[
+    "X-PART",
+    "X-PART-INF",
+    "X-PRELOAD-HINT",
+    "X-RENDITION-REPORT",
+    "X-SERVER-CONTROL",
+];
+
+/// A partial segment of a low-latency HLS playlist, written as `EXT-X-PART`.
+#[derive(Debug, Clone)]
+pub struct PartialSegment {
+    pub uri: String,
+    /// Duration in seconds.
+    pub duration: f64,
+    pub independent: bool,
+}
+
+impl fmt::Display for PartialSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DURATION={:.5},URI=\"{}\"", self.duration, self.uri)?;
+        if self.independent {
+            write!(f, ",INDEPENDENT=YES")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A date range signalling a SCTE-35 splice point, written as `EXT-X-DATERANGE`.
+#[derive(Debug, Clone)]
+pub struct DateRange {
+    pub id: String,
+    pub start_date: DateTime<Utc>,
+    pub end_date: Option<DateTime<Utc>>,
+    /// Planned duration in seconds.
+    pub planned_duration: Option<f64>,
+    /// `splice_info_section` that started the date range.
+    pub scte35_out: Option<Vec<u8>>,
+    /// `splice_info_section` that ended the date range.
+    pub scte35_in: Option<Vec<u8>>,
+}
+
+impl fmt::Display for DateRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let to_hex = |data: &[u8]| {
+            data.iter()
+                .fold(String::from("0x"), |acc, b| acc + &format!("{b:02X}"))
+        };
+
+        write!(
+            f,
+            "ID=\"{}\",START-DATE=\"{}\"",
+            self.id,
+            self.start_date.to_rfc3339_opts(SecondsFormat::Millis, true)
+        )?;
+        if let Some(end_date) = self.end_date {
+            write!(
+                f,
+                ",END-DATE=\"{}\",DURATION={:.3}",
+                end_date.to_rfc3339_opts(SecondsFormat::Millis, true),
+                (end_date - self.start_date).num_milliseconds() as f64 / 1_000.0
+            )?;
+        }
+        if let Some(planned_duration) = self.planned_duration {
+            write!(f, ",PLANNED-DURATION={planned_duration:.3}")?;
+        }
+        if let Some(ref scte35_out) = self.scte35_out {
+            write!(f, ",SCTE35-OUT={}", to_hex(scte35_out))?;
+        }
+        if let Some(ref scte35_in) = self.scte35_in {
+            write!(f, ",SCTE35-IN={}", to_hex(scte35_in))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Position of the last partial segment of another rendition, written as
+/// `EXT-X-RENDITION-REPORT`.
+#[derive(Debug, Clone)]
+pub struct RenditionReport {
+    pub uri: String,
+    pub last_msn: u64,
+    pub last_part: Option<u32>,
+}
+
+/// Low-latency HLS state of a playlist.
+#[derive(Debug, Clone)]
+struct LowLatency {
+    /// Part target duration in seconds.
+    part_target: f64,
+    /// Whether the server supports blocking playlist reloads.
+    can_block_reload: bool,
+    /// Partial segments of the last complete segments of the playlist, oldest first.
+    segment_parts: VecDeque<Vec<PartialSegment>>,
+    /// Partial segments of the segment that is currently being written.
+    parts: Vec<PartialSegment>,
+    /// Initialization section of the segment that is currently being written, if it changed.
+    map: Option<m3u8_rs::Map>,
+    preload_hint: Option<String>,
+    rendition_reports: Vec<RenditionReport>,
+}
+
 /// An HLS playlist.
 ///
 /// Controls the changes that needs to happen in the playlist as new segments are added. This
@@ -21,6 +129,9 @@ pub struct Playlist {
     status: PlaylistRenderState,
     turn_vod: bool,
     is_cmaf: bool,
+    low_latency: Option<LowLatency>,
+    /// Whether the next segment starts a discontinuity.
+    discontinuity: bool,
 }
 
 impl Playlist {
@@ -31,13 +142,145 @@ impl Playlist {
             status: PlaylistRenderState::Init,
             turn_vod,
             is_cmaf,
+            low_latency: None,
+            discontinuity: false,
         }
     }
 
+    /// Continues an existing playlist, e.g. after a restart.
+    ///
+    /// The segments of the existing playlist are kept and the media sequence continues after
+    /// them. The next segment is marked as a discontinuity.
+    pub fn resume(&mut self, mut existing: MediaPlaylist) {
+        for segment in &mut existing.segments {
+            segment
+                .unknown_tags
+                .retain(|tag| !LOW_LATENCY_TAGS.contains(&tag.tag.as_str()));
+        }
+
+        self.playlist_index = existing.media_sequence + existing.segments.len() as u64;
+        self.inner.media_sequence = existing.media_sequence;
+        self.inner.discontinuity_sequence = existing.discontinuity_sequence;
+        // Existing segments might be longer than the configured target duration
+        self.inner.target_duration = self.inner.target_duration.max(existing.target_duration);
+        self.inner.segments = existing.segments;
+        self.discontinuity = !self.inner.segments.is_empty();
+    }
+
+    /// Returns the media sequence number of the next segment.
+    pub fn next_media_sequence(&self) -> u64 {
+        self.playlist_index
+    }
+
+    /// Enables low-latency HLS with partial segments of the given target duration in seconds.
+    ///
+    /// `can_block_reload` announces that the server that serves the playlist supports blocking
+    /// playlist reloads, which the sink can't provide itself.
+    pub fn enable_low_latency(&mut self, part_target: f64, can_block_reload: bool) {
+        self.low_latency = Some(LowLatency {
+            part_target,
+            can_block_reload,
+            segment_parts: VecDeque::new(),
+            parts: Vec::new(),
+            map: None,
+            preload_hint: None,
+            rendition_reports: Vec::new(),
+        });
+    }
+
     /// Adds a new segment to the playlist.
-    pub fn add_segment(&mut self, segment: MediaSegment) {
+    ///
+    /// In low-latency mode all partial segments added since the previous segment become part
+    /// of this segment.
+    pub fn add_segment(&mut self, mut segment: MediaSegment) {
         self.start();
+        if std::mem::take(&mut self.discontinuity) {
+            segment.discontinuity = true;
+        }
         self.inner.segments.push(segment);
+
+        let Some(ll) = self.low_latency.as_mut() else {
+            return;
+        };
+
+        ll.map = None;
+        ll.segment_parts.push_back(std::mem::take(&mut ll.parts));
+
+        // Partial segments are only kept for the segments within three target durations
+        // from the end of the playlist
+        let limit = 3.0 * (self.inner.target_duration as f64).max(ll.part_target);
+        let mut duration = 0.0;
+        let mut keep = 0;
+        for segment in self
+            .inner
+            .segments
+            .iter()
+            .rev()
+            .take(ll.segment_parts.len())
+        {
+            if duration >= limit {
+                break;
+            }
+            duration += segment.duration as f64;
+            keep += 1;
+        }
+
+        while ll.segment_parts.len() > keep {
+            ll.segment_parts.pop_front();
+        }
+    }
+
+    /// Adds a new partial segment of the segment that is currently being written.
+    ///
+    /// `map` is the initialization section of the segment if it differs from the one of the
+    /// previous segment, and `preload_hint` the URI of the next partial segment.
+    pub fn add_part(
+        &mut self,
+        part: PartialSegment,
+        map: Option<m3u8_rs::Map>,
+        preload_hint: Option<String>,
+    ) {
+        self.start();
+
+        let ll = self
+            .low_latency
+            .as_mut()
+            .expect("low-latency mode not enabled");
+        if ll.parts.is_empty() {
+            ll.map = map;
+        }
+        ll.parts.push(part);
+        ll.preload_hint = preload_hint;
+    }
+
+    /// Returns the media sequence number and index of the last partial segment.
+    pub fn last_part(&self) -> Option<(u64, u32)> {
+        let ll = self.low_latency.as_ref()?;
+        let msn = self.inner.media_sequence + self.inner.segments.len() as u64;
+
+        if !ll.parts.is_empty() {
+            Some((msn, ll.parts.len() as u32 - 1))
+        } else {
+            let parts = ll.segment_parts.back()?;
+            Some((msn.checked_sub(1)?, (parts.len() as u32).checked_sub(1)?))
+        }
+    }
+
+    /// Sets the `EXT-X-RENDITION-REPORT` for the rendition with the same URI.
+    pub fn set_rendition_report(&mut self, report: RenditionReport) {
+        let Some(ll) = self.low_latency.as_mut() else {
+            return;
+        };
+
+        if let Some(existing) = ll
+            .rendition_reports
+            .iter_mut()
+            .find(|existing| existing.uri == report.uri)
+        {
+            *existing = report;
+        } else {
+            ll.rendition_reports.push(report);
+        }
     }
 
     /// Updates the playlist based on current state.
@@ -58,13 +301,29 @@ impl Playlist {
                 // or in case of the very first segment.
                 while self.inner.segments.len() > max_playlist_length {
                     let to_remove = self.inner.segments.remove(0);
+                    if to_remove.discontinuity {
+                        self.inner.discontinuity_sequence += 1;
+                    }
                     if self.inner.segments[0].map.is_none() {
                         self.inner.segments[0].map.clone_from(&to_remove.map)
                     }
+                    if self.inner.segments[0].key.is_none() {
+                        self.inner.segments[0].key.clone_from(&to_remove.key)
+                    }
                 }
             } else if self.inner.segments.len() > max_playlist_length {
                 let remove_len = self.inner.segments.len() - max_playlist_length;
-                self.inner.segments.drain(0..remove_len);
+                // The key is only given when it changes, so keep the one of the new first segment
+                let key = self.inner.segments[..=remove_len]
+                    .iter()
+                    .rev()
+                    .find_map(|segment| segment.key.clone());
+                for segment in self.inner.segments.drain(0..remove_len) {
+                    if segment.discontinuity {
+                        self.inner.discontinuity_sequence += 1;
+                    }
+                }
+                self.inner.segments[0].key = key;
             }
         }
 
@@ -80,6 +339,14 @@ impl Playlist {
 
     /// Sets the playlist to stopped state.
     pub fn stop(&mut self, write_endlist: bool) {
+        if let Some(ll) = self.low_latency.as_mut() {
+            // Partial segments of an unfinished segment can't be referenced anymore
+            ll.parts.clear();
+            ll.map = None;
+            ll.preload_hint = None;
+            ll.rendition_reports.clear();
+        }
+
         self.inner.end_list = write_endlist;
         if self.turn_vod {
             self.inner.playlist_type = Some(MediaPlaylistType::Vod);
@@ -103,7 +370,63 @@ impl Playlist {
 
     /// Writes the playlist in textual format to the provided `Write` reference.
     pub fn write_to<T: Write>(&self, w: &mut T) -> std::io::Result<()> {
-        self.inner.write_to(w)
+        let Some(ll) = self.low_latency.as_ref() else {
+            return self.inner.write_to(w);
+        };
+
+        // Partial segments of complete segments are written right before their parent segment
+        let mut playlist = self.inner.clone();
+        for (segment, parts) in playlist
+            .segments
+            .iter_mut()
+            .rev()
+            .zip(ll.segment_parts.iter().rev())
+        {
+            segment.unknown_tags.extend(parts.iter().map(|part| ExtTag {
+                tag: String::from("X-PART"),
+                rest: Some(part.to_string()),
+            }));
+        }
+
+        let mut content = Vec::new();
+        playlist.write_to(&mut content)?;
+        let content = String::from_utf8(content).map_err(std::io::Error::other)?;
+
+        for line in content.lines() {
+            writeln!(w, "{line}")?;
+
+            if line.starts_with("#EXT-X-TARGETDURATION:") {
+                write!(w, "#EXT-X-SERVER-CONTROL:")?;
+                if ll.can_block_reload {
+                    write!(w, "CAN-BLOCK-RELOAD=YES,")?;
+                }
+                writeln!(w, "PART-HOLD-BACK={:.5}", 3.0 * ll.part_target)?;
+                writeln!(w, "#EXT-X-PART-INF:PART-TARGET={:.5}", ll.part_target)?;
+            }
+        }
+
+        if let Some(ref map) = ll.map {
+            writeln!(w, "#EXT-X-MAP:URI=\"{}\"", map.uri)?;
+        }
+        for part in &ll.parts {
+            writeln!(w, "#EXT-X-PART:{part}")?;
+        }
+        if let Some(ref preload_hint) = ll.preload_hint {
+            writeln!(w, "#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"{preload_hint}\"")?;
+        }
+        for report in &ll.rendition_reports {
+            write!(
+                w,
+                "#EXT-X-RENDITION-REPORT:URI=\"{}\",LAST-MSN={}",
+                report.uri, report.last_msn
+            )?;
+            if let Some(last_part) = report.last_part {
+                write!(w, ",LAST-PART={last_part}")?;
+            }
+            writeln!(w)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -0,0 +1,415 @@
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Minimal SCTE-35 `splice_info_section` parsing for signalling splice points in playlists.
+
+use gst::glib;
+
+const TABLE_ID: u8 = 0xfc;
+const SPLICE_INSERT: u8 = 0x05;
+const TIME_SIGNAL: u8 = 0x06;
+const SEGMENTATION_DESCRIPTOR: u8 = 0x02;
+
+/// What happens at a splice point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpliceCommand {
+    /// Start of a break, e.g. for an ad, with its planned duration if known. If `auto_return`
+    /// is set then the break ends after the duration without a separate splice event.
+    Out {
+        duration: Option<gst::ClockTime>,
+        auto_return: bool,
+    },
+    /// End of a break.
+    In,
+    /// Cancels a previously signalled splice event that did not happen yet.
+    Cancel,
+}
+
+/// A splice event parsed from a `splice_info_section`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpliceInfo {
+    pub event_id: u32,
+    pub command: SpliceCommand,
+    /// The complete `splice_info_section`.
+    pub section: Vec<u8>,
+}
+
+/// Custom downstream event carrying a SCTE-35 `splice_info_section` together with the running
+/// time of its splice point.
+#[derive(Debug, Clone)]
+pub struct Scte35Event {
+    pub running_time: gst::ClockTime,
+    pub section: gst::Buffer,
+}
+
+impl Scte35Event {
+    pub fn try_parse(
+        event: &gst::event::CustomDownstream,
+    ) -> Option<Result<Self, glib::BoolError>> {
+        let s = event.structure()?;
+        if s.name() != "HlsSinkScte35" {
+            return None;
+        }
+
+        let running_time = match s.get::<gst::ClockTime>("running-time") {
+            Ok(running_time) => running_time,
+            Err(err) => {
+                return Some(Err(glib::bool_error!(
+                    "Invalid SCTE-35 event with wrong running-time field: {err}"
+                )))
+            }
+        };
+        let section = match s.get::<gst::Buffer>("section") {
+            Ok(section) => section,
+            Err(err) => {
+                return Some(Err(glib::bool_error!(
+                    "Invalid SCTE-35 event with wrong section field: {err}"
+                )))
+            }
+        };
+
+        Some(Ok(Scte35Event {
+            running_time,
+            section,
+        }))
+    }
+}
+
+/// Converts 90kHz ticks to a time.
+fn ticks_to_time(ticks: u64) -> gst::ClockTime {
+    gst::ClockTime::from_nseconds(ticks * 100_000 / 9)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], glib::BoolError> {
+        if self.data.len() < len {
+            return Err(glib::bool_error!("Truncated splice_info_section"));
+        }
+
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, glib::BoolError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, glib::BoolError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, glib::BoolError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Reads a 33 bit 90kHz timestamp or duration that follows 7 other bits.
+    fn time_90khz(&mut self) -> Result<gst::ClockTime, glib::BoolError> {
+        let high = (self.u8()? & 0x01) as u64;
+        let ticks = (high << 32) | self.u32()? as u64;
+        Ok(ticks_to_time(ticks))
+    }
+
+    /// Skips a `splice_time()`.
+    fn skip_splice_time(&mut self) -> Result<(), glib::BoolError> {
+        let time_specified = self.data.first().is_some_and(|b| b & 0x80 != 0);
+        self.take(if time_specified { 5 } else { 1 })?;
+        Ok(())
+    }
+}
+
+/// Parses a `splice_info_section`.
+///
+/// Returns `None` for commands that don't signal the start or end of a break, e.g.
+/// `splice_null()` or a `time_signal()` without segmentation descriptor.
+pub fn parse_splice_info_section(data: &[u8]) -> Result<Option<SpliceInfo>, glib::BoolError> {
+    let mut r = Reader { data };
+
+    if r.u8()? != TABLE_ID {
+        return Err(glib::bool_error!("Not a splice_info_section"));
+    }
+    let section_length = (r.u16()? & 0x0fff) as usize;
+    let mut r = Reader {
+        data: r.take(section_length)?,
+    };
+
+    let _protocol_version = r.u8()?;
+    if r.u8()? & 0x80 != 0 {
+        return Err(glib::bool_error!("Encrypted splice_info_section"));
+    }
+    // pts_adjustment, cw_index
+    r.take(5)?;
+    let tier_and_length = r.take(3)?;
+    let command_length =
+        (((tier_and_length[1] & 0x0f) as usize) << 8) | tier_and_length[2] as usize;
+    let command_type = r.u8()?;
+
+    // The command length is unknown in legacy sections
+    match command_type {
+        SPLICE_INSERT => {
+            let command = if command_length == 0xfff {
+                Reader { data: r.data }
+            } else {
+                Reader {
+                    data: r.take(command_length)?,
+                }
+            };
+            parse_splice_insert(command, data).map(Some)
+        }
+        TIME_SIGNAL => {
+            if command_length == 0xfff {
+                r.skip_splice_time()?;
+            } else {
+                r.take(command_length)?;
+            }
+            parse_segmentation_descriptors(r, data)
+        }
+        _ => Ok(None),
+    }
+}
+
+fn parse_splice_insert(mut r: Reader, section: &[u8]) -> Result<SpliceInfo, glib::BoolError> {
+    let event_id = r.u32()?;
+    if r.u8()? & 0x80 != 0 {
+        return Ok(SpliceInfo {
+            event_id,
+            command: SpliceCommand::Cancel,
+            section: section.to_vec(),
+        });
+    }
+
+    let flags = r.u8()?;
+    let out_of_network = flags & 0x80 != 0;
+    let program_splice = flags & 0x40 != 0;
+    let has_duration = flags & 0x20 != 0;
+    let splice_immediate = flags & 0x10 != 0;
+
+    if program_splice && !splice_immediate {
+        r.skip_splice_time()?;
+    }
+    if !program_splice {
+        let component_count = r.u8()?;
+        for _ in 0..component_count {
+            let _component_tag = r.u8()?;
+            if !splice_immediate {
+                r.skip_splice_time()?;
+            }
+        }
+    }
+
+    let command = if out_of_network {
+        let (duration, auto_return) = if has_duration {
+            let auto_return = r.data.first().is_some_and(|b| b & 0x80 != 0);
+            (Some(r.time_90khz()?), auto_return)
+        } else {
+            (None, false)
+        };
+
+        SpliceCommand::Out {
+            duration,
+            auto_return,
+        }
+    } else {
+        SpliceCommand::In
+    };
+
+    Ok(SpliceInfo {
+        event_id,
+        command,
+        section: section.to_vec(),
+    })
+}
+
+/// Parses the first segmentation descriptor that starts or ends a break.
+fn parse_segmentation_descriptors(
+    mut r: Reader,
+    section: &[u8],
+) -> Result<Option<SpliceInfo>, glib::BoolError> {
+    let descriptor_loop_length = r.u16()? as usize;
+    let mut r = Reader {
+        data: r.take(descriptor_loop_length)?,
+    };
+
+    while !r.data.is_empty() {
+        let tag = r.u8()?;
+        let length = r.u8()? as usize;
+        let mut d = Reader {
+            data: r.take(length)?,
+        };
+
+        if tag != SEGMENTATION_DESCRIPTOR || d.u32()? != u32::from_be_bytes(*b"CUEI") {
+            continue;
+        }
+
+        let event_id = d.u32()?;
+        if d.u8()? & 0x80 != 0 {
+            return Ok(Some(SpliceInfo {
+                event_id,
+                command: SpliceCommand::Cancel,
+                section: section.to_vec(),
+            }));
+        }
+
+        let flags = d.u8()?;
+        let program_segmentation = flags & 0x80 != 0;
+        let has_duration = flags & 0x40 != 0;
+
+        if !program_segmentation {
+            let component_count = d.u8()? as usize;
+            d.take(component_count * 6)?;
+        }
+        let duration = if has_duration {
+            let ticks = d
+                .take(5)?
+                .iter()
+                .fold(0u64, |acc, b| (acc << 8) | *b as u64);
+            Some(ticks_to_time(ticks))
+        } else {
+            None
+        };
+        let _upid_type = d.u8()?;
+        let upid_length = d.u8()? as usize;
+        d.take(upid_length)?;
+        let type_id = d.u8()?;
+
+        let command = match type_id {
+            // Break, provider / distributor advertisement and placement opportunity starts
+            0x22 | 0x30 | 0x32 | 0x34 | 0x36 => SpliceCommand::Out {
+                duration,
+                auto_return: false,
+            },
+            // ... and their ends
+            0x23 | 0x31 | 0x33 | 0x35 | 0x37 => SpliceCommand::In,
+            _ => continue,
+        };
+
+        return Ok(Some(SpliceInfo {
+            event_id,
+            command,
+            section: section.to_vec(),
+        }));
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wraps a splice command and descriptors into a `splice_info_section`. The CRC is not
+    /// checked and left as zero.
+    fn section(command_type: u8, command: &[u8], descriptors: &[u8]) -> Vec<u8> {
+        let mut body = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        body.extend([0xff, 0xf0 | (command.len() >> 8) as u8, command.len() as u8]);
+        body.push(command_type);
+        body.extend(command);
+        body.extend((descriptors.len() as u16).to_be_bytes());
+        body.extend(descriptors);
+        body.extend([0u8; 4]);
+
+        let mut data = vec![TABLE_ID, 0x30 | (body.len() >> 8) as u8, body.len() as u8];
+        data.extend(body);
+        data
+    }
+
+    #[test]
+    fn test_splice_insert() {
+        // Out of network, program splice at PTS 0x1_0000_0000 with an auto-return break of
+        // 30s
+        let command = [
+            0x00, 0x00, 0x00, 0x2a, 0x7f, 0xef, 0xff, 0x00, 0x00, 0x00, 0x00, 0xfe, 0x00, 0x29,
+            0x32, 0xe0, 0x00, 0x01, 0x00, 0x00,
+        ];
+        let data = section(SPLICE_INSERT, &command, &[]);
+        let info = parse_splice_info_section(&data).unwrap().unwrap();
+        assert_eq!(
+            info,
+            SpliceInfo {
+                event_id: 42,
+                command: SpliceCommand::Out {
+                    duration: Some(gst::ClockTime::from_seconds(30)),
+                    auto_return: true,
+                },
+                section: data.clone(),
+            }
+        );
+
+        // Return to network immediately
+        let command = [0x00, 0x00, 0x00, 0x2a, 0x7f, 0x5f, 0x00, 0x01, 0x00, 0x00];
+        let data = section(SPLICE_INSERT, &command, &[]);
+        let info = parse_splice_info_section(&data).unwrap().unwrap();
+        assert_eq!(info.event_id, 42);
+        assert_eq!(info.command, SpliceCommand::In);
+
+        // Cancelled
+        let command = [0x00, 0x00, 0x00, 0x2a, 0xff];
+        let data = section(SPLICE_INSERT, &command, &[]);
+        let info = parse_splice_info_section(&data).unwrap().unwrap();
+        assert_eq!(info.command, SpliceCommand::Cancel);
+    }
+
+    #[test]
+    fn test_time_signal() {
+        let command = [0xfe, 0x00, 0x00, 0x00, 0x00];
+
+        // Provider placement opportunity start of 10s without UPID
+        let descriptor = [
+            SEGMENTATION_DESCRIPTOR,
+            0x14,
+            b'C',
+            b'U',
+            b'E',
+            b'I',
+            0x00,
+            0x00,
+            0x00,
+            0x07,
+            0x7f,
+            0xff,
+            0x00,
+            0x00,
+            0x0d,
+            0xbb,
+            0xa0,
+            0x00,
+            0x00,
+            0x34,
+            0x00,
+            0x00,
+        ];
+        let data = section(TIME_SIGNAL, &command, &descriptor);
+        let info = parse_splice_info_section(&data).unwrap().unwrap();
+        assert_eq!(info.event_id, 7);
+        assert_eq!(
+            info.command,
+            SpliceCommand::Out {
+                duration: Some(gst::ClockTime::from_seconds(10)),
+                auto_return: false,
+            }
+        );
+
+        // Without descriptor there's no break
+        let data = section(TIME_SIGNAL, &command, &[]);
+        assert_eq!(parse_splice_info_section(&data).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(parse_splice_info_section(&[]).is_err());
+        assert!(parse_splice_info_section(&[0x00, 0x30, 0x00]).is_err());
+
+        let mut data = section(SPLICE_INSERT, &[0x00, 0x00, 0x00, 0x2a, 0xff], &[]);
+        data.truncate(10);
+        assert!(parse_splice_info_section(&data).is_err());
+    }
+}
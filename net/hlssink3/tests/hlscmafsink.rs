@@ -236,3 +236,183 @@ fn test_hlscmafsink_video_with_single_media_file() -> Result<(), ()> {
 
     Ok(())
 }
+
+#[test]
+fn test_hlscmafsink_low_latency() -> Result<(), ()> {
+    init();
+
+    let pipeline = gst::Pipeline::with_name("video_pipeline");
+
+    let video_src = gst::ElementFactory::make("videotestsrc")
+        .property("is-live", true)
+        .property("num-buffers", 300i32)
+        .build()
+        .unwrap();
+
+    let caps = gst::Caps::builder("video/x-raw")
+        .field("width", 320)
+        .field("height", 240)
+        .field("format", "I420")
+        .field("framerate", gst::Fraction::new(30, 1))
+        .build();
+    let capsfilter = gst::ElementFactory::make("capsfilter")
+        .property("caps", caps)
+        .build()
+        .expect("Must be able to instantiate capsfilter");
+    let x264enc = gst::ElementFactory::make("x264enc")
+        .property("key-int-max", 60u32)
+        .property("bitrate", 2000u32)
+        .property_from_str("speed-preset", "ultrafast")
+        .property_from_str("tune", "zerolatency")
+        .build()
+        .expect("Must be able to instantiate x264enc");
+    let h264parse = gst::ElementFactory::make("h264parse").build().unwrap();
+
+    let hlscmafsink = gst::ElementFactory::make("hlscmafsink")
+        .name("test_hlscmafsink")
+        .property("target-duration", 2u32)
+        .property("chunk-duration", gst::ClockTime::from_mseconds(500))
+        .property("playlist-length", 0u32)
+        .build()
+        .expect("Must be able to instantiate hlscmafsink");
+
+    let playlists = Arc::new(Mutex::new(Vec::<String>::new()));
+    let playlist_content = Arc::new(Mutex::new(String::from("")));
+    let fragment_locations = Arc::new(Mutex::new(Vec::<String>::new()));
+    let part_streams = Arc::new(Mutex::new(Vec::<gio::MemoryOutputStream>::new()));
+
+    hlscmafsink.connect("get-playlist-stream", false, {
+        let playlists = playlists.clone();
+        let playlist_content = playlist_content.clone();
+        move |_args| {
+            let playlist = MemoryPlaylistFile {
+                handler: Arc::clone(&playlist_content),
+            };
+
+            // Keep all intermediate playlists around for checking them later
+            let previous = playlist_content.lock().unwrap().clone();
+            if !previous.is_empty() {
+                playlists.lock().unwrap().push(previous);
+            }
+
+            playlist.clear_content();
+            let output = gio::WriteOutputStream::new(playlist);
+            Some(output.to_value())
+        }
+    });
+
+    hlscmafsink.connect("get-fragment-stream", false, {
+        let fragment_locations = fragment_locations.clone();
+        let part_streams = part_streams.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            let is_part = location.matches('.').count() == 2;
+            fragment_locations.lock().unwrap().push(location);
+
+            let stream = gio::MemoryOutputStream::new_resizable();
+            if is_part {
+                part_streams.lock().unwrap().push(stream.clone());
+            }
+            Some(stream.to_value())
+        }
+    });
+
+    pipeline
+        .add_many([&video_src, &capsfilter, &x264enc, &h264parse, &hlscmafsink])
+        .unwrap();
+    gst::Element::link_many([&video_src, &capsfilter, &x264enc, &h264parse, &hlscmafsink]).unwrap();
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+
+    let mut parts = Vec::new();
+    let mut segments = Vec::new();
+    let mut eos = false;
+    let bus = pipeline.bus().unwrap();
+    while let Some(msg) = bus.timed_pop(gst::ClockTime::NONE) {
+        use gst::MessageView;
+        match msg.view() {
+            MessageView::Eos(..) => {
+                eos = true;
+                break;
+            }
+            MessageView::Element(msg) => {
+                if let Some(structure) = msg.structure() {
+                    if structure.has_name("hls-part-added") {
+                        parts.push((
+                            structure.get::<String>("location").unwrap(),
+                            structure.get::<u64>("media-sequence").unwrap(),
+                            structure.get::<u32>("part-index").unwrap(),
+                        ));
+                    } else if structure.has_name("hls-segment-added") {
+                        segments.push(structure.get::<String>("location").unwrap());
+                    }
+                }
+            }
+            MessageView::Error(err) => panic!("{err}"),
+            _ => (),
+        }
+    }
+
+    pipeline.set_state(gst::State::Null).unwrap();
+    assert!(eos);
+
+    assert_eq!(
+        segments,
+        (0..5)
+            .map(|idx| format!("segment{idx:05}.m4s"))
+            .collect::<Vec<_>>()
+    );
+
+    // Every segment of 2s consists of 4 parts of 500ms
+    let expected_parts = (0..5u64)
+        .flat_map(|msn| (0..4u32).map(move |idx| (format!("segment{msn:05}.{idx}.m4s"), msn, idx)))
+        .collect::<Vec<_>>();
+    assert_eq!(parts, expected_parts);
+
+    // Segments and parts both go through the fragment stream signal, the parts of a segment
+    // are requested after the segment itself
+    let fragment_locations = fragment_locations.lock().unwrap();
+    assert_eq!(fragment_locations.len(), 5 + 20);
+    assert_eq!(fragment_locations[0], "segment00000.m4s");
+    assert_eq!(fragment_locations[1], "segment00000.0.m4s");
+
+    // Every part is closed once it is complete
+    let part_streams = part_streams.lock().unwrap();
+    assert_eq!(part_streams.len(), 20);
+    assert!(part_streams.iter().all(|stream| stream.is_closed()));
+
+    // Intermediate playlists announce the next part and contain the parts of the
+    // unfinished segment. Blocking reloads are not announced by default.
+    let playlists = playlists.lock().unwrap();
+    assert!(playlists.iter().all(|playlist| {
+        playlist.contains("#EXT-X-PART-INF:PART-TARGET=0.50000\n")
+            && playlist.contains("#EXT-X-SERVER-CONTROL:PART-HOLD-BACK=1.50000\n")
+            && !playlist.contains("CAN-BLOCK-RELOAD")
+    }));
+    let first_playlist = &playlists[0];
+    assert!(first_playlist.contains("#EXT-X-MAP:URI=\"init00000.mp4\"\n"));
+    assert!(first_playlist
+        .contains("#EXT-X-PART:DURATION=0.50000,URI=\"segment00000.0.m4s\",INDEPENDENT=YES\n"));
+    assert!(first_playlist.contains("#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"segment00000.1.m4s\"\n"));
+    assert!(!first_playlist.contains("#EXTINF"));
+
+    // The final playlist has no parts of an unfinished segment anymore, and only keeps the
+    // parts of the segments of the last 3 target durations
+    let playlist = playlist_content.lock().unwrap();
+    assert!(playlist.ends_with("#EXT-X-ENDLIST\n"));
+    assert!(!playlist.contains("#EXT-X-PRELOAD-HINT"));
+
+    let mut parts_per_segment = Vec::new();
+    let mut num_parts = 0;
+    for line in playlist.lines() {
+        if line.starts_with("#EXT-X-PART:") {
+            num_parts += 1;
+        } else if !line.is_empty() && !line.starts_with('#') {
+            parts_per_segment.push(num_parts);
+            num_parts = 0;
+        }
+    }
+    assert_eq!(parts_per_segment, vec![0, 0, 4, 4, 4]);
+
+    Ok(())
+}
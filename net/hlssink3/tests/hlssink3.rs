@@ -687,3 +687,685 @@ fn test_hlssink3_video_with_single_media_file() -> Result<(), ()> {
 
     Ok(())
 }
+
+#[test]
+fn test_hlssink3_aes128_encryption() -> Result<(), ()> {
+    use aes::cipher::{BlockDecrypt, KeyInit};
+
+    init();
+
+    const BUFFER_NB: i32 = 250;
+
+    let pipeline = gst::Pipeline::with_name("video_pipeline");
+
+    let video_src = try_create_element!("videotestsrc");
+    video_src.set_property("is-live", false);
+    video_src.set_property("num-buffers", BUFFER_NB);
+
+    let x264enc = try_create_element!("x264enc");
+    let h264parse = try_create_element!("h264parse");
+
+    let hlssink3 = gst::ElementFactory::make("hlssink3")
+        .name("test_hlssink3")
+        .property("target-duration", 2u32)
+        .property("playlist-length", 0u32)
+        .property("max-files", 0u32)
+        .property_from_str("encryption-method", "aes-128")
+        .property("key-uri", "https://example.com/key%d")
+        .property("key-rotation-period", 2u32)
+        .build()
+        .expect("Must be able to instantiate hlssink3");
+
+    let playlist_content = Arc::new(Mutex::new(String::from("")));
+    let fragments = Arc::new(Mutex::new(Vec::new()));
+    let keys = Arc::new(Mutex::new(Vec::new()));
+
+    hlssink3.connect("get-playlist-stream", false, {
+        let playlist_content = playlist_content.clone();
+        move |_args| {
+            let playlist = MemoryPlaylistFile {
+                handler: Arc::clone(&playlist_content),
+            };
+            playlist.clear_content();
+            let output = gio::WriteOutputStream::new(playlist);
+            Some(output.to_value())
+        }
+    });
+
+    hlssink3.connect("get-fragment-stream", false, {
+        let fragments = fragments.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+
+            let stream = gio::MemoryOutputStream::new_resizable();
+            fragments.lock().unwrap().push((location, stream.clone()));
+            Some(stream.to_value())
+        }
+    });
+
+    hlssink3.connect("get-key", false, |args| {
+        let index = args[1].get::<u32>().expect("No key index given");
+        Some(Some(gst::glib::Bytes::from_owned([index as u8; 16])).to_value())
+    });
+
+    hlssink3.connect("get-key-stream", false, {
+        let keys = keys.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+
+            let stream = gio::MemoryOutputStream::new_resizable();
+            keys.lock().unwrap().push((location, stream.clone()));
+            Some(stream.to_value())
+        }
+    });
+
+    try_or_pause!(pipeline.add_many([&video_src, &x264enc, &h264parse, &hlssink3,]));
+    try_or_pause!(gst::Element::link_many([
+        &video_src, &x264enc, &h264parse, &hlssink3
+    ]));
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+
+    let mut eos = false;
+    let bus = pipeline.bus().unwrap();
+    while let Some(msg) = bus.timed_pop(gst::ClockTime::NONE) {
+        use gst::MessageView;
+        match msg.view() {
+            MessageView::Eos(..) => {
+                eos = true;
+                break;
+            }
+            MessageView::Error(..) => unreachable!(),
+            _ => (),
+        }
+    }
+
+    pipeline.set_state(gst::State::Null).unwrap();
+    assert!(eos);
+
+    let fragments = fragments.lock().unwrap();
+    let keys = keys.lock().unwrap();
+    let keys = keys
+        .iter()
+        .map(|(location, stream)| (location.as_str(), stream.steal_as_bytes()))
+        .collect::<Vec<_>>();
+    let segments = fragments
+        .iter()
+        .map(|(location, stream)| {
+            assert!(location.ends_with(".ts"));
+            stream.steal_as_bytes()
+        })
+        .collect::<Vec<_>>();
+
+    // A new key is used every two segments
+    assert_eq!(segments.len(), 5);
+    assert_eq!(
+        keys.iter()
+            .map(|(location, key)| (*location, key.to_vec()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("key00000.key", vec![0u8; 16]),
+            ("key00001.key", vec![1u8; 16]),
+            ("key00002.key", vec![2u8; 16]),
+        ]
+    );
+
+    let contents = playlist_content.lock().unwrap();
+    let key_tags = contents
+        .lines()
+        .filter(|line| line.starts_with("#EXT-X-KEY:"))
+        .collect::<Vec<_>>();
+    assert_eq!(key_tags.len(), segments.len());
+    for (media_sequence, tag) in key_tags.iter().enumerate() {
+        assert!(tag.contains("METHOD=AES-128"));
+        assert!(tag.contains(&format!(
+            "URI=\"https://example.com/key{}\"",
+            media_sequence / 2
+        )));
+        assert!(tag.contains(&format!("IV=0x{media_sequence:032X}")));
+    }
+
+    // Segments are padded to the block size and decrypt to MPEG-TS packets
+    for (media_sequence, segment) in segments.iter().enumerate() {
+        assert_eq!(segment.len() % 16, 0);
+
+        let cipher = aes::Aes128::new(&[(media_sequence / 2) as u8; 16].into());
+        let mut block = aes::Block::clone_from_slice(&segment[..16]);
+        cipher.decrypt_block(&mut block);
+        let iv = (media_sequence as u128).to_be_bytes();
+        assert_eq!(block[0] ^ iv[0], 0x47);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hlssink3_key_deletion() -> Result<(), ()> {
+    init();
+
+    const BUFFER_NB: i32 = 250;
+
+    let pipeline = gst::Pipeline::with_name("video_pipeline");
+
+    let video_src = try_create_element!("videotestsrc");
+    video_src.set_property("is-live", false);
+    video_src.set_property("num-buffers", BUFFER_NB);
+
+    let x264enc = try_create_element!("x264enc");
+    let h264parse = try_create_element!("h264parse");
+
+    let hlssink3 = gst::ElementFactory::make("hlssink3")
+        .name("test_hlssink3")
+        .property("target-duration", 2u32)
+        .property("playlist-length", 2u32)
+        .property("max-files", 2u32)
+        .property_from_str("encryption-method", "aes-128")
+        .property("key-rotation-period", 2u32)
+        .build()
+        .expect("Must be able to instantiate hlssink3");
+
+    let deleted_fragments = Arc::new(Mutex::new(Vec::new()));
+    let deleted_keys = Arc::new(Mutex::new(Vec::new()));
+
+    hlssink3.connect("get-playlist-stream", false, move |_args| {
+        let stream = gio::MemoryOutputStream::new_resizable();
+        Some(stream.to_value())
+    });
+
+    hlssink3.connect("get-fragment-stream", false, move |_args| {
+        let stream = gio::MemoryOutputStream::new_resizable();
+        Some(stream.to_value())
+    });
+
+    hlssink3.connect("get-key-stream", false, move |_args| {
+        let stream = gio::MemoryOutputStream::new_resizable();
+        Some(stream.to_value())
+    });
+
+    hlssink3.connect("delete-fragment", false, {
+        let deleted_fragments = deleted_fragments.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            deleted_fragments.lock().unwrap().push(location);
+            Some(true.to_value())
+        }
+    });
+
+    hlssink3.connect("delete-key", false, {
+        let deleted_keys = deleted_keys.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            deleted_keys.lock().unwrap().push(location);
+            Some(true.to_value())
+        }
+    });
+
+    try_or_pause!(pipeline.add_many([&video_src, &x264enc, &h264parse, &hlssink3,]));
+    try_or_pause!(gst::Element::link_many([
+        &video_src, &x264enc, &h264parse, &hlssink3
+    ]));
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+
+    let mut eos = false;
+    let bus = pipeline.bus().unwrap();
+    while let Some(msg) = bus.timed_pop(gst::ClockTime::NONE) {
+        use gst::MessageView;
+        match msg.view() {
+            MessageView::Eos(..) => {
+                eos = true;
+                break;
+            }
+            MessageView::Error(..) => unreachable!(),
+            _ => (),
+        }
+    }
+
+    pipeline.set_state(gst::State::Null).unwrap();
+    assert!(eos);
+
+    // Only segment files are passed to delete-fragment
+    let deleted_fragments = deleted_fragments.lock().unwrap();
+    assert_eq!(
+        *deleted_fragments,
+        vec!["segment00000.ts", "segment00001.ts", "segment00002.ts"]
+    );
+
+    // The first key is deleted together with the last segment that was encrypted with it, the
+    // second key is still used by the fourth segment
+    let deleted_keys = deleted_keys.lock().unwrap();
+    assert_eq!(*deleted_keys, vec!["key00000.key"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_hlssink3_scte35_splice() -> Result<(), ()> {
+    init();
+
+    const BUFFER_NB: i32 = 250;
+
+    // splice_insert() for event 42 with an auto-return break of 3s
+    const SPLICE_INSERT: [u8; 40] = [
+        0xfc, 0x30, 0x25, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xf0, 0x14, 0x05, 0x00,
+        0x00, 0x00, 0x2a, 0x7f, 0xef, 0xff, 0x00, 0x00, 0x00, 0x00, 0xfe, 0x00, 0x04, 0x1e, 0xb0,
+        0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    let pipeline = gst::Pipeline::with_name("video_pipeline");
+
+    let video_src = try_create_element!("videotestsrc");
+    video_src.set_property("is-live", false);
+    video_src.set_property("num-buffers", BUFFER_NB);
+
+    let x264enc = try_create_element!("x264enc");
+    let h264parse = try_create_element!("h264parse");
+
+    let hlssink3 = gst::ElementFactory::make("hlssink3")
+        .name("test_hlssink3")
+        .property("target-duration", 2u32)
+        .property("playlist-length", 0u32)
+        .property("max-files", 0u32)
+        .build()
+        .expect("Must be able to instantiate hlssink3");
+
+    let playlist_content = Arc::new(Mutex::new(String::from("")));
+    hlssink3.connect("get-playlist-stream", false, {
+        let playlist_content = playlist_content.clone();
+        move |_args| {
+            let playlist = MemoryPlaylistFile {
+                handler: Arc::clone(&playlist_content),
+            };
+            playlist.clear_content();
+            let output = gio::WriteOutputStream::new(playlist);
+            Some(output.to_value())
+        }
+    });
+
+    hlssink3.connect("get-fragment-stream", false, move |_args| {
+        let stream = gio::MemoryOutputStream::new_resizable();
+        Some(stream.to_value())
+    });
+
+    try_or_pause!(pipeline.add_many([&video_src, &x264enc, &h264parse, &hlssink3,]));
+    try_or_pause!(gst::Element::link_many([
+        &video_src, &x264enc, &h264parse, &hlssink3
+    ]));
+
+    // Signal the splice point before the first buffer so the split is requested in time
+    h264parse
+        .static_pad("src")
+        .unwrap()
+        .add_probe(gst::PadProbeType::BUFFER, |pad, _info| {
+            let s = gst::Structure::builder("HlsSinkScte35")
+                .field("running-time", gst::ClockTime::from_seconds(3))
+                .field("section", gst::Buffer::from_slice(SPLICE_INSERT))
+                .build();
+            pad.push_event(gst::event::CustomDownstream::new(s));
+            gst::PadProbeReturn::Remove
+        });
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+
+    let mut eos = false;
+    let bus = pipeline.bus().unwrap();
+    while let Some(msg) = bus.timed_pop(gst::ClockTime::NONE) {
+        use gst::MessageView;
+        match msg.view() {
+            MessageView::Eos(..) => {
+                eos = true;
+                break;
+            }
+            MessageView::Error(..) => unreachable!(),
+            _ => (),
+        }
+    }
+
+    pipeline.set_state(gst::State::Null).unwrap();
+    assert!(eos);
+
+    let contents = playlist_content.lock().unwrap();
+
+    let dateranges = contents
+        .lines()
+        .filter(|line| line.starts_with("#EXT-X-DATERANGE:"))
+        .collect::<Vec<_>>();
+    assert_eq!(dateranges.len(), 2);
+    assert!(dateranges[0].contains("ID=\"42\""));
+    assert!(dateranges[0].contains("PLANNED-DURATION=3.000"));
+    assert!(dateranges[0].contains("SCTE35-OUT=0xFC3025"));
+    assert!(dateranges[1].contains("ID=\"42\""));
+    assert!(dateranges[1].contains("DURATION=3.000"));
+    assert!(dateranges[1].contains("END-DATE="));
+
+    // The break starts and ends on segment boundaries
+    let mut duration = 0.0;
+    let mut cue_out = None;
+    let mut cue_in = None;
+    for line in contents.lines() {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            duration += extinf.trim_end_matches(',').parse::<f64>().unwrap();
+        } else if line.starts_with("#EXT-X-CUE-OUT:") {
+            assert_eq!(line, "#EXT-X-CUE-OUT:DURATION=3.000");
+            cue_out = Some(duration);
+        } else if line == "#EXT-X-CUE-IN" {
+            cue_in = Some(duration);
+        }
+    }
+    assert!((cue_out.unwrap() - 3.0).abs() < 0.001);
+    assert!((cue_in.unwrap() - 6.0).abs() < 0.001);
+
+    Ok(())
+}
+
+#[test]
+fn test_hlssink3_resume_playlist() -> Result<(), ()> {
+    init();
+
+    const BUFFER_NB: i32 = 250;
+
+    // Playlist left behind by a previous run, with a discontinuity of its own
+    let dir = std::env::temp_dir().join(format!("hlssink3-resume-{}", std::process::id()));
+    let playlist_location = dir.join("playlist.m3u8");
+    const EXISTING_PLAYLIST: &str = r###"#EXTM3U
+#EXT-X-VERSION:3
+#EXT-X-TARGETDURATION:2
+#EXT-X-MEDIA-SEQUENCE:3
+#EXT-X-DISCONTINUITY-SEQUENCE:2
+#EXTINF:2,
+segment00003.ts
+#EXT-X-DISCONTINUITY
+#EXTINF:2,
+segment00004.ts
+#EXTINF:2,
+segment00005.ts
+#EXT-X-ENDLIST
+"###;
+
+    let pipeline = gst::Pipeline::with_name("video_pipeline");
+
+    let video_src = try_create_element!("videotestsrc");
+    video_src.set_property("is-live", false);
+    video_src.set_property("num-buffers", BUFFER_NB);
+
+    let x264enc = try_create_element!("x264enc");
+    let h264parse = try_create_element!("h264parse");
+
+    let segment_location = dir.join("segment%05d.ts");
+    let hlssink3 = gst::ElementFactory::make("hlssink3")
+        .name("test_hlssink3")
+        .property("location", segment_location.to_str().unwrap())
+        .property("playlist-location", playlist_location.to_str().unwrap())
+        .property("target-duration", 2u32)
+        .property("playlist-length", 5u32)
+        .property("max-files", 4u32)
+        .property("resume", true)
+        .build()
+        .expect("Must be able to instantiate hlssink3");
+
+    let (hls_events_sender, hls_events_receiver) = mpsc::sync_channel(30);
+
+    // The existing playlist is read via the signal instead of from the file system
+    let read_playlists = Arc::new(Mutex::new(Vec::new()));
+    hlssink3.connect("get-playlist-input-stream", false, {
+        let read_playlists = read_playlists.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            read_playlists.lock().unwrap().push(location);
+
+            let stream = gio::MemoryInputStream::from_bytes(&gst::glib::Bytes::from_static(
+                EXISTING_PLAYLIST.as_bytes(),
+            ));
+            Some(stream.to_value())
+        }
+    });
+
+    // Keeps all versions of the playlist that were written
+    let playlists = Arc::new(Mutex::new(Vec::new()));
+    hlssink3.connect("get-playlist-stream", false, {
+        let playlists = playlists.clone();
+        move |_args| {
+            let playlist_content = Arc::new(Mutex::new(String::from("")));
+            playlists.lock().unwrap().push(playlist_content.clone());
+            let output = gio::WriteOutputStream::new(MemoryPlaylistFile {
+                handler: playlist_content,
+            });
+            Some(output.to_value())
+        }
+    });
+
+    hlssink3.connect("get-fragment-stream", false, {
+        let hls_events_sender = hls_events_sender.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            hls_events_sender
+                .try_send(HlsSinkEvent::GetFragmentStream(location))
+                .expect("Send fragment event");
+
+            let stream = gio::MemoryOutputStream::new_resizable();
+            Some(stream.to_value())
+        }
+    });
+
+    hlssink3.connect("delete-fragment", false, {
+        let hls_events_sender = hls_events_sender.clone();
+        move |args| {
+            let location = args[1].get::<String>().expect("No location given");
+            hls_events_sender
+                .try_send(HlsSinkEvent::DeleteFragment(location))
+                .expect("Send delete fragment event");
+            Some(true.to_value())
+        }
+    });
+
+    try_or_pause!(pipeline.add_many([&video_src, &x264enc, &h264parse, &hlssink3,]));
+    try_or_pause!(gst::Element::link_many([
+        &video_src, &x264enc, &h264parse, &hlssink3
+    ]));
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+
+    let mut eos = false;
+    let bus = pipeline.bus().unwrap();
+    while let Some(msg) = bus.timed_pop(gst::ClockTime::NONE) {
+        use gst::MessageView;
+        match msg.view() {
+            MessageView::Eos(..) => {
+                eos = true;
+                break;
+            }
+            MessageView::Error(..) => unreachable!(),
+            _ => (),
+        }
+    }
+
+    pipeline.set_state(gst::State::Null).unwrap();
+    assert!(eos);
+
+    assert_eq!(
+        *read_playlists.lock().unwrap(),
+        vec![playlist_location.to_str().unwrap().to_string()]
+    );
+
+    // Segment numbering continues and segment files of the previous run are deleted too
+    let location = |index: u32| {
+        dir.join(format!("segment{index:05}.ts"))
+            .to_str()
+            .unwrap()
+            .to_string()
+    };
+    let mut actual_events = Vec::new();
+    while let Ok(event) = hls_events_receiver.try_recv() {
+        actual_events.push(event);
+    }
+    let fragments = actual_events
+        .iter()
+        .filter(|event| matches!(event, HlsSinkEvent::GetFragmentStream(_)))
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fragments,
+        (6..=10)
+            .map(|index| HlsSinkEvent::GetFragmentStream(location(index)))
+            .collect::<Vec<_>>()
+    );
+    let deleted = actual_events
+        .iter()
+        .filter(|event| matches!(event, HlsSinkEvent::DeleteFragment(_)))
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(
+        deleted,
+        (2..=6)
+            .map(|index| HlsSinkEvent::DeleteFragment(location(index)))
+            .collect::<Vec<_>>()
+    );
+
+    let playlists = playlists.lock().unwrap();
+
+    // The first new segment follows the existing ones after a discontinuity
+    let first = playlists[0].lock().unwrap();
+    assert!(first.contains("#EXT-X-MEDIA-SEQUENCE:3\n"));
+    assert!(first.contains("#EXT-X-DISCONTINUITY-SEQUENCE:2\n"));
+    assert!(first.contains("segment00005.ts\n#EXT-X-DISCONTINUITY\n#EXTINF:"));
+    assert!(first.trim_end().ends_with("segment00006.ts"));
+
+    // Removing the segment with the old discontinuity from the playlist bumps the sequence
+    let last = playlists.last().unwrap().lock().unwrap();
+    let uris = last
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        uris,
+        vec![
+            "segment00006.ts",
+            "segment00007.ts",
+            "segment00008.ts",
+            "segment00009.ts",
+            "segment00010.ts",
+        ]
+    );
+    assert!(last.contains("#EXT-X-DISCONTINUITY-SEQUENCE:3\n"));
+    assert!(last.contains("#EXT-X-DISCONTINUITY\n#EXTINF:"));
+
+    Ok(())
+}
+
+#[test]
+fn test_hlswebvttsink_manual_split() -> Result<(), ()> {
+    init();
+
+    let pipeline = gst::Pipeline::with_name("subtitle_pipeline");
+
+    let appsrc = gst_app::AppSrc::builder()
+        .caps(
+            &gst::Caps::builder("text/x-raw")
+                .field("format", "utf8")
+                .build(),
+        )
+        .format(gst::Format::Time)
+        .build();
+
+    let hlswebvttsink = gst::ElementFactory::make("hlswebvttsink")
+        .name("test_hlswebvttsink")
+        .property("target-duration", 2u32)
+        .property("manual-split", true)
+        .build()
+        .expect("Must be able to instantiate hlswebvttsink");
+
+    let playlist_content = Arc::new(Mutex::new(String::from("")));
+    hlswebvttsink.connect("get-playlist-stream", false, {
+        let playlist_content = playlist_content.clone();
+        move |_args| {
+            let playlist = MemoryPlaylistFile {
+                handler: Arc::clone(&playlist_content),
+            };
+
+            playlist.clear_content();
+            let output = gio::WriteOutputStream::new(playlist);
+            Some(output.to_value())
+        }
+    });
+
+    let fragments = Arc::new(Mutex::new(Vec::new()));
+    hlswebvttsink.connect("get-fragment-stream", false, {
+        let fragments = fragments.clone();
+        move |_args| {
+            let stream = gio::MemoryOutputStream::new_resizable();
+            fragments.lock().unwrap().push(stream.clone());
+            Some(stream.to_value())
+        }
+    });
+
+    pipeline
+        .add_many([appsrc.upcast_ref(), &hlswebvttsink])
+        .unwrap();
+    appsrc.link(&hlswebvttsink).unwrap();
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+
+    // Segment boundaries of another rendition that are not multiples of the target duration
+    for split in [0, 3, 7] {
+        hlswebvttsink.emit_by_name::<()>(
+            "split-at-running-time",
+            &[&gst::ClockTime::from_seconds(split).nseconds()],
+        );
+    }
+
+    for (start, duration, text) in [
+        (0, 1000, "Hello"),
+        (2500, 1000, "World"),
+        (4000, 1000, "Again"),
+        (7200, 300, "Bye"),
+    ] {
+        let mut buffer = gst::Buffer::from_slice(text);
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(gst::ClockTime::from_mseconds(start));
+            buffer.set_duration(gst::ClockTime::from_mseconds(duration));
+        }
+        appsrc.push_buffer(buffer).unwrap();
+    }
+    appsrc.end_of_stream().unwrap();
+
+    let mut eos = false;
+    let bus = pipeline.bus().unwrap();
+    while let Some(msg) = bus.timed_pop(gst::ClockTime::NONE) {
+        use gst::MessageView;
+        match msg.view() {
+            MessageView::Eos(..) => {
+                eos = true;
+                break;
+            }
+            MessageView::Error(..) => unreachable!(),
+            _ => (),
+        }
+    }
+
+    pipeline.set_state(gst::State::Null).unwrap();
+    assert!(eos);
+
+    let contents = playlist_content.lock().unwrap();
+    let playlist = m3u8_rs::parse_media_playlist_res(contents.as_bytes()).unwrap();
+    let durations = playlist
+        .segments
+        .iter()
+        .map(|segment| segment.duration)
+        .collect::<Vec<_>>();
+    assert_eq!(durations, vec![3.0, 4.0, 0.5]);
+
+    let fragments = fragments
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|stream| String::from_utf8(stream.steal_as_bytes().to_vec()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(fragments.len(), 3);
+    assert!(fragments[0].contains("Hello") && fragments[0].contains("World"));
+    // The cue spanning the boundary is repeated in the next segment
+    assert!(fragments[1].contains("World") && fragments[1].contains("Again"));
+    assert!(fragments[2].contains("Bye"));
+
+    Ok(())
+}
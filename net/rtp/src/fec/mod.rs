@@ -0,0 +1,515 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Common parts of the XOR based FEC schemes ULPFEC (RFC 5109) and FlexFEC (RFC 8627).
+//!
+//! Both schemes protect a group of media packets with a FEC packet that carries the bitwise XOR
+//! of the protected packets. If exactly one packet of a group is lost, it can be restored from
+//! the FEC packet and the remaining packets of the group.
+
+use std::collections::BTreeMap;
+
+use gst::glib;
+use rtp_types::RtpPacket;
+
+use crate::utils::seqnum_distance;
+
+/// Size of the fixed RTP header, which is not covered by the XOR of the packet data
+const RTP_HEADER_LEN: usize = 12;
+
+/// Number of seqnums for which received packets are kept around for restoring lost packets
+const HISTORY_SIZE: u64 = 512;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, glib::Enum, Default)]
+#[enum_type(name = "GstRtpFecProtection")]
+#[repr(i32)]
+pub enum Protection {
+    #[default]
+    #[enum_value(name = "Protect each row of consecutive packets", nick = "row")]
+    Row,
+    #[enum_value(name = "Protect each column of interleaved packets", nick = "column")]
+    Column,
+    #[enum_value(name = "Protect each row and each column", nick = "row-and-column")]
+    RowAndColumn,
+}
+
+/// Bitwise XOR of the protected fields of a group of RTP packets
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Parity {
+    /// Padding, extension and CSRC count bits of the first header byte
+    pub pxcc: u8,
+    /// Marker bit and payload type
+    pub mpt: u8,
+    pub timestamp: u32,
+    /// Length of everything following the fixed RTP header
+    pub length: u16,
+    /// Everything following the fixed RTP header: CSRCs, header extension, payload and padding
+    pub data: Vec<u8>,
+}
+
+impl Parity {
+    /// Adds a packet to the group, extending the data if the packet is longer than the previous
+    /// ones
+    pub fn add(&mut self, packet: &[u8]) {
+        self.xor_header(packet);
+
+        let data = &packet[RTP_HEADER_LEN..];
+        if data.len() > self.data.len() {
+            self.data.resize(data.len(), 0);
+        }
+        xor(&mut self.data, data);
+    }
+
+    /// Removes a received packet from the parity of a FEC packet. Only the part of the packet
+    /// covered by the FEC packet is taken into account.
+    pub fn remove(&mut self, packet: &[u8]) {
+        self.xor_header(packet);
+
+        let data = &packet[RTP_HEADER_LEN..];
+        let len = data.len().min(self.data.len());
+        xor(&mut self.data[..len], &data[..len]);
+    }
+
+    fn xor_header(&mut self, packet: &[u8]) {
+        self.pxcc ^= packet[0] & 0x3f;
+        self.mpt ^= packet[1];
+        self.timestamp ^= u32::from_be_bytes(packet[4..8].try_into().unwrap());
+        self.length ^= (packet.len() - RTP_HEADER_LEN) as u16;
+    }
+
+    /// Restores the only missing packet of the group after all other packets were removed
+    pub fn restore(&self, seqnum: u16, ssrc: u32) -> Option<Vec<u8>> {
+        let len = self.length as usize;
+        if len > self.data.len() {
+            return None;
+        }
+
+        let mut packet = Vec::with_capacity(RTP_HEADER_LEN + len);
+        packet.push(0x80 | self.pxcc);
+        packet.push(self.mpt);
+        packet.extend_from_slice(&seqnum.to_be_bytes());
+        packet.extend_from_slice(&self.timestamp.to_be_bytes());
+        packet.extend_from_slice(&ssrc.to_be_bytes());
+        packet.extend_from_slice(&self.data[..len]);
+
+        RtpPacket::parse(&packet).ok()?;
+
+        Some(packet)
+    }
+}
+
+fn xor(dest: &mut [u8], src: &[u8]) {
+    for (d, s) in dest.iter_mut().zip(src) {
+        *d ^= *s;
+    }
+}
+
+/// A group of media packets that is protected by one FEC packet
+#[derive(Debug, Default)]
+pub struct FecGroup {
+    /// Seqnums of the protected packets in sending order
+    pub seqnums: Vec<u16>,
+    pub parity: Parity,
+}
+
+impl FecGroup {
+    fn add(&mut self, seqnum: u16, packet: &[u8]) {
+        self.seqnums.push(seqnum);
+        self.parity.add(packet);
+    }
+}
+
+/// Arranges outgoing media packets in rows of `columns` consecutive packets.
+///
+/// With row protection, every row is protected by one FEC packet. With column protection,
+/// `rows` rows form a block and every column of the block is protected by one FEC packet.
+#[derive(Debug)]
+pub struct ProtectionMatrix {
+    protection: Protection,
+    columns: usize,
+    rows: usize,
+    position: usize,
+    row: FecGroup,
+    columns_groups: Vec<FecGroup>,
+}
+
+impl ProtectionMatrix {
+    pub fn new(protection: Protection, columns: u32, rows: u32) -> Self {
+        let columns = columns.max(1) as usize;
+        let rows = if protection == Protection::Row {
+            1
+        } else {
+            rows.max(1) as usize
+        };
+
+        ProtectionMatrix {
+            protection,
+            columns,
+            rows,
+            position: 0,
+            row: FecGroup::default(),
+            columns_groups: Vec::new(),
+        }
+    }
+
+    /// Maximum distance between the first and the last packet of a group, not counting any
+    /// other packets sent in between
+    pub fn span(&self) -> usize {
+        if self.protection == Protection::Row {
+            self.columns
+        } else {
+            (self.rows - 1) * self.columns + 1
+        }
+    }
+
+    /// Adds the next media packet and returns the groups that are complete with it
+    pub fn push(&mut self, seqnum: u16, packet: &[u8]) -> Vec<FecGroup> {
+        let mut groups = Vec::new();
+        let column = self.position % self.columns;
+
+        if self.protection != Protection::Column {
+            self.row.add(seqnum, packet);
+            if column == self.columns - 1 {
+                groups.push(std::mem::take(&mut self.row));
+            }
+        }
+
+        if self.protection != Protection::Row {
+            if self.columns_groups.is_empty() {
+                self.columns_groups
+                    .resize_with(self.columns, FecGroup::default);
+            }
+            self.columns_groups[column].add(seqnum, packet);
+        }
+
+        self.position += 1;
+        if self.position == self.columns * self.rows {
+            self.position = 0;
+            groups.append(&mut self.columns_groups);
+        }
+
+        groups
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub received: u64,
+    pub fec_received: u64,
+    pub recovered: u64,
+    pub lost: u64,
+}
+
+#[derive(Debug)]
+struct PendingFec {
+    seqnums: Vec<u64>,
+    parity: Parity,
+}
+
+/// Keeps the received packets of one media stream together with the FEC packets protecting
+/// them and restores lost packets
+#[derive(Debug)]
+pub struct Recovery {
+    ssrc: u32,
+    max_seqnum: Option<u64>,
+    /// Lowest extended seqnum that is still taken into account
+    window_start: u64,
+    /// Received and restored packets by extended seqnum. Packets that use a seqnum of the
+    /// stream but are not media packets, e.g. ULPFEC packets, are stored as `None`.
+    packets: BTreeMap<u64, Option<Vec<u8>>>,
+    fec: Vec<PendingFec>,
+    stats: Stats,
+}
+
+impl Recovery {
+    pub fn new(ssrc: u32) -> Self {
+        Recovery {
+            ssrc,
+            max_seqnum: None,
+            window_start: 0,
+            packets: BTreeMap::new(),
+            fec: Vec::new(),
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Extends a seqnum relative to the highest seqnum seen so far
+    pub fn extend_seqnum(&self, seqnum: u16) -> u64 {
+        match self.max_seqnum {
+            None => (1 << 16) + seqnum as u64,
+            Some(max) => (max as i64 + seqnum_distance(seqnum, max as u16) as i64) as u64,
+        }
+    }
+
+    /// Highest extended seqnum seen so far
+    pub fn max_seqnum(&self) -> Option<u64> {
+        self.max_seqnum
+    }
+
+    /// Whether a packet with this extended seqnum was received or restored
+    pub fn has_seqnum(&self, ext_seqnum: u64) -> bool {
+        self.packets.contains_key(&ext_seqnum)
+    }
+
+    fn update_window(&mut self, ext_seqnum: u64) {
+        let Some(max) = self.max_seqnum else {
+            self.max_seqnum = Some(ext_seqnum);
+            self.window_start = ext_seqnum;
+            return;
+        };
+        if ext_seqnum <= max {
+            return;
+        }
+        self.max_seqnum = Some(ext_seqnum);
+
+        let window_start = ext_seqnum.saturating_sub(HISTORY_SIZE - 1);
+        if window_start <= self.window_start {
+            return;
+        }
+
+        let remaining = self.packets.split_off(&window_start);
+        let expected = window_start - self.window_start;
+        self.stats.lost += expected.saturating_sub(self.packets.len() as u64);
+        self.packets = remaining;
+        self.window_start = window_start;
+
+        self.fec
+            .retain(|fec| fec.seqnums.iter().all(|&seqnum| seqnum >= window_start));
+    }
+
+    /// Stores a received media packet and returns its extended seqnum, or `None` if the packet
+    /// was already received or restored before. Packets that are too old to be of any use for
+    /// restoring other packets are not stored.
+    pub fn add_packet(&mut self, seqnum: u16, packet: &[u8]) -> Option<u64> {
+        let ext_seqnum = self.extend_seqnum(seqnum);
+        self.update_window(ext_seqnum);
+        if self.packets.contains_key(&ext_seqnum) {
+            return None;
+        }
+
+        self.stats.received += 1;
+        if ext_seqnum < self.window_start {
+            return Some(ext_seqnum);
+        }
+        self.packets.insert(ext_seqnum, Some(packet.to_vec()));
+
+        Some(ext_seqnum)
+    }
+
+    /// Marks a seqnum as received that is used by a packet which is not a media packet
+    pub fn add_non_media(&mut self, seqnum: u16) -> u64 {
+        let ext_seqnum = self.extend_seqnum(seqnum);
+        self.update_window(ext_seqnum);
+        if ext_seqnum >= self.window_start {
+            self.packets.insert(ext_seqnum, None);
+        }
+
+        ext_seqnum
+    }
+
+    /// Stores a received FEC packet protecting the given seqnums
+    pub fn add_fec(&mut self, seqnums: &[u16], parity: Parity) {
+        self.stats.fec_received += 1;
+
+        let seqnums = seqnums
+            .iter()
+            .map(|&seqnum| self.extend_seqnum(seqnum))
+            .collect::<Vec<_>>();
+        if let (Some(&min), Some(&max)) = (seqnums.iter().min(), seqnums.iter().max()) {
+            self.update_window(min);
+            self.update_window(max);
+        }
+        if seqnums.iter().any(|&seqnum| seqnum < self.window_start) {
+            return;
+        }
+
+        self.fec.push(PendingFec { seqnums, parity });
+    }
+
+    /// Restores all packets that can be restored with the stored FEC packets and returns them
+    /// with their extended seqnums in seqnum order
+    pub fn recover(&mut self) -> Vec<(u64, Vec<u8>)> {
+        let mut restored = Vec::new();
+
+        loop {
+            let mut progress = false;
+
+            let mut idx = 0;
+            while idx < self.fec.len() {
+                let mut missing = self.fec[idx]
+                    .seqnums
+                    .iter()
+                    .filter(|seqnum| !self.packets.contains_key(seqnum));
+                let first_missing = missing.next().copied();
+                let more_missing = missing.next().is_some();
+
+                match (first_missing, more_missing) {
+                    (None, _) => {
+                        self.fec.swap_remove(idx);
+                    }
+                    (Some(ext_seqnum), false) => {
+                        let fec = self.fec.swap_remove(idx);
+                        if let Some(packet) = self.restore(fec, ext_seqnum) {
+                            self.stats.recovered += 1;
+                            self.packets.insert(ext_seqnum, Some(packet.clone()));
+                            restored.push((ext_seqnum, packet));
+                            progress = true;
+                        }
+                    }
+                    _ => idx += 1,
+                }
+            }
+
+            if !progress {
+                break;
+            }
+        }
+
+        restored.sort_by_key(|(ext_seqnum, _)| *ext_seqnum);
+
+        restored
+    }
+
+    fn restore(&self, fec: PendingFec, missing: u64) -> Option<Vec<u8>> {
+        let mut parity = fec.parity;
+        for seqnum in fec.seqnums.iter().filter(|&&seqnum| seqnum != missing) {
+            // FEC packets only protect media packets
+            let packet = self.packets.get(seqnum)?.as_ref()?;
+            parity.remove(packet);
+        }
+
+        let packet = parity.restore(missing as u16, self.ssrc);
+        if packet.is_none() {
+            warn!("Failed to restore packet {}", missing as u16);
+        }
+
+        packet
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rtp_types::RtpPacketBuilder;
+
+    /// Creates a packet with a payload that differs for every seqnum
+    pub(crate) fn generate_packet(seqnum: u16, rtp_ts: u32, payload_len: usize) -> Vec<u8> {
+        let payload = (0..payload_len)
+            .map(|i| (seqnum as usize * 7 + i) as u8)
+            .collect::<Vec<_>>();
+
+        RtpPacketBuilder::new()
+            .payload_type(96)
+            .ssrc(0x1234)
+            .sequence_number(seqnum)
+            .timestamp(rtp_ts)
+            .marker_bit(seqnum % 2 == 0)
+            .payload(payload.as_slice())
+            .write_vec()
+            .unwrap()
+    }
+
+    #[test]
+    fn matrix_row() {
+        let mut matrix = ProtectionMatrix::new(Protection::Row, 3, 2);
+        assert_eq!(matrix.span(), 3);
+
+        let mut groups = Vec::new();
+        for seqnum in 0..7 {
+            groups.extend(matrix.push(seqnum, &generate_packet(seqnum, 0, 4)));
+        }
+
+        let seqnums = groups.iter().map(|g| g.seqnums.clone()).collect::<Vec<_>>();
+        assert_eq!(seqnums, vec![vec![0, 1, 2], vec![3, 4, 5]]);
+    }
+
+    #[test]
+    fn matrix_row_and_column() {
+        let mut matrix = ProtectionMatrix::new(Protection::RowAndColumn, 3, 2);
+        assert_eq!(matrix.span(), 4);
+
+        let mut groups = Vec::new();
+        for seqnum in 0..6 {
+            groups.extend(matrix.push(seqnum, &generate_packet(seqnum, 0, 4)));
+        }
+
+        let seqnums = groups.iter().map(|g| g.seqnums.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            seqnums,
+            vec![
+                vec![0, 1, 2],
+                vec![3, 4, 5],
+                vec![0, 3],
+                vec![1, 4],
+                vec![2, 5]
+            ]
+        );
+    }
+
+    #[test]
+    fn restore_packet() {
+        let packets = (0..4)
+            .map(|i| generate_packet(100 + i, 1000 + i as u32, 4 + i as usize))
+            .collect::<Vec<_>>();
+
+        let mut parity = Parity::default();
+        for packet in &packets {
+            parity.add(packet);
+        }
+
+        let mut recovery = Recovery::new(0x1234);
+        for (i, packet) in packets.iter().enumerate() {
+            if i != 2 {
+                assert!(recovery.add_packet(100 + i as u16, packet).is_some());
+            }
+        }
+        // Duplicates are rejected
+        assert!(recovery.add_packet(100, &packets[0]).is_none());
+
+        recovery.add_fec(&[100, 101, 102, 103], parity);
+        let restored = recovery.recover();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].1, packets[2]);
+        assert!(recovery.has_seqnum(restored[0].0));
+        assert_eq!(recovery.stats().recovered, 1);
+    }
+
+    #[test]
+    fn restore_two_dimensions() {
+        // Packets 1 and 4 of a 3x2 block are lost, which can't be restored from the rows alone
+        let packets = (0..6)
+            .map(|i| generate_packet(i, 0, 10))
+            .collect::<Vec<_>>();
+
+        let mut matrix = ProtectionMatrix::new(Protection::RowAndColumn, 3, 2);
+        let mut recovery = Recovery::new(0x1234);
+        let mut groups = Vec::new();
+        for (i, packet) in packets.iter().enumerate() {
+            groups.extend(matrix.push(i as u16, packet));
+            if i != 1 && i != 4 {
+                recovery.add_packet(i as u16, packet);
+            }
+        }
+
+        // Only the first row and the middle column arrive
+        for group in groups
+            .into_iter()
+            .filter(|group| group.seqnums == [0, 1, 2] || group.seqnums == [1, 4])
+        {
+            recovery.add_fec(&group.seqnums, group.parity);
+        }
+
+        let restored = recovery.recover();
+        assert_eq!(
+            restored.iter().map(|(_, p)| p).collect::<Vec<_>>(),
+            vec![&packets[1], &packets[4]]
+        );
+    }
+}
@@ -0,0 +1,351 @@
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * SECTION:element-rtpflexfecdec
+ * @see_also: rtpflexfecenc, rtpulpfecdec2, rtprecv
+ *
+ * Restores lost RTP packets from FlexFEC packets as per
+ * [draft-ietf-payload-flexible-fec-scheme-03][flexfec-03] and removes the FEC packets from the
+ * stream.
+ *
+ * The FEC packets are identified by the payload type configured with #rtpflexfecdec:pt and
+ * carry the SSRC of the stream they protect. As FlexFEC packets are sent in a separate stream,
+ * the seqnums of the media packets are not affected by them, and the element is placed in front
+ * of the `rtp_sink` pad of `rtprecv`. Restored packets are forwarded as soon as possible and put
+ * back in order by the jitterbuffer of `rtprecv`, so they are not counted as lost as long as they
+ * are restored within the configured latency.
+ *
+ * Statistics about the received, restored and lost packets are provided with
+ * #rtpflexfecdec:stats.
+ *
+ * [flexfec-03]: https://datatracker.ietf.org/doc/html/draft-ietf-payload-flexible-fec-scheme-03
+ *
+ * ## Example pipeline
+ *
+ * |[
+ * gst-launch-1.0 \
+ *  udpsrc port=5004 caps='application/x-rtp, media=video, clock-rate=90000, encoding-name=VP8, payload=96' \
+ *  ! rtpflexfecdec pt=124 ! recv.rtp_sink_0 \
+ *  rtprecv name=recv rtp-id=example-rtp-id latency=200 \
+ *  ! rtpvp8depay2 ! vp8dec ! videoconvert ! autovideosink
+ * ]| This will receive a VP8 stream protected by FlexFEC packets.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::{glib, prelude::*, subclass::prelude::*};
+use rtp_types::RtpPacket;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use crate::fec::{Recovery, Stats};
+use crate::flexfec;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rtpflexfecdec",
+        gst::DebugColorFlags::empty(),
+        Some("RTP FlexFEC Decoder"),
+    )
+});
+
+const DEFAULT_PT: u32 = 124;
+
+#[derive(Debug, Clone)]
+struct Settings {
+    pt: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { pt: DEFAULT_PT }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Received packets and FEC packets by media SSRC
+    recoveries: HashMap<u32, Recovery>,
+}
+
+impl State {
+    fn stats(&self) -> Stats {
+        self.recoveries
+            .values()
+            .map(|recovery| recovery.stats())
+            .fold(Stats::default(), |acc, stats| Stats {
+                received: acc.received + stats.received,
+                fec_received: acc.fec_received + stats.fec_received,
+                recovered: acc.recovered + stats.recovered,
+                lost: acc.lost + stats.lost,
+            })
+    }
+}
+
+pub struct RtpFlexFecDec {
+    srcpad: gst::Pad,
+    sinkpad: gst::Pad,
+    settings: Mutex<Settings>,
+    state: Mutex<State>,
+}
+
+impl RtpFlexFecDec {
+    fn sink_chain(
+        &self,
+        pad: &gst::Pad,
+        buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let settings = self.settings.lock().unwrap().clone();
+
+        let map = buffer.map_readable().map_err(|_| {
+            gst::error!(CAT, obj = pad, "Failed to map buffer readable");
+            gst::FlowError::Error
+        })?;
+        let rtp = match RtpPacket::parse(&map) {
+            Ok(rtp) => rtp,
+            Err(err) => {
+                gst::warning!(CAT, obj = pad, "Dropping invalid RTP packet: {err:?}");
+                return Ok(gst::FlowSuccess::Ok);
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let is_fec = rtp.payload_type() as u32 == settings.pt;
+
+        let recovery = if is_fec {
+            let Some((ssrc, seqnums, parity)) = flexfec::parse_payload(rtp.payload()) else {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Dropping invalid FEC packet {}",
+                    rtp.sequence_number()
+                );
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            gst::trace!(
+                CAT,
+                imp = self,
+                "FEC packet {} protecting {seqnums:?} of SSRC {ssrc}",
+                rtp.sequence_number()
+            );
+
+            let recovery = state
+                .recoveries
+                .entry(ssrc)
+                .or_insert_with(|| Recovery::new(ssrc));
+            recovery.add_fec(&seqnums, parity);
+            recovery
+        } else {
+            let ssrc = rtp.ssrc();
+            let recovery = state
+                .recoveries
+                .entry(ssrc)
+                .or_insert_with(|| Recovery::new(ssrc));
+            if recovery.add_packet(rtp.sequence_number(), &map).is_none() {
+                gst::debug!(
+                    CAT,
+                    imp = self,
+                    "Dropping duplicate packet {} of SSRC {ssrc}",
+                    rtp.sequence_number()
+                );
+                return Ok(gst::FlowSuccess::Ok);
+            }
+            recovery
+        };
+
+        let ssrc = recovery.ssrc();
+        let outbufs = recovery
+            .recover()
+            .into_iter()
+            .map(|(ext_seqnum, data)| {
+                gst::debug!(
+                    CAT,
+                    imp = self,
+                    "Restored packet {} of SSRC {ssrc}",
+                    ext_seqnum as u16
+                );
+
+                let mut outbuf = gst::Buffer::from_mut_slice(data);
+                {
+                    let outbuf = outbuf.get_mut().unwrap();
+                    outbuf.set_pts(buffer.pts());
+                    outbuf.set_dts(buffer.dts());
+                }
+                outbuf
+            })
+            .collect::<Vec<_>>();
+        drop(state);
+        drop(map);
+
+        if !is_fec {
+            self.srcpad.push(buffer)?;
+        }
+        for outbuf in outbufs {
+            self.srcpad.push(outbuf)?;
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn sink_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        gst::log!(CAT, obj = pad, "Handling event {event:?}");
+
+        if let gst::EventView::FlushStop(_) = event.view() {
+            self.state.lock().unwrap().recoveries.clear();
+        }
+
+        gst::Pad::event_default(pad, Some(&*self.obj()), event)
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RtpFlexFecDec {
+    const NAME: &'static str = "GstRtpFlexFecDec";
+    type Type = super::RtpFlexFecDec;
+    type ParentType = gst::Element;
+
+    fn with_class(klass: &Self::Class) -> Self {
+        let templ = klass.pad_template("sink").unwrap();
+        let sinkpad = gst::Pad::builder_from_template(&templ)
+            .chain_function(|pad, parent, buffer| {
+                Self::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::FlowError::Error),
+                    |this| this.sink_chain(pad, buffer),
+                )
+            })
+            .event_function(|pad, parent, event| {
+                Self::catch_panic_pad_function(parent, || false, |this| this.sink_event(pad, event))
+            })
+            .flags(gst::PadFlags::PROXY_CAPS)
+            .build();
+
+        let templ = klass.pad_template("src").unwrap();
+        let srcpad = gst::Pad::builder_from_template(&templ)
+            .flags(gst::PadFlags::PROXY_CAPS)
+            .build();
+
+        Self {
+            srcpad,
+            sinkpad,
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(State::default()),
+        }
+    }
+}
+
+impl ObjectImpl for RtpFlexFecDec {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecUInt::builder("pt")
+                    .nick("Payload Type")
+                    .blurb("Payload type of the FEC packets")
+                    .maximum(0x7f)
+                    .default_value(DEFAULT_PT)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecBoxed::builder::<gst::Structure>("stats")
+                    .nick("Statistics")
+                    .blurb("Various statistics")
+                    .read_only()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        match pspec.name() {
+            "pt" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.pt = value.get().expect("type checked upstream");
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "pt" => {
+                let settings = self.settings.lock().unwrap();
+                settings.pt.to_value()
+            }
+            "stats" => {
+                let stats = self.state.lock().unwrap().stats();
+
+                let s = gst::Structure::builder("application/x-rtp-flexfecdec-stats")
+                    .field("received-packets", stats.received)
+                    .field("received-fec-packets", stats.fec_received)
+                    .field("recovered-packets", stats.recovered)
+                    .field("lost-packets", stats.lost)
+                    .build();
+
+                s.to_value()
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let obj = self.obj();
+        obj.add_pad(&self.sinkpad).unwrap();
+        obj.add_pad(&self.srcpad).unwrap();
+    }
+}
+
+impl GstObjectImpl for RtpFlexFecDec {}
+
+impl ElementImpl for RtpFlexFecDec {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTP FlexFEC Decoder",
+                "Codec/Depayloader/Network/RTP",
+                "Restores lost RTP packets from FlexFEC packets (RFC 8627)",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let caps = gst::Caps::builder("application/x-rtp").build();
+
+            let srcpad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            let sinkpad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            vec![srcpad_template, sinkpad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::ReadyToPaused {
+            *self.state.lock().unwrap() = State::default();
+        }
+
+        self.parent_change_state(transition)
+    }
+}
@@ -0,0 +1,401 @@
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * SECTION:element-rtpflexfecenc
+ * @see_also: rtpflexfecdec, rtpulpfecenc2, rtpsend
+ *
+ * Protects an RTP stream with FlexFEC packets as per
+ * [draft-ietf-payload-flexible-fec-scheme-03][flexfec-03], the version of
+ * [RFC 8627][rfc-8627] used by WebRTC implementations.
+ *
+ * The media packets are arranged in rows of #rtpflexfecenc:columns consecutive packets. With
+ * `row` protection, one FEC packet is created per row, which allows restoring one lost packet
+ * per row. With `column` protection, #rtpflexfecenc:rows rows form a block and one FEC packet is
+ * created per column of the block, which allows restoring bursts of up to
+ * #rtpflexfecenc:columns lost packets. `row-and-column` protection combines both.
+ *
+ * The media packets are forwarded unchanged. The FEC packets are sent in a separate stream with
+ * the SSRC configured by #rtpflexfecenc:ssrc and the payload type configured by
+ * #rtpflexfecenc:pt. Only the first SSRC seen is protected, packets of other SSRCs are
+ * forwarded without protection.
+ *
+ * The output caps carry the FEC payload type in the `fec-payload` field. When linked to the
+ * `rtp_sink` pad of `rtpsend`, this makes the session aware of the FEC packets, so that the FEC
+ * stream is reported in RTCP like any other stream.
+ *
+ * [flexfec-03]: https://datatracker.ietf.org/doc/html/draft-ietf-payload-flexible-fec-scheme-03
+ * [rfc-8627]: https://www.rfc-editor.org/rfc/rfc8627.html
+ *
+ * ## Example pipeline
+ *
+ * |[
+ * gst-launch-1.0 videotestsrc ! vp8enc ! rtpvp8pay2 ! rtpflexfecenc pt=124 protection=column columns=10 rows=5 ! udpsink host=127.0.0.1 port=5004
+ * ]| This will send a VP8 stream protected by column FlexFEC packets.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::{glib, prelude::*, subclass::prelude::*};
+use rand::prelude::*;
+use rtp_types::{RtpPacket, RtpPacketBuilder};
+use std::sync::{LazyLock, Mutex};
+
+use crate::fec::{Protection, ProtectionMatrix};
+use crate::flexfec::{self, MAX_MASK_BITS};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rtpflexfecenc",
+        gst::DebugColorFlags::empty(),
+        Some("RTP FlexFEC Encoder"),
+    )
+});
+
+const DEFAULT_PT: u32 = 124;
+const DEFAULT_SSRC: u32 = 0;
+const DEFAULT_PROTECTION: Protection = Protection::Row;
+const DEFAULT_COLUMNS: u32 = 10;
+const DEFAULT_ROWS: u32 = 4;
+
+#[derive(Debug, Clone)]
+struct Settings {
+    pt: u32,
+    ssrc: u32,
+    protection: Protection,
+    columns: u32,
+    rows: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            pt: DEFAULT_PT,
+            ssrc: DEFAULT_SSRC,
+            protection: DEFAULT_PROTECTION,
+            columns: DEFAULT_COLUMNS,
+            rows: DEFAULT_ROWS,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    media_ssrc: u32,
+    fec_ssrc: u32,
+    next_seqnum: u16,
+    matrix: ProtectionMatrix,
+}
+
+pub struct RtpFlexFecEnc {
+    srcpad: gst::Pad,
+    sinkpad: gst::Pad,
+    settings: Mutex<Settings>,
+    state: Mutex<Option<State>>,
+}
+
+impl RtpFlexFecEnc {
+    fn sink_chain(
+        &self,
+        pad: &gst::Pad,
+        buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let settings = self.settings.lock().unwrap().clone();
+
+        let map = buffer.map_readable().map_err(|_| {
+            gst::error!(CAT, obj = pad, "Failed to map buffer readable");
+            gst::FlowError::Error
+        })?;
+        let rtp = match RtpPacket::parse(&map) {
+            Ok(rtp) => rtp,
+            Err(err) => {
+                gst::warning!(CAT, obj = pad, "Dropping invalid RTP packet: {err:?}");
+                return Ok(gst::FlowSuccess::Ok);
+            }
+        };
+
+        let mut state_guard = self.state.lock().unwrap();
+        let state = state_guard.get_or_insert_with(|| {
+            let mut rng = rand::rng();
+            let fec_ssrc = if settings.ssrc != 0 {
+                settings.ssrc
+            } else {
+                loop {
+                    let ssrc = rng.random::<u32>();
+                    if ssrc != 0 && ssrc != rtp.ssrc() {
+                        break ssrc;
+                    }
+                }
+            };
+
+            let matrix = ProtectionMatrix::new(settings.protection, settings.columns, settings.rows);
+            if matrix.span() > MAX_MASK_BITS {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Packets of a column are too far apart, increase the number of rows or reduce the number of columns"
+                );
+            }
+
+            gst::debug!(
+                CAT,
+                imp = self,
+                "Protecting SSRC {} with FEC SSRC {fec_ssrc}",
+                rtp.ssrc()
+            );
+
+            State {
+                media_ssrc: rtp.ssrc(),
+                fec_ssrc,
+                next_seqnum: rng.random::<u16>(),
+                matrix,
+            }
+        });
+
+        if rtp.ssrc() != state.media_ssrc {
+            gst::trace!(CAT, imp = self, "Not protecting SSRC {}", rtp.ssrc());
+            drop(state_guard);
+            drop(map);
+            return self.srcpad.push(buffer);
+        }
+
+        let mut outbufs = Vec::new();
+        for group in state.matrix.push(rtp.sequence_number(), &map) {
+            let Some(payload) =
+                flexfec::write_payload(state.media_ssrc, &group.seqnums, &group.parity)
+            else {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Can't protect packets {:?}, too far apart",
+                    group.seqnums
+                );
+                continue;
+            };
+
+            let seqnum = state.next_seqnum;
+            let data = match RtpPacketBuilder::<&[u8], &[u8]>::new()
+                .payload_type(settings.pt as u8)
+                .ssrc(state.fec_ssrc)
+                .sequence_number(seqnum)
+                .timestamp(rtp.timestamp())
+                .payload(payload.as_slice())
+                .write_vec()
+            {
+                Ok(data) => data,
+                Err(err) => {
+                    gst::warning!(CAT, imp = self, "Failed to create FEC packet: {err:?}");
+                    continue;
+                }
+            };
+            state.next_seqnum = state.next_seqnum.wrapping_add(1);
+
+            gst::trace!(
+                CAT,
+                imp = self,
+                "Created FEC packet {seqnum} protecting {:?}",
+                group.seqnums
+            );
+
+            let mut outbuf = gst::Buffer::from_mut_slice(data);
+            {
+                let outbuf = outbuf.get_mut().unwrap();
+                outbuf.set_pts(buffer.pts());
+                outbuf.set_dts(buffer.dts());
+            }
+            outbufs.push(outbuf);
+        }
+        drop(state_guard);
+        drop(map);
+
+        self.srcpad.push(buffer)?;
+        for outbuf in outbufs {
+            self.srcpad.push(outbuf)?;
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn sink_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        gst::log!(CAT, obj = pad, "Handling event {event:?}");
+
+        match event.view() {
+            gst::EventView::Caps(caps) => {
+                let pt = self.settings.lock().unwrap().pt;
+
+                let mut caps = caps.caps_owned();
+                {
+                    let caps = caps.make_mut();
+                    if let Some(s) = caps.structure_mut(0) {
+                        s.set("fec-payload", pt as i32);
+                    }
+                }
+
+                self.srcpad.push_event(gst::event::Caps::new(&caps))
+            }
+            _ => gst::Pad::event_default(pad, Some(&*self.obj()), event),
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RtpFlexFecEnc {
+    const NAME: &'static str = "GstRtpFlexFecEnc";
+    type Type = super::RtpFlexFecEnc;
+    type ParentType = gst::Element;
+
+    fn with_class(klass: &Self::Class) -> Self {
+        let templ = klass.pad_template("sink").unwrap();
+        let sinkpad = gst::Pad::builder_from_template(&templ)
+            .chain_function(|pad, parent, buffer| {
+                Self::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::FlowError::Error),
+                    |this| this.sink_chain(pad, buffer),
+                )
+            })
+            .event_function(|pad, parent, event| {
+                Self::catch_panic_pad_function(parent, || false, |this| this.sink_event(pad, event))
+            })
+            .build();
+
+        let templ = klass.pad_template("src").unwrap();
+        let srcpad = gst::Pad::builder_from_template(&templ).build();
+
+        Self {
+            srcpad,
+            sinkpad,
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(None),
+        }
+    }
+}
+
+impl ObjectImpl for RtpFlexFecEnc {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecUInt::builder("pt")
+                    .nick("Payload Type")
+                    .blurb("Payload type of the FEC packets")
+                    .maximum(0x7f)
+                    .default_value(DEFAULT_PT)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("ssrc")
+                    .nick("SSRC")
+                    .blurb("SSRC of the FEC packets (0 = random)")
+                    .default_value(DEFAULT_SSRC)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecEnum::builder_with_default("protection", DEFAULT_PROTECTION)
+                    .nick("Protection")
+                    .blurb("Which groups of packets are protected by FEC packets")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("columns")
+                    .nick("Columns")
+                    .blurb("Number of consecutive packets in a row")
+                    .minimum(1)
+                    .maximum(MAX_MASK_BITS as u32)
+                    .default_value(DEFAULT_COLUMNS)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("rows")
+                    .nick("Rows")
+                    .blurb("Number of rows in a block for column protection")
+                    .minimum(1)
+                    .maximum(MAX_MASK_BITS as u32)
+                    .default_value(DEFAULT_ROWS)
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "pt" => settings.pt = value.get().expect("type checked upstream"),
+            "ssrc" => settings.ssrc = value.get().expect("type checked upstream"),
+            "protection" => settings.protection = value.get().expect("type checked upstream"),
+            "columns" => settings.columns = value.get().expect("type checked upstream"),
+            "rows" => settings.rows = value.get().expect("type checked upstream"),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "pt" => settings.pt.to_value(),
+            "ssrc" => settings.ssrc.to_value(),
+            "protection" => settings.protection.to_value(),
+            "columns" => settings.columns.to_value(),
+            "rows" => settings.rows.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let obj = self.obj();
+        obj.add_pad(&self.sinkpad).unwrap();
+        obj.add_pad(&self.srcpad).unwrap();
+    }
+}
+
+impl GstObjectImpl for RtpFlexFecEnc {}
+
+impl ElementImpl for RtpFlexFecEnc {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTP FlexFEC Encoder",
+                "Codec/Payloader/Network/RTP",
+                "Protects RTP packets with FlexFEC packets (RFC 8627)",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let caps = gst::Caps::builder("application/x-rtp").build();
+
+            let srcpad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            let sinkpad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            vec![srcpad_template, sinkpad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::ReadyToPaused {
+            *self.state.lock().unwrap() = None;
+        }
+
+        self.parent_change_state(transition)
+    }
+}
@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! FlexFEC payload format as described in draft-ietf-payload-flexible-fec-scheme-03, which is
+//! the version used by WebRTC implementations, with a flexible mask and a single protected SSRC.
+
+pub mod dec;
+pub mod enc;
+
+#[cfg(test)]
+mod tests;
+
+use crate::fec::Parity;
+
+const FEC_HEADER_LEN: usize = 18;
+
+/// Number of mask bits in each of the up to three mask chunks, each preceded by a k-bit
+const MASK_CHUNKS: [(usize, usize); 3] = [(15, 2), (31, 4), (63, 8)];
+
+/// Maximum number of packets a FEC packet can protect
+pub const MAX_MASK_BITS: usize = 15 + 31 + 63;
+
+/// Creates the payload of a FEC packet protecting the packets of `ssrc` with the given seqnums.
+///
+/// The first seqnum is used as base seqnum, all other seqnums must follow within
+/// [`MAX_MASK_BITS`].
+pub fn write_payload(ssrc: u32, seqnums: &[u16], parity: &Parity) -> Option<Vec<u8>> {
+    let sn_base = *seqnums.first()?;
+
+    let mut offsets = Vec::with_capacity(seqnums.len());
+    for &seqnum in seqnums {
+        let offset = seqnum.wrapping_sub(sn_base) as usize;
+        if offset >= MAX_MASK_BITS {
+            return None;
+        }
+        offsets.push(offset);
+    }
+    let max_offset = *offsets.iter().max()?;
+
+    let mut payload = Vec::with_capacity(FEC_HEADER_LEN + 14 + parity.data.len());
+
+    // R and F bits are unset for a flexible mask
+    payload.push(parity.pxcc);
+    payload.push(parity.mpt);
+    payload.extend_from_slice(&parity.length.to_be_bytes());
+    payload.extend_from_slice(&parity.timestamp.to_be_bytes());
+    // SSRC count and reserved bits
+    payload.extend_from_slice(&[1, 0, 0, 0]);
+    payload.extend_from_slice(&ssrc.to_be_bytes());
+    payload.extend_from_slice(&sn_base.to_be_bytes());
+
+    let mut first_bit = 0;
+    for (idx, &(bits, bytes)) in MASK_CHUNKS.iter().enumerate() {
+        let last = max_offset < first_bit + bits || idx == MASK_CHUNKS.len() - 1;
+
+        let mut chunk = if last { 1u64 << (bytes * 8 - 1) } else { 0 };
+        for &offset in &offsets {
+            if (first_bit..first_bit + bits).contains(&offset) {
+                chunk |= 1 << (bits - 1 - (offset - first_bit));
+            }
+        }
+        payload.extend_from_slice(&chunk.to_be_bytes()[8 - bytes..]);
+
+        if last {
+            break;
+        }
+        first_bit += bits;
+    }
+
+    payload.extend_from_slice(&parity.data);
+
+    Some(payload)
+}
+
+/// Parses the payload of a FEC packet into the protected SSRC, the protected seqnums and the
+/// parity
+pub fn parse_payload(payload: &[u8]) -> Option<(u32, Vec<u16>, Parity)> {
+    if payload.len() < FEC_HEADER_LEN {
+        return None;
+    }
+
+    // Retransmissions and fixed masks are not supported
+    if payload[0] & 0xc0 != 0 {
+        return None;
+    }
+    // Only a single protected SSRC is supported
+    if payload[8] != 1 {
+        return None;
+    }
+    let ssrc = u32::from_be_bytes([payload[12], payload[13], payload[14], payload[15]]);
+    let sn_base = u16::from_be_bytes([payload[16], payload[17]]);
+
+    let mut seqnums = Vec::new();
+    let mut offset = FEC_HEADER_LEN;
+    let mut first_bit = 0;
+    for &(bits, bytes) in MASK_CHUNKS.iter() {
+        let chunk_data = payload.get(offset..offset + bytes)?;
+        let mut chunk = [0u8; 8];
+        chunk[8 - bytes..].copy_from_slice(chunk_data);
+        let chunk = u64::from_be_bytes(chunk);
+        offset += bytes;
+
+        for bit in 0..bits {
+            if chunk & (1 << (bits - 1 - bit)) != 0 {
+                seqnums.push(sn_base.wrapping_add((first_bit + bit) as u16));
+            }
+        }
+
+        // k-bit
+        if chunk & (1 << (bytes * 8 - 1)) != 0 {
+            break;
+        }
+        first_bit += bits;
+    }
+
+    if seqnums.is_empty() {
+        return None;
+    }
+
+    let parity = Parity {
+        pxcc: payload[0] & 0x3f,
+        mpt: payload[1],
+        length: u16::from_be_bytes([payload[2], payload[3]]),
+        timestamp: u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]),
+        data: payload[offset..].to_vec(),
+    };
+
+    Some((ssrc, seqnums, parity))
+}
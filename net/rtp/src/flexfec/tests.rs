@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::fec::{tests::generate_packet, Parity};
+use gst::prelude::*;
+use gst_check::Harness;
+use rtp_types::RtpPacket;
+
+fn init() {
+    use std::sync::Once;
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        gst::init().unwrap();
+        crate::plugin_register_static().expect("rtpflexfec test");
+    });
+}
+
+#[test]
+fn flexfec_mask_chunks() {
+    let mut parity = Parity::default();
+    parity.add(&generate_packet(100, 1000, 8));
+    parity.add(&generate_packet(101, 1000, 12));
+
+    // Header length without the mask, and the size of the mask depending on the highest offset
+    for (seqnums, mask_len) in [
+        (vec![65530, 65531, 65544], 2),
+        (vec![65530, 10], 6),
+        (vec![10, 11, 60, 118], 14),
+    ] {
+        let payload = super::write_payload(0x1234, &seqnums, &parity).unwrap();
+        assert_eq!(payload.len(), 18 + mask_len + parity.data.len());
+
+        let (ssrc, parsed_seqnums, parsed_parity) = super::parse_payload(&payload).unwrap();
+        assert_eq!(ssrc, 0x1234);
+        assert_eq!(parsed_seqnums, seqnums);
+        assert_eq!(parsed_parity, parity);
+    }
+
+    // Packets that are too far apart can't be protected
+    assert!(super::write_payload(0x1234, &[10, 119], &parity).is_none());
+}
+
+#[test]
+fn flexfec_restore_lost_packets() {
+    init();
+
+    let caps = gst::Caps::builder("application/x-rtp")
+        .field("media", "video")
+        .field("payload", 96)
+        .field("clock-rate", 90000)
+        .field("encoding-name", "VP8")
+        .build();
+
+    let mut enc = Harness::new("rtpflexfecenc");
+    {
+        let enc = enc.element().unwrap();
+        enc.set_property_from_str("protection", "row-and-column");
+        enc.set_property("columns", 3u32);
+        enc.set_property("rows", 2u32);
+        enc.set_property("ssrc", 0x5678u32);
+    }
+    enc.set_src_caps(caps.clone());
+    enc.play();
+
+    let media = (0..6u16)
+        .map(|seqnum| generate_packet(seqnum, seqnum as u32 * 3000, 10 + seqnum as usize))
+        .collect::<Vec<_>>();
+    for packet in &media {
+        enc.push(gst::Buffer::from_mut_slice(packet.clone()))
+            .unwrap();
+    }
+    enc.push_event(gst::event::Eos::new());
+
+    let fec_caps = enc.sinkpad().unwrap().current_caps().unwrap();
+    assert_eq!(
+        fec_caps.structure(0).unwrap().get::<i32>("fec-payload"),
+        Ok(124)
+    );
+
+    let mut packets = Vec::new();
+    while let Some(buffer) = enc.try_pull() {
+        packets.push(buffer.map_readable().unwrap().to_vec());
+    }
+
+    let fec_packets = packets
+        .iter()
+        .filter(|packet| {
+            let rtp = RtpPacket::parse(packet.as_slice()).unwrap();
+            rtp.payload_type() == 124 && rtp.ssrc() == 0x5678
+        })
+        .count();
+    assert_eq!(packets.len(), 6 + fec_packets);
+    // Two rows and three columns
+    assert_eq!(fec_packets, 5);
+
+    let mut dec = Harness::new("rtpflexfecdec");
+    dec.set_src_caps(caps);
+    dec.play();
+
+    // Drop two packets of the same row, which can only be restored with the columns
+    for packet in packets
+        .iter()
+        .filter(|packet| **packet != media[1] && **packet != media[2])
+    {
+        dec.push(gst::Buffer::from_mut_slice(packet.clone()))
+            .unwrap();
+    }
+    dec.push_event(gst::event::Eos::new());
+
+    let mut output = Vec::new();
+    while let Some(buffer) = dec.try_pull() {
+        output.push(buffer.map_readable().unwrap().to_vec());
+    }
+    output.sort_by_key(|packet| {
+        RtpPacket::parse(packet.as_slice())
+            .unwrap()
+            .sequence_number()
+    });
+    assert_eq!(output, media);
+
+    let stats = dec.element().unwrap().property::<gst::Structure>("stats");
+    assert_eq!(stats.get::<u64>("received-packets"), Ok(4));
+    assert_eq!(stats.get::<u64>("received-fec-packets"), Ok(5));
+    assert_eq!(stats.get::<u64>("recovered-packets"), Ok(2));
+}
@@ -3,9 +3,10 @@
  *
  * Implements the [Google Congestion Control algorithm](https://datatracker.ietf.org/doc/html/draft-ietf-rmcat-gcc-02).
  *
- * This element should always be placed right before a `rtpsession` and will only work
+ * This element should always be placed right before a `rtpsession` or `rtpsend` and will only work
  * when [twcc](https://datatracker.ietf.org/doc/html/draft-holmer-rmcat-transport-wide-cc-extensions-01) is enabled
- * as the bandwidth estimation relies on it.
+ * as the bandwidth estimation relies on it. With `rtpsend`, this is done with the
+ * `twcc-extension-id` property.
  *
  * This element implements the pacing as describe in the spec by running its
  * own streaming thread on its srcpad. It implements the mathematic as closely
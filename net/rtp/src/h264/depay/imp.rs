@@ -0,0 +1,615 @@
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * SECTION:element-rtph264depay2
+ * @see_also: rtph264pay2, rtph264depay, rtph264pay, h264parse, avdec_h264
+ *
+ * Depayload an H.264 video stream from RTP packets as per [RFC 6184][rfc-6184].
+ *
+ * Single NAL unit packets, STAP-A and FU-A packets are supported, i.e. packetization modes 0
+ * and 1. The interleaved packetization mode is not supported.
+ *
+ * The parameter sets from the `sprop-parameter-sets` caps field are inserted in-band before the
+ * first IDR frame if it does not contain its own parameter sets, or are signalled via the
+ * `codec_data` if `avc` output is negotiated.
+ *
+ * [rfc-6184]: https://www.rfc-editor.org/rfc/rfc6184.html
+ *
+ * ## Example pipeline
+ *
+ * ```shell
+ * gst-launch-1.0 udpsrc address=127.0.0.1 port=5004 caps='application/x-rtp,media=video,clock-rate=90000,encoding-name=H264' ! rtpjitterbuffer latency=100 ! rtph264depay2 ! decodebin3 ! videoconvertscale ! autovideosink
+ * ```
+ *
+ * This will depayload and decode an incoming RTP H.264 video stream. You can use the
+ * #rtph264pay2 element to create such an RTP stream.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use atomic_refcell::AtomicRefCell;
+use gst::{glib, prelude::*, subclass::prelude::*};
+use std::sync::{LazyLock, Mutex};
+
+use crate::{
+    basedepay::{PacketToBufferRelation, RtpBaseDepay2Ext, TimestampOffset},
+    h264::nal::{
+        nal_type, ParameterSets, NAL_TYPE_FU_A, NAL_TYPE_FU_B, NAL_TYPE_IDR, NAL_TYPE_MTAP16,
+        NAL_TYPE_MTAP24, NAL_TYPE_PPS, NAL_TYPE_SPS, NAL_TYPE_STAP_A, NAL_TYPE_STAP_B,
+    },
+    h26x::{StreamFormat, START_CODE},
+};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rtph264depay2",
+        gst::DebugColorFlags::empty(),
+        Some("RTP H.264 Depayloader"),
+    )
+});
+
+#[derive(Clone, Default)]
+struct Settings {
+    request_keyframe: bool,
+    wait_for_keyframe: bool,
+}
+
+/// Access unit that is currently reconstructed.
+struct PendingAu {
+    start_ext_seqnum: u64,
+    end_ext_seqnum: u64,
+    ext_timestamp: u64,
+    nals: Vec<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Negotiated output stream format and whether output is access unit aligned.
+    output_format: Option<(StreamFormat, bool)>,
+    /// Set if new caps have to be sent downstream before the next buffer.
+    caps_pending: bool,
+
+    /// Last known SPS and PPS, either from the caps or in-band.
+    parameter_sets: ParameterSets,
+    /// Whether the parameter sets were sent since the last discontinuity.
+    parameter_sets_sent: bool,
+
+    pending_au: Option<PendingAu>,
+    /// NAL unit currently reassembled from FU-A packets.
+    pending_fu: Option<Vec<u8>>,
+
+    /// Whether a keyframe was output since the last discontinuity.
+    seen_keyframe: bool,
+}
+
+#[derive(Default)]
+pub struct RtpH264Depay {
+    settings: Mutex<Settings>,
+    state: AtomicRefCell<State>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RtpH264Depay {
+    const NAME: &'static str = "GstRtpH264Depay2";
+    type Type = super::RtpH264Depay;
+    type ParentType = crate::basedepay::RtpBaseDepay2;
+}
+
+impl ObjectImpl for RtpH264Depay {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecBoolean::builder("request-keyframe")
+                    .nick("Request Keyframe")
+                    .blurb("Request new keyframe when packet loss is detected")
+                    .default_value(Settings::default().request_keyframe)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecBoolean::builder("wait-for-keyframe")
+                    .nick("Wait For Keyframe")
+                    .blurb("Wait for the next keyframe after packet loss")
+                    .default_value(Settings::default().wait_for_keyframe)
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        match pspec.name() {
+            "request-keyframe" => {
+                self.settings.lock().unwrap().request_keyframe = value.get().unwrap();
+            }
+            "wait-for-keyframe" => {
+                self.settings.lock().unwrap().wait_for_keyframe = value.get().unwrap();
+            }
+            _ => unimplemented!(),
+        };
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "request-keyframe" => self.settings.lock().unwrap().request_keyframe.to_value(),
+            "wait-for-keyframe" => self.settings.lock().unwrap().wait_for_keyframe.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+impl GstObjectImpl for RtpH264Depay {}
+
+impl ElementImpl for RtpH264Depay {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTP H.264 Depayloader",
+                "Codec/Depayloader/Network/RTP",
+                "Depayload H.264 from RTP packets (RFC 6184)",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let sink_pad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &gst::Caps::builder("application/x-rtp")
+                    .field("media", "video")
+                    .field("clock-rate", 90_000i32)
+                    .field("encoding-name", "H264")
+                    .build(),
+            )
+            .unwrap();
+
+            let src_pad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &gst::Caps::builder_full()
+                    .structure(
+                        gst::Structure::builder("video/x-h264")
+                            .field("stream-format", "byte-stream")
+                            .field("alignment", gst::List::new(["au", "nal"]))
+                            .build(),
+                    )
+                    .structure(
+                        gst::Structure::builder("video/x-h264")
+                            .field("stream-format", "avc")
+                            .field("alignment", "au")
+                            .build(),
+                    )
+                    .build(),
+            )
+            .unwrap();
+
+            vec![src_pad_template, sink_pad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+}
+
+impl crate::basedepay::RtpBaseDepay2Impl for RtpH264Depay {
+    const ALLOWED_META_TAGS: &'static [&'static str] = &["video"];
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.borrow_mut() = State::default();
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.borrow_mut() = State::default();
+
+        Ok(())
+    }
+
+    fn set_sink_caps(&self, caps: &gst::Caps) -> bool {
+        gst::debug!(CAT, imp = self, "received caps {caps:?}");
+
+        let s = caps.structure(0).unwrap();
+        let mut state = self.state.borrow_mut();
+
+        if let Ok(sprop) = s.get::<&str>("sprop-parameter-sets") {
+            match ParameterSets::from_sprop_parameter_sets(sprop) {
+                Ok(parameter_sets) => {
+                    gst::debug!(
+                        CAT,
+                        imp = self,
+                        "Parameter sets from caps {parameter_sets:?}"
+                    );
+                    state.parameter_sets = parameter_sets;
+                    state.parameter_sets_sent = false;
+                }
+                Err(err) => {
+                    gst::warning!(
+                        CAT,
+                        imp = self,
+                        "Failed to parse sprop-parameter-sets: {err}"
+                    );
+                }
+            }
+        }
+
+        // Renegotiate with downstream before the next buffer
+        state.output_format = None;
+
+        true
+    }
+
+    fn drain(&self) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let settings = self.settings.lock().unwrap().clone();
+        let mut state = self.state.borrow_mut();
+
+        let res = self.finish_au(&settings, &mut state);
+        self.reset(&mut state);
+
+        res
+    }
+
+    fn flush(&self) {
+        let mut state = self.state.borrow_mut();
+        self.reset(&mut state);
+    }
+
+    fn handle_packet(
+        &self,
+        packet: &crate::basedepay::Packet,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let settings = self.settings.lock().unwrap().clone();
+        let mut state = self.state.borrow_mut();
+
+        gst::trace!(CAT, imp = self, "Handling RTP packet {packet:?}");
+
+        // A different timestamp means that the previous access unit is finished even if its last
+        // packet with the marker bit got lost.
+        if state
+            .pending_au
+            .as_ref()
+            .is_some_and(|au| au.ext_timestamp != packet.ext_timestamp())
+        {
+            gst::debug!(CAT, imp = self, "Timestamp changed without marker bit");
+            self.finish_au(&settings, &mut state)?;
+        }
+
+        let payload = packet.payload();
+        let Some(&header) = payload.first() else {
+            gst::warning!(CAT, imp = self, "Empty packet");
+            self.drop_au_and_packet(&mut state, packet);
+            return Ok(gst::FlowSuccess::Ok);
+        };
+
+        match nal_type(header) {
+            1..=23 => {
+                gst::trace!(CAT, imp = self, "Single NAL unit packet");
+                self.discard_pending_fu(&mut state);
+                self.pending_au(&mut state, packet)
+                    .nals
+                    .push(payload.to_vec());
+            }
+            NAL_TYPE_STAP_A => {
+                gst::trace!(CAT, imp = self, "STAP-A packet");
+                self.discard_pending_fu(&mut state);
+
+                let mut nals = Vec::new();
+                let mut data = &payload[1..];
+                while !data.is_empty() {
+                    let len = match data {
+                        [a, b, ..] => u16::from_be_bytes([*a, *b]) as usize,
+                        _ => 0,
+                    };
+                    if len == 0 || data.len() < 2 + len {
+                        gst::warning!(CAT, imp = self, "Invalid STAP-A packet");
+                        self.drop_au_and_packet(&mut state, packet);
+                        return Ok(gst::FlowSuccess::Ok);
+                    }
+
+                    nals.push(data[2..][..len].to_vec());
+                    data = &data[2 + len..];
+                }
+
+                self.pending_au(&mut state, packet).nals.extend(nals);
+            }
+            NAL_TYPE_FU_A => {
+                let Some(&fu_header) = payload.get(1) else {
+                    gst::warning!(CAT, imp = self, "Invalid FU-A packet");
+                    self.drop_au_and_packet(&mut state, packet);
+                    return Ok(gst::FlowSuccess::Ok);
+                };
+                let start = fu_header & 0x80 != 0;
+                let end = fu_header & 0x40 != 0;
+
+                gst::trace!(CAT, imp = self, "FU-A packet, start {start}, end {end}");
+
+                if start {
+                    self.discard_pending_fu(&mut state);
+
+                    let mut nal = Vec::with_capacity(payload.len() - 1);
+                    nal.push((header & 0xe0) | nal_type(fu_header));
+                    nal.extend_from_slice(&payload[2..]);
+                    state.pending_fu = Some(nal);
+                } else if let Some(ref mut nal) = state.pending_fu {
+                    nal.extend_from_slice(&payload[2..]);
+                } else {
+                    gst::debug!(CAT, imp = self, "Waiting for start of FU-A");
+                    self.drop_au_and_packet(&mut state, packet);
+                    return Ok(gst::FlowSuccess::Ok);
+                }
+
+                self.pending_au(&mut state, packet);
+                if end {
+                    let nal = state.pending_fu.take().unwrap();
+                    state.pending_au.as_mut().unwrap().nals.push(nal);
+                }
+            }
+            NAL_TYPE_STAP_B | NAL_TYPE_MTAP16 | NAL_TYPE_MTAP24 | NAL_TYPE_FU_B => {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Interleaved packetization mode not supported"
+                );
+                self.drop_au_and_packet(&mut state, packet);
+                return Ok(gst::FlowSuccess::Ok);
+            }
+            nal_type => {
+                gst::warning!(CAT, imp = self, "Invalid NAL unit type {nal_type}");
+                self.drop_au_and_packet(&mut state, packet);
+                return Ok(gst::FlowSuccess::Ok);
+            }
+        }
+
+        // The marker bit is set for the last packet of an access unit.
+        if packet.marker_bit() {
+            self.finish_au(&settings, &mut state)?;
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+}
+
+impl RtpH264Depay {
+    fn reset(&self, state: &mut State) {
+        gst::debug!(CAT, imp = self, "resetting state");
+
+        state.pending_au = None;
+        state.pending_fu = None;
+        state.parameter_sets_sent = false;
+        state.seen_keyframe = false;
+    }
+
+    /// Returns the pending access unit and extends it by the given packet.
+    fn pending_au<'a>(
+        &self,
+        state: &'a mut State,
+        packet: &crate::basedepay::Packet,
+    ) -> &'a mut PendingAu {
+        let au = state.pending_au.get_or_insert_with(|| PendingAu {
+            start_ext_seqnum: packet.ext_seqnum(),
+            end_ext_seqnum: packet.ext_seqnum(),
+            ext_timestamp: packet.ext_timestamp(),
+            nals: Vec::new(),
+        });
+        au.end_ext_seqnum = packet.ext_seqnum();
+
+        au
+    }
+
+    fn discard_pending_fu(&self, state: &mut State) {
+        if state.pending_fu.take().is_some() {
+            gst::warning!(CAT, imp = self, "Discarding incomplete FU-A");
+        }
+    }
+
+    /// Drops the pending access unit together with the given packet.
+    fn drop_au_and_packet(&self, state: &mut State, packet: &crate::basedepay::Packet) {
+        state.pending_au = None;
+        state.pending_fu = None;
+        self.obj().drop_packet(packet);
+    }
+
+    /// Negotiates the output format with downstream if necessary.
+    fn output_format(&self, state: &mut State) -> (StreamFormat, bool) {
+        if let Some(output_format) = state.output_format {
+            return output_format;
+        }
+
+        let src_pad = self.obj().src_pad();
+        let caps = src_pad.peer_query_caps(Some(&src_pad.pad_template_caps()));
+        gst::debug!(CAT, imp = self, "Downstream caps {caps:?}");
+
+        let mut output_format = (StreamFormat::ByteStream, true);
+        if let Some(s) = caps.structure(0) {
+            let mut s = s.to_owned();
+            s.fixate_field_str("stream-format", "byte-stream");
+            s.fixate_field_str("alignment", "au");
+
+            if s.get::<&str>("stream-format") == Ok("avc") {
+                output_format = (StreamFormat::LengthPrefixed(4), true);
+            } else if s.get::<&str>("alignment") == Ok("nal") {
+                output_format = (StreamFormat::ByteStream, false);
+            }
+        }
+
+        gst::debug!(
+            CAT,
+            imp = self,
+            "Negotiated output format {output_format:?}"
+        );
+        state.output_format = Some(output_format);
+        state.caps_pending = true;
+
+        output_format
+    }
+
+    /// Outputs the pending access unit, if any.
+    fn finish_au(
+        &self,
+        settings: &Settings,
+        state: &mut State,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let Some(au) = state.pending_au.take() else {
+            return Ok(gst::FlowSuccess::Ok);
+        };
+        self.discard_pending_fu(state);
+
+        let seqnums = au.start_ext_seqnum..=au.end_ext_seqnum;
+        if au.nals.is_empty() {
+            self.obj().drop_packets(seqnums);
+            return Ok(gst::FlowSuccess::Ok);
+        }
+
+        let mut is_keyframe = false;
+        let mut has_sps = false;
+        let mut has_pps = false;
+        let mut parameter_sets_changed = false;
+        for nal in &au.nals {
+            match nal_type(nal[0]) {
+                NAL_TYPE_IDR => is_keyframe = true,
+                t @ (NAL_TYPE_SPS | NAL_TYPE_PPS) => {
+                    has_sps |= t == NAL_TYPE_SPS;
+                    has_pps |= t == NAL_TYPE_PPS;
+
+                    match state.parameter_sets.insert(nal) {
+                        Ok(changed) => parameter_sets_changed |= changed,
+                        Err(err) => {
+                            gst::warning!(CAT, imp = self, "Failed to parse parameter set: {err}");
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // If necessary wait for a keyframe after a discontinuity and/or request one from
+        // upstream.
+        if !is_keyframe && !state.seen_keyframe {
+            if settings.request_keyframe {
+                gst::debug!(CAT, imp = self, "Requesting keyframe from upstream");
+                let event = gst_video::UpstreamForceKeyUnitEvent::builder()
+                    .all_headers(true)
+                    .build();
+                let _ = self.obj().sink_pad().push_event(event);
+            }
+
+            if settings.wait_for_keyframe {
+                gst::trace!(CAT, imp = self, "Waiting for keyframe");
+                self.obj().drop_packets(seqnums);
+                return Ok(gst::FlowSuccess::Ok);
+            }
+        }
+        state.seen_keyframe |= is_keyframe;
+
+        let (stream_format, alignment_au) = self.output_format(state);
+        let is_avc = matches!(stream_format, StreamFormat::LengthPrefixed(_));
+
+        if state.caps_pending || (is_avc && parameter_sets_changed) {
+            let mut caps_builder = gst::Caps::builder("video/x-h264")
+                .field("stream-format", if is_avc { "avc" } else { "byte-stream" })
+                .field("alignment", if alignment_au { "au" } else { "nal" });
+
+            if is_avc {
+                let Some(codec_data) = state.parameter_sets.avc_decoder_configuration() else {
+                    gst::warning!(CAT, imp = self, "No SPS and PPS known yet");
+                    self.obj().drop_packets(seqnums);
+                    return Ok(gst::FlowSuccess::Ok);
+                };
+                caps_builder =
+                    caps_builder.field("codec_data", gst::Buffer::from_mut_slice(codec_data));
+            }
+
+            self.obj().set_src_caps(&caps_builder.build());
+            state.caps_pending = false;
+        }
+
+        let mut nals = Vec::with_capacity(au.nals.len() + 2);
+        if is_avc {
+            // Parameter sets are only signalled via the codec_data
+            nals.extend(
+                au.nals
+                    .iter()
+                    .map(Vec::as_slice)
+                    .filter(|nal| !matches!(nal_type(nal[0]), NAL_TYPE_SPS | NAL_TYPE_PPS)),
+            );
+        } else {
+            if is_keyframe && !state.parameter_sets_sent && !(has_sps && has_pps) {
+                gst::debug!(CAT, imp = self, "Inserting parameter sets before keyframe");
+                nals.extend(state.parameter_sets.iter());
+            }
+            nals.extend(au.nals.iter().map(Vec::as_slice));
+        }
+        state.parameter_sets_sent |= is_keyframe;
+
+        if nals.is_empty() {
+            self.obj().drop_packets(seqnums);
+            return Ok(gst::FlowSuccess::Ok);
+        }
+
+        let write_nal = |data: &mut Vec<u8>, nal: &[u8]| {
+            if is_avc {
+                data.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+            } else {
+                data.extend_from_slice(&START_CODE);
+            }
+            data.extend_from_slice(nal);
+        };
+
+        let mut buffers = Vec::new();
+        if alignment_au {
+            let mut data = Vec::with_capacity(nals.iter().map(|nal| 4 + nal.len()).sum());
+            for nal in &nals {
+                write_nal(&mut data, nal);
+            }
+            buffers.push(gst::Buffer::from_mut_slice(data));
+        } else {
+            for nal in &nals {
+                let mut data = Vec::with_capacity(4 + nal.len());
+                write_nal(&mut data, nal);
+                buffers.push(gst::Buffer::from_mut_slice(data));
+            }
+        }
+
+        gst::trace!(
+            CAT,
+            imp = self,
+            "Finishing {} with {} NAL units",
+            if is_keyframe {
+                "keyframe"
+            } else {
+                "delta-frame"
+            },
+            nals.len()
+        );
+
+        let n_buffers = buffers.len();
+        for (idx, mut buffer) in buffers.into_iter().enumerate() {
+            {
+                let buffer = buffer.get_mut().unwrap();
+                if !is_keyframe {
+                    buffer.set_flags(gst::BufferFlags::DELTA_UNIT);
+                }
+
+                // Set MARKER flag on the output so that the parser knows that this buffer ends
+                // an access unit.
+                if idx == n_buffers - 1 {
+                    buffer.set_flags(gst::BufferFlags::MARKER);
+                }
+            }
+
+            self.obj().queue_buffer(
+                PacketToBufferRelation::SeqnumsWithOffset {
+                    seqnums: seqnums.clone(),
+                    timestamp_offset: TimestampOffset::Pts(gst::ClockTime::ZERO.into_positive()),
+                },
+                buffer,
+            )?;
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+}
@@ -0,0 +1,20 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::glib;
+use gst::prelude::*;
+
+pub mod imp;
+
+glib::wrapper! {
+    pub struct RtpH264Depay(ObjectSubclass<imp::RtpH264Depay>)
+        @extends crate::basedepay::RtpBaseDepay2, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "rtph264depay2",
+        gst::Rank::MARGINAL,
+        RtpH264Depay::static_type(),
+    )
+}
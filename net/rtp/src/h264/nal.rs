@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context as _};
+use bitstream_io::{BigEndian, BitRead as _, BitReader};
+use gst::glib;
+
+use crate::h26x::{read_ue, remove_emulation_prevention};
+
+pub const NAL_TYPE_IDR: u8 = 5;
+pub const NAL_TYPE_SPS: u8 = 7;
+pub const NAL_TYPE_PPS: u8 = 8;
+pub const NAL_TYPE_AUD: u8 = 9;
+pub const NAL_TYPE_STAP_A: u8 = 24;
+pub const NAL_TYPE_STAP_B: u8 = 25;
+pub const NAL_TYPE_MTAP16: u8 = 26;
+pub const NAL_TYPE_MTAP24: u8 = 27;
+pub const NAL_TYPE_FU_A: u8 = 28;
+pub const NAL_TYPE_FU_B: u8 = 29;
+
+/// Returns the type of a NAL unit from its header byte.
+pub fn nal_type(header: u8) -> u8 {
+    header & 0x1f
+}
+
+fn parse_sps_id(nal: &[u8]) -> anyhow::Result<u32> {
+    if nal.len() < 5 {
+        bail!("SPS too short");
+    }
+
+    // Only the beginning of the SPS is needed for the id
+    let data = remove_emulation_prevention(&nal[1..nal.len().min(16)]);
+    let mut r = BitReader::endian(data.as_slice(), BigEndian);
+    // profile_idc, constraint flags and level_idc
+    r.skip(24).context("profile_level")?;
+    read_ue(&mut r).context("seq_parameter_set_id")
+}
+
+fn parse_pps_id(nal: &[u8]) -> anyhow::Result<u32> {
+    if nal.len() < 2 {
+        bail!("PPS too short");
+    }
+
+    let data = remove_emulation_prevention(&nal[1..nal.len().min(16)]);
+    let mut r = BitReader::endian(data.as_slice(), BigEndian);
+    read_ue(&mut r).context("pic_parameter_set_id")
+}
+
+/// SPS and PPS of a stream, indexed by their ids.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ParameterSets {
+    sps: BTreeMap<u32, Vec<u8>>,
+    pps: BTreeMap<u32, Vec<u8>>,
+}
+
+impl ParameterSets {
+    /// Parses the parameter sets from the `sprop-parameter-sets` caps field.
+    pub fn from_sprop_parameter_sets(sprop: &str) -> anyhow::Result<Self> {
+        let mut parameter_sets = ParameterSets::default();
+
+        for nal in sprop.split(',').filter(|s| !s.is_empty()) {
+            let nal = glib::base64_decode(nal);
+            if nal.is_empty() {
+                bail!("Invalid base64 parameter set");
+            }
+            parameter_sets.insert(&nal)?;
+        }
+
+        Ok(parameter_sets)
+    }
+
+    /// Parses the parameter sets and NAL unit length size from an `AVCDecoderConfigurationRecord`.
+    pub fn from_avc_decoder_configuration(data: &[u8]) -> anyhow::Result<(Self, usize)> {
+        if data.len() < 7 || data[0] != 1 {
+            bail!("Invalid AVCDecoderConfigurationRecord");
+        }
+
+        let nal_length_size = (data[4] & 0x03) as usize + 1;
+
+        let mut parameter_sets = ParameterSets::default();
+        let mut data = &data[5..];
+        for mask in [0x1f, 0xff] {
+            let Some((&count, rest)) = data.split_first() else {
+                bail!("Truncated AVCDecoderConfigurationRecord");
+            };
+            data = rest;
+
+            for _ in 0..(count & mask) {
+                if data.len() < 2 {
+                    bail!("Truncated AVCDecoderConfigurationRecord");
+                }
+                let len = u16::from_be_bytes([data[0], data[1]]) as usize;
+                if data.len() < 2 + len {
+                    bail!("Truncated AVCDecoderConfigurationRecord");
+                }
+                parameter_sets.insert(&data[2..][..len])?;
+                data = &data[2 + len..];
+            }
+        }
+
+        Ok((parameter_sets, nal_length_size))
+    }
+
+    /// Stores the NAL unit if it is an SPS or PPS.
+    ///
+    /// Returns `true` if the stored parameter sets changed.
+    pub fn insert(&mut self, nal: &[u8]) -> anyhow::Result<bool> {
+        let Some(&header) = nal.first() else {
+            return Ok(false);
+        };
+
+        let (map, id) = match nal_type(header) {
+            NAL_TYPE_SPS => (&mut self.sps, parse_sps_id(nal)?),
+            NAL_TYPE_PPS => (&mut self.pps, parse_pps_id(nal)?),
+            _ => return Ok(false),
+        };
+
+        if map.get(&id).is_some_and(|old| old == nal) {
+            return Ok(false);
+        }
+        map.insert(id, nal.to_vec());
+
+        Ok(true)
+    }
+
+    /// Returns `true` if at least one SPS and PPS are known.
+    pub fn is_complete(&self) -> bool {
+        !self.sps.is_empty() && !self.pps.is_empty()
+    }
+
+    /// Iterates over all SPS followed by all PPS.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.sps
+            .values()
+            .chain(self.pps.values())
+            .map(Vec::as_slice)
+    }
+
+    /// Value for the `sprop-parameter-sets` caps field.
+    pub fn sprop_parameter_sets(&self) -> Option<String> {
+        if !self.is_complete() {
+            return None;
+        }
+
+        Some(
+            self.iter()
+                .map(|nal| glib::base64_encode(nal).to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+    }
+
+    /// Value for the `profile-level-id` caps field, taken from the first SPS.
+    pub fn profile_level_id(&self) -> Option<String> {
+        let sps = self.sps.values().next()?;
+
+        Some(format!("{:02x}{:02x}{:02x}", sps[1], sps[2], sps[3]))
+    }
+
+    /// Creates an `AVCDecoderConfigurationRecord` with 4 byte NAL unit lengths.
+    pub fn avc_decoder_configuration(&self) -> Option<Vec<u8>> {
+        if !self.is_complete() {
+            return None;
+        }
+
+        let sps = self.sps.values().next().unwrap();
+
+        let mut data = vec![1, sps[1], sps[2], sps[3], 0xfc | 0x03];
+        for (count_bits, map) in [(0xe0, &self.sps), (0x00, &self.pps)] {
+            data.push(count_bits | map.len() as u8);
+            for nal in map.values() {
+                data.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+                data.extend_from_slice(nal);
+            }
+        }
+
+        Some(data)
+    }
+}
@@ -0,0 +1,626 @@
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * SECTION:element-rtph264pay2
+ * @see_also: rtph264depay2, rtph264pay, rtph264depay, x264enc, h264parse
+ *
+ * Payload an H.264 video stream into RTP packets as per [RFC 6184][rfc-6184].
+ *
+ * NAL units that fit into a single packet are sent as single NAL unit packets and bigger NAL
+ * units are fragmented into FU-A packets. Depending on the `aggregate-mode` property, small NAL
+ * units of the same access unit are combined into STAP-A packets.
+ *
+ * The SPS and PPS are signalled via the `sprop-parameter-sets` field of the caps, which is
+ * updated whenever the parameter sets change. Via the `config-interval` property they can
+ * additionally be inserted in-band before IDR frames.
+ *
+ * If the input has `nal` alignment then the end of an access unit has to be signalled via the
+ * `MARKER` buffer flag, as done by `h264parse`.
+ *
+ * [rfc-6184]: https://www.rfc-editor.org/rfc/rfc6184.html
+ *
+ * ## Aggregation Modes
+ *
+ * With the default aggregation mode `zero-latency` only NAL units of the same input buffer are
+ * aggregated. Nothing is ever kept back until the next input buffer arrives.
+ *
+ * With the aggregation mode `max` all NAL units of the same access unit are aggregated, which
+ * only makes a difference for input with `nal` alignment.
+ *
+ * With the aggregation mode `none` no STAP-A packets are created at all.
+ *
+ * ## Example pipeline
+ *
+ * |[
+ * gst-launch-1.0 videotestsrc ! video/x-raw,width=1280,height=720,format=I420 ! x264enc tune=zerolatency ! h264parse ! rtph264pay2 ! udpsink host=127.0.0.1 port=5004
+ * ]| This will encode a test pattern as H.264, payload it into RTP packets and send them out
+ * via UDP to localhost port 5004.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use atomic_refcell::AtomicRefCell;
+use gst::{glib, prelude::*, subclass::prelude::*};
+use smallvec::SmallVec;
+use std::{
+    mem,
+    ops::Range,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use crate::{
+    basepay::{PacketToBufferRelation, RtpBasePay2Ext},
+    h264::nal::{
+        nal_type, ParameterSets, NAL_TYPE_AUD, NAL_TYPE_FU_A, NAL_TYPE_IDR, NAL_TYPE_PPS,
+        NAL_TYPE_SPS, NAL_TYPE_STAP_A,
+    },
+    h26x::StreamFormat,
+};
+
+use super::AggregateMode;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rtph264pay2",
+        gst::DebugColorFlags::empty(),
+        Some("RTP H.264 Payloader"),
+    )
+});
+
+#[derive(Clone)]
+struct Settings {
+    aggregate_mode: AggregateMode,
+    config_interval: i32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            aggregate_mode: AggregateMode::default(),
+            config_interval: 0,
+        }
+    }
+}
+
+/// NAL unit that is waiting to be packetized.
+struct QueuedNal {
+    /// Id of the input buffer this NAL unit came from.
+    id: u64,
+    buffer: Arc<gst::MappedBuffer<gst::buffer::Readable>>,
+    range: Range<usize>,
+}
+
+impl QueuedNal {
+    fn data(&self) -> &[u8] {
+        &self.buffer[self.range.clone()]
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Input stream format, set from the caps.
+    stream_format: Option<StreamFormat>,
+    /// `true` if every input buffer contains a complete access unit.
+    alignment_au: bool,
+
+    /// Last known SPS and PPS.
+    parameter_sets: ParameterSets,
+    /// PTS of the last access unit that contained parameter sets.
+    last_parameter_sets_pts: Option<gst::ClockTime>,
+    /// Whether the current access unit already contains parameter sets.
+    au_has_parameter_sets: bool,
+
+    /// NAL units of the current access unit that are not packetized yet.
+    queued_nals: Vec<QueuedNal>,
+    /// PTS of the queued NAL units.
+    queued_pts: Option<gst::ClockTime>,
+}
+
+#[derive(Default)]
+pub struct RtpH264Pay {
+    settings: Mutex<Settings>,
+    state: AtomicRefCell<State>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RtpH264Pay {
+    const NAME: &'static str = "GstRtpH264Pay2";
+    type Type = super::RtpH264Pay;
+    type ParentType = crate::basepay::RtpBasePay2;
+}
+
+impl ObjectImpl for RtpH264Pay {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecEnum::builder::<AggregateMode>("aggregate-mode")
+                    .nick("Aggregate Mode")
+                    .blurb("Which NAL units to aggregate into STAP-A packets")
+                    .default_value(Settings::default().aggregate_mode)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecInt::builder("config-interval")
+                    .nick("Config Interval")
+                    .blurb("Send SPS and PPS in-band before IDR frames every this many seconds (0 = disabled, -1 = with every IDR frame)")
+                    .default_value(Settings::default().config_interval)
+                    .minimum(-1)
+                    .maximum(3600)
+                    .mutable_playing()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        match pspec.name() {
+            "aggregate-mode" => {
+                self.settings.lock().unwrap().aggregate_mode = value.get().unwrap();
+            }
+            "config-interval" => {
+                self.settings.lock().unwrap().config_interval = value.get().unwrap();
+            }
+            _ => unimplemented!(),
+        };
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "aggregate-mode" => self.settings.lock().unwrap().aggregate_mode.to_value(),
+            "config-interval" => self.settings.lock().unwrap().config_interval.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+impl GstObjectImpl for RtpH264Pay {}
+
+impl ElementImpl for RtpH264Pay {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTP H.264 payloader",
+                "Codec/Payloader/Network/RTP",
+                "Payload H.264 as RTP packets (RFC 6184)",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let sink_pad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &gst::Caps::builder_full()
+                    .structure(
+                        gst::Structure::builder("video/x-h264")
+                            .field("stream-format", "avc")
+                            .field("alignment", "au")
+                            .build(),
+                    )
+                    .structure(
+                        gst::Structure::builder("video/x-h264")
+                            .field("stream-format", "byte-stream")
+                            .field("alignment", gst::List::new(["nal", "au"]))
+                            .build(),
+                    )
+                    .build(),
+            )
+            .unwrap();
+
+            let src_pad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &gst::Caps::builder("application/x-rtp")
+                    .field("media", "video")
+                    .field("clock-rate", 90_000i32)
+                    .field("encoding-name", "H264")
+                    .build(),
+            )
+            .unwrap();
+
+            vec![src_pad_template, sink_pad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+}
+
+impl crate::basepay::RtpBasePay2Impl for RtpH264Pay {
+    const ALLOWED_META_TAGS: &'static [&'static str] = &["video"];
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.borrow_mut() = State::default();
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.borrow_mut() = State::default();
+
+        Ok(())
+    }
+
+    fn set_sink_caps(&self, caps: &gst::Caps) -> bool {
+        gst::debug!(CAT, imp = self, "received caps {caps:?}");
+
+        let s = caps.structure(0).unwrap();
+        let mut state = self.state.borrow_mut();
+
+        if s.get::<&str>("stream-format") == Ok("avc") {
+            let Ok(codec_data) = s.get::<gst::Buffer>("codec_data") else {
+                gst::error!(CAT, imp = self, "avc caps without codec_data");
+                return false;
+            };
+            let Ok(map) = codec_data.map_readable() else {
+                gst::error!(CAT, imp = self, "Failed to map codec_data");
+                return false;
+            };
+
+            match ParameterSets::from_avc_decoder_configuration(&map) {
+                Ok((parameter_sets, nal_length_size)) => {
+                    state.parameter_sets = parameter_sets;
+                    state.stream_format = Some(StreamFormat::LengthPrefixed(nal_length_size));
+                }
+                Err(err) => {
+                    gst::error!(CAT, imp = self, "Failed to parse codec_data: {err}");
+                    return false;
+                }
+            }
+        } else {
+            state.stream_format = Some(StreamFormat::ByteStream);
+        }
+
+        state.alignment_au = s.get::<&str>("alignment") != Ok("nal");
+
+        self.update_src_caps(&state);
+
+        true
+    }
+
+    fn handle_buffer(
+        &self,
+        buffer: &gst::Buffer,
+        id: u64,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let settings = self.settings.lock().unwrap().clone();
+        let mut state = self.state.borrow_mut();
+
+        let Some(stream_format) = state.stream_format else {
+            gst::error!(CAT, imp = self, "No caps received yet");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        gst::trace!(CAT, imp = self, "received buffer of size {}", buffer.size());
+
+        let pts = buffer.pts();
+
+        // Send out everything of the previous access unit if this buffer obviously starts a new
+        // one.
+        if !state.queued_nals.is_empty()
+            && (state.queued_pts != pts || buffer.flags().contains(gst::BufferFlags::DISCONT))
+        {
+            gst::trace!(CAT, imp = self, "Finishing previous access unit");
+            self.send_queued_nals(&settings, &mut state, true)?;
+        }
+
+        let map = buffer.clone().into_mapped_buffer_readable().map_err(|_| {
+            gst::element_imp_error!(
+                self,
+                gst::ResourceError::Read,
+                ["Failed to map buffer readable"]
+            );
+
+            gst::FlowError::Error
+        })?;
+        let map = Arc::new(map);
+
+        let nals = match stream_format.split(&map) {
+            Ok(nals) => nals,
+            Err(err) => {
+                gst::warning!(CAT, imp = self, "Failed to parse NAL units: {err}");
+                Vec::new()
+            }
+        };
+
+        let mut parameter_sets_changed = false;
+        for range in nals {
+            let header = map[range.start];
+
+            match nal_type(header) {
+                NAL_TYPE_AUD => {
+                    // Access unit boundaries are signalled via the marker bit
+                    gst::trace!(CAT, imp = self, "Dropping access unit delimiter");
+                    continue;
+                }
+                NAL_TYPE_SPS | NAL_TYPE_PPS => {
+                    match state.parameter_sets.insert(&map[range.clone()]) {
+                        Ok(changed) => parameter_sets_changed |= changed,
+                        Err(err) => {
+                            gst::warning!(CAT, imp = self, "Failed to parse parameter set: {err}");
+                        }
+                    }
+
+                    state.au_has_parameter_sets = true;
+                    if pts.is_some() {
+                        state.last_parameter_sets_pts = pts;
+                    }
+                }
+                NAL_TYPE_IDR
+                    if !state.au_has_parameter_sets
+                        && self.parameter_sets_due(&settings, &state, pts) =>
+                {
+                    gst::debug!(CAT, imp = self, "Inserting parameter sets");
+
+                    let parameter_sets = state
+                        .parameter_sets
+                        .iter()
+                        .map(|nal| QueuedNal {
+                            id,
+                            buffer: Arc::new(
+                                gst::Buffer::from_slice(nal.to_vec())
+                                    .into_mapped_buffer_readable()
+                                    .unwrap(),
+                            ),
+                            range: 0..nal.len(),
+                        })
+                        .collect::<Vec<_>>();
+                    state.queued_nals.extend(parameter_sets);
+
+                    state.au_has_parameter_sets = true;
+                    if pts.is_some() {
+                        state.last_parameter_sets_pts = pts;
+                    }
+                }
+                _ => (),
+            }
+
+            state.queued_nals.push(QueuedNal {
+                id,
+                buffer: map.clone(),
+                range,
+            });
+        }
+
+        if parameter_sets_changed {
+            gst::debug!(CAT, imp = self, "Parameter sets changed");
+            self.update_src_caps(&state);
+        }
+
+        if state.queued_nals.is_empty() {
+            gst::trace!(CAT, imp = self, "No NAL units in buffer");
+            self.obj().drop_buffers(..=id);
+            return Ok(gst::FlowSuccess::Ok);
+        }
+        state.queued_pts = pts;
+
+        let au_end = state.alignment_au || buffer.flags().contains(gst::BufferFlags::MARKER);
+        if settings.aggregate_mode == AggregateMode::Max && !au_end {
+            gst::trace!(CAT, imp = self, "Waiting for the end of the access unit");
+            return Ok(gst::FlowSuccess::Ok);
+        }
+
+        self.send_queued_nals(&settings, &mut state, au_end)
+    }
+
+    fn drain(&self) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let settings = self.settings.lock().unwrap().clone();
+        let mut state = self.state.borrow_mut();
+
+        if !state.queued_nals.is_empty() {
+            self.send_queued_nals(&settings, &mut state, true)?;
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn flush(&self) {
+        let mut state = self.state.borrow_mut();
+        state.queued_nals.clear();
+        state.queued_pts = None;
+        state.au_has_parameter_sets = false;
+    }
+}
+
+impl RtpH264Pay {
+    /// Configures the source pad caps based on the currently known parameter sets.
+    fn update_src_caps(&self, state: &State) {
+        let mut caps_builder = gst::Caps::builder("application/x-rtp")
+            .field("media", "video")
+            .field("clock-rate", 90_000i32)
+            .field("encoding-name", "H264")
+            .field("packetization-mode", "1");
+
+        if let Some(profile_level_id) = state.parameter_sets.profile_level_id() {
+            caps_builder = caps_builder.field("profile-level-id", profile_level_id);
+        }
+        if let Some(sprop_parameter_sets) = state.parameter_sets.sprop_parameter_sets() {
+            caps_builder = caps_builder.field("sprop-parameter-sets", sprop_parameter_sets);
+        }
+
+        self.obj().set_src_caps(&caps_builder.build());
+    }
+
+    /// Checks if parameter sets have to be inserted before the next IDR frame.
+    fn parameter_sets_due(
+        &self,
+        settings: &Settings,
+        state: &State,
+        pts: Option<gst::ClockTime>,
+    ) -> bool {
+        if !state.parameter_sets.is_complete() {
+            return false;
+        }
+
+        match settings.config_interval {
+            0 => false,
+            -1 => true,
+            interval => match (state.last_parameter_sets_pts, pts) {
+                (Some(last_pts), Some(pts)) => {
+                    pts.saturating_sub(last_pts) >= gst::ClockTime::from_seconds(interval as u64)
+                }
+                _ => true,
+            },
+        }
+    }
+
+    /// Packetizes all queued NAL units.
+    ///
+    /// If `au_end` is set then the marker bit is set on the last packet.
+    fn send_queued_nals(
+        &self,
+        settings: &Settings,
+        state: &mut State,
+        au_end: bool,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let max_payload_size = self.obj().max_payload_size() as usize;
+
+        let nals = mem::take(&mut state.queued_nals);
+        state.queued_pts = None;
+        if au_end {
+            state.au_has_parameter_sets = false;
+        }
+
+        let mut idx = 0;
+        while idx < nals.len() {
+            // Collect as many NAL units as fit into a single STAP-A packet
+            let mut end = idx + 1;
+            if settings.aggregate_mode != AggregateMode::None {
+                let mut size = 1 + 2 + nals[idx].range.len();
+                while end < nals.len() && size + 2 + nals[end].range.len() <= max_payload_size {
+                    size += 2 + nals[end].range.len();
+                    end += 1;
+                }
+            }
+
+            let marker = au_end && end == nals.len();
+            if end - idx > 1 {
+                self.send_stap_a(&nals[idx..end], marker)?;
+            } else {
+                self.send_nal(&nals[idx], max_payload_size, marker)?;
+            }
+
+            idx = end;
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    /// Sends multiple NAL units in a single STAP-A packet.
+    fn send_stap_a(
+        &self,
+        nals: &[QueuedNal],
+        marker: bool,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        gst::trace!(
+            CAT,
+            imp = self,
+            "Sending {} NAL units in a STAP-A packet",
+            nals.len()
+        );
+
+        // F bit is set if any NAL unit has it set, NRI is the maximum of all NAL units
+        let header =
+            nals.iter()
+                .map(|nal| nal.data()[0])
+                .fold(NAL_TYPE_STAP_A, |header, nal_header| {
+                    (header & 0x80)
+                        | (nal_header & 0x80)
+                        | (header & 0x60).max(nal_header & 0x60)
+                        | NAL_TYPE_STAP_A
+                });
+        let header = [header];
+
+        let sizes = nals
+            .iter()
+            .map(|nal| (nal.range.len() as u16).to_be_bytes())
+            .collect::<SmallVec<[_; 16]>>();
+
+        let mut packet = rtp_types::RtpPacketBuilder::new()
+            .marker_bit(marker)
+            .payload(header.as_slice());
+        for (nal, size) in Iterator::zip(nals.iter(), sizes.iter()) {
+            packet = packet.payload(size.as_slice()).payload(nal.data());
+        }
+
+        self.obj().queue_packet(
+            PacketToBufferRelation::Ids(nals[0].id..=nals[nals.len() - 1].id),
+            packet,
+        )
+    }
+
+    /// Sends a single NAL unit, either as single NAL unit packet or fragmented into FU-A packets.
+    fn send_nal(
+        &self,
+        nal: &QueuedNal,
+        max_payload_size: usize,
+        marker: bool,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let data = nal.data();
+
+        if data.len() <= max_payload_size {
+            gst::trace!(
+                CAT,
+                imp = self,
+                "Sending NAL unit of size {} in a single packet",
+                data.len()
+            );
+
+            return self.obj().queue_packet(
+                nal.id.into(),
+                rtp_types::RtpPacketBuilder::new()
+                    .marker_bit(marker)
+                    .payload(data),
+            );
+        }
+
+        let fragment_size = max_payload_size
+            .checked_sub(2)
+            .filter(|size| *size > 0)
+            .ok_or_else(|| {
+                gst::error!(CAT, imp = self, "Too small MTU configured for stream");
+                gst::element_imp_error!(
+                    self,
+                    gst::LibraryError::Settings,
+                    ["Too small MTU configured for stream"]
+                );
+                gst::FlowError::Error
+            })?;
+
+        gst::trace!(
+            CAT,
+            imp = self,
+            "Fragmenting NAL unit of size {} into FU-A packets",
+            data.len()
+        );
+
+        let fu_indicator = (data[0] & 0xe0) | NAL_TYPE_FU_A;
+        let mut payload = &data[1..];
+        let mut first = true;
+        while !payload.is_empty() {
+            let size = payload.len().min(fragment_size);
+            let last = size == payload.len();
+
+            let fu_header = ((first as u8) << 7) | ((last as u8) << 6) | nal_type(data[0]);
+            let fu = [fu_indicator, fu_header];
+
+            self.obj().queue_packet(
+                nal.id.into(),
+                rtp_types::RtpPacketBuilder::new()
+                    .marker_bit(marker && last)
+                    .payload(fu.as_slice())
+                    .payload(&payload[..size]),
+            )?;
+
+            payload = &payload[size..];
+            first = false;
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+}
@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::tests::{run_test_pipeline, ExpectedBuffer, ExpectedPacket, Source};
+
+fn init() {
+    use std::sync::Once;
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        gst::init().unwrap();
+        crate::plugin_register_static().expect("rtph264 test");
+    });
+}
+
+const SPS: &[u8] = &[
+    0x67, 0x42, 0xc0, 0x1e, 0xd9, 0x40, 0x50, 0x17, 0xfc, 0xb8, 0x08,
+];
+const PPS: &[u8] = &[0x68, 0xce, 0x3c, 0x80];
+
+/// Creates a NAL unit of the given size that contains no start code emulation.
+fn nal(header: u8, size: usize) -> Vec<u8> {
+    let mut nal = vec![header];
+    nal.extend((1..size).map(|i| (i % 255) as u8 + 1));
+    nal
+}
+
+/// Creates a byte-stream access unit from the given NAL units.
+fn access_unit(pts: gst::ClockTime, nals: &[&[u8]]) -> gst::Buffer {
+    let mut data = Vec::new();
+    for nal in nals {
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(nal);
+    }
+
+    let mut buffer = gst::Buffer::from_mut_slice(data);
+    buffer.get_mut().unwrap().set_pts(pts);
+    buffer
+}
+
+#[test]
+fn test_h264() {
+    init();
+
+    let caps = gst::Caps::builder("video/x-h264")
+        .field("stream-format", "byte-stream")
+        .field("alignment", "au")
+        .build();
+
+    let idr = nal(0x65, 3000);
+    let p = nal(0x41, 100);
+    let buffers = vec![
+        access_unit(gst::ClockTime::ZERO, &[SPS, PPS, &idr]),
+        access_unit(gst::ClockTime::from_mseconds(40), &[&p]),
+        access_unit(gst::ClockTime::from_mseconds(80), &[&p]),
+    ];
+
+    let pay = "rtph264pay2";
+    let depay = "rtph264depay2";
+
+    let expected_pay = vec![
+        vec![
+            // SPS and PPS are aggregated into a STAP-A packet
+            ExpectedPacket::builder()
+                .pts(gst::ClockTime::ZERO)
+                .flags(gst::BufferFlags::DISCONT)
+                .pt(96)
+                .rtp_time(0)
+                .marker_bit(false)
+                .size(12 + 1 + 2 + SPS.len() + 2 + PPS.len())
+                .build(),
+            // IDR is fragmented into three FU-A packets
+            ExpectedPacket::builder()
+                .pts(gst::ClockTime::ZERO)
+                .flags(gst::BufferFlags::empty())
+                .pt(96)
+                .rtp_time(0)
+                .marker_bit(false)
+                .size(1400)
+                .build(),
+            ExpectedPacket::builder()
+                .pts(gst::ClockTime::ZERO)
+                .flags(gst::BufferFlags::empty())
+                .pt(96)
+                .rtp_time(0)
+                .marker_bit(false)
+                .size(1400)
+                .build(),
+            ExpectedPacket::builder()
+                .pts(gst::ClockTime::ZERO)
+                .flags(gst::BufferFlags::MARKER)
+                .pt(96)
+                .rtp_time(0)
+                .marker_bit(true)
+                .size(12 + 2 + 2999 - 2 * 1386)
+                .build(),
+        ],
+        // Delta frames fit into a single NAL unit packet
+        vec![ExpectedPacket::builder()
+            .pts(gst::ClockTime::from_mseconds(40))
+            .flags(gst::BufferFlags::MARKER)
+            .pt(96)
+            .rtp_time(3_600)
+            .marker_bit(true)
+            .size(12 + 100)
+            .build()],
+        vec![ExpectedPacket::builder()
+            .pts(gst::ClockTime::from_mseconds(80))
+            .flags(gst::BufferFlags::MARKER)
+            .pt(96)
+            .rtp_time(7_200)
+            .marker_bit(true)
+            .size(12 + 100)
+            .build()],
+    ];
+
+    let expected_depay = vec![
+        // One buffer per access unit
+        vec![ExpectedBuffer::builder()
+            .pts(gst::ClockTime::ZERO)
+            .size(4 + SPS.len() + 4 + PPS.len() + 4 + 3000)
+            .flags(gst::BufferFlags::DISCONT | gst::BufferFlags::MARKER)
+            .build()],
+        vec![ExpectedBuffer::builder()
+            .pts(gst::ClockTime::from_mseconds(40))
+            .size(4 + 100)
+            .flags(gst::BufferFlags::MARKER | gst::BufferFlags::DELTA_UNIT)
+            .build()],
+        vec![ExpectedBuffer::builder()
+            .pts(gst::ClockTime::from_mseconds(80))
+            .size(4 + 100)
+            .flags(gst::BufferFlags::MARKER | gst::BufferFlags::DELTA_UNIT)
+            .build()],
+    ];
+
+    run_test_pipeline(
+        Source::Buffers(caps, buffers),
+        pay,
+        depay,
+        expected_pay,
+        expected_depay,
+    );
+}
+
+#[test]
+fn test_h264_config_interval() {
+    init();
+
+    let caps = gst::Caps::builder("video/x-h264")
+        .field("stream-format", "byte-stream")
+        .field("alignment", "au")
+        .build();
+
+    // The second IDR frame comes without parameter sets
+    let idr = nal(0x65, 100);
+    let p = nal(0x41, 50);
+    let buffers = vec![
+        access_unit(gst::ClockTime::ZERO, &[SPS, PPS, &idr]),
+        access_unit(gst::ClockTime::from_mseconds(40), &[&p]),
+        access_unit(gst::ClockTime::from_mseconds(80), &[&idr]),
+    ];
+
+    let pay = "rtph264pay2 config-interval=-1";
+    let depay = "rtph264depay2";
+
+    let stap_a_size = 12 + 1 + 2 + SPS.len() + 2 + PPS.len() + 2 + 100;
+    let expected_pay = vec![
+        vec![ExpectedPacket::builder()
+            .pts(gst::ClockTime::ZERO)
+            .flags(gst::BufferFlags::DISCONT | gst::BufferFlags::MARKER)
+            .pt(96)
+            .rtp_time(0)
+            .marker_bit(true)
+            .size(stap_a_size)
+            .build()],
+        vec![ExpectedPacket::builder()
+            .pts(gst::ClockTime::from_mseconds(40))
+            .flags(gst::BufferFlags::MARKER)
+            .pt(96)
+            .rtp_time(3_600)
+            .marker_bit(true)
+            .size(12 + 50)
+            .build()],
+        // Parameter sets are inserted before the IDR frame
+        vec![ExpectedPacket::builder()
+            .pts(gst::ClockTime::from_mseconds(80))
+            .flags(gst::BufferFlags::MARKER)
+            .pt(96)
+            .rtp_time(7_200)
+            .marker_bit(true)
+            .size(stap_a_size)
+            .build()],
+    ];
+
+    let keyframe_size = 4 + SPS.len() + 4 + PPS.len() + 4 + 100;
+    let expected_depay = vec![
+        vec![ExpectedBuffer::builder()
+            .pts(gst::ClockTime::ZERO)
+            .size(keyframe_size)
+            .flags(gst::BufferFlags::DISCONT | gst::BufferFlags::MARKER)
+            .build()],
+        vec![ExpectedBuffer::builder()
+            .pts(gst::ClockTime::from_mseconds(40))
+            .size(4 + 50)
+            .flags(gst::BufferFlags::MARKER | gst::BufferFlags::DELTA_UNIT)
+            .build()],
+        vec![ExpectedBuffer::builder()
+            .pts(gst::ClockTime::from_mseconds(80))
+            .size(keyframe_size)
+            .flags(gst::BufferFlags::MARKER)
+            .build()],
+    ];
+
+    run_test_pipeline(
+        Source::Buffers(caps, buffers),
+        pay,
+        depay,
+        expected_pay,
+        expected_depay,
+    );
+}
@@ -0,0 +1,20 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::glib;
+use gst::prelude::*;
+
+pub mod imp;
+
+glib::wrapper! {
+    pub struct RtpH265Depay(ObjectSubclass<imp::RtpH265Depay>)
+        @extends crate::basedepay::RtpBaseDepay2, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "rtph265depay2",
+        gst::Rank::MARGINAL,
+        RtpH265Depay::static_type(),
+    )
+}
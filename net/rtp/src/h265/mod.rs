@@ -0,0 +1,8 @@
+// SPDX-License-Identifier: MPL-2.0
+
+pub mod depay;
+mod nal;
+pub mod pay;
+
+#[cfg(test)]
+mod tests;
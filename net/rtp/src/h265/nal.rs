@@ -0,0 +1,248 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context as _};
+use bitstream_io::{BigEndian, BitRead as _, BitReader};
+use gst::glib;
+
+use crate::h26x::{read_ue, remove_emulation_prevention};
+
+pub const NAL_TYPE_BLA_W_LP: u8 = 16;
+pub const NAL_TYPE_RSV_IRAP_VCL23: u8 = 23;
+pub const NAL_TYPE_VPS: u8 = 32;
+pub const NAL_TYPE_SPS: u8 = 33;
+pub const NAL_TYPE_PPS: u8 = 34;
+pub const NAL_TYPE_AUD: u8 = 35;
+pub const NAL_TYPE_AP: u8 = 48;
+pub const NAL_TYPE_FU: u8 = 49;
+pub const NAL_TYPE_PACI: u8 = 50;
+
+/// Returns the type of a NAL unit from the first byte of its header.
+pub fn nal_type(header: u8) -> u8 {
+    (header >> 1) & 0x3f
+}
+
+/// Returns `true` if the NAL unit type is an IRAP picture, i.e. a keyframe.
+pub fn is_irap(nal_type: u8) -> bool {
+    (NAL_TYPE_BLA_W_LP..=NAL_TYPE_RSV_IRAP_VCL23).contains(&nal_type)
+}
+
+fn parse_vps_id(nal: &[u8]) -> anyhow::Result<u32> {
+    if nal.len() < 3 {
+        bail!("VPS too short");
+    }
+
+    Ok((nal[2] >> 4) as u32)
+}
+
+fn parse_sps_id(nal: &[u8]) -> anyhow::Result<u32> {
+    if nal.len() < 16 {
+        bail!("SPS too short");
+    }
+
+    let data = remove_emulation_prevention(&nal[2..]);
+    let mut r = BitReader::endian(data.as_slice(), BigEndian);
+
+    r.skip(4).context("sps_video_parameter_set_id")?;
+    let max_sub_layers_minus1 = r.read::<3, u8>().context("sps_max_sub_layers_minus1")?;
+    r.skip(1).context("sps_temporal_id_nesting_flag")?;
+
+    // profile_tier_level(): general profile and level
+    r.skip(96).context("general_profile_tier_level")?;
+
+    let mut sub_layer_flags = [(false, false); 7];
+    for flags in sub_layer_flags
+        .iter_mut()
+        .take(max_sub_layers_minus1 as usize)
+    {
+        flags.0 = r.read_bit().context("sub_layer_profile_present_flag")?;
+        flags.1 = r.read_bit().context("sub_layer_level_present_flag")?;
+    }
+    if max_sub_layers_minus1 > 0 {
+        r.skip(2 * (8 - max_sub_layers_minus1 as u32))
+            .context("reserved_zero_2bits")?;
+    }
+    for (profile_present, level_present) in
+        sub_layer_flags.iter().take(max_sub_layers_minus1 as usize)
+    {
+        if *profile_present {
+            r.skip(88).context("sub_layer_profile")?;
+        }
+        if *level_present {
+            r.skip(8).context("sub_layer_level_idc")?;
+        }
+    }
+
+    read_ue(&mut r).context("sps_seq_parameter_set_id")
+}
+
+fn parse_pps_id(nal: &[u8]) -> anyhow::Result<u32> {
+    if nal.len() < 3 {
+        bail!("PPS too short");
+    }
+
+    let data = remove_emulation_prevention(&nal[2..nal.len().min(16)]);
+    let mut r = BitReader::endian(data.as_slice(), BigEndian);
+    read_ue(&mut r).context("pps_pic_parameter_set_id")
+}
+
+/// VPS, SPS and PPS of a stream, indexed by their ids.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ParameterSets {
+    vps: BTreeMap<u32, Vec<u8>>,
+    sps: BTreeMap<u32, Vec<u8>>,
+    pps: BTreeMap<u32, Vec<u8>>,
+}
+
+impl ParameterSets {
+    /// Parses the parameter sets from the `sprop-vps`, `sprop-sps` and `sprop-pps` caps fields.
+    pub fn from_caps(s: &gst::StructureRef) -> anyhow::Result<Self> {
+        let mut parameter_sets = ParameterSets::default();
+
+        for field in ["sprop-vps", "sprop-sps", "sprop-pps"] {
+            let Ok(sprop) = s.get::<&str>(field) else {
+                continue;
+            };
+
+            for nal in sprop.split(',').filter(|s| !s.is_empty()) {
+                let nal = glib::base64_decode(nal);
+                if nal.is_empty() {
+                    bail!("Invalid base64 parameter set in {field}");
+                }
+                parameter_sets.insert(&nal)?;
+            }
+        }
+
+        Ok(parameter_sets)
+    }
+
+    /// Parses the parameter sets and NAL unit length size from an
+    /// `HEVCDecoderConfigurationRecord`.
+    pub fn from_hevc_decoder_configuration(data: &[u8]) -> anyhow::Result<(Self, usize)> {
+        if data.len() < 23 || data[0] != 1 {
+            bail!("Invalid HEVCDecoderConfigurationRecord");
+        }
+
+        let nal_length_size = (data[21] & 0x03) as usize + 1;
+        let num_arrays = data[22];
+
+        let mut parameter_sets = ParameterSets::default();
+        let mut data = &data[23..];
+        for _ in 0..num_arrays {
+            if data.len() < 3 {
+                bail!("Truncated HEVCDecoderConfigurationRecord");
+            }
+            let num_nalus = u16::from_be_bytes([data[1], data[2]]);
+            data = &data[3..];
+
+            for _ in 0..num_nalus {
+                if data.len() < 2 {
+                    bail!("Truncated HEVCDecoderConfigurationRecord");
+                }
+                let len = u16::from_be_bytes([data[0], data[1]]) as usize;
+                if data.len() < 2 + len {
+                    bail!("Truncated HEVCDecoderConfigurationRecord");
+                }
+                // Other NAL units, e.g. SEI, are ignored
+                parameter_sets.insert(&data[2..][..len])?;
+                data = &data[2 + len..];
+            }
+        }
+
+        Ok((parameter_sets, nal_length_size))
+    }
+
+    /// Stores the NAL unit if it is a VPS, SPS or PPS.
+    ///
+    /// Returns `true` if the stored parameter sets changed.
+    pub fn insert(&mut self, nal: &[u8]) -> anyhow::Result<bool> {
+        let Some(&header) = nal.first() else {
+            return Ok(false);
+        };
+
+        let (map, id) = match nal_type(header) {
+            NAL_TYPE_VPS => (&mut self.vps, parse_vps_id(nal)?),
+            NAL_TYPE_SPS => (&mut self.sps, parse_sps_id(nal)?),
+            NAL_TYPE_PPS => (&mut self.pps, parse_pps_id(nal)?),
+            _ => return Ok(false),
+        };
+
+        if map.get(&id).is_some_and(|old| old == nal) {
+            return Ok(false);
+        }
+        map.insert(id, nal.to_vec());
+
+        Ok(true)
+    }
+
+    /// Returns `true` if at least one VPS, SPS and PPS are known.
+    pub fn is_complete(&self) -> bool {
+        !self.vps.is_empty() && !self.sps.is_empty() && !self.pps.is_empty()
+    }
+
+    /// Iterates over all VPS followed by all SPS and all PPS.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.vps
+            .values()
+            .chain(self.sps.values())
+            .chain(self.pps.values())
+            .map(Vec::as_slice)
+    }
+
+    /// Values for the `sprop-vps`, `sprop-sps` and `sprop-pps` caps fields.
+    pub fn sprop_fields(&self) -> Vec<(&'static str, String)> {
+        if !self.is_complete() {
+            return Vec::new();
+        }
+
+        [
+            ("sprop-vps", &self.vps),
+            ("sprop-sps", &self.sps),
+            ("sprop-pps", &self.pps),
+        ]
+        .into_iter()
+        .map(|(field, map)| {
+            let sprop = map
+                .values()
+                .map(|nal| glib::base64_encode(nal).to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            (field, sprop)
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameter_set_ids() {
+        // Parameter sets of a Main profile, level 2 stream
+        let vps = [
+            0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00,
+            0x00, 0x03, 0x00, 0x00, 0x03, 0x00, 0x3c, 0x95, 0x94, 0x09,
+        ];
+        let sps = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x3c, 0xa0, 0x0a, 0x08, 0x0f, 0x16, 0x59, 0x59, 0x52, 0x93, 0x0b,
+            0xc0, 0x5a, 0x02, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00, 0x00, 0x03, 0x00, 0x32, 0x10,
+        ];
+        let pps = [0x44, 0x01, 0xc1, 0x73, 0xd1, 0x89];
+
+        assert_eq!(parse_vps_id(&vps).unwrap(), 0);
+        assert_eq!(parse_sps_id(&sps).unwrap(), 0);
+        assert_eq!(parse_pps_id(&pps).unwrap(), 0);
+
+        let mut parameter_sets = ParameterSets::default();
+        assert!(parameter_sets.insert(&vps).unwrap());
+        assert!(parameter_sets.insert(&sps).unwrap());
+        assert!(!parameter_sets.is_complete());
+        assert!(parameter_sets.insert(&pps).unwrap());
+        assert!(!parameter_sets.insert(&pps).unwrap());
+        assert!(parameter_sets.is_complete());
+        assert_eq!(parameter_sets.iter().count(), 3);
+    }
+}
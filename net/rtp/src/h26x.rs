@@ -0,0 +1,196 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Helpers shared between the H.264 and H.265 payloaders and depayloaders.
+
+use std::ops::Range;
+
+use anyhow::{bail, Context as _};
+use bitstream_io::BitRead;
+
+/// Start code used for all NAL units in byte-stream output.
+pub const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// Framing of the NAL units in a non-RTP stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// Annex B byte-stream with start codes.
+    ByteStream,
+    /// Each NAL unit is prefixed with its size in the given number of bytes.
+    LengthPrefixed(usize),
+}
+
+impl StreamFormat {
+    /// Splits `data` into the ranges of the contained NAL units.
+    pub fn split(self, data: &[u8]) -> anyhow::Result<Vec<Range<usize>>> {
+        match self {
+            StreamFormat::ByteStream => Ok(split_byte_stream(data)),
+            StreamFormat::LengthPrefixed(nal_length_size) => {
+                split_length_prefixed(data, nal_length_size)
+            }
+        }
+    }
+}
+
+/// Splits an Annex B byte-stream into the ranges of the contained NAL units.
+///
+/// Leading data before the first start code and trailing zero bytes of each NAL unit are skipped.
+pub fn split_byte_stream(data: &[u8]) -> Vec<Range<usize>> {
+    let mut nals = Vec::new();
+
+    let mut current_start = None;
+    let mut idx = 0;
+    while idx + 3 <= data.len() {
+        if data[idx..idx + 3] != [0, 0, 1] {
+            idx += 1;
+            continue;
+        }
+
+        if let Some(start) = current_start {
+            push_trimmed(&mut nals, data, start..idx);
+        }
+        idx += 3;
+        current_start = Some(idx);
+    }
+
+    if let Some(start) = current_start {
+        push_trimmed(&mut nals, data, start..data.len());
+    }
+
+    nals
+}
+
+fn push_trimmed(nals: &mut Vec<Range<usize>>, data: &[u8], mut range: Range<usize>) {
+    while range.end > range.start && data[range.end - 1] == 0 {
+        range.end -= 1;
+    }
+
+    if !range.is_empty() {
+        nals.push(range);
+    }
+}
+
+/// Splits a stream of NAL units prefixed with their size into the ranges of the NAL units.
+pub fn split_length_prefixed(
+    data: &[u8],
+    nal_length_size: usize,
+) -> anyhow::Result<Vec<Range<usize>>> {
+    assert!((1..=4).contains(&nal_length_size));
+
+    let mut nals = Vec::new();
+
+    let mut idx = 0;
+    while idx < data.len() {
+        if idx + nal_length_size > data.len() {
+            bail!("Truncated NAL unit size at offset {idx}");
+        }
+
+        let size = data[idx..][..nal_length_size]
+            .iter()
+            .fold(0usize, |size, b| (size << 8) | *b as usize);
+        idx += nal_length_size;
+
+        if idx + size > data.len() {
+            bail!(
+                "NAL unit of size {size} at offset {idx} exceeds buffer size {}",
+                data.len()
+            );
+        }
+
+        if size > 0 {
+            nals.push(idx..idx + size);
+        }
+        idx += size;
+    }
+
+    Ok(nals)
+}
+
+/// Removes the emulation prevention bytes from a NAL unit so that it can be parsed.
+pub fn remove_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(data.len());
+
+    let mut zeroes = 0;
+    for &b in data {
+        if zeroes >= 2 && b == 3 {
+            zeroes = 0;
+            continue;
+        }
+
+        if b == 0 {
+            zeroes += 1;
+        } else {
+            zeroes = 0;
+        }
+        res.push(b);
+    }
+
+    res
+}
+
+/// Reads an unsigned Exp-Golomb code, `ue(v)`.
+pub fn read_ue<R: BitRead + ?Sized>(r: &mut R) -> anyhow::Result<u32> {
+    let mut leading_zeroes = 0;
+    while !r.read_bit().context("ue_leading_zero")? {
+        leading_zeroes += 1;
+        if leading_zeroes > 31 {
+            bail!("Exp-Golomb code too long");
+        }
+    }
+
+    let mut value = 0u32;
+    for _ in 0..leading_zeroes {
+        value = (value << 1) | r.read_bit().context("ue_bit")? as u32;
+    }
+
+    Ok((1u32 << leading_zeroes) - 1 + value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitstream_io::{BigEndian, BitReader};
+
+    #[test]
+    fn byte_stream() {
+        let data = [
+            0, 0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x68, 3, 0, 0, 0, 0, 1, 0x65, 4, 5, 6,
+        ];
+
+        let nals = split_byte_stream(&data);
+        assert_eq!(nals, vec![4..7, 10..12, 17..21]);
+
+        // No start code at all
+        assert!(split_byte_stream(&[1, 2, 3, 4]).is_empty());
+    }
+
+    #[test]
+    fn length_prefixed() {
+        let data = [0, 0, 0, 2, 0x67, 1, 0, 0, 0, 1, 0x68];
+        assert_eq!(split_length_prefixed(&data, 4).unwrap(), vec![4..6, 10..11]);
+
+        let data = [0, 2, 0x67, 1, 0, 1, 0x68];
+        assert_eq!(split_length_prefixed(&data, 2).unwrap(), vec![2..4, 6..7]);
+        // Truncated NAL unit
+        assert!(split_length_prefixed(&data[..6], 2).is_err());
+    }
+
+    #[test]
+    fn emulation_prevention() {
+        assert_eq!(
+            remove_emulation_prevention(&[0x67, 0, 0, 3, 1, 0, 0, 3, 0, 3]),
+            vec![0x67, 0, 0, 1, 0, 0, 0, 3]
+        );
+    }
+
+    #[test]
+    fn exp_golomb() {
+        // 1, 010, 011, 00100, 0001000
+        let data = [0b1010_0110, 0b0100_0001, 0b0000_0000];
+        let mut r = BitReader::endian(data.as_slice(), BigEndian);
+        assert_eq!(read_ue(&mut r).unwrap(), 0);
+        assert_eq!(read_ue(&mut r).unwrap(), 1);
+        assert_eq!(read_ue(&mut r).unwrap(), 2);
+        assert_eq!(read_ue(&mut r).unwrap(), 3);
+        assert_eq!(read_ue(&mut r).unwrap(), 7);
+    }
+}
@@ -25,6 +25,13 @@ mod utils;
 mod gcc;
 mod rtpbin2;
 
+mod fec;
+mod flexfec;
+mod red;
+mod ulpfec;
+
+mod srtp;
+
 mod audio_discont;
 mod baseaudiopay;
 mod basedepay;
@@ -33,6 +40,9 @@ mod basepay;
 mod ac3;
 mod amr;
 mod av1;
+mod h264;
+mod h265;
+mod h26x;
 mod jpeg;
 mod klv;
 mod mp2t;
@@ -50,6 +60,16 @@ fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
     gcc::register(plugin)?;
     rtpbin2::register(plugin)?;
 
+    red::enc::register(plugin)?;
+    red::dec::register(plugin)?;
+    ulpfec::enc::register(plugin)?;
+    ulpfec::dec::register(plugin)?;
+    flexfec::enc::register(plugin)?;
+    flexfec::dec::register(plugin)?;
+
+    srtp::enc::register(plugin)?;
+    srtp::dec::register(plugin)?;
+
     #[cfg(feature = "doc")]
     {
         use gst::prelude::*;
@@ -71,6 +91,12 @@ fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
     av1::depay::register(plugin)?;
     av1::pay::register(plugin)?;
 
+    h264::depay::register(plugin)?;
+    h264::pay::register(plugin)?;
+
+    h265::depay::register(plugin)?;
+    h265::pay::register(plugin)?;
+
     jpeg::depay::register(plugin)?;
     jpeg::pay::register(plugin)?;
 
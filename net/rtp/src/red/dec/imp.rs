@@ -0,0 +1,400 @@
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * SECTION:element-rtpreddec2
+ * @see_also: rtpredenc2, rtpulpfecdec2, rtprecv
+ *
+ * Decapsulates RED packets as per [RFC 2198][rfc-2198]. Packets with other payload types are
+ * passed through unchanged.
+ *
+ * The primary block of every RED packet is forwarded with the seqnum of the RED packet.
+ * Redundant blocks are assumed to belong to the packets directly preceding the RED packet and
+ * are forwarded only if the corresponding packet was not received before. This requires the
+ * packets to arrive in order, so the element is placed after a `src` pad of `rtprecv`.
+ *
+ * As the RED packets can carry different payload types, the caps for each payload type can be
+ * provided with #rtpreddec2:pt-map. Otherwise the caps of the RED stream are used with the
+ * `payload` field updated.
+ *
+ * [rfc-2198]: https://www.rfc-editor.org/rfc/rfc2198.html
+ *
+ * ## Example pipeline
+ *
+ * |[
+ * gst-launch-1.0 \
+ *  udpsrc port=5004 caps='application/x-rtp, media=video, clock-rate=90000, encoding-name=RED, payload=122' \
+ *  ! recv.rtp_sink_0 \
+ *  rtprecv name=recv rtp-id=example-rtp-id latency=200 \
+ *  ! rtpreddec2 pt=122 ! rtpulpfecdec2 pt=123 ! rtpvp8depay2 ! vp8dec ! videoconvert ! autovideosink
+ * ]| This will receive a VP8 stream with ULPFEC packets encapsulated in RED.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::{glib, prelude::*, subclass::prelude::*};
+use rtp_types::{RtpPacket, RtpPacketBuilder};
+use std::sync::{LazyLock, Mutex};
+
+use crate::red;
+use crate::utils::seqnum_distance;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rtpreddec2",
+        gst::DebugColorFlags::empty(),
+        Some("RTP RED Decoder"),
+    )
+});
+
+const DEFAULT_PT: u32 = 122;
+
+#[derive(Debug, Clone)]
+struct Settings {
+    pt: u32,
+    pt_map: Option<gst::Structure>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            pt: DEFAULT_PT,
+            pt_map: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    ssrc: Option<u32>,
+    last_seqnum: Option<u16>,
+    input_caps: Option<gst::Caps>,
+    output_pt: Option<u8>,
+}
+
+pub struct RtpRedDec {
+    srcpad: gst::Pad,
+    sinkpad: gst::Pad,
+    settings: Mutex<Settings>,
+    state: Mutex<State>,
+}
+
+impl RtpRedDec {
+    fn sink_chain(
+        &self,
+        pad: &gst::Pad,
+        buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let settings = self.settings.lock().unwrap().clone();
+
+        let map = buffer.map_readable().map_err(|_| {
+            gst::error!(CAT, obj = pad, "Failed to map buffer readable");
+            gst::FlowError::Error
+        })?;
+        let rtp = match RtpPacket::parse(&map) {
+            Ok(rtp) => rtp,
+            Err(err) => {
+                gst::warning!(CAT, obj = pad, "Dropping invalid RTP packet: {err:?}");
+                return Ok(gst::FlowSuccess::Ok);
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if state.ssrc != Some(rtp.ssrc()) {
+            gst::debug!(CAT, imp = self, "New SSRC {}", rtp.ssrc());
+            state.ssrc = Some(rtp.ssrc());
+            state.last_seqnum = None;
+        }
+
+        let seqnum = rtp.sequence_number();
+        let last_seqnum = state.last_seqnum;
+        if last_seqnum.is_none_or(|last| seqnum_distance(seqnum, last) > 0) {
+            state.last_seqnum = Some(seqnum);
+        }
+
+        if rtp.payload_type() as u32 != settings.pt {
+            let pt = rtp.payload_type();
+            drop(map);
+            let caps = self.caps_for_pt(&mut state, &settings, pt);
+            drop(state);
+
+            if let Some(caps) = caps {
+                self.srcpad.push_event(gst::event::Caps::new(&caps));
+            }
+            return self.srcpad.push(buffer);
+        }
+
+        let Some((redundant, primary)) = red::parse_payload(rtp.payload()) else {
+            gst::warning!(CAT, imp = self, "Dropping invalid RED packet {seqnum}");
+            return Ok(gst::FlowSuccess::Ok);
+        };
+
+        let mut packets = Vec::with_capacity(redundant.len() + 1);
+        let n_redundant = redundant.len();
+        for (idx, block) in redundant.iter().enumerate() {
+            let block_seqnum = seqnum.wrapping_sub((n_redundant - idx) as u16);
+            // Only restore packets that were not forwarded yet
+            if last_seqnum.is_none_or(|last| seqnum_distance(block_seqnum, last) <= 0) {
+                continue;
+            }
+
+            gst::debug!(
+                CAT,
+                imp = self,
+                "Restoring packet {block_seqnum} from redundant block"
+            );
+            let mut builder = RtpPacketBuilder::<&[u8], &[u8]>::new()
+                .payload_type(block.pt)
+                .ssrc(rtp.ssrc())
+                .sequence_number(block_seqnum)
+                .timestamp(rtp.timestamp().wrapping_sub(block.timestamp_offset))
+                .payload(block.data);
+            for csrc in rtp.csrc() {
+                builder = builder.add_csrc(csrc);
+            }
+            if let Ok(data) = builder.write_vec() {
+                packets.push((block.pt, data));
+            }
+        }
+
+        let mut builder = RtpPacketBuilder::<&[u8], &[u8]>::new()
+            .payload_type(primary.pt)
+            .ssrc(rtp.ssrc())
+            .sequence_number(seqnum)
+            .timestamp(rtp.timestamp())
+            .marker_bit(rtp.marker_bit())
+            .payload(primary.data);
+        for csrc in rtp.csrc() {
+            builder = builder.add_csrc(csrc);
+        }
+        if let Some((id, data)) = rtp.extension() {
+            builder = builder.extension(id, data);
+        }
+        match builder.write_vec() {
+            Ok(data) => packets.push((primary.pt, data)),
+            Err(err) => {
+                gst::warning!(CAT, imp = self, "Failed to create packet: {err:?}");
+            }
+        }
+        drop(map);
+
+        let mut ret = Ok(gst::FlowSuccess::Ok);
+        for (pt, data) in packets {
+            let mut outbuf = gst::Buffer::from_mut_slice(data);
+            {
+                let outbuf_mut = outbuf.get_mut().unwrap();
+                let _ = buffer.copy_into(
+                    outbuf_mut,
+                    gst::BufferCopyFlags::FLAGS
+                        | gst::BufferCopyFlags::TIMESTAMPS
+                        | gst::BufferCopyFlags::META,
+                    ..,
+                );
+            }
+
+            let caps = self.caps_for_pt(&mut state, &settings, pt);
+            drop(state);
+            if let Some(caps) = caps {
+                self.srcpad.push_event(gst::event::Caps::new(&caps));
+            }
+            ret = self.srcpad.push(outbuf);
+            if ret.is_err() {
+                break;
+            }
+            state = self.state.lock().unwrap();
+        }
+
+        ret
+    }
+
+    /// Returns the caps to push before a packet with this payload type if the payload type
+    /// changed
+    fn caps_for_pt(&self, state: &mut State, settings: &Settings, pt: u8) -> Option<gst::Caps> {
+        if state.output_pt == Some(pt) {
+            return None;
+        }
+        state.output_pt = Some(pt);
+
+        if let Some(caps) = settings
+            .pt_map
+            .as_ref()
+            .and_then(|pt_map| pt_map.get::<gst::Caps>(pt.to_string()).ok())
+        {
+            return Some(caps);
+        }
+
+        let mut caps = state.input_caps.clone()?;
+        {
+            let caps = caps.make_mut();
+            if let Some(s) = caps.structure_mut(0) {
+                s.set("payload", pt as i32);
+            }
+        }
+
+        Some(caps)
+    }
+
+    fn sink_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        gst::log!(CAT, obj = pad, "Handling event {event:?}");
+
+        match event.view() {
+            gst::EventView::Caps(caps) => {
+                let mut state = self.state.lock().unwrap();
+                state.input_caps = Some(caps.caps_owned());
+                state.output_pt = None;
+                drop(state);
+
+                gst::Pad::event_default(pad, Some(&*self.obj()), event)
+            }
+            gst::EventView::FlushStop(_) => {
+                let mut state = self.state.lock().unwrap();
+                state.ssrc = None;
+                state.last_seqnum = None;
+                drop(state);
+
+                gst::Pad::event_default(pad, Some(&*self.obj()), event)
+            }
+            _ => gst::Pad::event_default(pad, Some(&*self.obj()), event),
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RtpRedDec {
+    const NAME: &'static str = "GstRtpRedDec2";
+    type Type = super::RtpRedDec;
+    type ParentType = gst::Element;
+
+    fn with_class(klass: &Self::Class) -> Self {
+        let templ = klass.pad_template("sink").unwrap();
+        let sinkpad = gst::Pad::builder_from_template(&templ)
+            .chain_function(|pad, parent, buffer| {
+                Self::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::FlowError::Error),
+                    |this| this.sink_chain(pad, buffer),
+                )
+            })
+            .event_function(|pad, parent, event| {
+                Self::catch_panic_pad_function(parent, || false, |this| this.sink_event(pad, event))
+            })
+            .build();
+
+        let templ = klass.pad_template("src").unwrap();
+        let srcpad = gst::Pad::builder_from_template(&templ).build();
+
+        Self {
+            srcpad,
+            sinkpad,
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(State::default()),
+        }
+    }
+}
+
+impl ObjectImpl for RtpRedDec {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecUInt::builder("pt")
+                    .nick("Payload Type")
+                    .blurb("Payload type of the RED packets")
+                    .maximum(0x7f)
+                    .default_value(DEFAULT_PT)
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRtpRedDec2:pt-map:
+                 *
+                 * Caps for the payload types carried in the RED packets, e.g.
+                 * `application/x-rtp2-pt-map, 96=(GstCaps)"application/x-rtp\,..."`.
+                 */
+                glib::ParamSpecBoxed::builder::<gst::Structure>("pt-map")
+                    .nick("RTP Payload Type Map")
+                    .blurb("Mapping of RTP payload type to caps")
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "pt" => settings.pt = value.get().expect("type checked upstream"),
+            "pt-map" => settings.pt_map = value.get().expect("type checked upstream"),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "pt" => settings.pt.to_value(),
+            "pt-map" => settings.pt_map.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let obj = self.obj();
+        obj.add_pad(&self.sinkpad).unwrap();
+        obj.add_pad(&self.srcpad).unwrap();
+    }
+}
+
+impl GstObjectImpl for RtpRedDec {}
+
+impl ElementImpl for RtpRedDec {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTP RED Decoder",
+                "Codec/Depayloader/Network/RTP",
+                "Decapsulates RTP packets from RED packets (RFC 2198)",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let caps = gst::Caps::builder("application/x-rtp").build();
+
+            let srcpad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            let sinkpad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            vec![srcpad_template, sinkpad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::ReadyToPaused {
+            *self.state.lock().unwrap() = State::default();
+        }
+
+        self.parent_change_state(transition)
+    }
+}
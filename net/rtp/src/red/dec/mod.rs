@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::glib;
+use gst::prelude::*;
+
+pub mod imp;
+
+glib::wrapper! {
+    pub struct RtpRedDec(ObjectSubclass<imp::RtpRedDec>) @extends gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "rtpreddec2",
+        gst::Rank::NONE,
+        RtpRedDec::static_type(),
+    )
+}
@@ -0,0 +1,364 @@
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * SECTION:element-rtpredenc2
+ * @see_also: rtpreddec2, rtpulpfecenc2, rtpsend
+ *
+ * Encapsulates RTP packets into RED packets as per [RFC 2198][rfc-2198].
+ *
+ * With #rtpredenc2:distance set, every RED packet carries the payload of that many previous
+ * packets as redundant blocks, which allows the receiver to restore lost packets. With a
+ * distance of 0, RED packets only contain the primary block. This is commonly used for WebRTC,
+ * where the media packets and the ULPFEC packets produced by `rtpulpfecenc2` are sent together
+ * in one RED stream.
+ *
+ * The output caps are the input caps with the `payload` field set to #rtpredenc2:pt, so the
+ * element is placed right before the `rtp_sink` pad of `rtpsend`.
+ *
+ * [rfc-2198]: https://www.rfc-editor.org/rfc/rfc2198.html
+ *
+ * ## Example pipeline
+ *
+ * |[
+ * gst-launch-1.0 videotestsrc ! vp8enc ! rtpvp8pay2 ! rtpulpfecenc2 pt=123 ! rtpredenc2 pt=122 ! udpsink host=127.0.0.1 port=5004
+ * ]| This will send a VP8 stream together with ULPFEC packets encapsulated in RED.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::{glib, prelude::*, subclass::prelude::*};
+use rtp_types::{RtpPacket, RtpPacketBuilder};
+use std::collections::VecDeque;
+use std::sync::{LazyLock, Mutex};
+
+use crate::red::{self, Block, MAX_BLOCK_LENGTH, MAX_TIMESTAMP_OFFSET};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rtpredenc2",
+        gst::DebugColorFlags::empty(),
+        Some("RTP RED Encoder"),
+    )
+});
+
+const DEFAULT_PT: u32 = 122;
+const DEFAULT_DISTANCE: u32 = 0;
+const DEFAULT_ALLOW_NO_RED_BLOCKS: bool = true;
+
+#[derive(Debug, Clone)]
+struct Settings {
+    pt: u32,
+    distance: u32,
+    allow_no_red_blocks: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            pt: DEFAULT_PT,
+            distance: DEFAULT_DISTANCE,
+            allow_no_red_blocks: DEFAULT_ALLOW_NO_RED_BLOCKS,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct HistoryPacket {
+    pt: u8,
+    timestamp: u32,
+    payload: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    ssrc: Option<u32>,
+    history: VecDeque<HistoryPacket>,
+}
+
+pub struct RtpRedEnc {
+    srcpad: gst::Pad,
+    sinkpad: gst::Pad,
+    settings: Mutex<Settings>,
+    state: Mutex<State>,
+}
+
+impl RtpRedEnc {
+    fn sink_chain(
+        &self,
+        pad: &gst::Pad,
+        buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let settings = self.settings.lock().unwrap().clone();
+
+        let map = buffer.map_readable().map_err(|_| {
+            gst::error!(CAT, obj = pad, "Failed to map buffer readable");
+            gst::FlowError::Error
+        })?;
+        let rtp = match RtpPacket::parse(&map) {
+            Ok(rtp) => rtp,
+            Err(err) => {
+                gst::warning!(CAT, obj = pad, "Dropping invalid RTP packet: {err:?}");
+                return Ok(gst::FlowSuccess::Ok);
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if state.ssrc != Some(rtp.ssrc()) {
+            gst::debug!(CAT, imp = self, "New SSRC {}", rtp.ssrc());
+            state.ssrc = Some(rtp.ssrc());
+            state.history.clear();
+        }
+
+        let redundant = state
+            .history
+            .iter()
+            .filter_map(|packet| {
+                let timestamp_offset = rtp.timestamp().wrapping_sub(packet.timestamp);
+                if timestamp_offset > MAX_TIMESTAMP_OFFSET
+                    || packet.payload.len() > MAX_BLOCK_LENGTH
+                {
+                    gst::trace!(CAT, imp = self, "Packet can't be used as redundant block");
+                    return None;
+                }
+
+                Some(Block {
+                    pt: packet.pt,
+                    timestamp_offset,
+                    data: &packet.payload,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let data = if redundant.is_empty() && !settings.allow_no_red_blocks {
+            None
+        } else {
+            let payload = red::write_payload(&redundant, rtp.payload_type(), rtp.payload());
+
+            let mut builder = RtpPacketBuilder::<&[u8], &[u8]>::new()
+                .payload_type(settings.pt as u8)
+                .ssrc(rtp.ssrc())
+                .sequence_number(rtp.sequence_number())
+                .timestamp(rtp.timestamp())
+                .marker_bit(rtp.marker_bit())
+                .payload(payload.as_slice());
+            for csrc in rtp.csrc() {
+                builder = builder.add_csrc(csrc);
+            }
+            if let Some((id, data)) = rtp.extension() {
+                builder = builder.extension(id, data);
+            }
+
+            match builder.write_vec() {
+                Ok(data) => Some(data),
+                Err(err) => {
+                    gst::warning!(CAT, imp = self, "Failed to create RED packet: {err:?}");
+                    None
+                }
+            }
+        };
+
+        if settings.distance > 0 {
+            state.history.push_back(HistoryPacket {
+                pt: rtp.payload_type(),
+                timestamp: rtp.timestamp(),
+                payload: rtp.payload().to_vec(),
+            });
+        }
+        while state.history.len() > settings.distance as usize {
+            state.history.pop_front();
+        }
+        drop(state);
+        drop(map);
+
+        let outbuf = match data {
+            None => buffer,
+            Some(data) => {
+                let mut outbuf = gst::Buffer::from_mut_slice(data);
+                {
+                    let outbuf_mut = outbuf.get_mut().unwrap();
+                    let _ = buffer.copy_into(
+                        outbuf_mut,
+                        gst::BufferCopyFlags::FLAGS
+                            | gst::BufferCopyFlags::TIMESTAMPS
+                            | gst::BufferCopyFlags::META,
+                        ..,
+                    );
+                }
+                outbuf
+            }
+        };
+
+        self.srcpad.push(outbuf)
+    }
+
+    fn sink_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        gst::log!(CAT, obj = pad, "Handling event {event:?}");
+
+        match event.view() {
+            gst::EventView::Caps(caps) => {
+                let pt = self.settings.lock().unwrap().pt;
+
+                let mut caps = caps.caps_owned();
+                {
+                    let caps = caps.make_mut();
+                    if let Some(s) = caps.structure_mut(0) {
+                        s.set("payload", pt as i32);
+                    }
+                }
+
+                self.srcpad.push_event(gst::event::Caps::new(&caps))
+            }
+            gst::EventView::FlushStop(_) => {
+                *self.state.lock().unwrap() = State::default();
+                gst::Pad::event_default(pad, Some(&*self.obj()), event)
+            }
+            _ => gst::Pad::event_default(pad, Some(&*self.obj()), event),
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RtpRedEnc {
+    const NAME: &'static str = "GstRtpRedEnc2";
+    type Type = super::RtpRedEnc;
+    type ParentType = gst::Element;
+
+    fn with_class(klass: &Self::Class) -> Self {
+        let templ = klass.pad_template("sink").unwrap();
+        let sinkpad = gst::Pad::builder_from_template(&templ)
+            .chain_function(|pad, parent, buffer| {
+                Self::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::FlowError::Error),
+                    |this| this.sink_chain(pad, buffer),
+                )
+            })
+            .event_function(|pad, parent, event| {
+                Self::catch_panic_pad_function(parent, || false, |this| this.sink_event(pad, event))
+            })
+            .build();
+
+        let templ = klass.pad_template("src").unwrap();
+        let srcpad = gst::Pad::builder_from_template(&templ).build();
+
+        Self {
+            srcpad,
+            sinkpad,
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(State::default()),
+        }
+    }
+}
+
+impl ObjectImpl for RtpRedEnc {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecUInt::builder("pt")
+                    .nick("Payload Type")
+                    .blurb("Payload type of the RED packets")
+                    .maximum(0x7f)
+                    .default_value(DEFAULT_PT)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("distance")
+                    .nick("Distance")
+                    .blurb("Number of previous packets to include as redundant blocks")
+                    .default_value(DEFAULT_DISTANCE)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecBoolean::builder("allow-no-red-blocks")
+                    .nick("Allow No RED Blocks")
+                    .blurb("Whether to create RED packets without redundant blocks")
+                    .default_value(DEFAULT_ALLOW_NO_RED_BLOCKS)
+                    .mutable_playing()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "pt" => settings.pt = value.get().expect("type checked upstream"),
+            "distance" => settings.distance = value.get().expect("type checked upstream"),
+            "allow-no-red-blocks" => {
+                settings.allow_no_red_blocks = value.get().expect("type checked upstream")
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "pt" => settings.pt.to_value(),
+            "distance" => settings.distance.to_value(),
+            "allow-no-red-blocks" => settings.allow_no_red_blocks.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let obj = self.obj();
+        obj.add_pad(&self.sinkpad).unwrap();
+        obj.add_pad(&self.srcpad).unwrap();
+    }
+}
+
+impl GstObjectImpl for RtpRedEnc {}
+
+impl ElementImpl for RtpRedEnc {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTP RED Encoder",
+                "Codec/Payloader/Network/RTP",
+                "Encapsulates RTP packets into RED packets (RFC 2198)",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let caps = gst::Caps::builder("application/x-rtp").build();
+
+            let srcpad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            let sinkpad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            vec![srcpad_template, sinkpad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::ReadyToPaused {
+            *self.state.lock().unwrap() = State::default();
+        }
+
+        self.parent_change_state(transition)
+    }
+}
@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::glib;
+use gst::prelude::*;
+
+pub mod imp;
+
+glib::wrapper! {
+    pub struct RtpRedEnc(ObjectSubclass<imp::RtpRedEnc>) @extends gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "rtpredenc2",
+        gst::Rank::NONE,
+        RtpRedEnc::static_type(),
+    )
+}
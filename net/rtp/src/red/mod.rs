@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! RTP payload format for redundant data (RED) as described in RFC 2198.
+
+pub mod dec;
+pub mod enc;
+
+/// Maximum offset of the RTP timestamp of a redundant block
+pub const MAX_TIMESTAMP_OFFSET: u32 = 0x3fff;
+/// Maximum length of a redundant block
+pub const MAX_BLOCK_LENGTH: usize = 0x3ff;
+
+/// A block of a RED payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Block<'a> {
+    pub pt: u8,
+    /// Offset of the block's RTP timestamp before the RTP timestamp of the RED packet
+    pub timestamp_offset: u32,
+    pub data: &'a [u8],
+}
+
+/// Creates a RED payload from the redundant blocks, oldest first, and the primary block.
+///
+/// The redundant blocks must not exceed [`MAX_TIMESTAMP_OFFSET`] and [`MAX_BLOCK_LENGTH`].
+pub fn write_payload(redundant: &[Block], primary_pt: u8, primary: &[u8]) -> Vec<u8> {
+    let len = redundant
+        .iter()
+        .map(|block| 4 + block.data.len())
+        .sum::<usize>()
+        + 1
+        + primary.len();
+    let mut payload = Vec::with_capacity(len);
+
+    for block in redundant {
+        assert!(block.timestamp_offset <= MAX_TIMESTAMP_OFFSET);
+        assert!(block.data.len() <= MAX_BLOCK_LENGTH);
+
+        let length = block.data.len();
+        payload.push(0x80 | block.pt);
+        payload.push((block.timestamp_offset >> 6) as u8);
+        payload.push(((block.timestamp_offset & 0x3f) << 2) as u8 | (length >> 8) as u8);
+        payload.push(length as u8);
+    }
+    payload.push(primary_pt & 0x7f);
+
+    for block in redundant {
+        payload.extend_from_slice(block.data);
+    }
+    payload.extend_from_slice(primary);
+
+    payload
+}
+
+/// Parses a RED payload into the redundant blocks, oldest first, and the primary block
+pub fn parse_payload(payload: &[u8]) -> Option<(Vec<Block>, Block)> {
+    let mut headers = Vec::new();
+    let mut offset = 0;
+
+    let primary_pt = loop {
+        let header = payload.get(offset)?;
+        if header & 0x80 == 0 {
+            offset += 1;
+            break header & 0x7f;
+        }
+
+        let header = payload.get(offset..offset + 4)?;
+        let pt = header[0] & 0x7f;
+        let timestamp_offset = ((header[1] as u32) << 6) | (header[2] >> 2) as u32;
+        let length = (((header[2] & 0x03) as usize) << 8) | header[3] as usize;
+        headers.push((pt, timestamp_offset, length));
+        offset += 4;
+    };
+
+    let mut redundant = Vec::with_capacity(headers.len());
+    for (pt, timestamp_offset, length) in headers {
+        let data = payload.get(offset..offset + length)?;
+        redundant.push(Block {
+            pt,
+            timestamp_offset,
+            data,
+        });
+        offset += length;
+    }
+
+    let primary = Block {
+        pt: primary_pt,
+        timestamp_offset: 0,
+        data: &payload[offset..],
+    };
+
+    Some((redundant, primary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_parse() {
+        let redundant = [
+            Block {
+                pt: 96,
+                timestamp_offset: 1920,
+                data: &[1, 2, 3],
+            },
+            Block {
+                pt: 97,
+                timestamp_offset: 960,
+                data: &[4, 5],
+            },
+        ];
+        let payload = write_payload(&redundant, 96, &[6, 7, 8, 9]);
+        assert_eq!(
+            payload,
+            [0xe0, 0x1e, 0x00, 0x03, 0xe1, 0x0f, 0x00, 0x02, 0x60, 1, 2, 3, 4, 5, 6, 7, 8, 9]
+        );
+
+        let (parsed, primary) = parse_payload(&payload).unwrap();
+        assert_eq!(parsed, redundant);
+        assert_eq!(primary.pt, 96);
+        assert_eq!(primary.data, &[6, 7, 8, 9]);
+
+        // Only a primary block
+        let (parsed, primary) = parse_payload(&[0x60, 1, 2]).unwrap();
+        assert!(parsed.is_empty());
+        assert_eq!(primary.data, &[1, 2]);
+
+        // Truncated block
+        assert!(parse_payload(&[0xe0, 0x1e, 0x00, 0x03, 0x60, 1, 2]).is_none());
+        assert!(parse_payload(&[0xe0, 0x1e]).is_none());
+        assert!(parse_payload(&[]).is_none());
+    }
+}
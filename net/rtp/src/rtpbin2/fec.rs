@@ -0,0 +1,278 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Forward error correction of RTP packets with ULPFEC as described in RFC 5109.
+//!
+//! The FEC packets are sent in the same stream as the packets they protect, with the same SSRC
+//! and a different payload type, and share the seqnum space with them.
+
+use std::collections::HashMap;
+
+use rtp_types::{RtpPacket, RtpPacketBuilder};
+
+use crate::fec::{FecGroup, Protection, ProtectionMatrix, Recovery, Stats};
+use crate::ulpfec::{self, MAX_MASK_BITS};
+
+pub const DEFAULT_PROTECTION: Protection = Protection::Row;
+pub const DEFAULT_COLUMNS: u32 = 10;
+pub const DEFAULT_ROWS: u32 = 4;
+
+#[derive(Debug)]
+struct FecStream {
+    fec_pt: u8,
+    matrix: ProtectionMatrix,
+    // Next seqnum of the stream, shared by the media and FEC packets
+    next_seqnum: u16,
+    // Complete groups for which FEC packets are sent at the next frame boundary
+    pending: Vec<FecGroup>,
+    last_timestamp: Option<u32>,
+}
+
+impl FecStream {
+    fn create_fec_packets(&mut self, ssrc: u32) -> Vec<Vec<u8>> {
+        let groups = std::mem::take(&mut self.pending);
+        let Some(timestamp) = self.last_timestamp else {
+            return Vec::new();
+        };
+
+        let mut packets = Vec::with_capacity(groups.len());
+        for group in groups {
+            let Some(payload) = ulpfec::write_payload(&group.seqnums, &group.parity) else {
+                warn!("Can't protect packets {:?}, too far apart", group.seqnums);
+                continue;
+            };
+
+            let seqnum = self.next_seqnum;
+            let data = match RtpPacketBuilder::<&[u8], &[u8]>::new()
+                .payload_type(self.fec_pt)
+                .ssrc(ssrc)
+                .sequence_number(seqnum)
+                .timestamp(timestamp)
+                .payload(payload.as_slice())
+                .write_vec()
+            {
+                Ok(data) => data,
+                Err(err) => {
+                    warn!("Failed to create FEC packet: {err:?}");
+                    continue;
+                }
+            };
+            self.next_seqnum = self.next_seqnum.wrapping_add(1);
+
+            trace!("Created FEC packet {seqnum} protecting {:?}", group.seqnums);
+            packets.push(data);
+        }
+
+        packets
+    }
+}
+
+/// Protects the sent packets of each SSRC with ULPFEC packets. FEC packets are only sent between
+/// frames, i.e. after a packet with the marker bit set or before a packet with a new RTP
+/// timestamp.
+#[derive(Debug)]
+pub struct FecSender {
+    protection: Protection,
+    columns: u32,
+    rows: u32,
+    streams: HashMap<u32, FecStream>,
+}
+
+impl Default for FecSender {
+    fn default() -> Self {
+        Self {
+            protection: DEFAULT_PROTECTION,
+            columns: DEFAULT_COLUMNS,
+            rows: DEFAULT_ROWS,
+            streams: HashMap::new(),
+        }
+    }
+}
+
+impl FecSender {
+    pub fn set_protection(&mut self, protection: Protection, columns: u32, rows: u32) {
+        self.protection = protection;
+        self.columns = columns;
+        self.rows = rows;
+        if ProtectionMatrix::new(protection, columns, rows).span() > MAX_MASK_BITS {
+            warn!("Packets of a column are too far apart, increase the number of rows or reduce the number of columns");
+        }
+    }
+
+    /// Prepares sending a media packet of a protected stream. Returns the seqnum the packet has
+    /// to be sent with and the FEC packets that have to be sent before it.
+    pub fn prepare(&mut self, rtp: &RtpPacket, fec_pt: u8) -> (u16, Vec<Vec<u8>>) {
+        let ssrc = rtp.ssrc();
+        let stream = self.streams.entry(ssrc).or_insert_with(|| {
+            debug!("Protecting ssrc {ssrc:#08x} with FEC payload type {fec_pt}");
+            FecStream {
+                fec_pt,
+                matrix: ProtectionMatrix::new(self.protection, self.columns, self.rows),
+                next_seqnum: rtp.sequence_number(),
+                pending: Vec::new(),
+                last_timestamp: None,
+            }
+        });
+
+        let mut packets = Vec::new();
+        if stream
+            .last_timestamp
+            .is_some_and(|last| last != rtp.timestamp())
+        {
+            packets = stream.create_fec_packets(ssrc);
+        }
+        stream.fec_pt = fec_pt;
+
+        let seqnum = stream.next_seqnum;
+        stream.next_seqnum = stream.next_seqnum.wrapping_add(1);
+
+        (seqnum, packets)
+    }
+
+    /// Adds a media packet as it is sent, after [`Self::prepare`]. Returns the FEC packets that
+    /// have to be sent after it.
+    pub fn sent(&mut self, rtp: &RtpPacket, packet: &[u8]) -> Vec<Vec<u8>> {
+        let ssrc = rtp.ssrc();
+        let Some(stream) = self.streams.get_mut(&ssrc) else {
+            return Vec::new();
+        };
+
+        let groups = stream.matrix.push(rtp.sequence_number(), packet);
+        stream.pending.extend(groups);
+        stream.last_timestamp = Some(rtp.timestamp());
+
+        if rtp.marker_bit() {
+            stream.create_fec_packets(ssrc)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Creates the FEC packets for all complete groups that were not sent yet
+    pub fn drain(&mut self) -> Vec<Vec<u8>> {
+        self.streams
+            .iter_mut()
+            .flat_map(|(&ssrc, stream)| stream.create_fec_packets(ssrc))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.streams.clear();
+    }
+}
+
+/// Restores lost packets of each SSRC from the received ULPFEC packets
+#[derive(Debug, Default)]
+pub struct FecReceiver {
+    streams: HashMap<u32, Recovery>,
+    // Statistics of streams that were removed
+    previous_stats: Stats,
+}
+
+impl FecReceiver {
+    /// Handles a received packet of a protected stream or a FEC packet protecting it. Returns
+    /// the restored packets in seqnum order.
+    pub fn handle_packet(&mut self, rtp: &RtpPacket, packet: &[u8], is_fec: bool) -> Vec<Vec<u8>> {
+        let recovery = self
+            .streams
+            .entry(rtp.ssrc())
+            .or_insert_with_key(|&ssrc| Recovery::new(ssrc));
+
+        let seqnum = rtp.sequence_number();
+        if is_fec {
+            recovery.add_non_media(seqnum);
+            match ulpfec::parse_payload(rtp.payload()) {
+                Some((seqnums, parity)) => {
+                    trace!("FEC packet {seqnum} protecting {seqnums:?}");
+                    recovery.add_fec(&seqnums, parity);
+                }
+                None => {
+                    warn!("Invalid FEC packet {seqnum}");
+                    return Vec::new();
+                }
+            }
+        } else if recovery.add_packet(seqnum, packet).is_none() {
+            return Vec::new();
+        }
+
+        recovery
+            .recover()
+            .into_iter()
+            .map(|(ext_seqnum, packet)| {
+                debug!(
+                    "Restored packet {} of ssrc {:#08x}",
+                    ext_seqnum as u16,
+                    rtp.ssrc()
+                );
+                packet
+            })
+            .collect()
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.streams.values().map(|recovery| recovery.stats()).fold(
+            self.previous_stats,
+            |acc, stats| Stats {
+                received: acc.received + stats.received,
+                fec_received: acc.fec_received + stats.fec_received,
+                recovered: acc.recovered + stats.recovered,
+                lost: acc.lost + stats.lost,
+            },
+        )
+    }
+
+    pub fn clear(&mut self) {
+        self.previous_stats = self.stats();
+        self.streams.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fec::tests::generate_packet;
+
+    #[test]
+    fn send_receive() {
+        let mut sender = FecSender::default();
+        sender.set_protection(Protection::Row, 4, 1);
+        let mut receiver = FecReceiver::default();
+
+        // Two packets per frame, starting with seqnum 100
+        let mut sent = Vec::new();
+        for i in 0..8u16 {
+            let packet = generate_packet(100 + i, 1000 * (i as u32 / 2), 20 + i as usize);
+            let rtp = RtpPacket::parse(&packet).unwrap();
+            let (seqnum, fec_packets) = sender.prepare(&rtp, 123);
+            sent.extend(fec_packets);
+
+            let mut packet = packet.clone();
+            packet[2..4].copy_from_slice(&seqnum.to_be_bytes());
+            let rtp = RtpPacket::parse(&packet).unwrap();
+            let fec_packets = sender.sent(&rtp, &packet);
+            sent.push(packet.clone());
+            sent.extend(fec_packets);
+        }
+        sent.extend(sender.drain());
+
+        let seqnums = sent
+            .iter()
+            .map(|packet| RtpPacket::parse(packet).unwrap().sequence_number())
+            .collect::<Vec<_>>();
+        assert_eq!(seqnums, (100..110).collect::<Vec<_>>());
+
+        // The second packet of the first row is lost
+        let mut restored = Vec::new();
+        for packet in sent
+            .iter()
+            .filter(|packet| packet[2..4] != 101u16.to_be_bytes())
+        {
+            let rtp = RtpPacket::parse(packet).unwrap();
+            restored.extend(receiver.handle_packet(&rtp, packet, rtp.payload_type() == 123));
+        }
+        assert_eq!(restored, vec![sent[1].clone()]);
+
+        let stats = receiver.stats();
+        assert_eq!(stats.fec_received, 2);
+        assert_eq!(stats.recovered, 1);
+    }
+}
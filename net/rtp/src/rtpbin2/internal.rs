@@ -4,15 +4,18 @@ use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
     task::Waker,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use gst::{glib, prelude::*};
 use std::sync::{LazyLock, OnceLock};
 
 use super::config::Rtp2Session;
+use super::fec::FecSender;
+use super::rtx::RtxSender;
 use super::session::{RtpProfile, Session};
 use super::source::ReceivedRb;
+use super::twcc::{self, Twcc};
 
 static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
@@ -28,6 +31,8 @@ static SHARED_RTP_STATE: OnceLock<Mutex<HashMap<String, SharedRtpState>>> = Once
 pub struct SharedRtpState {
     name: String,
     inner: Arc<Mutex<SharedRtpStateInner>>,
+    // Transport-wide congestion control is shared by all sessions
+    twcc: Arc<Mutex<Twcc>>,
 }
 
 #[derive(Debug)]
@@ -54,6 +59,7 @@ impl SharedRtpState {
                     send_outstanding: false,
                     recv_outstanding: true,
                 })),
+                twcc: Arc::new(Mutex::new(Twcc::default())),
             })
             .clone()
     }
@@ -74,6 +80,7 @@ impl SharedRtpState {
                     send_outstanding: true,
                     recv_outstanding: false,
                 })),
+                twcc: Arc::new(Mutex::new(Twcc::default())),
             })
             .clone()
     }
@@ -111,7 +118,11 @@ impl SharedRtpState {
             .unwrap()
             .sessions
             .entry(id)
-            .or_insert_with(f)
+            .or_insert_with(|| {
+                let session = f();
+                session.inner.lock().unwrap().twcc = self.twcc.clone();
+                session
+            })
             .clone()
     }
 }
@@ -151,9 +162,21 @@ pub(crate) struct SharedSessionInner {
     pub(crate) session: Session,
 
     pub(crate) pt_map: HashMap<u8, gst::Caps>,
+    // Payload type to the payload type of its retransmission stream
+    pub(crate) rtx_pt_map: HashMap<u8, u8>,
+    pub(crate) rtx_sender: RtxSender,
+    // Payload type to the payload type of the ULPFEC packets protecting it
+    pub(crate) fec_pt_map: HashMap<u8, u8>,
+    pub(crate) fec_sender: FecSender,
+
+    // Header extension ids of the transport-wide sequence numbers
+    pub(crate) twcc_send_ext_id: Option<u8>,
+    pub(crate) twcc_recv_ext_id: Option<u8>,
+    pub(crate) twcc: Arc<Mutex<Twcc>>,
 
     pub(crate) rtcp_waker: Option<Waker>,
     pub(crate) rtp_send_sinkpad: Option<gst::Pad>,
+    pub(crate) rtp_send_srcpad: Option<gst::Pad>,
 }
 
 impl SharedSessionInner {
@@ -164,8 +187,16 @@ impl SharedSessionInner {
             session: Session::new(),
 
             pt_map: HashMap::default(),
+            rtx_pt_map: HashMap::default(),
+            rtx_sender: RtxSender::default(),
+            fec_pt_map: HashMap::default(),
+            fec_sender: FecSender::default(),
+            twcc_send_ext_id: None,
+            twcc_recv_ext_id: None,
+            twcc: Arc::new(Mutex::new(Twcc::default())),
             rtcp_waker: None,
             rtp_send_sinkpad: None,
+            rtp_send_srcpad: None,
         }
     }
 
@@ -177,12 +208,149 @@ impl SharedSessionInner {
         let Some((pt, clock_rate)) = pt_clock_rate_from_caps(&caps) else {
             return;
         };
+        let rtx_apt = rtx_apt_from_caps(&caps);
+        let fec_pt = fec_pt_from_caps(&caps);
+        let twcc_ext_id = caps.structure(0).and_then(twcc::extension_id_from_caps);
         let caps_clone = caps.clone();
         self.pt_map
             .entry(pt)
             .and_modify(move |entry| *entry = caps)
             .or_insert_with(move || caps_clone);
         self.session.set_pt_clock_rate(pt, clock_rate);
+
+        // FEC packets are sent with the clock rate of the stream they protect
+        if let Some(fec_pt) = fec_pt {
+            gst::debug!(CAT, "Payload type {fec_pt} is FEC for {pt}");
+            self.session.set_pt_clock_rate(fec_pt, clock_rate);
+        }
+        if let Some(&fec_pt) = self.fec_pt_map.get(&pt) {
+            self.session.set_pt_clock_rate(fec_pt, clock_rate);
+        }
+
+        if let Some(apt) = rtx_apt {
+            gst::debug!(CAT, "Payload type {pt} is retransmission of {apt}");
+            self.rtx_pt_map.insert(apt, pt);
+        }
+
+        if let Some(id) = twcc_ext_id {
+            if self.twcc_recv_ext_id.is_none() {
+                gst::debug!(
+                    CAT,
+                    "Using transport-wide sequence numbers with extension id {id}"
+                );
+                self.twcc_recv_ext_id = Some(id);
+            }
+        }
+    }
+
+    /// Adds the mapping of payload types to their retransmission payload types from a
+    /// structure with fields of the form `96=(uint)97`
+    pub fn add_rtx_pt_map(&mut self, map: &gst::StructureRef) {
+        for (pt, rtx_pt) in map.iter() {
+            let Some((pt, rtx_pt)) = Option::zip(
+                pt.parse::<u8>().ok(),
+                rtx_pt
+                    .get::<u32>()
+                    .ok()
+                    .and_then(|rtx_pt| u8::try_from(rtx_pt).ok()),
+            ) else {
+                gst::warning!(CAT, "Invalid RTX payload type mapping {pt}={rtx_pt:?}");
+                continue;
+            };
+            self.rtx_pt_map.insert(pt, rtx_pt);
+        }
+    }
+
+    pub(crate) fn rtx_pt(&self, pt: u8) -> Option<u8> {
+        self.rtx_pt_map.get(&pt).copied()
+    }
+
+    /// The payload type that is retransmitted with the given RTX payload type
+    pub(crate) fn rtx_apt(&self, rtx_pt: u8) -> Option<u8> {
+        self.rtx_pt_map
+            .iter()
+            .find_map(|(&pt, &other)| (other == rtx_pt).then_some(pt))
+    }
+
+    /// Adds the mapping of payload types to the payload types of the ULPFEC packets protecting
+    /// them from a structure with fields of the form `96=(uint)123`
+    pub fn add_fec_pt_map(&mut self, map: &gst::StructureRef) {
+        for (pt, fec_pt) in map.iter() {
+            let Some((pt, fec_pt)) = Option::zip(
+                pt.parse::<u8>().ok(),
+                fec_pt
+                    .get::<u32>()
+                    .ok()
+                    .and_then(|fec_pt| u8::try_from(fec_pt).ok()),
+            ) else {
+                gst::warning!(CAT, "Invalid FEC payload type mapping {pt}={fec_pt:?}");
+                continue;
+            };
+            if let Some(clock_rate) = self.session.clock_rate_from_pt(pt) {
+                self.session.set_pt_clock_rate(fec_pt, clock_rate);
+            }
+            self.fec_pt_map.insert(pt, fec_pt);
+        }
+    }
+
+    /// The payload type of the FEC packets protecting the given payload type
+    pub(crate) fn fec_pt(&self, pt: u8) -> Option<u8> {
+        self.fec_pt_map.get(&pt).copied()
+    }
+
+    /// Whether the given payload type is used by FEC packets
+    pub(crate) fn is_fec_pt(&self, pt: u8) -> bool {
+        self.fec_pt_map.values().any(|&fec_pt| fec_pt == pt)
+    }
+
+    /// Creates RTX packets for the requested packets of a local sender
+    pub(crate) fn retransmission_packets(
+        &mut self,
+        ssrc: u32,
+        seqnums: &[u16],
+        now: Instant,
+    ) -> Vec<Vec<u8>> {
+        let rtx_pt_map = &self.rtx_pt_map;
+        let packets = self
+            .rtx_sender
+            .retransmit(ssrc, seqnums, |pt| rtx_pt_map.get(&pt).copied());
+
+        let Some(id) = self.twcc_send_ext_id else {
+            return packets;
+        };
+
+        // Retransmissions are sent over the same transport and need their own transport-wide
+        // sequence numbers
+        let mut twcc = self.twcc.lock().unwrap();
+        packets
+            .into_iter()
+            .map(|data| {
+                let stamped = match rtp_types::RtpPacket::parse(&data) {
+                    Ok(rtp) => twcc.sender.stamp(&rtp, id, now),
+                    Err(_) => None,
+                };
+                stamped.unwrap_or(data)
+            })
+            .collect()
+    }
+
+    /// Generates a transport-wide feedback packet if one is due
+    pub(crate) fn poll_twcc_feedback(&mut self, now: Instant) -> Option<Vec<u8>> {
+        let mut twcc = self.twcc.lock().unwrap();
+        if twcc
+            .receiver
+            .poll_feedback_timeout()
+            .is_none_or(|timeout| timeout > now)
+        {
+            return None;
+        }
+
+        let ssrc = self.session.ensure_internal_send_src();
+        twcc.receiver.poll_feedback(now, ssrc)
+    }
+
+    pub(crate) fn poll_twcc_feedback_timeout(&self) -> Option<Instant> {
+        self.twcc.lock().unwrap().receiver.poll_feedback_timeout()
     }
 
     pub(crate) fn caps_from_pt(&self, pt: u8) -> gst::Caps {
@@ -369,6 +537,32 @@ pub fn pt_clock_rate_from_caps(caps: &gst::CapsRef) -> Option<(u8, u32)> {
     }
 }
 
+fn rtx_apt_from_caps(caps: &gst::CapsRef) -> Option<u8> {
+    let s = caps.structure(0)?;
+    if !s
+        .get::<&str>("encoding-name")
+        .is_ok_and(|encoding_name| encoding_name.eq_ignore_ascii_case("RTX"))
+    {
+        return None;
+    }
+
+    // SDP fmtp parameters end up as string fields in the caps
+    let apt = match s.get::<&str>("apt") {
+        Ok(apt) => apt.parse::<i32>().ok(),
+        Err(_) => s.get::<i32>("apt").ok(),
+    }?;
+
+    u8::try_from(apt).ok().filter(|apt| *apt <= 127)
+}
+
+/// Payload type of the FEC packets protecting a stream, as set by the FEC encoders
+fn fec_pt_from_caps(caps: &gst::CapsRef) -> Option<u8> {
+    let s = caps.structure(0)?;
+    let fec_pt = s.get::<i32>("fec-payload").ok()?;
+
+    u8::try_from(fec_pt).ok().filter(|fec_pt| *fec_pt <= 127)
+}
+
 static RUST_CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
         "rust-log",
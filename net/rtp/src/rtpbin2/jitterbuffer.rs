@@ -1,15 +1,28 @@
 use crate::utils::ExtendedSeqnum;
 use rtp_types::RtpPacket;
 use std::cmp::Ordering;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::{Duration, Instant};
 
+/// Round-trip time assumed for retransmission requests until it could be measured
+const DEFAULT_RTX_RTT: Duration = Duration::from_millis(40);
+/// Minimum interval between two retransmission requests for the same packet
+const MIN_RTX_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+/// Time to wait for a reordered packet before requesting its retransmission
+const RTX_REORDER_DELAY: Duration = Duration::from_millis(5);
+/// Gaps larger than this are considered a discontinuity of the stream rather than packet loss
+const MAX_RTX_GAP: u64 = 1000;
+
 #[derive(Debug, Clone, Copy)]
 struct Stats {
     num_late: u64,
     num_lost: u64,
     num_duplicates: u64,
     num_pushed: u64,
+    num_rtx_requests: u64,
+    num_rtx_success: u64,
+    num_rtx_failed: u64,
+    rtx_rtt: Duration,
 }
 
 impl From<Stats> for gst::Structure {
@@ -19,6 +32,10 @@ impl From<Stats> for gst::Structure {
             .field("num-duplicates", stats.num_duplicates)
             .field("num-lost", stats.num_lost)
             .field("num-pushed", stats.num_pushed)
+            .field("num-rtx-requests", stats.num_rtx_requests)
+            .field("num-rtx-success", stats.num_rtx_success)
+            .field("num-rtx-failed", stats.num_rtx_failed)
+            .field("rtx-rtt", stats.rtx_rtt.as_nanos() as u64)
             .build()
     }
 }
@@ -41,6 +58,26 @@ pub struct JitterBuffer {
     stats: Stats,
     flushing: bool,
     can_forward_packets_when_empty: bool,
+    do_retransmission: bool,
+    // Highest extended seqnum received so far and its PTS
+    highest_input: Option<(u64, u64)>,
+    // Packets that were not received yet and whose retransmission can still be requested
+    missing: BTreeMap<u64, MissingPacket>,
+}
+
+#[derive(Debug)]
+struct MissingPacket {
+    // Time by which the packet has to arrive to still be output
+    deadline: Instant,
+    next_request: Instant,
+    last_request: Option<Instant>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RetransmissionRequest {
+    pub seqnums: Vec<u16>,
+    // Maximum delay after which the request is useless
+    pub max_delay: Duration,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -109,9 +146,24 @@ impl JitterBuffer {
                 num_lost: 0,
                 num_duplicates: 0,
                 num_pushed: 0,
+                num_rtx_requests: 0,
+                num_rtx_success: 0,
+                num_rtx_failed: 0,
+                rtx_rtt: DEFAULT_RTX_RTT,
             },
             flushing: true,
             can_forward_packets_when_empty: false,
+            do_retransmission: false,
+            highest_input: None,
+            missing: BTreeMap::new(),
+        }
+    }
+
+    /// Track missing packets so that their retransmission can be requested
+    pub fn set_do_retransmission(&mut self, do_retransmission: bool) {
+        self.do_retransmission = do_retransmission;
+        if !do_retransmission {
+            self.missing.clear();
         }
     }
 
@@ -140,6 +192,8 @@ impl JitterBuffer {
         self.flushing = flushing;
         self.last_output_seqnum = None;
         self.can_forward_packets_when_empty = false;
+        self.highest_input = None;
+        self.missing.clear();
     }
 
     pub fn queue_packet(&mut self, rtp: &RtpPacket, mut pts: u64, now: Instant) -> QueueResult {
@@ -149,14 +203,26 @@ impl JitterBuffer {
 
         // From this point on we always work with extended sequence numbers
         let seqnum = self.extended_seqnum.next(rtp.sequence_number());
+        let retransmitted = self.missing.contains_key(&seqnum);
 
-        if let Some(ts) = self.last_input_ts {
-            pts = pts.max(ts);
-        }
+        if retransmitted {
+            // Packets filling a gap keep their place in the timeline instead of being
+            // clamped to the latest input timestamp, which would delay all packets after them
+            if let Some(ts) = self.last_input_ts {
+                pts = pts.min(ts);
+            }
+            if let Some((_, base_ts)) = self.base_times {
+                pts = pts.max(base_ts);
+            }
+        } else {
+            if let Some(ts) = self.last_input_ts {
+                pts = pts.max(ts);
+            }
 
-        self.last_input_ts = Some(pts);
+            self.last_input_ts = Some(pts);
+        }
 
-        self.base_times.get_or_insert_with(|| {
+        let (base_instant, base_ts) = *self.base_times.get_or_insert_with(|| {
             debug!("Selected base times {now:?} {pts}");
 
             (now, pts)
@@ -192,6 +258,10 @@ impl JitterBuffer {
             }
         }
 
+        if self.do_retransmission {
+            self.update_missing(seqnum, pts, now, base_instant, base_ts);
+        }
+
         if self.items.is_empty()
             // can forward after the first packet's deadline has been reached
             && self.can_forward_packets_when_empty
@@ -286,6 +356,9 @@ impl JitterBuffer {
             // Safe unwrap, we know the queue isn't empty at this point
             let packet = self.items.pop_first().unwrap();
 
+            // Everything up to the packet is either output or lost now
+            self.missing = self.missing.split_off(&(packet.seqnum + 1));
+
             self.stats.num_pushed += 1;
             self.can_forward_packets_when_empty = true;
 
@@ -299,6 +372,122 @@ impl JitterBuffer {
         }
     }
 
+    fn update_missing(
+        &mut self,
+        seqnum: u64,
+        pts: u64,
+        now: Instant,
+        base_instant: Instant,
+        base_ts: u64,
+    ) {
+        if let Some(missing) = self.missing.remove(&seqnum) {
+            if let Some(last_request) = missing.last_request {
+                let rtt = now.saturating_duration_since(last_request);
+                self.stats.rtx_rtt = (self.stats.rtx_rtt * 7 + rtt) / 8;
+                self.stats.num_rtx_success += 1;
+                debug!(
+                    "Received retransmission of {seqnum} after {rtt:?}, rtt now {:?}",
+                    self.stats.rtx_rtt
+                );
+            }
+            return;
+        }
+
+        let Some((highest_seqnum, highest_pts)) = self.highest_input else {
+            self.highest_input = Some((seqnum, pts));
+            return;
+        };
+
+        if seqnum <= highest_seqnum {
+            return;
+        }
+
+        self.highest_input = Some((seqnum, pts));
+
+        let gap = seqnum - highest_seqnum - 1;
+        if gap == 0 {
+            return;
+        }
+
+        if gap > MAX_RTX_GAP {
+            debug!("Not requesting retransmission for gap of {gap} packets");
+            return;
+        }
+
+        debug!("Missing packets {} to {}", highest_seqnum + 1, seqnum - 1);
+
+        for i in 1..=gap {
+            // Interpolate the PTS the missing packet would have had
+            let expected_pts = highest_pts + (pts - highest_pts) * i / (gap + 1);
+            let deadline = base_instant
+                + Duration::from_nanos(expected_pts.saturating_sub(base_ts))
+                + self.latency;
+
+            self.missing.insert(
+                highest_seqnum + i,
+                MissingPacket {
+                    deadline,
+                    next_request: now + RTX_REORDER_DELAY,
+                    last_request: None,
+                },
+            );
+        }
+    }
+
+    /// Returns the seqnums whose retransmission should be requested now
+    pub fn poll_retransmission_request(&mut self, now: Instant) -> Option<RetransmissionRequest> {
+        let rtt = self.stats.rtx_rtt;
+        let retry_interval = rtt.max(MIN_RTX_RETRY_INTERVAL);
+        let mut seqnums = Vec::new();
+        let mut max_delay: Option<Duration> = None;
+        let mut num_failed = 0;
+
+        self.missing.retain(|&seqnum, missing| {
+            // A retransmission would not arrive in time anymore
+            if now + rtt >= missing.deadline {
+                trace!("Giving up on retransmission of {seqnum}");
+                if missing.last_request.is_some() {
+                    num_failed += 1;
+                }
+                return false;
+            }
+
+            if missing.next_request <= now {
+                missing.last_request = Some(now);
+                missing.next_request = now + retry_interval;
+                seqnums.push((seqnum & 0xffff) as u16);
+
+                let delay = missing.deadline - now - rtt;
+                max_delay = Some(max_delay.map_or(delay, |max_delay| max_delay.min(delay)));
+            }
+
+            true
+        });
+
+        self.stats.num_rtx_failed += num_failed;
+
+        let max_delay = max_delay?;
+        self.stats.num_rtx_requests += seqnums.len() as u64;
+        debug!("Requesting retransmission of {seqnums:?}");
+
+        Some(RetransmissionRequest { seqnums, max_delay })
+    }
+
+    /// Time at which the next retransmission request is due, if any
+    pub fn next_retransmission_request(&self) -> Option<Instant> {
+        self.missing
+            .values()
+            .map(|missing| missing.next_request)
+            .min()
+    }
+
+    /// Whether the packet with this seqnum is missing and was already requested
+    pub fn is_requested(&self, seqnum: u16) -> bool {
+        self.missing.iter().any(|(&missing_seqnum, missing)| {
+            (missing_seqnum & 0xffff) as u16 == seqnum && missing.last_request.is_some()
+        })
+    }
+
     pub fn stats(&self) -> gst::Structure {
         self.stats.into()
     }
@@ -691,4 +880,142 @@ mod tests {
         jb.set_flushing(false);
         assert_eq!(jb.poll(now), PollResult::Empty);
     }
+
+    #[test]
+    fn retransmission_requests() {
+        let mut jb = JitterBuffer::new(Duration::from_secs(1));
+        jb.set_flushing(false);
+        jb.set_do_retransmission(true);
+
+        let base = Instant::now();
+        let mut now = base;
+
+        let rtp_data = generate_rtp_packet(0x12345678, 0, 0, 4);
+        let packet = RtpPacket::parse(&rtp_data).unwrap();
+        let QueueResult::Queued(id_first) = jb.queue_packet(&packet, 0, now) else {
+            unreachable!()
+        };
+
+        let rtp_data = generate_rtp_packet(0x12345678, 3, 5400, 4);
+        let packet = RtpPacket::parse(&rtp_data).unwrap();
+        let QueueResult::Queued(id_fourth) = jb.queue_packet(&packet, 60_000_000, now) else {
+            unreachable!()
+        };
+
+        // Give reordered packets a chance to arrive first
+        assert_eq!(jb.poll_retransmission_request(now), None);
+        assert_eq!(
+            jb.next_retransmission_request(),
+            Some(now + RTX_REORDER_DELAY)
+        );
+
+        // The deadline of packet 1 is at 20ms + latency, minus the default RTT
+        now += RTX_REORDER_DELAY;
+        assert_eq!(
+            jb.poll_retransmission_request(now),
+            Some(RetransmissionRequest {
+                seqnums: vec![1, 2],
+                max_delay: Duration::from_millis(1020) - RTX_REORDER_DELAY - DEFAULT_RTX_RTT,
+            })
+        );
+        assert!(jb.is_requested(1));
+        assert_eq!(jb.poll_retransmission_request(now), None);
+        assert_eq!(
+            jb.next_retransmission_request(),
+            Some(now + DEFAULT_RTX_RTT)
+        );
+
+        // The retransmission of packet 1 arrives and keeps its timestamp
+        now += Duration::from_millis(10);
+        let rtp_data = generate_rtp_packet(0x12345678, 1, 1800, 4);
+        let packet = RtpPacket::parse(&rtp_data).unwrap();
+        let QueueResult::Queued(id_second) = jb.queue_packet(&packet, 20_000_000, now) else {
+            unreachable!()
+        };
+        assert!(!jb.is_requested(1));
+
+        let stats = jb.stats();
+        assert_eq!(stats.get::<u64>("num-rtx-requests").unwrap(), 2);
+        assert_eq!(stats.get::<u64>("num-rtx-success").unwrap(), 1);
+        let rtt = (DEFAULT_RTX_RTT * 7 + Duration::from_millis(10)) / 8;
+        assert_eq!(stats.get::<u64>("rtx-rtt").unwrap(), rtt.as_nanos() as u64);
+
+        // Packet 2 is requested again after one RTT
+        now = base + RTX_REORDER_DELAY + DEFAULT_RTX_RTT;
+        assert_eq!(
+            jb.poll_retransmission_request(now),
+            Some(RetransmissionRequest {
+                seqnums: vec![2],
+                max_delay: Duration::from_millis(1040) - RTX_REORDER_DELAY - DEFAULT_RTX_RTT - rtt,
+            })
+        );
+
+        now = base + Duration::from_secs(1);
+        assert_eq!(
+            jb.poll(now),
+            PollResult::Forward {
+                id: id_first,
+                discont: true
+            }
+        );
+        assert_eq!(
+            jb.poll(now),
+            PollResult::Timeout(base + Duration::from_millis(1020))
+        );
+
+        now = base + Duration::from_millis(1020);
+        assert_eq!(
+            jb.poll(now),
+            PollResult::Forward {
+                id: id_second,
+                discont: false
+            }
+        );
+
+        // Packet 2 is lost, nothing is requested anymore after the packet following it was output
+        now = base + Duration::from_millis(1060);
+        assert_eq!(
+            jb.poll(now),
+            PollResult::Forward {
+                id: id_fourth,
+                discont: true
+            }
+        );
+        assert_eq!(jb.next_retransmission_request(), None);
+    }
+
+    #[test]
+    fn retransmission_too_late() {
+        let mut jb = JitterBuffer::new(Duration::from_millis(50));
+        jb.set_flushing(false);
+        jb.set_do_retransmission(true);
+
+        let base = Instant::now();
+
+        let rtp_data = generate_rtp_packet(0x12345678, 0, 0, 4);
+        let packet = RtpPacket::parse(&rtp_data).unwrap();
+        jb.queue_packet(&packet, 0, base);
+
+        let rtp_data = generate_rtp_packet(0x12345678, 2, 3600, 4);
+        let packet = RtpPacket::parse(&rtp_data).unwrap();
+        jb.queue_packet(&packet, 40_000_000, base);
+
+        let now = base + RTX_REORDER_DELAY;
+        assert_eq!(
+            jb.poll_retransmission_request(now),
+            Some(RetransmissionRequest {
+                seqnums: vec![1],
+                max_delay: Duration::from_millis(70) - RTX_REORDER_DELAY - DEFAULT_RTX_RTT,
+            })
+        );
+
+        // A second request would not be answered before the deadline of the packet
+        let now = base + RTX_REORDER_DELAY + DEFAULT_RTX_RTT;
+        assert_eq!(jb.poll_retransmission_request(now), None);
+        assert_eq!(jb.next_retransmission_request(), None);
+
+        let stats = jb.stats();
+        assert_eq!(stats.get::<u64>("num-rtx-requests").unwrap(), 1);
+        assert_eq!(stats.get::<u64>("num-rtx-failed").unwrap(), 1);
+    }
 }
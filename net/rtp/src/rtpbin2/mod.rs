@@ -6,14 +6,17 @@ use std::sync::{LazyLock, OnceLock};
 use std::time::Duration;
 use tokio::runtime;
 mod config;
+mod fec;
 mod internal;
 mod jitterbuffer;
 mod rtprecv;
 mod rtpsend;
+mod rtx;
 mod session;
 mod source;
 mod sync;
 mod time;
+mod twcc;
 
 glib::wrapper! {
     pub struct RtpSend(ObjectSubclass<rtpsend::RtpSend>) @extends gst::Element, gst::Object;
@@ -35,13 +35,51 @@
  * RTCP back to the sender on UDP port 5007.
  * See #rtpsend for an example of how to produce such packets.
  *
+ * ## Retransmission
+ *
+ * With #rtprecv:do-retransmission enabled, the jitterbuffer keeps track of missing packets and
+ * requests their retransmission with generic NACKs (RFC 4585) as long as they can still arrive
+ * before their deadline, based on the latency and the measured round-trip time. This requires
+ * `rtp-profile=avpf` on the #rtpsend element with the same `rtp-id`.
+ *
+ * Retransmitted packets received on an RTX payload type (RFC 4588), configured either with
+ * #rtprecv:rtx-payload-type-map or with `encoding-name=RTX` caps with an `apt` field in the
+ * session's pt-map, are restored to the original packet before further processing.
+ *
+ * NACKs received for the streams of the #rtpsend element with the same `rtp-id` are answered
+ * by that element.
+ *
+ * ## Forward error correction
+ *
+ * Lost packets of payload types that have a FEC payload type configured with
+ * #rtprecv:fec-payload-type-map are restored from the ULPFEC packets (RFC 5109) in the same
+ * stream, as sent by #rtpsend with the same map, before they reach the jitterbuffer. The FEC
+ * packets themselves are not output. Statistics are provided in the `fec-stats` field of each
+ * session in #rtprecv:stats.
+ *
+ * ## Transport-wide congestion control
+ *
+ * If #rtprecv:twcc-extension-id is set, or the caps contain the transport-wide sequence number
+ * header extension, the arrival times of the received packets are reported back to the sender
+ * every #rtprecv:twcc-feedback-interval with transport-wide congestion control feedback packets
+ * on the `rtcp_src` pad of the #rtpsend element with the same `rtp-id`.
+ *
+ * Feedback received for the packets sent by the #rtpsend element with the same `rtp-id` is
+ * forwarded upstream of its `rtp_sink` pad as `RTPTWCCPackets` event, which is used by
+ * `rtpgccbwe` for bandwidth estimation.
+ *
+ * ## Encryption
+ *
+ * For SRTP, a `rtpsrtpdec` element is linked to each `rtp_sink` and `rtcp_sink` pad. SRTCP
+ * packets multiplexed with the SRTP packets are handled by the same element.
+ *
  * Since: plugins-rs-0.13.0
  */
 use std::collections::{BTreeMap, HashMap};
 use std::net::SocketAddr;
 use std::ops::{ControlFlow, Deref};
 use std::pin::Pin;
-use std::sync::{Arc, LazyLock, Mutex, MutexGuard};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, Weak};
 use std::task::{Poll, Waker};
 use std::time::{Duration, Instant, SystemTime};
 
@@ -50,18 +88,26 @@ use futures::{stream, StreamExt};
 use gst::{glib, prelude::*, subclass::prelude::*};
 use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
-use super::internal::{pt_clock_rate_from_caps, GstRustLogger, SharedRtpState, SharedSession};
+use super::fec::FecReceiver;
+use super::internal::{
+    pt_clock_rate_from_caps, GstRustLogger, SharedRtpState, SharedSession, SharedSessionInner,
+};
 use super::jitterbuffer::{self, JitterBuffer};
+use super::rtx;
 use super::session::{
-    KeyUnitRequestType, RecvReply, RequestRemoteKeyUnitReply, RtcpRecvReply, RtpProfile,
-    RTCP_MIN_REPORT_INTERVAL,
+    KeyUnitRequestType, RecvReply, RequestNackReply, RequestRemoteKeyUnitReply, RtcpRecvReply,
+    RtpProfile, RTCP_MIN_REPORT_INTERVAL,
 };
 use super::source::SourceState;
 use super::sync;
+use super::twcc;
 
 use crate::rtpbin2;
 
 const DEFAULT_LATENCY: gst::ClockTime = gst::ClockTime::from_mseconds(200);
+const DEFAULT_DO_RETRANSMISSION: bool = false;
+const DEFAULT_TWCC_EXTENSION_ID: u32 = 0;
+const DEFAULT_TWCC_FEEDBACK_INTERVAL: Duration = twcc::DEFAULT_FEEDBACK_INTERVAL;
 
 /// Initial capacity for `SmallVec`s handling items related to src pads for
 /// a given RTP seession. E.g.: `RtpRecvSrcPads`, `JitterBufferStreams`, ...
@@ -80,6 +126,11 @@ struct Settings {
     rtp_id: String,
     latency: gst::ClockTime,
     timestamping_mode: sync::TimestampingMode,
+    do_retransmission: bool,
+    rtx_pt_map: Option<gst::Structure>,
+    fec_pt_map: Option<gst::Structure>,
+    twcc_extension_id: u32,
+    twcc_feedback_interval: Duration,
 }
 
 impl Default for Settings {
@@ -88,6 +139,11 @@ impl Default for Settings {
             rtp_id: String::from("rtp-id"),
             latency: DEFAULT_LATENCY,
             timestamping_mode: sync::TimestampingMode::default(),
+            do_retransmission: DEFAULT_DO_RETRANSMISSION,
+            rtx_pt_map: None,
+            fec_pt_map: None,
+            twcc_extension_id: DEFAULT_TWCC_EXTENSION_ID,
+            twcc_feedback_interval: DEFAULT_TWCC_FEEDBACK_INTERVAL,
         }
     }
 }
@@ -134,6 +190,16 @@ impl futures::stream::Stream for JitterBufferStream {
 
         let mut jitterbuffer_store = self.recv_src_pad.jitter_buffer_store.lock().unwrap();
 
+        let retransmission_request = jitterbuffer_store
+            .jitterbuffer
+            .poll_retransmission_request(now);
+        if let Some(next_request) = jitterbuffer_store
+            .jitterbuffer
+            .next_retransmission_request()
+        {
+            lowest_wait = Some(next_request);
+        }
+
         let mut pending_items = JitterBufferPendingItems::new();
         loop {
             let ret = jitterbuffer_store.jitterbuffer.poll(now);
@@ -225,6 +291,10 @@ impl futures::stream::Stream for JitterBufferStream {
         let store_is_empty = jitterbuffer_store.store.is_empty();
         drop(jitterbuffer_store);
 
+        if let Some(request) = retransmission_request {
+            self.recv_src_pad.request_retransmission(now, request);
+        }
+
         if !pending_items.is_empty() {
             gst::trace!(
                 CAT,
@@ -291,21 +361,59 @@ struct RtpRecvSrcPadInner {
     pad: gst::Pad,
     semaphore: Arc<Semaphore>,
     jitter_buffer_store: Arc<Mutex<JitterBufferStore>>,
+    // For sending retransmission requests
+    session: Weak<Mutex<SharedSessionInner>>,
 }
 
 #[derive(Debug, Clone)]
 struct RtpRecvSrcPad(Arc<RtpRecvSrcPadInner>);
 
 impl RtpRecvSrcPad {
-    fn new(pt: u8, ssrc: u32, pad: gst::Pad, jb_store: JitterBufferStore) -> RtpRecvSrcPad {
+    fn new(
+        pt: u8,
+        ssrc: u32,
+        pad: gst::Pad,
+        session: Weak<Mutex<SharedSessionInner>>,
+        jb_store: JitterBufferStore,
+    ) -> RtpRecvSrcPad {
         RtpRecvSrcPad(Arc::new(RtpRecvSrcPadInner {
             pt,
             ssrc,
             pad,
             semaphore: Arc::new(Semaphore::new(1)),
             jitter_buffer_store: Arc::new(Mutex::new(jb_store)),
+            session,
         }))
     }
+
+    fn request_retransmission(&self, now: Instant, request: jitterbuffer::RetransmissionRequest) {
+        let Some(session) = self.session.upgrade() else {
+            return;
+        };
+        let mut session = session.lock().unwrap();
+
+        gst::debug!(
+            CAT,
+            obj = self.pad,
+            "Requesting retransmission of {:?}",
+            request.seqnums
+        );
+        let replies =
+            session
+                .session
+                .request_nack(now, self.ssrc, request.seqnums, request.max_delay);
+
+        for reply in replies {
+            match reply {
+                RequestNackReply::TimerReconsideration => {
+                    if let Some(waker) = session.rtcp_waker.take() {
+                        // reconsider timers means that we wake the rtcp task to get a new timeout
+                        waker.wake();
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl Deref for RtpRecvSrcPad {
@@ -406,15 +514,35 @@ struct RecvSession {
 
     rtp_recv_srcpads: Vec<RtpRecvSrcPad>,
     recv_flow_combiner: Arc<Mutex<gst_base::UniqueFlowCombiner>>,
+    // RTX SSRC to the SSRC of the retransmitted stream
+    rtx_ssrc_map: HashMap<u32, u32>,
+    fec_receiver: FecReceiver,
 
     rtcp_recv_sinkpad: Option<gst::Pad>,
 }
 
 impl RecvSession {
-    fn new(shared_state: &SharedRtpState, id: usize) -> Self {
+    fn new(shared_state: &SharedRtpState, id: usize, settings: &Settings) -> Self {
         let internal_session = shared_state.session_get_or_init(id, || {
             SharedSession::new(id, RtpProfile::Avp, RTCP_MIN_REPORT_INTERVAL, false)
         });
+        let mut inner = internal_session.inner.lock().unwrap();
+        if let Some(ref rtx_pt_map) = settings.rtx_pt_map {
+            inner.add_rtx_pt_map(rtx_pt_map);
+        }
+        if let Some(ref fec_pt_map) = settings.fec_pt_map {
+            inner.add_fec_pt_map(fec_pt_map);
+        }
+        if settings.twcc_extension_id != 0 {
+            inner.twcc_recv_ext_id = Some(settings.twcc_extension_id as u8);
+        }
+        inner
+            .twcc
+            .lock()
+            .unwrap()
+            .receiver
+            .set_feedback_interval(settings.twcc_feedback_interval);
+        drop(inner);
 
         let recv_flow_combiner = Arc::new(Mutex::new(gst_base::UniqueFlowCombiner::new()));
         let (task, rtp_task_cmd_tx) = RecvSessionSrcTask::new(recv_flow_combiner.clone());
@@ -437,11 +565,40 @@ impl RecvSession {
 
             rtp_recv_srcpads: vec![],
             recv_flow_combiner,
+            rtx_ssrc_map: HashMap::new(),
+            fec_receiver: FecReceiver::default(),
 
             rtcp_recv_sinkpad: None,
         }
     }
 
+    /// Finds the stream an RTX SSRC retransmits packets of. This is either the only stream with
+    /// the associated payload type, or the one that requested the retransmission of the packet.
+    fn associate_rtx_ssrc(&mut self, rtx_ssrc: u32, pt: u8, seqnum: u16) -> Option<u32> {
+        let mut candidates = self.rtp_recv_srcpads.iter().filter(|r| r.pt == pt);
+
+        let ssrc = match (candidates.next(), candidates.next()) {
+            (Some(recv_pad), None) => recv_pad.ssrc,
+            _ => {
+                self.rtp_recv_srcpads
+                    .iter()
+                    .filter(|r| r.pt == pt)
+                    .find(|r| {
+                        r.jitter_buffer_store
+                            .lock()
+                            .unwrap()
+                            .jitterbuffer
+                            .is_requested(seqnum)
+                    })?
+                    .ssrc
+            }
+        };
+
+        self.rtx_ssrc_map.insert(rtx_ssrc, ssrc);
+
+        Some(ssrc)
+    }
+
     fn activate_recv_src_pad(&mut self, pad: &gst::Pad) {
         gst::debug!(CAT, obj = pad, "Activating rtp recv src pad");
 
@@ -541,14 +698,18 @@ impl RecvSession {
 
             srcpad.use_fixed_caps();
 
+            let mut jitterbuffer = JitterBuffer::new(settings.latency.into());
+            jitterbuffer.set_do_retransmission(settings.do_retransmission);
+
             let recv_pad = RtpRecvSrcPad::new(
                 pt,
                 ssrc,
                 srcpad.clone(),
+                Arc::downgrade(&self.internal_session.inner),
                 JitterBufferStore {
                     waker: None,
                     store: BTreeMap::new(),
-                    jitterbuffer: JitterBuffer::new(settings.latency.into()),
+                    jitterbuffer,
                 },
             );
 
@@ -828,6 +989,16 @@ impl State {
             }));
 
             session_stats.set("jitterbuffer-stats", jb_stats);
+            let fec_stats = session.fec_receiver.stats();
+            session_stats.set(
+                "fec-stats",
+                gst::Structure::builder("application/x-rtp-fec-stats")
+                    .field("received-packets", fec_stats.received)
+                    .field("received-fec-packets", fec_stats.fec_received)
+                    .field("recovered-packets", fec_stats.recovered)
+                    .field("lost-packets", fec_stats.lost)
+                    .build(),
+            );
             ret = ret.field(sess_id.to_string(), session_stats);
         }
         ret.build()
@@ -932,15 +1103,167 @@ impl RtpRecv {
         gst::Iterator::from_vec(vec![])
     }
 
+    /// Restores the original packet if `buffer` is a retransmission (RFC 4588) of a known stream
+    fn unwrap_rtx(
+        &self,
+        pad: &gst::Pad,
+        session: &mut RecvSession,
+        buffer: gst::Buffer,
+    ) -> Result<Option<gst::Buffer>, gst::FlowError> {
+        let data = {
+            let session_inner = session.internal_session.inner.lock().unwrap();
+            if session_inner.rtx_pt_map.is_empty() {
+                return Ok(Some(buffer));
+            }
+
+            let mapped = buffer.map_readable().map_err(|e| {
+                gst::error!(CAT, imp = self, "Failed to map input buffer {e:?}");
+                gst::FlowError::Error
+            })?;
+            let Ok(rtp) = rtp_types::RtpPacket::parse(&mapped) else {
+                drop(session_inner);
+                drop(mapped);
+                return Ok(Some(buffer));
+            };
+            let Some(pt) = session_inner.rtx_apt(rtp.payload_type()) else {
+                drop(session_inner);
+                drop(mapped);
+                return Ok(Some(buffer));
+            };
+            drop(session_inner);
+
+            let Some(seqnum) = rtx::original_seqnum(&rtp) else {
+                gst::debug!(CAT, obj = pad, "Dropping RTX packet without payload");
+                return Ok(None);
+            };
+
+            let ssrc = match session.rtx_ssrc_map.get(&rtp.ssrc()) {
+                Some(&ssrc) => ssrc,
+                None => {
+                    let Some(ssrc) = session.associate_rtx_ssrc(rtp.ssrc(), pt, seqnum) else {
+                        gst::debug!(
+                            CAT,
+                            obj = pad,
+                            "Dropping RTX packet of unknown ssrc {:#08x}",
+                            rtp.ssrc()
+                        );
+                        return Ok(None);
+                    };
+                    gst::debug!(
+                        CAT,
+                        obj = pad,
+                        "Associated RTX ssrc {:#08x} with ssrc {ssrc:#08x}",
+                        rtp.ssrc()
+                    );
+                    ssrc
+                }
+            };
+
+            gst::trace!(
+                CAT,
+                obj = pad,
+                "Received retransmission of packet {seqnum} of ssrc {ssrc:#08x}"
+            );
+
+            let Some(data) = rtx::unwrap_rtx_packet(&rtp, pt, ssrc) else {
+                return Ok(None);
+            };
+
+            data
+        };
+
+        let mut unwrapped = gst::Buffer::from_mut_slice(data);
+        {
+            let unwrapped_mut = unwrapped.get_mut().unwrap();
+            let _ = buffer.copy_into(
+                unwrapped_mut,
+                gst::BufferCopyFlags::FLAGS
+                    | gst::BufferCopyFlags::TIMESTAMPS
+                    | gst::BufferCopyFlags::META,
+                ..,
+            );
+        }
+
+        Ok(Some(unwrapped))
+    }
+
+    /// Passes a packet through the FEC recovery of the session. FEC packets are consumed, packets
+    /// restored with the help of the packet are added in front of it.
+    fn recover_fec(
+        &self,
+        pad: &gst::Pad,
+        session: &mut RecvSession,
+        buffer: gst::Buffer,
+        buffers: &mut Vec<gst::Buffer>,
+    ) -> Result<(), gst::FlowError> {
+        let (is_fec, restored) = {
+            let mapped = buffer.map_readable().map_err(|e| {
+                gst::error!(CAT, imp = self, "Failed to map input buffer {e:?}");
+                gst::FlowError::Error
+            })?;
+            let Ok(rtp) = rtp_types::RtpPacket::parse(&mapped) else {
+                drop(mapped);
+                buffers.push(buffer);
+                return Ok(());
+            };
+
+            let session_inner = session.internal_session.inner.lock().unwrap();
+            let is_fec = session_inner.is_fec_pt(rtp.payload_type());
+            let protected = session_inner.fec_pt(rtp.payload_type()).is_some();
+            drop(session_inner);
+            if !is_fec && !protected {
+                drop(mapped);
+                buffers.push(buffer);
+                return Ok(());
+            }
+
+            if is_fec {
+                gst::trace!(
+                    CAT,
+                    obj = pad,
+                    "Received FEC packet {} of ssrc {:#08x}",
+                    rtp.sequence_number(),
+                    rtp.ssrc()
+                );
+            }
+
+            let restored = session.fec_receiver.handle_packet(&rtp, &mapped, is_fec);
+            (is_fec, restored)
+        };
+
+        for data in restored {
+            let mut restored = gst::Buffer::from_mut_slice(data);
+            {
+                let restored_mut = restored.get_mut().unwrap();
+                let _ = buffer.copy_into(
+                    restored_mut,
+                    gst::BufferCopyFlags::TIMESTAMPS | gst::BufferCopyFlags::META,
+                    ..,
+                );
+            }
+            buffers.push(restored);
+        }
+
+        if !is_fec {
+            buffers.push(buffer);
+        }
+
+        Ok(())
+    }
+
     fn handle_buffer_locked<const H: usize, const P: usize>(
         &self,
         pad: &gst::Pad,
         session: &mut RecvSession,
-        mut buffer: gst::Buffer,
+        buffer: gst::Buffer,
         now: Instant,
         items_to_pre_push: &mut smallvec::SmallVec<[HeldRecvItem; P]>,
         held_buffers: &mut smallvec::SmallVec<[HeldRecvBuffer; H]>,
     ) -> Result<RecvRtpBuffer, gst::FlowError> {
+        let Some(mut buffer) = self.unwrap_rtx(pad, session, buffer)? else {
+            return Ok(RecvRtpBuffer::Drop);
+        };
+
         // TODO: this is different from the old C implementation, where we
         // simply used the RTP timestamps as they were instead of doing any
         // sort of skew calculations.
@@ -1009,6 +1332,25 @@ impl RtpRecv {
         let internal_session = session.internal_session.clone();
         let mut session_inner = internal_session.inner.lock().unwrap();
 
+        if let Some(seqnum) = session_inner
+            .twcc_recv_ext_id
+            .and_then(|id| twcc::read_seqnum(&rtp, id))
+        {
+            let arrival = Duration::from_nanos(arrival_time.nseconds());
+            let scheduled = session_inner.twcc.lock().unwrap().receiver.record(
+                rtp.ssrc(),
+                seqnum,
+                arrival,
+                now,
+            );
+            if scheduled {
+                // Wake up the rtcp task so that the feedback is sent in time
+                if let Some(waker) = session_inner.rtcp_waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+
         let pts = {
             let mut sync_context = self.sync_context.lock().unwrap();
             let sync_context = sync_context.as_mut().unwrap();
@@ -1370,7 +1712,76 @@ impl RtpRecv {
         Ok(state)
     }
 
+    fn has_fec(&self, id: usize) -> bool {
+        let state = self.state.lock().unwrap();
+        state.session_by_id(id).is_some_and(|session| {
+            !session
+                .internal_session
+                .inner
+                .lock()
+                .unwrap()
+                .fec_pt_map
+                .is_empty()
+        })
+    }
+
+    /// Runs the FEC recovery of the session on the received packets
+    fn handle_fec(
+        &self,
+        pad: &gst::Pad,
+        id: usize,
+        packets: impl IntoIterator<Item = gst::Buffer>,
+    ) -> Result<Vec<gst::Buffer>, gst::FlowError> {
+        let mut state = self.state.lock().unwrap();
+        let Some(session) = state.mut_session_by_id(id) else {
+            return Err(gst::FlowError::Error);
+        };
+
+        let mut buffers = Vec::new();
+        for buffer in packets {
+            self.recover_fec(pad, session, buffer, &mut buffers)?;
+        }
+
+        Ok(buffers)
+    }
+
     fn rtp_sink_chain_list(
+        &self,
+        pad: &gst::Pad,
+        id: usize,
+        list: gst::BufferList,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        if !self.has_fec(id) {
+            return self.handle_rtp_buffer_list(pad, id, list);
+        }
+
+        let buffers = self.handle_fec(pad, id, list.iter_owned())?;
+        if buffers.is_empty() {
+            return Ok(gst::FlowSuccess::Ok);
+        }
+
+        self.handle_rtp_buffer_list(pad, id, buffers.into_iter().collect())
+    }
+
+    fn rtp_sink_chain(
+        &self,
+        pad: &gst::Pad,
+        id: usize,
+        buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        if !self.has_fec(id) {
+            return self.handle_rtp_buffer(pad, id, buffer);
+        }
+
+        let mut buffers = self.handle_fec(pad, id, [buffer])?;
+        match buffers.len() {
+            0 => Ok(gst::FlowSuccess::Ok),
+            1 => self.handle_rtp_buffer(pad, id, buffers.pop().unwrap()),
+            _ => self.handle_rtp_buffer_list(pad, id, buffers.into_iter().collect()),
+        }
+    }
+
+    fn handle_rtp_buffer_list(
         &self,
         pad: &gst::Pad,
         id: usize,
@@ -1485,7 +1896,7 @@ impl RtpRecv {
         Ok(gst::FlowSuccess::Ok)
     }
 
-    fn rtp_sink_chain(
+    fn handle_rtp_buffer(
         &self,
         pad: &gst::Pad,
         id: usize,
@@ -1584,10 +1995,34 @@ impl RtpRecv {
             session_inner
                 .session
                 .handle_rtcp_recv(rtcp, mapped.len(), addr, now, ntp_now);
+        let twcc_packets = {
+            let feedback = twcc::parse_feedback(&mapped);
+            if feedback.is_empty() {
+                vec![]
+            } else {
+                let mut twcc = session_inner.twcc.lock().unwrap();
+                feedback
+                    .iter()
+                    .flat_map(|feedback| twcc.sender.handle_feedback(feedback))
+                    .collect::<Vec<_>>()
+            }
+        };
         let rtp_send_sinkpad = session_inner.rtp_send_sinkpad.clone();
         drop(session_inner);
         drop(state);
 
+        if !twcc_packets.is_empty() {
+            if let Some(ref rtp_send_sinkpad) = rtp_send_sinkpad {
+                gst::trace!(
+                    CAT,
+                    imp = self,
+                    "Received transport-wide feedback for {} packets",
+                    twcc_packets.len()
+                );
+                let _ = rtp_send_sinkpad.push_event(twcc::packets_event(&twcc_packets));
+            }
+        }
+
         for reply in replies {
             match reply {
                 RtcpRecvReply::NewSsrc(ssrc) => {
@@ -1640,6 +2075,39 @@ impl RtpRecv {
                         );
                     }
                 }
+                RtcpRecvReply::RequestRetransmission { ssrc, seqnums } => {
+                    let mut session_inner = internal_session.inner.lock().unwrap();
+                    let packets = session_inner.retransmission_packets(ssrc, &seqnums, now);
+                    let rtp_send_srcpad = session_inner.rtp_send_srcpad.clone();
+                    drop(session_inner);
+
+                    let Some(rtp_send_srcpad) = rtp_send_srcpad else {
+                        gst::debug!(
+                            CAT,
+                            imp = self,
+                            "Can't retransmit packets because of missing srcpad"
+                        );
+                        continue;
+                    };
+
+                    gst::debug!(
+                        CAT,
+                        imp = self,
+                        "Retransmitting {} of {} requested packets for ssrc {ssrc:#08x}",
+                        packets.len(),
+                        seqnums.len()
+                    );
+
+                    // The retransmission stream is not part of the session, the packets are
+                    // accounted for with the original stream
+                    for packet in packets {
+                        if let Err(err) = rtp_send_srcpad.push(gst::Buffer::from_mut_slice(packet))
+                        {
+                            gst::debug!(CAT, imp = self, "Failed to push retransmission: {err:?}");
+                            break;
+                        }
+                    }
+                }
                 RtcpRecvReply::NewCName((cname, ssrc)) => {
                     let mut sync_context = self.sync_context.lock().unwrap();
 
@@ -1936,6 +2404,7 @@ impl RtpRecv {
             gst::EventView::FlushStop(_fs) => {
                 let mut state = self.state.lock().unwrap();
                 if let Some(session) = state.mut_session_by_id(id) {
+                    session.fec_receiver.clear();
                     let pads = session
                         .rtp_recv_srcpads
                         .iter()
@@ -2056,6 +2525,65 @@ impl ObjectImpl for RtpRecv {
                     .default_value(sync::TimestampingMode::default())
                     .mutable_ready()
                     .build(),
+                glib::ParamSpecBoolean::builder("do-retransmission")
+                    .nick("Do Retransmission")
+                    .blurb("Request the retransmission of missing packets with NACKs")
+                    .default_value(DEFAULT_DO_RETRANSMISSION)
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRtpRecv:rtx-payload-type-map:
+                 *
+                 * Map of payload types to the payload types of their retransmission streams,
+                 * e.g. `application/x-rtp-pt-map, 96=(uint)97`.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecBoxed::builder::<gst::Structure>("rtx-payload-type-map")
+                    .nick("RTX Payload Type Map")
+                    .blurb("Map of payload types to the payload types used for their retransmission")
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRtpRecv:fec-payload-type-map:
+                 *
+                 * Map of payload types to the payload types of the ULPFEC packets protecting
+                 * them, e.g. `application/x-rtp-pt-map, 96=(uint)123`.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecBoxed::builder::<gst::Structure>("fec-payload-type-map")
+                    .nick("FEC Payload Type Map")
+                    .blurb("Map of payload types to the payload types used for their ULPFEC packets")
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRtpRecv:twcc-extension-id:
+                 *
+                 * Id of the transport-wide sequence number RTP header extension. Arrival times
+                 * of packets carrying it are reported back to the sender with transport-wide
+                 * congestion control feedback from the #rtpsend element with the same `rtp-id`.
+                 *
+                 * If 0, the id is taken from an `extmap-N` caps field with the
+                 * `http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01`
+                 * URI, if any.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecUInt::builder("twcc-extension-id")
+                    .nick("TWCC Extension ID")
+                    .blurb("Id of the transport-wide sequence number header extension (0 = from caps)")
+                    .maximum(255)
+                    .default_value(DEFAULT_TWCC_EXTENSION_ID)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("twcc-feedback-interval")
+                    .nick("TWCC Feedback Interval")
+                    .blurb("Interval in ms between transport-wide congestion control feedback packets")
+                    .minimum(1)
+                    .default_value(DEFAULT_TWCC_FEEDBACK_INTERVAL.as_millis() as u32)
+                    .mutable_ready()
+                    .build(),
             ]
         });
 
@@ -2087,6 +2615,32 @@ impl ObjectImpl for RtpRecv {
                     .get::<sync::TimestampingMode>()
                     .expect("Type checked upstream");
             }
+            "do-retransmission" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.do_retransmission = value.get::<bool>().expect("Type checked upstream");
+            }
+            "rtx-payload-type-map" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.rtx_pt_map = value
+                    .get::<Option<gst::Structure>>()
+                    .expect("Type checked upstream");
+            }
+            "fec-payload-type-map" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.fec_pt_map = value
+                    .get::<Option<gst::Structure>>()
+                    .expect("Type checked upstream");
+            }
+            "twcc-extension-id" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.twcc_extension_id = value.get::<u32>().expect("Type checked upstream");
+            }
+            "twcc-feedback-interval" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.twcc_feedback_interval = Duration::from_millis(
+                    value.get::<u32>().expect("Type checked upstream").into(),
+                );
+            }
             _ => unimplemented!(),
         }
     }
@@ -2109,6 +2663,26 @@ impl ObjectImpl for RtpRecv {
                 let settings = self.settings.lock().unwrap();
                 settings.timestamping_mode.to_value()
             }
+            "do-retransmission" => {
+                let settings = self.settings.lock().unwrap();
+                settings.do_retransmission.to_value()
+            }
+            "rtx-payload-type-map" => {
+                let settings = self.settings.lock().unwrap();
+                settings.rtx_pt_map.to_value()
+            }
+            "fec-payload-type-map" => {
+                let settings = self.settings.lock().unwrap();
+                settings.fec_pt_map.to_value()
+            }
+            "twcc-extension-id" => {
+                let settings = self.settings.lock().unwrap();
+                settings.twcc_extension_id.to_value()
+            }
+            "twcc-feedback-interval" => {
+                let settings = self.settings.lock().unwrap();
+                (settings.twcc_feedback_interval.as_millis() as u32).to_value()
+            }
             _ => unimplemented!(),
         }
     }
@@ -2275,7 +2849,7 @@ impl ElementImpl for RtpRecv {
                     let shared_state = state
                         .shared_state
                         .get_or_insert_with(|| SharedRtpState::recv_get_or_init(rtp_id));
-                    let mut session = RecvSession::new(shared_state, id);
+                    let mut session = RecvSession::new(shared_state, id, &settings);
                     let ret = new_pad(&mut session);
                     state.sessions.push(session);
                     ret
@@ -2320,7 +2894,7 @@ impl ElementImpl for RtpRecv {
                     let shared_state = state
                         .shared_state
                         .get_or_insert_with(|| SharedRtpState::recv_get_or_init(rtp_id));
-                    let mut session = RecvSession::new(shared_state, id);
+                    let mut session = RecvSession::new(shared_state, id, &settings);
                     let ret = new_pad(&mut session);
                     state.sessions.push(session);
                     ret
@@ -2396,6 +2970,8 @@ impl ElementImpl for RtpRecv {
             for id in removed_srcpads_session_ids {
                 if let Some(session) = state.mut_session_by_id(id) {
                     session.rtp_recv_srcpads.clear();
+                    session.rtx_ssrc_map.clear();
+                    session.fec_receiver.clear();
                 }
             }
             for id in removed_session_ids {
@@ -2467,7 +3043,19 @@ impl ElementImpl for RtpRecv {
 
                     session.recv_flow_combiner.lock().unwrap().clear();
                     session.rtp_recv_srcpads.clear();
+                    session.rtx_ssrc_map.clear();
+                    session.fec_receiver.clear();
                     session.recv_store.clear();
+                    session
+                        .internal_session
+                        .inner
+                        .lock()
+                        .unwrap()
+                        .twcc
+                        .lock()
+                        .unwrap()
+                        .receiver
+                        .reset();
 
                     session.rtp_recv_sink_caps = None;
                     session.rtp_recv_sink_segment = None;
@@ -2564,6 +3152,7 @@ mod tests {
             gst::Pad::builder(gst::PadDirection::Src)
                 .name(format!("rtp_src_{session_id}_{pt}_{ssrc}"))
                 .build(),
+            Weak::new(),
             JitterBufferStore {
                 waker: None,
                 store: BTreeMap::new(),
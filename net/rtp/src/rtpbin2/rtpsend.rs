@@ -32,6 +32,51 @@
  * over UDP ports 5004 & 5005. The pipeline expects RTCP to be sent back on UDP port 5007.
  * See #rtprecv for an example of how to process such packets.
  *
+ * ## Retransmission
+ *
+ * Packets of payload types that have a retransmission payload type configured, either with
+ * #rtpsend:rtx-payload-type-map or with `encoding-name=RTX` caps with an `apt` field in the
+ * session's pt-map, are kept in a history. Generic NACKs received by the #rtprecv element with
+ * the same `rtp-id` are answered with RTX packets as specified in RFC 4588 on the same source pad.
+ *
+ * ## Transport-wide congestion control
+ *
+ * With #rtpsend:twcc-extension-id set, all sent packets carry a transport-wide sequence number
+ * header extension as specified in draft-holmer-rmcat-transport-wide-cc-extensions-01. The
+ * sequence numbers are shared by all sessions of the element. Feedback received by the #rtprecv
+ * element with the same `rtp-id` is sent upstream as `RTPTWCCPackets` event, so that
+ * `rtpgccbwe` can be placed in front of the `rtp_sink` pads for bandwidth estimation:
+ *
+ * |[
+ * gst-launch-1.0 \
+ *   videotestsrc is-live=true ! vp8enc ! rtpvp8pay2 ! rtpgccbwe \
+ *   ! rtpsend name=send rtp-id=example-rtp-id rtp-profile=avpf twcc-extension-id=1 \
+ *       send.rtp_src_0 ! udpsink port=5004 host=127.0.0.1 \
+ *       send.rtcp_src_0 ! udpsink port=5005 host=127.0.0.1 async=false \
+ *   rtprecv name=recv rtp-id=example-rtp-id \
+ *   udpsrc port=5007 caps='application/x-rtcp' ! recv.rtcp_sink_0
+ * ]|
+ *
+ * ## Forward error correction
+ *
+ * Packets of payload types that have a FEC payload type configured with
+ * #rtpsend:fec-payload-type-map are protected with ULPFEC packets as specified in RFC 5109,
+ * which are sent in the same stream with the same SSRC. As the FEC packets share the seqnum
+ * space with the media packets, the seqnums of the media packets are rewritten. The groups of
+ * protected packets are configured with #rtpsend:fec-protection, #rtpsend:fec-columns and
+ * #rtpsend:fec-rows, in the same way as for `rtpulpfecenc2`. The #rtprecv element of the
+ * receiver restores lost packets with the same #rtprecv:fec-payload-type-map.
+ *
+ * FEC packets created by `rtpulpfecenc2` or `rtpflexfecenc` in front of a `rtp_sink` pad are
+ * sent like any other packets. The encoders announce the FEC payload type with a `fec-payload`
+ * field in their caps, which makes the session use the clock rate of the protected stream for
+ * them. FlexFEC packets use their own SSRC and are reported in RTCP as a separate stream.
+ *
+ * ## Encryption
+ *
+ * For SRTP, a `rtpsrtpenc` element is linked to each `rtp_src` and `rtcp_src` pad. The packets
+ * are encrypted after all processing of the session, including retransmissions.
+ *
  * Since: plugins-rs-0.13.0
  */
 use std::collections::HashMap;
@@ -45,14 +90,23 @@ use futures::StreamExt;
 use gst::{glib, prelude::*, subclass::prelude::*};
 use std::sync::LazyLock;
 
+use super::fec;
 use super::internal::{pt_clock_rate_from_caps, GstRustLogger, SharedRtpState, SharedSession};
+use super::rtx;
 use super::session::{RtcpSendReply, RtpProfile, SendReply, RTCP_MIN_REPORT_INTERVAL};
 use super::source::SourceState;
 
+use crate::fec::Protection;
 use crate::rtpbin2;
 
 const DEFAULT_MIN_RTCP_INTERVAL: Duration = RTCP_MIN_REPORT_INTERVAL;
 const DEFAULT_REDUCED_SIZE_RTCP: bool = false;
+const DEFAULT_RTX_MAX_SIZE_PACKETS: u32 = rtx::DEFAULT_MAX_SIZE_PACKETS;
+const DEFAULT_RTX_MAX_SIZE_TIME: Duration = rtx::DEFAULT_MAX_SIZE_TIME;
+const DEFAULT_TWCC_EXTENSION_ID: u32 = 0;
+const DEFAULT_FEC_PROTECTION: Protection = fec::DEFAULT_PROTECTION;
+const DEFAULT_FEC_COLUMNS: u32 = fec::DEFAULT_COLUMNS;
+const DEFAULT_FEC_ROWS: u32 = fec::DEFAULT_ROWS;
 
 static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
@@ -97,6 +151,15 @@ struct Settings {
     min_rtcp_interval: Duration,
     profile: Profile,
     reduced_size_rtcp: bool,
+    rtx_pt_map: Option<gst::Structure>,
+    rtx_ssrc_map: Option<gst::Structure>,
+    rtx_max_size_packets: u32,
+    rtx_max_size_time: Duration,
+    twcc_extension_id: u32,
+    fec_pt_map: Option<gst::Structure>,
+    fec_protection: Protection,
+    fec_columns: u32,
+    fec_rows: u32,
 }
 
 impl Default for Settings {
@@ -106,6 +169,15 @@ impl Default for Settings {
             min_rtcp_interval: DEFAULT_MIN_RTCP_INTERVAL,
             profile: Profile::default(),
             reduced_size_rtcp: DEFAULT_REDUCED_SIZE_RTCP,
+            rtx_pt_map: None,
+            rtx_ssrc_map: None,
+            rtx_max_size_packets: DEFAULT_RTX_MAX_SIZE_PACKETS,
+            rtx_max_size_time: DEFAULT_RTX_MAX_SIZE_TIME,
+            twcc_extension_id: DEFAULT_TWCC_EXTENSION_ID,
+            fec_pt_map: None,
+            fec_protection: DEFAULT_FEC_PROTECTION,
+            fec_columns: DEFAULT_FEC_COLUMNS,
+            fec_rows: DEFAULT_FEC_ROWS,
         }
     }
 }
@@ -144,11 +216,19 @@ impl futures::stream::Stream for RtcpSendStream {
             if let Some(reply) = session_inner.session.poll_rtcp_send(now, ntp_now) {
                 return Poll::Ready(Some(reply));
             }
+            if let Some(data) = session_inner.poll_twcc_feedback(now) {
+                return Poll::Ready(Some(RtcpSendReply::Data(data)));
+            }
             if let Some(wait) = session_inner.session.poll_rtcp_send_timeout(now) {
                 if lowest_wait.is_none_or(|lowest_wait| wait < lowest_wait) {
                     lowest_wait = Some(wait);
                 }
             }
+            if let Some(wait) = session_inner.poll_twcc_feedback_timeout() {
+                if lowest_wait.is_none_or(|lowest_wait| wait < lowest_wait) {
+                    lowest_wait = Some(wait);
+                }
+            }
             session_inner.rtcp_waker = Some(cx.waker().clone());
         }
         drop(state);
@@ -199,6 +279,36 @@ impl SendSession {
         inner
             .session
             .set_reduced_size_rtcp(settings.reduced_size_rtcp);
+        if let Some(ref rtx_pt_map) = settings.rtx_pt_map {
+            inner.add_rtx_pt_map(rtx_pt_map);
+        }
+        if let Some(ref rtx_ssrc_map) = settings.rtx_ssrc_map {
+            inner.rtx_sender.set_ssrc_map(
+                rtx_ssrc_map
+                    .iter()
+                    .filter_map(|(ssrc, rtx_ssrc)| {
+                        Option::zip(ssrc.parse::<u32>().ok(), rtx_ssrc.get::<u32>().ok())
+                    })
+                    .collect(),
+            );
+        }
+        inner
+            .rtx_sender
+            .set_max_size_packets(settings.rtx_max_size_packets);
+        inner
+            .rtx_sender
+            .set_max_size_time(settings.rtx_max_size_time);
+        if settings.twcc_extension_id != 0 {
+            inner.twcc_send_ext_id = Some(settings.twcc_extension_id as u8);
+        }
+        if let Some(ref fec_pt_map) = settings.fec_pt_map {
+            inner.add_fec_pt_map(fec_pt_map);
+            inner.fec_sender.set_protection(
+                settings.fec_protection,
+                settings.fec_columns,
+                settings.fec_rows,
+            );
+        }
         drop(inner);
 
         Self {
@@ -356,6 +466,90 @@ impl RtpSend {
         internal_session: &SharedSession,
         buffer: gst::Buffer,
         now: Instant,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let (buffer, fec_packets) = self.prepare_fec(internal_session, buffer)?;
+        for packet in fec_packets {
+            let fec_buffer = Self::fec_buffer(&buffer, packet);
+            self.send_buffer(sinkpad, srcpad, internal_session, fec_buffer, now)?;
+        }
+
+        self.send_buffer(sinkpad, srcpad, internal_session, buffer, now)
+    }
+
+    /// Rewrites the seqnum of a packet of a stream protected by FEC, so that the FEC packets fit
+    /// into the stream, and returns the FEC packets that have to be sent before it
+    fn prepare_fec(
+        &self,
+        internal_session: &SharedSession,
+        mut buffer: gst::Buffer,
+    ) -> Result<(gst::Buffer, Vec<Vec<u8>>), gst::FlowError> {
+        let (seqnum, fec_packets) = {
+            let mut session_inner = internal_session.inner.lock().unwrap();
+            if session_inner.fec_pt_map.is_empty() {
+                return Ok((buffer, Vec::new()));
+            }
+
+            let mapped = buffer.map_readable().map_err(|e| {
+                gst::error!(CAT, imp = self, "Failed to map input buffer {e:?}");
+                gst::FlowError::Error
+            })?;
+            // Invalid packets are handled when sending them
+            let Ok(rtp) = rtp_types::RtpPacket::parse(&mapped) else {
+                drop(mapped);
+                return Ok((buffer, Vec::new()));
+            };
+            let Some(fec_pt) = session_inner.fec_pt(rtp.payload_type()) else {
+                drop(mapped);
+                return Ok((buffer, Vec::new()));
+            };
+
+            let (seqnum, fec_packets) = session_inner.fec_sender.prepare(&rtp, fec_pt);
+            if seqnum == rtp.sequence_number() {
+                drop(mapped);
+                return Ok((buffer, fec_packets));
+            }
+
+            gst::trace!(
+                CAT,
+                imp = self,
+                "Rewriting seqnum {} to {seqnum}",
+                rtp.sequence_number()
+            );
+            (seqnum, fec_packets)
+        };
+
+        {
+            let buffer = buffer.make_mut();
+            let mut map = buffer.map_writable().map_err(|e| {
+                gst::error!(CAT, imp = self, "Failed to map buffer writable {e:?}");
+                gst::FlowError::Error
+            })?;
+            map[2..4].copy_from_slice(&seqnum.to_be_bytes());
+        }
+
+        Ok((buffer, fec_packets))
+    }
+
+    /// Wraps a FEC packet into a buffer with the timestamps of the media packet it was created
+    /// for
+    fn fec_buffer(buffer: &gst::Buffer, packet: Vec<u8>) -> gst::Buffer {
+        let mut fec_buffer = gst::Buffer::from_mut_slice(packet);
+        {
+            let fec_buffer = fec_buffer.get_mut().unwrap();
+            fec_buffer.set_pts(buffer.pts());
+            fec_buffer.set_dts(buffer.dts());
+        }
+
+        fec_buffer
+    }
+
+    fn send_buffer(
+        &self,
+        sinkpad: &gst::Pad,
+        srcpad: &gst::Pad,
+        internal_session: &SharedSession,
+        buffer: gst::Buffer,
+        now: Instant,
     ) -> Result<gst::FlowSuccess, gst::FlowError> {
         let mapped = buffer.map_readable().map_err(|e| {
             gst::error!(CAT, imp = self, "Failed to map input buffer {e:?}");
@@ -394,10 +588,62 @@ impl RtpSend {
                 SendReply::Drop => return Ok(gst::FlowSuccess::Ok),
             }
         }
+
+        // Keep the packet around in case it is NACKed
+        if session_inner.rtx_pt(rtp.payload_type()).is_some() {
+            session_inner.rtx_sender.store(&rtp, buffer.clone(), now);
+        }
+
+        let protected = session_inner.fec_pt(rtp.payload_type()).is_some();
+
+        let stamped = session_inner.twcc_send_ext_id.and_then(|id| {
+            session_inner
+                .twcc
+                .lock()
+                .unwrap()
+                .sender
+                .stamp(&rtp, id, now)
+        });
+
         // TODO: handle other processing
         drop(mapped);
         drop(session_inner);
 
+        let buffer = match stamped {
+            Some(data) => {
+                let mut stamped = gst::Buffer::from_mut_slice(data);
+                {
+                    let stamped_mut = stamped.get_mut().unwrap();
+                    let _ = buffer.copy_into(
+                        stamped_mut,
+                        gst::BufferCopyFlags::FLAGS
+                            | gst::BufferCopyFlags::TIMESTAMPS
+                            | gst::BufferCopyFlags::META,
+                        ..,
+                    );
+                }
+                stamped
+            }
+            None => buffer,
+        };
+
+        // FEC packets are created from the packets as they are sent, including the
+        // transport-wide sequence number
+        let fec_packets = if protected {
+            let mapped = buffer.map_readable().map_err(|e| {
+                gst::error!(CAT, imp = self, "Failed to map output buffer {e:?}");
+                gst::FlowError::Error
+            })?;
+            let rtp = rtp_types::RtpPacket::parse(&mapped).map_err(|e| {
+                gst::error!(CAT, imp = self, "Failed to parse output buffer {e:?}");
+                gst::FlowError::Error
+            })?;
+            let mut session_inner = internal_session.inner.lock().unwrap();
+            session_inner.fec_sender.sent(&rtp, &mapped)
+        } else {
+            Vec::new()
+        };
+
         for ssrc in ssrc_collision {
             // XXX: Another option is to have us rewrite ssrc's instead of asking upstream to do
             // so.
@@ -411,7 +657,16 @@ impl RtpSend {
             );
         }
 
-        srcpad.push(buffer)
+        let fec_buffers = fec_packets
+            .into_iter()
+            .map(|packet| Self::fec_buffer(&buffer, packet))
+            .collect::<Vec<_>>();
+        let ret = srcpad.push(buffer)?;
+        for fec_buffer in fec_buffers {
+            self.send_buffer(sinkpad, srcpad, internal_session, fec_buffer, now)?;
+        }
+
+        Ok(ret)
     }
 
     fn rtp_sink_chain_list(
@@ -478,6 +733,27 @@ impl RtpSend {
             }
             gst::EventView::Eos(_eos) => {
                 let now = Instant::now();
+                let state = self.state.lock().unwrap();
+                let fec = state.session_by_id(id).and_then(|session| {
+                    let srcpad = session.rtp_send_srcpad.clone()?;
+                    let internal_session = session.internal_session.clone();
+                    let fec_packets = internal_session.inner.lock().unwrap().fec_sender.drain();
+                    Some((srcpad, internal_session, fec_packets))
+                });
+                drop(state);
+                // FEC packets of the last frame that were not sent yet
+                if let Some((srcpad, internal_session, fec_packets)) = fec {
+                    for packet in fec_packets {
+                        let buffer = gst::Buffer::from_mut_slice(packet);
+                        if self
+                            .send_buffer(pad, &srcpad, &internal_session, buffer, now)
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                }
+
                 let state = self.state.lock().unwrap();
                 if let Some(session) = state.session_by_id(id) {
                     let mut session = session.internal_session.inner.lock().unwrap();
@@ -571,6 +847,95 @@ impl ObjectImpl for RtpSend {
                     .default_value(DEFAULT_REDUCED_SIZE_RTCP)
                     .mutable_ready()
                     .build(),
+                /**
+                 * GstRtpSend:rtx-payload-type-map:
+                 *
+                 * Map of payload types to the payload types of their retransmission streams,
+                 * e.g. `application/x-rtp-pt-map, 96=(uint)97`.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecBoxed::builder::<gst::Structure>("rtx-payload-type-map")
+                    .nick("RTX Payload Type Map")
+                    .blurb("Map of payload types to the payload types used for their retransmission")
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRtpSend:rtx-ssrc-map:
+                 *
+                 * Map of SSRCs to the SSRCs of their retransmission streams,
+                 * e.g. `application/x-rtp-ssrc-map, 12345=(uint)67890`. A random SSRC is used
+                 * for the retransmission of SSRCs that are not in the map.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecBoxed::builder::<gst::Structure>("rtx-ssrc-map")
+                    .nick("RTX SSRC Map")
+                    .blurb("Map of SSRCs to the SSRCs used for their retransmission")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("rtx-max-size-packets")
+                    .nick("RTX Max Size Packets")
+                    .blurb("Maximum number of packets per SSRC kept for retransmission (0 = unlimited)")
+                    .default_value(DEFAULT_RTX_MAX_SIZE_PACKETS)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("rtx-max-size-time")
+                    .nick("RTX Max Size Time")
+                    .blurb("Maximum time (in ms) packets are kept for retransmission (0 = unlimited)")
+                    .default_value(DEFAULT_RTX_MAX_SIZE_TIME.as_millis() as u32)
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRtpSend:twcc-extension-id:
+                 *
+                 * Id of the transport-wide sequence number RTP header extension that is added
+                 * to all packets sent by this element, or 0 to not add it. The sequence numbers
+                 * are shared by all sessions.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecUInt::builder("twcc-extension-id")
+                    .nick("TWCC Extension ID")
+                    .blurb("Id of the transport-wide sequence number header extension to add (0 = disabled)")
+                    .maximum(255)
+                    .default_value(DEFAULT_TWCC_EXTENSION_ID)
+                    .mutable_ready()
+                    .build(),
+                /**
+                 * GstRtpSend:fec-payload-type-map:
+                 *
+                 * Map of payload types to the payload types of the ULPFEC packets protecting
+                 * them, e.g. `application/x-rtp-pt-map, 96=(uint)123`.
+                 *
+                 * Since: plugins-rs-0.15.0
+                 */
+                glib::ParamSpecBoxed::builder::<gst::Structure>("fec-payload-type-map")
+                    .nick("FEC Payload Type Map")
+                    .blurb("Map of payload types to the payload types used for their ULPFEC packets")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecEnum::builder_with_default("fec-protection", DEFAULT_FEC_PROTECTION)
+                    .nick("FEC Protection")
+                    .blurb("Which groups of packets are protected by ULPFEC packets")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("fec-columns")
+                    .nick("FEC Columns")
+                    .blurb("Number of consecutive packets in a row of packets protected by ULPFEC")
+                    .minimum(1)
+                    .maximum(crate::ulpfec::MAX_MASK_BITS as u32)
+                    .default_value(DEFAULT_FEC_COLUMNS)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("fec-rows")
+                    .nick("FEC Rows")
+                    .blurb("Number of rows in a block of packets protected by ULPFEC with column protection")
+                    .minimum(1)
+                    .maximum(crate::ulpfec::MAX_MASK_BITS as u32)
+                    .default_value(DEFAULT_FEC_ROWS)
+                    .mutable_ready()
+                    .build(),
             ]
         });
 
@@ -597,6 +962,50 @@ impl ObjectImpl for RtpSend {
                 let mut settings = self.settings.lock().unwrap();
                 settings.reduced_size_rtcp = value.get::<bool>().expect("Type checked upstream");
             }
+            "rtx-payload-type-map" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.rtx_pt_map = value
+                    .get::<Option<gst::Structure>>()
+                    .expect("Type checked upstream");
+            }
+            "rtx-ssrc-map" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.rtx_ssrc_map = value
+                    .get::<Option<gst::Structure>>()
+                    .expect("Type checked upstream");
+            }
+            "rtx-max-size-packets" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.rtx_max_size_packets = value.get::<u32>().expect("Type checked upstream");
+            }
+            "rtx-max-size-time" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.rtx_max_size_time = Duration::from_millis(
+                    value.get::<u32>().expect("type checked upstream").into(),
+                );
+            }
+            "twcc-extension-id" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.twcc_extension_id = value.get::<u32>().expect("Type checked upstream");
+            }
+            "fec-payload-type-map" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.fec_pt_map = value
+                    .get::<Option<gst::Structure>>()
+                    .expect("Type checked upstream");
+            }
+            "fec-protection" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.fec_protection = value.get::<Protection>().expect("Type checked upstream");
+            }
+            "fec-columns" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.fec_columns = value.get::<u32>().expect("Type checked upstream");
+            }
+            "fec-rows" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.fec_rows = value.get::<u32>().expect("Type checked upstream");
+            }
             _ => unimplemented!(),
         }
     }
@@ -623,6 +1032,42 @@ impl ObjectImpl for RtpSend {
                 let settings = self.settings.lock().unwrap();
                 settings.reduced_size_rtcp.to_value()
             }
+            "rtx-payload-type-map" => {
+                let settings = self.settings.lock().unwrap();
+                settings.rtx_pt_map.to_value()
+            }
+            "rtx-ssrc-map" => {
+                let settings = self.settings.lock().unwrap();
+                settings.rtx_ssrc_map.to_value()
+            }
+            "rtx-max-size-packets" => {
+                let settings = self.settings.lock().unwrap();
+                settings.rtx_max_size_packets.to_value()
+            }
+            "rtx-max-size-time" => {
+                let settings = self.settings.lock().unwrap();
+                (settings.rtx_max_size_time.as_millis() as u32).to_value()
+            }
+            "twcc-extension-id" => {
+                let settings = self.settings.lock().unwrap();
+                settings.twcc_extension_id.to_value()
+            }
+            "fec-payload-type-map" => {
+                let settings = self.settings.lock().unwrap();
+                settings.fec_pt_map.to_value()
+            }
+            "fec-protection" => {
+                let settings = self.settings.lock().unwrap();
+                settings.fec_protection.to_value()
+            }
+            "fec-columns" => {
+                let settings = self.settings.lock().unwrap();
+                settings.fec_columns.to_value()
+            }
+            "fec-rows" => {
+                let settings = self.settings.lock().unwrap();
+                settings.fec_rows.to_value()
+            }
             _ => unimplemented!(),
         }
     }
@@ -782,12 +1227,10 @@ impl ElementImpl for RtpSend {
                         .build();
                     session.rtp_send_sinkpad = Some(sinkpad.clone());
                     session.rtp_send_srcpad = Some(srcpad.clone());
-                    session
-                        .internal_session
-                        .inner
-                        .lock()
-                        .unwrap()
-                        .rtp_send_sinkpad = Some(sinkpad.clone());
+                    let mut inner = session.internal_session.inner.lock().unwrap();
+                    inner.rtp_send_sinkpad = Some(sinkpad.clone());
+                    inner.rtp_send_srcpad = Some(srcpad.clone());
+                    drop(inner);
                     Some((sinkpad, Some(srcpad), id, vec![]))
                 };
 
@@ -893,12 +1336,12 @@ impl ElementImpl for RtpSend {
             if let Some(session) = state.mut_session_by_id(id) {
                 if Some(pad) == session.rtp_send_sinkpad.as_ref() {
                     session.rtp_send_sinkpad = None;
-                    session
-                        .internal_session
-                        .inner
-                        .lock()
-                        .unwrap()
-                        .rtp_send_sinkpad = None;
+                    let mut inner = session.internal_session.inner.lock().unwrap();
+                    inner.rtp_send_sinkpad = None;
+                    inner.rtp_send_srcpad = None;
+                    inner.rtx_sender.clear();
+                    inner.fec_sender.clear();
+                    drop(inner);
 
                     if let Some(srcpad) = session.rtp_send_srcpad.take() {
                         removed_pads.push(srcpad);
@@ -993,6 +1436,10 @@ impl ElementImpl for RtpSend {
                 let mut state = self.state.lock().unwrap();
                 for session in state.sessions.iter_mut() {
                     session.stop_rtcp_task();
+                    let mut inner = session.internal_session.inner.lock().unwrap();
+                    inner.rtx_sender.clear();
+                    inner.fec_sender.clear();
+                    inner.twcc.lock().unwrap().sender.reset();
                 }
             }
             _ => (),
@@ -0,0 +1,303 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Retransmission of RTP packets as described in RFC 4588.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use rand::prelude::*;
+use rtp_types::{RtpPacket, RtpPacketBuilder};
+
+pub const DEFAULT_MAX_SIZE_PACKETS: u32 = 100;
+pub const DEFAULT_MAX_SIZE_TIME: Duration = Duration::ZERO;
+
+#[derive(Debug)]
+struct HistoryPacket {
+    seqnum: u16,
+    pt: u8,
+    sent: Instant,
+    buffer: gst::Buffer,
+}
+
+#[derive(Debug)]
+struct RtxStream {
+    rtx_ssrc: u32,
+    next_seqnum: u16,
+    history: VecDeque<HistoryPacket>,
+}
+
+/// Keeps a history of the sent packets per SSRC and wraps them into RTX packets on request
+#[derive(Debug)]
+pub struct RtxSender {
+    // 0 means unlimited
+    max_size_packets: usize,
+    // Zero means unlimited
+    max_size_time: Duration,
+    // Media SSRC to RTX SSRC
+    ssrc_map: HashMap<u32, u32>,
+    streams: HashMap<u32, RtxStream>,
+}
+
+impl Default for RtxSender {
+    fn default() -> Self {
+        Self {
+            max_size_packets: DEFAULT_MAX_SIZE_PACKETS as usize,
+            max_size_time: DEFAULT_MAX_SIZE_TIME,
+            ssrc_map: HashMap::new(),
+            streams: HashMap::new(),
+        }
+    }
+}
+
+impl RtxSender {
+    pub fn set_max_size_packets(&mut self, max_size_packets: u32) {
+        self.max_size_packets = max_size_packets as usize;
+    }
+
+    pub fn set_max_size_time(&mut self, max_size_time: Duration) {
+        self.max_size_time = max_size_time;
+    }
+
+    pub fn set_ssrc_map(&mut self, ssrc_map: HashMap<u32, u32>) {
+        for (ssrc, stream) in self.streams.iter_mut() {
+            if let Some(&rtx_ssrc) = ssrc_map.get(ssrc) {
+                stream.rtx_ssrc = rtx_ssrc;
+            }
+        }
+        self.ssrc_map = ssrc_map;
+    }
+
+    pub fn rtx_ssrc(&self, ssrc: u32) -> Option<u32> {
+        self.streams.get(&ssrc).map(|stream| stream.rtx_ssrc)
+    }
+
+    /// Store a sent packet in the history
+    pub fn store(&mut self, rtp: &RtpPacket, buffer: gst::Buffer, now: Instant) {
+        let ssrc_map = &self.ssrc_map;
+        let stream = self.streams.entry(rtp.ssrc()).or_insert_with_key(|&ssrc| {
+            let mut rng = rand::rng();
+            let rtx_ssrc = ssrc_map.get(&ssrc).copied().unwrap_or_else(|| loop {
+                let rtx_ssrc = rng.random::<u32>();
+                if rtx_ssrc != ssrc {
+                    break rtx_ssrc;
+                }
+            });
+            debug!("Using RTX ssrc {rtx_ssrc} for ssrc {ssrc}");
+
+            RtxStream {
+                rtx_ssrc,
+                next_seqnum: rng.random::<u16>(),
+                history: VecDeque::new(),
+            }
+        });
+
+        stream.history.push_back(HistoryPacket {
+            seqnum: rtp.sequence_number(),
+            pt: rtp.payload_type(),
+            sent: now,
+            buffer,
+        });
+
+        while self.max_size_packets > 0 && stream.history.len() > self.max_size_packets {
+            stream.history.pop_front();
+        }
+
+        if !self.max_size_time.is_zero() {
+            while stream.history.front().is_some_and(|packet| {
+                now.saturating_duration_since(packet.sent) > self.max_size_time
+            }) {
+                stream.history.pop_front();
+            }
+        }
+    }
+
+    /// Creates RTX packets for the requested seqnums of `ssrc` that are still in the history.
+    /// `rtx_pt` maps the payload type of a packet to its RTX payload type.
+    pub fn retransmit(
+        &mut self,
+        ssrc: u32,
+        seqnums: &[u16],
+        rtx_pt: impl Fn(u8) -> Option<u8>,
+    ) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+
+        let Some(stream) = self.streams.get_mut(&ssrc) else {
+            trace!("No history for ssrc {ssrc}");
+            return packets;
+        };
+
+        for &seqnum in seqnums {
+            let Some(packet) = stream
+                .history
+                .iter()
+                .rev()
+                .find(|packet| packet.seqnum == seqnum)
+            else {
+                debug!("Packet {seqnum} of ssrc {ssrc} not in history anymore");
+                continue;
+            };
+
+            let Some(rtx_pt) = rtx_pt(packet.pt) else {
+                debug!("No RTX payload type for payload type {}", packet.pt);
+                continue;
+            };
+
+            let Ok(map) = packet.buffer.map_readable() else {
+                continue;
+            };
+            let Ok(rtp) = RtpPacket::parse(&map) else {
+                continue;
+            };
+
+            if let Some(data) = wrap_rtx_packet(&rtp, rtx_pt, stream.rtx_ssrc, stream.next_seqnum) {
+                trace!(
+                    "Retransmitting packet {seqnum} of ssrc {ssrc} with seqnum {}",
+                    stream.next_seqnum
+                );
+                stream.next_seqnum = stream.next_seqnum.wrapping_add(1);
+                packets.push(data);
+            }
+        }
+
+        packets
+    }
+
+    pub fn clear(&mut self) {
+        self.streams.clear();
+    }
+}
+
+/// Wraps a packet into an RTX packet, which carries the original seqnum in front of the payload
+pub fn wrap_rtx_packet(
+    rtp: &RtpPacket,
+    rtx_pt: u8,
+    rtx_ssrc: u32,
+    rtx_seqnum: u16,
+) -> Option<Vec<u8>> {
+    let osn = rtp.sequence_number().to_be_bytes();
+
+    let mut builder = RtpPacketBuilder::<&[u8], &[u8]>::new()
+        .payload_type(rtx_pt)
+        .ssrc(rtx_ssrc)
+        .sequence_number(rtx_seqnum)
+        .timestamp(rtp.timestamp())
+        .marker_bit(rtp.marker_bit())
+        .payload(osn.as_slice())
+        .payload(rtp.payload());
+    for csrc in rtp.csrc() {
+        builder = builder.add_csrc(csrc);
+    }
+    if let Some((id, data)) = rtp.extension() {
+        builder = builder.extension(id, data);
+    }
+
+    builder.write_vec().ok()
+}
+
+/// Restores the original packet from an RTX packet
+pub fn unwrap_rtx_packet(rtx: &RtpPacket, pt: u8, ssrc: u32) -> Option<Vec<u8>> {
+    let payload = rtx.payload();
+    if payload.len() < 2 {
+        return None;
+    }
+    let osn = u16::from_be_bytes([payload[0], payload[1]]);
+
+    let mut builder = RtpPacketBuilder::<&[u8], &[u8]>::new()
+        .payload_type(pt)
+        .ssrc(ssrc)
+        .sequence_number(osn)
+        .timestamp(rtx.timestamp())
+        .marker_bit(rtx.marker_bit())
+        .payload(&payload[2..]);
+    for csrc in rtx.csrc() {
+        builder = builder.add_csrc(csrc);
+    }
+    if let Some((id, data)) = rtx.extension() {
+        builder = builder.extension(id, data);
+    }
+
+    builder.write_vec().ok()
+}
+
+/// The original seqnum carried by an RTX packet
+pub fn original_seqnum(rtx: &RtpPacket) -> Option<u16> {
+    let payload = rtx.payload();
+    (payload.len() >= 2).then(|| u16::from_be_bytes([payload[0], payload[1]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtpbin2::session::tests::{generate_rtp_packet, init_logs};
+
+    #[test]
+    fn wrap_unwrap() {
+        init_logs();
+
+        let rtp_data = generate_rtp_packet(0x12345678, 500, 9000, 4);
+        let rtp = RtpPacket::parse(&rtp_data).unwrap();
+
+        let rtx_data = wrap_rtx_packet(&rtp, 97, 0x87654321, 10).unwrap();
+        let rtx = RtpPacket::parse(&rtx_data).unwrap();
+        assert_eq!(rtx.payload_type(), 97);
+        assert_eq!(rtx.ssrc(), 0x87654321);
+        assert_eq!(rtx.sequence_number(), 10);
+        assert_eq!(rtx.timestamp(), 9000);
+        assert_eq!(rtx.payload(), &[0x01, 0xf4, 1, 1, 1, 1]);
+        assert_eq!(original_seqnum(&rtx), Some(500));
+
+        let data = unwrap_rtx_packet(&rtx, rtp.payload_type(), rtp.ssrc()).unwrap();
+        assert_eq!(data, rtp_data);
+    }
+
+    #[test]
+    fn history() {
+        init_logs();
+
+        let mut sender = RtxSender::default();
+        sender.set_max_size_packets(2);
+        sender.set_ssrc_map(HashMap::from([(0x12345678, 0x87654321)]));
+
+        let now = Instant::now();
+        for seqnum in 500..503 {
+            let rtp_data = generate_rtp_packet(0x12345678, seqnum, 0, 4);
+            let rtp = RtpPacket::parse(&rtp_data).unwrap();
+            let buffer = gst::Buffer::from_slice(rtp_data.clone());
+            sender.store(&rtp, buffer, now);
+        }
+        assert_eq!(sender.rtx_ssrc(0x12345678), Some(0x87654321));
+
+        // Packet 500 was already removed from the history and nothing is known about other ssrcs
+        assert!(sender
+            .retransmit(0x12345678, &[500], |_| Some(97))
+            .is_empty());
+        assert!(sender
+            .retransmit(0x11111111, &[501], |_| Some(97))
+            .is_empty());
+        // No RTX payload type is configured
+        assert!(sender.retransmit(0x12345678, &[501], |_| None).is_empty());
+
+        let packets = sender.retransmit(0x12345678, &[501, 502], |_| Some(97));
+        assert_eq!(packets.len(), 2);
+        let first = RtpPacket::parse(&packets[0]).unwrap();
+        let second = RtpPacket::parse(&packets[1]).unwrap();
+        assert_eq!(first.ssrc(), 0x87654321);
+        assert_eq!(original_seqnum(&first), Some(501));
+        assert_eq!(original_seqnum(&second), Some(502));
+        assert_eq!(
+            second.sequence_number(),
+            first.sequence_number().wrapping_add(1)
+        );
+
+        // Older packets are removed after the maximum time
+        sender.set_max_size_time(Duration::from_millis(100));
+        let rtp_data = generate_rtp_packet(0x12345678, 503, 0, 4);
+        let rtp = RtpPacket::parse(&rtp_data).unwrap();
+        let buffer = gst::Buffer::from_slice(rtp_data.clone());
+        sender.store(&rtp, buffer, now + Duration::from_millis(200));
+        assert!(sender
+            .retransmit(0x12345678, &[502], |_| Some(97))
+            .is_empty());
+        assert_eq!(sender.retransmit(0x12345678, &[503], |_| Some(97)).len(), 1);
+    }
+}
@@ -148,6 +148,8 @@ pub enum RtcpRecvReply {
     TimerReconsideration,
     /// Request a key unit for the given SSRC of ours
     RequestKeyUnit { ssrcs: Vec<u32>, fir: bool },
+    /// Request the retransmission of packets with the given seqnums for the given SSRC of ours
+    RequestRetransmission { ssrc: u32, seqnums: Vec<u16> },
     /// A new cname to ssrc mapping was found in a sdes: (cname, ssrc)
     NewCName((String, u32)),
     /// A new RTP to NTP mapping was received for an ssrc: (ssrc, RTP, NTP)
@@ -170,6 +172,12 @@ pub enum RequestRemoteKeyUnitReply {
     TimerReconsideration,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RequestNackReply {
+    /// RTCP timer needs to be reconsidered.  Call poll_rtcp_send_timeout() to get the new time
+    TimerReconsideration,
+}
+
 impl Session {
     pub fn new() -> Self {
         let cname = generate_cname();
@@ -731,7 +739,19 @@ impl Session {
                         );
                     }
                 }
-                Ok(Packet::TransportFeedback(_)) | Ok(Packet::Xr(_)) | Ok(Packet::Unknown(_)) => (),
+                Ok(Packet::TransportFeedback(tf)) => {
+                    if let Ok(nack) = tf.parse_fci::<rtcp_types::Nack>() {
+                        let ssrc = tf.media_ssrc();
+                        if self.local_senders.contains_key(&ssrc) {
+                            let seqnums = nack.entries().collect::<Vec<_>>();
+                            debug!("Received NACK for ssrc {ssrc}: {seqnums:?}");
+                            replies.push(RtcpRecvReply::RequestRetransmission { ssrc, seqnums });
+                        } else {
+                            trace!("Ignoring NACK for unknown ssrc {ssrc}");
+                        }
+                    }
+                }
+                Ok(Packet::Xr(_)) | Ok(Packet::Unknown(_)) => (),
                 // TODO: in RFC4585 profile, need to listen for feedback messages and remove any
                 // that we would have sent
                 Err(_) => (),
@@ -904,7 +924,7 @@ impl Session {
         self.internal_rtcp_sender_src
     }
 
-    fn ensure_internal_send_src(&mut self) -> u32 {
+    pub(crate) fn ensure_internal_send_src(&mut self) -> u32 {
         match self.internal_rtcp_sender_src {
             Some(ssrc) => ssrc,
             None => loop {
@@ -1120,6 +1140,34 @@ impl Session {
         rtcp
     }
 
+    fn generate_nack<'a>(
+        &mut self,
+        mut rtcp: CompoundBuilder<'a>,
+        _now: Instant,
+    ) -> CompoundBuilder<'a> {
+        if self
+            .remote_senders
+            .values()
+            .all(|source| !source.has_pending_nack())
+        {
+            return rtcp;
+        }
+
+        let ssrc = self.ensure_internal_send_src();
+
+        for source in self.remote_senders.values_mut() {
+            if let Some(nack) = source.generate_nack() {
+                debug!("Generating NACK for sender {}: {:?}", source.ssrc(), nack);
+                rtcp = rtcp.add_packet(
+                    rtcp_types::TransportFeedback::builder_owned(nack)
+                        .sender_ssrc(ssrc)
+                        .media_ssrc(source.ssrc()),
+                );
+            }
+        }
+        rtcp
+    }
+
     // RFC 3550 6.3.5
     // FIXME: we should surface this information to the element in order
     // to perform clean up of the sync context
@@ -1235,6 +1283,7 @@ impl Session {
             rtcp = self.generate_sdes(rtcp, is_early);
             rtcp = self.generate_pli(rtcp, now);
             rtcp = self.generate_fir(rtcp, now);
+            rtcp = self.generate_nack(rtcp, now);
             rtcp = self.generate_bye(rtcp, now);
 
             let size = rtcp.calculate_size().unwrap();
@@ -1583,6 +1632,37 @@ impl Session {
 
         replies
     }
+
+    /// Request the retransmission of packets from a remote sender with a generic NACK (RFC 4585).
+    /// The request is dropped if it can't be sent within `max_delay`.
+    pub(crate) fn request_nack(
+        &mut self,
+        now: Instant,
+        ssrc: u32,
+        seqnums: impl IntoIterator<Item = u16>,
+        max_delay: Duration,
+    ) -> Vec<RequestNackReply> {
+        let mut replies = Vec::new();
+
+        if !self.remote_senders.contains_key(&ssrc) {
+            trace!("No remote sender with ssrc {ssrc} known");
+            return replies;
+        };
+
+        let res = self.request_early_rtcp(now, max_delay);
+        if res == RequestEarlyRtcpResult::TimerReconsideration {
+            replies.push(RequestNackReply::TimerReconsideration);
+        }
+
+        if res != RequestEarlyRtcpResult::NotScheduled {
+            let source = self.remote_senders.get_mut(&ssrc).unwrap();
+            source.request_nack(seqnums);
+        } else {
+            debug!("Can't send NACK for ssrc {ssrc} within {max_delay:?}");
+        }
+
+        replies
+    }
 }
 
 fn generate_cname() -> String {
@@ -2654,4 +2734,98 @@ pub(crate) mod tests {
         );
         assert!(!session.is_point_to_point);
     }
+
+    #[test]
+    fn request_nack() {
+        init_logs();
+        let mut session = Session::new();
+        session.set_pt_clock_rate(TEST_PT, TEST_CLOCK_RATE);
+        session.set_profile(RtpProfile::Avpf);
+        let now = Instant::now();
+        let ntp_now = SystemTime::now();
+        let ssrc = 0x11223344;
+
+        // NACKs can only be requested for known senders
+        assert!(session
+            .request_nack(now, ssrc, [501], RTCP_MIN_REPORT_INTERVAL)
+            .is_empty());
+        assert!(session.next_early_rtcp_time.is_none());
+
+        let rtp_data = generate_rtp_packet(ssrc, 500, 0, 4);
+        let packet = RtpPacket::parse(&rtp_data).unwrap();
+        session_recv_first_packet_disable_probation(&mut session, &packet, now);
+        assert_eq!(
+            session.handle_recv(&packet, None, now),
+            RecvReply::Passthrough
+        );
+
+        // complete first regular rtcp
+        let (rtcp_data, now, ntp_now) = next_rtcp_packet(&mut session, now, ntp_now);
+        let RtcpSendReply::Data(_rtcp_data) = rtcp_data else {
+            unreachable!();
+        };
+
+        session.request_nack(now, ssrc, [501, 503, 501], RTCP_MIN_REPORT_INTERVAL);
+        assert!(session.next_early_rtcp_time.is_some());
+
+        let (rtcp_data, _now, _ntp_now) = next_rtcp_packet(&mut session, now, ntp_now);
+        let RtcpSendReply::Data(rtcp_data) = rtcp_data else {
+            unreachable!();
+        };
+        let rtcp = Compound::parse(&rtcp_data).unwrap();
+        let mut n_nack = 0;
+        for p in rtcp {
+            if let Ok(Packet::TransportFeedback(tf)) = p {
+                assert_eq!(tf.media_ssrc(), ssrc);
+                let nack = tf.parse_fci::<rtcp_types::Nack>().unwrap();
+                assert_eq!(nack.entries().collect::<Vec<_>>(), vec![501, 503]);
+                n_nack += 1;
+            }
+        }
+        assert_eq!(n_nack, 1);
+    }
+
+    #[test]
+    fn receive_nack() {
+        init_logs();
+        let mut session = Session::new();
+        session.set_pt_clock_rate(TEST_PT, TEST_CLOCK_RATE);
+        let now = Instant::now();
+        let ntp_now = SystemTime::now();
+        let send_ssrc = 0x11223344;
+        let recv_ssrc = 0x55667788;
+
+        let rtp_data = generate_rtp_packet(send_ssrc, 500, 0, 4);
+        let packet = RtpPacket::parse(&rtp_data).unwrap();
+        assert_eq!(
+            session.handle_send(&packet, now),
+            SendReply::NewSsrc(send_ssrc, TEST_PT)
+        );
+        assert_eq!(session.handle_send(&packet, now), SendReply::Passthrough);
+
+        for (media_ssrc, expected) in [(send_ssrc, true), (0x99aabbcc, false)] {
+            let mut data = vec![0; 128];
+            let len = Compound::builder()
+                .add_packet(
+                    TransportFeedback::builder_owned(
+                        rtcp_types::Nack::builder()
+                            .add_rtp_sequence(498)
+                            .add_rtp_sequence(499),
+                    )
+                    .sender_ssrc(recv_ssrc)
+                    .media_ssrc(media_ssrc),
+                )
+                .write_into(&mut data)
+                .unwrap();
+            let rtcp = Compound::parse(&data[..len]).unwrap();
+            let replies = session.handle_rtcp_recv(rtcp, len, None, now, ntp_now);
+            assert_eq!(
+                replies.contains(&RtcpRecvReply::RequestRetransmission {
+                    ssrc: send_ssrc,
+                    seqnums: vec![498, 499],
+                }),
+                expected
+            );
+        }
+    }
 }
@@ -22,6 +22,8 @@ pub const DEFAULT_MAX_DROPOUT: u32 = 3000;
 pub const DEFAULT_MAX_MISORDER: u32 = 100;
 
 const BITRATE_WINDOW: Duration = Duration::from_secs(3);
+// Each seqnum needs at most 4 bytes in a generic NACK
+const MAX_NACKS_PER_RTCP: usize = 64;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Rb {
@@ -430,6 +432,8 @@ pub struct RemoteSendSource {
     send_fir_seqnum: u8,
     // Count from the ForceKeyUnitEvent to de-duplicate FIR
     send_fir_count: Option<u32>,
+    // Seqnums of packets for which a generic NACK is pending with the next RTCP packet
+    send_nacks: Vec<u16>,
 }
 
 // The first time we recev a packet for jitter calculations
@@ -460,6 +464,7 @@ impl RemoteSendSource {
             send_fir: false,
             send_fir_seqnum: 0,
             send_fir_count: None,
+            send_nacks: Vec::new(),
         }
     }
 
@@ -958,6 +963,35 @@ impl RemoteSendSource {
             fir
         }
     }
+
+    pub(crate) fn request_nack(&mut self, seqnums: impl IntoIterator<Item = u16>) {
+        for seqnum in seqnums {
+            if !self.send_nacks.contains(&seqnum) {
+                self.send_nacks.push(seqnum);
+            }
+        }
+    }
+
+    pub(crate) fn has_pending_nack(&self) -> bool {
+        !self.send_nacks.is_empty()
+    }
+
+    pub(crate) fn generate_nack(&mut self) -> Option<rtcp_types::NackBuilder> {
+        if self.send_nacks.is_empty() {
+            return None;
+        }
+
+        // Keep the RTCP packet within the MTU, the remaining seqnums are sent with the next one
+        let len = self.send_nacks.len().min(MAX_NACKS_PER_RTCP);
+        let nack = self
+            .send_nacks
+            .drain(..len)
+            .fold(rtcp_types::Nack::builder(), |nack, seqnum| {
+                nack.add_rtp_sequence(seqnum)
+            });
+
+        Some(nack)
+    }
 }
 
 #[derive(Debug)]
@@ -1115,6 +1149,7 @@ impl RemoteReceiveSource {
             send_fir: false,
             send_fir_seqnum: 0,
             send_fir_count: None,
+            send_nacks: Vec::new(),
         }
     }
 
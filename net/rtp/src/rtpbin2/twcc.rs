@@ -0,0 +1,787 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Transport-wide congestion control as described in
+//! draft-holmer-rmcat-transport-wide-cc-extensions-01.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use gst::glib;
+use rtp_types::{RtpPacket, RtpPacketBuilder};
+
+pub const TWCC_EXTENSION_URI: &str =
+    "http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01";
+
+pub const DEFAULT_FEEDBACK_INTERVAL: Duration = Duration::from_millis(100);
+
+// Number of sent packets that are remembered for matching feedback
+const MAX_SENT_HISTORY: usize = 8192;
+// Maximum number of packet statuses in a single feedback packet
+const MAX_STATUS_COUNT: u64 = 0x1000;
+
+const RTCP_PT_RTPFB: u8 = 205;
+const RTCP_FMT_TWCC: u8 = 15;
+
+const REFERENCE_TIME_UNIT_US: i64 = 64_000;
+const DELTA_UNIT_US: i64 = 250;
+
+const ONE_BYTE_HEADER_PROFILE: u16 = 0xbede;
+const TWO_BYTE_HEADER_PROFILE: u16 = 0x1000;
+
+/// Transport-wide state shared by all sessions with the same `rtp-id`
+#[derive(Debug, Default)]
+pub struct Twcc {
+    pub sender: TwccSender,
+    pub receiver: TwccReceiver,
+}
+
+/// Finds the id of the transport-wide sequence number header extension from the `extmap-N`
+/// fields of RTP caps
+pub fn extension_id_from_caps(s: &gst::StructureRef) -> Option<u8> {
+    s.iter().find_map(|(name, value)| {
+        let id = name.strip_prefix("extmap-")?.parse::<u8>().ok()?;
+        let uri = match value.get::<String>() {
+            Ok(uri) => uri,
+            // (direction, uri, attributes)
+            Err(_) => value
+                .get::<gst::Array>()
+                .ok()?
+                .as_slice()
+                .get(1)?
+                .get::<String>()
+                .ok()?,
+        };
+
+        (uri == TWCC_EXTENSION_URI).then_some(id)
+    })
+}
+
+fn extension_elements<'a>(profile: u16, data: &'a [u8]) -> Option<Vec<(u8, &'a [u8])>> {
+    let mut elements = Vec::new();
+    let mut i = 0;
+
+    if profile == ONE_BYTE_HEADER_PROFILE {
+        while i < data.len() {
+            let b = data[i];
+            i += 1;
+            if b == 0 {
+                continue;
+            }
+            let id = b >> 4;
+            if id == 15 {
+                break;
+            }
+            let len = (b & 0x0f) as usize + 1;
+            elements.push((id, data.get(i..i + len)?));
+            i += len;
+        }
+    } else if profile & 0xfff0 == TWO_BYTE_HEADER_PROFILE {
+        while i < data.len() {
+            let id = data[i];
+            i += 1;
+            if id == 0 {
+                continue;
+            }
+            let len = *data.get(i)? as usize;
+            i += 1;
+            elements.push((id, data.get(i..i + len)?));
+            i += len;
+        }
+    } else {
+        return None;
+    }
+
+    Some(elements)
+}
+
+/// Reads the transport-wide sequence number from the header extension with the given id
+pub fn read_seqnum(rtp: &RtpPacket, id: u8) -> Option<u16> {
+    let (profile, data) = rtp.extension()?;
+    extension_elements(profile, data)?
+        .into_iter()
+        .find_map(|(other, data)| {
+            (other == id && data.len() >= 2).then(|| u16::from_be_bytes([data[0], data[1]]))
+        })
+}
+
+/// Writes the transport-wide sequence number into a copy of the packet, replacing an existing
+/// header extension with the same id
+pub fn write_seqnum(rtp: &RtpPacket, id: u8, seqnum: u16) -> Option<Vec<u8>> {
+    let seqnum = seqnum.to_be_bytes();
+    let mut elements = match rtp.extension() {
+        Some((profile, data)) => extension_elements(profile, data)?,
+        None => Vec::new(),
+    };
+    elements.retain(|(other, _)| *other != id);
+    elements.push((id, seqnum.as_slice()));
+
+    let one_byte = elements
+        .iter()
+        .all(|(id, data)| (1..=14).contains(id) && (1..=16).contains(&data.len()));
+    let mut ext = Vec::new();
+    let profile = if one_byte {
+        for (id, data) in &elements {
+            ext.push((id << 4) | (data.len() as u8 - 1));
+            ext.extend_from_slice(data);
+        }
+        ONE_BYTE_HEADER_PROFILE
+    } else {
+        for (id, data) in &elements {
+            ext.push(*id);
+            ext.push(u8::try_from(data.len()).ok()?);
+            ext.extend_from_slice(data);
+        }
+        TWO_BYTE_HEADER_PROFILE
+    };
+    while ext.len() % 4 != 0 {
+        ext.push(0);
+    }
+
+    let mut builder = RtpPacketBuilder::<&[u8], &[u8]>::new()
+        .payload_type(rtp.payload_type())
+        .ssrc(rtp.ssrc())
+        .sequence_number(rtp.sequence_number())
+        .timestamp(rtp.timestamp())
+        .marker_bit(rtp.marker_bit())
+        .extension(profile, ext.as_slice())
+        .payload(rtp.payload());
+    for csrc in rtp.csrc() {
+        builder = builder.add_csrc(csrc);
+    }
+    if let Some(padding) = rtp.padding() {
+        builder = builder.padding(padding);
+    }
+
+    builder.write_vec().ok()
+}
+
+#[derive(Debug)]
+struct SentPacket {
+    ssrc: u32,
+    pt: u8,
+    size: usize,
+    sent: Instant,
+    reported: bool,
+}
+
+/// Feedback about a single sent packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacketFeedback {
+    pub seqnum: u16,
+    pub ssrc: u32,
+    pub pt: u8,
+    pub size: usize,
+    pub local_ts: Duration,
+    /// `None` if the packet was lost
+    pub remote_ts: Option<Duration>,
+}
+
+/// Stamps outgoing packets with transport-wide sequence numbers and matches received feedback
+/// to them
+#[derive(Debug, Default)]
+pub struct TwccSender {
+    next_seqnum: u16,
+    // Sent packets in order of their seqnums, starting at `first_seqnum`
+    first_seqnum: u16,
+    sent: VecDeque<SentPacket>,
+    base_instant: Option<Instant>,
+}
+
+impl TwccSender {
+    /// Returns a copy of the packet with the next transport-wide sequence number in the header
+    /// extension with the given id
+    pub fn stamp(&mut self, rtp: &RtpPacket, id: u8, now: Instant) -> Option<Vec<u8>> {
+        let data = write_seqnum(rtp, id, self.next_seqnum)?;
+
+        if self.sent.is_empty() {
+            self.first_seqnum = self.next_seqnum;
+        }
+        self.base_instant.get_or_insert(now);
+        self.sent.push_back(SentPacket {
+            ssrc: rtp.ssrc(),
+            pt: rtp.payload_type(),
+            size: data.len(),
+            sent: now,
+            reported: false,
+        });
+        self.next_seqnum = self.next_seqnum.wrapping_add(1);
+
+        while self.sent.len() > MAX_SENT_HISTORY {
+            self.sent.pop_front();
+            self.first_seqnum = self.first_seqnum.wrapping_add(1);
+        }
+
+        Some(data)
+    }
+
+    /// Matches feedback to the sent packets. Every packet is only reported once.
+    pub fn handle_feedback(&mut self, feedback: &Feedback) -> Vec<PacketFeedback> {
+        let Some(base_instant) = self.base_instant else {
+            return Vec::new();
+        };
+
+        let mut ret = Vec::new();
+        for (seqnum, remote_ts) in feedback.packets() {
+            let idx = seqnum.wrapping_sub(self.first_seqnum) as usize;
+            let Some(packet) = self.sent.get_mut(idx) else {
+                trace!("Feedback for unknown packet {seqnum}");
+                continue;
+            };
+            if packet.reported {
+                continue;
+            }
+            packet.reported = true;
+
+            ret.push(PacketFeedback {
+                seqnum,
+                ssrc: packet.ssrc,
+                pt: packet.pt,
+                size: packet.size,
+                local_ts: packet.sent.saturating_duration_since(base_instant),
+                remote_ts,
+            });
+        }
+
+        ret
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Creates the `RTPTWCCPackets` upstream event that is also used by `rtpsession` and understood
+/// by `rtpgccbwe`
+pub fn packets_event(packets: &[PacketFeedback]) -> gst::Event {
+    let packets = glib::ValueArray::new(packets.iter().map(|packet| {
+        let mut s = gst::Structure::builder("RTPTWCCPacket")
+            .field("seqnum", packet.seqnum as u32)
+            .field(
+                "local-ts",
+                gst::ClockTime::from_nseconds(packet.local_ts.as_nanos() as u64),
+            )
+            .field("size", packet.size as u32)
+            .field("pt", packet.pt as u32)
+            .field("ssrc", packet.ssrc)
+            .field("lost", packet.remote_ts.is_none());
+        if let Some(remote_ts) = packet.remote_ts {
+            s = s.field(
+                "remote-ts",
+                gst::ClockTime::from_nseconds(remote_ts.as_nanos() as u64),
+            );
+        }
+        s.build()
+    }));
+
+    gst::event::CustomUpstream::new(
+        gst::Structure::builder("RTPTWCCPackets")
+            .field("packets", packets)
+            .build(),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    NotReceived,
+    SmallDelta(u8),
+    LargeDelta(i16),
+}
+
+impl Status {
+    fn symbol(&self) -> u16 {
+        match self {
+            Status::NotReceived => 0,
+            Status::SmallDelta(_) => 1,
+            Status::LargeDelta(_) => 2,
+        }
+    }
+}
+
+/// Records the arrival times of packets with transport-wide sequence numbers and generates
+/// feedback packets from them
+#[derive(Debug)]
+pub struct TwccReceiver {
+    feedback_interval: Duration,
+    media_ssrc: u32,
+    // Extended seqnum of the highest received packet
+    max_seqnum: Option<u64>,
+    // Extended seqnum of the first packet that was not covered by feedback yet
+    next_seqnum: Option<u64>,
+    arrivals: BTreeMap<u64, Duration>,
+    fb_pkt_count: u8,
+    next_feedback: Option<Instant>,
+}
+
+impl Default for TwccReceiver {
+    fn default() -> Self {
+        Self {
+            feedback_interval: DEFAULT_FEEDBACK_INTERVAL,
+            media_ssrc: 0,
+            max_seqnum: None,
+            next_seqnum: None,
+            arrivals: BTreeMap::new(),
+            fb_pkt_count: 0,
+            next_feedback: None,
+        }
+    }
+}
+
+impl TwccReceiver {
+    pub fn set_feedback_interval(&mut self, feedback_interval: Duration) {
+        self.feedback_interval = feedback_interval;
+    }
+
+    /// Records the arrival of a packet. Returns `true` if a new feedback packet was scheduled.
+    pub fn record(&mut self, ssrc: u32, seqnum: u16, arrival: Duration, now: Instant) -> bool {
+        let seqnum = match self.max_seqnum {
+            // Start far enough from 0 so that reordered packets can't underflow
+            None => 0x1_0000 + seqnum as u64,
+            Some(max) => {
+                let diff = seqnum.wrapping_sub(max as u16) as i16;
+                max.saturating_add_signed(diff as i64)
+            }
+        };
+
+        if self.next_seqnum.is_some_and(|next| seqnum < next) {
+            trace!("Packet {seqnum} arrived after its feedback was sent");
+            return false;
+        }
+
+        self.max_seqnum = Some(self.max_seqnum.map_or(seqnum, |max| max.max(seqnum)));
+        self.arrivals.entry(seqnum).or_insert(arrival);
+        self.media_ssrc = ssrc;
+
+        if self.next_feedback.is_none() {
+            self.next_feedback = Some(now + self.feedback_interval);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn poll_feedback_timeout(&self) -> Option<Instant> {
+        self.next_feedback
+    }
+
+    /// Generates a feedback packet once the feedback interval has passed
+    pub fn poll_feedback(&mut self, now: Instant, sender_ssrc: u32) -> Option<Vec<u8>> {
+        if self
+            .next_feedback
+            .is_none_or(|next_feedback| now < next_feedback)
+        {
+            return None;
+        }
+
+        let Some((&first_received, &first_arrival)) = self.arrivals.first_key_value() else {
+            self.next_feedback = None;
+            return None;
+        };
+        let max_seqnum = self.max_seqnum.unwrap();
+        let mut base_seqnum = self.next_seqnum.unwrap_or(first_received);
+        if first_received - base_seqnum > MAX_STATUS_COUNT {
+            // Don't report long runs of lost packets
+            base_seqnum = first_received;
+        }
+
+        let reference_time = first_arrival.as_micros() as i64 / REFERENCE_TIME_UNIT_US;
+        let mut last_us = reference_time * REFERENCE_TIME_UNIT_US;
+        let mut statuses = Vec::new();
+        let mut end = base_seqnum;
+        while end <= max_seqnum && end - base_seqnum < MAX_STATUS_COUNT {
+            let status = match self.arrivals.get(&end) {
+                None => Status::NotReceived,
+                Some(arrival) => {
+                    let delta = (arrival.as_micros() as i64 - last_us).div_euclid(DELTA_UNIT_US);
+                    let status = if let Ok(delta) = u8::try_from(delta) {
+                        Status::SmallDelta(delta)
+                    } else if let Ok(delta) = i16::try_from(delta) {
+                        Status::LargeDelta(delta)
+                    } else {
+                        // Continue in the next feedback packet with a new reference time
+                        break;
+                    };
+                    last_us += delta * DELTA_UNIT_US;
+                    status
+                }
+            };
+            statuses.push(status);
+            end += 1;
+        }
+
+        let data = write_feedback(
+            sender_ssrc,
+            self.media_ssrc,
+            base_seqnum as u16,
+            reference_time as u32 & 0x00ff_ffff,
+            self.fb_pkt_count,
+            &statuses,
+        );
+        self.fb_pkt_count = self.fb_pkt_count.wrapping_add(1);
+
+        self.arrivals = self.arrivals.split_off(&end);
+        self.next_seqnum = Some(end);
+        self.next_feedback = if self.arrivals.is_empty() {
+            None
+        } else {
+            Some(now)
+        };
+
+        trace!(
+            "Generated feedback for {} packets starting at {}",
+            statuses.len(),
+            base_seqnum as u16
+        );
+
+        Some(data)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self {
+            feedback_interval: self.feedback_interval,
+            ..Self::default()
+        };
+    }
+}
+
+fn write_feedback(
+    sender_ssrc: u32,
+    media_ssrc: u32,
+    base_seqnum: u16,
+    reference_time: u32,
+    fb_pkt_count: u8,
+    statuses: &[Status],
+) -> Vec<u8> {
+    let mut data = vec![0x80 | RTCP_FMT_TWCC, RTCP_PT_RTPFB, 0, 0];
+    data.extend_from_slice(&sender_ssrc.to_be_bytes());
+    data.extend_from_slice(&media_ssrc.to_be_bytes());
+    data.extend_from_slice(&base_seqnum.to_be_bytes());
+    data.extend_from_slice(&(statuses.len() as u16).to_be_bytes());
+    data.extend_from_slice(&((reference_time << 8) | fb_pkt_count as u32).to_be_bytes());
+
+    // Run length chunks for runs of at least 7 equal statuses, otherwise status vector chunks
+    // with 7 two-bit symbols
+    let mut i = 0;
+    while i < statuses.len() {
+        let symbol = statuses[i].symbol();
+        let run = statuses[i..]
+            .iter()
+            .take(0x1fff)
+            .take_while(|status| status.symbol() == symbol)
+            .count();
+        if run >= 7 {
+            data.extend_from_slice(&((symbol << 13) | run as u16).to_be_bytes());
+            i += run;
+        } else {
+            let mut chunk = 0xc000u16;
+            for (j, status) in statuses[i..].iter().take(7).enumerate() {
+                chunk |= status.symbol() << (12 - 2 * j);
+            }
+            data.extend_from_slice(&chunk.to_be_bytes());
+            i += 7;
+        }
+    }
+
+    for status in statuses {
+        match status {
+            Status::NotReceived => (),
+            Status::SmallDelta(delta) => data.push(*delta),
+            Status::LargeDelta(delta) => data.extend_from_slice(&delta.to_be_bytes()),
+        }
+    }
+
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+    let length = (data.len() / 4 - 1) as u16;
+    data[2..4].copy_from_slice(&length.to_be_bytes());
+
+    data
+}
+
+/// A parsed transport-wide feedback packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feedback {
+    pub sender_ssrc: u32,
+    pub media_ssrc: u32,
+    pub base_seqnum: u16,
+    pub fb_pkt_count: u8,
+    /// Remote arrival time of each packet starting at `base_seqnum`, `None` if not received
+    pub arrivals: Vec<Option<Duration>>,
+}
+
+impl Feedback {
+    /// Iterates over the seqnums and remote arrival times of the reported packets
+    pub fn packets(&self) -> impl Iterator<Item = (u16, Option<Duration>)> + '_ {
+        self.arrivals
+            .iter()
+            .enumerate()
+            .map(|(i, arrival)| (self.base_seqnum.wrapping_add(i as u16), *arrival))
+    }
+}
+
+/// Extracts all transport-wide feedback packets from a compound RTCP packet
+pub fn parse_feedback(data: &[u8]) -> Vec<Feedback> {
+    let mut ret = Vec::new();
+    let mut i = 0;
+
+    while i + 4 <= data.len() {
+        let header = &data[i..i + 4];
+        if header[0] >> 6 != 2 {
+            break;
+        }
+        let len = (u16::from_be_bytes([header[2], header[3]]) as usize + 1) * 4;
+        let Some(packet) = data.get(i..i + len) else {
+            break;
+        };
+        if header[1] == RTCP_PT_RTPFB && header[0] & 0x1f == RTCP_FMT_TWCC {
+            match parse_feedback_packet(packet) {
+                Some(feedback) => ret.push(feedback),
+                None => debug!("Failed to parse transport-wide feedback packet"),
+            }
+        }
+        i += len;
+    }
+
+    ret
+}
+
+fn parse_feedback_packet(data: &[u8]) -> Option<Feedback> {
+    let read_u16 = |pos: usize| -> Option<u16> {
+        data.get(pos..pos + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    let read_u32 = |pos: usize| -> Option<u32> {
+        data.get(pos..pos + 4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    let sender_ssrc = read_u32(4)?;
+    let media_ssrc = read_u32(8)?;
+    let base_seqnum = read_u16(12)?;
+    let count = read_u16(14)? as usize;
+    let reference = read_u32(16)?;
+    let reference_time = (reference >> 8) as i64;
+    let fb_pkt_count = reference as u8;
+
+    let mut pos = 20;
+    let mut symbols = Vec::with_capacity(count);
+    while symbols.len() < count {
+        let chunk = read_u16(pos)?;
+        pos += 2;
+
+        let remaining = count - symbols.len();
+        if chunk & 0x8000 == 0 {
+            let symbol = (chunk >> 13) & 0x3;
+            let run = (chunk & 0x1fff) as usize;
+            symbols.extend(std::iter::repeat_n(symbol, run.min(remaining)));
+        } else if chunk & 0x4000 == 0 {
+            symbols.extend((0..14).take(remaining).map(|j| (chunk >> (13 - j)) & 0x1));
+        } else {
+            symbols.extend(
+                (0..7)
+                    .take(remaining)
+                    .map(|j| (chunk >> (12 - 2 * j)) & 0x3),
+            );
+        }
+    }
+
+    let mut time_us = reference_time * REFERENCE_TIME_UNIT_US;
+    let mut arrivals = Vec::with_capacity(count);
+    for symbol in symbols {
+        let delta = match symbol {
+            0 => {
+                arrivals.push(None);
+                continue;
+            }
+            1 => {
+                let delta = *data.get(pos)? as i64;
+                pos += 1;
+                delta
+            }
+            2 => {
+                let delta = read_u16(pos)? as i16 as i64;
+                pos += 2;
+                delta
+            }
+            _ => return None,
+        };
+        time_us += delta * DELTA_UNIT_US;
+        arrivals.push(Some(Duration::from_micros(time_us.max(0) as u64)));
+    }
+
+    Some(Feedback {
+        sender_ssrc,
+        media_ssrc,
+        base_seqnum,
+        fb_pkt_count,
+        arrivals,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtpbin2::session::tests::{generate_rtp_packet, init_logs};
+
+    #[test]
+    fn header_extension() {
+        init_logs();
+
+        let rtp_data = generate_rtp_packet(0x12345678, 500, 9000, 4);
+        let rtp = RtpPacket::parse(&rtp_data).unwrap();
+        assert_eq!(read_seqnum(&rtp, 3), None);
+
+        let data = write_seqnum(&rtp, 3, 0x1234).unwrap();
+        let stamped = RtpPacket::parse(&data).unwrap();
+        assert_eq!(read_seqnum(&stamped, 3), Some(0x1234));
+        assert_eq!(stamped.sequence_number(), 500);
+        assert_eq!(stamped.timestamp(), 9000);
+        assert_eq!(stamped.payload(), rtp.payload());
+
+        // Replaces the existing element
+        let data = write_seqnum(&stamped, 3, 0x5678).unwrap();
+        let restamped = RtpPacket::parse(&data).unwrap();
+        assert_eq!(read_seqnum(&restamped, 3), Some(0x5678));
+        assert_eq!(restamped.extension().unwrap().1.len(), 4);
+
+        // Ids that don't fit into the one-byte header switch to the two-byte header
+        let data = write_seqnum(&restamped, 20, 0x0102).unwrap();
+        let two_byte = RtpPacket::parse(&data).unwrap();
+        assert_eq!(
+            two_byte.extension().unwrap().0 & 0xfff0,
+            TWO_BYTE_HEADER_PROFILE
+        );
+        assert_eq!(read_seqnum(&two_byte, 3), Some(0x5678));
+        assert_eq!(read_seqnum(&two_byte, 20), Some(0x0102));
+    }
+
+    #[test]
+    fn caps_extension_id() {
+        gst::init().unwrap();
+
+        let s = gst::Structure::builder("application/x-rtp")
+            .field("extmap-2", "urn:ietf:params:rtp-hdrext:sdes:mid")
+            .field("extmap-5", TWCC_EXTENSION_URI)
+            .build();
+        assert_eq!(extension_id_from_caps(&s), Some(5));
+
+        let s = gst::Structure::builder("application/x-rtp")
+            .field("extmap-7", gst::Array::new(["", TWCC_EXTENSION_URI, ""]))
+            .build();
+        assert_eq!(extension_id_from_caps(&s), Some(7));
+
+        let s = gst::Structure::builder("application/x-rtp").build();
+        assert_eq!(extension_id_from_caps(&s), None);
+    }
+
+    #[test]
+    fn feedback_roundtrip() {
+        init_logs();
+
+        let statuses = [
+            Status::SmallDelta(4),
+            Status::NotReceived,
+            Status::LargeDelta(-3),
+            Status::LargeDelta(1000),
+        ]
+        .into_iter()
+        .chain(std::iter::repeat_n(Status::SmallDelta(0), 10))
+        .collect::<Vec<_>>();
+        let data = write_feedback(0x1111, 0x2222, 65534, 10, 3, &statuses);
+        assert_eq!(data.len() % 4, 0);
+
+        let feedback = parse_feedback(&data);
+        assert_eq!(feedback.len(), 1);
+        let feedback = &feedback[0];
+        assert_eq!(feedback.sender_ssrc, 0x1111);
+        assert_eq!(feedback.media_ssrc, 0x2222);
+        assert_eq!(feedback.fb_pkt_count, 3);
+
+        let base = 10 * 64_000;
+        let mut packets = feedback.packets();
+        assert_eq!(
+            packets.next(),
+            Some((65534, Some(Duration::from_micros(base + 1_000))))
+        );
+        assert_eq!(packets.next(), Some((65535, None)));
+        assert_eq!(
+            packets.next(),
+            Some((0, Some(Duration::from_micros(base + 1_000 - 750))))
+        );
+        assert_eq!(
+            packets.next(),
+            Some((1, Some(Duration::from_micros(base + 250 + 250_000))))
+        );
+        assert_eq!(packets.count(), 10);
+    }
+
+    #[test]
+    fn simulated_network() {
+        init_logs();
+
+        let mut sender = TwccSender::default();
+        let mut receiver = TwccReceiver::default();
+
+        // Send a packet every 10ms over a network with 50ms delay that increases by 1ms per
+        // packet, loses every 5th packet and reorders packets 11 and 12
+        let now = Instant::now();
+        let mut network = Vec::new();
+        for i in 0..20u16 {
+            let rtp_data = generate_rtp_packet(0x12345678, 100 + i, 0, 100);
+            let rtp = RtpPacket::parse(&rtp_data).unwrap();
+            let sent = now + Duration::from_millis(10 * i as u64);
+            let data = sender.stamp(&rtp, 1, sent).unwrap();
+            if i % 5 == 4 {
+                continue;
+            }
+            let arrival = Duration::from_millis(1000 + 10 * i as u64 + 50 + i as u64);
+            network.push((arrival, data));
+        }
+        network.swap(9, 10);
+
+        let mut feedback = Vec::new();
+        for (arrival, data) in network {
+            let rtp = RtpPacket::parse(&data).unwrap();
+            let seqnum = read_seqnum(&rtp, 1).unwrap();
+            let at = now + arrival - Duration::from_millis(1000);
+            receiver.record(rtp.ssrc(), seqnum, arrival, at);
+            if let Some(data) = receiver.poll_feedback(at, 0x87654321) {
+                feedback.extend(parse_feedback(&data));
+            }
+        }
+        let end = now + Duration::from_secs(1);
+        assert!(receiver
+            .poll_feedback_timeout()
+            .is_some_and(|timeout| timeout < end));
+        while let Some(data) = receiver.poll_feedback(end, 0x87654321) {
+            feedback.extend(parse_feedback(&data));
+        }
+        assert!(receiver.poll_feedback_timeout().is_none());
+        assert!(feedback.len() >= 2);
+
+        let packets = feedback
+            .iter()
+            .flat_map(|feedback| sender.handle_feedback(feedback))
+            .collect::<Vec<_>>();
+        // The last lost packet is never reported as nothing arrived after it
+        assert_eq!(packets.len(), 19);
+        for packet in &packets {
+            let i = packet.seqnum as u64;
+            assert_eq!(packet.ssrc, 0x12345678);
+            assert_eq!(packet.local_ts, Duration::from_millis(10 * i));
+            if i % 5 == 4 {
+                assert_eq!(packet.remote_ts, None);
+            } else {
+                assert_eq!(
+                    packet.remote_ts,
+                    Some(Duration::from_millis(1000 + 11 * i + 50))
+                );
+            }
+        }
+
+        // Feedback is only reported once
+        assert!(feedback
+            .iter()
+            .all(|feedback| sender.handle_feedback(feedback).is_empty()));
+    }
+}
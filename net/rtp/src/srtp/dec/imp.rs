@@ -0,0 +1,615 @@
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * SECTION:element-rtpsrtpdec
+ * @see_also: rtpsrtpenc, rtprecv
+ *
+ * Authenticates and decrypts SRTP and SRTCP packets as per [RFC 3711][rfc-3711].
+ *
+ * Supported are the AES counter mode profiles with HMAC-SHA1 authentication from RFC 3711 and
+ * [RFC 6188][rfc-6188], and the AES-GCM profiles from [RFC 7714][rfc-7714].
+ *
+ * The element is placed in front of the `rtp_sink` or `rtcp_sink` pad of `rtprecv`. Packets are
+ * handled as SRTCP if the input caps are `application/x-srtcp`, or if they are SRTCP packets
+ * multiplexed with the SRTP packets as per RFC 5761.
+ *
+ * The master key and master salt of an SSRC are requested with the #rtpsrtpdec::request-key
+ * signal when the first packet of the SSRC is received. If no handler provides a key, the key
+ * from the `srtp-key`, `srtp-cipher` and `srtp-auth` fields of the input caps is used, and
+ * otherwise the #rtpsrtpdec:key and #rtpsrtpdec:profile properties. For DTLS-SRTP, the keying
+ * material exported from the DTLS connection can be passed to the
+ * #rtpsrtpdec::set-keying-material action signal.
+ *
+ * The rollover counter of each SSRC is estimated from the seqnums of the received packets.
+ * Packets that fail authentication, and packets that were already received or are older than
+ * the #rtpsrtpdec:replay-window-size, are dropped and counted in #rtpsrtpdec:stats.
+ *
+ * [rfc-3711]: https://www.rfc-editor.org/rfc/rfc3711.html
+ * [rfc-6188]: https://www.rfc-editor.org/rfc/rfc6188.html
+ * [rfc-7714]: https://www.rfc-editor.org/rfc/rfc7714.html
+ *
+ * ## Example pipeline
+ *
+ * |[
+ * gst-launch-1.0 \
+ *  udpsrc port=5004 caps='application/x-srtp, media=audio, clock-rate=48000, encoding-name=OPUS, payload=96' \
+ *  ! rtpsrtpdec key=000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d ! recv.rtp_sink_0 \
+ *  udpsrc port=5005 caps='application/x-srtcp' \
+ *  ! rtpsrtpdec key=000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d ! recv.rtcp_sink_0 \
+ *  rtprecv name=recv rtp-id=example-rtp-id latency=200 \
+ *  ! rtpopusdepay2 ! opusdec ! audioconvert ! audioresample ! autoaudiosink
+ * ]| This will receive an Opus stream sent with SRTP and SRTCP.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::{glib, prelude::*, subclass::prelude::*};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use crate::srtp::{self, Context, MasterKey, Profile, ReplayWindow};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rtpsrtpdec",
+        gst::DebugColorFlags::empty(),
+        Some("RTP SRTP Decoder"),
+    )
+});
+
+const DEFAULT_PROFILE: Profile = Profile::Aes128CmHmacSha1_80;
+const DEFAULT_REPLAY_WINDOW_SIZE: u32 = 128;
+
+/// Caps fields with keying information, which are not forwarded downstream
+const KEY_FIELDS: [&str; 6] = [
+    "srtp-key",
+    "srtp-cipher",
+    "srtp-auth",
+    "srtcp-cipher",
+    "srtcp-auth",
+    "mki",
+];
+
+#[derive(Debug, Clone)]
+struct Settings {
+    profile: Profile,
+    key: Option<gst::Buffer>,
+    replay_window_size: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            profile: DEFAULT_PROFILE,
+            key: None,
+            replay_window_size: DEFAULT_REPLAY_WINDOW_SIZE,
+        }
+    }
+}
+
+impl Settings {
+    fn master_key(&self) -> Option<MasterKey> {
+        let key = self.key.as_ref()?.map_readable().ok()?;
+        MasterKey::new(self.profile, &key)
+    }
+}
+
+#[derive(Debug)]
+struct Stream {
+    /// Key provided for this SSRC, or `None` if the default key is used
+    key: Option<MasterKey>,
+    context: Context,
+    rtp_window: ReplayWindow,
+    rtcp_window: ReplayWindow,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    is_rtcp: bool,
+    /// Key from the input caps, which takes precedence over the properties
+    caps_key: Option<MasterKey>,
+    streams: HashMap<u32, Stream>,
+    received: u64,
+    auth_failures: u64,
+    replayed: u64,
+}
+
+impl State {
+    /// Updates all streams that use the default key after it changed
+    fn update_default_key(&mut self, key: Option<&MasterKey>) {
+        match key {
+            Some(key) => {
+                let context = || Context::new(key);
+                for stream in self.streams.values_mut().filter(|s| s.key.is_none()) {
+                    stream.context = context();
+                }
+            }
+            None => self.streams.retain(|_, stream| stream.key.is_some()),
+        }
+    }
+}
+
+pub struct RtpSrtpDec {
+    srcpad: gst::Pad,
+    sinkpad: gst::Pad,
+    settings: Mutex<Settings>,
+    state: Mutex<State>,
+}
+
+impl RtpSrtpDec {
+    fn default_key(&self, state: &State) -> Option<MasterKey> {
+        state
+            .caps_key
+            .clone()
+            .or_else(|| self.settings.lock().unwrap().master_key())
+    }
+
+    /// Creates the crypto context for a new SSRC, or returns `false` if there is no key for it
+    fn ensure_stream(&self, ssrc: u32) -> bool {
+        if self.state.lock().unwrap().streams.contains_key(&ssrc) {
+            return true;
+        }
+
+        let key = self
+            .obj()
+            .emit_by_name::<Option<gst::Caps>>("request-key", &[&ssrc])
+            .and_then(|caps| {
+                let key = caps.structure(0).and_then(MasterKey::from_structure);
+                if key.is_none() {
+                    gst::warning!(CAT, imp = self, "Invalid key for SSRC {ssrc}: {caps:?}");
+                }
+                key
+            });
+
+        let replay_window_size = self.settings.lock().unwrap().replay_window_size;
+        let mut state = self.state.lock().unwrap();
+        let Some(master_key) = key.clone().or_else(|| self.default_key(&state)) else {
+            gst::warning!(CAT, imp = self, "No key for SSRC {ssrc}");
+            return false;
+        };
+        let context = Context::new(&master_key);
+        gst::debug!(CAT, imp = self, "New SSRC {ssrc} with {context:?}");
+
+        state.streams.entry(ssrc).or_insert(Stream {
+            key,
+            context,
+            rtp_window: ReplayWindow::new(replay_window_size),
+            rtcp_window: ReplayWindow::new(replay_window_size),
+        });
+
+        true
+    }
+
+    fn sink_chain(
+        &self,
+        pad: &gst::Pad,
+        buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let map = buffer.map_readable().map_err(|_| {
+            gst::error!(CAT, obj = pad, "Failed to map buffer readable");
+            gst::FlowError::Error
+        })?;
+
+        let is_rtcp = self.state.lock().unwrap().is_rtcp || srtp::is_rtcp(&map);
+        let data = if is_rtcp {
+            let Some(ssrc) = srtp::parse_rtcp_ssrc(&map) else {
+                gst::warning!(CAT, obj = pad, "Dropping invalid SRTCP packet");
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            if !self.ensure_stream(ssrc) {
+                return Ok(gst::FlowSuccess::Ok);
+            }
+
+            let mut state = self.state.lock().unwrap();
+            let state = &mut *state;
+            let Some(stream) = state.streams.get_mut(&ssrc) else {
+                // The key was removed in the meantime
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            let Some(index) = stream.context.rtcp_index(&map) else {
+                gst::warning!(CAT, obj = pad, "Dropping invalid SRTCP packet");
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            if !stream.rtcp_window.check(index as u64) {
+                gst::debug!(
+                    CAT,
+                    imp = self,
+                    "Dropping replayed SRTCP packet {index} of SSRC {ssrc}"
+                );
+                state.replayed += 1;
+                return Ok(gst::FlowSuccess::Ok);
+            }
+            let Some(data) = stream.context.unprotect_rtcp(&map) else {
+                gst::debug!(
+                    CAT,
+                    imp = self,
+                    "Authentication of SRTCP packet {index} of SSRC {ssrc} failed"
+                );
+                state.auth_failures += 1;
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            stream.rtcp_window.update(index as u64);
+            state.received += 1;
+
+            data
+        } else {
+            let Some((seqnum, ssrc, header_len)) = srtp::parse_rtp_header(&map) else {
+                gst::warning!(CAT, obj = pad, "Dropping invalid SRTP packet");
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            if !self.ensure_stream(ssrc) {
+                return Ok(gst::FlowSuccess::Ok);
+            }
+
+            let mut state = self.state.lock().unwrap();
+            let state = &mut *state;
+            let Some(stream) = state.streams.get_mut(&ssrc) else {
+                // The key was removed in the meantime
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            let index = srtp::estimate_index(stream.rtp_window.max_index(), seqnum)
+                .filter(|&index| stream.rtp_window.check(index));
+            let Some(index) = index else {
+                gst::debug!(
+                    CAT,
+                    imp = self,
+                    "Dropping replayed SRTP packet {seqnum} of SSRC {ssrc}"
+                );
+                state.replayed += 1;
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            let Some(data) = stream.context.unprotect_rtp(&map, header_len, ssrc, index) else {
+                gst::debug!(
+                    CAT,
+                    imp = self,
+                    "Authentication of SRTP packet {seqnum} of SSRC {ssrc} failed"
+                );
+                state.auth_failures += 1;
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            stream.rtp_window.update(index);
+            state.received += 1;
+
+            data
+        };
+        drop(map);
+
+        let mut outbuf = gst::Buffer::from_mut_slice(data);
+        {
+            let outbuf_mut = outbuf.get_mut().unwrap();
+            let _ = buffer.copy_into(
+                outbuf_mut,
+                gst::BufferCopyFlags::FLAGS
+                    | gst::BufferCopyFlags::TIMESTAMPS
+                    | gst::BufferCopyFlags::META,
+                ..,
+            );
+        }
+
+        self.srcpad.push(outbuf)
+    }
+
+    fn sink_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        gst::log!(CAT, obj = pad, "Handling event {event:?}");
+
+        match event.view() {
+            gst::EventView::Caps(caps) => {
+                let mut caps = caps.caps_owned();
+                let Some(s) = caps.structure(0) else {
+                    return false;
+                };
+                let is_rtcp = s.name() == "application/x-srtcp";
+                let caps_key = MasterKey::from_structure(s);
+
+                {
+                    let mut state = self.state.lock().unwrap();
+                    state.is_rtcp = is_rtcp;
+                    if caps_key != state.caps_key {
+                        gst::debug!(CAT, imp = self, "Key from caps changed");
+                        state.caps_key = caps_key;
+                        let key = self.default_key(&state);
+                        state.update_default_key(key.as_ref());
+                    }
+                }
+
+                {
+                    let caps = caps.make_mut();
+                    let s = caps.structure_mut(0).unwrap();
+                    s.set_name(if is_rtcp {
+                        "application/x-rtcp"
+                    } else {
+                        "application/x-rtp"
+                    });
+                    s.remove_fields(KEY_FIELDS);
+                }
+
+                self.srcpad.push_event(gst::event::Caps::new(&caps))
+            }
+            _ => gst::Pad::event_default(pad, Some(&*self.obj()), event),
+        }
+    }
+
+    fn set_keying_material(&self, material: &gst::Buffer, is_client: bool) -> bool {
+        let Ok(material) = material.map_readable() else {
+            return false;
+        };
+
+        let mut settings = self.settings.lock().unwrap();
+        // Packets are received with the write key of the other side of the DTLS connection
+        let Some(key) = MasterKey::from_keying_material(settings.profile, &material, !is_client)
+        else {
+            gst::error!(CAT, imp = self, "Not enough keying material");
+            return false;
+        };
+        gst::debug!(
+            CAT,
+            imp = self,
+            "Using keying material as DTLS client {is_client}"
+        );
+
+        settings.key = Some(gst::Buffer::from_slice(key.key.clone()));
+        drop(settings);
+
+        let mut state = self.state.lock().unwrap();
+        let key = self.default_key(&state);
+        state.update_default_key(key.as_ref());
+
+        true
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RtpSrtpDec {
+    const NAME: &'static str = "GstRtpSrtpDec";
+    type Type = super::RtpSrtpDec;
+    type ParentType = gst::Element;
+
+    fn with_class(klass: &Self::Class) -> Self {
+        let templ = klass.pad_template("sink").unwrap();
+        let sinkpad = gst::Pad::builder_from_template(&templ)
+            .chain_function(|pad, parent, buffer| {
+                Self::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::FlowError::Error),
+                    |this| this.sink_chain(pad, buffer),
+                )
+            })
+            .event_function(|pad, parent, event| {
+                Self::catch_panic_pad_function(parent, || false, |this| this.sink_event(pad, event))
+            })
+            .build();
+
+        let templ = klass.pad_template("src").unwrap();
+        let srcpad = gst::Pad::builder_from_template(&templ).build();
+
+        Self {
+            srcpad,
+            sinkpad,
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(State::default()),
+        }
+    }
+}
+
+impl ObjectImpl for RtpSrtpDec {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecEnum::builder_with_default("profile", DEFAULT_PROFILE)
+                    .nick("Profile")
+                    .blurb("SRTP protection profile of the default key")
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecBoxed::builder::<gst::Buffer>("key")
+                    .nick("Key")
+                    .blurb("Default master key followed by the master salt")
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecUInt::builder("replay-window-size")
+                    .nick("Replay Window Size")
+                    .blurb("Number of packets in the replay protection window")
+                    .minimum(64)
+                    .maximum(32768)
+                    .default_value(DEFAULT_REPLAY_WINDOW_SIZE)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecBoxed::builder::<gst::Structure>("stats")
+                    .nick("Statistics")
+                    .blurb("Various statistics")
+                    .read_only()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn signals() -> &'static [glib::subclass::Signal] {
+        static SIGNALS: LazyLock<Vec<glib::subclass::Signal>> = LazyLock::new(|| {
+            vec![
+                /**
+                 * GstRtpSrtpDec::request-key:
+                 * @ssrc: The SSRC
+                 *
+                 * Requests the key for a new SSRC as caps with `srtp-key`, `srtp-cipher` and
+                 * `srtp-auth` fields, as used by the `srtpdec` element. If no handler returns
+                 * caps, the default key from the input caps or the properties is used.
+                 */
+                glib::subclass::Signal::builder("request-key")
+                    .param_types([u32::static_type()])
+                    .return_type::<Option<gst::Caps>>()
+                    .accumulator(|_hint, _acc, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+                /**
+                 * GstRtpSrtpDec::remove-key:
+                 * @ssrc: The SSRC
+                 *
+                 * Removes the key and state of an SSRC. The key is requested again with the
+                 * next packet of the SSRC.
+                 */
+                glib::subclass::Signal::builder("remove-key")
+                    .param_types([u32::static_type()])
+                    .action()
+                    .class_handler(|args| {
+                        let element = args[0].get::<super::RtpSrtpDec>().expect("signal arg");
+                        let ssrc = args[1].get::<u32>().expect("signal arg");
+                        let imp = element.imp();
+
+                        gst::debug!(CAT, imp = imp, "Removing key of SSRC {ssrc}");
+                        imp.state.lock().unwrap().streams.remove(&ssrc);
+
+                        None
+                    })
+                    .build(),
+                /**
+                 * GstRtpSrtpDec::set-keying-material:
+                 * @material: Keying material exported from the DTLS connection
+                 * @is_client: Whether this side is the DTLS client
+                 *
+                 * Sets the default key from the keying material exported from a DTLS
+                 * connection with the `EXTRACTOR-dtls_srtp` label as per RFC 5764, using the
+                 * configured profile.
+                 *
+                 * Returns: %TRUE if the keying material was valid.
+                 */
+                glib::subclass::Signal::builder("set-keying-material")
+                    .param_types([gst::Buffer::static_type(), bool::static_type()])
+                    .return_type::<bool>()
+                    .action()
+                    .class_handler(|args| {
+                        let element = args[0].get::<super::RtpSrtpDec>().expect("signal arg");
+                        let material = args[1].get::<gst::Buffer>().expect("signal arg");
+                        let is_client = args[2].get::<bool>().expect("signal arg");
+
+                        Some(
+                            element
+                                .imp()
+                                .set_keying_material(&material, is_client)
+                                .to_value(),
+                        )
+                    })
+                    .build(),
+            ]
+        });
+
+        SIGNALS.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "profile" => settings.profile = value.get().expect("type checked upstream"),
+            "key" => settings.key = value.get().expect("type checked upstream"),
+            "replay-window-size" => {
+                settings.replay_window_size = value.get().expect("type checked upstream");
+                return;
+            }
+            _ => unimplemented!(),
+        }
+
+        if settings.master_key().is_none() && settings.key.is_some() {
+            gst::warning!(
+                CAT,
+                imp = self,
+                "Key length does not match profile {:?}",
+                settings.profile
+            );
+        }
+        drop(settings);
+
+        let mut state = self.state.lock().unwrap();
+        let key = self.default_key(&state);
+        state.update_default_key(key.as_ref());
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "profile" => self.settings.lock().unwrap().profile.to_value(),
+            "key" => self.settings.lock().unwrap().key.to_value(),
+            "replay-window-size" => self.settings.lock().unwrap().replay_window_size.to_value(),
+            "stats" => {
+                let state = self.state.lock().unwrap();
+
+                let s = gst::Structure::builder("application/x-rtp-srtpdec-stats")
+                    .field("received-packets", state.received)
+                    .field("auth-failures", state.auth_failures)
+                    .field("replayed-packets", state.replayed)
+                    .build();
+
+                s.to_value()
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let obj = self.obj();
+        obj.add_pad(&self.sinkpad).unwrap();
+        obj.add_pad(&self.srcpad).unwrap();
+    }
+}
+
+impl GstObjectImpl for RtpSrtpDec {}
+
+impl ElementImpl for RtpSrtpDec {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTP SRTP Decoder",
+                "Filter/Network/SRTP",
+                "Authenticates and decrypts SRTP and SRTCP packets (RFC 3711)",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let sink_caps = gst::Caps::builder_full()
+                .structure(gst::Structure::new_empty("application/x-srtp"))
+                .structure(gst::Structure::new_empty("application/x-srtcp"))
+                .build();
+            let src_caps = gst::Caps::builder_full()
+                .structure(gst::Structure::new_empty("application/x-rtp"))
+                .structure(gst::Structure::new_empty("application/x-rtcp"))
+                .build();
+
+            let srcpad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &src_caps,
+            )
+            .unwrap();
+
+            let sinkpad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &sink_caps,
+            )
+            .unwrap();
+
+            vec![srcpad_template, sinkpad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::ReadyToPaused {
+            *self.state.lock().unwrap() = State::default();
+        }
+
+        self.parent_change_state(transition)
+    }
+}
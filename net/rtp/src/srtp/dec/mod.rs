@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::glib;
+use gst::prelude::*;
+
+pub mod imp;
+
+glib::wrapper! {
+    pub struct RtpSrtpDec(ObjectSubclass<imp::RtpSrtpDec>) @extends gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "rtpsrtpdec",
+        gst::Rank::NONE,
+        RtpSrtpDec::static_type(),
+    )
+}
@@ -0,0 +1,527 @@
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * SECTION:element-rtpsrtpenc
+ * @see_also: rtpsrtpdec, rtpsend
+ *
+ * Encrypts and authenticates RTP and RTCP packets as per [RFC 3711][rfc-3711] (SRTP).
+ *
+ * Supported are the AES counter mode profiles with HMAC-SHA1 authentication from RFC 3711 and
+ * [RFC 6188][rfc-6188], and the AES-GCM profiles from [RFC 7714][rfc-7714].
+ *
+ * The element is placed after the `rtp_src` or `rtcp_src` pad of `rtpsend`. Packets are handled
+ * as RTCP if the input caps are `application/x-rtcp`, or if they are RTCP packets multiplexed with
+ * the RTP packets as per RFC 5761.
+ *
+ * The master key and master salt of an SSRC are requested with the #rtpsrtpenc::request-key
+ * signal when the first packet of the SSRC is seen. If no handler provides a key, the
+ * #rtpsrtpenc:key and #rtpsrtpenc:profile properties are used. For DTLS-SRTP, the keying
+ * material exported from the DTLS connection can be passed to the
+ * #rtpsrtpenc::set-keying-material action signal.
+ *
+ * The rollover counter of each SSRC is tracked based on the seqnums of its packets and each
+ * SSRC uses its own SRTCP index.
+ *
+ * [rfc-3711]: https://www.rfc-editor.org/rfc/rfc3711.html
+ * [rfc-6188]: https://www.rfc-editor.org/rfc/rfc6188.html
+ * [rfc-7714]: https://www.rfc-editor.org/rfc/rfc7714.html
+ *
+ * ## Example pipeline
+ *
+ * |[
+ * gst-launch-1.0 \
+ *  audiotestsrc ! opusenc ! rtpopuspay2 ! send.rtp_sink_0 \
+ *  rtpsend name=send rtp-id=example-rtp-id \
+ *  send.rtp_src_0 ! rtpsrtpenc key=000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d \
+ *    ! udpsink host=127.0.0.1 port=5004 \
+ *  send.rtcp_src_0 ! rtpsrtpenc key=000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d \
+ *    ! udpsink host=127.0.0.1 port=5005 sync=false async=false
+ * ]| This will send an Opus stream with SRTP and SRTCP.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::{glib, prelude::*, subclass::prelude::*};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use crate::srtp::{self, Context, MasterKey, Profile};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rtpsrtpenc",
+        gst::DebugColorFlags::empty(),
+        Some("RTP SRTP Encoder"),
+    )
+});
+
+const DEFAULT_PROFILE: Profile = Profile::Aes128CmHmacSha1_80;
+
+#[derive(Debug, Clone)]
+struct Settings {
+    profile: Profile,
+    key: Option<gst::Buffer>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            profile: DEFAULT_PROFILE,
+            key: None,
+        }
+    }
+}
+
+impl Settings {
+    fn master_key(&self) -> Option<MasterKey> {
+        let key = self.key.as_ref()?.map_readable().ok()?;
+        MasterKey::new(self.profile, &key)
+    }
+}
+
+#[derive(Debug)]
+struct Stream {
+    /// Key provided for this SSRC, or `None` if the default key is used
+    key: Option<MasterKey>,
+    context: Context,
+    /// Highest RTP packet index sent so far
+    max_index: Option<u64>,
+    next_rtcp_index: u32,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    is_rtcp: bool,
+    streams: HashMap<u32, Stream>,
+}
+
+impl State {
+    /// Updates all streams that use the default key after it changed
+    fn update_default_key(&mut self, key: Option<&MasterKey>) {
+        match key {
+            Some(key) => {
+                let context = || Context::new(key);
+                for stream in self.streams.values_mut().filter(|s| s.key.is_none()) {
+                    stream.context = context();
+                }
+            }
+            None => self.streams.retain(|_, stream| stream.key.is_some()),
+        }
+    }
+}
+
+pub struct RtpSrtpEnc {
+    srcpad: gst::Pad,
+    sinkpad: gst::Pad,
+    settings: Mutex<Settings>,
+    state: Mutex<State>,
+}
+
+impl RtpSrtpEnc {
+    /// Creates the crypto context for a new SSRC
+    fn ensure_stream(&self, ssrc: u32) -> Result<(), gst::FlowError> {
+        if self.state.lock().unwrap().streams.contains_key(&ssrc) {
+            return Ok(());
+        }
+
+        let key = self
+            .obj()
+            .emit_by_name::<Option<gst::Caps>>("request-key", &[&ssrc])
+            .and_then(|caps| {
+                let key = caps.structure(0).and_then(MasterKey::from_structure);
+                if key.is_none() {
+                    gst::warning!(CAT, imp = self, "Invalid key for SSRC {ssrc}: {caps:?}");
+                }
+                key
+            });
+
+        let context = match key
+            .clone()
+            .or_else(|| self.settings.lock().unwrap().master_key())
+        {
+            Some(master_key) => Context::new(&master_key),
+            None => {
+                gst::element_imp_error!(
+                    self,
+                    gst::LibraryError::Settings,
+                    ["No valid key for SSRC {ssrc}"]
+                );
+                return Err(gst::FlowError::NotNegotiated);
+            }
+        };
+        gst::debug!(CAT, imp = self, "New SSRC {ssrc} with {context:?}");
+
+        self.state
+            .lock()
+            .unwrap()
+            .streams
+            .entry(ssrc)
+            .or_insert(Stream {
+                key,
+                context,
+                max_index: None,
+                next_rtcp_index: 0,
+            });
+
+        Ok(())
+    }
+
+    fn sink_chain(
+        &self,
+        pad: &gst::Pad,
+        buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let map = buffer.map_readable().map_err(|_| {
+            gst::error!(CAT, obj = pad, "Failed to map buffer readable");
+            gst::FlowError::Error
+        })?;
+
+        let is_rtcp = self.state.lock().unwrap().is_rtcp || srtp::is_rtcp(&map);
+        let data = if is_rtcp {
+            let Some(ssrc) = srtp::parse_rtcp_ssrc(&map) else {
+                gst::warning!(CAT, obj = pad, "Dropping invalid RTCP packet");
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            self.ensure_stream(ssrc)?;
+
+            let mut state = self.state.lock().unwrap();
+            let Some(stream) = state.streams.get_mut(&ssrc) else {
+                // The key was removed in the meantime
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            let index = stream.next_rtcp_index;
+            stream.next_rtcp_index = (index + 1) & 0x7fff_ffff;
+            gst::trace!(
+                CAT,
+                imp = self,
+                "Protecting RTCP packet {index} of SSRC {ssrc}"
+            );
+
+            stream.context.protect_rtcp(&map, index)
+        } else {
+            let Some((seqnum, ssrc, header_len)) = srtp::parse_rtp_header(&map) else {
+                gst::warning!(CAT, obj = pad, "Dropping invalid RTP packet");
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            self.ensure_stream(ssrc)?;
+
+            let mut state = self.state.lock().unwrap();
+            let Some(stream) = state.streams.get_mut(&ssrc) else {
+                // The key was removed in the meantime
+                return Ok(gst::FlowSuccess::Ok);
+            };
+            let index = srtp::estimate_index(stream.max_index, seqnum).unwrap_or(seqnum as u64);
+            if stream.max_index.is_none_or(|max_index| index > max_index) {
+                stream.max_index = Some(index);
+            }
+            gst::trace!(
+                CAT,
+                imp = self,
+                "Protecting RTP packet {seqnum} of SSRC {ssrc} with index {index}"
+            );
+
+            Some(stream.context.protect_rtp(&map, header_len, ssrc, index))
+        };
+        drop(map);
+
+        let Some(data) = data else {
+            gst::warning!(CAT, imp = self, "Dropping invalid RTCP packet");
+            return Ok(gst::FlowSuccess::Ok);
+        };
+
+        let mut outbuf = gst::Buffer::from_mut_slice(data);
+        {
+            let outbuf_mut = outbuf.get_mut().unwrap();
+            let _ = buffer.copy_into(
+                outbuf_mut,
+                gst::BufferCopyFlags::FLAGS
+                    | gst::BufferCopyFlags::TIMESTAMPS
+                    | gst::BufferCopyFlags::META,
+                ..,
+            );
+        }
+
+        self.srcpad.push(outbuf)
+    }
+
+    fn sink_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        gst::log!(CAT, obj = pad, "Handling event {event:?}");
+
+        match event.view() {
+            gst::EventView::Caps(caps) => {
+                let mut caps = caps.caps_owned();
+                let is_rtcp = caps
+                    .structure(0)
+                    .is_some_and(|s| s.name() == "application/x-rtcp");
+                self.state.lock().unwrap().is_rtcp = is_rtcp;
+
+                {
+                    let caps = caps.make_mut();
+                    if let Some(s) = caps.structure_mut(0) {
+                        s.set_name(if is_rtcp {
+                            "application/x-srtcp"
+                        } else {
+                            "application/x-srtp"
+                        });
+                    }
+                }
+
+                self.srcpad.push_event(gst::event::Caps::new(&caps))
+            }
+            _ => gst::Pad::event_default(pad, Some(&*self.obj()), event),
+        }
+    }
+
+    fn set_keying_material(&self, material: &gst::Buffer, is_client: bool) -> bool {
+        let Ok(material) = material.map_readable() else {
+            return false;
+        };
+
+        let mut settings = self.settings.lock().unwrap();
+        // Packets are sent with the write key of our side of the DTLS connection
+        let Some(key) = MasterKey::from_keying_material(settings.profile, &material, is_client)
+        else {
+            gst::error!(CAT, imp = self, "Not enough keying material");
+            return false;
+        };
+        gst::debug!(
+            CAT,
+            imp = self,
+            "Using keying material as DTLS client {is_client}"
+        );
+
+        settings.key = Some(gst::Buffer::from_slice(key.key.clone()));
+        drop(settings);
+
+        self.state.lock().unwrap().update_default_key(Some(&key));
+
+        true
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RtpSrtpEnc {
+    const NAME: &'static str = "GstRtpSrtpEnc";
+    type Type = super::RtpSrtpEnc;
+    type ParentType = gst::Element;
+
+    fn with_class(klass: &Self::Class) -> Self {
+        let templ = klass.pad_template("sink").unwrap();
+        let sinkpad = gst::Pad::builder_from_template(&templ)
+            .chain_function(|pad, parent, buffer| {
+                Self::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::FlowError::Error),
+                    |this| this.sink_chain(pad, buffer),
+                )
+            })
+            .event_function(|pad, parent, event| {
+                Self::catch_panic_pad_function(parent, || false, |this| this.sink_event(pad, event))
+            })
+            .build();
+
+        let templ = klass.pad_template("src").unwrap();
+        let srcpad = gst::Pad::builder_from_template(&templ).build();
+
+        Self {
+            srcpad,
+            sinkpad,
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(State::default()),
+        }
+    }
+}
+
+impl ObjectImpl for RtpSrtpEnc {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecEnum::builder_with_default("profile", DEFAULT_PROFILE)
+                    .nick("Profile")
+                    .blurb("SRTP protection profile of the default key")
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecBoxed::builder::<gst::Buffer>("key")
+                    .nick("Key")
+                    .blurb("Default master key followed by the master salt")
+                    .mutable_playing()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn signals() -> &'static [glib::subclass::Signal] {
+        static SIGNALS: LazyLock<Vec<glib::subclass::Signal>> = LazyLock::new(|| {
+            vec![
+                /**
+                 * GstRtpSrtpEnc::request-key:
+                 * @ssrc: The SSRC
+                 *
+                 * Requests the key for a new SSRC as caps with `srtp-key`, `srtp-cipher` and
+                 * `srtp-auth` fields, as used by the `srtpenc` element. If no handler returns
+                 * caps, the default key from the properties is used.
+                 */
+                glib::subclass::Signal::builder("request-key")
+                    .param_types([u32::static_type()])
+                    .return_type::<Option<gst::Caps>>()
+                    .accumulator(|_hint, _acc, value| {
+                        // First signal handler wins
+                        std::ops::ControlFlow::Break(value.clone())
+                    })
+                    .build(),
+                /**
+                 * GstRtpSrtpEnc::remove-key:
+                 * @ssrc: The SSRC
+                 *
+                 * Removes the key and state of an SSRC. The key is requested again with the
+                 * next packet of the SSRC.
+                 */
+                glib::subclass::Signal::builder("remove-key")
+                    .param_types([u32::static_type()])
+                    .action()
+                    .class_handler(|args| {
+                        let element = args[0].get::<super::RtpSrtpEnc>().expect("signal arg");
+                        let ssrc = args[1].get::<u32>().expect("signal arg");
+                        let imp = element.imp();
+
+                        gst::debug!(CAT, imp = imp, "Removing key of SSRC {ssrc}");
+                        imp.state.lock().unwrap().streams.remove(&ssrc);
+
+                        None
+                    })
+                    .build(),
+                /**
+                 * GstRtpSrtpEnc::set-keying-material:
+                 * @material: Keying material exported from the DTLS connection
+                 * @is_client: Whether this side is the DTLS client
+                 *
+                 * Sets the default key from the keying material exported from a DTLS
+                 * connection with the `EXTRACTOR-dtls_srtp` label as per RFC 5764, using the
+                 * configured profile.
+                 *
+                 * Returns: %TRUE if the keying material was valid.
+                 */
+                glib::subclass::Signal::builder("set-keying-material")
+                    .param_types([gst::Buffer::static_type(), bool::static_type()])
+                    .return_type::<bool>()
+                    .action()
+                    .class_handler(|args| {
+                        let element = args[0].get::<super::RtpSrtpEnc>().expect("signal arg");
+                        let material = args[1].get::<gst::Buffer>().expect("signal arg");
+                        let is_client = args[2].get::<bool>().expect("signal arg");
+
+                        Some(
+                            element
+                                .imp()
+                                .set_keying_material(&material, is_client)
+                                .to_value(),
+                        )
+                    })
+                    .build(),
+            ]
+        });
+
+        SIGNALS.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "profile" => settings.profile = value.get().expect("type checked upstream"),
+            "key" => settings.key = value.get().expect("type checked upstream"),
+            _ => unimplemented!(),
+        }
+
+        let key = settings.master_key();
+        if key.is_none() && settings.key.is_some() {
+            gst::warning!(
+                CAT,
+                imp = self,
+                "Key length does not match profile {:?}",
+                settings.profile
+            );
+        }
+        drop(settings);
+
+        self.state.lock().unwrap().update_default_key(key.as_ref());
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "profile" => settings.profile.to_value(),
+            "key" => settings.key.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let obj = self.obj();
+        obj.add_pad(&self.sinkpad).unwrap();
+        obj.add_pad(&self.srcpad).unwrap();
+    }
+}
+
+impl GstObjectImpl for RtpSrtpEnc {}
+
+impl ElementImpl for RtpSrtpEnc {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTP SRTP Encoder",
+                "Filter/Network/SRTP",
+                "Encrypts and authenticates RTP and RTCP packets (RFC 3711)",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let sink_caps = gst::Caps::builder_full()
+                .structure(gst::Structure::new_empty("application/x-rtp"))
+                .structure(gst::Structure::new_empty("application/x-rtcp"))
+                .build();
+            let src_caps = gst::Caps::builder_full()
+                .structure(gst::Structure::new_empty("application/x-srtp"))
+                .structure(gst::Structure::new_empty("application/x-srtcp"))
+                .build();
+
+            let srcpad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &src_caps,
+            )
+            .unwrap();
+
+            let sinkpad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &sink_caps,
+            )
+            .unwrap();
+
+            vec![srcpad_template, sinkpad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::ReadyToPaused {
+            *self.state.lock().unwrap() = State::default();
+        }
+
+        self.parent_change_state(transition)
+    }
+}
@@ -0,0 +1,638 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! SRTP and SRTCP packet protection as described in RFC 3711, together with the AES-256 counter
+//! mode profiles from RFC 6188 and the AES-GCM profiles from RFC 7714. MKIs and key derivation
+//! rates other than zero are not supported.
+
+pub mod dec;
+pub mod enc;
+
+#[cfg(test)]
+mod tests;
+
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes_gcm::aead::AeadInPlace;
+use gst::glib;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, glib::Enum, Default)]
+#[enum_type(name = "GstRtpSrtpProfile")]
+#[repr(i32)]
+pub enum Profile {
+    #[default]
+    #[enum_value(
+        name = "AES-128 counter mode with 80 bit HMAC-SHA1 tag (SRTP_AES128_CM_HMAC_SHA1_80)",
+        nick = "aes-128-cm-hmac-sha1-80"
+    )]
+    Aes128CmHmacSha1_80,
+    #[enum_value(
+        name = "AES-128 counter mode with 32 bit HMAC-SHA1 tag (SRTP_AES128_CM_HMAC_SHA1_32)",
+        nick = "aes-128-cm-hmac-sha1-32"
+    )]
+    Aes128CmHmacSha1_32,
+    #[enum_value(
+        name = "AES-256 counter mode with 80 bit HMAC-SHA1 tag (SRTP_AES256_CM_SHA1_80)",
+        nick = "aes-256-cm-hmac-sha1-80"
+    )]
+    Aes256CmHmacSha1_80,
+    #[enum_value(
+        name = "AES-256 counter mode with 32 bit HMAC-SHA1 tag (SRTP_AES256_CM_SHA1_32)",
+        nick = "aes-256-cm-hmac-sha1-32"
+    )]
+    Aes256CmHmacSha1_32,
+    #[enum_value(
+        name = "AES-128 GCM (SRTP_AEAD_AES_128_GCM)",
+        nick = "aead-aes-128-gcm"
+    )]
+    AeadAes128Gcm,
+    #[enum_value(
+        name = "AES-256 GCM (SRTP_AEAD_AES_256_GCM)",
+        nick = "aead-aes-256-gcm"
+    )]
+    AeadAes256Gcm,
+}
+
+impl Profile {
+    pub fn key_len(self) -> usize {
+        match self {
+            Profile::Aes128CmHmacSha1_80
+            | Profile::Aes128CmHmacSha1_32
+            | Profile::AeadAes128Gcm => 16,
+            Profile::Aes256CmHmacSha1_80
+            | Profile::Aes256CmHmacSha1_32
+            | Profile::AeadAes256Gcm => 32,
+        }
+    }
+
+    pub fn salt_len(self) -> usize {
+        if self.is_aead() {
+            12
+        } else {
+            14
+        }
+    }
+
+    fn is_aead(self) -> bool {
+        matches!(self, Profile::AeadAes128Gcm | Profile::AeadAes256Gcm)
+    }
+
+    fn rtp_tag_len(self) -> usize {
+        match self {
+            Profile::Aes128CmHmacSha1_80 | Profile::Aes256CmHmacSha1_80 => 10,
+            Profile::Aes128CmHmacSha1_32 | Profile::Aes256CmHmacSha1_32 => 4,
+            Profile::AeadAes128Gcm | Profile::AeadAes256Gcm => 16,
+        }
+    }
+
+    fn rtcp_tag_len(self) -> usize {
+        // The short tags are only used for SRTP, SRTCP always uses 80 bit tags
+        if self.is_aead() {
+            16
+        } else {
+            10
+        }
+    }
+
+    /// Maps the `srtp-cipher` and `srtp-auth` caps fields as used by the `srtpenc` and `srtpdec`
+    /// elements to a profile
+    pub fn from_cipher_auth(cipher: &str, auth: &str) -> Option<Profile> {
+        match (cipher, auth) {
+            ("aes-128-icm", "hmac-sha1-80") => Some(Profile::Aes128CmHmacSha1_80),
+            ("aes-128-icm", "hmac-sha1-32") => Some(Profile::Aes128CmHmacSha1_32),
+            ("aes-256-icm", "hmac-sha1-80") => Some(Profile::Aes256CmHmacSha1_80),
+            ("aes-256-icm", "hmac-sha1-32") => Some(Profile::Aes256CmHmacSha1_32),
+            ("aes-128-gcm", _) => Some(Profile::AeadAes128Gcm),
+            ("aes-256-gcm", _) => Some(Profile::AeadAes256Gcm),
+            _ => None,
+        }
+    }
+}
+
+/// Master key and master salt of a crypto context
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MasterKey {
+    pub profile: Profile,
+    /// Master key followed by the master salt
+    pub key: Vec<u8>,
+}
+
+impl MasterKey {
+    pub fn new(profile: Profile, key: &[u8]) -> Option<Self> {
+        if key.len() != profile.key_len() + profile.salt_len() {
+            return None;
+        }
+
+        Some(MasterKey {
+            profile,
+            key: key.to_vec(),
+        })
+    }
+
+    /// Reads the key from the `srtp-key`, `srtp-cipher` and `srtp-auth` fields of a structure
+    pub fn from_structure(s: &gst::StructureRef) -> Option<Self> {
+        let key = s.get::<gst::Buffer>("srtp-key").ok()?;
+        let profile = Profile::from_cipher_auth(
+            s.get::<&str>("srtp-cipher").ok()?,
+            s.get::<&str>("srtp-auth").unwrap_or("null"),
+        )?;
+        let map = key.map_readable().ok()?;
+
+        MasterKey::new(profile, &map)
+    }
+
+    /// Extracts the master key of one side from the keying material exported from a DTLS
+    /// connection as described in RFC 5764 section 4.2
+    pub fn from_keying_material(profile: Profile, material: &[u8], client: bool) -> Option<Self> {
+        let key_len = profile.key_len();
+        let salt_len = profile.salt_len();
+        if material.len() < 2 * (key_len + salt_len) {
+            return None;
+        }
+
+        let (keys, salts) = material.split_at(2 * key_len);
+        let (key, salt) = if client {
+            (&keys[..key_len], &salts[..salt_len])
+        } else {
+            (&keys[key_len..], &salts[salt_len..2 * salt_len])
+        };
+
+        Some(MasterKey {
+            profile,
+            key: [key, salt].concat(),
+        })
+    }
+}
+
+enum Cipher {
+    Aes128(aes::Aes128),
+    Aes256(aes::Aes256),
+}
+
+impl Cipher {
+    fn new(key: &[u8]) -> Self {
+        match key.len() {
+            16 => Cipher::Aes128(aes::Aes128::new_from_slice(key).unwrap()),
+            32 => Cipher::Aes256(aes::Aes256::new_from_slice(key).unwrap()),
+            _ => unreachable!(),
+        }
+    }
+
+    fn encrypt_block(&self, block: u128) -> u128 {
+        let mut block = aes::Block::from(block.to_be_bytes());
+        match self {
+            Cipher::Aes128(cipher) => cipher.encrypt_block(&mut block),
+            Cipher::Aes256(cipher) => cipher.encrypt_block(&mut block),
+        }
+        u128::from_be_bytes(block.as_slice().try_into().unwrap())
+    }
+
+    /// Applies the AES counter mode keystream starting at `counter`
+    fn apply_ctr(&self, counter: u128, data: &mut [u8]) {
+        for (i, chunk) in data.chunks_mut(16).enumerate() {
+            let keystream = self
+                .encrypt_block(counter.wrapping_add(i as u128))
+                .to_be_bytes();
+            for (b, k) in chunk.iter_mut().zip(keystream.iter()) {
+                *b ^= k;
+            }
+        }
+    }
+}
+
+enum Gcm {
+    Aes128(aes_gcm::Aes128Gcm),
+    Aes256(aes_gcm::Aes256Gcm),
+}
+
+impl Gcm {
+    fn new(key: &[u8]) -> Self {
+        match key.len() {
+            16 => Gcm::Aes128(aes_gcm::Aes128Gcm::new_from_slice(key).unwrap()),
+            32 => Gcm::Aes256(aes_gcm::Aes256Gcm::new_from_slice(key).unwrap()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Encrypts `data` in place and returns the authentication tag
+    fn seal(&self, iv: &[u8; 12], aad: &[u8], data: &mut [u8]) -> [u8; 16] {
+        let nonce = aes_gcm::Nonce::from_slice(iv);
+        let tag = match self {
+            Gcm::Aes128(cipher) => cipher.encrypt_in_place_detached(nonce, aad, data),
+            Gcm::Aes256(cipher) => cipher.encrypt_in_place_detached(nonce, aad, data),
+        }
+        .unwrap();
+        tag.as_slice().try_into().unwrap()
+    }
+
+    /// Authenticates and decrypts `data` in place, or returns `false` if authentication failed
+    fn open(&self, iv: &[u8; 12], aad: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+        if tag.len() != 16 {
+            return false;
+        }
+        let nonce = aes_gcm::Nonce::from_slice(iv);
+        let tag = aes_gcm::Tag::from_slice(tag);
+        match self {
+            Gcm::Aes128(cipher) => cipher.decrypt_in_place_detached(nonce, aad, data, tag),
+            Gcm::Aes256(cipher) => cipher.decrypt_in_place_detached(nonce, aad, data, tag),
+        }
+        .is_ok()
+    }
+}
+
+/// Key derivation with the AES counter mode PRF and a key derivation rate of zero
+fn derive_key(master_cipher: &Cipher, master_salt: &[u8], label: u8, len: usize) -> Vec<u8> {
+    let mut x = [0u8; 16];
+    x[..master_salt.len()].copy_from_slice(master_salt);
+    x[7] ^= label;
+
+    let mut out = vec![0; len];
+    master_cipher.apply_ctr(u128::from_be_bytes(x), &mut out);
+    out
+}
+
+const LABEL_RTP_ENCRYPTION: u8 = 0;
+const LABEL_RTP_AUTH: u8 = 1;
+const LABEL_RTP_SALT: u8 = 2;
+const LABEL_RTCP_ENCRYPTION: u8 = 3;
+const LABEL_RTCP_AUTH: u8 = 4;
+const LABEL_RTCP_SALT: u8 = 5;
+
+/// Session keys for either SRTP or SRTCP
+struct SessionKeys {
+    cipher: Cipher,
+    /// Session salt, padded with zeroes
+    salt: u128,
+    /// HMAC-SHA1 state for the counter mode profiles
+    auth: Option<Hmac<Sha1>>,
+    /// AES-GCM state for the AEAD profiles
+    gcm: Option<Gcm>,
+}
+
+impl SessionKeys {
+    fn derive(master: &MasterKey, labels: [u8; 3]) -> Self {
+        let profile = master.profile;
+        let (master_key, master_salt) = master.key.split_at(profile.key_len());
+        let master_cipher = Cipher::new(master_key);
+        let derive = |label: u8, len: usize| derive_key(&master_cipher, master_salt, label, len);
+
+        let key = derive(labels[0], profile.key_len());
+        let salt = derive(labels[2], profile.salt_len());
+
+        if profile.is_aead() {
+            SessionKeys::new_aead(&key, &salt)
+        } else {
+            let mut keys = SessionKeys::new(&key, &salt, None);
+            keys.auth =
+                Some(<Hmac<Sha1> as KeyInit>::new_from_slice(&derive(labels[1], 20)).unwrap());
+            keys
+        }
+    }
+
+    fn new(key: &[u8], salt: &[u8], gcm: Option<Gcm>) -> Self {
+        let mut padded_salt = [0u8; 16];
+        padded_salt[..salt.len()].copy_from_slice(salt);
+
+        SessionKeys {
+            cipher: Cipher::new(key),
+            salt: u128::from_be_bytes(padded_salt),
+            auth: None,
+            gcm,
+        }
+    }
+
+    /// Session keys for the AEAD profiles from the session key and session salt
+    fn new_aead(key: &[u8], salt: &[u8]) -> Self {
+        SessionKeys::new(key, salt, Some(Gcm::new(key)))
+    }
+
+    fn hmac(&self, data: &[&[u8]]) -> Hmac<Sha1> {
+        let mut mac = self.auth.clone().unwrap();
+        for data in data {
+            mac.update(data);
+        }
+        mac
+    }
+
+    /// Counter mode IV from the session salt, SSRC and packet index
+    fn ctr_iv(&self, ssrc: u32, index: u64) -> u128 {
+        self.salt ^ ((ssrc as u128) << 64) ^ ((index as u128) << 16)
+    }
+
+    /// GCM IV from the session salt and the 96 bit SSRC and index value
+    fn gcm_iv(&self, iv: u128) -> [u8; 12] {
+        // The salt is stored in the upper 96 bits
+        ((iv << 32) ^ self.salt).to_be_bytes()[..12]
+            .try_into()
+            .unwrap()
+    }
+
+    fn gcm_seal(&self, iv: &[u8; 12], aad: &[u8], data: &mut [u8]) -> [u8; 16] {
+        self.gcm.as_ref().unwrap().seal(iv, aad, data)
+    }
+
+    fn gcm_open(&self, iv: &[u8; 12], aad: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+        self.gcm.as_ref().unwrap().open(iv, aad, data, tag)
+    }
+}
+
+/// Crypto context for the SRTP and SRTCP packets of one SSRC
+pub struct Context {
+    profile: Profile,
+    rtp: SessionKeys,
+    rtcp: SessionKeys,
+}
+
+impl std::fmt::Debug for Context {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Context")
+            .field("profile", &self.profile)
+            .finish()
+    }
+}
+
+/// Flag in the SRTCP index word signalling an encrypted packet
+const SRTCP_E_FLAG: u32 = 0x8000_0000;
+
+impl Context {
+    pub fn new(master: &MasterKey) -> Self {
+        Context {
+            profile: master.profile,
+            rtp: SessionKeys::derive(
+                master,
+                [LABEL_RTP_ENCRYPTION, LABEL_RTP_AUTH, LABEL_RTP_SALT],
+            ),
+            rtcp: SessionKeys::derive(
+                master,
+                [LABEL_RTCP_ENCRYPTION, LABEL_RTCP_AUTH, LABEL_RTCP_SALT],
+            ),
+        }
+    }
+
+    /// Encrypts and authenticates an RTP packet with the given header length and 48 bit index
+    pub fn protect_rtp(&self, packet: &[u8], header_len: usize, ssrc: u32, index: u64) -> Vec<u8> {
+        let mut out = Vec::with_capacity(packet.len() + self.profile.rtp_tag_len());
+        out.extend_from_slice(packet);
+
+        if self.profile.is_aead() {
+            let iv = ((ssrc as u128) << 48) | (index as u128 & 0xffff_ffff_ffff);
+            let iv = self.rtp.gcm_iv(iv);
+            let (header, payload) = out.split_at_mut(header_len);
+            let tag = self.rtp.gcm_seal(&iv, header, payload);
+            out.extend_from_slice(&tag);
+        } else {
+            let iv = self.rtp.ctr_iv(ssrc, index);
+            self.rtp.cipher.apply_ctr(iv, &mut out[header_len..]);
+            let roc = ((index >> 16) as u32).to_be_bytes();
+            let tag = self.rtp.hmac(&[&out, &roc]).finalize().into_bytes();
+            out.extend_from_slice(&tag[..self.profile.rtp_tag_len()]);
+        }
+
+        out
+    }
+
+    /// Authenticates and decrypts an SRTP packet, or returns `None` if authentication failed
+    pub fn unprotect_rtp(
+        &self,
+        packet: &[u8],
+        header_len: usize,
+        ssrc: u32,
+        index: u64,
+    ) -> Option<Vec<u8>> {
+        let tag_len = self.profile.rtp_tag_len();
+        if packet.len() < header_len + tag_len {
+            return None;
+        }
+        let (data, tag) = packet.split_at(packet.len() - tag_len);
+        let mut out = data.to_vec();
+
+        if self.profile.is_aead() {
+            let iv = ((ssrc as u128) << 48) | (index as u128 & 0xffff_ffff_ffff);
+            let iv = self.rtp.gcm_iv(iv);
+            let (header, payload) = out.split_at_mut(header_len);
+            if !self.rtp.gcm_open(&iv, header, payload, tag) {
+                return None;
+            }
+        } else {
+            let roc = ((index >> 16) as u32).to_be_bytes();
+            self.rtp
+                .hmac(&[data, &roc])
+                .verify_truncated_left(tag)
+                .ok()?;
+            let iv = self.rtp.ctr_iv(ssrc, index);
+            self.rtp.cipher.apply_ctr(iv, &mut out[header_len..]);
+        }
+
+        Some(out)
+    }
+
+    /// Encrypts and authenticates a compound RTCP packet with the given 31 bit SRTCP index
+    pub fn protect_rtcp(&self, packet: &[u8], index: u32) -> Option<Vec<u8>> {
+        if packet.len() < 8 {
+            return None;
+        }
+        let ssrc = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+        let index_word = (SRTCP_E_FLAG | (index & 0x7fff_ffff)).to_be_bytes();
+
+        let mut out = Vec::with_capacity(packet.len() + 4 + self.profile.rtcp_tag_len());
+        out.extend_from_slice(packet);
+
+        if self.profile.is_aead() {
+            let iv = ((ssrc as u128) << 48) | (index & 0x7fff_ffff) as u128;
+            let iv = self.rtcp.gcm_iv(iv);
+            let (header, payload) = out.split_at_mut(8);
+            let aad = [&*header, &index_word].concat();
+            let tag = self.rtcp.gcm_seal(&iv, &aad, payload);
+            out.extend_from_slice(&tag);
+            out.extend_from_slice(&index_word);
+        } else {
+            let iv = self.rtcp.ctr_iv(ssrc, (index & 0x7fff_ffff) as u64);
+            self.rtcp.cipher.apply_ctr(iv, &mut out[8..]);
+            out.extend_from_slice(&index_word);
+            let tag = self.rtcp.hmac(&[&out]).finalize().into_bytes();
+            out.extend_from_slice(&tag[..self.profile.rtcp_tag_len()]);
+        }
+
+        Some(out)
+    }
+
+    /// Reads the SRTCP index of an SRTCP packet without authenticating it
+    pub fn rtcp_index(&self, packet: &[u8]) -> Option<u32> {
+        let tag_len = self.profile.rtcp_tag_len();
+        if packet.len() < 8 + 4 + tag_len {
+            return None;
+        }
+
+        let pos = if self.profile.is_aead() {
+            packet.len() - 4
+        } else {
+            packet.len() - tag_len - 4
+        };
+        let index_word = u32::from_be_bytes(packet[pos..pos + 4].try_into().unwrap());
+
+        Some(index_word & 0x7fff_ffff)
+    }
+
+    /// Authenticates and decrypts an SRTCP packet, or returns `None` if authentication failed
+    pub fn unprotect_rtcp(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        let tag_len = self.profile.rtcp_tag_len();
+        if packet.len() < 8 + 4 + tag_len {
+            return None;
+        }
+        let ssrc = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+
+        if self.profile.is_aead() {
+            let (data, index_word) = packet.split_at(packet.len() - 4);
+            let (data, tag) = data.split_at(data.len() - tag_len);
+            let index_word = u32::from_be_bytes(index_word.try_into().unwrap());
+            let index = index_word & 0x7fff_ffff;
+            let iv = ((ssrc as u128) << 48) | index as u128;
+            let iv = self.rtcp.gcm_iv(iv);
+
+            let mut out = data.to_vec();
+            if index_word & SRTCP_E_FLAG != 0 {
+                let (header, payload) = out.split_at_mut(8);
+                let aad = [&*header, &index_word.to_be_bytes()].concat();
+                if !self.rtcp.gcm_open(&iv, &aad, payload, tag) {
+                    return None;
+                }
+            } else {
+                // Unencrypted packets are completely authenticated as additional data
+                let aad = [&*out, &index_word.to_be_bytes()].concat();
+                if !self.rtcp.gcm_open(&iv, &aad, &mut [], tag) {
+                    return None;
+                }
+            }
+
+            Some(out)
+        } else {
+            let (data, tag) = packet.split_at(packet.len() - tag_len);
+            self.rtcp.hmac(&[data]).verify_truncated_left(tag).ok()?;
+
+            let (data, index_word) = data.split_at(data.len() - 4);
+            let index_word = u32::from_be_bytes(index_word.try_into().unwrap());
+            let mut out = data.to_vec();
+            if index_word & SRTCP_E_FLAG != 0 {
+                let iv = self.rtcp.ctr_iv(ssrc, (index_word & 0x7fff_ffff) as u64);
+                self.rtcp.cipher.apply_ctr(iv, &mut out[8..]);
+            }
+
+            Some(out)
+        }
+    }
+}
+
+/// Whether a packet received on an RTP stream is an RTCP packet multiplexed as per RFC 5761
+pub fn is_rtcp(packet: &[u8]) -> bool {
+    packet.len() >= 2 && (192..=223).contains(&packet[1])
+}
+
+/// Parses the seqnum, SSRC and header length of an (S)RTP packet.
+///
+/// The padding of SRTP packets is encrypted, so only the header is looked at.
+pub fn parse_rtp_header(packet: &[u8]) -> Option<(u16, u32, usize)> {
+    if packet.len() < 12 || packet[0] >> 6 != 2 {
+        return None;
+    }
+
+    let seqnum = u16::from_be_bytes([packet[2], packet[3]]);
+    let ssrc = u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]);
+    let mut header_len = 12 + 4 * (packet[0] & 0x0f) as usize;
+    if packet[0] & 0x10 != 0 {
+        let ext = packet.get(header_len..header_len + 4)?;
+        header_len += 4 + 4 * u16::from_be_bytes([ext[2], ext[3]]) as usize;
+    }
+    if header_len > packet.len() {
+        return None;
+    }
+
+    Some((seqnum, ssrc, header_len))
+}
+
+/// Parses the SSRC of the sender of an (S)RTCP packet
+pub fn parse_rtcp_ssrc(packet: &[u8]) -> Option<u32> {
+    if packet.len() < 8 || packet[0] >> 6 != 2 {
+        return None;
+    }
+
+    Some(u32::from_be_bytes([
+        packet[4], packet[5], packet[6], packet[7],
+    ]))
+}
+
+/// Guesses the 48 bit packet index, i.e. the rollover counter and the seqnum, of a packet from
+/// the highest index seen so far as described in RFC 3711 section 3.3.1
+pub fn estimate_index(max_index: Option<u64>, seqnum: u16) -> Option<u64> {
+    let Some(max_index) = max_index else {
+        return Some(seqnum as u64);
+    };
+
+    let distance = seqnum.wrapping_sub(max_index as u16) as i16 as i64;
+    u64::try_from(max_index as i64 + distance).ok()
+}
+
+/// Sliding window of recently received packet indices for replay protection
+#[derive(Debug)]
+pub struct ReplayWindow {
+    size: u64,
+    max_index: Option<u64>,
+    bitmap: Vec<u64>,
+}
+
+impl ReplayWindow {
+    /// Creates a window for the given number of packets, rounded up to a multiple of 64
+    pub fn new(size: u32) -> Self {
+        let words = (size as usize).div_ceil(64).max(1);
+        ReplayWindow {
+            size: words as u64 * 64,
+            max_index: None,
+            bitmap: vec![0; words],
+        }
+    }
+
+    pub fn max_index(&self) -> Option<u64> {
+        self.max_index
+    }
+
+    fn bit(&self, index: u64) -> (usize, u64) {
+        let pos = index % self.size;
+        ((pos / 64) as usize, 1 << (pos % 64))
+    }
+
+    /// Whether a packet with this index was not received yet and is still inside the window
+    pub fn check(&self, index: u64) -> bool {
+        let Some(max_index) = self.max_index else {
+            return true;
+        };
+        if index > max_index {
+            return true;
+        }
+        if max_index - index >= self.size {
+            return false;
+        }
+
+        let (word, mask) = self.bit(index);
+        self.bitmap[word] & mask == 0
+    }
+
+    /// Marks a packet as received after it was successfully authenticated
+    pub fn update(&mut self, index: u64) {
+        match self.max_index {
+            Some(max_index) if index <= max_index => (),
+            Some(max_index) if index - max_index < self.size => {
+                for i in max_index + 1..=index {
+                    let (word, mask) = self.bit(i);
+                    self.bitmap[word] &= !mask;
+                }
+                self.max_index = Some(index);
+            }
+            _ => {
+                self.bitmap.fill(0);
+                self.max_index = Some(index);
+            }
+        }
+
+        let (word, mask) = self.bit(index);
+        self.bitmap[word] |= mask;
+    }
+}
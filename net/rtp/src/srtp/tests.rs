@@ -0,0 +1,398 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::*;
+use crate::fec::tests::generate_packet;
+use gst::prelude::*;
+use gst_check::Harness;
+
+fn init() {
+    use std::sync::Once;
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        gst::init().unwrap();
+        crate::plugin_register_static().expect("rtpsrtp test");
+    });
+}
+
+const PROFILES: [Profile; 6] = [
+    Profile::Aes128CmHmacSha1_80,
+    Profile::Aes128CmHmacSha1_32,
+    Profile::Aes256CmHmacSha1_80,
+    Profile::Aes256CmHmacSha1_32,
+    Profile::AeadAes128Gcm,
+    Profile::AeadAes256Gcm,
+];
+
+fn master_key(profile: Profile) -> MasterKey {
+    let key = (0..profile.key_len() + profile.salt_len())
+        .map(|i| i as u8)
+        .collect::<Vec<_>>();
+    MasterKey::new(profile, &key).unwrap()
+}
+
+fn generate_rtcp_packet(ssrc: u32) -> Vec<u8> {
+    // Receiver report without report blocks followed by a BYE
+    let mut packet = vec![0x80, 201, 0, 1];
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    packet.extend_from_slice(&[0x81, 203, 0, 1]);
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    packet
+}
+
+#[test]
+fn key_derivation() {
+    // RFC 3711 appendix B.3
+    let master_key = hex::decode("E1F97A0D3E018BE0D64FA32C06DE4139").unwrap();
+    let master_salt = hex::decode("0EC675AD498AFEEBB6960B3AABE6").unwrap();
+    let cipher = Cipher::new(&master_key);
+
+    assert_eq!(
+        derive_key(&cipher, &master_salt, LABEL_RTP_ENCRYPTION, 16),
+        hex::decode("C61E7A93744F39EE10734AFE3FF7A087").unwrap()
+    );
+    assert_eq!(
+        derive_key(&cipher, &master_salt, LABEL_RTP_SALT, 14),
+        hex::decode("30CBBC08863D8C85D49DB34A9AE1").unwrap()
+    );
+    assert_eq!(
+        derive_key(&cipher, &master_salt, LABEL_RTP_AUTH, 20),
+        hex::decode("CEBE321F6FF7716B6FD4AB49AF256A156D38BAA4").unwrap()
+    );
+}
+
+#[test]
+fn aes_cm_keystream() {
+    // RFC 3711 appendix B.2
+    let cipher = Cipher::new(&hex::decode("2B7E151628AED2A6ABF7158809CF4F3C").unwrap());
+    let iv = u128::from_be_bytes(
+        hex::decode("F0F1F2F3F4F5F6F7F8F9FAFBFCFD0000")
+            .unwrap()
+            .try_into()
+            .unwrap(),
+    );
+
+    let mut keystream = [0u8; 48];
+    cipher.apply_ctr(iv, &mut keystream);
+    assert_eq!(
+        keystream.as_slice(),
+        hex::decode(concat!(
+            "E03EAD0935C95E80E166B16DD92B4EB4",
+            "D23513162B02D0F72A43A2FE4A5F97AB",
+            "41E95B3BB0A2E8DD477901E4FCA894C0"
+        ))
+        .unwrap()
+    );
+}
+
+/// Context for the AEAD_AES_128_GCM test vectors of RFC 7714, which use the session key and
+/// session salt directly
+fn rfc7714_context() -> Context {
+    let key = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+    let salt = hex::decode("517569642070726f2071756f").unwrap();
+
+    Context {
+        profile: Profile::AeadAes128Gcm,
+        rtp: SessionKeys::new_aead(&key, &salt),
+        rtcp: SessionKeys::new_aead(&key, &salt),
+    }
+}
+
+#[test]
+fn aead_rtp() {
+    // RFC 7714 section 16.1.1
+    let context = rfc7714_context();
+    let packet = hex::decode(concat!(
+        "8040f17b8041f8d35501a0b2",
+        "47616c6c696120657374206f6d6e6973",
+        "2064697669736120696e207061727465",
+        "732074726573"
+    ))
+    .unwrap();
+    let protected = hex::decode(concat!(
+        "8040f17b8041f8d35501a0b2",
+        "f24de3a3fb34de6cacba861c9d7e4bca",
+        "be633bd50d294e6f42a5f47a51c7d19b",
+        "36de3adf8833",
+        "899d7f27beb16a9152cf765ee4390cce"
+    ))
+    .unwrap();
+    let (_, ssrc, header_len) = parse_rtp_header(&packet).unwrap();
+    let index = 0xf17b;
+
+    assert_eq!(
+        context.protect_rtp(&packet, header_len, ssrc, index),
+        protected
+    );
+    assert_eq!(
+        context.unprotect_rtp(&protected, header_len, ssrc, index),
+        Some(packet)
+    );
+
+    let mut modified = protected.clone();
+    modified[protected.len() - 1] ^= 1;
+    assert_eq!(
+        context.unprotect_rtp(&modified, header_len, ssrc, index),
+        None
+    );
+}
+
+#[test]
+fn aead_rtcp() {
+    // RFC 7714 section 17.1.1
+    let context = rfc7714_context();
+    let packet = hex::decode(concat!(
+        "81c8000d4d617273",
+        "4e5450314e545032525450200000042a",
+        "0000e9304c756e61deadbeefdeadbeef",
+        "deadbeefdeadbeefdeadbeef"
+    ))
+    .unwrap();
+    let protected = hex::decode(concat!(
+        "81c8000d4d617273",
+        "63e94885dcdab67ca727d7662f6b7e99",
+        "7ff5c0f76c06f32dc676a5f1730d6fda",
+        "4ce09b4686303ded0bb9275b",
+        "c84aa45896cf4d2fc5abf87245d9eade",
+        "800005d4"
+    ))
+    .unwrap();
+
+    assert_eq!(
+        context.protect_rtcp(&packet, 0x5d4),
+        Some(protected.clone())
+    );
+    assert_eq!(context.rtcp_index(&protected), Some(0x5d4));
+    assert_eq!(context.unprotect_rtcp(&protected), Some(packet));
+
+    let mut modified = protected.clone();
+    modified[8] ^= 1;
+    assert_eq!(context.unprotect_rtcp(&modified), None);
+}
+
+#[test]
+fn protect_unprotect_rtp() {
+    for profile in PROFILES {
+        let context = Context::new(&master_key(profile));
+        let packet = generate_packet(65535, 1000, 100);
+        let (_, ssrc, header_len) = parse_rtp_header(&packet).unwrap();
+        let index = (1 << 16) + 65535;
+
+        let protected = context.protect_rtp(&packet, header_len, ssrc, index);
+        assert_eq!(protected.len(), packet.len() + profile.rtp_tag_len());
+        assert_eq!(protected[..header_len], packet[..header_len]);
+        assert_ne!(protected[header_len..packet.len()], packet[header_len..]);
+
+        assert_eq!(
+            context.unprotect_rtp(&protected, header_len, ssrc, index),
+            Some(packet.clone()),
+            "{profile:?}"
+        );
+
+        // A wrong rollover counter or modified packets fail authentication
+        assert_eq!(
+            context.unprotect_rtp(&protected, header_len, ssrc, 65535),
+            None
+        );
+        let mut modified = protected.clone();
+        modified[header_len] ^= 1;
+        assert_eq!(
+            context.unprotect_rtp(&modified, header_len, ssrc, index),
+            None
+        );
+
+        // A different key fails authentication
+        let other = Context::new(
+            &MasterKey::new(profile, &vec![0xff; master_key(profile).key.len()]).unwrap(),
+        );
+        assert_eq!(
+            other.unprotect_rtp(&protected, header_len, ssrc, index),
+            None
+        );
+    }
+}
+
+#[test]
+fn protect_unprotect_rtcp() {
+    for profile in PROFILES {
+        let context = Context::new(&master_key(profile));
+        let packet = generate_rtcp_packet(0x1234);
+
+        let protected = context.protect_rtcp(&packet, 5).unwrap();
+        assert_eq!(protected.len(), packet.len() + 4 + profile.rtcp_tag_len());
+        assert_eq!(protected[..8], packet[..8]);
+        assert_ne!(protected[8..packet.len()], packet[8..]);
+        assert!(is_rtcp(&protected));
+
+        assert_eq!(context.rtcp_index(&protected), Some(5));
+        assert_eq!(
+            context.unprotect_rtcp(&protected),
+            Some(packet.clone()),
+            "{profile:?}"
+        );
+
+        let mut modified = protected.clone();
+        modified[10] ^= 1;
+        assert_eq!(context.unprotect_rtcp(&modified), None);
+    }
+}
+
+#[test]
+fn keying_material() {
+    let profile = Profile::Aes128CmHmacSha1_80;
+    let material = (0..60).collect::<Vec<u8>>();
+
+    let client = MasterKey::from_keying_material(profile, &material, true).unwrap();
+    let server = MasterKey::from_keying_material(profile, &material, false).unwrap();
+    assert_eq!(client.key, [&material[0..16], &material[32..46]].concat());
+    assert_eq!(server.key, [&material[16..32], &material[46..60]].concat());
+
+    assert!(MasterKey::from_keying_material(profile, &material[..59], true).is_none());
+}
+
+#[test]
+fn index_estimation() {
+    assert_eq!(estimate_index(None, 65000), Some(65000));
+    assert_eq!(estimate_index(Some(65000), 65100), Some(65100));
+    // Seqnum wraparound
+    assert_eq!(estimate_index(Some(65500), 10), Some(65536 + 10));
+    // Reordered packet from before the wraparound
+    assert_eq!(estimate_index(Some(65536 + 10), 65500), Some(65500));
+    // Can't be before the first packet
+    assert_eq!(estimate_index(Some(10), 65500), None);
+}
+
+#[test]
+fn replay_window() {
+    let mut window = ReplayWindow::new(100);
+    assert!(window.check(1000));
+    window.update(1000);
+    assert!(!window.check(1000));
+
+    // Reordered packets inside the window are accepted once
+    assert!(window.check(999));
+    window.update(999);
+    assert!(!window.check(999));
+    assert!(window.check(998));
+
+    // The window is rounded up to 128 packets
+    window.update(1127);
+    assert!(!window.check(1000));
+    assert!(window.check(1001));
+    assert!(!window.check(999));
+    assert!(!window.check(1127));
+
+    // Jumps forward by more than the window reset it
+    window.update(5000);
+    assert!(window.check(4999));
+    assert!(!window.check(1127));
+}
+
+#[test]
+fn encrypt_decrypt() {
+    init();
+
+    let key = gst::Buffer::from_slice(master_key(Profile::default()).key);
+
+    let mut enc = Harness::new("rtpsrtpenc");
+    enc.element().unwrap().set_property("key", &key);
+    enc.set_src_caps(
+        gst::Caps::builder("application/x-rtp")
+            .field("media", "audio")
+            .field("payload", 96)
+            .field("clock-rate", 48000)
+            .build(),
+    );
+    enc.play();
+
+    let packets = (0..3u16)
+        .map(|seqnum| generate_packet(65534u16.wrapping_add(seqnum), 1000, 20))
+        .collect::<Vec<_>>();
+    let mut protected = Vec::new();
+    for packet in &packets {
+        let outbuf = enc
+            .push_and_pull(gst::Buffer::from_slice(packet.clone()))
+            .unwrap();
+        protected.push(outbuf.map_readable().unwrap().to_vec());
+    }
+    let caps = enc.sinkpad().unwrap().current_caps().unwrap();
+    assert_eq!(caps.structure(0).unwrap().name(), "application/x-srtp");
+
+    let mut dec = Harness::new("rtpsrtpdec");
+    dec.element().unwrap().set_property("key", &key);
+    dec.set_src_caps(caps);
+    dec.play();
+
+    for (packet, protected) in packets.iter().zip(&protected) {
+        let outbuf = dec
+            .push_and_pull(gst::Buffer::from_slice(protected.clone()))
+            .unwrap();
+        assert_eq!(outbuf.map_readable().unwrap().as_slice(), packet.as_slice());
+    }
+    let caps = dec.sinkpad().unwrap().current_caps().unwrap();
+    assert_eq!(caps.structure(0).unwrap().name(), "application/x-rtp");
+
+    // Replayed and modified packets are dropped
+    dec.push(gst::Buffer::from_slice(protected[1].clone()))
+        .unwrap();
+    let mut modified = generate_packet(1, 2000, 20);
+    let len = modified.len();
+    modified.extend_from_slice(&[0; 10]);
+    modified[len - 1] ^= 1;
+    dec.push(gst::Buffer::from_slice(modified)).unwrap();
+    assert_eq!(dec.buffers_in_queue(), 0);
+
+    let stats = dec.element().unwrap().property::<gst::Structure>("stats");
+    assert_eq!(stats.get::<u64>("received-packets"), Ok(3));
+    assert_eq!(stats.get::<u64>("replayed-packets"), Ok(1));
+    assert_eq!(stats.get::<u64>("auth-failures"), Ok(1));
+}
+
+#[test]
+fn request_key() {
+    init();
+
+    let profile = Profile::AeadAes128Gcm;
+    let key = gst::Buffer::from_slice(master_key(profile).key);
+    let key_caps = gst::Caps::builder("application/x-srtp")
+        .field("srtp-key", &key)
+        .field("srtp-cipher", "aes-128-gcm")
+        .field("srtp-auth", "null")
+        .build();
+
+    let mut enc = Harness::new("rtpsrtpenc");
+    enc.element()
+        .unwrap()
+        .connect("request-key", false, move |args| {
+            assert_eq!(args[1].get::<u32>(), Ok(0x1234));
+            Some(key_caps.to_value())
+        });
+    enc.set_src_caps(gst::Caps::builder("application/x-rtcp").build());
+    enc.play();
+
+    let packet = generate_rtcp_packet(0x1234);
+    let outbuf = enc
+        .push_and_pull(gst::Buffer::from_slice(packet.clone()))
+        .unwrap();
+    let caps = enc.sinkpad().unwrap().current_caps().unwrap();
+    assert_eq!(caps.structure(0).unwrap().name(), "application/x-srtcp");
+
+    // The decoder takes the key from the caps
+    let mut dec = Harness::new("rtpsrtpdec");
+    dec.set_src_caps(
+        gst::Caps::builder("application/x-srtcp")
+            .field("srtp-key", &key)
+            .field("srtp-cipher", "aes-128-gcm")
+            .field("srtp-auth", "null")
+            .build(),
+    );
+    dec.play();
+
+    let outbuf = dec.push_and_pull(outbuf).unwrap();
+    assert_eq!(outbuf.map_readable().unwrap().as_slice(), packet.as_slice());
+
+    let caps = dec.sinkpad().unwrap().current_caps().unwrap();
+    let s = caps.structure(0).unwrap();
+    assert_eq!(s.name(), "application/x-rtcp");
+    assert!(!s.has_field("srtp-key"));
+}
@@ -0,0 +1,423 @@
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * SECTION:element-rtpulpfecdec2
+ * @see_also: rtpulpfecenc2, rtpreddec2, rtpflexfecdec, rtprecv
+ *
+ * Restores lost RTP packets from ULPFEC packets as per [RFC 5109][rfc-5109] and removes the
+ * FEC packets from the stream.
+ *
+ * The FEC packets are expected in the same stream as the media packets, with the payload type
+ * configured by #rtpulpfecdec2:pt. As both share the seqnum space, a gap in the seqnums is
+ * either a lost media packet or a lost FEC packet. The element is placed after a `src` pad of
+ * `rtprecv`, which forwards the packets in order, and after `rtpreddec2` if the stream is
+ * encapsulated in RED.
+ *
+ * Because FEC packets are sent after the packets they protect, packets following a gap are held
+ * back until the missing packet is restored, or until enough packets arrived to be sure that no
+ * FEC packet protecting the missing packet will follow. This keeps the output in order.
+ *
+ * Statistics about the received, restored and lost packets are provided with
+ * #rtpulpfecdec2:stats.
+ *
+ * [rfc-5109]: https://www.rfc-editor.org/rfc/rfc5109.html
+ *
+ * ## Example pipeline
+ *
+ * |[
+ * gst-launch-1.0 \
+ *  udpsrc port=5004 caps='application/x-rtp, media=video, clock-rate=90000, encoding-name=RED, payload=122' \
+ *  ! recv.rtp_sink_0 \
+ *  rtprecv name=recv rtp-id=example-rtp-id latency=200 \
+ *  ! rtpreddec2 pt=122 ! rtpulpfecdec2 pt=123 ! rtpvp8depay2 ! vp8dec ! videoconvert ! autovideosink
+ * ]| This will receive a VP8 stream with ULPFEC packets encapsulated in RED.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::{glib, prelude::*, subclass::prelude::*};
+use rtp_types::RtpPacket;
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, Mutex};
+
+use crate::fec::{Recovery, Stats};
+use crate::ulpfec;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rtpulpfecdec2",
+        gst::DebugColorFlags::empty(),
+        Some("RTP ULPFEC Decoder"),
+    )
+});
+
+const DEFAULT_PT: u32 = 123;
+
+#[derive(Debug, Clone)]
+struct Settings {
+    pt: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { pt: DEFAULT_PT }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    recovery: Option<Recovery>,
+    /// Statistics of previous SSRCs
+    previous_stats: Stats,
+    /// Media packets waiting to be forwarded in order, by extended seqnum
+    pending: BTreeMap<u64, gst::Buffer>,
+    /// Extended seqnum of the next packet to forward
+    next_seqnum: Option<u64>,
+    /// Maximum distance between a FEC packet and the first packet it protects
+    max_fec_distance: u64,
+}
+
+impl State {
+    fn stats(&self) -> Stats {
+        let current = self
+            .recovery
+            .as_ref()
+            .map(|recovery| recovery.stats())
+            .unwrap_or_default();
+
+        Stats {
+            received: self.previous_stats.received + current.received,
+            fec_received: self.previous_stats.fec_received + current.fec_received,
+            recovered: self.previous_stats.recovered + current.recovered,
+            lost: self.previous_stats.lost + current.lost,
+        }
+    }
+
+    /// Takes all pending packets that can be forwarded now
+    fn drain_pending(&mut self, all: bool) -> Vec<gst::Buffer> {
+        let mut buffers = Vec::new();
+
+        while let Some(entry) = self.pending.first_entry() {
+            let ext_seqnum = *entry.key();
+            let next_seqnum = *self.next_seqnum.get_or_insert(ext_seqnum);
+
+            if !all && ext_seqnum > next_seqnum {
+                let Some(recovery) = self.recovery.as_ref() else {
+                    break;
+                };
+                let max_seqnum = recovery.max_seqnum().unwrap_or(ext_seqnum);
+
+                // Missing packets can still be restored as long as FEC packets protecting them
+                // might arrive
+                let can_forward = (next_seqnum..ext_seqnum).all(|seqnum| {
+                    recovery.has_seqnum(seqnum) || seqnum + self.max_fec_distance < max_seqnum
+                });
+                if !can_forward {
+                    break;
+                }
+            }
+
+            buffers.push(entry.remove());
+            self.next_seqnum = Some(next_seqnum.max(ext_seqnum + 1));
+        }
+
+        buffers
+    }
+}
+
+pub struct RtpUlpFecDec {
+    srcpad: gst::Pad,
+    sinkpad: gst::Pad,
+    settings: Mutex<Settings>,
+    state: Mutex<State>,
+}
+
+impl RtpUlpFecDec {
+    fn sink_chain(
+        &self,
+        pad: &gst::Pad,
+        buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let settings = self.settings.lock().unwrap().clone();
+
+        let map = buffer.map_readable().map_err(|_| {
+            gst::error!(CAT, obj = pad, "Failed to map buffer readable");
+            gst::FlowError::Error
+        })?;
+        let rtp = match RtpPacket::parse(&map) {
+            Ok(rtp) => rtp,
+            Err(err) => {
+                gst::warning!(CAT, obj = pad, "Dropping invalid RTP packet: {err:?}");
+                return Ok(gst::FlowSuccess::Ok);
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let mut outbufs = Vec::new();
+
+        if state
+            .recovery
+            .as_ref()
+            .is_none_or(|recovery| recovery.ssrc() != rtp.ssrc())
+        {
+            gst::debug!(CAT, imp = self, "New SSRC {}", rtp.ssrc());
+            outbufs.extend(state.drain_pending(true));
+            state.previous_stats = state.stats();
+            state.recovery = Some(Recovery::new(rtp.ssrc()));
+            state.next_seqnum = None;
+            state.max_fec_distance = 0;
+        }
+
+        let seqnum = rtp.sequence_number();
+        let recovery = state.recovery.as_mut().unwrap();
+        let is_fec = rtp.payload_type() as u32 == settings.pt;
+
+        let media_seqnum = if is_fec {
+            recovery.add_non_media(seqnum);
+
+            match ulpfec::parse_payload(rtp.payload()) {
+                Some((seqnums, parity)) => {
+                    gst::trace!(
+                        CAT,
+                        imp = self,
+                        "FEC packet {seqnum} protecting {seqnums:?}"
+                    );
+                    let distance = seqnum.wrapping_sub(seqnums[0]) as u64;
+                    recovery.add_fec(&seqnums, parity);
+                    state.max_fec_distance = state.max_fec_distance.max(distance);
+                }
+                None => {
+                    gst::warning!(CAT, imp = self, "Invalid FEC packet {seqnum}");
+                }
+            }
+
+            None
+        } else {
+            let Some(ext_seqnum) = recovery.add_packet(seqnum, &map) else {
+                gst::debug!(CAT, imp = self, "Dropping duplicate packet {seqnum}");
+                return Ok(gst::FlowSuccess::Ok);
+            };
+
+            Some(ext_seqnum)
+        };
+
+        let recovery = state.recovery.as_mut().unwrap();
+        for (ext_seqnum, data) in recovery.recover() {
+            gst::debug!(CAT, imp = self, "Restored packet {}", ext_seqnum as u16);
+
+            let mut outbuf = gst::Buffer::from_mut_slice(data);
+            {
+                let outbuf = outbuf.get_mut().unwrap();
+                outbuf.set_pts(buffer.pts());
+                outbuf.set_dts(buffer.dts());
+            }
+            state.pending.insert(ext_seqnum, outbuf);
+        }
+        drop(map);
+
+        if let Some(ext_seqnum) = media_seqnum {
+            state.pending.insert(ext_seqnum, buffer);
+        }
+
+        outbufs.extend(state.drain_pending(false));
+        drop(state);
+
+        for buffer in outbufs {
+            self.srcpad.push(buffer)?;
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn sink_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        gst::log!(CAT, obj = pad, "Handling event {event:?}");
+
+        match event.view() {
+            gst::EventView::Caps(caps) => {
+                let pt = self.settings.lock().unwrap().pt;
+
+                // Caps for the FEC packets, e.g. from rtpreddec2, are not forwarded
+                let caps_pt = caps
+                    .caps()
+                    .structure(0)
+                    .and_then(|s| s.get::<i32>("payload").ok());
+                if caps_pt == Some(pt as i32) {
+                    return true;
+                }
+
+                gst::Pad::event_default(pad, Some(&*self.obj()), event)
+            }
+            gst::EventView::Eos(_) => {
+                let buffers = self.state.lock().unwrap().drain_pending(true);
+                for buffer in buffers {
+                    if self.srcpad.push(buffer).is_err() {
+                        break;
+                    }
+                }
+
+                gst::Pad::event_default(pad, Some(&*self.obj()), event)
+            }
+            gst::EventView::FlushStop(_) => {
+                let mut state = self.state.lock().unwrap();
+                let stats = state.stats();
+                *state = State {
+                    previous_stats: stats,
+                    ..State::default()
+                };
+                drop(state);
+
+                gst::Pad::event_default(pad, Some(&*self.obj()), event)
+            }
+            _ => gst::Pad::event_default(pad, Some(&*self.obj()), event),
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RtpUlpFecDec {
+    const NAME: &'static str = "GstRtpUlpFecDec2";
+    type Type = super::RtpUlpFecDec;
+    type ParentType = gst::Element;
+
+    fn with_class(klass: &Self::Class) -> Self {
+        let templ = klass.pad_template("sink").unwrap();
+        let sinkpad = gst::Pad::builder_from_template(&templ)
+            .chain_function(|pad, parent, buffer| {
+                Self::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::FlowError::Error),
+                    |this| this.sink_chain(pad, buffer),
+                )
+            })
+            .event_function(|pad, parent, event| {
+                Self::catch_panic_pad_function(parent, || false, |this| this.sink_event(pad, event))
+            })
+            .build();
+
+        let templ = klass.pad_template("src").unwrap();
+        let srcpad = gst::Pad::builder_from_template(&templ).build();
+
+        Self {
+            srcpad,
+            sinkpad,
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(State::default()),
+        }
+    }
+}
+
+impl ObjectImpl for RtpUlpFecDec {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecUInt::builder("pt")
+                    .nick("Payload Type")
+                    .blurb("Payload type of the FEC packets")
+                    .maximum(0x7f)
+                    .default_value(DEFAULT_PT)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecBoxed::builder::<gst::Structure>("stats")
+                    .nick("Statistics")
+                    .blurb("Various statistics")
+                    .read_only()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        match pspec.name() {
+            "pt" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.pt = value.get().expect("type checked upstream");
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "pt" => {
+                let settings = self.settings.lock().unwrap();
+                settings.pt.to_value()
+            }
+            "stats" => {
+                let stats = self.state.lock().unwrap().stats();
+
+                let s = gst::Structure::builder("application/x-rtp-ulpfecdec-stats")
+                    .field("received-packets", stats.received)
+                    .field("received-fec-packets", stats.fec_received)
+                    .field("recovered-packets", stats.recovered)
+                    .field("lost-packets", stats.lost)
+                    .build();
+
+                s.to_value()
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let obj = self.obj();
+        obj.add_pad(&self.sinkpad).unwrap();
+        obj.add_pad(&self.srcpad).unwrap();
+    }
+}
+
+impl GstObjectImpl for RtpUlpFecDec {}
+
+impl ElementImpl for RtpUlpFecDec {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTP ULPFEC Decoder",
+                "Codec/Depayloader/Network/RTP",
+                "Restores lost RTP packets from ULPFEC packets (RFC 5109)",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let caps = gst::Caps::builder("application/x-rtp").build();
+
+            let srcpad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            let sinkpad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            vec![srcpad_template, sinkpad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::ReadyToPaused {
+            *self.state.lock().unwrap() = State::default();
+        }
+
+        self.parent_change_state(transition)
+    }
+}
@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::glib;
+use gst::prelude::*;
+
+pub mod imp;
+
+glib::wrapper! {
+    pub struct RtpUlpFecDec(ObjectSubclass<imp::RtpUlpFecDec>) @extends gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "rtpulpfecdec2",
+        gst::Rank::NONE,
+        RtpUlpFecDec::static_type(),
+    )
+}
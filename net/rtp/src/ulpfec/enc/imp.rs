@@ -0,0 +1,435 @@
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * SECTION:element-rtpulpfecenc2
+ * @see_also: rtpulpfecdec2, rtpredenc2, rtpflexfecenc, rtpsend
+ *
+ * Protects an RTP stream with ULPFEC packets as per [RFC 5109][rfc-5109].
+ *
+ * The media packets are arranged in rows of #rtpulpfecenc2:columns consecutive packets. With
+ * `row` protection, one FEC packet is created per row, which allows restoring one lost packet
+ * per row. With `column` protection, #rtpulpfecenc2:rows rows form a block and one FEC packet is
+ * created per column of the block, which allows restoring bursts of up to
+ * #rtpulpfecenc2:columns lost packets. `row-and-column` protection combines both.
+ *
+ * The FEC packets are sent in the same stream as the media packets, with the same SSRC and a
+ * different payload type. As they share the seqnum space with the media packets, the seqnums
+ * of the media packets are rewritten. FEC packets are only sent between frames, i.e. after a
+ * packet with the marker bit set or before a packet with a new RTP timestamp.
+ *
+ * The output caps carry the FEC payload type in the `fec-payload` field. When linked to the
+ * `rtp_sink` pad of `rtpsend`, this makes the session aware of the FEC packets. For WebRTC, the
+ * output is usually encapsulated into RED with `rtpredenc2`.
+ *
+ * [rfc-5109]: https://www.rfc-editor.org/rfc/rfc5109.html
+ *
+ * ## Example pipeline
+ *
+ * |[
+ * gst-launch-1.0 videotestsrc ! vp8enc ! rtpvp8pay2 ! rtpulpfecenc2 pt=123 protection=row-and-column columns=5 rows=4 ! rtpredenc2 pt=122 ! udpsink host=127.0.0.1 port=5004
+ * ]| This will send a VP8 stream protected by row and column ULPFEC packets in RED.
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::{glib, prelude::*, subclass::prelude::*};
+use rtp_types::{RtpPacket, RtpPacketBuilder};
+use std::sync::{LazyLock, Mutex};
+
+use crate::fec::{FecGroup, Protection, ProtectionMatrix};
+use crate::ulpfec::{self, MAX_MASK_BITS};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rtpulpfecenc2",
+        gst::DebugColorFlags::empty(),
+        Some("RTP ULPFEC Encoder"),
+    )
+});
+
+const DEFAULT_PT: u32 = 123;
+const DEFAULT_PROTECTION: Protection = Protection::Row;
+const DEFAULT_COLUMNS: u32 = 10;
+const DEFAULT_ROWS: u32 = 4;
+
+#[derive(Debug, Clone)]
+struct Settings {
+    pt: u32,
+    protection: Protection,
+    columns: u32,
+    rows: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            pt: DEFAULT_PT,
+            protection: DEFAULT_PROTECTION,
+            columns: DEFAULT_COLUMNS,
+            rows: DEFAULT_ROWS,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    ssrc: Option<u32>,
+    matrix: Option<ProtectionMatrix>,
+    /// Next seqnum of the output stream, shared by the media and FEC packets
+    next_seqnum: u16,
+    /// Complete groups for which FEC packets are sent at the next frame boundary
+    pending: Vec<FecGroup>,
+    last_timestamp: Option<u32>,
+    last_pts: Option<gst::ClockTime>,
+    last_dts: Option<gst::ClockTime>,
+}
+
+pub struct RtpUlpFecEnc {
+    srcpad: gst::Pad,
+    sinkpad: gst::Pad,
+    settings: Mutex<Settings>,
+    state: Mutex<State>,
+}
+
+impl RtpUlpFecEnc {
+    fn sink_chain(
+        &self,
+        pad: &gst::Pad,
+        mut buffer: gst::Buffer,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let settings = self.settings.lock().unwrap().clone();
+
+        let (ssrc, seqnum, timestamp, marker) = {
+            let map = buffer.map_readable().map_err(|_| {
+                gst::error!(CAT, obj = pad, "Failed to map buffer readable");
+                gst::FlowError::Error
+            })?;
+            match RtpPacket::parse(&map) {
+                Ok(rtp) => (
+                    rtp.ssrc(),
+                    rtp.sequence_number(),
+                    rtp.timestamp(),
+                    rtp.marker_bit(),
+                ),
+                Err(err) => {
+                    gst::warning!(CAT, obj = pad, "Dropping invalid RTP packet: {err:?}");
+                    return Ok(gst::FlowSuccess::Ok);
+                }
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let mut outbufs = Vec::new();
+
+        if state.ssrc != Some(ssrc) {
+            gst::debug!(CAT, imp = self, "New SSRC {ssrc}");
+            outbufs.extend(self.create_fec_packets(&mut state, &settings));
+
+            let matrix =
+                ProtectionMatrix::new(settings.protection, settings.columns, settings.rows);
+            if matrix.span() > MAX_MASK_BITS {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Packets of a column are too far apart, increase the number of rows or reduce the number of columns"
+                );
+            }
+
+            state.ssrc = Some(ssrc);
+            state.matrix = Some(matrix);
+            state.next_seqnum = seqnum;
+            state.last_timestamp = None;
+        }
+
+        // FEC packets for the previous frame are sent before the first packet of a new frame
+        if state.last_timestamp.is_some_and(|last| last != timestamp) {
+            outbufs.extend(self.create_fec_packets(&mut state, &settings));
+        }
+
+        let out_seqnum = state.next_seqnum;
+        state.next_seqnum = state.next_seqnum.wrapping_add(1);
+        if out_seqnum != seqnum {
+            gst::trace!(CAT, imp = self, "Rewriting seqnum {seqnum} to {out_seqnum}");
+            let buffer = buffer.make_mut();
+            let mut map = buffer.map_writable().map_err(|_| {
+                gst::error!(CAT, imp = self, "Failed to map buffer writable");
+                gst::FlowError::Error
+            })?;
+            map[2..4].copy_from_slice(&out_seqnum.to_be_bytes());
+        }
+
+        {
+            let map = buffer.map_readable().map_err(|_| {
+                gst::error!(CAT, imp = self, "Failed to map buffer readable");
+                gst::FlowError::Error
+            })?;
+            let groups = state.matrix.as_mut().unwrap().push(out_seqnum, &map);
+            state.pending.extend(groups);
+        }
+
+        state.last_timestamp = Some(timestamp);
+        state.last_pts = buffer.pts();
+        state.last_dts = buffer.dts();
+        outbufs.push(buffer);
+
+        if marker {
+            outbufs.extend(self.create_fec_packets(&mut state, &settings));
+        }
+        drop(state);
+
+        for buffer in outbufs {
+            self.srcpad.push(buffer)?;
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    /// Creates the FEC packets for all pending groups
+    fn create_fec_packets(&self, state: &mut State, settings: &Settings) -> Vec<gst::Buffer> {
+        let groups = std::mem::take(&mut state.pending);
+        let (Some(ssrc), Some(timestamp)) = (state.ssrc, state.last_timestamp) else {
+            return Vec::new();
+        };
+
+        let mut buffers = Vec::with_capacity(groups.len());
+        for group in groups {
+            let Some(payload) = ulpfec::write_payload(&group.seqnums, &group.parity) else {
+                gst::warning!(
+                    CAT,
+                    imp = self,
+                    "Can't protect packets {:?}, too far apart",
+                    group.seqnums
+                );
+                continue;
+            };
+
+            let seqnum = state.next_seqnum;
+            let data = match RtpPacketBuilder::<&[u8], &[u8]>::new()
+                .payload_type(settings.pt as u8)
+                .ssrc(ssrc)
+                .sequence_number(seqnum)
+                .timestamp(timestamp)
+                .payload(payload.as_slice())
+                .write_vec()
+            {
+                Ok(data) => data,
+                Err(err) => {
+                    gst::warning!(CAT, imp = self, "Failed to create FEC packet: {err:?}");
+                    continue;
+                }
+            };
+            state.next_seqnum = state.next_seqnum.wrapping_add(1);
+
+            gst::trace!(
+                CAT,
+                imp = self,
+                "Created FEC packet {seqnum} protecting {:?}",
+                group.seqnums
+            );
+
+            let mut buffer = gst::Buffer::from_mut_slice(data);
+            {
+                let buffer = buffer.get_mut().unwrap();
+                buffer.set_pts(state.last_pts);
+                buffer.set_dts(state.last_dts);
+            }
+            buffers.push(buffer);
+        }
+
+        buffers
+    }
+
+    fn sink_event(&self, pad: &gst::Pad, event: gst::Event) -> bool {
+        gst::log!(CAT, obj = pad, "Handling event {event:?}");
+
+        match event.view() {
+            gst::EventView::Caps(caps) => {
+                let pt = self.settings.lock().unwrap().pt;
+
+                let mut caps = caps.caps_owned();
+                {
+                    let caps = caps.make_mut();
+                    if let Some(s) = caps.structure_mut(0) {
+                        s.set("fec-payload", pt as i32);
+                    }
+                }
+
+                self.srcpad.push_event(gst::event::Caps::new(&caps))
+            }
+            gst::EventView::Eos(_) => {
+                let settings = self.settings.lock().unwrap().clone();
+                let mut state = self.state.lock().unwrap();
+                let buffers = self.create_fec_packets(&mut state, &settings);
+                drop(state);
+
+                for buffer in buffers {
+                    if self.srcpad.push(buffer).is_err() {
+                        break;
+                    }
+                }
+
+                gst::Pad::event_default(pad, Some(&*self.obj()), event)
+            }
+            gst::EventView::FlushStop(_) => {
+                *self.state.lock().unwrap() = State::default();
+                gst::Pad::event_default(pad, Some(&*self.obj()), event)
+            }
+            _ => gst::Pad::event_default(pad, Some(&*self.obj()), event),
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RtpUlpFecEnc {
+    const NAME: &'static str = "GstRtpUlpFecEnc2";
+    type Type = super::RtpUlpFecEnc;
+    type ParentType = gst::Element;
+
+    fn with_class(klass: &Self::Class) -> Self {
+        let templ = klass.pad_template("sink").unwrap();
+        let sinkpad = gst::Pad::builder_from_template(&templ)
+            .chain_function(|pad, parent, buffer| {
+                Self::catch_panic_pad_function(
+                    parent,
+                    || Err(gst::FlowError::Error),
+                    |this| this.sink_chain(pad, buffer),
+                )
+            })
+            .event_function(|pad, parent, event| {
+                Self::catch_panic_pad_function(parent, || false, |this| this.sink_event(pad, event))
+            })
+            .build();
+
+        let templ = klass.pad_template("src").unwrap();
+        let srcpad = gst::Pad::builder_from_template(&templ).build();
+
+        Self {
+            srcpad,
+            sinkpad,
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(State::default()),
+        }
+    }
+}
+
+impl ObjectImpl for RtpUlpFecEnc {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecUInt::builder("pt")
+                    .nick("Payload Type")
+                    .blurb("Payload type of the FEC packets")
+                    .maximum(0x7f)
+                    .default_value(DEFAULT_PT)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecEnum::builder_with_default("protection", DEFAULT_PROTECTION)
+                    .nick("Protection")
+                    .blurb("Which groups of packets are protected by FEC packets")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("columns")
+                    .nick("Columns")
+                    .blurb("Number of consecutive packets in a row")
+                    .minimum(1)
+                    .maximum(MAX_MASK_BITS as u32)
+                    .default_value(DEFAULT_COLUMNS)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("rows")
+                    .nick("Rows")
+                    .blurb("Number of rows in a block for column protection")
+                    .minimum(1)
+                    .maximum(MAX_MASK_BITS as u32)
+                    .default_value(DEFAULT_ROWS)
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "pt" => settings.pt = value.get().expect("type checked upstream"),
+            "protection" => settings.protection = value.get().expect("type checked upstream"),
+            "columns" => settings.columns = value.get().expect("type checked upstream"),
+            "rows" => settings.rows = value.get().expect("type checked upstream"),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "pt" => settings.pt.to_value(),
+            "protection" => settings.protection.to_value(),
+            "columns" => settings.columns.to_value(),
+            "rows" => settings.rows.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let obj = self.obj();
+        obj.add_pad(&self.sinkpad).unwrap();
+        obj.add_pad(&self.srcpad).unwrap();
+    }
+}
+
+impl GstObjectImpl for RtpUlpFecEnc {}
+
+impl ElementImpl for RtpUlpFecEnc {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTP ULPFEC Encoder",
+                "Codec/Payloader/Network/RTP",
+                "Protects RTP packets with ULPFEC packets (RFC 5109)",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let caps = gst::Caps::builder("application/x-rtp").build();
+
+            let srcpad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            let sinkpad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            vec![srcpad_template, sinkpad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::ReadyToPaused {
+            *self.state.lock().unwrap() = State::default();
+        }
+
+        self.parent_change_state(transition)
+    }
+}
@@ -0,0 +1,24 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use gst::glib;
+use gst::prelude::*;
+
+pub mod imp;
+
+glib::wrapper! {
+    pub struct RtpUlpFecEnc(ObjectSubclass<imp::RtpUlpFecEnc>) @extends gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    #[cfg(feature = "doc")]
+    {
+        crate::fec::Protection::static_type().mark_as_plugin_api(gst::PluginAPIFlags::empty());
+    }
+
+    gst::Element::register(
+        Some(plugin),
+        "rtpulpfecenc2",
+        gst::Rank::NONE,
+        RtpUlpFecEnc::static_type(),
+    )
+}
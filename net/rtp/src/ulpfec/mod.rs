@@ -0,0 +1,106 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! ULPFEC payload format as described in RFC 5109. Only FEC level 0 is supported.
+
+pub mod dec;
+pub mod enc;
+
+#[cfg(test)]
+mod tests;
+
+use crate::fec::Parity;
+
+const FEC_HEADER_LEN: usize = 10;
+
+/// Maximum number of packets a FEC packet can protect with the long mask
+pub const MAX_MASK_BITS: usize = 48;
+
+/// Creates the payload of a FEC packet protecting the packets with the given seqnums.
+///
+/// The first seqnum is used as base seqnum, all other seqnums must follow within
+/// [`MAX_MASK_BITS`].
+pub fn write_payload(seqnums: &[u16], parity: &Parity) -> Option<Vec<u8>> {
+    let sn_base = *seqnums.first()?;
+
+    let mut mask = 0u64;
+    for &seqnum in seqnums {
+        let offset = seqnum.wrapping_sub(sn_base) as usize;
+        if offset >= MAX_MASK_BITS {
+            return None;
+        }
+        mask |= 1 << (MAX_MASK_BITS - 1 - offset);
+    }
+    let long_mask = mask & 0xffff_ffff != 0;
+    let protection_length = u16::try_from(parity.data.len()).ok()?;
+
+    let mut payload =
+        Vec::with_capacity(FEC_HEADER_LEN + if long_mask { 8 } else { 4 } + parity.data.len());
+
+    // FEC header, the E bit is always unset
+    payload.push(if long_mask { 0x40 } else { 0x00 } | parity.pxcc);
+    payload.push(parity.mpt);
+    payload.extend_from_slice(&sn_base.to_be_bytes());
+    payload.extend_from_slice(&parity.timestamp.to_be_bytes());
+    payload.extend_from_slice(&parity.length.to_be_bytes());
+
+    // FEC level 0 header
+    payload.extend_from_slice(&protection_length.to_be_bytes());
+    if long_mask {
+        payload.extend_from_slice(&mask.to_be_bytes()[2..]);
+    } else {
+        payload.extend_from_slice(&((mask >> 32) as u16).to_be_bytes());
+    }
+
+    payload.extend_from_slice(&parity.data);
+
+    Some(payload)
+}
+
+/// Parses the payload of a FEC packet into the protected seqnums and the FEC level 0 parity
+pub fn parse_payload(payload: &[u8]) -> Option<(Vec<u16>, Parity)> {
+    if payload.len() < FEC_HEADER_LEN {
+        return None;
+    }
+
+    // Extension bit, reserved for future use
+    if payload[0] & 0x80 != 0 {
+        return None;
+    }
+    let long_mask = payload[0] & 0x40 != 0;
+    let sn_base = u16::from_be_bytes([payload[2], payload[3]]);
+
+    let level0 = &payload[FEC_HEADER_LEN..];
+    let (mask, mask_bits, level0_header_len) = if long_mask {
+        if level0.len() < 8 {
+            return None;
+        }
+        let mut mask = [0u8; 8];
+        mask[2..].copy_from_slice(&level0[2..8]);
+        (u64::from_be_bytes(mask), MAX_MASK_BITS, 8)
+    } else {
+        if level0.len() < 4 {
+            return None;
+        }
+        (u16::from_be_bytes([level0[2], level0[3]]) as u64, 16, 4)
+    };
+    let protection_length = u16::from_be_bytes([level0[0], level0[1]]) as usize;
+    let data = level0.get(level0_header_len..level0_header_len + protection_length)?;
+
+    let seqnums = (0..mask_bits)
+        .filter(|offset| mask & (1 << (mask_bits - 1 - offset)) != 0)
+        .map(|offset| sn_base.wrapping_add(offset as u16))
+        .collect::<Vec<_>>();
+    if seqnums.is_empty() {
+        return None;
+    }
+
+    let parity = Parity {
+        pxcc: payload[0] & 0x3f,
+        mpt: payload[1],
+        timestamp: u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]),
+        length: u16::from_be_bytes([payload[8], payload[9]]),
+        data: data.to_vec(),
+    };
+
+    Some((seqnums, parity))
+}
@@ -0,0 +1,137 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::fec::{tests::generate_packet, Parity};
+use gst::prelude::*;
+use gst_check::Harness;
+use rtp_types::RtpPacket;
+
+fn init() {
+    use std::sync::Once;
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        gst::init().unwrap();
+        crate::plugin_register_static().expect("rtpulpfec test");
+    });
+}
+
+fn parity_for(packets: &[Vec<u8>]) -> Parity {
+    let mut parity = Parity::default();
+    for packet in packets {
+        parity.add(packet);
+    }
+    parity
+}
+
+#[test]
+fn ulpfec_short_mask() {
+    let packets = (0..3)
+        .map(|i| generate_packet(65534u16.wrapping_add(i), 100, 8))
+        .collect::<Vec<_>>();
+    let parity = parity_for(&packets);
+    let seqnums = [65534, 65535, 0];
+
+    let payload = super::write_payload(&seqnums, &parity).unwrap();
+    // FEC header and FEC level 0 header with the short mask
+    assert_eq!(payload.len(), 10 + 4 + parity.data.len());
+    assert_eq!(payload[0] & 0x40, 0);
+
+    let (parsed_seqnums, parsed_parity) = super::parse_payload(&payload).unwrap();
+    assert_eq!(parsed_seqnums, seqnums);
+    assert_eq!(parsed_parity, parity);
+}
+
+#[test]
+fn ulpfec_long_mask() {
+    let packets = [generate_packet(10, 100, 8), generate_packet(50, 100, 12)];
+    let parity = parity_for(&packets);
+    let seqnums = [10, 50];
+
+    let payload = super::write_payload(&seqnums, &parity).unwrap();
+    assert_eq!(payload.len(), 10 + 8 + parity.data.len());
+    assert_ne!(payload[0] & 0x40, 0);
+
+    let (parsed_seqnums, parsed_parity) = super::parse_payload(&payload).unwrap();
+    assert_eq!(parsed_seqnums, seqnums);
+    assert_eq!(parsed_parity, parity);
+
+    // Packets that are too far apart can't be protected
+    assert!(super::write_payload(&[10, 58], &parity).is_none());
+}
+
+#[test]
+fn ulpfec_restore_lost_packet() {
+    init();
+
+    let caps = gst::Caps::builder("application/x-rtp")
+        .field("media", "video")
+        .field("payload", 96)
+        .field("clock-rate", 90000)
+        .field("encoding-name", "VP8")
+        .build();
+
+    let mut enc = Harness::new("rtpulpfecenc2");
+    {
+        let enc = enc.element().unwrap();
+        enc.set_property_from_str("protection", "row");
+        enc.set_property("columns", 4u32);
+        enc.set_property("rows", 1u32);
+    }
+    enc.set_src_caps(caps.clone());
+    enc.play();
+
+    for seqnum in 0..8u16 {
+        let mut buffer = gst::Buffer::from_mut_slice(generate_packet(
+            seqnum,
+            seqnum as u32 * 3000,
+            10 + seqnum as usize,
+        ));
+        buffer
+            .get_mut()
+            .unwrap()
+            .set_pts(gst::ClockTime::from_mseconds(seqnum as u64 * 33));
+        enc.push(buffer).unwrap();
+    }
+    enc.push_event(gst::event::Eos::new());
+
+    let fec_caps = enc.sinkpad().unwrap().current_caps().unwrap();
+    assert_eq!(
+        fec_caps.structure(0).unwrap().get::<i32>("fec-payload"),
+        Ok(123)
+    );
+
+    let mut packets = Vec::new();
+    while let Some(buffer) = enc.try_pull() {
+        packets.push(buffer.map_readable().unwrap().to_vec());
+    }
+
+    let (media, fec): (Vec<_>, Vec<_>) = packets
+        .iter()
+        .partition(|packet| RtpPacket::parse(packet.as_slice()).unwrap().payload_type() == 96);
+    assert_eq!(media.len(), 8);
+    assert_eq!(fec.len(), 2);
+
+    let mut dec = Harness::new("rtpulpfecdec2");
+    dec.set_src_caps(caps);
+    dec.play();
+
+    // Drop the second media packet
+    let lost = media[1].clone();
+    for packet in packets.iter().filter(|packet| **packet != lost) {
+        dec.push(gst::Buffer::from_mut_slice(packet.clone()))
+            .unwrap();
+    }
+    dec.push_event(gst::event::Eos::new());
+
+    let mut output = Vec::new();
+    while let Some(buffer) = dec.try_pull() {
+        output.push(buffer.map_readable().unwrap().to_vec());
+    }
+    assert_eq!(output.iter().collect::<Vec<_>>(), media);
+
+    let stats = dec.element().unwrap().property::<gst::Structure>("stats");
+    assert_eq!(stats.get::<u64>("received-packets"), Ok(7));
+    assert_eq!(stats.get::<u64>("received-fec-packets"), Ok(2));
+    assert_eq!(stats.get::<u64>("recovered-packets"), Ok(1));
+    assert_eq!(stats.get::<u64>("lost-packets"), Ok(0));
+}
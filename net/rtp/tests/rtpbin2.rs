@@ -9,7 +9,7 @@
 
 use std::sync::{atomic::AtomicUsize, Arc, Mutex};
 
-use gst::{prelude::*, Caps};
+use gst::{glib, prelude::*, Caps};
 use gst_check::Harness;
 use rtp_types::*;
 
@@ -72,7 +72,12 @@ fn send_init() -> gst_check::Harness {
         .property("rtp-id", id.to_string())
         .build()
         .unwrap();
-    let mut h = Harness::with_element(&elem, Some("rtp_sink_0"), Some("rtp_src_0"));
+
+    send_init_with_element(&elem)
+}
+
+fn send_init_with_element(elem: &gst::Element) -> gst_check::Harness {
+    let mut h = Harness::with_element(elem, Some("rtp_sink_0"), Some("rtp_src_0"));
     h.play();
 
     let caps = Caps::builder("application/x-rtp")
@@ -287,8 +292,19 @@ where
         .property("rtp-id", id.to_string())
         .build()
         .unwrap();
+
+    receive_init_with_element(&elem, new_srcpad)
+}
+
+fn receive_init_with_element<F>(
+    elem: &gst::Element,
+    new_srcpad: F,
+) -> Arc<Mutex<gst_check::Harness>>
+where
+    F: Fn(&mut gst_check::Harness, &gst::Pad) + Send + Sync + 'static,
+{
     let h = Arc::new(Mutex::new(Harness::with_element(
-        &elem,
+        elem,
         Some("rtp_sink_0"),
         None,
     )));
@@ -450,6 +466,394 @@ fn test_receive_list() {
     receive_check_stats(h, PACKETS_TEST_1);
 }
 
+#[test]
+fn test_receive_retransmission() {
+    init();
+
+    let id = next_element_counter();
+    let elem = gst::ElementFactory::make("rtprecv")
+        .property("rtp-id", id.to_string())
+        .property("do-retransmission", true)
+        .property(
+            "rtx-payload-type-map",
+            gst::Structure::builder("application/x-rtp-pt-map")
+                .field(TEST_PT.to_string(), 97u32)
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let h = receive_init_with_element(&elem, |h, srcpad| h.add_element_src_pad(srcpad));
+
+    // Packet 501 is lost and only arrives later as retransmission on a different ssrc
+    let packets = [
+        PacketInfo {
+            seq_no: 500,
+            rtp_ts: 20,
+            payload_len: 13,
+        },
+        PacketInfo {
+            seq_no: 502,
+            rtp_ts: 40,
+            payload_len: 5,
+        },
+    ];
+    receive_push(h.clone(), packets, false);
+
+    let payload = vec![4; 7];
+    let rtx = RtpPacketBuilder::new()
+        .ssrc(0x87654321)
+        .payload_type(97)
+        .sequence_number(1000)
+        .timestamp(30)
+        .payload(501u16.to_be_bytes().as_slice())
+        .payload(payload.as_slice())
+        .write_vec()
+        .unwrap();
+    let inner = h.lock().unwrap();
+    let push_pad = inner
+        .element()
+        .unwrap()
+        .static_pad("rtp_sink_0")
+        .unwrap()
+        .peer()
+        .unwrap();
+    drop(inner);
+    push_pad.push(gst::Buffer::from_mut_slice(rtx)).unwrap();
+
+    let mut inner = h.lock().unwrap();
+    for seq_no in 500..503 {
+        let buffer = inner.pull().unwrap();
+        let mapped = buffer.map_readable().unwrap();
+        let rtp = rtp_types::RtpPacket::parse(&mapped).unwrap();
+        assert_eq!(rtp.sequence_number(), seq_no);
+        assert_eq!(rtp.ssrc(), TEST_SSRC);
+        assert_eq!(rtp.payload_type(), TEST_PT);
+        if seq_no == 501 {
+            assert_eq!(rtp.payload(), payload.as_slice());
+        }
+    }
+}
+
+#[test]
+fn test_fec_recovery() {
+    init();
+
+    const FEC_PT: u8 = 123;
+    let fec_pt_map = gst::Structure::builder("application/x-rtp-pt-map")
+        .field(TEST_PT.to_string(), FEC_PT as u32)
+        .build();
+
+    let send = gst::ElementFactory::make("rtpsend")
+        .property("rtp-id", next_element_counter().to_string())
+        .property("fec-payload-type-map", &fec_pt_map)
+        .property_from_str("fec-protection", "row")
+        .property("fec-columns", 4u32)
+        .build()
+        .unwrap();
+    let mut h_send = send_init_with_element(&send);
+
+    let recv = gst::ElementFactory::make("rtprecv")
+        .property("rtp-id", next_element_counter().to_string())
+        .property("fec-payload-type-map", &fec_pt_map)
+        .build()
+        .unwrap();
+    let h_recv = receive_init_with_element(&recv, |h, srcpad| h.add_element_src_pad(srcpad));
+
+    // Every packet starts a new frame, so the FEC packet of a row is sent before the first packet
+    // of the next row and the seqnums of the following media packets are shifted
+    for i in 0..9 {
+        let packet = PacketInfo {
+            seq_no: 100 + i,
+            rtp_ts: 480 * i as u32,
+            payload_len: 10 + i as usize,
+        };
+        h_send.push(packet.generate_buffer(None)).unwrap();
+    }
+
+    let mut sent = Vec::new();
+    for seq_no in 100..111u16 {
+        let buffer = h_send.pull().unwrap();
+        let mapped = buffer.map_readable().unwrap();
+        let rtp = RtpPacket::parse(&mapped).unwrap();
+        assert_eq!(rtp.sequence_number(), seq_no);
+        assert_eq!(rtp.ssrc(), TEST_SSRC);
+        if seq_no == 104 || seq_no == 109 {
+            assert_eq!(rtp.payload_type(), FEC_PT);
+        } else {
+            assert_eq!(rtp.payload_type(), TEST_PT);
+        }
+        drop(mapped);
+        sent.push(buffer);
+    }
+
+    let inner = h_recv.lock().unwrap();
+    let recv_push_pad = inner
+        .element()
+        .unwrap()
+        .static_pad("rtp_sink_0")
+        .unwrap()
+        .peer()
+        .unwrap();
+    drop(inner);
+
+    // One media packet of each row is lost
+    for buffer in sent.iter() {
+        let seq_no = RtpPacket::parse(&buffer.map_readable().unwrap())
+            .unwrap()
+            .sequence_number();
+        if seq_no == 101 || seq_no == 106 {
+            continue;
+        }
+        recv_push_pad.push(buffer.clone()).unwrap();
+    }
+
+    let mut inner = h_recv.lock().unwrap();
+    for sent in sent.iter().filter(|buffer| {
+        RtpPacket::parse(&buffer.map_readable().unwrap())
+            .unwrap()
+            .payload_type()
+            == TEST_PT
+    }) {
+        let buffer = inner.pull().unwrap();
+        let mapped = buffer.map_readable().unwrap();
+        let sent = sent.map_readable().unwrap();
+        assert_eq!(mapped.as_slice(), sent.as_slice());
+    }
+
+    let stats = inner.element().unwrap().property::<gst::Structure>("stats");
+    drop(inner);
+    let fec_stats = stats
+        .get::<gst::Structure>("0")
+        .unwrap()
+        .get::<gst::Structure>("fec-stats")
+        .unwrap();
+    assert_eq!(fec_stats.get::<u64>("received-fec-packets").unwrap(), 2);
+    assert_eq!(fec_stats.get::<u64>("recovered-packets").unwrap(), 2);
+}
+
+#[test]
+fn test_twcc_feedback() {
+    init();
+
+    // Sending side, adding transport-wide sequence numbers and receiving the feedback
+    let send_id = next_element_counter();
+    let send = gst::ElementFactory::make("rtpsend")
+        .property("rtp-id", send_id.to_string())
+        .property("twcc-extension-id", 1u32)
+        .build()
+        .unwrap();
+    let mut h_send = send_init_with_element(&send);
+    let send_rtcp_recv = gst::ElementFactory::make("rtprecv")
+        .property("rtp-id", send_id.to_string())
+        .build()
+        .unwrap();
+    let mut h_send_rtcp = Harness::with_element(&send_rtcp_recv, Some("rtcp_sink_0"), None);
+    h_send_rtcp.play();
+    h_send_rtcp.set_src_caps_str("application/x-rtcp");
+
+    // Receiving side, generating the feedback
+    let recv_id = next_element_counter();
+    let recv = gst::ElementFactory::make("rtprecv")
+        .property("rtp-id", recv_id.to_string())
+        .property("twcc-extension-id", 1u32)
+        .property("twcc-feedback-interval", 10u32)
+        .build()
+        .unwrap();
+    let h_recv = receive_init_with_element(&recv, |h, srcpad| h.add_element_src_pad(srcpad));
+    let recv_rtcp_send = gst::ElementFactory::make("rtpsend")
+        .property("rtp-id", recv_id.to_string())
+        .build()
+        .unwrap();
+    let mut h_recv_rtcp = Harness::with_element(&recv_rtcp_send, None, Some("rtcp_src_0"));
+    h_recv_rtcp.play();
+
+    let inner = h_recv.lock().unwrap();
+    let recv_push_pad = inner
+        .element()
+        .unwrap()
+        .static_pad("rtp_sink_0")
+        .unwrap()
+        .peer()
+        .unwrap();
+    drop(inner);
+
+    // Simulate a network that loses every 5th packet and where the delay grows by 1ms per packet
+    const N_PACKETS: u64 = 20;
+    for i in 0..N_PACKETS {
+        let packet = PacketInfo {
+            seq_no: 100 + i as u16,
+            rtp_ts: 480 * i as u32,
+            payload_len: 100,
+        };
+        h_send.push(packet.generate_buffer(None)).unwrap();
+
+        let mut buffer = h_send.pull().unwrap();
+        {
+            let mapped = buffer.map_readable().unwrap();
+            let rtp = RtpPacket::parse(&mapped).unwrap();
+            assert_eq!(rtp.sequence_number(), packet.seq_no);
+            let (profile, ext) = rtp.extension().unwrap();
+            assert_eq!(profile, 0xbede);
+            assert_eq!(ext[0], 0x11);
+            assert_eq!(u16::from_be_bytes([ext[1], ext[2]]), i as u16);
+        }
+
+        if i % 5 == 4 {
+            continue;
+        }
+        buffer
+            .make_mut()
+            .set_dts(gst::ClockTime::from_mseconds(10 * i + 50 + i));
+        recv_push_pad.push(buffer).unwrap();
+    }
+
+    // Forward the feedback until all packets were reported. The last packet is lost and is never
+    // reported as no packet arrived after it.
+    let mut reported = Vec::new();
+    while reported.len() < N_PACKETS as usize - 1 {
+        let rtcp = h_recv_rtcp.pull().unwrap();
+        h_send_rtcp.push(rtcp).unwrap();
+
+        while let Some(event) = h_send.try_pull_upstream_event() {
+            let Some(s) = event.structure() else {
+                continue;
+            };
+            if s.name() != "RTPTWCCPackets" {
+                continue;
+            }
+            let packets = s.get::<glib::ValueArray>("packets").unwrap();
+            reported.extend(
+                packets
+                    .iter()
+                    .map(|packet| packet.get::<gst::Structure>().unwrap()),
+            );
+        }
+    }
+
+    assert_eq!(reported.len(), N_PACKETS as usize - 1);
+    for (i, packet) in reported.iter().enumerate() {
+        let i = i as u64;
+        assert_eq!(packet.get::<u32>("seqnum").unwrap(), i as u32);
+        assert_eq!(packet.get::<u32>("ssrc").unwrap(), TEST_SSRC);
+        assert_eq!(packet.get::<u32>("pt").unwrap(), TEST_PT as u32);
+        assert!(packet.get::<u32>("size").unwrap() > 100);
+        assert!(packet.get::<gst::ClockTime>("local-ts").is_ok());
+        if i % 5 == 4 {
+            assert!(packet.get::<bool>("lost").unwrap());
+        } else {
+            assert!(!packet.get::<bool>("lost").unwrap());
+            assert_eq!(
+                packet.get::<gst::ClockTime>("remote-ts").unwrap(),
+                gst::ClockTime::from_mseconds(10 * i + 50 + i)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_twcc_bandwidth_estimation() {
+    init();
+
+    const INITIAL_BITRATE: u32 = 1_000_000;
+
+    // Bandwidth estimation in front of the sending side, driven by the feedback
+    let bwe = gst::ElementFactory::make("rtpgccbwe")
+        .property("estimated-bitrate", INITIAL_BITRATE)
+        .property("min-bitrate", 10_000u32)
+        .build()
+        .unwrap();
+    let mut h_bwe = Harness::with_element(&bwe, Some("sink"), Some("src"));
+    h_bwe.play();
+    h_bwe.set_src_caps(
+        Caps::builder("application/x-rtp")
+            .field("media", "audio")
+            .field("payload", TEST_PT as i32)
+            .field("clock-rate", TEST_CLOCK_RATE as i32)
+            .field("encoding-name", "custom-test")
+            .build(),
+    );
+
+    let send_id = next_element_counter();
+    let send = gst::ElementFactory::make("rtpsend")
+        .property("rtp-id", send_id.to_string())
+        .property("twcc-extension-id", 1u32)
+        .build()
+        .unwrap();
+    let mut h_send = send_init_with_element(&send);
+    let send_rtcp_recv = gst::ElementFactory::make("rtprecv")
+        .property("rtp-id", send_id.to_string())
+        .build()
+        .unwrap();
+    let mut h_send_rtcp = Harness::with_element(&send_rtcp_recv, Some("rtcp_sink_0"), None);
+    h_send_rtcp.play();
+    h_send_rtcp.set_src_caps_str("application/x-rtcp");
+
+    let recv_id = next_element_counter();
+    let recv = gst::ElementFactory::make("rtprecv")
+        .property("rtp-id", recv_id.to_string())
+        .property("twcc-extension-id", 1u32)
+        .property("twcc-feedback-interval", 20u32)
+        .build()
+        .unwrap();
+    let h_recv = receive_init_with_element(&recv, |h, srcpad| h.add_element_src_pad(srcpad));
+    let recv_rtcp_send = gst::ElementFactory::make("rtpsend")
+        .property("rtp-id", recv_id.to_string())
+        .build()
+        .unwrap();
+    let mut h_recv_rtcp = Harness::with_element(&recv_rtcp_send, None, Some("rtcp_src_0"));
+    h_recv_rtcp.play();
+
+    let inner = h_recv.lock().unwrap();
+    let recv_push_pad = inner
+        .element()
+        .unwrap()
+        .static_pad("rtp_sink_0")
+        .unwrap()
+        .peer()
+        .unwrap();
+    drop(inner);
+
+    // Simulate a network that loses every third packet until the loss shows in the estimate
+    let start = std::time::Instant::now();
+    let mut i = 0u64;
+    while bwe.property::<u32>("estimated-bitrate") >= INITIAL_BITRATE {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+
+        for _ in 0..10 {
+            let packet = PacketInfo {
+                seq_no: i as u16,
+                rtp_ts: 480 * i as u32,
+                payload_len: 100,
+            };
+            h_bwe.push(packet.generate_buffer(None)).unwrap();
+            let buffer = h_bwe.pull().unwrap();
+            h_send.push(buffer).unwrap();
+
+            let mut buffer = h_send.pull().unwrap();
+            if i % 3 != 2 {
+                buffer
+                    .make_mut()
+                    .set_dts(gst::ClockTime::from_mseconds(10 * i + 50));
+                recv_push_pad.push(buffer).unwrap();
+            }
+            i += 1;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+
+        while let Some(rtcp) = h_recv_rtcp.try_pull() {
+            h_send_rtcp.push(rtcp).unwrap();
+        }
+        while let Some(event) = h_send.try_pull_upstream_event() {
+            if event
+                .structure()
+                .is_some_and(|s| s.name() == "RTPTWCCPackets")
+            {
+                assert!(h_bwe.push_upstream_event(event));
+            }
+        }
+    }
+}
+
 #[test]
 fn test_receive_flush() {
     init();
@@ -15,10 +15,13 @@
  */
 use gst::glib;
 
+mod rtsp;
+mod rtspserver;
 mod rtspsrc;
 
 fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
     rtspsrc::register(plugin)?;
+    rtspserver::register(plugin)?;
     Ok(())
 }
 
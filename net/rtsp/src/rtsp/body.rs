@@ -36,7 +36,6 @@ impl Default for Body {
 
 impl Body {
     /// Create a body from custom memory without copying.
-    #[allow(dead_code)]
     pub fn custom<T: AsRef<[u8]> + Send + Sync + 'static>(custom: T) -> Self {
         Body(Inner::Custom(Box::new(custom)))
     }
@@ -0,0 +1,27 @@
+// GStreamer RTSP plugin (in Rust)
+//
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! RTSP message handling, transports and SDP helpers that are shared by the client and the
+//! server elements.
+
+use std::sync::LazyLock;
+
+pub(crate) mod body;
+pub(crate) mod sdp;
+pub(crate) mod tcp_message;
+pub(crate) mod transport;
+
+pub(crate) static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rsrtsp",
+        gst::DebugColorFlags::empty(),
+        Some("RTSP messages and transports"),
+    )
+});
@@ -0,0 +1,121 @@
+// GStreamer RTSP plugin (in Rust)
+//
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use super::CAT;
+
+// Caps fields that are not written to the `a=fmtp` line, the same as in
+// gst_sdp_media_set_media_from_caps()
+const FMTP_SKIP_FIELDS: &[&str] = &[
+    "media",
+    "payload",
+    "clock-rate",
+    "encoding-name",
+    "encoding-params",
+    "ssrc",
+    "timestamp-offset",
+    "seqnum-offset",
+    "srtp-key",
+    "srtp-cipher",
+    "srtp-auth",
+    "srtcp-cipher",
+    "srtcp-auth",
+    "mikey",
+];
+const FMTP_SKIP_PREFIXES: &[&str] = &["rtcp-fb-", "extmap-", "ssrc-"];
+
+/// Writes the SDP media description for the RTP caps of a stream, which is the reverse of
+/// `rtspsrc::sdp::parse_media_attributes()`. Returns `None` if the caps lack the fields of
+/// the `a=rtpmap`.
+pub fn format_media(s: &gst::StructureRef, control: &str, connection: &str) -> Option<String> {
+    let media = s.get::<&str>("media").ok()?;
+    let pt = s.get::<i32>("payload").ok()?;
+    let encoding_name = s.get::<&str>("encoding-name").ok()?;
+    let clock_rate = s.get::<i32>("clock-rate").ok()?;
+
+    let mut sdp = format!("m={media} 0 RTP/AVP {pt}\r\nc={connection}\r\n");
+    match s.get::<&str>("encoding-params") {
+        Ok(params) => sdp.push_str(&format!(
+            "a=rtpmap:{pt} {encoding_name}/{clock_rate}/{params}\r\n"
+        )),
+        Err(_) => sdp.push_str(&format!("a=rtpmap:{pt} {encoding_name}/{clock_rate}\r\n")),
+    }
+
+    let mut fmtp = Vec::new();
+    for (field, value) in s.iter() {
+        let field = field.as_str();
+        if FMTP_SKIP_FIELDS.contains(&field)
+            || FMTP_SKIP_PREFIXES.iter().any(|p| field.starts_with(p))
+        {
+            continue;
+        }
+        let Ok(Ok(value)) = value.transform::<String>().map(|v| v.get::<String>()) else {
+            gst::warning!(CAT, "Can't write caps field {field} to the SDP, ignoring");
+            continue;
+        };
+
+        if let Some(attr) = field.strip_prefix("a-") {
+            // Written without the payload type by `parse_framesize()`
+            if attr == "framesize" {
+                sdp.push_str(&format!("a=framesize:{pt} {value}\r\n"));
+            } else if value.is_empty() {
+                sdp.push_str(&format!("a={attr}\r\n"));
+            } else {
+                sdp.push_str(&format!("a={attr}:{value}\r\n"));
+            }
+        } else if field.starts_with("x-") {
+            sdp.push_str(&format!("a={field}:{value}\r\n"));
+        } else {
+            fmtp.push(format!("{field}={value}"));
+        }
+    }
+    if !fmtp.is_empty() {
+        sdp.push_str(&format!("a=fmtp:{pt} {}\r\n", fmtp.join(";")));
+    }
+    sdp.push_str(&format!("a=control:{control}\r\n"));
+
+    Some(sdp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn media_from_caps() {
+        gst::init().unwrap();
+
+        let caps = gst::Structure::builder("application/x-rtp")
+            .field("media", "audio")
+            .field("payload", 96i32)
+            .field("clock-rate", 44100i32)
+            .field("encoding-name", "MP4A-LATM")
+            .field("encoding-params", "2")
+            .field("ssrc", 0x1234u32)
+            .field("cpresent", "0")
+            .field("config", "40002410")
+            .build();
+        assert_eq!(
+            format_media(&caps, "stream=0", "IN IP4 0.0.0.0").unwrap(),
+            "m=audio 0 RTP/AVP 96\r\n\
+             c=IN IP4 0.0.0.0\r\n\
+             a=rtpmap:96 MP4A-LATM/44100/2\r\n\
+             a=fmtp:96 cpresent=0;config=40002410\r\n\
+             a=control:stream=0\r\n"
+        );
+
+        // No encoding-name
+        let caps = gst::Structure::builder("application/x-rtp")
+            .field("media", "video")
+            .field("payload", 96i32)
+            .field("clock-rate", 90000i32)
+            .build();
+        assert_eq!(format_media(&caps, "stream=0", "IN IP4 0.0.0.0"), None);
+    }
+}
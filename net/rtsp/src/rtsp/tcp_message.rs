@@ -96,7 +96,7 @@ pub(crate) fn async_read<R: AsyncRead + Unpin + Send>(
                                 write_pos = 0;
                             }
 
-                            gst::trace!(super::imp::CAT, "Read message {:?}", msg);
+                            gst::trace!(super::CAT, "Read message {:?}", msg);
 
                             return Ok((Some(msg), write_pos, read_pos));
                         }
@@ -122,7 +122,7 @@ pub(crate) fn async_read<R: AsyncRead + Unpin + Send>(
                 assert_eq!(read_pos, 0);
 
                 if write_pos == max_size {
-                    gst::error!(super::imp::CAT, "Message bigger than maximum {}", max_size);
+                    gst::error!(super::CAT, "Message bigger than maximum {}", max_size);
                     return Err(ReadError::TooBig);
                 }
 
@@ -138,7 +138,7 @@ pub(crate) fn async_read<R: AsyncRead + Unpin + Send>(
                 let b = read.read(&mut buf[write_pos..]).await?;
 
                 if b == 0 {
-                    gst::debug!(super::imp::CAT, "Connection closed");
+                    gst::debug!(super::CAT, "Connection closed");
                     return Ok((None, write_pos, read_pos));
                 }
                 write_pos += b;
@@ -159,7 +159,7 @@ pub(crate) fn async_read<R: AsyncRead + Unpin + Send>(
             )),
             Ok((None, _, _)) => None,
             Err(err) => {
-                gst::error!(super::imp::CAT, "Read error {}", err);
+                gst::error!(super::CAT, "Read error {}", err);
                 Some((Err(err), None))
             }
         }
@@ -181,7 +181,7 @@ pub(crate) fn async_write<W: AsyncWrite + Unpin + Send>(
 
     futures::sink::unfold(state, |mut state, item: Message<Body>| {
         async move {
-            gst::trace!(super::imp::CAT, "Writing message {:?}", item);
+            gst::trace!(super::CAT, "Writing message {:?}", item);
 
             // TODO: Write data messages more efficiently by writing header / body separately
             state.buffer.clear();
@@ -189,11 +189,11 @@ pub(crate) fn async_write<W: AsyncWrite + Unpin + Send>(
 
             match state.write.write_all(&state.buffer).await {
                 Ok(_) => {
-                    gst::trace!(super::imp::CAT, "Finished writing queued message");
+                    gst::trace!(super::CAT, "Finished writing queued message");
                     Ok(state)
                 }
                 Err(err) => {
-                    gst::error!(super::imp::CAT, "Write error {}", err);
+                    gst::error!(super::CAT, "Write error {}", err);
                     Err(err)
                 }
             }
@@ -10,11 +10,31 @@
 //
 // https://www.rfc-editor.org/rfc/rfc2326.html
 
-use super::imp::{RtspError, RtspProtocol};
 use rtsp_types::headers::{transport::RtpTransport, RtpLowerTransport};
-use std::{convert::TryFrom, net::IpAddr};
+use std::{convert::TryFrom, fmt, net::IpAddr};
 use tokio::net::UdpSocket;
 
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RtspProtocol {
+    UdpMulticast,
+    Udp,
+    Tcp,
+}
+
+impl fmt::Display for RtspProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            RtspProtocol::Udp => write!(f, "udp"),
+            RtspProtocol::UdpMulticast => write!(f, "udp-mcast"),
+            RtspProtocol::Tcp => write!(f, "tcp"),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Invalid transport: {0}")]
+pub struct TransportError(String);
+
 #[derive(Debug)]
 pub enum RtspTransportInfo {
     Tcp {
@@ -34,13 +54,13 @@ pub enum RtspTransportInfo {
 }
 
 impl TryFrom<&RtpTransport> for RtspTransportInfo {
-    type Error = RtspError;
+    type Error = TransportError;
 
     fn try_from(t: &RtpTransport) -> Result<Self, Self::Error> {
         match &t.lower_transport {
             Some(RtpLowerTransport::Tcp) => match t.params.interleaved {
                 Some(v) => Ok(RtspTransportInfo::Tcp { channels: v }),
-                None => Err(RtspError::Fatal(format!(
+                None => Err(TransportError(format!(
                     "Expected interleaved channels: {t:#?}",
                 ))),
             },
@@ -50,19 +70,19 @@ impl TryFrom<&RtpTransport> for RtspTransportInfo {
                         match d.parse::<IpAddr>() {
                             Ok(d) => d,
                             Err(err) => {
-                                return Err(RtspError::Fatal(format!(
+                                return Err(TransportError(format!(
                                     "Failed to parse multicast dest addr: {err:?}"
                                 )));
                             }
                         }
                     } else {
-                        return Err(RtspError::Fatal(format!(
+                        return Err(TransportError(format!(
                             "Need multicast dest addr: {:#?}",
                             t.params,
                         )));
                     };
                     let Some(port) = t.params.port else {
-                        return Err(RtspError::Fatal(format!(
+                        return Err(TransportError(format!(
                             "Need multicast UDP port(s): {:#?}",
                             t.params,
                         )));
@@ -81,7 +101,7 @@ impl TryFrom<&RtpTransport> for RtspTransportInfo {
                     })
                 }
             }
-            Some(RtpLowerTransport::Other(token)) => Err(RtspError::Fatal(format!(
+            Some(RtpLowerTransport::Other(token)) => Err(TransportError(format!(
                 "Unsupported RTP lower transport {token:?}"
             ))),
         }
@@ -0,0 +1,527 @@
+// GStreamer RTSP Server Sink
+//
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use anyhow::{Context, Result};
+
+use gst::glib;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+
+use super::server::{Media, MediaSettings, MulticastGroup, Server};
+
+const DEFAULT_ADDRESS: &str = "0.0.0.0";
+const DEFAULT_PORT: u32 = 8554;
+const DEFAULT_MOUNT_POINT: &str = "/test";
+const DEFAULT_USER_ID: Option<String> = None;
+const DEFAULT_USER_PW: Option<String> = None;
+const DEFAULT_MULTICAST_ADDRESS: Option<String> = None;
+const DEFAULT_MULTICAST_PORT: u32 = 5000;
+const DEFAULT_MULTICAST_TTL: u32 = 1;
+
+static RTCP_CAPS: LazyLock<gst::Caps> =
+    LazyLock::new(|| gst::Caps::from(gst::Structure::new_empty("application/x-rtcp")));
+
+pub(crate) static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "rtspserversink",
+        gst::DebugColorFlags::empty(),
+        Some("RTSP server sink"),
+    )
+});
+
+#[derive(Debug, Clone)]
+struct Settings {
+    address: String,
+    port: u32,
+    mount_point: String,
+    user_id: Option<String>,
+    user_pw: Option<String>,
+    multicast_address: Option<String>,
+    multicast_port: u32,
+    multicast_ttl: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            address: DEFAULT_ADDRESS.to_string(),
+            port: DEFAULT_PORT,
+            mount_point: DEFAULT_MOUNT_POINT.to_string(),
+            user_id: DEFAULT_USER_ID,
+            user_pw: DEFAULT_USER_PW,
+            multicast_address: DEFAULT_MULTICAST_ADDRESS,
+            multicast_port: DEFAULT_MULTICAST_PORT,
+            multicast_ttl: DEFAULT_MULTICAST_TTL,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    // Server and the mount point of our media on it
+    server: Option<(Arc<Server>, String)>,
+    // rtpsend and rtprecv, created for the first pad
+    rtp: Option<(gst::Element, gst::Element)>,
+}
+
+#[derive(Debug, Default)]
+pub struct RtspServerSink {
+    settings: Mutex<Settings>,
+    state: Mutex<State>,
+    media: Arc<Media>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RtspServerSink {
+    const NAME: &'static str = "GstRtspServerSink";
+    type Type = super::RtspServerSink;
+    type ParentType = gst::Bin;
+}
+
+impl ObjectImpl for RtspServerSink {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecString::builder("address")
+                    .nick("Address")
+                    .blurb("Address to listen on for RTSP connections")
+                    .default_value(Some(DEFAULT_ADDRESS))
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("port")
+                    .nick("Port")
+                    .blurb("Port to listen on for RTSP connections, elements with the same address and port share the server (0 = automatic selection)")
+                    .maximum(u16::MAX as u32)
+                    .default_value(DEFAULT_PORT)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecInt::builder("bound-port")
+                    .nick("Bound port")
+                    .blurb("Port the server is listening on, -1 if not started")
+                    .minimum(-1)
+                    .maximum(u16::MAX as i32)
+                    .default_value(-1)
+                    .read_only()
+                    .build(),
+                glib::ParamSpecString::builder("mount-point")
+                    .nick("Mount point")
+                    .blurb("Path of the media in the RTSP URL, e.g. /test for rtsp://127.0.0.1:8554/test")
+                    .default_value(Some(DEFAULT_MOUNT_POINT))
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecString::builder("user-id")
+                    .nick("User ID")
+                    .blurb("User ID for the Basic authentication of clients, no authentication if not set")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecString::builder("user-pw")
+                    .nick("User password")
+                    .blurb("User password for the Basic authentication of clients")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecString::builder("multicast-address")
+                    .nick("Multicast address")
+                    .blurb("Multicast group for clients that request multicast, multicast is disabled if not set")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("multicast-port")
+                    .nick("Multicast port")
+                    .blurb("Multicast RTP port of the first stream, stream N uses this port plus 2 * N, and the next port for RTCP")
+                    .minimum(1)
+                    .maximum(u16::MAX as u32 - 1)
+                    .default_value(DEFAULT_MULTICAST_PORT)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("multicast-ttl")
+                    .nick("Multicast TTL")
+                    .blurb("Time to live of the multicast packets")
+                    .minimum(1)
+                    .maximum(u8::MAX as u32)
+                    .default_value(DEFAULT_MULTICAST_TTL)
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "address" => {
+                settings.address = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream")
+                    .unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+            }
+            "port" => {
+                settings.port = value.get().expect("type checked upstream");
+            }
+            "mount-point" => {
+                settings.mount_point = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream")
+                    .unwrap_or_else(|| DEFAULT_MOUNT_POINT.to_string());
+            }
+            "user-id" => {
+                settings.user_id = value.get().expect("type checked upstream");
+            }
+            "user-pw" => {
+                settings.user_pw = value.get().expect("type checked upstream");
+            }
+            "multicast-address" => {
+                settings.multicast_address = value.get().expect("type checked upstream");
+            }
+            "multicast-port" => {
+                settings.multicast_port = value.get().expect("type checked upstream");
+            }
+            "multicast-ttl" => {
+                settings.multicast_ttl = value.get().expect("type checked upstream");
+            }
+            name => unimplemented!("Property '{name}'"),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        if pspec.name() == "bound-port" {
+            let state = self.state.lock().unwrap();
+            return state
+                .server
+                .as_ref()
+                .map_or(-1, |(server, _)| server.port() as i32)
+                .to_value();
+        }
+
+        let settings = self.settings.lock().unwrap();
+        match pspec.name() {
+            "address" => settings.address.to_value(),
+            "port" => settings.port.to_value(),
+            "mount-point" => settings.mount_point.to_value(),
+            "user-id" => settings.user_id.to_value(),
+            "user-pw" => settings.user_pw.to_value(),
+            "multicast-address" => settings.multicast_address.to_value(),
+            "multicast-port" => settings.multicast_port.to_value(),
+            "multicast-ttl" => settings.multicast_ttl.to_value(),
+            name => unimplemented!("Property '{name}'"),
+        }
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+
+        let obj = self.obj();
+        obj.set_suppressed_flags(gst::ElementFlags::SINK | gst::ElementFlags::SOURCE);
+        obj.set_element_flags(gst::ElementFlags::SINK);
+    }
+}
+
+impl GstObjectImpl for RtspServerSink {}
+
+impl ElementImpl for RtspServerSink {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "RTSP Server Sink",
+                "Sink/Network",
+                "Serve RTP streams to clients via the Real Time Streaming Protocol (RTSP) (RFC 2326)",
+                "agent <agent@local>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let sink_pad_template = gst::PadTemplate::new(
+                "sink_%u",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Request,
+                &gst::Caps::new_empty_simple("application/x-rtp"),
+            )
+            .unwrap();
+
+            vec![sink_pad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn request_new_pad(
+        &self,
+        templ: &gst::PadTemplate,
+        name: Option<&str>,
+        _caps: Option<&gst::Caps>,
+    ) -> Option<gst::Pad> {
+        let obj = self.obj();
+        let n = match name {
+            Some(name) => name.strip_prefix("sink_")?.parse::<u32>().ok()?,
+            None => (0..)
+                .find(|n| obj.static_pad(&format!("sink_{n}")).is_none())
+                .unwrap(),
+        };
+        if obj.static_pad(&format!("sink_{n}")).is_some() {
+            gst::warning!(CAT, imp = self, "Pad sink_{n} already exists");
+            return None;
+        }
+
+        match self.add_stream(templ, n) {
+            Ok(pad) => Some(pad),
+            Err(err) => {
+                gst::error!(CAT, imp = self, "Failed to add stream {n}: {err:#}");
+                None
+            }
+        }
+    }
+
+    fn release_pad(&self, pad: &gst::Pad) {
+        let obj = self.obj();
+        let Some(n) = pad
+            .name()
+            .strip_prefix("sink_")
+            .and_then(|n| n.parse::<u32>().ok())
+        else {
+            return;
+        };
+
+        let _ = pad.set_active(false);
+        self.media.remove_stream(n);
+        for name in [
+            format!("rtp_appsink_{n}"),
+            format!("rtcp_appsink_{n}"),
+            format!("rtcp_appsrc_{n}"),
+        ] {
+            if let Some(element) = obj.by_name(&name) {
+                let _ = element.set_state(gst::State::Null);
+                let _ = obj.remove(&element);
+            }
+        }
+
+        let rtp = self.state.lock().unwrap().rtp.clone();
+        if let Some((rtpsend, rtprecv)) = rtp {
+            for (element, name) in [
+                (&rtpsend, format!("rtp_sink_{n}")),
+                (&rtpsend, format!("rtcp_src_{n}")),
+                (&rtprecv, format!("rtcp_sink_{n}")),
+            ] {
+                if let Some(pad) = element.static_pad(&name) {
+                    element.release_request_pad(&pad);
+                }
+            }
+        }
+        let _ = obj.remove_pad(pad);
+    }
+
+    fn change_state(
+        &self,
+        transition: gst::StateChange,
+    ) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        if transition == gst::StateChange::NullToReady {
+            self.start().map_err(|err_msg| {
+                self.post_error_message(err_msg);
+                gst::StateChangeError
+            })?;
+        }
+
+        let ret = self.parent_change_state(transition);
+
+        if transition == gst::StateChange::ReadyToNull
+            || (transition == gst::StateChange::NullToReady && ret.is_err())
+        {
+            self.stop();
+        }
+
+        ret
+    }
+}
+
+impl BinImpl for RtspServerSink {}
+
+impl RtspServerSink {
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        let settings = self.settings.lock().unwrap().clone();
+
+        let address = settings.address.parse::<IpAddr>().map_err(|err| {
+            gst::error_msg!(
+                gst::ResourceError::Settings,
+                ["Invalid address '{}': {err}", settings.address]
+            )
+        })?;
+        if !settings.mount_point.starts_with('/') {
+            return Err(gst::error_msg!(
+                gst::ResourceError::Settings,
+                [
+                    "Mount point '{}' does not start with '/'",
+                    settings.mount_point
+                ]
+            ));
+        }
+        let multicast = match &settings.multicast_address {
+            Some(group) => {
+                let Some(group) = group.parse::<IpAddr>().ok().filter(IpAddr::is_multicast) else {
+                    return Err(gst::error_msg!(
+                        gst::ResourceError::Settings,
+                        ["Invalid multicast address '{group}'"]
+                    ));
+                };
+                Some(MulticastGroup {
+                    address: group,
+                    port: settings.multicast_port as u16,
+                    ttl: settings.multicast_ttl as u8,
+                })
+            }
+            None => None,
+        };
+
+        self.media.set_settings(MediaSettings {
+            credentials: settings
+                .user_id
+                .map(|user_id| (user_id, settings.user_pw.unwrap_or_default())),
+            multicast,
+        });
+
+        let server =
+            Server::get(SocketAddr::new(address, settings.port as u16)).map_err(|err| {
+                gst::error_msg!(
+                    gst::ResourceError::OpenWrite,
+                    ["Failed to start RTSP server: {err:#}"]
+                )
+            })?;
+        server
+            .mount(&settings.mount_point, self.media.clone())
+            .map_err(|err| gst::error_msg!(gst::ResourceError::Busy, ["{err:#}"]))?;
+        gst::info!(
+            CAT,
+            imp = self,
+            "Serving rtsp://{}:{}{}",
+            settings.address,
+            server.port(),
+            settings.mount_point
+        );
+        self.state.lock().unwrap().server = Some((server, settings.mount_point));
+
+        Ok(())
+    }
+
+    fn stop(&self) {
+        let server = self.state.lock().unwrap().server.take();
+        if let Some((server, mount_point)) = server {
+            server.unmount(&mount_point);
+        }
+    }
+
+    /// Returns rtpsend and rtprecv, which are added on first use.
+    fn rtp_elements(&self) -> Result<(gst::Element, gst::Element)> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(rtp) = &state.rtp {
+            return Ok(rtp.clone());
+        }
+
+        // Both elements share the RTP sessions, so that the SRs of rtpsend take the RRs of
+        // rtprecv into account
+        let rtprecv = gst::ElementFactory::make("rtprecv")
+            .build()
+            .context("rtprecv not found")?;
+        let rtpsend = gst::ElementFactory::make("rtpsend")
+            .property("rtp-id", rtprecv.property::<String>("rtp-id"))
+            .build()
+            .context("rtpsend not found")?;
+        self.obj().add_many([&rtpsend, &rtprecv])?;
+        rtpsend.sync_state_with_parent()?;
+        rtprecv.sync_state_with_parent()?;
+
+        state.rtp = Some((rtpsend.clone(), rtprecv.clone()));
+        Ok((rtpsend, rtprecv))
+    }
+
+    /// Links a new stream of the media: `sink_n` is a ghost pad of `rtp_sink_n` of rtpsend, whose
+    /// RTP and RTCP packets are sent to the clients by appsinks, and the RTCP packets of the
+    /// clients go to rtprecv through an appsrc.
+    fn add_stream(&self, templ: &gst::PadTemplate, n: u32) -> Result<gst::Pad> {
+        let obj = self.obj();
+        let (rtpsend, rtprecv) = self.rtp_elements()?;
+
+        let rtcp_appsrc = gst_app::AppSrc::builder()
+            .name(format!("rtcp_appsrc_{n}"))
+            .format(gst::Format::Time)
+            .caps(&RTCP_CAPS)
+            .stream_type(gst_app::AppStreamType::Stream)
+            .is_live(true)
+            .do_timestamp(true)
+            .build();
+        obj.add(&rtcp_appsrc)?;
+        let rtcp_sinkpad = rtprecv
+            .request_pad_simple(&format!("rtcp_sink_{n}"))
+            .context("No RTCP sink pad")?;
+        rtcp_appsrc.static_pad("src").unwrap().link(&rtcp_sinkpad)?;
+
+        let stream = self.media.add_stream(n, rtcp_appsrc.clone());
+
+        // Synchronizes to the clock, for sending the packets of non-live sources in real time
+        let rtp_stream = stream.clone();
+        let rtp_appsink = gst_app::AppSink::builder()
+            .name(format!("rtp_appsink_{n}"))
+            .callbacks(
+                gst_app::AppSinkCallbacks::builder()
+                    .new_sample(move |appsink| {
+                        let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                        rtp_stream.push_sample(sample, false);
+                        Ok(gst::FlowSuccess::Ok)
+                    })
+                    .build(),
+            )
+            .build();
+        let rtcp_appsink = gst_app::AppSink::builder()
+            .name(format!("rtcp_appsink_{n}"))
+            .sync(false)
+            .async_(false)
+            .callbacks(
+                gst_app::AppSinkCallbacks::builder()
+                    .new_sample(move |appsink| {
+                        let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                        stream.push_sample(sample, true);
+                        Ok(gst::FlowSuccess::Ok)
+                    })
+                    .build(),
+            )
+            .build();
+        // Only the RTP appsinks decide when the bin is EOS
+        rtcp_appsink.unset_element_flags(gst::ElementFlags::SINK);
+        obj.add_many([&rtp_appsink, &rtcp_appsink])?;
+
+        let rtp_sinkpad = rtpsend
+            .request_pad_simple(&format!("rtp_sink_{n}"))
+            .context("No RTP sink pad")?;
+        rtpsend
+            .static_pad(&format!("rtp_src_{n}"))
+            .context("No RTP src pad")?
+            .link(&rtp_appsink.static_pad("sink").unwrap())?;
+        rtpsend
+            .request_pad_simple(&format!("rtcp_src_{n}"))
+            .context("No RTCP src pad")?
+            .link(&rtcp_appsink.static_pad("sink").unwrap())?;
+
+        rtcp_appsrc.sync_state_with_parent()?;
+        rtp_appsink.sync_state_with_parent()?;
+        rtcp_appsink.sync_state_with_parent()?;
+
+        let ghostpad = gst::GhostPad::builder_from_template_with_target(templ, &rtp_sinkpad)?
+            .name(format!("sink_{n}"))
+            .build();
+        gst::info!(CAT, imp = self, "Adding sinkpad {}", ghostpad.name());
+        obj.add_pad(&ghostpad)?;
+
+        Ok(ghostpad.upcast())
+    }
+}
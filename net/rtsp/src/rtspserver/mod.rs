@@ -0,0 +1,60 @@
+// GStreamer RTSP Server Sink
+//
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+
+/**
+ * SECTION:element-rtspserversink
+ *
+ * `rtspserversink` serves the RTP streams linked to its `sink_%u` request pads to RTSP clients,
+ * without the need for the C gst-rtsp-server library. It shares the RTSP, SDP and transport
+ * handling with `rtspsrc2`.
+ *
+ * All elements with the same `address` and `port` share one server, each of them serving its
+ * media at its own `mount-point`.
+ *
+ * ## Example launch line
+ *
+ * ```
+ * gst-launch-1.0 videotestsrc is-live=true ! x264enc tune=zerolatency ! rtph264pay config-interval=-1 ! rtspserversink mount-point=/test
+ * ```
+ * Serves an H.264 stream at `rtsp://127.0.0.1:8554/test`.
+ *
+ * Implemented features:
+ * * RTSP 1.0 support: OPTIONS, DESCRIBE, SETUP, PLAY, PAUSE, TEARDOWN and GET_PARAMETER
+ * * Lower transports: TCP, UDP, UDP-Multicast
+ * * RTCP SR and RTCP RR
+ * * Basic authentication
+ * * Sessions that time out after 60 seconds without a request from the client, and that can be
+ *   used from any connection to the server
+ *
+ * Some missing features:
+ * * Digest authentication
+ * * RTSP over TLS (`rtsps://`)
+ * * RECORD and VOD media, clients always receive the live stream
+ *
+ * Since: plugins-rs-0.15.0
+ */
+use gst::glib;
+use gst::prelude::*;
+
+mod imp;
+mod server;
+
+glib::wrapper! {
+    pub struct RtspServerSink(ObjectSubclass<imp::RtspServerSink>) @extends gst::Bin, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "rtspserversink",
+        gst::Rank::NONE,
+        RtspServerSink::static_type(),
+    )
+}
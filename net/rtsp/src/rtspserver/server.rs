@@ -0,0 +1,1194 @@
+// GStreamer RTSP Server
+//
+// Copyright (C) 2026 agent <agent@local>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public License, v2.0.
+// If a copy of the MPL was not distributed with this file, You can obtain one at
+// <https://mozilla.org/MPL/2.0/>.
+//
+// SPDX-License-Identifier: MPL-2.0
+//
+// https://www.rfc-editor.org/rfc/rfc2326.html
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, LazyLock, Mutex, Weak};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use data_encoding::BASE64;
+use futures::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::runtime;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use rtsp_types::headers::{
+    CSeq, RtpLowerTransport, RtpProfile, RtpTransport, RtpTransportParameters, Session, Transport,
+    Transports, AUTHORIZATION, CONTENT_BASE, CONTENT_TYPE, PUBLIC, RANGE, RTP_INFO, SERVER,
+    WWW_AUTHENTICATE,
+};
+use rtsp_types::{Message, Method, Request, Response, StatusCode, Version};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use gst::buffer::{MappedBuffer, Readable};
+use gst::glib;
+
+use crate::rtsp::body::Body;
+use crate::rtsp::transport::RtspTransportInfo;
+use crate::rtsp::{sdp, tcp_message};
+
+use super::imp::CAT;
+
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+const MAX_BIND_PORT_RETRY: u16 = 100;
+const UDP_PACKET_MAX_SIZE: usize = 65535 - 8;
+// https://datatracker.ietf.org/doc/html/rfc2326#section-12.37
+const SESSION_TIMEOUT: u64 = 60;
+// How often sessions are checked for expiry
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// How long DESCRIBE waits for the first packet of a live source
+const DESCRIBE_TIMEOUT: Duration = Duration::from_secs(5);
+// Packets that are queued for a stream or a TCP connection, newer packets are dropped when full
+const STREAM_QUEUE_SIZE: usize = 256;
+const CONNECTION_QUEUE_SIZE: usize = 256;
+const REALM: &str = "GStreamer RTSP Server";
+const PUBLIC_METHODS: &str =
+    "OPTIONS, DESCRIBE, SETUP, PLAY, PAUSE, TEARDOWN, GET_PARAMETER, SET_PARAMETER";
+
+// Hardcoded for now
+const SERVER_NAME: &str = concat!(
+    "GStreamer rtspserversink ",
+    env!("CARGO_PKG_VERSION"),
+    "-",
+    env!("COMMIT_ID")
+);
+
+static RUNTIME: LazyLock<runtime::Runtime> = LazyLock::new(|| {
+    runtime::Builder::new_multi_thread()
+        .enable_all()
+        .worker_threads(1)
+        .build()
+        .unwrap()
+});
+
+// Servers by the address they were requested for, to share them between elements
+static SERVERS: LazyLock<Mutex<HashMap<SocketAddr, Weak<Server>>>> =
+    LazyLock::new(Default::default);
+
+/// Multicast group of a media. Stream `n` is sent to `port + 2 * n`, and its RTCP to the port
+/// after that.
+#[derive(Debug, Clone)]
+pub(crate) struct MulticastGroup {
+    pub address: IpAddr,
+    pub port: u16,
+    pub ttl: u8,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MediaSettings {
+    /// User ID and password for Basic authentication
+    pub credentials: Option<(String, String)>,
+    pub multicast: Option<MulticastGroup>,
+}
+
+/// Live media that is served at a mount point. All clients receive the packets of the same
+/// streams, there is no media pipeline per client.
+#[derive(Debug, Default)]
+pub(crate) struct Media {
+    settings: Mutex<MediaSettings>,
+    streams: Mutex<BTreeMap<u32, Arc<Stream>>>,
+}
+
+impl Media {
+    pub fn set_settings(&self, settings: MediaSettings) {
+        *self.settings.lock().unwrap() = settings;
+        // The multicast group might have changed
+        for stream in self.streams.lock().unwrap().values() {
+            *stream.multicast.lock().unwrap() = None;
+        }
+    }
+
+    /// Adds the stream with the control path `stream=n`. RTCP packets of the clients are pushed
+    /// into `rtcp_appsrc`.
+    pub fn add_stream(&self, n: u32, rtcp_appsrc: gst_app::AppSrc) -> Arc<Stream> {
+        let stream = Stream::new(n, rtcp_appsrc);
+        self.streams.lock().unwrap().insert(n, stream.clone());
+        stream
+    }
+
+    pub fn remove_stream(&self, n: u32) {
+        self.streams.lock().unwrap().remove(&n);
+    }
+
+    fn stream(&self, n: u32) -> Option<Arc<Stream>> {
+        self.streams.lock().unwrap().get(&n).cloned()
+    }
+
+    fn multicast(&self) -> Option<MulticastGroup> {
+        self.settings.lock().unwrap().multicast.clone()
+    }
+
+    /// Checks the `Authorization` header of a request against the credentials of the media.
+    fn authorize(&self, req: &Request<Body>) -> Result<(), StatusCode> {
+        let settings = self.settings.lock().unwrap();
+        let Some((user_id, user_pw)) = &settings.credentials else {
+            return Ok(());
+        };
+
+        let authorized = req
+            .header(&AUTHORIZATION)
+            .and_then(|v| v.as_str().strip_prefix("Basic "))
+            .and_then(|v| BASE64.decode(v.trim().as_bytes()).ok())
+            .is_some_and(|v| constant_time_eq(&v, format!("{user_id}:{user_pw}").as_bytes()));
+
+        if authorized {
+            Ok(())
+        } else {
+            Err(StatusCode::Unauthorized)
+        }
+    }
+
+    /// SDP with all streams whose caps are known, `None` if there are none yet.
+    fn sdp(&self, local: IpAddr) -> Option<String> {
+        let (addrtype, connection) = match local {
+            IpAddr::V4(_) => ("IP4", "IN IP4 0.0.0.0"),
+            IpAddr::V6(_) => ("IP6", "IN IP6 ::"),
+        };
+
+        let medias = self
+            .streams
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|stream| {
+                let caps = stream.caps.lock().unwrap();
+                sdp::format_media(
+                    caps.as_ref()?.structure(0)?,
+                    &format!("stream={}", stream.n),
+                    connection,
+                )
+            })
+            .collect::<String>();
+        if medias.is_empty() {
+            return None;
+        }
+
+        Some(format!(
+            "v=0\r\n\
+             o=- {} 1 IN {addrtype} {local}\r\n\
+             s=Session streamed with GStreamer\r\n\
+             i=rtspserversink\r\n\
+             t=0 0\r\n\
+             a=tool:GStreamer\r\n\
+             a=type:broadcast\r\n\
+             a=control:*\r\n\
+             a=range:npt=now-\r\n\
+             {medias}",
+            glib::random_int(),
+        ))
+    }
+}
+
+/// Compares the hashes of the credentials, so that neither the time nor the length of the
+/// comparison depend on how much of them was guessed right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let (a, b) = (Sha256::digest(a), Sha256::digest(b));
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Where the packets of a stream are sent to for a client.
+#[derive(Debug)]
+enum ClientTransport {
+    Udp {
+        rtp: SocketAddr,
+        rtcp: Option<SocketAddr>,
+    },
+    Multicast,
+    Tcp {
+        channels: (u8, Option<u8>),
+        tx: mpsc::Sender<Message<Body>>,
+    },
+}
+
+#[derive(Debug)]
+struct StreamClient {
+    session_id: String,
+    transport: ClientTransport,
+    playing: bool,
+}
+
+/// Server ports of a stream for the unicast UDP clients.
+#[derive(Debug)]
+struct UdpSockets {
+    rtp: UdpSocket,
+    rtcp: Arc<UdpSocket>,
+    rtcp_task: JoinHandle<()>,
+}
+
+impl Drop for UdpSockets {
+    fn drop(&mut self) {
+        self.rtcp_task.abort();
+    }
+}
+
+#[derive(Debug)]
+struct MulticastSender {
+    socket: UdpSocket,
+    rtp: SocketAddr,
+    rtcp: SocketAddr,
+}
+
+#[derive(Debug)]
+struct Packet {
+    rtcp: bool,
+    map: MappedBuffer<Readable>,
+}
+
+/// Packet that is sent to several TCP clients without copying.
+struct SharedBuffer(Arc<MappedBuffer<Readable>>);
+
+impl AsRef<[u8]> for SharedBuffer {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Stream {
+    n: u32,
+    caps: Mutex<Option<gst::Caps>>,
+    // Sequence number and RTP time of the last packet, for the RTP-Info header
+    last_rtp: Mutex<Option<(u16, u32)>>,
+    clients: Mutex<Vec<StreamClient>>,
+    udp: Mutex<Option<Arc<UdpSockets>>>,
+    multicast: Mutex<Option<Arc<MulticastSender>>>,
+    rtcp_appsrc: gst_app::AppSrc,
+    packet_tx: mpsc::Sender<Packet>,
+}
+
+impl Stream {
+    fn new(n: u32, rtcp_appsrc: gst_app::AppSrc) -> Arc<Self> {
+        let (packet_tx, packet_rx) = mpsc::channel(STREAM_QUEUE_SIZE);
+        let stream = Arc::new(Stream {
+            n,
+            caps: Mutex::new(None),
+            last_rtp: Mutex::new(None),
+            clients: Mutex::new(Vec::new()),
+            udp: Mutex::new(None),
+            multicast: Mutex::new(None),
+            rtcp_appsrc,
+            packet_tx,
+        });
+        RUNTIME.spawn(send_packets(Arc::downgrade(&stream), packet_rx));
+
+        stream
+    }
+
+    /// Queues an RTP or RTCP packet for all playing clients. The caps of RTP samples are used
+    /// for the SDP.
+    pub fn push_sample(&self, sample: gst::Sample, rtcp: bool) {
+        if !rtcp {
+            if let Some(caps) = sample.caps() {
+                let mut current = self.caps.lock().unwrap();
+                if current.as_deref() != Some(caps) {
+                    gst::debug!(CAT, "Stream {} has caps {caps:?}", self.n);
+                    *current = Some(caps.to_owned());
+                }
+            }
+        }
+
+        let Some(buffer) = sample.buffer_owned() else {
+            return;
+        };
+        let Ok(map) = buffer.into_mapped_buffer_readable() else {
+            gst::error!(CAT, "Failed to map buffer of stream {}", self.n);
+            return;
+        };
+        if !rtcp && map.len() >= 8 {
+            let seq = u16::from_be_bytes([map[2], map[3]]);
+            let rtptime = u32::from_be_bytes([map[4], map[5], map[6], map[7]]);
+            *self.last_rtp.lock().unwrap() = Some((seq, rtptime));
+        }
+
+        if !self.clients.lock().unwrap().iter().any(|c| c.playing) {
+            return;
+        }
+
+        match self.packet_tx.try_send(Packet { rtcp, map }) {
+            Ok(()) | Err(mpsc::error::TrySendError::Closed(_)) => (),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                gst::warning!(
+                    CAT,
+                    "Send queue of stream {} is full, dropping packet",
+                    self.n
+                );
+            }
+        }
+    }
+
+    async fn send(&self, packet: Packet) {
+        let Packet { rtcp, map } = packet;
+        let map = Arc::new(map);
+
+        let mut udp_dests = Vec::new();
+        let mut multicast = false;
+        {
+            let clients = self.clients.lock().unwrap();
+            for client in clients.iter().filter(|c| c.playing) {
+                match &client.transport {
+                    ClientTransport::Udp {
+                        rtp,
+                        rtcp: rtcp_dest,
+                    } => {
+                        let dest = if rtcp { *rtcp_dest } else { Some(*rtp) };
+                        if let Some(dest) = dest {
+                            udp_dests.push(dest);
+                        }
+                    }
+                    ClientTransport::Multicast => multicast = true,
+                    ClientTransport::Tcp {
+                        channels: (rtp_channel, rtcp_channel),
+                        tx,
+                    } => {
+                        let channel = if rtcp {
+                            *rtcp_channel
+                        } else {
+                            Some(*rtp_channel)
+                        };
+                        let Some(channel) = channel else {
+                            continue;
+                        };
+                        let data =
+                            rtsp_types::Data::new(channel, Body::custom(SharedBuffer(map.clone())));
+                        // A slow client must not hold back the other clients
+                        if let Err(mpsc::error::TrySendError::Full(_)) =
+                            tx.try_send(Message::Data(data))
+                        {
+                            gst::warning!(
+                                CAT,
+                                "Connection of session {} is too slow, dropping packet",
+                                client.session_id
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        if !udp_dests.is_empty() {
+            let sockets = self.udp.lock().unwrap().clone();
+            if let Some(sockets) = sockets {
+                let socket = if rtcp { &*sockets.rtcp } else { &sockets.rtp };
+                for dest in udp_dests {
+                    if let Err(err) = socket.send_to(&map, dest).await {
+                        gst::warning!(CAT, "Failed to send to {dest}: {err}");
+                    }
+                }
+            }
+        }
+
+        if multicast {
+            let sender = self.multicast.lock().unwrap().clone();
+            if let Some(sender) = sender {
+                let dest = if rtcp { sender.rtcp } else { sender.rtp };
+                if let Err(err) = sender.socket.send_to(&map, dest).await {
+                    gst::warning!(CAT, "Failed to send to {dest}: {err}");
+                }
+            }
+        }
+    }
+
+    fn add_client(&self, session_id: &str, transport: ClientTransport) {
+        let mut clients = self.clients.lock().unwrap();
+        // A new SETUP of the same session replaces the transport
+        clients.retain(|c| c.session_id != session_id);
+        clients.push(StreamClient {
+            session_id: session_id.to_string(),
+            transport,
+            playing: false,
+        });
+    }
+
+    fn remove_client(&self, session_id: &str) {
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|c| c.session_id != session_id);
+    }
+
+    fn set_playing(&self, session_id: &str, playing: bool) {
+        for client in self.clients.lock().unwrap().iter_mut() {
+            if client.session_id == session_id {
+                client.playing = playing;
+            }
+        }
+    }
+
+    /// Expected sequence number and RTP time of the next packet.
+    fn rtp_info(&self) -> Option<(u16, u32)> {
+        self.last_rtp
+            .lock()
+            .unwrap()
+            .map(|(seq, rtptime)| (seq.wrapping_add(1), rtptime))
+    }
+
+    /// Returns the server ports for unicast UDP clients, which are bound on first use.
+    async fn udp_ports(&self, ipv4: bool) -> io::Result<(u16, u16)> {
+        let existing = self.udp.lock().unwrap().clone();
+        let sockets = match existing {
+            Some(sockets) => sockets,
+            None => {
+                let (rtp, rtcp) = bind_port_pair(ipv4).await?;
+                let rtcp = Arc::new(rtcp);
+                let rtcp_task = RUNTIME.spawn(receive_rtcp(rtcp.clone(), self.rtcp_appsrc.clone()));
+                let sockets = Arc::new(UdpSockets {
+                    rtp,
+                    rtcp,
+                    rtcp_task,
+                });
+                // Another connection might have been faster
+                self.udp.lock().unwrap().get_or_insert(sockets).clone()
+            }
+        };
+
+        Ok((
+            sockets.rtp.local_addr()?.port(),
+            sockets.rtcp.local_addr()?.port(),
+        ))
+    }
+
+    /// Returns the RTP and RTCP ports of the stream in the multicast group, and creates the socket
+    /// for sending to it on first use.
+    fn multicast_ports(&self, group: &MulticastGroup) -> io::Result<(u16, u16)> {
+        let rtp_port = u16::try_from(group.port as u32 + 2 * self.n)
+            .ok()
+            .filter(|p| *p < u16::MAX)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "Multicast port out of range")
+            })?;
+
+        let mut multicast = self.multicast.lock().unwrap();
+        if multicast.is_none() {
+            let ip = match group.address {
+                IpAddr::V4(_) => IpAddr::from(Ipv4Addr::UNSPECIFIED),
+                IpAddr::V6(_) => IpAddr::from(Ipv6Addr::UNSPECIFIED),
+            };
+            let socket = std::net::UdpSocket::bind(SocketAddr::new(ip, 0))?;
+            let sock_ref = socket2::SockRef::from(&socket);
+            if ip.is_ipv4() {
+                sock_ref.set_multicast_ttl_v4(group.ttl as u32)?;
+            } else {
+                sock_ref.set_multicast_hops_v6(group.ttl as u32)?;
+            }
+            socket.set_nonblocking(true)?;
+
+            *multicast = Some(Arc::new(MulticastSender {
+                socket: UdpSocket::from_std(socket)?,
+                rtp: SocketAddr::new(group.address, rtp_port),
+                rtcp: SocketAddr::new(group.address, rtp_port + 1),
+            }));
+        }
+
+        Ok((rtp_port, rtp_port + 1))
+    }
+}
+
+async fn send_packets(stream: Weak<Stream>, mut packet_rx: mpsc::Receiver<Packet>) {
+    while let Some(packet) = packet_rx.recv().await {
+        let Some(stream) = stream.upgrade() else {
+            break;
+        };
+        stream.send(packet).await;
+    }
+}
+
+async fn bind_port_pair(ipv4: bool) -> io::Result<(UdpSocket, UdpSocket)> {
+    let ip = if ipv4 {
+        IpAddr::from(Ipv4Addr::UNSPECIFIED)
+    } else {
+        IpAddr::from(Ipv6Addr::UNSPECIFIED)
+    };
+
+    for _ in 0..MAX_BIND_PORT_RETRY {
+        let rtp = UdpSocket::bind(SocketAddr::new(ip, 0)).await?;
+        let port = rtp.local_addr()?.port();
+        // RTP uses an even port and RTCP the next one
+        if port % 2 != 0 {
+            continue;
+        }
+        if let Ok(rtcp) = UdpSocket::bind(SocketAddr::new(ip, port + 1)).await {
+            return Ok((rtp, rtcp));
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AddrInUse,
+        "No free port pair for RTP and RTCP",
+    ))
+}
+
+/// Forwards the RTCP packets of the unicast UDP clients to `rtprecv`.
+async fn receive_rtcp(socket: Arc<UdpSocket>, appsrc: gst_app::AppSrc) {
+    let mut buf = vec![0; UDP_PACKET_MAX_SIZE];
+    loop {
+        match socket.recv_from(&mut buf).await {
+            Ok((len, addr)) => {
+                gst::trace!(CAT, "Received {len} bytes of RTCP from {addr}");
+                // Fails while the element is not running
+                let _ = appsrc.push_buffer(gst::Buffer::from_slice(buf[..len].to_vec()));
+            }
+            // ICMP port unreachable of a previous packet on Windows
+            Err(err) if err.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(err) => {
+                gst::warning!(CAT, "Failed to receive RTCP: {err}");
+                break;
+            }
+        }
+    }
+}
+
+/// RTSP server for the media that are mounted on it. Elements with the same address share the
+/// server, so a single port can serve several mount points.
+#[derive(Debug)]
+pub(crate) struct Server {
+    addr: SocketAddr,
+    mounts: Mutex<HashMap<String, Arc<Media>>>,
+    // Sessions of all connections by their ID, a session can be used from any connection
+    sessions: Mutex<HashMap<String, ClientSession>>,
+    accept_task: JoinHandle<()>,
+    expire_task: JoinHandle<()>,
+    connections: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Server {
+    /// Returns the server that listens on `addr`, starting one if needed. A port of 0 always
+    /// starts a new server.
+    pub fn get(addr: SocketAddr) -> Result<Arc<Server>> {
+        let mut servers = SERVERS.lock().unwrap();
+        servers.retain(|_, server| server.strong_count() > 0);
+        if addr.port() != 0 {
+            if let Some(server) = servers.get(&addr).and_then(Weak::upgrade) {
+                return Ok(server);
+            }
+        }
+
+        let listener = std::net::TcpListener::bind(addr)
+            .with_context(|| format!("Failed to listen on {addr}"))?;
+        listener.set_nonblocking(true)?;
+        let listener = {
+            let _guard = RUNTIME.enter();
+            TcpListener::from_std(listener)?
+        };
+        let local_addr = listener.local_addr()?;
+        gst::info!(CAT, "Listening on {local_addr}");
+
+        let server = Arc::new_cyclic(|server: &Weak<Server>| Server {
+            addr: local_addr,
+            mounts: Mutex::new(HashMap::new()),
+            sessions: Mutex::new(HashMap::new()),
+            accept_task: RUNTIME.spawn(accept(listener, server.clone())),
+            expire_task: RUNTIME.spawn(expire_sessions(server.clone())),
+            connections: Mutex::new(Vec::new()),
+        });
+        servers.insert(local_addr, Arc::downgrade(&server));
+
+        Ok(server)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    pub fn mount(&self, path: &str, media: Arc<Media>) -> Result<()> {
+        let path = path.trim_end_matches('/');
+        let mut mounts = self.mounts.lock().unwrap();
+        if mounts.contains_key(path) {
+            anyhow::bail!("Mount point '{path}' is already used on {}", self.addr);
+        }
+        mounts.insert(path.to_string(), media);
+
+        Ok(())
+    }
+
+    /// Unmounts the media at `path` and ends all its sessions.
+    pub fn unmount(&self, path: &str) {
+        let Some(media) = self
+            .mounts
+            .lock()
+            .unwrap()
+            .remove(path.trim_end_matches('/'))
+        else {
+            return;
+        };
+
+        let removed = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let ids = sessions
+                .iter()
+                .filter(|(_, session)| Arc::ptr_eq(&session.media, &media))
+                .map(|(id, _)| id.clone())
+                .collect::<Vec<_>>();
+            ids.into_iter()
+                .filter_map(|id| sessions.remove_entry(&id))
+                .collect::<Vec<_>>()
+        };
+        for (session_id, session) in removed {
+            session.end(&session_id);
+        }
+    }
+
+    fn media(&self, path: &str) -> Option<Arc<Media>> {
+        self.mounts.lock().unwrap().get(path).cloned()
+    }
+
+    /// Looks up a session and keeps it alive.
+    fn session(&self, session_id: &str) -> Option<ClientSession> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(session_id)?;
+        session.last_activity = time::Instant::now();
+
+        Some(session.clone())
+    }
+
+    /// Adds a stream to a session, which is created if needed.
+    fn add_session_stream(&self, session_id: &str, media: Arc<Media>, stream: Arc<Stream>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .entry(session_id.to_string())
+            .or_insert_with(|| ClientSession {
+                media,
+                streams: Vec::new(),
+                last_activity: time::Instant::now(),
+            });
+        session.last_activity = time::Instant::now();
+        if !session.streams.iter().any(|s| Arc::ptr_eq(s, &stream)) {
+            session.streams.push(stream);
+        }
+    }
+
+    fn remove_session(&self, session_id: &str) -> Option<ClientSession> {
+        let session = self.sessions.lock().unwrap().remove(session_id)?;
+        session.end(session_id);
+
+        Some(session)
+    }
+
+    /// Ends the sessions that were not kept alive within the session timeout.
+    fn expire_sessions(&self) {
+        let timeout = Duration::from_secs(SESSION_TIMEOUT);
+        let expired = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let ids = sessions
+                .iter()
+                .filter(|(_, session)| session.last_activity.elapsed() > timeout)
+                .map(|(id, _)| id.clone())
+                .collect::<Vec<_>>();
+            ids.into_iter()
+                .filter_map(|id| sessions.remove_entry(&id))
+                .collect::<Vec<_>>()
+        };
+        for (session_id, session) in expired {
+            gst::info!(CAT, "Session {session_id} timed out");
+            session.end(&session_id);
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        gst::info!(CAT, "Stopped listening on {}", self.addr);
+        self.accept_task.abort();
+        self.expire_task.abort();
+        for connection in self.connections.get_mut().unwrap().drain(..) {
+            connection.abort();
+        }
+        for (session_id, session) in self.sessions.get_mut().unwrap().drain() {
+            session.end(&session_id);
+        }
+    }
+}
+
+async fn accept(listener: TcpListener, server: Weak<Server>) {
+    loop {
+        let (socket, peer) = match listener.accept().await {
+            Ok(v) => v,
+            Err(err) => {
+                // e.g. too many open files, which is not going to change immediately
+                gst::warning!(CAT, "Failed to accept connection: {err}");
+                time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let Some(server_ref) = server.upgrade() else {
+            break;
+        };
+
+        gst::debug!(CAT, "New connection from {peer}");
+        let connection = RUNTIME.spawn(handle_connection(socket, peer, server.clone()));
+        let mut connections = server_ref.connections.lock().unwrap();
+        connections.retain(|c| !c.is_finished());
+        connections.push(connection);
+    }
+}
+
+async fn expire_sessions(server: Weak<Server>) {
+    let mut interval = time::interval(SESSION_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(server) = server.upgrade() else {
+            break;
+        };
+        server.expire_sessions();
+    }
+}
+
+async fn handle_connection(socket: TcpStream, peer: SocketAddr, server: Weak<Server>) {
+    let local = match socket.local_addr() {
+        Ok(local) => local,
+        Err(err) => {
+            gst::warning!(CAT, "Connection from {peer} failed: {err}");
+            return;
+        }
+    };
+    let _ = socket.set_nodelay(true);
+
+    let (read, write) = socket.into_split();
+    let mut messages = Box::pin(tcp_message::async_read(read, MAX_MESSAGE_SIZE));
+    let mut sink = Box::pin(tcp_message::async_write(write));
+
+    // Responses and interleaved data are written by a single task, which ends once the connection
+    // and all its clients are gone
+    let (tx, mut rx) = mpsc::channel::<Message<Body>>(CONNECTION_QUEUE_SIZE);
+    RUNTIME.spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            if let Err(err) = sink.send(msg).await {
+                gst::debug!(CAT, "Failed to write to {peer}: {err}");
+                break;
+            }
+        }
+    });
+
+    let mut connection = Connection {
+        server,
+        peer,
+        local,
+        tx,
+        interleaved_sessions: HashSet::new(),
+        rtcp_channels: HashMap::new(),
+    };
+    while let Some(msg) = messages.next().await {
+        match msg {
+            Ok(Message::Request(req)) => {
+                let rsp = connection.handle_request(req).await;
+                if connection.tx.send(rsp.into()).await.is_err() {
+                    break;
+                }
+            }
+            Ok(Message::Data(data)) => connection.handle_data(data),
+            Ok(Message::Response(rsp)) => {
+                gst::debug!(CAT, "Ignoring response from {peer}: {rsp:?}");
+            }
+            Err(err) => {
+                gst::debug!(CAT, "Failed to read from {peer}: {err}");
+                break;
+            }
+        }
+    }
+
+    gst::debug!(CAT, "Connection from {peer} closed");
+}
+
+/// Session of a client. Sessions end with TEARDOWN, or when no request kept them alive within the
+/// session timeout. Sessions with interleaved transports also end together with their connection.
+#[derive(Debug, Clone)]
+struct ClientSession {
+    media: Arc<Media>,
+    streams: Vec<Arc<Stream>>,
+    last_activity: time::Instant,
+}
+
+impl ClientSession {
+    fn end(&self, session_id: &str) {
+        gst::debug!(CAT, "Ending session {session_id}");
+        for stream in &self.streams {
+            stream.remove_client(session_id);
+        }
+    }
+}
+
+struct Connection {
+    server: Weak<Server>,
+    peer: SocketAddr,
+    local: SocketAddr,
+    tx: mpsc::Sender<Message<Body>>,
+    // Sessions whose packets are sent over this connection
+    interleaved_sessions: HashSet<String>,
+    // Streams by the RTCP channel of their interleaved transport
+    rtcp_channels: HashMap<u8, Arc<Stream>>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let Some(server) = self.server.upgrade() else {
+            return;
+        };
+        for session_id in self.interleaved_sessions.drain() {
+            server.remove_session(&session_id);
+        }
+    }
+}
+
+impl Connection {
+    async fn handle_request(&mut self, req: Request<Body>) -> Response<Body> {
+        gst::debug!(CAT, "{} <<-- {req:#?}", self.peer);
+
+        let res = match req.method() {
+            Method::Options => self.options(&req),
+            Method::Describe => self.describe(&req).await,
+            Method::Setup => self.setup(&req).await,
+            Method::Play => self.play(&req),
+            Method::Pause => self.pause(&req),
+            Method::Teardown => self.teardown(&req),
+            // Used by clients to keep the session alive
+            Method::GetParameter | Method::SetParameter => self.keep_alive(&req),
+            _ => Err(StatusCode::NotImplemented),
+        };
+
+        let mut rsp = res.unwrap_or_else(|status| {
+            let unauthorized = status == StatusCode::Unauthorized;
+            let mut rsp = Response::builder(Version::V1_0, status).build(Body::default());
+            if unauthorized {
+                rsp.insert_header(WWW_AUTHENTICATE, format!("Basic realm=\"{REALM}\""));
+            }
+            rsp
+        });
+        if let Ok(Some(cseq)) = req.typed_header::<CSeq>() {
+            rsp.insert_typed_header::<CSeq>(&cseq);
+        }
+        rsp.insert_header(SERVER, SERVER_NAME);
+        gst::debug!(CAT, "{} -->> {rsp:#?}", self.peer);
+
+        rsp
+    }
+
+    fn server(&self) -> Result<Arc<Server>, StatusCode> {
+        self.server.upgrade().ok_or(StatusCode::ServiceUnavailable)
+    }
+
+    /// Looks up the media of the request URI, and the stream for URIs with the `stream=n`
+    /// control path of the SDP.
+    fn media(&self, req: &Request<Body>) -> Result<(Arc<Media>, Option<u32>), StatusCode> {
+        let server = self.server()?;
+        let path = req
+            .request_uri()
+            .map(Url::path)
+            .ok_or(StatusCode::BadRequest)?
+            .trim_end_matches('/');
+
+        if let Some(media) = server.media(path) {
+            return Ok((media, None));
+        }
+
+        let (mount, control) = path.rsplit_once('/').ok_or(StatusCode::NotFound)?;
+        let n = control
+            .strip_prefix("stream=")
+            .and_then(|n| n.parse::<u32>().ok())
+            .ok_or(StatusCode::NotFound)?;
+        let media = server
+            .media(mount.trim_end_matches('/'))
+            .ok_or(StatusCode::NotFound)?;
+
+        Ok((media, Some(n)))
+    }
+
+    /// Looks up the session of a request and keeps it alive.
+    fn session(&self, req: &Request<Body>) -> Result<(String, ClientSession), StatusCode> {
+        let Ok(Some(Session(session_id, _))) = req.typed_header::<Session>() else {
+            return Err(StatusCode::SessionNotFound);
+        };
+        let session = self
+            .server()?
+            .session(&session_id)
+            .ok_or(StatusCode::SessionNotFound)?;
+
+        Ok((session_id, session))
+    }
+
+    fn options(&self, req: &Request<Body>) -> Result<Response<Body>, StatusCode> {
+        // Also used by clients to keep the session alive
+        if let Ok(Some(Session(session_id, _))) = req.typed_header::<Session>() {
+            self.server()?.session(&session_id);
+        }
+
+        Ok(Response::builder(Version::V1_0, StatusCode::Ok)
+            .header(PUBLIC, PUBLIC_METHODS)
+            .build(Body::default()))
+    }
+
+    async fn describe(&self, req: &Request<Body>) -> Result<Response<Body>, StatusCode> {
+        let (media, _) = self.media(req)?;
+        media.authorize(req)?;
+
+        let deadline = time::Instant::now() + DESCRIBE_TIMEOUT;
+        let sdp = loop {
+            if let Some(sdp) = media.sdp(self.local.ip()) {
+                break sdp;
+            }
+            if time::Instant::now() >= deadline {
+                gst::info!(CAT, "No caps for any stream yet");
+                return Err(StatusCode::ServiceUnavailable);
+            }
+            time::sleep(Duration::from_millis(100)).await;
+        };
+        let uri = req.request_uri().ok_or(StatusCode::BadRequest)?;
+
+        Ok(Response::builder(Version::V1_0, StatusCode::Ok)
+            .header(
+                CONTENT_BASE,
+                format!("{}/", uri.as_str().trim_end_matches('/')),
+            )
+            .header(CONTENT_TYPE, "application/sdp")
+            .build(Body::from(sdp.into_bytes())))
+    }
+
+    async fn setup(&mut self, req: &Request<Body>) -> Result<Response<Body>, StatusCode> {
+        let (media, Some(n)) = self.media(req)? else {
+            // Aggregate SETUP is not supported
+            return Err(StatusCode::NotFound);
+        };
+        media.authorize(req)?;
+        let stream = media.stream(n).ok_or(StatusCode::NotFound)?;
+        let Ok(Some(transports)) = req.typed_header::<Transports>() else {
+            return Err(StatusCode::BadRequest);
+        };
+
+        let server = self.server()?;
+        let session_id = match req.typed_header::<Session>() {
+            Ok(Some(Session(session_id, _))) => {
+                let session = server
+                    .session(&session_id)
+                    .ok_or(StatusCode::SessionNotFound)?;
+                if !Arc::ptr_eq(&session.media, &media) {
+                    return Err(StatusCode::BadRequest);
+                }
+                session_id
+            }
+            _ => format!("{:016x}", rand::random::<u64>()),
+        };
+
+        let (transport, rsp_transport) =
+            self.select_transport(&media, &stream, &transports).await?;
+        if let ClientTransport::Tcp { channels, .. } = &transport {
+            if let (_, Some(rtcp_channel)) = channels {
+                self.rtcp_channels.insert(*rtcp_channel, stream.clone());
+            }
+            self.interleaved_sessions.insert(session_id.clone());
+        }
+        stream.add_client(&session_id, transport);
+        server.add_session_stream(&session_id, media, stream);
+
+        let transports: Transports = [rsp_transport].as_slice().into();
+        Ok(Response::builder(Version::V1_0, StatusCode::Ok)
+            .typed_header::<Transports>(&transports)
+            .typed_header::<Session>(&Session(session_id, Some(SESSION_TIMEOUT)))
+            .build(Body::default()))
+    }
+
+    /// Selects the first of the client's transports that we support, and returns the transport
+    /// for the response.
+    async fn select_transport(
+        &self,
+        media: &Media,
+        stream: &Stream,
+        transports: &Transports,
+    ) -> Result<(ClientTransport, Transport), StatusCode> {
+        for transport in transports.iter() {
+            let Transport::Rtp(t) = transport else {
+                continue;
+            };
+
+            // Clients don't know the multicast group yet, which RtspTransportInfo requires
+            if t.params.multicast {
+                let Some(group) = media.multicast() else {
+                    continue;
+                };
+                let (rtp_port, rtcp_port) = match stream.multicast_ports(&group) {
+                    Ok(ports) => ports,
+                    Err(err) => {
+                        gst::error!(CAT, "Failed to set up multicast: {err}");
+                        continue;
+                    }
+                };
+                let params = RtpTransportParameters {
+                    multicast: true,
+                    destination: Some(group.address.to_string()),
+                    port: Some((rtp_port, Some(rtcp_port))),
+                    ttl: Some(group.ttl),
+                    ..Default::default()
+                };
+                return Ok((
+                    ClientTransport::Multicast,
+                    Transport::Rtp(RtpTransport {
+                        profile: RtpProfile::Avp,
+                        lower_transport: Some(RtpLowerTransport::Udp),
+                        params,
+                    }),
+                ));
+            }
+
+            match RtspTransportInfo::try_from(t) {
+                Ok(RtspTransportInfo::Tcp { channels }) => {
+                    let params = RtpTransportParameters {
+                        unicast: true,
+                        interleaved: Some(channels),
+                        ..Default::default()
+                    };
+                    return Ok((
+                        ClientTransport::Tcp {
+                            channels,
+                            tx: self.tx.clone(),
+                        },
+                        Transport::Rtp(RtpTransport {
+                            profile: RtpProfile::Avp,
+                            lower_transport: Some(RtpLowerTransport::Tcp),
+                            params,
+                        }),
+                    ));
+                }
+                Ok(RtspTransportInfo::Udp {
+                    client_port: Some((rtp_port, rtcp_port)),
+                    ..
+                }) => {
+                    let (server_rtp_port, server_rtcp_port) =
+                        match stream.udp_ports(self.peer.is_ipv4()).await {
+                            Ok(ports) => ports,
+                            Err(err) => {
+                                gst::error!(CAT, "Failed to bind UDP ports: {err}");
+                                continue;
+                            }
+                        };
+                    // Packets go to the address of the connection, not to a destination chosen
+                    // by the client
+                    let ip = self.peer.ip();
+                    let params = RtpTransportParameters {
+                        unicast: true,
+                        client_port: Some((rtp_port, rtcp_port)),
+                        server_port: Some((server_rtp_port, Some(server_rtcp_port))),
+                        ..Default::default()
+                    };
+                    return Ok((
+                        ClientTransport::Udp {
+                            rtp: SocketAddr::new(ip, rtp_port),
+                            rtcp: rtcp_port.map(|port| SocketAddr::new(ip, port)),
+                        },
+                        Transport::Rtp(RtpTransport {
+                            profile: RtpProfile::Avp,
+                            lower_transport: Some(RtpLowerTransport::Udp),
+                            params,
+                        }),
+                    ));
+                }
+                _ => continue,
+            }
+        }
+
+        Err(StatusCode::UnsupportedTransport)
+    }
+
+    fn play(&self, req: &Request<Body>) -> Result<Response<Body>, StatusCode> {
+        let (session_id, session) = self.session(req)?;
+        session.media.authorize(req)?;
+
+        // RTP-Info URLs are relative to the aggregate control URL
+        let uri = req.request_uri().ok_or(StatusCode::BadRequest)?.as_str();
+        let uri = uri.trim_end_matches('/');
+        let base = match uri.rsplit_once('/') {
+            Some((base, control)) if control.starts_with("stream=") => base,
+            _ => uri,
+        };
+
+        let mut rtp_info = Vec::new();
+        for stream in &session.streams {
+            stream.set_playing(&session_id, true);
+            if let Some((seq, rtptime)) = stream.rtp_info() {
+                rtp_info.push(format!(
+                    "url={base}/stream={};seq={seq};rtptime={rtptime}",
+                    stream.n
+                ));
+            }
+        }
+
+        let mut rsp = Response::builder(Version::V1_0, StatusCode::Ok)
+            .header(RANGE, "npt=now-")
+            .typed_header::<Session>(&Session(session_id, Some(SESSION_TIMEOUT)))
+            .build(Body::default());
+        if !rtp_info.is_empty() {
+            rsp.insert_header(RTP_INFO, rtp_info.join(","));
+        }
+
+        Ok(rsp)
+    }
+
+    fn pause(&self, req: &Request<Body>) -> Result<Response<Body>, StatusCode> {
+        let (session_id, session) = self.session(req)?;
+        session.media.authorize(req)?;
+
+        for stream in &session.streams {
+            stream.set_playing(&session_id, false);
+        }
+
+        Ok(Response::builder(Version::V1_0, StatusCode::Ok)
+            .typed_header::<Session>(&Session(session_id, Some(SESSION_TIMEOUT)))
+            .build(Body::default()))
+    }
+
+    fn teardown(&mut self, req: &Request<Body>) -> Result<Response<Body>, StatusCode> {
+        let (session_id, session) = self.session(req)?;
+        session.media.authorize(req)?;
+
+        // Might have timed out in the meantime
+        let session = self
+            .server()?
+            .remove_session(&session_id)
+            .ok_or(StatusCode::SessionNotFound)?;
+        self.interleaved_sessions.remove(&session_id);
+        self.rtcp_channels
+            .retain(|_, stream| !session.streams.iter().any(|s| Arc::ptr_eq(s, stream)));
+
+        Ok(Response::builder(Version::V1_0, StatusCode::Ok).build(Body::default()))
+    }
+
+    fn keep_alive(&self, req: &Request<Body>) -> Result<Response<Body>, StatusCode> {
+        let Ok(Some(_)) = req.typed_header::<Session>() else {
+            return Ok(Response::builder(Version::V1_0, StatusCode::Ok).build(Body::default()));
+        };
+        let (session_id, _) = self.session(req)?;
+
+        Ok(Response::builder(Version::V1_0, StatusCode::Ok)
+            .typed_header::<Session>(&Session(session_id, Some(SESSION_TIMEOUT)))
+            .build(Body::default()))
+    }
+
+    /// Forwards the RTCP packets of interleaved clients to `rtprecv`.
+    fn handle_data(&self, data: rtsp_types::Data<Body>) {
+        let Some(stream) = self.rtcp_channels.get(&data.channel_id()) else {
+            gst::trace!(
+                CAT,
+                "Ignoring data on channel {} from {}",
+                data.channel_id(),
+                self.peer
+            );
+            return;
+        };
+
+        // Fails while the element is not running
+        let _ = stream
+            .rtcp_appsrc
+            .push_buffer(gst::Buffer::from_slice(data.into_body()));
+    }
+}
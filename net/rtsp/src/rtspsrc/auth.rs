@@ -0,0 +1,377 @@
+// GStreamer RTSP Source 2
+//
+// SPDX-License-Identifier: MPL-2.0
+//
+// https://www.rfc-editor.org/rfc/rfc2617.html
+// https://www.rfc-editor.org/rfc/rfc7616.html
+
+use std::collections::HashMap;
+
+use data_encoding::{BASE64, HEXLOWER};
+use gst::glib;
+use md5::{Digest, Md5};
+use sha2::Sha256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DigestAlgorithm {
+    Md5,
+    Sha256,
+}
+
+impl DigestAlgorithm {
+    fn name(self) -> &'static str {
+        match self {
+            DigestAlgorithm::Md5 => "MD5",
+            DigestAlgorithm::Sha256 => "SHA-256",
+        }
+    }
+
+    fn hash(self, data: &str) -> String {
+        match self {
+            DigestAlgorithm::Md5 => HEXLOWER.encode(&Md5::digest(data.as_bytes())),
+            DigestAlgorithm::Sha256 => HEXLOWER.encode(&Sha256::digest(data.as_bytes())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestChallenge {
+    pub realm: String,
+    pub nonce: String,
+    pub opaque: Option<String>,
+    pub algorithm: DigestAlgorithm,
+    /// Whether the server offered `qop=auth`. `auth-int` is not supported.
+    pub qop_auth: bool,
+    pub stale: bool,
+}
+
+/// A single challenge from a `WWW-Authenticate` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Challenge {
+    Basic,
+    Digest(DigestChallenge),
+}
+
+impl Challenge {
+    /// Higher is better.
+    fn strength(&self) -> u8 {
+        match self {
+            Challenge::Basic => 0,
+            Challenge::Digest(DigestChallenge {
+                algorithm: DigestAlgorithm::Md5,
+                ..
+            }) => 1,
+            Challenge::Digest(DigestChallenge {
+                algorithm: DigestAlgorithm::Sha256,
+                ..
+            }) => 2,
+        }
+    }
+
+    fn from_params(scheme: &str, mut params: HashMap<String, String>) -> Option<Self> {
+        match scheme {
+            "basic" => Some(Challenge::Basic),
+            "digest" => {
+                let algorithm = match params.get("algorithm").map(String::as_str) {
+                    None => DigestAlgorithm::Md5,
+                    Some(a) if a.eq_ignore_ascii_case("MD5") => DigestAlgorithm::Md5,
+                    Some(a) if a.eq_ignore_ascii_case("SHA-256") => DigestAlgorithm::Sha256,
+                    // Session variants and SHA-512-256 are not supported
+                    Some(_) => return None,
+                };
+                let qop_auth = match params.get("qop") {
+                    None => false,
+                    Some(qop) if qop.split(',').any(|q| q.trim() == "auth") => true,
+                    // Only auth-int offered
+                    Some(_) => return None,
+                };
+
+                Some(Challenge::Digest(DigestChallenge {
+                    realm: params.remove("realm")?,
+                    nonce: params.remove("nonce")?,
+                    opaque: params.remove("opaque"),
+                    algorithm,
+                    qop_auth,
+                    stale: params
+                        .get("stale")
+                        .is_some_and(|s| s.eq_ignore_ascii_case("true")),
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Splits `s` at every `,` that is not inside a quoted string.
+fn split_unquoted(s: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for (idx, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                items.push(&s[start..idx]);
+                start = idx + 1;
+            }
+            _ => (),
+        }
+    }
+    items.push(&s[start..]);
+
+    items
+}
+
+fn unquote(s: &str) -> String {
+    let Some(s) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) else {
+        return s.to_string();
+    };
+
+    let mut res = String::with_capacity(s.len());
+    let mut escaped = false;
+    for c in s.chars() {
+        if c == '\\' && !escaped {
+            escaped = true;
+            continue;
+        }
+        escaped = false;
+        res.push(c);
+    }
+
+    res
+}
+
+/// Parses all supported challenges of a `WWW-Authenticate` header.
+///
+/// Multiple `WWW-Authenticate` headers are merged into a single comma separated value, so one
+/// header value can contain multiple challenges.
+pub fn parse_challenges(value: &str) -> Vec<Challenge> {
+    let mut challenges = Vec::new();
+    let mut current: Option<(String, HashMap<String, String>)> = None;
+
+    let mut finish = |current: Option<(String, HashMap<String, String>)>| {
+        if let Some((scheme, params)) = current {
+            if let Some(challenge) = Challenge::from_params(&scheme, params) {
+                challenges.push(challenge);
+            }
+        }
+    };
+
+    for item in split_unquoted(value) {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        // A new challenge starts with the scheme, optionally followed by its first parameter
+        let param = match item.split_once(|c: char| c.is_ascii_whitespace()) {
+            Some((scheme, rest)) if !scheme.contains('=') => {
+                finish(current.take());
+                current = Some((scheme.to_ascii_lowercase(), HashMap::new()));
+                rest.trim()
+            }
+            None if !item.contains('=') => {
+                finish(current.take());
+                current = Some((item.to_ascii_lowercase(), HashMap::new()));
+                continue;
+            }
+            _ => item,
+        };
+
+        if let (Some((_, params)), Some((key, value))) = (current.as_mut(), param.split_once('=')) {
+            params.insert(key.trim().to_ascii_lowercase(), unquote(value.trim()));
+        }
+    }
+    finish(current.take());
+
+    challenges
+}
+
+#[allow(clippy::too_many_arguments)]
+fn digest_response(
+    algorithm: DigestAlgorithm,
+    username: &str,
+    password: &str,
+    realm: &str,
+    method: &str,
+    uri: &str,
+    nonce: &str,
+    qop: Option<(u32, &str)>,
+) -> String {
+    let ha1 = algorithm.hash(&format!("{username}:{realm}:{password}"));
+    let ha2 = algorithm.hash(&format!("{method}:{uri}"));
+
+    match qop {
+        Some((nc, cnonce)) => {
+            algorithm.hash(&format!("{ha1}:{nonce}:{nc:08x}:{cnonce}:auth:{ha2}"))
+        }
+        None => algorithm.hash(&format!("{ha1}:{nonce}:{ha2}")),
+    }
+}
+
+/// Creates the `Authorization` headers for all requests after a server challenge.
+#[derive(Debug)]
+pub struct Authenticator {
+    username: String,
+    password: String,
+    challenge: Challenge,
+    nonce_count: u32,
+    cnonce: String,
+}
+
+impl Authenticator {
+    /// Selects the strongest of the supported `challenges`.
+    pub fn new(username: &str, password: &str, challenges: Vec<Challenge>) -> Option<Self> {
+        let challenge = challenges.into_iter().max_by_key(Challenge::strength)?;
+
+        Some(Authenticator {
+            username: username.to_string(),
+            password: password.to_string(),
+            challenge,
+            nonce_count: 0,
+            cnonce: format!("{:08x}{:08x}", glib::random_int(), glib::random_int()),
+        })
+    }
+
+    pub fn challenge(&self) -> &Challenge {
+        &self.challenge
+    }
+
+    /// Value of the `Authorization` header for a request.
+    pub fn authorization(&mut self, method: &str, uri: &str) -> String {
+        let digest = match &self.challenge {
+            Challenge::Basic => {
+                let credentials = format!("{}:{}", self.username, self.password);
+                return format!("Basic {}", BASE64.encode(credentials.as_bytes()));
+            }
+            Challenge::Digest(digest) => digest,
+        };
+
+        let qop = if digest.qop_auth {
+            self.nonce_count += 1;
+            Some((self.nonce_count, self.cnonce.as_str()))
+        } else {
+            None
+        };
+
+        let response = digest_response(
+            digest.algorithm,
+            &self.username,
+            &self.password,
+            &digest.realm,
+            method,
+            uri,
+            &digest.nonce,
+            qop,
+        );
+
+        let mut value = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{uri}\", response=\"{response}\", algorithm={}",
+            self.username,
+            digest.realm,
+            digest.nonce,
+            digest.algorithm.name(),
+        );
+        if let Some(opaque) = &digest.opaque {
+            value.push_str(&format!(", opaque=\"{opaque}\""));
+        }
+        if let Some((nc, cnonce)) = qop {
+            value.push_str(&format!(", qop=auth, nc={nc:08x}, cnonce=\"{cnonce}\""));
+        }
+
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenges() {
+        let challenges = parse_challenges(
+            "Digest realm=\"Camera, Inc.\", nonce=\"abc\", algorithm=SHA-256, qop=\"auth,auth-int\", stale=TRUE, Basic realm=\"Camera\"",
+        );
+        assert_eq!(
+            challenges,
+            vec![
+                Challenge::Digest(DigestChallenge {
+                    realm: "Camera, Inc.".to_string(),
+                    nonce: "abc".to_string(),
+                    opaque: None,
+                    algorithm: DigestAlgorithm::Sha256,
+                    qop_auth: true,
+                    stale: true,
+                }),
+                Challenge::Basic,
+            ]
+        );
+
+        // Unsupported schemes and algorithms are skipped
+        let challenges = parse_challenges(
+            "Negotiate, Digest realm=\"r\", nonce=\"n\", algorithm=MD5-sess, Digest realm=\"r\", nonce=\"n\"",
+        );
+        assert_eq!(
+            challenges,
+            vec![Challenge::Digest(DigestChallenge {
+                realm: "r".to_string(),
+                nonce: "n".to_string(),
+                opaque: None,
+                algorithm: DigestAlgorithm::Md5,
+                qop_auth: false,
+                stale: false,
+            })]
+        );
+    }
+
+    #[test]
+    fn digest() {
+        // RFC 2617 section 3.5
+        assert_eq!(
+            digest_response(
+                DigestAlgorithm::Md5,
+                "Mufasa",
+                "Circle Of Life",
+                "testrealm@host.com",
+                "GET",
+                "/dir/index.html",
+                "dcd98b7102dd2f0e8b11d0f600bfb0c093",
+                Some((1, "0a4f113b")),
+            ),
+            "6629fae49393a05397450978507c4ef1"
+        );
+
+        // RFC 7616 section 3.9.1
+        assert_eq!(
+            digest_response(
+                DigestAlgorithm::Sha256,
+                "Mufasa",
+                "Circle of Life",
+                "http-auth@example.org",
+                "GET",
+                "/dir/index.html",
+                "7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v",
+                Some((1, "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ")),
+            ),
+            "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1"
+        );
+    }
+
+    #[test]
+    fn basic() {
+        let mut auth = Authenticator::new(
+            "Aladdin",
+            "open sesame",
+            parse_challenges("Basic realm=\"WallyWorld\""),
+        )
+        .unwrap();
+        assert_eq!(
+            auth.authorization("DESCRIBE", "rtsp://localhost/"),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+    }
+}
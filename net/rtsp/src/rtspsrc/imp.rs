@@ -11,16 +11,15 @@
 //
 // https://www.rfc-editor.org/rfc/rfc2326.html
 
-use std::collections::{btree_set::BTreeSet, HashMap};
+use std::collections::{btree_set::BTreeSet, HashMap, VecDeque};
 use std::convert::TryFrom;
-use std::fmt;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::num::NonZeroUsize;
 use std::pin::Pin;
-use std::sync::Mutex;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::sync::LazyLock;
 
 use futures::{Sink, SinkExt, Stream, StreamExt};
@@ -33,8 +32,9 @@ use tokio::time;
 
 use rtsp_types::headers::{
     CSeq, NptRange, NptTime, Public, Range, RtpInfos, RtpLowerTransport, RtpProfile, RtpTransport,
-    RtpTransportParameters, Session, Transport, TransportMode, Transports, ACCEPT, CONTENT_BASE,
-    CONTENT_LOCATION, USER_AGENT,
+    RtpTransportParameters, Session, Transport, TransportMode, Transports, ACCEPT, AUTHORIZATION,
+    CONTENT_BASE, CONTENT_LOCATION, PUBLIC, RANGE, REQUIRE, SCALE, SPEED, USER_AGENT,
+    WWW_AUTHENTICATE,
 };
 use rtsp_types::{Message, Method, Request, Response, StatusCode, Version};
 
@@ -47,9 +47,12 @@ use gst::prelude::*;
 use gst::subclass::prelude::*;
 use gst_net::gio;
 
-use super::body::Body;
+use super::auth::{self, Authenticator};
 use super::sdp;
-use super::transport::RtspTransportInfo;
+use super::Backchannel;
+use crate::rtsp::body::Body;
+use crate::rtsp::tcp_message;
+use crate::rtsp::transport::{RtspProtocol, RtspTransportInfo};
 
 const DEFAULT_LOCATION: Option<Url> = None;
 const DEFAULT_TIMEOUT: gst::ClockTime = gst::ClockTime::from_seconds(5);
@@ -59,15 +62,53 @@ const DEFAULT_PROTOCOLS: &str = "udp-mcast,udp,tcp";
 // Equal to MTU + 8 by default to avoid incorrectly detecting an MTU sized buffer as having
 // possibly overflown our receive buffer, and triggering a doubling of the buffer sizes.
 const DEFAULT_RECEIVE_MTU: u32 = 1500 + 8;
+const DEFAULT_USER_ID: Option<String> = None;
+const DEFAULT_USER_PW: Option<String> = None;
+const DEFAULT_TLS_VALIDATION_FLAGS: gio::TlsCertificateFlags =
+    gio::TlsCertificateFlags::VALIDATE_ALL;
+const DEFAULT_TLS_CA_FILE: Option<String> = None;
+const DEFAULT_DO_RTSP_KEEP_ALIVE: bool = true;
+const DEFAULT_MAX_RECONNECT_ATTEMPTS: i32 = 10;
+const DEFAULT_BACKCHANNEL: Backchannel = Backchannel::None;
+
+const DEFAULT_RTSP_PORT: u16 = 554;
+const DEFAULT_RTSPS_PORT: u16 = 322;
 
 const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
 const MAX_BIND_PORT_RETRY: u16 = 100;
 const UDP_PACKET_MAX_SIZE: u32 = 65535 - 8;
 const RTCP_ADDR_CACHE_SIZE: usize = 100;
+// https://datatracker.ietf.org/doc/html/rfc2326#section-12.37
+const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+// Backchannel data is queued together with the commands, so leave room for some packets
+const COMMAND_QUEUE_SIZE: usize = 64;
+// https://www.onvif.org/specs/stream/ONVIF-Streaming-Spec.pdf section 5.3
+const ONVIF_BACKCHANNEL_REQUIRE: &str = "www.onvif.org/ver20/backchannel";
 
 static RTCP_CAPS: LazyLock<gst::Caps> =
     LazyLock::new(|| gst::Caps::from(gst::Structure::new_empty("application/x-rtcp")));
 
+// RTP, or audio that we can payload for the codecs ONVIF devices support on the backchannel
+static BACKCHANNEL_CAPS: LazyLock<gst::Caps> = LazyLock::new(|| {
+    gst::Caps::builder_full()
+        .structure(
+            gst::Structure::builder("application/x-rtp")
+                .field("media", "audio")
+                .build(),
+        )
+        .structure(gst::Structure::new_empty("audio/x-mulaw"))
+        .structure(gst::Structure::new_empty("audio/x-alaw"))
+        .structure(
+            gst::Structure::builder("audio/mpeg")
+                .field("mpegversion", 4i32)
+                .field("stream-format", "raw")
+                .build(),
+        )
+        .build()
+});
+
 // Hardcoded for now
 const DEFAULT_USER_AGENT: &str = concat!(
     "GStreamer rtspsrc2 ",
@@ -76,23 +117,6 @@ const DEFAULT_USER_AGENT: &str = concat!(
     env!("COMMIT_ID")
 );
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub enum RtspProtocol {
-    UdpMulticast,
-    Udp,
-    Tcp,
-}
-
-impl fmt::Display for RtspProtocol {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self {
-            RtspProtocol::Udp => write!(f, "udp"),
-            RtspProtocol::UdpMulticast => write!(f, "udp-mcast"),
-            RtspProtocol::Tcp => write!(f, "tcp"),
-        }
-    }
-}
-
 #[derive(Debug, Clone)]
 struct Settings {
     location: Option<Url>,
@@ -100,6 +124,13 @@ struct Settings {
     protocols: Vec<RtspProtocol>,
     timeout: gst::ClockTime,
     receive_mtu: u32,
+    user_id: Option<String>,
+    user_pw: Option<String>,
+    tls_validation_flags: gio::TlsCertificateFlags,
+    tls_ca_file: Option<String>,
+    do_rtsp_keep_alive: bool,
+    max_reconnect_attempts: i32,
+    backchannel: Backchannel,
 }
 
 impl Default for Settings {
@@ -110,14 +141,91 @@ impl Default for Settings {
             timeout: DEFAULT_TIMEOUT,
             protocols: parse_protocols_str(DEFAULT_PROTOCOLS).unwrap(),
             receive_mtu: DEFAULT_RECEIVE_MTU,
+            user_id: DEFAULT_USER_ID,
+            user_pw: DEFAULT_USER_PW,
+            tls_validation_flags: DEFAULT_TLS_VALIDATION_FLAGS,
+            tls_ca_file: DEFAULT_TLS_CA_FILE,
+            do_rtsp_keep_alive: DEFAULT_DO_RTSP_KEEP_ALIVE,
+            max_reconnect_attempts: DEFAULT_MAX_RECONNECT_ATTEMPTS,
+            backchannel: DEFAULT_BACKCHANNEL,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+struct SeekParams {
+    seqnum: gst::Seqnum,
+    rate: f64,
+    flags: gst::SeekFlags,
+    // `None` continues from the current position
+    start: Option<gst::ClockTime>,
+    stop: Option<gst::ClockTime>,
+}
+
+/// Playback state of the RTSP task, which is kept when reconnecting.
+#[derive(Debug, Default)]
+struct PlayState {
+    // PLAY was sent and not followed by a PAUSE yet
+    playing: bool,
+    play_auth_retried: bool,
+    // Seek to apply with the next PLAY
+    pending_seek: Option<SeekParams>,
+    // Seek waiting for the PAUSE response before sending PLAY
+    seek_in_progress: Option<SeekParams>,
+    last_play_seek: Option<SeekParams>,
+}
+
+/// Estimated NPT position of on-demand media, to continue from there after reconnecting.
+#[derive(Debug, Clone, Copy)]
+struct NptPosition {
+    npt: gst::ClockTime,
+    rate: f64,
+    // Set while the server is playing
+    since: Option<Instant>,
+}
+
+impl NptPosition {
+    fn current(&self) -> gst::ClockTime {
+        let Some(since) = self.since else {
+            return self.npt;
+        };
+
+        let elapsed = since.elapsed().as_secs_f64() * self.rate;
+        let elapsed = gst::ClockTime::from_nseconds((elapsed.abs() * 1_000_000_000.0) as u64);
+        if self.rate >= 0.0 {
+            self.npt.saturating_add(elapsed)
+        } else {
+            self.npt.saturating_sub(elapsed)
+        }
+    }
+}
+
+/// How the RTP packets of a backchannel stream reach the server.
+#[derive(Debug, Clone)]
+enum BackchannelSender {
+    Tcp {
+        channel: u8,
+        cmd_tx: mpsc::Sender<Commands>,
+    },
+    Udp {
+        socket: Arc<std::net::UdpSocket>,
+        dest: SocketAddr,
+    },
+}
+
+/// A `sendonly` stream of the SDP, which receives the data of the `backchannel_%u` pad with the
+/// same index.
+#[derive(Debug, Clone)]
+struct BackchannelStream {
+    caps: gst::Caps,
+    sender: BackchannelSender,
+}
+
 #[derive(Debug)]
 enum Commands {
     Play,
-    //Pause,
+    Pause,
+    Seek(SeekParams),
     Teardown(Option<oneshot::Sender<()>>),
     Data(rtsp_types::Data<Body>),
 }
@@ -127,6 +235,13 @@ pub struct RtspSrc {
     settings: Mutex<Settings>,
     task_handle: Mutex<Option<JoinHandle<()>>>,
     command_queue: Mutex<Option<mpsc::Sender<Commands>>>,
+    // NPT range of on-demand media, `None` for live media
+    vod_range: Mutex<Option<(gst::ClockTime, gst::ClockTime)>>,
+    // Seqnum of the last handled seek, because it arrives on every source pad
+    seek_seqnum: Mutex<Option<gst::Seqnum>>,
+    // Seqnum and NPT start of the last flushing seek, for the segments after the flush
+    seek_segment: Mutex<Option<(gst::Seqnum, Option<gst::ClockTime>)>>,
+    backchannel_streams: Mutex<Vec<BackchannelStream>>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -135,7 +250,7 @@ pub enum RtspError {
     #[error("Generic I/O error")]
     IOGeneric(#[from] std::io::Error),
     #[error("Read I/O error")]
-    Read(#[from] super::tcp_message::ReadError),
+    Read(#[from] tcp_message::ReadError),
     #[error("RTSP header parse error")]
     HeaderParser(#[from] rtsp_types::headers::HeaderParseError),
     #[error("SDP parse error")]
@@ -211,20 +326,15 @@ impl RtspSrc {
             )
         })?;
 
-        if uri.password().is_some() || !uri.username().is_empty() {
-            // TODO
-            gst::fixme!(CAT, "URI credentials are currently ignored");
-        }
-
         match (uri.host_str(), uri.port()) {
             (Some(_), Some(_)) | (Some(_), None) => Ok(()),
             _ => Err(glib::Error::new(gst::URIError::BadUri, "Invalid host")),
         }?;
 
         let protocols: &[RtspProtocol] = match uri.scheme() {
-            "rtspu" => &[RtspProtocol::UdpMulticast, RtspProtocol::Udp],
-            "rtspt" => &[RtspProtocol::Tcp],
-            "rtsp" => &settings.protocols,
+            "rtspu" | "rtspsu" => &[RtspProtocol::UdpMulticast, RtspProtocol::Udp],
+            "rtspt" | "rtspst" => &[RtspProtocol::Tcp],
+            "rtsp" | "rtsps" => &settings.protocols,
             scheme => {
                 return Err(glib::Error::new(
                     gst::URIError::UnsupportedProtocol,
@@ -309,6 +419,46 @@ impl ObjectImpl for RtspSrc {
                     .default_value(DEFAULT_TIMEOUT.into())
                     .mutable_ready()
                     .build(),
+                glib::ParamSpecString::builder("user-id")
+                    .nick("User ID")
+                    .blurb("RTSP user ID for authentication, overridden by credentials in the location")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecString::builder("user-pw")
+                    .nick("User password")
+                    .blurb("RTSP user password for authentication, overridden by credentials in the location")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecFlags::builder::<gio::TlsCertificateFlags>("tls-validation-flags")
+                    .nick("TLS validation flags")
+                    .blurb("TLS certificate validation errors that make the connection fail")
+                    .default_value(DEFAULT_TLS_VALIDATION_FLAGS)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecString::builder("tls-ca-file")
+                    .nick("TLS CA file")
+                    .blurb("PEM file with additional root certificates to trust for rtsps:// connections")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecBoolean::builder("do-rtsp-keep-alive")
+                    .nick("Do RTSP Keep Alive")
+                    .blurb("Send RTSP keep alive packets, disable for old incompatible server")
+                    .default_value(DEFAULT_DO_RTSP_KEEP_ALIVE)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecInt::builder("max-reconnect-attempts")
+                    .nick("Max reconnect attempts")
+                    .blurb("Maximum number of attempts to reconnect when the connection to the server is lost (0 = disabled, -1 = unlimited)")
+                    .minimum(-1)
+                    .default_value(DEFAULT_MAX_RECONNECT_ATTEMPTS)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecEnum::builder::<Backchannel>("backchannel")
+                    .nick("Backchannel type")
+                    .blurb("The type of backchannel to set up, its streams receive the data of the backchannel_%u request pads")
+                    .default_value(DEFAULT_BACKCHANNEL)
+                    .mutable_ready()
+                    .build(),
             ]
         });
 
@@ -349,6 +499,41 @@ impl ObjectImpl for RtspSrc {
                 settings.timeout = timeout;
                 Ok(())
             }
+            "user-id" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.user_id = value.get().expect("type checked upstream");
+                Ok(())
+            }
+            "user-pw" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.user_pw = value.get().expect("type checked upstream");
+                Ok(())
+            }
+            "tls-validation-flags" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.tls_validation_flags = value.get().expect("type checked upstream");
+                Ok(())
+            }
+            "tls-ca-file" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.tls_ca_file = value.get().expect("type checked upstream");
+                Ok(())
+            }
+            "do-rtsp-keep-alive" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.do_rtsp_keep_alive = value.get().expect("type checked upstream");
+                Ok(())
+            }
+            "max-reconnect-attempts" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.max_reconnect_attempts = value.get().expect("type checked upstream");
+                Ok(())
+            }
+            "backchannel" => {
+                let mut settings = self.settings.lock().unwrap();
+                settings.backchannel = value.get().expect("type checked upstream");
+                Ok(())
+            }
             name => unimplemented!("Property '{name}'"),
         };
 
@@ -393,6 +578,34 @@ impl ObjectImpl for RtspSrc {
                 let settings = self.settings.lock().unwrap();
                 settings.timeout.to_value()
             }
+            "user-id" => {
+                let settings = self.settings.lock().unwrap();
+                settings.user_id.to_value()
+            }
+            "user-pw" => {
+                let settings = self.settings.lock().unwrap();
+                settings.user_pw.to_value()
+            }
+            "tls-validation-flags" => {
+                let settings = self.settings.lock().unwrap();
+                settings.tls_validation_flags.to_value()
+            }
+            "tls-ca-file" => {
+                let settings = self.settings.lock().unwrap();
+                settings.tls_ca_file.to_value()
+            }
+            "do-rtsp-keep-alive" => {
+                let settings = self.settings.lock().unwrap();
+                settings.do_rtsp_keep_alive.to_value()
+            }
+            "max-reconnect-attempts" => {
+                let settings = self.settings.lock().unwrap();
+                settings.max_reconnect_attempts.to_value()
+            }
+            "backchannel" => {
+                let settings = self.settings.lock().unwrap();
+                settings.backchannel.to_value()
+            }
             name => unimplemented!("Property '{name}'"),
         }
     }
@@ -432,12 +645,114 @@ impl ElementImpl for RtspSrc {
             )
             .unwrap();
 
-            vec![src_pad_template]
+            let backchannel_pad_template = gst::PadTemplate::new(
+                "backchannel_%u",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Request,
+                &BACKCHANNEL_CAPS,
+            )
+            .unwrap();
+
+            vec![src_pad_template, backchannel_pad_template]
         });
 
         PAD_TEMPLATES.as_ref()
     }
 
+    fn request_new_pad(
+        &self,
+        templ: &gst::PadTemplate,
+        name: Option<&str>,
+        _caps: Option<&gst::Caps>,
+    ) -> Option<gst::Pad> {
+        let obj = self.obj();
+        let n = match name {
+            Some(name) => name.strip_prefix("backchannel_")?.parse::<usize>().ok()?,
+            None => (0..)
+                .find(|n| obj.static_pad(&format!("backchannel_{n}")).is_none())
+                .unwrap(),
+        };
+        if obj.static_pad(&format!("backchannel_{n}")).is_some() {
+            gst::warning!(CAT, imp = self, "Pad backchannel_{n} already exists");
+            return None;
+        }
+
+        let src = obj.downgrade();
+        let appsink = gst_app::AppSink::builder()
+            .name(format!("backchannel_appsink_{n}"))
+            .sync(false)
+            .async_(false)
+            .callbacks(
+                gst_app::AppSinkCallbacks::builder()
+                    .new_sample(move |appsink| {
+                        let Some(src) = src.upgrade() else {
+                            return Err(gst::FlowError::Flushing);
+                        };
+                        src.imp().on_backchannel_sample(n, appsink)
+                    })
+                    .build(),
+            )
+            .build();
+        obj.add(&appsink).ok()?;
+        if let Err(err) = appsink.sync_state_with_parent() {
+            gst::warning!(
+                CAT,
+                imp = self,
+                "Failed to start backchannel appsink: {err}"
+            );
+        }
+
+        let ghostpad = gst::GhostPad::builder_from_template_with_target(
+            templ,
+            &appsink.static_pad("sink").unwrap(),
+        )
+        .ok()?
+        .name(format!("backchannel_{n}"))
+        .event_function(move |pad, parent, event| {
+            RtspSrc::catch_panic_pad_function(
+                parent,
+                || false,
+                |imp| imp.backchannel_event(pad, n, event),
+            )
+        })
+        .query_function(move |pad, parent, query| {
+            RtspSrc::catch_panic_pad_function(
+                parent,
+                || false,
+                |imp| imp.backchannel_query(pad, n, query),
+            )
+        })
+        .build();
+        gst::info!(
+            CAT,
+            imp = self,
+            "Adding backchannel sinkpad {}",
+            ghostpad.name()
+        );
+        obj.add_pad(&ghostpad).ok()?;
+
+        Some(ghostpad.upcast())
+    }
+
+    fn release_pad(&self, pad: &gst::Pad) {
+        let obj = self.obj();
+        let Some(n) = pad
+            .name()
+            .strip_prefix("backchannel_")
+            .and_then(|n| n.parse::<usize>().ok())
+        else {
+            return;
+        };
+
+        let _ = pad.set_active(false);
+        self.remove_backchannel_payloader(n);
+        if let Some(appsink) = obj.by_name(&format!("backchannel_appsink_{n}")) {
+            let _ = appsink.set_state(gst::State::Null);
+            let _ = obj.remove(&appsink);
+        }
+        let _ = obj.remove_pad(pad);
+    }
+
     fn change_state(
         &self,
         transition: gst::StateChange,
@@ -454,6 +769,10 @@ impl ElementImpl for RtspSrc {
                 //self.async_start().map_err(|_| gst::StateChangeError)?;
                 RUNTIME.spawn(async move { cmd_queue.send(Commands::Play).await });
             }
+            gst::StateChange::PlayingToPaused => {
+                let cmd_queue = self.cmd_queue();
+                RUNTIME.spawn(async move { cmd_queue.send(Commands::Pause).await });
+            }
             _ => {}
         }
 
@@ -498,6 +817,17 @@ impl ElementImpl for RtspSrc {
 
         Ok(ret)
     }
+
+    fn send_event(&self, event: gst::Event) -> bool {
+        match event.view() {
+            gst::EventView::Seek(seek) => self.handle_seek(seek),
+            _ => self.parent_send_event(event),
+        }
+    }
+
+    fn query(&self, query: &mut gst::QueryRef) -> bool {
+        self.handle_query(query) || self.parent_query(query)
+    }
 }
 
 impl BinImpl for RtspSrc {}
@@ -506,7 +836,7 @@ impl URIHandlerImpl for RtspSrc {
     const URI_TYPE: gst::URIType = gst::URIType::Src;
 
     fn protocols() -> &'static [&'static str] {
-        &["rtsp", "rtspu", "rtspt"]
+        &["rtsp", "rtspu", "rtspt", "rtsps", "rtspsu", "rtspst"]
     }
 
     fn uri(&self) -> Option<String> {
@@ -520,8 +850,7 @@ impl URIHandlerImpl for RtspSrc {
     }
 }
 
-type RtspStream =
-    Pin<Box<dyn Stream<Item = Result<Message<Body>, super::tcp_message::ReadError>> + Send>>;
+type RtspStream = Pin<Box<dyn Stream<Item = Result<Message<Body>, tcp_message::ReadError>> + Send>>;
 type RtspSink = Pin<Box<dyn Sink<Message<Body>, Error = std::io::Error> + Send>>;
 
 impl RtspSrc {
@@ -531,13 +860,35 @@ impl RtspSrc {
     }
 
     fn start(&self) -> Result<(), gst::ErrorMessage> {
-        let Some(url) = self.settings.lock().unwrap().location.clone() else {
+        let settings = self.settings.lock().unwrap().clone();
+        let Some(mut url) = settings.location.clone() else {
             return Err(gst::error_msg!(
                 gst::ResourceError::Settings,
                 ["No location set"]
             ));
         };
 
+        // Credentials in the location take precedence over the properties, and must not be
+        // part of any request URI
+        let credentials = if !url.username().is_empty() {
+            let decode = |s: &str| -> String {
+                percent_encoding::percent_decode_str(s)
+                    .decode_utf8_lossy()
+                    .into_owned()
+            };
+            Some((
+                decode(url.username()),
+                url.password().map(decode).unwrap_or_default(),
+            ))
+        } else {
+            settings
+                .user_id
+                .clone()
+                .map(|user_id| (user_id, settings.user_pw.clone().unwrap_or_default()))
+        };
+        let _ = url.set_username("");
+        let _ = url.set_password(None);
+
         gst::info!(CAT, imp = self, "Location: {url}",);
 
         gst::info!(CAT, imp = self, "Starting RTSP connection thread.. ");
@@ -546,7 +897,7 @@ impl RtspSrc {
 
         let mut task_handle = self.task_handle.lock().unwrap();
 
-        let (tx, rx) = mpsc::channel(1);
+        let (tx, rx) = mpsc::channel(COMMAND_QUEUE_SIZE);
         {
             let mut cmd_queue_opt = self.command_queue.lock().unwrap();
             debug_assert!(cmd_queue_opt.is_none());
@@ -554,32 +905,18 @@ impl RtspSrc {
         }
 
         let join_handle = RUNTIME.spawn(async move {
-            gst::info!(CAT, "Connecting to {url} ..");
-            let hostname_port =
-                format!("{}:{}", url.host_str().unwrap(), url.port().unwrap_or(554));
-
-            // TODO: Add TLS support
-            let s = match TcpStream::connect(hostname_port).await {
+            let (stream, sink) = match connect(&url, &settings).await {
                 Ok(s) => s,
                 Err(err) => {
-                    gst::element_imp_error!(
-                        task_src,
-                        gst::ResourceError::OpenRead,
-                        ["Failed to connect to RTSP server: {err:#?}"]
-                    );
+                    gst::element_imp_error!(task_src, gst::ResourceError::OpenRead, ["{err:#}"]);
                     return;
                 }
             };
-            let _ = s.set_nodelay(true);
 
             gst::info!(CAT, "Connected!");
 
-            let (read, write) = s.into_split();
-
-            let stream = Box::pin(super::tcp_message::async_read(read, MAX_MESSAGE_SIZE).fuse());
-            let sink = Box::pin(super::tcp_message::async_write(write));
-
-            let mut state = RtspTaskState::new(url, stream, sink);
+            let mut state = RtspTaskState::new(url, credentials, stream, sink);
+            state.onvif_backchannel = settings.backchannel == Backchannel::Onvif;
 
             let task_ret = task_src.rtsp_task(&mut state, rx).await;
             gst::info!(CAT, "Exited rtsp_task");
@@ -609,6 +946,10 @@ impl RtspSrc {
                 let Ok(e) = e else {
                     continue;
                 };
+                // Kept until their request pad is released
+                if e.name().starts_with("backchannel_") {
+                    continue;
+                }
                 if let Err(err) = obj.remove(&e) {
                     gst::warning!(CAT, "Failed to remove element {}: {err:?}", e.name());
                 }
@@ -656,12 +997,297 @@ impl RtspSrc {
         }
 
         self.command_queue.lock().unwrap().take();
+        *self.vod_range.lock().unwrap() = None;
+        *self.seek_seqnum.lock().unwrap() = None;
+        *self.seek_segment.lock().unwrap() = None;
+        self.backchannel_streams.lock().unwrap().clear();
 
         gst::info!(CAT, imp = self, "Stopped");
 
         Ok(())
     }
 
+    fn handle_seek(&self, seek: &gst::event::Seek) -> bool {
+        let (rate, flags, start_type, start, stop_type, stop) = seek.get();
+
+        let Some((range_start, range_end)) = *self.vod_range.lock().unwrap() else {
+            gst::debug!(CAT, imp = self, "Can't seek in live media");
+            return false;
+        };
+        let (Ok(start), Ok(stop)) = (
+            Option::<gst::ClockTime>::try_from(start),
+            Option::<gst::ClockTime>::try_from(stop),
+        ) else {
+            gst::warning!(CAT, imp = self, "Only seeking in TIME format is supported");
+            return false;
+        };
+
+        {
+            let mut seek_seqnum = self.seek_seqnum.lock().unwrap();
+            if *seek_seqnum == Some(seek.seqnum()) {
+                return true;
+            }
+            *seek_seqnum = Some(seek.seqnum());
+        }
+
+        let to_npt = |seek_type, time: Option<gst::ClockTime>| match seek_type {
+            gst::SeekType::Set => time.map(|t| t.clamp(range_start, range_end)),
+            gst::SeekType::End => time.map(|t| range_end.saturating_sub(t).max(range_start)),
+            _ => None,
+        };
+        let params = SeekParams {
+            seqnum: seek.seqnum(),
+            rate,
+            flags,
+            start: to_npt(start_type, start),
+            stop: to_npt(stop_type, stop),
+        };
+
+        let Some(cmd_queue) = self.command_queue.lock().unwrap().clone() else {
+            return false;
+        };
+        if flags.contains(gst::SeekFlags::FLUSH) {
+            *self.seek_segment.lock().unwrap() = Some((params.seqnum, params.start));
+        }
+        gst::debug!(CAT, imp = self, "Seeking {params:?}");
+        RUNTIME.spawn(async move { cmd_queue.send(Commands::Seek(params)).await });
+
+        true
+    }
+
+    fn handle_query(&self, query: &mut gst::QueryRef) -> bool {
+        match query.view_mut() {
+            gst::QueryViewMut::Duration(q) if q.format() == gst::Format::Time => {
+                let Some((_, end)) = *self.vod_range.lock().unwrap() else {
+                    return false;
+                };
+                q.set(end);
+                true
+            }
+            gst::QueryViewMut::Seeking(q) if q.format() == gst::Format::Time => {
+                match *self.vod_range.lock().unwrap() {
+                    Some((start, end)) => q.set(true, start, end),
+                    None => q.set(false, gst::ClockTime::NONE, gst::ClockTime::NONE),
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn src_event(&self, pad: &gst::GhostPad, event: gst::Event) -> bool {
+        match event.view() {
+            gst::EventView::Seek(seek) => self.handle_seek(seek),
+            _ => gst::Pad::event_default(pad, Some(&*self.obj()), event),
+        }
+    }
+
+    fn src_query(&self, pad: &gst::GhostPad, query: &mut gst::QueryRef) -> bool {
+        self.handle_query(query) || gst::Pad::query_default(pad, Some(&*self.obj()), query)
+    }
+
+    /// Gives segments after a flushing seek the seqnum of the seek, and maps the start of the
+    /// segment to the NPT the server plays from.
+    fn update_segment(&self, event: &mut gst::Event) {
+        let gst::EventView::Segment(segment) = event.view() else {
+            return;
+        };
+        let Some((seqnum, start)) = *self.seek_segment.lock().unwrap() else {
+            return;
+        };
+        let Some(segment) = segment.segment().downcast_ref::<gst::ClockTime>() else {
+            return;
+        };
+
+        let mut segment = segment.clone();
+        if let Some(start) = start {
+            segment.set_time(start);
+        }
+        gst::debug!(CAT, imp = self, "Updating segment to {segment:?}");
+
+        *event = gst::event::Segment::builder(&segment)
+            .seqnum(seqnum)
+            .build();
+    }
+
+    /// Caps of a backchannel pad, restricted to its stream once the SDP is known.
+    fn backchannel_caps(&self, n: usize) -> gst::Caps {
+        match self.backchannel_streams.lock().unwrap().get(n) {
+            Some(stream) => stream.caps.clone(),
+            None => BACKCHANNEL_CAPS.clone(),
+        }
+    }
+
+    fn backchannel_event(&self, pad: &gst::GhostPad, n: usize, event: gst::Event) -> bool {
+        if let gst::EventView::Caps(c) = event.view() {
+            if let Err(err) = self.backchannel_set_caps(pad, n, c.caps()) {
+                gst::element_imp_error!(
+                    self,
+                    gst::CoreError::Negotiation,
+                    ["Failed to set up backchannel_{n}: {err:#}"]
+                );
+                return false;
+            }
+        }
+
+        gst::Pad::event_default(pad, Some(&*self.obj()), event)
+    }
+
+    fn backchannel_query(&self, pad: &gst::GhostPad, n: usize, query: &mut gst::QueryRef) -> bool {
+        match query.view_mut() {
+            gst::QueryViewMut::Caps(q) => {
+                let caps = self.backchannel_caps(n);
+                let caps = match q.filter() {
+                    Some(filter) => {
+                        filter.intersect_with_mode(&caps, gst::CapsIntersectMode::First)
+                    }
+                    None => caps,
+                };
+                q.set_result(&caps);
+                true
+            }
+            gst::QueryViewMut::AcceptCaps(q) => {
+                let accepted = q.caps().is_subset(&self.backchannel_caps(n));
+                q.set_result(accepted);
+                true
+            }
+            _ => gst::Pad::query_default(pad, Some(&*self.obj()), query),
+        }
+    }
+
+    /// Targets the appsink of the backchannel pad for RTP, or puts a payloader in front of it
+    /// for encoded audio.
+    fn backchannel_set_caps(
+        &self,
+        pad: &gst::GhostPad,
+        n: usize,
+        caps: &gst::CapsRef,
+    ) -> Result<()> {
+        let obj = self.obj();
+        let s = caps.structure(0).context("Empty caps")?;
+        let appsink = obj
+            .by_name(&format!("backchannel_appsink_{n}"))
+            .context("No backchannel appsink")?;
+        let payloader_name = format!("backchannel_pay_{n}");
+
+        if s.name() == "application/x-rtp" {
+            self.remove_backchannel_payloader(n);
+            pad.set_target(Some(&appsink.static_pad("sink").unwrap()))?;
+            return Ok(());
+        }
+
+        // The stream is only known if the SDP was received before the caps
+        let rtp_caps = self
+            .backchannel_streams
+            .lock()
+            .unwrap()
+            .get(n)
+            .map(|stream| stream.caps.clone());
+        let rtp_s = rtp_caps.as_ref().and_then(|caps| caps.structure(0));
+        let factory =
+            backchannel_payloader(s, rtp_s).with_context(|| format!("Unsupported caps {caps}"))?;
+
+        if let Some(payloader) = obj.by_name(&payloader_name) {
+            if payloader.factory().is_some_and(|f| f.name() == factory) {
+                return Ok(());
+            }
+            self.remove_backchannel_payloader(n);
+        }
+
+        gst::debug!(CAT, imp = self, "Payloading backchannel_{n} with {factory}");
+        let payloader = gst::ElementFactory::make(factory)
+            .name(payloader_name.as_str())
+            .build()?;
+        if let Some(pt) = rtp_s.and_then(|s| s.get::<i32>("payload").ok()) {
+            payloader.set_property("pt", pt as u32);
+        }
+        obj.add(&payloader)?;
+        pad.set_target(None::<&gst::Pad>)?;
+        payloader.link(&appsink)?;
+        payloader.sync_state_with_parent()?;
+        pad.set_target(Some(&payloader.static_pad("sink").unwrap()))?;
+
+        Ok(())
+    }
+
+    fn remove_backchannel_payloader(&self, n: usize) {
+        let obj = self.obj();
+        let Some(payloader) = obj.by_name(&format!("backchannel_pay_{n}")) else {
+            return;
+        };
+
+        if let Some(pad) = obj.static_pad(&format!("backchannel_{n}")) {
+            let _ = pad
+                .downcast::<gst::GhostPad>()
+                .expect("rtspsrc backchannel pads are ghost pads")
+                .set_target(None::<&gst::Pad>);
+        }
+        let _ = payloader.set_state(gst::State::Null);
+        let _ = obj.remove(&payloader);
+    }
+
+    fn on_backchannel_sample(
+        &self,
+        n: usize,
+        appsink: &gst_app::AppSink,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let Ok(sample) = appsink.pull_sample() else {
+            return Err(gst::FlowError::Error);
+        };
+        let Some(buffer) = sample.buffer_owned() else {
+            return Ok(gst::FlowSuccess::Ok);
+        };
+        let Some(sender) = self
+            .backchannel_streams
+            .lock()
+            .unwrap()
+            .get(n)
+            .map(|stream| stream.sender.clone())
+        else {
+            gst::log!(
+                CAT,
+                imp = self,
+                "No backchannel stream {n} set up, dropping buffer"
+            );
+            return Ok(gst::FlowSuccess::Ok);
+        };
+        let map = buffer.into_mapped_buffer_readable().map_err(|_| {
+            gst::error!(CAT, imp = self, "Failed to map buffer");
+            gst::FlowError::Error
+        })?;
+
+        match sender {
+            BackchannelSender::Tcp { channel, cmd_tx } => {
+                let data = rtsp_types::Data::new(channel, Body::mapped(map));
+                // Never block the streaming thread on the connection, drop packets like with UDP
+                match cmd_tx.try_send(Commands::Data(data)) {
+                    Ok(_) => (),
+                    Err(mpsc::error::TrySendError::Full(_)) => {
+                        gst::warning!(
+                            CAT,
+                            imp = self,
+                            "Backchannel queue is full, dropping buffer"
+                        );
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => {
+                        return Err(gst::FlowError::Flushing);
+                    }
+                }
+            }
+            BackchannelSender::Udp { socket, dest } => {
+                if let Err(err) = socket.send_to(&map, dest) {
+                    gst::warning!(
+                        CAT,
+                        imp = self,
+                        "Failed to send backchannel data to {dest}: {err:?}"
+                    );
+                }
+            }
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
     fn make_rtp_appsrc(
         &self,
         rtpsession_n: usize,
@@ -695,7 +1321,25 @@ impl RtspSrc {
         let templ = obj.pad_template("stream_%u").unwrap();
         let ghostpad = gst::GhostPad::builder_from_template(&templ)
             .name(format!("stream_{rtpsession_n}"))
+            .event_function(|pad, parent, event| {
+                RtspSrc::catch_panic_pad_function(parent, || false, |imp| imp.src_event(pad, event))
+            })
+            .query_function(|pad, parent, query| {
+                RtspSrc::catch_panic_pad_function(parent, || false, |imp| imp.src_query(pad, query))
+            })
             .build();
+        ghostpad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, {
+            let self_weak = self.downgrade();
+            move |_pad, info| {
+                let Some(imp) = self_weak.upgrade() else {
+                    return gst::PadProbeReturn::Ok;
+                };
+                if let Some(gst::PadProbeData::Event(ref mut event)) = info.data {
+                    imp.update_segment(event);
+                }
+                gst::PadProbeReturn::Ok
+            }
+        });
         gst::info!(CAT, "Adding ghost srcpad {}", ghostpad.name());
         obj.add_pad(&ghostpad)
             .expect("Adding a ghostpad should never fail");
@@ -703,6 +1347,31 @@ impl RtspSrc {
         Ok(appsrc)
     }
 
+    /// Returns the RTP appsrc of a stream, which is only created for new streams.
+    fn rtp_appsrc(
+        &self,
+        rtpsession_n: usize,
+        rtp_appsrc: &mut Option<gst_app::AppSrc>,
+        caps: &gst::Caps,
+        manager: &RtspManager,
+    ) -> Result<gst_app::AppSrc> {
+        if let Some(appsrc) = rtp_appsrc {
+            appsrc.set_caps(Some(caps));
+            return Ok(appsrc.clone());
+        }
+
+        let appsrc = self.make_rtp_appsrc(rtpsession_n, caps, manager)?;
+        *rtp_appsrc = Some(appsrc.clone());
+        Ok(appsrc)
+    }
+
+    fn rtcp_appsrc(&self, rtpsession_n: usize, manager: &RtspManager) -> Result<gst_app::AppSrc> {
+        match self.obj().by_name(&format!("rtcp_appsrc_{rtpsession_n}")) {
+            Some(appsrc) => Ok(appsrc.downcast().expect("rtcp_appsrc elements are appsrcs")),
+            None => self.make_rtcp_appsrc(rtpsession_n, manager),
+        }
+    }
+
     fn make_rtcp_appsrc(
         &self,
         rtpsession_n: usize,
@@ -725,7 +1394,7 @@ impl RtspSrc {
         Ok(appsrc)
     }
 
-    fn make_rtcp_appsink<
+    fn rtcp_appsink<
         F: FnMut(&gst_app::AppSink) -> Result<gst::FlowSuccess, gst::FlowError> + Send + 'static,
     >(
         &self,
@@ -744,6 +1413,14 @@ impl RtspSrc {
             .new_sample(on_rtcp)
             .build();
 
+        if let Some(rtcp_appsink) = self.obj().by_name(&format!("rtcp_appsink_{rtpsession_n}")) {
+            rtcp_appsink
+                .downcast::<gst_app::AppSink>()
+                .expect("rtcp_appsink elements are appsinks")
+                .set_callbacks(cbs);
+            return Ok(());
+        }
+
         let rtcp_appsink = gst_app::AppSink::builder()
             .name(format!("rtcp_appsink_{rtpsession_n}"))
             .sync(false)
@@ -782,42 +1459,25 @@ impl RtspSrc {
         let _ = obj.post_message(msg);
     }
 
-    async fn rtsp_task(
+    /// Starts receiving on the transports of all streams that were set up. Source pads and
+    /// elements from a previous session are reused, which keeps them around when reconnecting.
+    fn setup_transports(
         &self,
         state: &mut RtspTaskState,
-        mut cmd_rx: mpsc::Receiver<Commands>,
-    ) -> Result<()> {
+        manager: &RtspManager,
+        settings: &Settings,
+    ) -> Result<HashMap<u8, gst_app::AppSrc>> {
         let cmd_tx = self.cmd_queue();
-
-        let settings = { self.settings.lock().unwrap().clone() };
-
-        // OPTIONS
-        state.options().await?;
-
-        // DESCRIBE
-        state.describe().await?;
-
-        let mut session: Option<Session> = None;
-        // SETUP streams (TCP interleaved)
-        state.setup_params = {
-            state
-                .setup(
-                    &mut session,
-                    settings.port_start,
-                    &settings.protocols,
-                    TransportMode::Play,
-                )
-                .await?
-        };
-        let manager = RtspManager::new(std::env::var("USE_RTP2").is_ok_and(|s| s == "1"));
-
-        let obj = self.obj();
-        manager
-            .add_to(obj.upcast_ref::<gst::Bin>())
-            .expect("Adding the manager cannot fail");
+        let timeout = settings.timeout;
+        let receive_mtu = settings.receive_mtu;
 
         let mut tcp_interleave_appsrcs = HashMap::new();
-        for (rtpsession_n, p) in state.setup_params.iter_mut().enumerate() {
+        for (rtpsession_n, p) in state
+            .setup_params
+            .iter_mut()
+            .filter(|p| !p.backchannel)
+            .enumerate()
+        {
             let (tx, rx) = mpsc::channel(1);
             let on_rtcp = move |appsink: &_| on_rtcp_udp(appsink, tx.clone());
             match &mut p.transport {
@@ -875,25 +1535,19 @@ impl RtspSrc {
                         }
                     };
 
-                    let rtp_appsrc = self.make_rtp_appsrc(rtpsession_n, &p.caps, &manager)?;
-                    p.rtp_appsrc = Some(rtp_appsrc.clone());
+                    let rtp_appsrc =
+                        self.rtp_appsrc(rtpsession_n, &mut p.rtp_appsrc, &p.caps, manager)?;
                     // Spawn RTP udp receive task
                     state.handles.push(RUNTIME.spawn(async move {
-                        udp_rtp_task(
-                            &rtp_socket,
-                            rtp_appsrc,
-                            settings.timeout,
-                            settings.receive_mtu,
-                            None,
-                        )
-                        .await
+                        udp_rtp_task(&rtp_socket, rtp_appsrc, timeout, receive_mtu, None).await
                     }));
 
                     // Spawn RTCP udp send/recv task
                     if let Some(rtcp_socket) = rtcp_socket {
                         let rtcp_dest = rtcp_port.and_then(|p| Some(SocketAddr::new(*dest, p)));
-                        let rtcp_appsrc = self.make_rtcp_appsrc(rtpsession_n, &manager)?;
-                        self.make_rtcp_appsink(rtpsession_n, &manager, on_rtcp)?;
+                        let rtcp_appsrc = self.rtcp_appsrc(rtpsession_n, manager)?;
+                        p.rtcp_appsrc = Some(rtcp_appsrc.clone());
+                        self.rtcp_appsink(rtpsession_n, manager, on_rtcp)?;
                         state.handles.push(RUNTIME.spawn(async move {
                             udp_rtcp_task(&rtcp_socket, rtcp_appsrc, rtcp_dest, true, rx).await
                         }));
@@ -928,14 +1582,14 @@ impl RtspSrc {
                     };
 
                     // Spawn RTP udp receive task
-                    let rtp_appsrc = self.make_rtp_appsrc(rtpsession_n, &p.caps, &manager)?;
-                    p.rtp_appsrc = Some(rtp_appsrc.clone());
+                    let rtp_appsrc =
+                        self.rtp_appsrc(rtpsession_n, &mut p.rtp_appsrc, &p.caps, manager)?;
                     state.handles.push(RUNTIME.spawn(async move {
                         udp_rtp_task(
                             &rtp_socket,
                             rtp_appsrc,
-                            settings.timeout,
-                            settings.receive_mtu,
+                            timeout,
+                            receive_mtu,
                             rtp_sender_addr,
                         )
                         .await
@@ -943,8 +1597,9 @@ impl RtspSrc {
 
                     // Spawn RTCP udp send/recv task
                     if let Some(rtcp_socket) = rtcp_socket {
-                        let rtcp_appsrc = self.make_rtcp_appsrc(rtpsession_n, &manager)?;
-                        self.make_rtcp_appsink(rtpsession_n, &manager, on_rtcp)?;
+                        let rtcp_appsrc = self.rtcp_appsrc(rtpsession_n, manager)?;
+                        p.rtcp_appsrc = Some(rtcp_appsrc.clone());
+                        self.rtcp_appsink(rtpsession_n, manager, on_rtcp)?;
                         state.handles.push(RUNTIME.spawn(async move {
                             udp_rtcp_task(&rtcp_socket, rtcp_appsrc, rtcp_sender_addr, false, rx)
                                 .await
@@ -954,24 +1609,244 @@ impl RtspSrc {
                 RtspTransportInfo::Tcp {
                     channels: (rtp_channel, rtcp_channel),
                 } => {
-                    let rtp_appsrc = self.make_rtp_appsrc(rtpsession_n, &p.caps, &manager)?;
-                    p.rtp_appsrc = Some(rtp_appsrc.clone());
+                    let rtp_appsrc =
+                        self.rtp_appsrc(rtpsession_n, &mut p.rtp_appsrc, &p.caps, manager)?;
                     tcp_interleave_appsrcs.insert(*rtp_channel, rtp_appsrc);
 
-                    if let Some(rtcp_channel) = rtcp_channel {
-                        // RTCP SR
-                        let rtcp_appsrc = self.make_rtcp_appsrc(rtpsession_n, &manager)?;
-                        tcp_interleave_appsrcs.insert(*rtcp_channel, rtcp_appsrc.clone());
-                        // RTCP RR
-                        let rtcp_channel = *rtcp_channel;
-                        let cmd_tx = cmd_tx.clone();
-                        self.make_rtcp_appsink(rtpsession_n, &manager, move |appsink| {
-                            on_rtcp_tcp(appsink, cmd_tx.clone(), rtcp_channel)
-                        })?;
-                    }
-                }
-            }
-        }
+                    if let Some(rtcp_channel) = rtcp_channel {
+                        // RTCP SR
+                        let rtcp_appsrc = self.rtcp_appsrc(rtpsession_n, manager)?;
+                        p.rtcp_appsrc = Some(rtcp_appsrc.clone());
+                        tcp_interleave_appsrcs.insert(*rtcp_channel, rtcp_appsrc.clone());
+                        // RTCP RR
+                        let rtcp_channel = *rtcp_channel;
+                        let cmd_tx = cmd_tx.clone();
+                        self.rtcp_appsink(rtpsession_n, manager, move |appsink| {
+                            on_rtcp_tcp(appsink, cmd_tx.clone(), rtcp_channel)
+                        })?;
+                    }
+                }
+            }
+        }
+
+        let mut backchannel_streams = Vec::new();
+        for p in state.setup_params.iter_mut().filter(|p| p.backchannel) {
+            let sender = match &mut p.transport {
+                RtspTransportInfo::Tcp {
+                    channels: (rtp_channel, _),
+                } => BackchannelSender::Tcp {
+                    channel: *rtp_channel,
+                    cmd_tx: cmd_tx.clone(),
+                },
+                RtspTransportInfo::Udp {
+                    source: Some(source),
+                    server_port: Some((rtp_port, _)),
+                    sockets,
+                    ..
+                } => {
+                    let ip = source
+                        .parse::<IpAddr>()
+                        .with_context(|| format!("Invalid backchannel address {source}"))?;
+                    let (rtp_socket, _) = sockets.take().context("No backchannel UDP socket")?;
+                    // Sent from the appsink streaming thread
+                    let socket = rtp_socket.into_std()?;
+                    socket.set_nonblocking(false)?;
+                    BackchannelSender::Udp {
+                        socket: Arc::new(socket),
+                        dest: SocketAddr::new(ip, *rtp_port),
+                    }
+                }
+                transport => {
+                    return Err(RtspError::Fatal(format!(
+                        "Unsupported backchannel transport {transport:?}"
+                    ))
+                    .into())
+                }
+            };
+
+            let n = backchannel_streams.len();
+            let caps = backchannel_caps(&p.caps);
+            if let Some(payloader) = self.obj().by_name(&format!("backchannel_pay_{n}")) {
+                if let Ok(pt) = p.caps.structure(0).unwrap().get::<i32>("payload") {
+                    payloader.set_property("pt", pt as u32);
+                }
+            }
+            gst::info!(CAT, imp = self, "Backchannel stream {n}: {caps}");
+            backchannel_streams.push(BackchannelStream { caps, sender });
+        }
+        *self.backchannel_streams.lock().unwrap() = backchannel_streams;
+
+        Ok(tcp_interleave_appsrcs)
+    }
+
+    /// Sets up a new session after the connection to the server was lost, retrying with
+    /// exponential backoff. The existing source pads are kept for the new session.
+    ///
+    /// Returns `false` if a teardown was requested in the meantime.
+    #[allow(clippy::too_many_arguments)]
+    async fn reconnect(
+        &self,
+        state: &mut RtspTaskState,
+        settings: &Settings,
+        manager: &RtspManager,
+        cmd_rx: &mut mpsc::Receiver<Commands>,
+        session: &mut Option<Session>,
+        tcp_interleave_appsrcs: &mut HashMap<u8, gst_app::AppSrc>,
+        play: &mut PlayState,
+    ) -> Result<bool> {
+        for h in state.handles.drain(..) {
+            h.abort();
+            let _ = h.await;
+        }
+        tcp_interleave_appsrcs.clear();
+        state.pause_npt_position();
+        self.post_start("reconnect", "Reconnecting to the server");
+
+        let mut backoff = RECONNECT_BACKOFF_MIN;
+        let mut attempt = 0;
+        loop {
+            if settings.max_reconnect_attempts >= 0 && attempt >= settings.max_reconnect_attempts {
+                self.post_cancelled("reconnect", "Giving up reconnecting to the server");
+                return Err(RtspError::Fatal(format!(
+                    "Failed to reconnect after {attempt} attempts"
+                ))
+                .into());
+            }
+            attempt += 1;
+
+            gst::info!(
+                CAT,
+                imp = self,
+                "Reconnecting in {backoff:?}, attempt {attempt}"
+            );
+            let sleep = time::sleep(backoff);
+            tokio::pin!(sleep);
+            loop {
+                tokio::select! {
+                    _ = &mut sleep => break,
+                    Some(cmd) = cmd_rx.recv() => match cmd {
+                        Commands::Play => play.playing = true,
+                        Commands::Pause => play.playing = false,
+                        Commands::Seek(seek) => {
+                            if seek.flags.contains(gst::SeekFlags::FLUSH) {
+                                state.flush_start(seek.seqnum);
+                                state.flush_stop(seek.seqnum, true);
+                            }
+                            play.pending_seek = Some(seek);
+                        }
+                        Commands::Teardown(tx) => {
+                            gst::info!(CAT, "Received Teardown command while reconnecting");
+                            if let Some(tx) = tx {
+                                let _ = tx.send(());
+                            }
+                            return Ok(false);
+                        }
+                        // No connection to send RTCP RR or backchannel data on
+                        Commands::Data(_) => (),
+                    },
+                }
+            }
+            backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+
+            match self.try_reconnect(state, settings, manager, session).await {
+                Ok(appsrcs) => {
+                    *tcp_interleave_appsrcs = appsrcs;
+                    break;
+                }
+                Err(err) => gst::warning!(CAT, imp = self, "Failed to reconnect: {err:#}"),
+            }
+        }
+
+        gst::info!(CAT, imp = self, "Reconnected after {attempt} attempts");
+
+        // The new session starts with other RTP sequence numbers and timestamps, which would
+        // confuse the jitterbuffers. Flush them, but keep the running time because the packets
+        // are timestamped with it and the timestamps have to continue from before.
+        let seqnum = gst::Seqnum::next();
+        state.flush_start(seqnum);
+        state.flush_stop(seqnum, false);
+        self.post_complete("reconnect", "Reconnected to the server");
+
+        Ok(true)
+    }
+
+    async fn try_reconnect(
+        &self,
+        state: &mut RtspTaskState,
+        settings: &Settings,
+        manager: &RtspManager,
+        session: &mut Option<Session>,
+    ) -> Result<HashMap<u8, gst_app::AppSrc>> {
+        let (stream, sink) = connect(&state.url, settings).await?;
+        state.stream = stream;
+        state.sink = sink;
+        // The server sends a new challenge on the new connection if needed
+        state.auth = None;
+
+        state.options().await?;
+        state.describe().await?;
+        *self.vod_range.lock().unwrap() = state.vod_range;
+
+        let mut new_session = None;
+        let mut setup_params = state
+            .setup(
+                &mut new_session,
+                settings.port_start,
+                &settings.protocols,
+                TransportMode::Play,
+            )
+            .await?;
+        if setup_params.len() != state.setup_params.len() {
+            return Err(RtspError::Fatal(format!(
+                "Number of streams changed from {} to {}",
+                state.setup_params.len(),
+                setup_params.len()
+            ))
+            .into());
+        }
+        for (new, old) in setup_params.iter_mut().zip(state.setup_params.iter_mut()) {
+            new.rtp_appsrc = old.rtp_appsrc.take();
+        }
+        state.setup_params = setup_params;
+        *session = new_session;
+
+        self.setup_transports(state, manager, settings)
+    }
+
+    async fn rtsp_task(
+        &self,
+        state: &mut RtspTaskState,
+        mut cmd_rx: mpsc::Receiver<Commands>,
+    ) -> Result<()> {
+        let settings = { self.settings.lock().unwrap().clone() };
+
+        // OPTIONS
+        state.options().await?;
+
+        // DESCRIBE
+        state.describe().await?;
+        *self.vod_range.lock().unwrap() = state.vod_range;
+
+        let mut session: Option<Session> = None;
+        // SETUP streams (TCP interleaved)
+        state.setup_params = {
+            state
+                .setup(
+                    &mut session,
+                    settings.port_start,
+                    &settings.protocols,
+                    TransportMode::Play,
+                )
+                .await?
+        };
+        let manager = RtspManager::new(std::env::var("USE_RTP2").is_ok_and(|s| s == "1"));
+
+        let obj = self.obj();
+        manager
+            .add_to(obj.upcast_ref::<gst::Bin>())
+            .expect("Adding the manager cannot fail");
+
+        let mut tcp_interleave_appsrcs = self.setup_transports(state, &manager, &settings)?;
 
         obj.no_more_pads();
 
@@ -1021,8 +1896,54 @@ impl RtspSrc {
             }
         });
 
-        let mut expected_response: Option<(Method, u32)> = None;
+        let mut expected_responses: VecDeque<(Method, u32)> = VecDeque::new();
+        let mut play = PlayState::default();
+        let mut keep_alive = keep_alive_interval(state.session_timeout);
+        let mut reconnect = false;
         loop {
+            if reconnect {
+                reconnect = false;
+                if !self
+                    .reconnect(
+                        state,
+                        &settings,
+                        &manager,
+                        &mut cmd_rx,
+                        &mut session,
+                        &mut tcp_interleave_appsrcs,
+                        &mut play,
+                    )
+                    .await?
+                {
+                    break;
+                }
+                expected_responses.clear();
+                keep_alive = keep_alive_interval(state.session_timeout);
+
+                if let Some(seek) = play.seek_in_progress.take() {
+                    if seek.flags.contains(gst::SeekFlags::FLUSH) {
+                        state.flush_stop(seek.seqnum, true);
+                    }
+                    play.pending_seek = Some(seek);
+                    play.playing = true;
+                }
+                // The new session would start at the beginning of on-demand media otherwise
+                if play.pending_seek.is_none() {
+                    play.pending_seek = state.resume_seek(play.last_play_seek.as_ref());
+                }
+                if std::mem::take(&mut play.playing) {
+                    let Some(s) = &session else {
+                        return Err(RtspError::InvalidMessage("Can't PLAY, no SETUP").into());
+                    };
+                    play.play_auth_retried = false;
+                    let seek = play.pending_seek.take();
+                    let cseq = state.play(s, seek.as_ref()).await?;
+                    expected_responses.push_back((Method::Play, cseq));
+                    play.last_play_seek = seek;
+                    play.playing = true;
+                }
+            }
+
             tokio::select! {
                 msg = state.stream.next() => match msg {
                     Some(Ok(rtsp_types::Message::Data(data))) => {
@@ -1049,12 +1970,17 @@ impl RtspSrc {
                         }
                     }
                     Some(Ok(rtsp_types::Message::Request(req))) => {
-                        // TODO: implement incoming GET_PARAMETER requests
                         gst::debug!(CAT, "<-- {req:#?}");
+                        if state.handle_request(&req, session.as_ref()).await? {
+                            *self.vod_range.lock().unwrap() = state.vod_range;
+                            let _ = obj.post_message(
+                                gst::message::DurationChanged::builder().src(&*obj).build(),
+                            );
+                        }
                     }
                     Some(Ok(rtsp_types::Message::Response(rsp))) => {
                         gst::debug!(CAT, "<-- {rsp:#?}");
-                        let Some((expected, cseq)) = &expected_response else {
+                        let Some((expected, cseq)) = expected_responses.pop_front() else {
                             continue;
                         };
                         let Some(s) = &session else {
@@ -1062,35 +1988,125 @@ impl RtspSrc {
                         };
                         match expected {
                             Method::Play => {
-                                state.play_response(&rsp, *cseq, s).await?;
+                                if !play.play_auth_retried && state.handle_unauthorized(&rsp) {
+                                    play.play_auth_retried = true;
+                                    let cseq = state.play(s, play.last_play_seek.as_ref()).await?;
+                                    expected_responses.push_back((Method::Play, cseq));
+                                    continue;
+                                }
+                                state.play_response(&rsp, cseq, s).await?;
                                 self.post_complete("request", "PLAY response received");
                             }
-                            Method::Teardown => state.teardown_response(&rsp, *cseq, s).await?,
+                            Method::Pause => {
+                                if let Err(err) = state.pause_response(&rsp, cseq, s).await {
+                                    gst::warning!(CAT, "PAUSE request failed: {err:?}");
+                                }
+                                if let Some(seek) = play.seek_in_progress.take() {
+                                    if seek.flags.contains(gst::SeekFlags::FLUSH) {
+                                        state.flush_stop(seek.seqnum, true);
+                                    }
+                                    play.play_auth_retried = false;
+                                    let cseq = state.play(s, Some(&seek)).await?;
+                                    expected_responses.push_back((Method::Play, cseq));
+                                    play.last_play_seek = Some(seek);
+                                    play.playing = true;
+                                }
+                            }
+                            Method::GetParameter | Method::Options => {
+                                if let Err(err) = RtspTaskState::check_response(&rsp, cseq, expected, Some(s)) {
+                                    gst::warning!(CAT, "Keep-alive request failed: {err:?}");
+                                }
+                            }
+                            Method::Teardown => state.teardown_response(&rsp, cseq, s).await?,
                             m => unreachable!("BUG: unexpected response method: {m:?}"),
                         };
                     }
                     Some(Err(e)) => {
-                        // TODO: reconnect or ignore if UDP sockets are still receiving data
-                        gst::error!(CAT, "I/O error: {e:?}, quitting");
-                        return Err(gst::FlowError::Error.into());
+                        if settings.max_reconnect_attempts == 0 {
+                            gst::error!(CAT, "I/O error: {e:?}, quitting");
+                            return Err(gst::FlowError::Error.into());
+                        }
+                        gst::warning!(CAT, "I/O error: {e:?}, reconnecting");
+                        reconnect = true;
                     }
                     None => {
-                        // TODO: reconnect or ignore if UDP sockets are still receiving data
-                        gst::error!(CAT, "TCP connection EOF, quitting");
-                        return Err(gst::FlowError::Eos.into());
+                        if settings.max_reconnect_attempts == 0 {
+                            gst::error!(CAT, "TCP connection EOF, quitting");
+                            return Err(gst::FlowError::Eos.into());
+                        }
+                        gst::warning!(CAT, "TCP connection EOF, reconnecting");
+                        reconnect = true;
                     }
                 },
+                _ = keep_alive.tick(), if settings.do_rtsp_keep_alive => {
+                    let Some(s) = &session else {
+                        continue;
+                    };
+                    let (method, cseq) = state.keep_alive(s).await?;
+                    expected_responses.push_back((method, cseq));
+                }
                 Some(cmd) = cmd_rx.recv() => match cmd {
                     Commands::Play => {
                         let Some(s) = &session else {
                             return Err(RtspError::InvalidMessage("Can't PLAY, no SETUP").into());
                         };
+                        if play.playing || play.seek_in_progress.is_some() {
+                            continue;
+                        }
                         self.post_start("request", "PLAY request sent");
-                        let cseq = state.play(s).await.inspect_err(|_err| {
+                        play.play_auth_retried = false;
+                        let seek = play.pending_seek.take();
+                        let cseq = state.play(s, seek.as_ref()).await.inspect_err(|_err| {
                             self.post_cancelled("request", "PLAY request cancelled");
                         })?;
-                        expected_response = Some((Method::Play, cseq));
+                        expected_responses.push_back((Method::Play, cseq));
+                        play.last_play_seek = seek;
+                        play.playing = true;
                     },
+                    Commands::Pause => {
+                        let Some(s) = &session else {
+                            return Err(RtspError::InvalidMessage("Can't PAUSE, no SETUP").into());
+                        };
+                        // Live media keeps streaming, the element is live anyway
+                        if !play.playing || state.vod_range.is_none() || !state.supports_pause {
+                            continue;
+                        }
+                        let cseq = state.pause(s).await?;
+                        expected_responses.push_back((Method::Pause, cseq));
+                        play.playing = false;
+                    }
+                    Commands::Seek(seek) => {
+                        let Some(s) = &session else {
+                            return Err(RtspError::InvalidMessage("Can't seek, no SETUP").into());
+                        };
+                        let flush = seek.flags.contains(gst::SeekFlags::FLUSH);
+                        if flush {
+                            state.flush_start(seek.seqnum);
+                        }
+                        if let Some(in_progress) = &mut play.seek_in_progress {
+                            // Still waiting for the PAUSE response, only the latest seek matters
+                            *in_progress = seek;
+                        } else if play.playing && state.supports_pause {
+                            // Otherwise the server would queue the new range after the current one
+                            let cseq = state.pause(s).await?;
+                            expected_responses.push_back((Method::Pause, cseq));
+                            play.playing = false;
+                            play.seek_in_progress = Some(seek);
+                        } else if play.playing {
+                            if flush {
+                                state.flush_stop(seek.seqnum, true);
+                            }
+                            play.play_auth_retried = false;
+                            let cseq = state.play(s, Some(&seek)).await?;
+                            expected_responses.push_back((Method::Play, cseq));
+                            play.last_play_seek = Some(seek);
+                        } else {
+                            if flush {
+                                state.flush_stop(seek.seqnum, true);
+                            }
+                            play.pending_seek = Some(seek);
+                        }
+                    }
                     Commands::Teardown(tx) => {
                         gst::info!(CAT, "Received Teardown command");
                         let Some(s) = &session else {
@@ -1103,10 +2119,10 @@ impl RtspSrc {
                         break;
                     }
                     Commands::Data(data) => {
-                        // We currently only send RTCP RR as data messages, this will change when
-                        // we support TCP ONVIF backchannels
+                        // RTCP RR, or RTP of the ONVIF backchannel
+                        let channel_id = data.channel_id();
                         state.sink.send(Message::Data(data)).await?;
-                        gst::debug!(CAT, "Sent RTCP RR over TCP");
+                        gst::trace!(CAT, "Sent data on channel {channel_id}");
                     }
                 },
                 else => {
@@ -1214,9 +2230,18 @@ struct RtspTaskState {
     content_base_or_location: Option<String>,
     aggregate_control: Option<Url>,
     sdp: Option<sdp_types::Session>,
-
-    stream:
-        Pin<Box<dyn Stream<Item = Result<Message<Body>, super::tcp_message::ReadError>> + Send>>,
+    /// NPT range of on-demand media, `None` for live media
+    vod_range: Option<(gst::ClockTime, gst::ClockTime)>,
+    npt_position: Option<NptPosition>,
+    supports_pause: bool,
+    supports_get_parameter: bool,
+    session_timeout: Duration,
+    onvif_backchannel: bool,
+
+    credentials: Option<(String, String)>,
+    auth: Option<Authenticator>,
+
+    stream: Pin<Box<dyn Stream<Item = Result<Message<Body>, tcp_message::ReadError>> + Send>>,
     sink: Pin<Box<dyn Sink<Message<Body>, Error = std::io::Error> + Send>>,
 
     setup_params: Vec<RtspSetupParams>,
@@ -1227,11 +2252,19 @@ struct RtspSetupParams {
     control_url: Url,
     transport: RtspTransportInfo,
     rtp_appsrc: Option<gst_app::AppSrc>,
+    rtcp_appsrc: Option<gst_app::AppSrc>,
     caps: gst::Caps,
+    // `sendonly` media that we send to
+    backchannel: bool,
 }
 
 impl RtspTaskState {
-    fn new(url: Url, stream: RtspStream, sink: RtspSink) -> Self {
+    fn new(
+        url: Url,
+        credentials: Option<(String, String)>,
+        stream: RtspStream,
+        sink: RtspSink,
+    ) -> Self {
         RtspTaskState {
             cseq: 0u32,
             url,
@@ -1239,6 +2272,14 @@ impl RtspTaskState {
             content_base_or_location: None,
             aggregate_control: None,
             sdp: None,
+            vod_range: None,
+            npt_position: None,
+            supports_pause: false,
+            supports_get_parameter: false,
+            session_timeout: DEFAULT_SESSION_TIMEOUT,
+            onvif_backchannel: false,
+            credentials,
+            auth: None,
             stream,
             sink,
             setup_params: Vec::new(),
@@ -1299,6 +2340,99 @@ impl RtspTaskState {
         Ok(())
     }
 
+    /// Sets up authentication from the challenges of a `401 Unauthorized` response.
+    ///
+    /// Returns `true` if the request should be sent again.
+    fn handle_unauthorized(&mut self, rsp: &Response<Body>) -> bool {
+        if rsp.status() != StatusCode::Unauthorized {
+            return false;
+        }
+
+        let Some((user_id, user_pw)) = &self.credentials else {
+            gst::warning!(
+                CAT,
+                "Server requires authentication, but no credentials were provided"
+            );
+            return false;
+        };
+
+        let challenges = rsp
+            .header(&WWW_AUTHENTICATE)
+            .map(|v| auth::parse_challenges(v.as_str()))
+            .unwrap_or_default();
+        let Some(authenticator) = Authenticator::new(user_id, user_pw, challenges) else {
+            gst::warning!(
+                CAT,
+                "No supported authentication method in {:?}",
+                rsp.header(&WWW_AUTHENTICATE)
+            );
+            return false;
+        };
+
+        // Our credentials were already rejected, unless the server only wants a new nonce
+        let stale = matches!(authenticator.challenge(), auth::Challenge::Digest(d) if d.stale);
+        if self.auth.is_some() && !stale {
+            gst::warning!(CAT, "Server rejected our credentials");
+            return false;
+        }
+
+        gst::debug!(
+            CAT,
+            "Authenticating as {user_id} with {:?}",
+            authenticator.challenge()
+        );
+        self.auth = Some(authenticator);
+
+        true
+    }
+
+    /// Sends a request, with an `Authorization` header once the server asked for one.
+    async fn send_request(&mut self, mut req: Request<Body>) -> Result<(), RtspError> {
+        // Servers only describe the backchannel media when we require it
+        if self.onvif_backchannel {
+            req.insert_header(REQUIRE, ONVIF_BACKCHANNEL_REQUIRE);
+        }
+        if let Some(auth) = &mut self.auth {
+            let uri = req.request_uri().map(Url::as_str).unwrap_or("*");
+            let authorization = auth.authorization(<&str>::from(req.method()), uri);
+            req.insert_header(AUTHORIZATION, authorization);
+        }
+
+        gst::debug!(CAT, "-->> {req:#?}");
+        self.sink.send(req.into()).await?;
+
+        Ok(())
+    }
+
+    /// Sends a request and waits for its response. If the server asks for authentication, the
+    /// request is sent once more with credentials.
+    async fn request(
+        &mut self,
+        mut req: Request<Body>,
+        name: &'static str,
+    ) -> Result<Response<Body>, RtspError> {
+        let mut retried = false;
+        loop {
+            self.send_request(req.clone()).await?;
+
+            let rsp = match self.stream.next().await {
+                Some(Ok(rtsp_types::Message::Response(rsp))) => Ok(rsp),
+                Some(Ok(m)) => Err(RtspError::UnexpectedMessage(name, m)),
+                Some(Err(e)) => Err(e.into()),
+                None => Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, name).into()),
+            }?;
+
+            if !retried && self.handle_unauthorized(&rsp) {
+                retried = true;
+                self.cseq += 1;
+                req.insert_typed_header::<CSeq>(&self.cseq.into());
+                continue;
+            }
+
+            return Ok(rsp);
+        }
+    }
+
     async fn options(&mut self) -> Result<(), RtspError> {
         self.cseq += 1;
         let req = Request::builder(Method::Options, self.version)
@@ -1307,17 +2441,7 @@ impl RtspTaskState {
             .header(USER_AGENT, DEFAULT_USER_AGENT)
             .build(Body::default());
 
-        gst::debug!(CAT, "-->> {req:#?}");
-        self.sink.send(req.into()).await?;
-
-        let rsp = match self.stream.next().await {
-            Some(Ok(rtsp_types::Message::Response(rsp))) => Ok(rsp),
-            Some(Ok(m)) => Err(RtspError::UnexpectedMessage("OPTIONS response", m)),
-            Some(Err(e)) => Err(e.into()),
-            None => Err(
-                std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "options response").into(),
-            ),
-        }?;
+        let rsp = self.request(req, "OPTIONS response").await?;
         gst::debug!(CAT, "<<-- {rsp:#?}");
         Self::check_response(&rsp, self.cseq, Method::Options, None)?;
 
@@ -1326,6 +2450,8 @@ impl RtspTaskState {
                 "OPTIONS response does not contain a valid Public header",
             ));
         };
+        self.supports_pause = methods.contains(&Method::Pause);
+        self.supports_get_parameter = methods.contains(&Method::GetParameter);
 
         let needed = [
             Method::Describe,
@@ -1350,6 +2476,23 @@ impl RtspTaskState {
         }
     }
 
+    // Media without an end time are live
+    fn parse_vod_range(sdp: &sdp_types::Session) -> Option<(gst::ClockTime, gst::ClockTime)> {
+        let range = sdp
+            .get_first_attribute_value("range")
+            .ok()
+            .flatten()
+            .or_else(|| {
+                sdp.medias
+                    .first()
+                    .and_then(|m| m.get_first_attribute_value("range").ok().flatten())
+            });
+
+        range
+            .and_then(sdp::parse_npt_range)
+            .and_then(|(start, end)| Some((start, end?)))
+    }
+
     async fn describe(&mut self) -> Result<(), RtspError> {
         self.cseq += 1;
         let req = Request::builder(Method::Describe, self.version)
@@ -1359,19 +2502,7 @@ impl RtspTaskState {
             .request_uri(self.url.clone())
             .build(Body::default());
 
-        gst::debug!(CAT, "-->> {req:#?}");
-        self.sink.send(req.into()).await?;
-
-        let rsp = match self.stream.next().await {
-            Some(Ok(rtsp_types::Message::Response(rsp))) => Ok(rsp),
-            Some(Ok(m)) => Err(RtspError::UnexpectedMessage("DESCRIBE response", m)),
-            Some(Err(e)) => Err(e.into()),
-            None => Err(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "describe response",
-            )
-            .into()),
-        }?;
+        let rsp = self.request(req, "DESCRIBE response").await?;
         gst::debug!(
             CAT,
             "<<-- Response {:#?}",
@@ -1385,10 +2516,12 @@ impl RtspTaskState {
             .map(|v| v.to_string());
 
         gst::info!(CAT, "{}", std::str::from_utf8(rsp.body()).unwrap());
-        // TODO: read range attribute from SDP for VOD use-cases
         let sdp = sdp_types::Session::parse(rsp.body())?;
         gst::debug!(CAT, "{sdp:#?}");
 
+        self.vod_range = Self::parse_vod_range(&sdp);
+        gst::debug!(CAT, "VOD range: {:?}", self.vod_range);
+
         self.sdp.replace(sdp);
         Ok(())
     }
@@ -1424,7 +2557,7 @@ impl RtspTaskState {
             let parsed = match RtspTransportInfo::try_from(t) {
                 Ok(p) => p,
                 Err(err) => {
-                    last_error = err;
+                    last_error = RtspError::Fatal(err.to_string());
                     continue;
                 }
             };
@@ -1448,7 +2581,8 @@ impl RtspTaskState {
         protocols: &[RtspProtocol],
         mode: TransportMode,
     ) -> Result<Vec<RtspSetupParams>, RtspError> {
-        let sdp = self.sdp.as_ref().expect("Must have SDP by now");
+        // Cloned because requests need mutable access to the state
+        let sdp = self.sdp.clone().expect("Must have SDP by now");
         let base = self
             .content_base_or_location
             .as_ref()
@@ -1462,7 +2596,6 @@ impl RtspTaskState {
             .and_then(|v| sdp::parse_control_path(v, &base));
         let mut b = gst::Structure::builder("application/x-rtp");
 
-        // TODO: parse range for VOD
         let skip_attrs = ["control", "range"];
         for sdp_types::Attribute { attribute, value } in &sdp.attributes {
             if skip_attrs.contains(&attribute.as_str()) {
@@ -1494,8 +2627,7 @@ impl RtspTaskState {
                 .ok()
                 .flatten()
                 .and_then(|v| sdp::parse_control_path(v, &base));
-            let Some(control_url) = media_control.as_ref().or(self.aggregate_control.as_ref())
-            else {
+            let Some(control_url) = media_control.or_else(|| self.aggregate_control.clone()) else {
                 gst::warning!(
                     CAT,
                     "No session control or media control for {} fmt {}, ignoring",
@@ -1505,6 +2637,12 @@ impl RtspTaskState {
                 continue;
             };
 
+            let backchannel = m.attributes.iter().any(|a| a.attribute == "sendonly");
+            if backchannel && !self.onvif_backchannel {
+                gst::info!(CAT, "Ignoring backchannel media {} fmt {}", m.media, m.fmt);
+                continue;
+            }
+
             // RTP caps
             let Ok(pt) = m.fmt.parse::<u8>() else {
                 gst::error!(CAT, "Could not parse pt: {}, ignoring media", m.fmt);
@@ -1532,12 +2670,16 @@ impl RtspTaskState {
             let mut transports = Vec::new();
             let (conn_protocols, is_ipv4) = sdp::parse_connections(&m.connections);
 
-            let protocols = if !conn_protocols.is_empty() {
+            let mut protocols = if !conn_protocols.is_empty() {
                 let p = protocols.iter().cloned().collect::<BTreeSet<_>>();
                 p.intersection(&conn_protocols).cloned().collect::<Vec<_>>()
             } else {
                 protocols.to_owned()
             };
+            // We have to send to the server's address
+            if backchannel {
+                protocols.retain(|p| *p != RtspProtocol::UdpMulticast);
+            }
 
             if protocols.is_empty() {
                 gst::error!(CAT, "No available protocols left, skipping media");
@@ -1602,28 +2744,18 @@ impl RtspTaskState {
                 req
             };
             let req = req.build(Body::default());
-            let cseq = self.cseq;
-
-            gst::debug!(CAT, "-->> {req:#?}");
-            self.sink.send(req.into()).await?;
 
             // RTSP 2 supports pipelining of SETUP requests, so this ping-pong would have to be
             // reworked if we want to support it.
-            let rsp = match self.stream.next().await {
-                Some(Ok(rtsp_types::Message::Response(rsp))) => Ok(rsp),
-                Some(Ok(m)) => Err(RtspError::UnexpectedMessage("SETUP response", m)),
-                Some(Err(e)) => Err(e.into()),
-                None => Err(std::io::Error::new(
-                    std::io::ErrorKind::UnexpectedEof,
-                    "setup response",
-                )
-                .into()),
-            }?;
+            let rsp = self.request(req, "SETUP response").await?;
             gst::debug!(CAT, "<<-- {rsp:#?}");
-            Self::check_response(&rsp, cseq, Method::Setup, session.as_ref())?;
+            Self::check_response(&rsp, self.cseq, Method::Setup, session.as_ref())?;
             let new_session = rsp
                 .typed_header::<Session>()?
                 .ok_or(RtspError::InvalidMessage("No session in SETUP response"))?;
+            self.session_timeout = new_session
+                .1
+                .map_or(DEFAULT_SESSION_TIMEOUT, Duration::from_secs);
             // Manually strip timeout field: https://github.com/sdroege/rtsp-types/issues/24
             session.replace(Session(new_session.0, None));
             let mut parsed_transport = if let Some(transports) = rsp.typed_header::<Transports>()? {
@@ -1693,28 +2825,62 @@ impl RtspTaskState {
             };
             let caps = gst::Caps::from(s);
             setup_params.push(RtspSetupParams {
-                control_url: control_url.clone(),
+                control_url,
                 transport: parsed_transport,
                 rtp_appsrc: None,
+                rtcp_appsrc: None,
                 caps,
+                backchannel,
             });
         }
         Ok(setup_params)
     }
 
-    async fn play(&mut self, session: &Session) -> Result<u32, RtspError> {
+    async fn play(
+        &mut self,
+        session: &Session,
+        seek: Option<&SeekParams>,
+    ) -> Result<u32, RtspError> {
         self.cseq += 1;
         let request_uri = self.aggregate_control.as_ref().unwrap_or(&self.url).clone();
-        let req = Request::builder(Method::Play, self.version)
+        let mut req = Request::builder(Method::Play, self.version)
             .typed_header::<CSeq>(&self.cseq.into())
-            .typed_header::<Range>(&Range::Npt(NptRange::From(NptTime::Now)))
             .header(USER_AGENT, DEFAULT_USER_AGENT)
             .request_uri(request_uri)
             .typed_header::<Session>(session);
 
+        if self.vod_range.is_none() {
+            req = req.typed_header::<Range>(&Range::Npt(NptRange::From(NptTime::Now)));
+        } else if let Some(SeekParams {
+            start: Some(start),
+            stop,
+            ..
+        }) = seek
+        {
+            // Without a Range the server resumes from the pause point
+            let stop = stop.map(sdp::format_npt_time).unwrap_or_default();
+            req = req.header(
+                RANGE,
+                format!("npt={}-{stop}", sdp::format_npt_time(*start)),
+            );
+        }
+
+        if let Some(seek) = seek.filter(|seek| seek.rate != 1.0) {
+            // Same mapping as rtspsrc: Scale for trick modes, Speed otherwise
+            if seek.flags.contains(gst::SeekFlags::TRICKMODE) {
+                req = req.header(SCALE, format!("{:.3}", seek.rate));
+            } else if seek.rate < 0.0 {
+                req = req.header(SCALE, "-1.000");
+                if seek.rate != -1.0 {
+                    req = req.header(SPEED, format!("{:.3}", -seek.rate));
+                }
+            } else {
+                req = req.header(SPEED, format!("{:.3}", seek.rate));
+            }
+        }
+
         let req = req.build(Body::default());
-        gst::debug!(CAT, "-->> {req:#?}");
-        self.sink.send(req.into()).await?;
+        self.send_request(req).await?;
         Ok(self.cseq)
     }
 
@@ -1725,31 +2891,229 @@ impl RtspTaskState {
         session: &Session,
     ) -> Result<(), RtspError> {
         Self::check_response(rsp, cseq, Method::Play, Some(session))?;
-        if let Some(RtpInfos::V1(rtpinfos)) = rsp.typed_header::<RtpInfos>()? {
-            for rtpinfo in rtpinfos {
-                for params in self.setup_params.iter_mut() {
-                    if params.control_url == rtpinfo.uri {
-                        let mut changed = false;
-                        let mut caps = params.rtp_appsrc.as_ref().unwrap().caps().unwrap();
-                        let capsref = caps.make_mut();
-                        if let Some(v) = rtpinfo.seq {
-                            capsref.set("seqnum-base", v as u32);
-                            changed = true;
-                        }
-                        if let Some(v) = rtpinfo.rtptime {
-                            capsref.set("clock-base", v);
-                            changed = true;
-                        }
-                        if changed {
-                            params.rtp_appsrc.as_ref().unwrap().set_caps(Some(&caps));
-                        }
-                    }
+        let rtpinfos = match rsp.typed_header::<RtpInfos>()? {
+            Some(RtpInfos::V1(rtpinfos)) => rtpinfos,
+            _ => {
+                gst::warning!(CAT, "No RTPInfos V1 header in PLAY response");
+                Vec::new()
+            }
+        };
+
+        // Same caps fields as rtspsrc, used by rtpjitterbuffer for VOD playback
+        let range = rsp
+            .header(&RANGE)
+            .and_then(|v| sdp::parse_npt_range(v.as_str()));
+        let scale = rsp
+            .header(&SCALE)
+            .and_then(|v| v.as_str().trim().parse::<f64>().ok());
+        let speed = rsp
+            .header(&SPEED)
+            .and_then(|v| v.as_str().trim().parse::<f64>().ok());
+
+        if let Some((start, _)) = range.filter(|_| self.vod_range.is_some()) {
+            self.npt_position = Some(NptPosition {
+                npt: start,
+                rate: scale.unwrap_or(1.0) * speed.unwrap_or(1.0),
+                since: Some(Instant::now()),
+            });
+        }
+
+        for params in self.setup_params.iter_mut() {
+            let Some(appsrc) = params.rtp_appsrc.as_ref() else {
+                continue;
+            };
+            let Some(mut caps) = appsrc.caps() else {
+                continue;
+            };
+            let old_caps = caps.clone();
+            let capsref = caps.make_mut();
+            let s = capsref.structure_mut(0).unwrap();
+
+            for rtpinfo in rtpinfos.iter().filter(|i| params.control_url == i.uri) {
+                if let Some(v) = rtpinfo.seq {
+                    s.set("seqnum-base", v as u32);
+                }
+                if let Some(v) = rtpinfo.rtptime {
+                    s.set("clock-base", v);
+                }
+            }
+            if let Some((start, stop)) = range {
+                s.set("npt-start", start.nseconds());
+                match stop {
+                    Some(stop) => s.set("npt-stop", stop.nseconds()),
+                    None => s.remove_field("npt-stop"),
                 }
             }
+            if let Some(scale) = scale {
+                s.set("play-scale", scale);
+            }
+            if let Some(speed) = speed {
+                s.set("play-speed", speed);
+            }
+
+            if caps != old_caps {
+                appsrc.set_caps(Some(&caps));
+            }
+        }
+        Ok(())
+    }
+
+    async fn pause(&mut self, session: &Session) -> Result<u32, RtspError> {
+        self.cseq += 1;
+        let request_uri = self.aggregate_control.as_ref().unwrap_or(&self.url).clone();
+        let req = Request::builder(Method::Pause, self.version)
+            .typed_header::<CSeq>(&self.cseq.into())
+            .header(USER_AGENT, DEFAULT_USER_AGENT)
+            .request_uri(request_uri)
+            .typed_header::<Session>(session);
+
+        let req = req.build(Body::default());
+        self.send_request(req).await?;
+        Ok(self.cseq)
+    }
+
+    async fn pause_response(
+        &mut self,
+        rsp: &Response<Body>,
+        cseq: u32,
+        session: &Session,
+    ) -> Result<(), RtspError> {
+        Self::check_response(rsp, cseq, Method::Pause, Some(session))?;
+        self.pause_npt_position();
+        Ok(())
+    }
+
+    fn pause_npt_position(&mut self) {
+        if let Some(position) = &mut self.npt_position {
+            position.npt = position.current();
+            position.since = None;
+        }
+    }
+
+    /// Seek that continues on-demand media from the estimated current position.
+    fn resume_seek(&self, last_seek: Option<&SeekParams>) -> Option<SeekParams> {
+        let (start, end) = self.vod_range?;
+        let position = self.npt_position?.current().clamp(start, end);
+
+        Some(SeekParams {
+            seqnum: gst::Seqnum::next(),
+            rate: last_seek.map_or(1.0, |seek| seek.rate),
+            flags: last_seek.map_or(gst::SeekFlags::empty(), |seek| {
+                seek.flags.difference(gst::SeekFlags::FLUSH)
+            }),
+            start: Some(position),
+            stop: last_seek.and_then(|seek| seek.stop),
+        })
+    }
+
+    /// Sends a request that keeps the session alive.
+    async fn keep_alive(&mut self, session: &Session) -> Result<(Method, u32), RtspError> {
+        // Servers refresh the session on any request, but GET_PARAMETER is the recommended one
+        let method = if self.supports_get_parameter {
+            Method::GetParameter
         } else {
-            gst::warning!(CAT, "No RTPInfos V1 header in PLAY response");
+            Method::Options
         };
-        Ok(())
+
+        self.cseq += 1;
+        let request_uri = self.aggregate_control.as_ref().unwrap_or(&self.url).clone();
+        let req = Request::builder(method.clone(), self.version)
+            .typed_header::<CSeq>(&self.cseq.into())
+            .header(USER_AGENT, DEFAULT_USER_AGENT)
+            .request_uri(request_uri)
+            .typed_header::<Session>(session);
+
+        let req = req.build(Body::default());
+        self.send_request(req).await?;
+        Ok((method, self.cseq))
+    }
+
+    /// Answers a request from the server.
+    ///
+    /// Returns `true` if the server announced a new description with a different range.
+    async fn handle_request(
+        &mut self,
+        req: &Request<Body>,
+        session: Option<&Session>,
+    ) -> Result<bool, RtspError> {
+        let Ok(Some(cseq)) = req.typed_header::<CSeq>() else {
+            gst::warning!(CAT, "Ignoring {:?} request without CSeq", req.method());
+            return Ok(false);
+        };
+
+        let mut range_changed = false;
+        let status = match req.method() {
+            // Empty requests are used by servers to check if we are still alive
+            Method::GetParameter | Method::SetParameter if req.body().is_empty() => StatusCode::Ok,
+            Method::GetParameter | Method::SetParameter => {
+                gst::info!(
+                    CAT,
+                    "Unknown parameters in {:?} request: {:?}",
+                    req.method(),
+                    std::str::from_utf8(req.body())
+                );
+                StatusCode::ParameterNotUnderstood
+            }
+            Method::Options => StatusCode::Ok,
+            Method::Announce => match sdp_types::Session::parse(req.body()) {
+                Ok(sdp) => {
+                    let vod_range = Self::parse_vod_range(&sdp);
+                    gst::info!(
+                        CAT,
+                        "Server announced a new description, range {vod_range:?}"
+                    );
+                    range_changed = vod_range != self.vod_range;
+                    self.vod_range = vod_range;
+                    self.sdp = Some(sdp);
+                    StatusCode::Ok
+                }
+                Err(err) => {
+                    gst::warning!(CAT, "Invalid SDP in ANNOUNCE request: {err:?}");
+                    StatusCode::BadRequest
+                }
+            },
+            _ => StatusCode::NotImplemented,
+        };
+
+        let mut rsp = Response::builder(self.version, status)
+            .typed_header::<CSeq>(&cseq)
+            .header(USER_AGENT, DEFAULT_USER_AGENT);
+        if let Some(session) = session {
+            rsp = rsp.typed_header::<Session>(session);
+        }
+        if *req.method() == Method::Options {
+            rsp = rsp.header(PUBLIC, "OPTIONS, GET_PARAMETER, SET_PARAMETER, ANNOUNCE");
+        }
+
+        let rsp = rsp.build(Body::default());
+        gst::debug!(CAT, "-->> {rsp:#?}");
+        self.sink.send(rsp.into()).await?;
+
+        Ok(range_changed)
+    }
+
+    /// RTP and RTCP appsrcs of all streams.
+    fn appsrcs(&self) -> impl Iterator<Item = &gst_app::AppSrc> {
+        self.setup_params
+            .iter()
+            .flat_map(|p| [p.rtp_appsrc.as_ref(), p.rtcp_appsrc.as_ref()])
+            .flatten()
+    }
+
+    fn flush_start(&self, seqnum: gst::Seqnum) {
+        for appsrc in self.appsrcs() {
+            appsrc.send_event(gst::event::FlushStart::builder().seqnum(seqnum).build());
+        }
+    }
+
+    fn flush_stop(&self, seqnum: gst::Seqnum, reset_time: bool) {
+        for appsrc in self.appsrcs() {
+            appsrc.send_event(
+                gst::event::FlushStop::builder(reset_time)
+                    .seqnum(seqnum)
+                    .build(),
+            );
+        }
     }
 
     async fn teardown(&mut self, session: &Session) -> Result<u32, RtspError> {
@@ -1762,8 +3126,7 @@ impl RtspTaskState {
             .typed_header::<Session>(session);
 
         let req = req.build(Body::default());
-        gst::debug!(CAT, "-->> {req:#?}");
-        self.sink.send(req.into()).await?;
+        self.send_request(req).await?;
         Ok(self.cseq)
     }
 
@@ -1778,6 +3141,67 @@ impl RtspTaskState {
     }
 }
 
+/// Opens the TCP connection for RTSP messages, with TLS for `rtsps://` URLs.
+async fn connect(url: &Url, settings: &Settings) -> Result<(RtspStream, RtspSink)> {
+    gst::info!(CAT, "Connecting to {url} ..");
+    let tls = matches!(url.scheme(), "rtsps" | "rtspsu" | "rtspst");
+    let default_port = if tls {
+        DEFAULT_RTSPS_PORT
+    } else {
+        DEFAULT_RTSP_PORT
+    };
+    let hostname_port = format!(
+        "{}:{}",
+        url.host_str().unwrap(),
+        url.port().unwrap_or(default_port)
+    );
+
+    let s = TcpStream::connect(hostname_port)
+        .await
+        .context("Failed to connect to RTSP server")?;
+    let _ = s.set_nodelay(true);
+
+    let (stream, sink): (RtspStream, RtspSink) = if tls {
+        let server_name = match url.host() {
+            Some(url::Host::Ipv6(addr)) => addr.to_string(),
+            _ => url.host_str().unwrap().to_string(),
+        };
+        let s = super::tls::connect(
+            s,
+            &server_name,
+            settings.tls_validation_flags,
+            settings.tls_ca_file.as_deref(),
+        )
+        .await
+        .context("TLS handshake with RTSP server failed")?;
+        let (read, write) = tokio::io::split(s);
+        (
+            Box::pin(tcp_message::async_read(read, MAX_MESSAGE_SIZE).fuse()),
+            Box::pin(tcp_message::async_write(write)),
+        )
+    } else {
+        let (read, write) = s.into_split();
+        (
+            Box::pin(tcp_message::async_read(read, MAX_MESSAGE_SIZE).fuse()),
+            Box::pin(tcp_message::async_write(write)),
+        )
+    };
+
+    Ok((stream, sink))
+}
+
+fn keep_alive_interval(session_timeout: Duration) -> time::Interval {
+    // Leave enough time for the request to reach the server before the session times out
+    let period = if session_timeout >= Duration::from_secs(20) {
+        session_timeout - Duration::from_secs(5)
+    } else {
+        (session_timeout / 2).max(Duration::from_secs(1))
+    };
+    let mut interval = time::interval_at(time::Instant::now() + period, period);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    interval
+}
+
 fn bind_port(port: u16, is_ipv4: bool) -> Result<UdpSocket, std::io::Error> {
     let domain = if is_ipv4 {
         socket2::Domain::IPV4
@@ -1884,6 +3308,65 @@ fn on_rtcp_tcp(
     }
 }
 
+/// Caps of a backchannel stream: its RTP caps from the SDP, and the audio we can payload for it.
+fn backchannel_caps(rtp_caps: &gst::Caps) -> gst::Caps {
+    let s = rtp_caps.structure(0).unwrap();
+
+    // Only the fields a payloader sets, the SDP attributes would never match
+    let mut rtp = gst::Structure::new_empty("application/x-rtp");
+    for field in [
+        "media",
+        "payload",
+        "clock-rate",
+        "encoding-name",
+        "encoding-params",
+    ] {
+        if let Ok(value) = s.value(field) {
+            rtp.set_value(field, value.clone());
+        }
+    }
+    let mut caps = gst::Caps::from(rtp);
+
+    let mut encoded = match s.get::<&str>("encoding-name") {
+        Ok("PCMU") => gst::Structure::new_empty("audio/x-mulaw"),
+        Ok("PCMA") => gst::Structure::new_empty("audio/x-alaw"),
+        Ok("MPEG4-GENERIC") | Ok("MP4A-LATM") => gst::Structure::builder("audio/mpeg")
+            .field("mpegversion", 4i32)
+            .field("stream-format", "raw")
+            .build(),
+        _ => return caps,
+    };
+    if let Ok(rate) = s.get::<i32>("clock-rate") {
+        encoded.set("rate", rate);
+    }
+    let channels = s
+        .get::<&str>("encoding-params")
+        .ok()
+        .and_then(|c| c.parse::<i32>().ok())
+        .unwrap_or(1);
+    encoded.set("channels", channels);
+    caps.make_mut().append_structure(encoded);
+
+    caps
+}
+
+/// Payloader for encoded audio on a backchannel pad. Without a stream from the SDP yet, AAC is
+/// payloaded as MPEG4-GENERIC.
+fn backchannel_payloader(
+    s: &gst::StructureRef,
+    rtp_s: Option<&gst::StructureRef>,
+) -> Option<&'static str> {
+    let encoding_name = rtp_s.and_then(|s| s.get::<&str>("encoding-name").ok());
+
+    match (s.name().as_str(), encoding_name) {
+        ("audio/x-mulaw", _) => Some("rtppcmupay"),
+        ("audio/x-alaw", _) => Some("rtppcmapay"),
+        ("audio/mpeg", Some("MP4A-LATM")) => Some("rtpmp4apay"),
+        ("audio/mpeg", _) => Some("rtpmp4gpay"),
+        _ => None,
+    }
+}
+
 async fn udp_rtp_task(
     socket: &UdpSocket,
     appsrc: gst_app::AppSrc,
@@ -21,12 +21,16 @@
  * * RTCP-based A/V sync
  * * Lower transport selection and priority (NEW!)
  *   - Also supports different lower transports for each SETUP
+ * * Basic and Digest authentication
+ * * RTSP over TLS (`rtsps://`)
+ * * VOD support: PAUSE, seeking and trick-play with Scale/Speed
+ * * Session keep-alive and replies to server requests (GET_PARAMETER, SET_PARAMETER, ANNOUNCE)
+ * * Reconnection with backoff when the connection to the server is lost
+ * * ONVIF audio backchannel with the `backchannel_%u` request sink pads
  *
  * Some missing features:
- * * SET_PARAMETER/GET_PARAMETER messages
  * * SRTP support
- * * VOD support: PAUSE, seeking, etc
- * * ONVIF backchannel and trick mode support
+ * * ONVIF trick mode support
  * * and more
  *
  * Please see the [README](https://gitlab.freedesktop.org/gstreamer/gst-plugins-rs/-/blob/main/net/rtsp/README.md)
@@ -35,17 +39,19 @@
 use gst::glib;
 use gst::prelude::*;
 
-mod body;
+mod auth;
 mod imp;
 mod sdp;
-mod tcp_message;
-mod transport;
+mod tls;
 
 glib::wrapper! {
     pub struct RtspSrc(ObjectSubclass<imp::RtspSrc>) @extends gst::Bin, gst::Element, gst::Object, @implements gst::URIHandler;
 }
 
 pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    #[cfg(feature = "doc")]
+    Backchannel::static_type().mark_as_plugin_api(gst::PluginAPIFlags::empty());
+
     gst::Element::register(
         Some(plugin),
         "rtspsrc2",
@@ -53,3 +59,14 @@ pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
         RtspSrc::static_type(),
     )
 }
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, glib::Enum, Default)]
+#[enum_type(name = "GstRtspSrc2Backchannel")]
+#[repr(i32)]
+pub enum Backchannel {
+    #[default]
+    #[enum_value(name = "No backchannel", nick = "none")]
+    None,
+    #[enum_value(name = "ONVIF audio backchannel", nick = "onvif")]
+    Onvif,
+}
@@ -9,8 +9,8 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use super::imp::RtspError;
-use super::imp::RtspProtocol;
 use super::imp::CAT;
+use crate::rtsp::transport::RtspProtocol;
 use sdp_types::Attribute;
 use sdp_types::Connection;
 use std::collections::BTreeSet;
@@ -188,6 +188,68 @@ pub fn parse_control_path(path: &str, base: &Url) -> Option<Url> {
     }
 }
 
+// Seconds with an optional fraction, e.g. `12.345`, in nanoseconds
+fn parse_npt_seconds(secs: &str) -> Option<u64> {
+    let (secs, frac) = secs.split_once('.').unwrap_or((secs, ""));
+    if secs.is_empty() || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let nanos = frac
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(9)
+        .fold(0, |acc, b| acc * 10 + (b - b'0') as u64);
+
+    secs.parse::<u64>()
+        .ok()?
+        .checked_mul(1_000_000_000)?
+        .checked_add(nanos)
+}
+
+fn parse_npt_time(time: &str) -> Option<gst::ClockTime> {
+    let nseconds = match *time.split(':').collect::<Vec<_>>() {
+        [secs] => parse_npt_seconds(secs)?,
+        [h, m, secs] => {
+            let minutes = h.parse::<u64>().ok()?.checked_mul(60)? + m.parse::<u8>().ok()? as u64;
+            minutes
+                .checked_mul(60_000_000_000)?
+                .checked_add(parse_npt_seconds(secs)?)?
+        }
+        _ => return None,
+    };
+
+    Some(gst::ClockTime::from_nseconds(
+        nseconds.min(gst::ClockTime::MAX.nseconds()),
+    ))
+}
+
+// https://datatracker.ietf.org/doc/html/rfc2326#section-3.6
+// Used by the `a=range` SDP attribute and the `Range` header. Returns `None` for non-NPT ranges
+// and for live ranges starting at `now`.
+pub fn parse_npt_range(range: &str) -> Option<(gst::ClockTime, Option<gst::ClockTime>)> {
+    let range = range.split(';').next()?.trim();
+    let (start, end) = range
+        .strip_prefix("npt")?
+        .trim_start()
+        .strip_prefix('=')?
+        .split_once('-')?;
+    let start = match start.trim() {
+        "" => gst::ClockTime::ZERO,
+        start => parse_npt_time(start)?,
+    };
+    let end = match end.trim() {
+        "" => None,
+        end => Some(parse_npt_time(end)?),
+    };
+
+    Some((start, end))
+}
+
+pub fn format_npt_time(time: gst::ClockTime) -> String {
+    format!("{}.{:03}", time.seconds(), time.mseconds() % 1000)
+}
+
 #[allow(clippy::result_large_err)]
 fn parse_rtpmap(
     rtpmap: &str,
@@ -431,3 +493,66 @@ pub fn parse_connections(conns: &Vec<Connection>) -> (BTreeSet<RtspProtocol>, bo
     }
     (conn_protocols, is_ipv4)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtsp::sdp::format_media;
+
+    #[test]
+    fn npt_range() {
+        assert_eq!(
+            parse_npt_range("npt=0-123.45"),
+            Some((
+                gst::ClockTime::ZERO,
+                Some(gst::ClockTime::from_mseconds(123_450))
+            ))
+        );
+        assert_eq!(
+            parse_npt_range("npt=00:01:05.5-"),
+            Some((gst::ClockTime::from_mseconds(65_500), None))
+        );
+        assert_eq!(
+            parse_npt_range("npt=10-20;time=19970123T143720Z"),
+            Some((
+                gst::ClockTime::from_seconds(10),
+                Some(gst::ClockTime::from_seconds(20))
+            ))
+        );
+        assert_eq!(parse_npt_range("npt=now-"), None);
+        assert_eq!(parse_npt_range("clock=19961108T142300Z-"), None);
+
+        assert_eq!(
+            format_npt_time(gst::ClockTime::from_mseconds(65_500)),
+            "65.500"
+        );
+    }
+
+    #[test]
+    fn media_roundtrip() {
+        gst::init().unwrap();
+
+        let caps = gst::Structure::builder("application/x-rtp")
+            .field("media", "audio")
+            .field("payload", 96i32)
+            .field("clock-rate", 44100i32)
+            .field("encoding-name", "MP4A-LATM")
+            .field("encoding-params", "2")
+            .field("ssrc", 0x1234u32)
+            .field("cpresent", "0")
+            .field("config", "40002410")
+            .build();
+        // Written by the server
+        let media = format_media(&caps, "stream=0", "IN IP4 0.0.0.0").unwrap();
+        let sdp = sdp_types::Session::parse(
+            format!("v=0\r\no=- 0 0 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\n{media}").as_bytes(),
+        )
+        .unwrap();
+        let mut s = gst::Structure::new_empty("application/x-rtp");
+        parse_media_attributes(&sdp.medias[0].attributes, 96, "audio", &mut s).unwrap();
+        assert_eq!(s.get::<&str>("encoding-name").unwrap(), "MP4A-LATM");
+        assert_eq!(s.get::<i32>("clock-rate").unwrap(), 44100);
+        assert_eq!(s.get::<&str>("encoding-params").unwrap(), "2");
+        assert_eq!(s.get::<&str>("config").unwrap(), "40002410");
+    }
+}
@@ -0,0 +1,458 @@
+// GStreamer RTSP Source 2
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::client::{verify_server_name, WebPkiServerVerifier};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::server::ParsedCertificate;
+use tokio_rustls::rustls::{CertificateError, DigitallySignedStruct, SignatureScheme};
+use tokio_rustls::TlsConnector;
+
+use gst_net::gio;
+
+use super::imp::CAT;
+
+fn certificate_error_flag(err: &rustls::Error) -> gio::TlsCertificateFlags {
+    let rustls::Error::InvalidCertificate(err) = err else {
+        return gio::TlsCertificateFlags::GENERIC_ERROR;
+    };
+
+    match err {
+        CertificateError::UnknownIssuer => gio::TlsCertificateFlags::UNKNOWN_CA,
+        CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. } => {
+            gio::TlsCertificateFlags::BAD_IDENTITY
+        }
+        CertificateError::Expired | CertificateError::ExpiredContext { .. } => {
+            gio::TlsCertificateFlags::EXPIRED
+        }
+        CertificateError::NotValidYet | CertificateError::NotValidYetContext { .. } => {
+            gio::TlsCertificateFlags::NOT_ACTIVATED
+        }
+        CertificateError::Revoked => gio::TlsCertificateFlags::REVOKED,
+        _ => gio::TlsCertificateFlags::GENERIC_ERROR,
+    }
+}
+
+/// Reads a DER tag-length-value, returns the tag, the value and the remaining data.
+fn der_read(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&len, mut data) = data.split_first()?;
+
+    let len = if len & 0x80 == 0 {
+        len as usize
+    } else {
+        let num_bytes = (len & 0x7f) as usize;
+        if num_bytes == 0 || num_bytes > 4 || data.len() < num_bytes {
+            return None;
+        }
+        let (len_bytes, rest) = data.split_at(num_bytes);
+        data = rest;
+        len_bytes
+            .iter()
+            .fold(0usize, |len, &b| (len << 8) | b as usize)
+    };
+
+    if data.len() < len {
+        return None;
+    }
+    let (value, rest) = data.split_at(len);
+
+    Some((tag, value, rest))
+}
+
+/// Parses an ASN.1 `UTCTime` or `GeneralizedTime` into seconds since the Unix epoch.
+fn der_time(tag: u8, value: &[u8]) -> Option<u64> {
+    let value = std::str::from_utf8(value).ok()?.strip_suffix('Z')?;
+    if !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (year, rest) = match tag {
+        // UTCTime, RFC 5280 section 4.1.2.5.1
+        0x17 if value.len() == 12 => {
+            let year = value[..2].parse::<i64>().unwrap();
+            (
+                if year >= 50 { 1900 + year } else { 2000 + year },
+                &value[2..],
+            )
+        }
+        // GeneralizedTime
+        0x18 if value.len() == 14 => (value[..4].parse::<i64>().unwrap(), &value[4..]),
+        _ => return None,
+    };
+    let field = |idx: usize| rest[idx..idx + 2].parse::<i64>().unwrap();
+    let (month, day) = (field(0), field(2));
+    let (hour, minute, second) = (field(4), field(6), field(8));
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since the epoch of the proleptic Gregorian calendar
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    u64::try_from(days * 86_400 + hour * 3_600 + minute * 60 + second).ok()
+}
+
+/// Returns the `notBefore` and `notAfter` times of a certificate in seconds since the Unix epoch.
+fn certificate_validity(cert: &CertificateDer<'_>) -> Option<(u64, u64)> {
+    let (0x30, cert, _) = der_read(cert)? else {
+        return None;
+    };
+    let (0x30, mut tbs, _) = der_read(cert)? else {
+        return None;
+    };
+
+    // Optional version, serialNumber, signature and issuer
+    let (tag, _, rest) = der_read(tbs)?;
+    if tag == 0xa0 {
+        tbs = rest;
+    }
+    for _ in 0..3 {
+        tbs = der_read(tbs)?.2;
+    }
+
+    let (0x30, validity, _) = der_read(tbs)? else {
+        return None;
+    };
+    let (tag, not_before, rest) = der_read(validity)?;
+    let not_before = der_time(tag, not_before)?;
+    let (tag, not_after, _) = der_read(rest)?;
+    let not_after = der_time(tag, not_after)?;
+
+    Some((not_before, not_after))
+}
+
+/// Verifies the server certificate against the system and user provided root certificates, but
+/// only fails for the errors selected in the `tls-validation-flags` property.
+#[derive(Debug)]
+struct CertificateVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    validation_flags: gio::TlsCertificateFlags,
+}
+
+impl CertificateVerifier {
+    /// Checks the chain, the validity period and the server name independently of each other, as
+    /// webpki stops at the first error.
+    fn certificate_errors(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Vec<rustls::Error> {
+        let mut errors = Vec::new();
+
+        let Some((not_before, not_after)) = certificate_validity(end_entity) else {
+            errors.push(rustls::Error::InvalidCertificate(
+                CertificateError::BadEncoding,
+            ));
+            return errors;
+        };
+        if now.as_secs() < not_before {
+            errors.push(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidYet,
+            ));
+        } else if now.as_secs() > not_after {
+            errors.push(rustls::Error::InvalidCertificate(CertificateError::Expired));
+        }
+
+        match ParsedCertificate::try_from(end_entity) {
+            Ok(cert) => {
+                if let Err(err) = verify_server_name(&cert, server_name) {
+                    errors.push(err);
+                }
+            }
+            Err(err) => errors.push(err),
+        }
+
+        // The chain is verified at a time at which the certificate is valid, so that an expired
+        // certificate doesn't hide chain errors. Name errors were already checked above.
+        let valid_time = UnixTime::since_unix_epoch(Duration::from_secs(not_before));
+        if let Err(err) = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            valid_time,
+        ) {
+            if certificate_error_flag(&err) != gio::TlsCertificateFlags::BAD_IDENTITY {
+                errors.push(err);
+            }
+        }
+
+        errors
+    }
+}
+
+impl ServerCertVerifier for CertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let Err(err) = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        ) else {
+            return Ok(ServerCertVerified::assertion());
+        };
+        if self
+            .validation_flags
+            .intersects(certificate_error_flag(&err))
+        {
+            return Err(err);
+        }
+
+        // The ignored error might have prevented further checks
+        for err in
+            self.certificate_errors(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            if self
+                .validation_flags
+                .intersects(certificate_error_flag(&err))
+            {
+                return Err(err);
+            }
+            gst::warning!(CAT, "Ignoring server certificate error: {err}");
+        }
+
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+fn root_certificates(ca_file: Option<&str>) -> Result<rustls::RootCertStore> {
+    let mut roots = rustls::RootCertStore::empty();
+
+    let native = rustls_native_certs::load_native_certs();
+    for err in &native.errors {
+        gst::warning!(CAT, "Failed to load system root certificates: {err}");
+    }
+    let (added, ignored) = roots.add_parsable_certificates(native.certs);
+    gst::debug!(
+        CAT,
+        "Loaded {added} system root certificates, ignored {ignored}"
+    );
+
+    if let Some(ca_file) = ca_file {
+        let file = File::open(ca_file).with_context(|| format!("Failed to open {ca_file}"))?;
+        for cert in rustls_pemfile::certs(&mut BufReader::new(file)) {
+            let cert =
+                cert.with_context(|| format!("Failed to read certificate from {ca_file}"))?;
+            roots
+                .add(cert)
+                .with_context(|| format!("Invalid certificate in {ca_file}"))?;
+        }
+    }
+
+    Ok(roots)
+}
+
+/// Performs the TLS handshake for an `rtsps://` connection.
+pub(crate) async fn connect(
+    stream: TcpStream,
+    host: &str,
+    validation_flags: gio::TlsCertificateFlags,
+    ca_file: Option<&str>,
+) -> Result<TlsStream<TcpStream>> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let inner = WebPkiServerVerifier::builder_with_provider(
+        Arc::new(root_certificates(ca_file)?),
+        provider.clone(),
+    )
+    .build()
+    .context("Failed to create certificate verifier")?;
+
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(CertificateVerifier {
+            inner,
+            validation_flags,
+        }))
+        .with_no_client_auth();
+
+    let server_name = ServerName::try_from(host.to_string())
+        .with_context(|| format!("Invalid server name {host}"))?;
+
+    let stream = TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await?;
+
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn self_signed(
+        name: &str,
+        not_before: (i32, u8, u8),
+        not_after: (i32, u8, u8),
+    ) -> CertificateDer<'static> {
+        let mut params = rcgen::CertificateParams::new(vec![name.to_string()]).unwrap();
+        params.not_before = rcgen::date_time_ymd(not_before.0, not_before.1, not_before.2);
+        params.not_after = rcgen::date_time_ymd(not_after.0, not_after.1, not_after.2);
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+
+        params.self_signed(&key_pair).unwrap().der().clone()
+    }
+
+    fn verify(
+        cert: &CertificateDer<'static>,
+        host: &str,
+        validation_flags: gio::TlsCertificateFlags,
+    ) -> Result<(), rustls::Error> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        // The test certificates are self-signed, so they are never issued by this root
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(self_signed("ca.example.com", (2020, 1, 1), (2090, 1, 1)))
+            .unwrap();
+        let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .unwrap();
+
+        CertificateVerifier {
+            inner,
+            validation_flags,
+        }
+        .verify_server_cert(
+            cert,
+            &[],
+            &ServerName::try_from(host.to_string()).unwrap(),
+            &[],
+            UnixTime::now(),
+        )
+        .map(|_| ())
+    }
+
+    #[test]
+    fn validity() {
+        let cert = self_signed("localhost", (2000, 1, 1), (2001, 1, 1));
+        assert_eq!(
+            certificate_validity(&cert),
+            Some((946_684_800, 978_307_200))
+        );
+
+        // GeneralizedTime is used from 2050 on
+        let cert = self_signed("localhost", (2020, 2, 29), (2050, 3, 1));
+        assert_eq!(
+            certificate_validity(&cert),
+            Some((1_582_934_400, 2_529_705_600))
+        );
+    }
+
+    #[test]
+    fn self_signed_wrong_hostname() {
+        let cert = self_signed("example.com", (2020, 1, 1), (2090, 1, 1));
+        let all = gio::TlsCertificateFlags::VALIDATE_ALL;
+
+        assert!(matches!(
+            verify(&cert, "localhost", all),
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::UnknownIssuer
+            ))
+        ));
+        // Ignoring the unknown issuer must not skip the hostname check
+        assert!(matches!(
+            verify(
+                &cert,
+                "localhost",
+                all - gio::TlsCertificateFlags::UNKNOWN_CA
+            ),
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. }
+            ))
+        ));
+        assert!(verify(
+            &cert,
+            "localhost",
+            all - gio::TlsCertificateFlags::UNKNOWN_CA - gio::TlsCertificateFlags::BAD_IDENTITY
+        )
+        .is_ok());
+        assert!(verify(
+            &cert,
+            "example.com",
+            all - gio::TlsCertificateFlags::UNKNOWN_CA
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn expired() {
+        let cert = self_signed("localhost", (2000, 1, 1), (2001, 1, 1));
+        let all = gio::TlsCertificateFlags::VALIDATE_ALL;
+
+        // Ignoring the unknown issuer must not skip the expiry check
+        assert!(matches!(
+            verify(
+                &cert,
+                "localhost",
+                all - gio::TlsCertificateFlags::UNKNOWN_CA
+            ),
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::Expired | CertificateError::ExpiredContext { .. }
+            ))
+        ));
+        // Ignoring the expiry must not skip the chain check
+        assert!(matches!(
+            verify(&cert, "localhost", all - gio::TlsCertificateFlags::EXPIRED),
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::UnknownIssuer
+            ))
+        ));
+        assert!(verify(
+            &cert,
+            "localhost",
+            all - gio::TlsCertificateFlags::UNKNOWN_CA - gio::TlsCertificateFlags::EXPIRED
+        )
+        .is_ok());
+    }
+}